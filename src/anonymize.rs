@@ -0,0 +1,265 @@
+//! Path anonymization (`--anonymize-paths`).
+//!
+//! Runs on sensitive trees can still ship their result logs, reports and log lines to
+//! central systems if file names are replaced before they leave the host:
+//!
+//! - [`AnonymizeMode::Hash`] replaces every path component with a salted hash, so
+//!   `/data/patients/smith.pdf` becomes `/1f3a0c9e27b4/8d02e6a1f5c3/c47b19e0d2aa`. The
+//!   same name always gets the same hash, so the tree's shape (and `--stats-by dir`)
+//!   survives.
+//! - [`AnonymizeMode::StripPrefix`] replaces the target directory a path is under with
+//!   its position on the command line, `<0>/smith.pdf`, for when only the location of
+//!   the tree is sensitive. Paths outside every target are hashed.
+//!
+//! Every anonymized path is written with its original to a mapping file that stays on
+//! the host, for de-anonymizing a report when needed. The salt is kept in the same file
+//! and reused on later runs, so hashes stay comparable between runs on one host.
+//!
+//! The anonymizer is installed process-wide; [`display`] is what reports and log lines
+//! format paths with, and shows them unchanged when no anonymizer is installed.
+
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use log::warn;
+use sha2::{Digest, Sha256};
+
+/// Mapping file used unless `--anonymize-map` says otherwise
+pub const DEFAULT_MAP_FILE: &str = "rust-cache-warmer-paths.tsv";
+
+/// Hex digits kept from each component's hash
+const HASH_LEN: usize = 12;
+
+const SALT_PREFIX: &str = "# salt ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnonymizeMode {
+    Hash,
+    StripPrefix,
+}
+
+impl FromStr for AnonymizeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "hash" => Ok(AnonymizeMode::Hash),
+            "strip-prefix" => Ok(AnonymizeMode::StripPrefix),
+            other => Err(format!("unknown anonymization mode '{}' (expected 'hash' or 'strip-prefix')", other)),
+        }
+    }
+}
+
+impl fmt::Display for AnonymizeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnonymizeMode::Hash => write!(f, "hash"),
+            AnonymizeMode::StripPrefix => write!(f, "strip-prefix"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MapFile {
+    writer: BufWriter<File>,
+    /// Hashes of the anonymized paths already written this run
+    written: HashSet<u64>,
+}
+
+/// Rewrites paths for output and records what they were; see the module docs
+#[derive(Debug)]
+pub struct PathAnonymizer {
+    mode: AnonymizeMode,
+    roots: Vec<PathBuf>,
+    salt: String,
+    map_path: PathBuf,
+    map: Mutex<MapFile>,
+}
+
+impl PathAnonymizer {
+    /// Anonymize paths under `roots` (the target directories, in command-line order),
+    /// appending the mapping to `map_path`. An existing mapping file's salt is reused.
+    pub fn open(mode: AnonymizeMode, roots: &[PathBuf], map_path: &Path) -> Result<Self, std::io::Error> {
+        let salt = match File::open(map_path) {
+            Ok(file) => read_salt(file)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut writer = BufWriter::new(options.open(map_path)?);
+        let salt = match salt {
+            Some(salt) => salt,
+            None => {
+                let salt = new_salt();
+                writeln!(writer, "# rust-cache-warmer path map: anonymized<TAB>original")?;
+                writeln!(writer, "{}{}", SALT_PREFIX, salt)?;
+                salt
+            }
+        };
+        let anonymizer = Self {
+            mode,
+            roots: roots.to_vec(),
+            salt,
+            map_path: map_path.to_path_buf(),
+            map: Mutex::new(MapFile { writer, written: HashSet::new() }),
+        };
+        if mode == AnonymizeMode::StripPrefix {
+            for root in &anonymizer.roots {
+                anonymizer.anonymize(root);
+            }
+        }
+        Ok(anonymizer)
+    }
+
+    pub fn mode(&self) -> AnonymizeMode {
+        self.mode
+    }
+
+    pub fn map_path(&self) -> &Path {
+        &self.map_path
+    }
+
+    /// The anonymized form of `path`, recording it in the mapping file
+    pub fn anonymize(&self, path: &Path) -> String {
+        let anonymized = match self.mode {
+            AnonymizeMode::Hash => self.hash_path(path),
+            AnonymizeMode::StripPrefix => self.strip_prefix(path).unwrap_or_else(|| self.hash_path(path)),
+        };
+        self.record(&anonymized, path);
+        anonymized
+    }
+
+    /// Write buffered mapping entries to the file
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.map.lock().unwrap().writer.flush()
+    }
+
+    fn strip_prefix(&self, path: &Path) -> Option<String> {
+        let (index, root) = self.roots.iter().enumerate().find(|(_, root)| path.starts_with(root))?;
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        Some(if relative.as_os_str().is_empty() {
+            format!("<{}>", index)
+        } else {
+            format!("<{}>/{}", index, relative.display())
+        })
+    }
+
+    fn hash_path(&self, path: &Path) -> String {
+        let mut out = String::new();
+        for component in path.components() {
+            match component {
+                Component::RootDir => out.push('/'),
+                Component::Normal(name) => {
+                    if !out.is_empty() && !out.ends_with('/') {
+                        out.push('/');
+                    }
+                    out.push_str(&self.hash_component(name.as_encoded_bytes()));
+                }
+                other => {
+                    if !out.is_empty() && !out.ends_with('/') {
+                        out.push('/');
+                    }
+                    out.push_str(&other.as_os_str().to_string_lossy());
+                }
+            }
+        }
+        out
+    }
+
+    fn hash_component(&self, name: &[u8]) -> String {
+        let digest = Sha256::new().chain_update(self.salt.as_bytes()).chain_update(name).finalize();
+        let mut hex = to_hex(&digest);
+        hex.truncate(HASH_LEN);
+        hex
+    }
+
+    fn record(&self, anonymized: &str, original: &Path) {
+        let mut hasher = DefaultHasher::new();
+        anonymized.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let mut map = self.map.lock().unwrap();
+        if !map.written.insert(key) {
+            return;
+        }
+        if let Err(e) = writeln!(map.writer, "{}\t{}", anonymized, original.display()) {
+            warn!("Failed to write the path map {}: {}", self.map_path.display(), e);
+        }
+    }
+}
+
+fn read_salt(file: File) -> Result<Option<String>, std::io::Error> {
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Some(salt) = line.strip_prefix(SALT_PREFIX) {
+            return Ok(Some(salt.trim().to_string()));
+        }
+        if !line.starts_with('#') {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// 128 random bits as hex, from the standard library's per-process hash keys
+fn new_salt() -> String {
+    let mut bytes = Vec::with_capacity(16);
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        std::process::id().hash(&mut hasher);
+        std::time::SystemTime::now().hash(&mut hasher);
+        bytes.extend_from_slice(&hasher.finish().to_le_bytes());
+    }
+    to_hex(&bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+static GLOBAL: OnceLock<PathAnonymizer> = OnceLock::new();
+
+/// Make `anonymizer` the one [`display`] uses for the rest of the process. Returns
+/// false, leaving the installed one in place, if there already is one.
+pub fn install(anonymizer: PathAnonymizer) -> bool {
+    GLOBAL.set(anonymizer).is_ok()
+}
+
+/// The installed anonymizer, if any
+pub fn global() -> Option<&'static PathAnonymizer> {
+    GLOBAL.get()
+}
+
+/// Flush the installed anonymizer's mapping file, if there is one
+pub fn flush() -> Result<(), std::io::Error> {
+    global().map_or(Ok(()), PathAnonymizer::flush)
+}
+
+/// `path` as it should appear in reports and logs: anonymized if an anonymizer is
+/// installed, as is otherwise
+pub fn display(path: &Path) -> Display<'_> {
+    Display(path)
+}
+
+/// Formats a path for output; see [`display`]
+#[derive(Debug, Clone, Copy)]
+pub struct Display<'a>(&'a Path);
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match global() {
+            Some(anonymizer) => f.write_str(&anonymizer.anonymize(self.0)),
+            None => self.0.display().fmt(f),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Strategy micro-benchmark (`rust-cache-warmer bench`).
+//!
+//! Which strategy warms fastest depends on the kernel, the filesystem and the volume,
+//! so the reliable answer is to measure on the host. The benchmark warms a sample of the
+//! target files with each available strategy alone, without fallback, and compares
+//! throughput and per-file latency.
+//!
+//! A block of a volume restored from a snapshot is slow only the first time it's read,
+//! so no two strategies read the same file: the sample is sorted by size and dealt out
+//! between them, giving each a similar mix. Strategies take turns one batch at a time,
+//! so a volume that slows down during the run (say, once its burst credits run out)
+//! costs them alike. Pages are dropped before every read, so files that happen to be
+//! cached don't flatter one strategy.
+//!
+//! With a synthetic file, every strategy reads the same freshly written file instead.
+//! Written blocks are never cold, so that compares the strategies' read paths to the
+//! device rather than EBS hydration.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
+use ignore::WalkBuilder;
+use log::debug;
+
+use crate::anonymize;
+use crate::verify::{LatencyPercentiles, Rng};
+use crate::warming::{Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingOptions};
+
+/// Files each strategy warms unless told otherwise
+pub const DEFAULT_FILES: usize = 64;
+/// Name of the synthetic test file, created in the first target directory
+pub const SYNTHETIC_FILE_NAME: &str = ".rust-cache-warmer-bench";
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub directories: Vec<PathBuf>,
+    /// Strategies to compare; empty compares every one available
+    pub strategies: Vec<Strategy>,
+    /// Files each strategy warms, or times it reads the synthetic file
+    pub files: usize,
+    /// Files warmed at once in each batch
+    pub queue_depth: usize,
+    /// Size of a synthetic file to read instead of sampling the targets; 0 samples
+    pub synthetic_size: u64,
+    /// Settings shared by every strategy; each runs with its own strategy and no fallback
+    pub warming: WarmingOptions,
+    /// Seed for the file sample, so a run can be reproduced
+    pub seed: u64,
+}
+
+/// What one strategy did
+#[derive(Debug, Clone)]
+pub struct StrategyResult {
+    pub strategy: Strategy,
+    pub files: u64,
+    pub failed: u64,
+    /// Size of the files warmed successfully
+    pub bytes: u64,
+    /// Time spent in this strategy's batches
+    pub elapsed: Duration,
+    /// Per-file latency of the successful warms
+    pub latency: LatencyPercentiles,
+    /// Only advises the kernel, so its timings don't include reading the data
+    pub advisory: bool,
+}
+
+impl StrategyResult {
+    /// Bytes per second over the time spent in this strategy's batches
+    pub fn throughput(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            self.bytes as f64 / self.elapsed.as_secs_f64()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    /// What was read, e.g. "64 sampled files per strategy"
+    pub source: String,
+    pub queue_depth: usize,
+    pub results: Vec<StrategyResult>,
+    /// Strategies that weren't run, and why
+    pub skipped: Vec<(Strategy, &'static str)>,
+}
+
+impl BenchReport {
+    /// The strategy with the highest throughput among those that read every file
+    /// without a failure. Advisory strategies aren't recommended: they finish before
+    /// the data has been read.
+    pub fn recommendation(&self) -> Option<Strategy> {
+        self.results
+            .iter()
+            .filter(|result| !result.advisory && result.failed == 0 && result.files > 0)
+            .max_by(|a, b| a.throughput().total_cmp(&b.throughput()))
+            .map(|result| result.strategy)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Strategy benchmark: {}, {} at a time", self.source, self.queue_depth)?;
+        writeln!(
+            f,
+            "  {:<10} {:>6} {:>7} {:>10} {:>10} {:>12} {:>12} {:>12}",
+            "strategy", "files", "failed", "MB", "MB/s", "p50", "p90", "p99"
+        )?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "  {:<10} {:>6} {:>7} {:>10.2} {:>10.2} {:>12.2?} {:>12.2?} {:>12.2?}{}",
+                result.strategy.name(),
+                result.files,
+                result.failed,
+                result.bytes as f64 / (1024.0 * 1024.0),
+                result.throughput() / (1024.0 * 1024.0),
+                result.latency.p50,
+                result.latency.p90,
+                result.latency.p99,
+                if result.advisory { "  (advice only; timings exclude the reads)" } else { "" }
+            )?;
+        }
+        for (strategy, why) in &self.skipped {
+            writeln!(f, "  {:<10} skipped: {}", strategy.name(), why)?;
+        }
+        match self.recommendation() {
+            Some(strategy) => writeln!(f, "  Recommended: --strategy {}", strategy),
+            None => writeln!(f, "  No strategy warmed every file; nothing to recommend"),
+        }
+    }
+}
+
+/// The strategies to run out of `requested` (every strategy if empty), and those left
+/// out with the reason
+pub fn candidates(registry: &StrategyRegistry, requested: &[Strategy], use_direct_io: bool) -> (Vec<Strategy>, Vec<(Strategy, &'static str)>) {
+    let requested = if requested.is_empty() { &Strategy::ALL[..] } else { requested };
+    let (mut run, mut skipped) = (Vec::new(), Vec::new());
+    for &strategy in requested {
+        let why = match registry.backend(strategy) {
+            _ if strategy == Strategy::Auto => Some("picks a strategy per file"),
+            None => Some("not registered"),
+            Some(_) if !registry.is_available(strategy) => Some("unavailable on this host"),
+            Some(backend) if !backend.supports(use_direct_io) => {
+                Some(if use_direct_io { "doesn't support --direct-io" } else { "needs --direct-io" })
+            }
+            Some(_) => None,
+        };
+        match why {
+            Some(why) => skipped.push((strategy, why)),
+            None if !run.contains(&strategy) => run.push(strategy),
+            None => {}
+        }
+    }
+    (run, skipped)
+}
+
+/// A uniform sample of up to `count` non-empty files under `directories`, smallest first
+pub fn sample_files(directories: &[PathBuf], count: usize, seed: u64) -> Vec<(PathBuf, u64)> {
+    let mut rng = Rng::new(seed);
+    let mut sample: Vec<(PathBuf, u64)> = Vec::with_capacity(count);
+    let mut seen = 0u64;
+    for root in directories {
+        for entry in WalkBuilder::new(root).standard_filters(false).build().flatten() {
+            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.len() == 0 {
+                continue;
+            }
+            // Reservoir sampling keeps memory to the sample size however large the tree
+            seen += 1;
+            if sample.len() < count {
+                sample.push((entry.into_path(), metadata.len()));
+            } else {
+                let slot = rng.below(seen) as usize;
+                if slot < count {
+                    sample[slot] = (entry.into_path(), metadata.len());
+                }
+            }
+        }
+    }
+    sample.sort_by_key(|(_, size)| *size);
+    sample
+}
+
+/// Deal `files`, sorted by size, between `hands` so each gets a similar mix of sizes
+pub fn deal<T>(files: Vec<T>, hands: usize) -> Vec<Vec<T>> {
+    let mut dealt: Vec<Vec<T>> = (0..hands).map(|_| Vec::new()).collect();
+    if hands == 0 {
+        return dealt;
+    }
+    for (i, file) in files.into_iter().enumerate() {
+        // Back and forth, so the first hand doesn't always get the smaller file of each round
+        let round = i / hands;
+        let seat = if round.is_multiple_of(2) { i % hands } else { hands - 1 - i % hands };
+        dealt[seat].push(file);
+    }
+    dealt
+}
+
+/// Write a synthetic test file of `size` bytes in `dir`. The data is pseudo-random so
+/// compressing or deduplicating storage can't shortcut the reads.
+pub fn write_synthetic(dir: &Path, size: u64, seed: u64) -> std::io::Result<PathBuf> {
+    let path = dir.join(SYNTHETIC_FILE_NAME);
+    let mut file = std::fs::File::create(&path)?;
+    let mut rng = Rng::new(seed);
+    let block: Vec<u8> = (0..1024 * 1024 / 8).flat_map(|_| rng.next_u64().to_le_bytes()).collect();
+    let mut written = 0u64;
+    while written < size {
+        let len = (size - written).min(block.len() as u64) as usize;
+        file.write_all(&block[..len])?;
+        written += len as u64;
+    }
+    file.sync_all()?;
+    Ok(path)
+}
+
+/// Evict `path` from the page cache, so the next read goes to the device
+fn drop_pages(path: &Path) {
+    #[cfg(target_os = "linux")]
+    if let Ok(file) = std::fs::File::open(path) {
+        use std::os::unix::io::AsRawFd;
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = path;
+}
+
+/// Run the benchmark with the backends of `registry`
+pub async fn run(options: &BenchOptions, registry: &StrategyRegistry) -> std::io::Result<BenchReport> {
+    let (strategies, skipped) = candidates(registry, &options.strategies, options.warming.use_direct_io);
+    let files = options.files.max(1);
+
+    let synthetic = match (options.synthetic_size, options.directories.first()) {
+        (0, _) => None,
+        (size, Some(dir)) => Some((write_synthetic(dir, size, options.seed)?, size)),
+        (_, None) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "a synthetic file needs a directory to be written to")),
+    };
+    // Concurrent reads of the one synthetic file would share its pages
+    let queue_depth = if synthetic.is_some() { 1 } else { options.queue_depth.max(1) };
+    let (source, hands) = match &synthetic {
+        Some((path, size)) => (
+            format!("{} reads per strategy of a {:.2} MB synthetic file", files, *size as f64 / (1024.0 * 1024.0)),
+            strategies.iter().map(|_| vec![(path.clone(), *size); files]).collect(),
+        ),
+        None => {
+            let sample = sample_files(&options.directories, files * strategies.len(), options.seed);
+            let per_strategy = if strategies.is_empty() { 0 } else { sample.len() / strategies.len() };
+            // Leftovers would give some strategies one file more than others
+            let sample = sample.into_iter().take(per_strategy * strategies.len()).collect();
+            (format!("{} sampled files per strategy", per_strategy), deal(sample, strategies.len()))
+        }
+    };
+
+    let arms: Vec<WarmingOptions> = strategies
+        .iter()
+        .map(|&strategy| WarmingOptions { strategy, fallback: FallbackPolicy::None, ..options.warming.clone() })
+        .collect();
+    let mut queues: Vec<VecDeque<(PathBuf, u64)>> = hands.into_iter().map(VecDeque::from).collect();
+    let mut latencies: Vec<Vec<Duration>> = vec![Vec::new(); strategies.len()];
+    let mut results: Vec<StrategyResult> = strategies
+        .iter()
+        .map(|&strategy| StrategyResult {
+            strategy,
+            files: 0,
+            failed: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            latency: LatencyPercentiles::default(),
+            advisory: registry.backend(strategy).is_some_and(|backend| backend.is_advisory()),
+        })
+        .collect();
+
+    // Strategies take turns a batch at a time, starting with a different one each round
+    let mut round = 0;
+    while queues.iter().any(|queue| !queue.is_empty()) {
+        for turn in 0..strategies.len() {
+            let arm = (round + turn) % strategies.len();
+            let queue = &mut queues[arm];
+            let batch: Vec<(PathBuf, u64)> = queue.drain(..queue_depth.min(queue.len())).collect();
+            if batch.is_empty() {
+                continue;
+            }
+            for (path, _) in &batch {
+                drop_pages(path);
+            }
+            let warming = &arms[arm];
+            let start = Instant::now();
+            let outcomes: Vec<_> = stream::iter(batch)
+                .map(|(path, size)| async move {
+                    let file_start = Instant::now();
+                    let result = registry.warm(&path, size, warming).await;
+                    (path, size, file_start.elapsed(), result)
+                })
+                .buffer_unordered(queue_depth)
+                .collect()
+                .await;
+            let result = &mut results[arm];
+            result.elapsed += start.elapsed();
+            for (path, size, latency, outcome) in outcomes {
+                result.files += 1;
+                match outcome {
+                    Ok(warmed) if warmed.success && warmed.coverage != Coverage::Partial => {
+                        result.bytes += size;
+                        latencies[arm].push(latency);
+                    }
+                    Ok(_) => result.failed += 1,
+                    Err(e) => {
+                        debug!("{} failed on {}: {}", strategies[arm], anonymize::display(&path), e);
+                        result.failed += 1;
+                    }
+                }
+            }
+        }
+        round += 1;
+    }
+
+    for (result, mut latencies) in results.iter_mut().zip(latencies) {
+        latencies.sort_unstable();
+        result.latency = LatencyPercentiles::from_sorted(&latencies);
+    }
+    if let Some((path, _)) = &synthetic {
+        if let Err(e) = std::fs::remove_file(path) {
+            debug!("Failed to remove synthetic file {}: {}", path.display(), e);
+        }
+    }
+    Ok(BenchReport { source, queue_depth, results, skipped })
+}
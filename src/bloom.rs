@@ -0,0 +1,123 @@
+//! Remembering what watch mode already warmed, in bounded memory.
+//!
+//! Editors, log writers and build tools produce a stream of inotify events for files
+//! whose contents end up unchanged: a file opened for writing and closed again, or
+//! renamed back and forth. Watch mode records the `(path, mtime, size)` identity of
+//! every file it warms in a [`RecentSet`] and skips events for an identity it has seen,
+//! without a stat-and-compare against any stored state.
+//!
+//! The set is a pair of bloom filters. A bloom filter can't forget, and its false
+//! positive rate climbs past the one it was sized for once it holds more than its
+//! capacity, so when the current filter fills up it becomes the previous one and a
+//! new, empty filter takes its place; the generation before is dropped. Memory stays at
+//! two filters, and identities warmed recently are remembered for at least one
+//! capacity's worth of insertions. A false positive skips a file that did change; with
+//! two filters to ask, that happens at up to twice the rate each was sized for.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A fixed-size bloom filter sized for `capacity` items at a chosen false positive rate
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    len: usize,
+    capacity: usize,
+}
+
+impl BloomFilter {
+    /// A filter that answers with at most `false_positive_rate` false positives while it
+    /// holds up to `capacity` items
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1);
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let bits = (-(capacity as f64) * rate.ln() / (std::f64::consts::LN_2 * std::f64::consts::LN_2)).ceil() as usize;
+        let words = bits.div_ceil(64).max(1);
+        let hashes = ((words * 64) as f64 / capacity as f64 * std::f64::consts::LN_2).round().clamp(1.0, 32.0) as u32;
+        Self { bits: vec![0; words], hashes, len: 0, capacity }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for bit in self.positions(item) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    /// Whether `item` may have been inserted; never false for an item that was
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.positions(item).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Insertions so far, counting repeats
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the filter holds as many items as it was sized for
+    pub fn is_full(&self) -> bool {
+        self.len >= self.capacity
+    }
+
+    /// Bytes used by the bit array
+    pub fn memory_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Bit indexes for `item`, by double hashing two independent hashes of it
+    fn positions<T: Hash + ?Sized>(&self, item: &T) -> impl Iterator<Item = usize> {
+        let mut first = DefaultHasher::new();
+        item.hash(&mut first);
+        let mut second = DefaultHasher::new();
+        0x9e37_79b9_7f4a_7c15u64.hash(&mut second);
+        item.hash(&mut second);
+        let (h1, h2) = (first.finish(), second.finish() | 1);
+        let bits = (self.bits.len() * 64) as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+}
+
+/// Bounded-memory set of recently inserted items: a current and a previous bloom filter,
+/// rotated whenever the current one fills up
+#[derive(Debug, Clone)]
+pub struct RecentSet {
+    current: BloomFilter,
+    previous: Option<BloomFilter>,
+    false_positive_rate: f64,
+    rotations: u64,
+}
+
+impl RecentSet {
+    /// Remembers at least the last `capacity` insertions at `false_positive_rate`
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        Self { current: BloomFilter::new(capacity, false_positive_rate), previous: None, false_positive_rate, rotations: 0 }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        if self.current.is_full() {
+            let fresh = BloomFilter::new(self.current.capacity, self.false_positive_rate);
+            self.previous = Some(std::mem::replace(&mut self.current, fresh));
+            self.rotations += 1;
+        }
+        self.current.insert(item);
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.current.contains(item) || self.previous.as_ref().is_some_and(|previous| previous.contains(item))
+    }
+
+    /// Times the current filter filled up and was replaced
+    pub fn rotations(&self) -> u64 {
+        self.rotations
+    }
+
+    /// Bytes used by both filters once the first rotation has happened
+    pub fn memory_bytes(&self) -> usize {
+        2 * self.current.memory_bytes()
+    }
+}
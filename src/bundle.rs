@@ -0,0 +1,395 @@
+//! Debug bundles (`rust-cache-warmer debug-bundle out.tar.gz`).
+//!
+//! Most bug reports come down to the host: which strategies probed as available, the
+//! kernel, how the targets are mounted, a low `RLIMIT_MEMLOCK` or a cgroup memory limit.
+//! [`Environment::capture`] gathers all of that into one structured snapshot, and
+//! [`create`] writes it to a gzipped tarball as `environment.json`, along with raw copies
+//! of the `/proc` files it came from, the config file and the files a previous run left
+//! behind (checkpoint, result log, textfile metrics), ready to attach to an issue.
+//!
+//! The archive is written by a small ustar writer over `miniz_oxide`, which the build
+//! already pulls in, rather than a full archive library.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::config::ConfigFile;
+use crate::disk;
+use crate::logfile;
+use crate::warming::{StrategyRegistry, WarmingOptions};
+
+/// Top-level directory of every entry in the archive
+pub const ROOT: &str = "rust-cache-warmer-debug";
+
+/// Files larger than this are cut down to their last `MAX_FILE_BYTES`, which is where a
+/// log's most recent (and usually most interesting) records are
+pub const MAX_FILE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// `/proc` files copied verbatim into `proc/`
+const PROC_FILES: &[&str] = &["/proc/version", "/proc/self/mountinfo", "/proc/self/limits", "/proc/self/cgroup", "/proc/meminfo"];
+
+/// Limit files read from a cgroup v2 directory
+const CGROUP_V2_FILES: &[&str] = &["memory.max", "memory.high", "memory.current", "cpu.max", "io.max", "pids.max"];
+
+/// Limit files read per cgroup v1 controller
+const CGROUP_V1_FILES: &[(&str, &[&str])] = &[
+    ("memory", &["memory.limit_in_bytes", "memory.usage_in_bytes"]),
+    ("cpu", &["cpu.cfs_quota_us", "cpu.cfs_period_us"]),
+    ("blkio", &["blkio.throttle.read_bps_device", "blkio.throttle.read_iops_device"]),
+    ("pids", &["pids.max"]),
+];
+
+/// What goes into a bundle besides the environment snapshot
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+    pub config: Option<PathBuf>,
+    /// Target directories to report the disk kind of
+    pub directories: Vec<PathBuf>,
+    /// State and report files of earlier runs
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Kernel {
+    pub sysname: String,
+    pub release: String,
+    pub version: String,
+    pub machine: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StrategyProbe {
+    pub strategy: &'static str,
+    pub available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Target {
+    pub path: PathBuf,
+    pub disk: String,
+}
+
+/// One line of `/proc/self/mounts`
+#[derive(Debug, Clone, Serialize)]
+pub struct Mount {
+    pub device: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub options: String,
+}
+
+/// A resource limit; `None` is unlimited
+#[derive(Debug, Clone, Serialize)]
+pub struct Rlimit {
+    pub resource: &'static str,
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Cgroup {
+    pub version: u8,
+    /// Path of the process's cgroup (the unified hierarchy's on v2)
+    pub path: String,
+    /// Limit file name → contents, for the files that exist
+    pub limits: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigCheck {
+    pub path: PathBuf,
+    /// Why the file doesn't load, if it doesn't
+    pub error: Option<String>,
+}
+
+/// A file copied into the bundle
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledFile {
+    pub source: PathBuf,
+    pub entry: String,
+    pub size: u64,
+    /// Only the last [`MAX_FILE_BYTES`] were included
+    pub truncated: bool,
+}
+
+/// Snapshot of the host as the warmer sees it
+#[derive(Debug, Clone, Serialize)]
+pub struct Environment {
+    pub version: &'static str,
+    pub captured_at: u64,
+    pub kernel: Option<Kernel>,
+    pub strategies: Vec<StrategyProbe>,
+    /// Strategies a default run would try, in order
+    pub default_plan: Vec<&'static str>,
+    pub targets: Vec<Target>,
+    pub mounts: Vec<Mount>,
+    pub rlimits: Vec<Rlimit>,
+    pub cgroup: Option<Cgroup>,
+    pub config: Option<ConfigCheck>,
+    pub files: Vec<BundledFile>,
+    /// Things that couldn't be captured, and why
+    pub notes: Vec<String>,
+}
+
+impl Environment {
+    /// Probe the host. Nothing here fails: whatever can't be read is left out and noted.
+    pub fn capture(options: &BundleOptions) -> Self {
+        let registry = StrategyRegistry::global();
+        let mut notes = Vec::new();
+        let mounts = match std::fs::read_to_string("/proc/self/mounts") {
+            Ok(text) => parse_mounts(&text),
+            Err(e) => {
+                notes.push(format!("mount table: {}", e));
+                Vec::new()
+            }
+        };
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            captured_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            kernel: kernel(),
+            strategies: registry
+                .probe()
+                .iter()
+                .map(|probe| StrategyProbe { strategy: probe.strategy.name(), available: probe.available, reason: probe.reason.clone() })
+                .collect(),
+            default_plan: registry.plan(&WarmingOptions::default()).iter().map(|s| s.name()).collect(),
+            targets: options
+                .directories
+                .iter()
+                .map(|dir| Target { path: dir.clone(), disk: disk::detect(dir).to_string() })
+                .collect(),
+            mounts,
+            rlimits: rlimits(),
+            cgroup: cgroup(),
+            config: options.config.as_ref().map(|path| ConfigCheck {
+                path: path.clone(),
+                error: ConfigFile::load(path).err().map(|e| format!("{:#}", e)),
+            }),
+            files: Vec::new(),
+            notes,
+        }
+    }
+}
+
+/// Parse `/proc/self/mounts` (fstab format)
+pub fn parse_mounts(text: &str) -> Vec<Mount> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(Mount {
+                device: fields.next()?.to_string(),
+                mount_point: fields.next()?.to_string(),
+                fs_type: fields.next()?.to_string(),
+                options: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The running kernel, from uname(2)
+#[cfg(unix)]
+pub fn kernel() -> Option<Kernel> {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return None;
+    }
+    let field = |chars: &[libc::c_char]| {
+        let bytes: Vec<u8> = chars.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    Some(Kernel { sysname: field(&uts.sysname), release: field(&uts.release), version: field(&uts.version), machine: field(&uts.machine) })
+}
+
+#[cfg(not(unix))]
+pub fn kernel() -> Option<Kernel> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn rlimits() -> Vec<Rlimit> {
+    let resources = [
+        ("nofile", libc::RLIMIT_NOFILE),
+        // io_uring rings are charged against this on older kernels
+        ("memlock", libc::RLIMIT_MEMLOCK),
+        ("as", libc::RLIMIT_AS),
+        ("data", libc::RLIMIT_DATA),
+        ("nproc", libc::RLIMIT_NPROC),
+        ("fsize", libc::RLIMIT_FSIZE),
+        ("stack", libc::RLIMIT_STACK),
+    ];
+    let finite = |value: libc::rlim_t| (value != libc::RLIM_INFINITY).then_some(value);
+    resources
+        .into_iter()
+        .filter_map(|(resource, id)| {
+            let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+            (unsafe { libc::getrlimit(id, &mut limit) } == 0)
+                .then(|| Rlimit { resource, soft: finite(limit.rlim_cur), hard: finite(limit.rlim_max) })
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rlimits() -> Vec<Rlimit> {
+    Vec::new()
+}
+
+fn cgroup() -> Option<Cgroup> {
+    let text = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let mut cgroup = Cgroup { version: 2, path: String::new(), limits: BTreeMap::new() };
+    for line in text.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let relative = path.trim_start_matches('/');
+        if controllers.is_empty() {
+            // The unified (v2) hierarchy
+            cgroup.path = path.to_string();
+            read_limits(&Path::new("/sys/fs/cgroup").join(relative), CGROUP_V2_FILES, "", &mut cgroup.limits);
+            continue;
+        }
+        cgroup.version = 1;
+        for (controller, files) in CGROUP_V1_FILES {
+            if controllers.split(',').any(|c| c == *controller) {
+                let dir = Path::new("/sys/fs/cgroup").join(controllers).join(relative);
+                read_limits(&dir, files, controller, &mut cgroup.limits);
+            }
+        }
+    }
+    Some(cgroup)
+}
+
+fn read_limits(dir: &Path, files: &[&str], controller: &str, limits: &mut BTreeMap<String, String>) {
+    for file in files {
+        if let Ok(value) = std::fs::read_to_string(dir.join(file)) {
+            let key = if controller.is_empty() { file.to_string() } else { format!("{}/{}", controller, file) };
+            limits.insert(key, value.trim().to_string());
+        }
+    }
+}
+
+/// Capture the environment and write it, with everything `options` lists, to `output`
+pub fn create(output: &Path, options: &BundleOptions) -> Result<Environment, std::io::Error> {
+    let mut environment = Environment::capture(options);
+    let mut archive = Archive::new(environment.captured_at);
+
+    for source in PROC_FILES {
+        match std::fs::read(source) {
+            Ok(data) => archive.add(&format!("proc/{}", source.trim_start_matches("/proc/").replace('/', "_")), &data),
+            Err(e) => environment.notes.push(format!("{}: {}", source, e)),
+        }
+    }
+    let config = options.config.iter().map(|path| (path, "config"));
+    let files = options.files.iter().map(|path| (path, "files"));
+    let mut names: Vec<String> = Vec::new();
+    for (source, dir) in config.chain(files) {
+        match read_tail(source) {
+            Ok((data, size)) => {
+                let entry = unique_entry(dir, source, &mut names);
+                archive.add(&entry, &data);
+                environment.files.push(BundledFile { source: source.clone(), entry, size, truncated: size > data.len() as u64 });
+            }
+            Err(e) => environment.notes.push(format!("{}: {}", source.display(), e)),
+        }
+    }
+
+    let json = serde_json::to_vec_pretty(&environment).map_err(std::io::Error::other)?;
+    archive.add("environment.json", &json);
+    std::fs::write(output, archive.finish())?;
+    Ok(environment)
+}
+
+/// Contents of `path`, or its last [`MAX_FILE_BYTES`] if it's bigger, plus its full size
+fn read_tail(path: &Path) -> Result<(Vec<u8>, u64), std::io::Error> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if size > MAX_FILE_BYTES {
+        file.seek(SeekFrom::Start(size - MAX_FILE_BYTES))?;
+    }
+    let mut data = Vec::with_capacity(size.min(MAX_FILE_BYTES) as usize);
+    file.take(MAX_FILE_BYTES).read_to_end(&mut data)?;
+    Ok((data, size))
+}
+
+/// `dir/<file name>`, numbered if an earlier file had the same name. Names are
+/// shortened to stay within the 100-byte ustar name field.
+fn unique_entry(dir: &str, source: &Path, names: &mut Vec<String>) -> String {
+    let name = source.file_name().map_or_else(|| "file".to_string(), |name| name.to_string_lossy().into_owned());
+    let mut short = String::new();
+    for c in name.chars() {
+        if short.len() + c.len_utf8() > 64 {
+            break;
+        }
+        short.push(c);
+    }
+    let mut entry = format!("{}/{}", dir, short);
+    let mut n = 1;
+    while names.contains(&entry) {
+        n += 1;
+        entry = format!("{}/{}-{}", dir, n, short);
+    }
+    names.push(entry.clone());
+    entry
+}
+
+/// In-memory ustar archive of regular files under [`ROOT`], gzipped on [`Archive::finish`]
+struct Archive {
+    tar: Vec<u8>,
+    mtime: u64,
+}
+
+impl Archive {
+    fn new(mtime: u64) -> Self {
+        Self { tar: Vec::new(), mtime }
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) {
+        let name = format!("{}/{}", ROOT, name);
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], data.len() as u64);
+        octal(&mut header[136..148], self.mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        // The checksum is computed with its own field filled with spaces
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|&b| u64::from(b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.tar.extend_from_slice(&header);
+        self.tar.extend_from_slice(data);
+        self.tar.resize(self.tar.len().next_multiple_of(512), 0);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        // Two zero blocks end the archive
+        self.tar.resize(self.tar.len() + 1024, 0);
+        gzip(&self.tar)
+    }
+}
+
+/// Zero-padded octal number filling `field`, NUL-terminated
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    // Magic, deflate, no flags, no mtime, no extra flags, unknown OS
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+    out.extend(logfile::crc32(0, data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
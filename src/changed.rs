@@ -0,0 +1,299 @@
+//! Incremental warming (`--changed-since`).
+//!
+//! A volume restored from tonight's snapshot differs from last night's in a few files;
+//! warming all of it again costs hours for nothing. [`ChangeFilter`] keeps discovery to
+//! the files whose content changed after a cutoff: their modification or status-change
+//! time is later. The status-change time is checked as well because copying a file in
+//! with its modification time preserved (`cp -p`, `rsync -a`, `tar x`) leaves the mtime
+//! old, but always sets the ctime.
+//!
+//! The cutoff is either a timestamp or an earlier run's result log (`--result-log`),
+//! which stands for the moment that run started. The log is also diffed: files it lists
+//! as failed, or at a different size, are warmed again even if they look unchanged.
+//! Files it doesn't list at all were unchanged when that run started, so they were
+//! warmed by it or by the run before, and are left alone; a chain of incremental runs
+//! each given the previous one's log therefore covers every change.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+
+use crate::anonymize;
+use crate::logfile::{self, LogReader};
+use crate::result_log::ResultFormat;
+
+/// `--changed-since`: a point in time, or the result log of the run to diff against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangedSince {
+    Time(SystemTime),
+    Manifest(PathBuf),
+}
+
+impl FromStr for ChangedSince {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_timestamp(s) {
+            Some(time) => Ok(ChangedSince::Time(time)),
+            None if s.trim().is_empty() => Err("expected a timestamp or a result log".to_string()),
+            None => Ok(ChangedSince::Manifest(PathBuf::from(s))),
+        }
+    }
+}
+
+/// Seconds since the epoch (`1760666400` or `@1760666400`), or an RFC 3339 date and
+/// time in UTC or with an offset: `2026-10-17`, `2026-10-17T02:00`,
+/// `2026-10-17 02:00:00Z`, `2026-10-17T04:00:00+02:00`
+pub fn parse_timestamp(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    let digits = s.strip_prefix('@').unwrap_or(s);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Some(UNIX_EPOCH + Duration::from_secs(digits.parse().ok()?));
+    }
+
+    let number = |text: &str| -> Option<i64> { text.bytes().all(|b| b.is_ascii_digit()).then(|| text.parse().ok()).flatten() };
+    let (date, time) = match s.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, time),
+        None => (s, "00:00"),
+    };
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (number(parts.next()?)?, number(parts.next()?)?, number(parts.next()?)?);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+
+    // The offset from UTC: Z, +HH:MM or -HH:MM; none means UTC
+    let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else if let Some(at) = time.rfind(['+', '-']) {
+        let (hours, minutes) = time[at + 1..].split_once(':')?;
+        let offset = number(hours)? * 3600 + number(minutes)? * 60;
+        (&time[..at], if time.as_bytes()[at] == b'-' { -offset } else { offset })
+    } else {
+        (time, 0)
+    };
+    let mut parts = time.split(':');
+    let hour = number(parts.next()?)?;
+    let minute = number(parts.next()?)?;
+    let second = parts.next().map_or(Some(0), number)?;
+    if parts.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The fields of a result log record that the diff needs
+#[derive(Debug, Deserialize)]
+struct Record {
+    path: String,
+    size: u64,
+    status: String,
+}
+
+/// What an earlier run did with each file it processed, by path as the log shows it
+#[derive(Debug, Default)]
+pub struct Manifest {
+    files: HashMap<String, (u64, bool)>,
+}
+
+impl Manifest {
+    /// Read every segment of the result log at `path`, NDJSON or CSV
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let reader = LogReader::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let csv = ResultFormat::for_path(path) == ResultFormat::Csv;
+        let mut manifest = Manifest::default();
+        let mut unreadable = 0usize;
+        for line in std::io::BufReader::new(reader).lines() {
+            let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+            let record = if csv { parse_csv(&line) } else { serde_json::from_str::<Record>(&line).ok() };
+            match record {
+                Some(record) => {
+                    manifest.files.insert(record.path, (record.size, record.status == "warmed"));
+                }
+                None if line.trim().is_empty() || (csv && line.starts_with("path,")) => {}
+                None => unreadable += 1,
+            }
+        }
+        if unreadable > 0 {
+            warn!("{}: skipped {} lines that aren't result log records", path.display(), unreadable);
+        }
+        Ok(manifest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Whether the earlier run left a file of `size` bytes at `path` as it should be:
+    /// `None` when it didn't process the file, otherwise whether it warmed it at this size
+    pub fn warmed(&self, path: &Path, size: u64) -> Option<bool> {
+        let &(logged_size, warmed) = self.files.get(anonymize::display(path).to_string().as_str())?;
+        Some(warmed && logged_size == size)
+    }
+}
+
+/// The path, size and status of a CSV record; the path may be quoted
+fn parse_csv(line: &str) -> Option<Record> {
+    let (path, rest) = match line.strip_prefix('"') {
+        Some(quoted) => {
+            let mut path = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (i, '"') if quoted[i + 1..].starts_with('"') => {
+                        path.push('"');
+                        chars.next();
+                    }
+                    (i, '"') => break i + 1,
+                    (_, c) => path.push(c),
+                }
+            };
+            (path, quoted[end..].strip_prefix(',')?)
+        }
+        None => {
+            let (path, rest) = line.split_once(',')?;
+            (path.to_string(), rest)
+        }
+    };
+    let mut fields = rest.splitn(3, ',');
+    let size = fields.next()?.parse().ok()?;
+    let status = fields.next()?.to_string();
+    Some(Record { path, size, status })
+}
+
+/// When the run that wrote the log at `path` started: the creation time of its first
+/// segment, or, where the filesystem doesn't record one, its last modification
+pub fn log_started(path: &Path) -> Result<SystemTime> {
+    let first = logfile::segments(path).into_iter().next().unwrap_or_else(|| path.to_path_buf());
+    let metadata = std::fs::metadata(&first).with_context(|| format!("failed to read {}", first.display()))?;
+    match metadata.created() {
+        Ok(created) => Ok(created),
+        Err(_) => {
+            warn!(
+                "{} has no creation time on this filesystem; using when it was last written, which misses files changed while that run was going",
+                first.display()
+            );
+            Ok(metadata.modified()?)
+        }
+    }
+}
+
+/// Decides which discovered files changed since the cutoff
+#[derive(Debug)]
+pub struct ChangeFilter {
+    cutoff: SystemTime,
+    manifest: Option<Manifest>,
+    unchanged: AtomicU64,
+}
+
+impl ChangeFilter {
+    /// Files changed after `cutoff`
+    pub fn since(cutoff: SystemTime) -> ChangeFilter {
+        ChangeFilter { cutoff, manifest: None, unchanged: AtomicU64::new(0) }
+    }
+
+    /// Files changed since the run that wrote `manifest` started at `cutoff`, and those
+    /// it didn't warm at their current size
+    pub fn against(cutoff: SystemTime, manifest: Manifest) -> ChangeFilter {
+        ChangeFilter { manifest: Some(manifest), ..ChangeFilter::since(cutoff) }
+    }
+
+    /// Load the filter `--changed-since` describes
+    pub fn load(since: &ChangedSince) -> Result<ChangeFilter> {
+        match since {
+            ChangedSince::Time(time) => Ok(ChangeFilter::since(*time)),
+            ChangedSince::Manifest(path) => {
+                let context = || format!("--changed-since {}: not a timestamp (e.g. 2026-10-17T02:00:00Z) or a readable result log", path.display());
+                let started = log_started(path).with_context(context)?;
+                Ok(ChangeFilter::against(started, Manifest::load(path).with_context(context)?))
+            }
+        }
+    }
+
+    pub fn cutoff(&self) -> SystemTime {
+        self.cutoff
+    }
+
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Whether the file at `path` needs warming, counting it as unchanged if not
+    pub fn changed(&self, path: &Path, metadata: &std::fs::Metadata) -> bool {
+        let changed = self.is_changed(path, metadata);
+        if !changed {
+            self.unchanged.fetch_add(1, Ordering::Relaxed);
+        }
+        changed
+    }
+
+    fn is_changed(&self, path: &Path, metadata: &std::fs::Metadata) -> bool {
+        if last_change(metadata).is_none_or(|changed| changed > self.cutoff) {
+            return true;
+        }
+        self.manifest.as_ref().and_then(|manifest| manifest.warmed(path, metadata.len())) == Some(false)
+    }
+
+    /// Files left out as unchanged so far
+    pub fn unchanged(&self) -> u64 {
+        self.unchanged.load(Ordering::Relaxed)
+    }
+}
+
+/// The later of the modification and status-change times
+#[cfg(unix)]
+fn last_change(metadata: &std::fs::Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+
+    let ctime = u64::try_from(metadata.ctime()).ok().map(|secs| UNIX_EPOCH + Duration::new(secs, metadata.ctime_nsec() as u32));
+    metadata.modified().ok().max(ctime)
+}
+
+#[cfg(not(unix))]
+fn last_change(metadata: &std::fs::Metadata) -> Option<SystemTime> {
+    metadata.modified().ok()
+}
+
+impl fmt::Display for ChangeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.cutoff.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        write!(f, "changed since {} UTC", crate::history::utc(secs * 1000))?;
+        if let Some(manifest) = &self.manifest {
+            write!(f, ", or not warmed at their size by the earlier run ({} files logged)", manifest.len())?;
+        }
+        Ok(())
+    }
+}
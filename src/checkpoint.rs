@@ -0,0 +1,188 @@
+//! Resumable runs.
+//!
+//! A checkpoint is an append-only journal with one JSON line per finished file. A run
+//! pointed at an existing journal skips every file it lists and replays the recorded
+//! outcomes into its stats, so the final summary covers all attempts, not just the last
+//! one. The journal is flushed after every batch; a crash loses at most the files of
+//! in-flight batches, which are simply warmed again. Journals named `*.gz` or `*.zst`
+//! are compressed and may be rotated into segments (see [`crate::logfile`]).
+
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::logfile::{LogReader, LogWriter};
+use crate::stats::{FileOutcome, FileStatus, StatsCollector};
+use crate::symlinks::{self, LinkedRoot};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    path: String,
+    bytes: u64,
+    // Absent from journals written before coverage was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes_covered: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes_read: Option<u64>,
+    latency_us: u64,
+    status: String,
+}
+
+fn status_name(status: FileStatus) -> &'static str {
+    match status {
+        FileStatus::Warmed => "warmed",
+        FileStatus::Failed => "failed",
+        FileStatus::Skipped => "skipped",
+    }
+}
+
+fn parse_status(name: &str) -> Option<FileStatus> {
+    match name {
+        "warmed" => Some(FileStatus::Warmed),
+        "failed" => Some(FileStatus::Failed),
+        "skipped" => Some(FileStatus::Skipped),
+        _ => None,
+    }
+}
+
+/// Journal of finished files for one logical run
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    completed: HashSet<PathBuf>,
+    /// Outcomes loaded from earlier attempts, replayed into the next run's stats
+    previous: Vec<(PathBuf, FileOutcome)>,
+    writer: Mutex<LogWriter>,
+}
+
+impl Checkpoint {
+    /// Open (or create) the journal at `path`, loading whatever earlier attempts recorded
+    pub fn open(path: &Path) -> Result<Self, std::io::Error> {
+        Self::open_rotating(path, 0)
+    }
+
+    /// Like [`Checkpoint::open`], starting a new journal segment whenever the current one
+    /// reaches `rotate_bytes` (0 never rotates)
+    pub fn open_rotating(path: &Path, rotate_bytes: u64) -> Result<Self, std::io::Error> {
+        let mut completed = HashSet::new();
+        let mut previous = Vec::new();
+        for line in BufReader::new(LogReader::open(path)?).lines() {
+            let line = line?;
+            // A process killed mid-write leaves a truncated last line; that file is re-warmed
+            let Ok(entry) = serde_json::from_str::<Entry>(&line) else {
+                debug!("Ignoring malformed checkpoint line in {}: {:?}", path.display(), line);
+                continue;
+            };
+            let Some(status) = parse_status(&entry.status) else { continue };
+            let file_path = PathBuf::from(entry.path);
+            if completed.insert(file_path.clone()) {
+                // Older journals don't say; they're taken to have read warmed files in full
+                let bytes_covered = entry.bytes_covered.unwrap_or(if status == FileStatus::Warmed { entry.bytes } else { 0 });
+                let outcome = FileOutcome {
+                    bytes: entry.bytes,
+                    bytes_covered,
+                    bytes_read: entry.bytes_read.unwrap_or(bytes_covered),
+                    latency: Duration::from_micros(entry.latency_us),
+                    status,
+                };
+                previous.push((file_path, outcome));
+            }
+        }
+
+        let writer = LogWriter::append(path, rotate_bytes)?;
+        Ok(Self { path: path.to_path_buf(), completed, previous, writer: Mutex::new(writer) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Count files an earlier attempt journaled through a symlinked target as done under
+    /// the directory it resolves to, so a run given the other spelling resumes
+    pub fn resolve_links(&mut self, linked: &[LinkedRoot]) {
+        if linked.is_empty() {
+            return;
+        }
+        let mut rebased = 0;
+        self.completed.clear();
+        for (path, outcome) in std::mem::take(&mut self.previous) {
+            let path = match symlinks::rebase(&path, linked) {
+                Some(resolved) => {
+                    rebased += 1;
+                    resolved
+                }
+                None => path,
+            };
+            // Journaled under both spellings: the first record wins, as when loading
+            if self.completed.insert(path.clone()) {
+                self.previous.push((path, outcome));
+            }
+        }
+        if rebased > 0 {
+            debug!("{} checkpointed files were recorded through a symlinked target", rebased);
+        }
+    }
+
+    /// Number of files finished by earlier attempts
+    pub fn completed_files(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Bytes covered by the files finished by earlier attempts
+    pub fn completed_bytes(&self) -> u64 {
+        self.previous.iter().map(|(_, outcome)| outcome.bytes).sum()
+    }
+
+    pub fn is_completed(&self, path: &Path) -> bool {
+        self.completed.contains(path)
+    }
+
+    /// Feed the outcomes of earlier attempts into `stats`
+    pub fn replay(&self, stats: &StatsCollector) {
+        for (path, outcome) in &self.previous {
+            stats.record(path, *outcome);
+        }
+    }
+
+    /// Journal a finished file. Paths that aren't valid UTF-8 can't be journaled and are
+    /// warmed again on resume.
+    pub fn record(&self, path: &Path, outcome: &FileOutcome) {
+        let Some(path_str) = path.to_str() else {
+            debug!("Not checkpointing non-UTF-8 path {}", path.display());
+            return;
+        };
+        let entry = Entry {
+            path: path_str.to_string(),
+            bytes: outcome.bytes,
+            bytes_covered: Some(outcome.bytes_covered),
+            bytes_read: Some(outcome.bytes_read),
+            latency_us: outcome.latency.as_micros() as u64,
+            status: status_name(outcome.status).to_string(),
+        };
+        let mut line = serde_json::to_string(&entry).expect("checkpoint entries always serialize");
+        line.push('\n');
+        if let Err(e) = self.writer.lock().unwrap().write_record(line.as_bytes()) {
+            warn!("Failed to write checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Push buffered records to the file
+    pub fn flush(&self) {
+        if let Err(e) = self.writer.lock().unwrap().flush() {
+            warn!("Failed to flush checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Complete the journal file, e.g. write the trailer of a compressed one. Records
+    /// journaled afterwards go to a new segment.
+    pub fn finish(&self) {
+        if let Err(e) = self.writer.lock().unwrap().finish() {
+            warn!("Failed to finish checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+}
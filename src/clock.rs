@@ -0,0 +1,119 @@
+//! Running time that leaves out pauses.
+//!
+//! `Instant` keeps counting while the process is stopped: `SIGSTOP`/Ctrl-Z, a frozen
+//! cgroup (`docker pause`, systemd's freezer) or a VM that was paused. A run stopped for
+//! an hour would report a fraction of its real throughput, and the ETA would assume
+//! that rate. A monitor thread wakes every [`TICK`]; when it wakes much later than
+//! asked, the process wasn't running in between, and the gap is added to the
+//! [`PauseClock`]. [`Stopwatch`] subtracts the pauses that fall in its interval, so
+//! durations, throughput and ETAs cover running time only. Wall-clock time is still
+//! available for the "total execution time" figures.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Once, OnceLock};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+/// How often the monitor thread wakes
+pub const TICK: Duration = Duration::from_millis(250);
+/// Lateness beyond which a wakeup counts as a pause; below it, it's scheduling noise
+pub const PAUSE_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// Total time the process has spent paused
+#[derive(Debug, Default)]
+pub struct PauseClock {
+    paused_us: AtomicU64,
+    pauses: AtomicU64,
+}
+
+impl PauseClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The process-wide clock fed by [`start_monitor`]
+    pub fn global() -> &'static PauseClock {
+        static GLOBAL: OnceLock<PauseClock> = OnceLock::new();
+        GLOBAL.get_or_init(PauseClock::new)
+    }
+
+    /// Count `duration` as paused
+    pub fn add_pause(&self, duration: Duration) {
+        self.paused_us.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.pauses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Time paused so far
+    pub fn paused(&self) -> Duration {
+        Duration::from_micros(self.paused_us.load(Ordering::Relaxed))
+    }
+
+    /// Pauses so far; changes whenever one is detected
+    pub fn pauses(&self) -> u64 {
+        self.pauses.load(Ordering::Relaxed)
+    }
+}
+
+/// How much of a gap between two monitor wakeups, `tick` apart, was a pause
+pub fn pause_in(gap: Duration, tick: Duration) -> Option<Duration> {
+    (gap > tick + PAUSE_THRESHOLD).then(|| gap - tick)
+}
+
+/// Start the monitor thread feeding [`PauseClock::global`]. Later calls do nothing.
+/// Runs on its own thread rather than the runtime, so a busy runtime isn't mistaken
+/// for a stopped process.
+pub fn start_monitor() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        let spawned = std::thread::Builder::new().name("warmer-clock".into()).spawn(|| {
+            let mut last = Instant::now();
+            loop {
+                std::thread::sleep(TICK);
+                let now = Instant::now();
+                if let Some(pause) = pause_in(now - last, TICK) {
+                    info!("Resumed after being paused for {:.1?}; the pause doesn't count toward throughput or ETA", pause);
+                    PauseClock::global().add_pause(pause);
+                }
+                last = now;
+            }
+        });
+        if let Err(e) = spawned {
+            log::debug!("Not tracking pauses: {}", e);
+        }
+    });
+}
+
+/// Measures running time: wall-clock time minus the pauses of a [`PauseClock`]
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    clock: &'static PauseClock,
+    started: Instant,
+    paused_before: Duration,
+}
+
+impl Stopwatch {
+    /// Start measuring against the global clock
+    pub fn start() -> Self {
+        Self::start_on(PauseClock::global())
+    }
+
+    pub fn start_on(clock: &'static PauseClock) -> Self {
+        Self { clock, started: Instant::now(), paused_before: clock.paused() }
+    }
+
+    /// Time since the start, including pauses
+    pub fn wall(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Time paused since the start
+    pub fn paused(&self) -> Duration {
+        self.clock.paused().saturating_sub(self.paused_before)
+    }
+
+    /// Time since the start, excluding pauses
+    pub fn active(&self) -> Duration {
+        self.wall().saturating_sub(self.paused())
+    }
+}
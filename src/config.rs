@@ -0,0 +1,95 @@
+//! TOML configuration files.
+//!
+//! A config file sets defaults for any command-line option, keyed by the option's name
+//! (`queue_depth = 64`, `direct-io = true`, `include = ["*.parquet"]`), plus named
+//! profiles under `[profile.NAME]` whose settings apply on top of the top-level ones.
+//! Values are handed to the argument parser as defaults, so flags given on the command
+//! line always win: CLI > profile > top-level config > built-in defaults.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// Options that only make sense on the command line
+const RESERVED_KEYS: &[&str] = &["config", "config_profile", "help", "version"];
+
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFile {
+    settings: BTreeMap<String, toml::Value>,
+    profiles: BTreeMap<String, BTreeMap<String, toml::Value>>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read config file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let table: toml::Table = text.parse()?;
+        let mut config = Self::default();
+        for (key, value) in table {
+            if key != "profile" {
+                config.settings.insert(normalize(&key), value);
+                continue;
+            }
+            let toml::Value::Table(profiles) = value else {
+                bail!("'profile' must be a table of [profile.NAME] sections");
+            };
+            for (name, profile) in profiles {
+                let toml::Value::Table(profile) = profile else {
+                    bail!("profile '{}' must be a table", name);
+                };
+                let profile = profile.into_iter().map(|(key, value)| (normalize(&key), value)).collect();
+                config.profiles.insert(name, profile);
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Settings as option name → values, with `profile` (if any) applied on top of the
+    /// top-level settings. Scalars become one value; arrays one value per element.
+    pub fn resolve(&self, profile: Option<&str>) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut merged: BTreeMap<&str, &toml::Value> = self.settings.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        if let Some(name) = profile {
+            let Some(settings) = self.profiles.get(name) else {
+                let known: Vec<&str> = self.profile_names().collect();
+                bail!("unknown profile '{}' (defined: {})", name, if known.is_empty() { "none".to_string() } else { known.join(", ") });
+            };
+            merged.extend(settings.iter().map(|(k, v)| (k.as_str(), v)));
+        }
+
+        let mut resolved = BTreeMap::new();
+        for (key, value) in merged {
+            if RESERVED_KEYS.contains(&key) {
+                bail!("'{}' can only be given on the command line", key);
+            }
+            let values = match value {
+                toml::Value::Array(items) => items.iter().map(|item| scalar(key, item)).collect::<Result<_>>()?,
+                other => vec![scalar(key, other)?],
+            };
+            resolved.insert(key.to_string(), values);
+        }
+        Ok(resolved)
+    }
+}
+
+/// Option names are accepted in flag form (`queue-depth`) or field form (`queue_depth`)
+fn normalize(key: &str) -> String {
+    key.replace('-', "_")
+}
+
+fn scalar(key: &str, value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => bail!("'{}' must be a string, number, boolean or array of those", key),
+    }
+}
@@ -0,0 +1,378 @@
+//! Status and control API (`--status-listen`).
+//!
+//! A long warm job sometimes needs a hand: the application came up early and wants the
+//! volume to itself for a while, or the job should run slower (or faster) than it was
+//! started with. Restarting loses the in-flight files and, without a checkpoint, the
+//! progress. With `--status-listen ADDR` the warmer serves a small HTTP/1.1 API instead:
+//!
+//! | Request                    | Effect                                                |
+//! |----------------------------|-------------------------------------------------------|
+//! | `GET /status`              | Progress, counters and the slowest in-flight files    |
+//! | `POST /pause`              | Start no new files; files being read still finish     |
+//! | `POST /resume`             | Start files again                                     |
+//! | `POST /throttle?mbps=N`    | Read at most N MB/s across the run; `0` lifts the cap |
+//!
+//! Every response is a JSON object; the control requests answer with the new settings.
+//! [`Control`] is the state the server shares with the pipeline, which waits on it
+//! before each file. The throttle reserves a file's bytes up front, like `--pace`, so a
+//! large file is read at full speed and the files after it wait for its share. Changing
+//! the settings wakes the waiting files, so a raised cap takes effect at once.
+//!
+//! The API has no authentication; bind it to a loopback address unless the network
+//! around the host is trusted.
+//!
+//! The pause doesn't need the API: [`listen_for_pause_signals`] pauses the same
+//! [`Control`] on SIGUSR1 and resumes it on SIGUSR2, and says so on stderr, including
+//! when the files that were in flight have finished and the disk is left alone.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+
+use crate::anonymize;
+use crate::events::WarmEvent;
+use crate::introspect::Introspection;
+use crate::live::LiveStats;
+use crate::progress::ByteProgress;
+
+const MB: f64 = 1024.0 * 1024.0;
+/// How often the throughput average is updated
+const TICK: Duration = Duration::from_secs(1);
+/// Longest request head accepted; the API has no request bodies
+const MAX_REQUEST: usize = 8192;
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// In-flight files listed in `/status`
+const STATUS_SLOWEST: usize = 10;
+/// How often a signalled pause checks whether the files in flight have finished
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+
+/// What operators have asked for through the API
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Settings {
+    pub paused: bool,
+    /// Cap on the run's read rate, in bytes per second
+    pub throttle: Option<f64>,
+}
+
+/// Pause and throttle state shared by the API server and the pipeline. Cheap to clone;
+/// clones share the same state.
+#[derive(Debug, Clone)]
+pub struct Control {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    settings: watch::Sender<Settings>,
+    /// When the bandwidth reserved under the throttle so far runs out
+    next: Mutex<Instant>,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self { inner: Arc::new(Inner { settings: watch::channel(Settings::default()).0, next: Mutex::new(Instant::now()) }) }
+    }
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn settings(&self) -> Settings {
+        *self.inner.settings.borrow()
+    }
+
+    pub fn pause(&self) {
+        self.update(|settings| settings.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|settings| settings.paused = false);
+    }
+
+    /// Cap reads at `mbps` MB/s; `None`, zero or a negative rate lifts the cap
+    pub fn set_throttle(&self, mbps: Option<f64>) {
+        let throttle = mbps.filter(|&mbps| mbps > 0.0).map(|mbps| mbps * MB);
+        self.update(|settings| settings.throttle = throttle);
+    }
+
+    /// Apply `change` and start the throttle's schedule over: files waiting under the old
+    /// settings reserve their bytes again
+    fn update(&self, change: impl FnOnce(&mut Settings)) {
+        *self.inner.next.lock().unwrap() = Instant::now();
+        self.inner.settings.send_if_modified(|settings| {
+            let before = *settings;
+            change(settings);
+            *settings != before
+        });
+    }
+
+    /// Reserve bandwidth for reading `bytes` under the throttle, as of `now`. Returns how
+    /// long to wait before starting the read; zero without a throttle.
+    pub fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let Some(rate) = self.settings().throttle else { return Duration::ZERO };
+        let mut next = self.inner.next.lock().unwrap();
+        let start = (*next).max(now);
+        *next = start + Duration::from_secs_f64(bytes as f64 / rate);
+        start - now
+    }
+
+    /// Wait until the run isn't paused and the throttle leaves room for `bytes`
+    pub async fn wait_turn(&self, bytes: u64) {
+        let mut settings = self.inner.settings.subscribe();
+        loop {
+            // The sender lives as long as `self`
+            let _ = settings.wait_for(|settings| !settings.paused).await;
+            let wait = self.reserve(bytes, Instant::now());
+            if wait.is_zero() {
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => return,
+                _ = settings.changed() => {}
+            }
+        }
+    }
+}
+
+/// Pause on SIGUSR1 and resume on SIGUSR2, printing each change to stderr. A pause
+/// starts no new files and lets the ones in flight finish; once they have, that's
+/// printed too, so operators know the disk is theirs.
+#[cfg(unix)]
+pub fn listen_for_pause_signals(control: Control, introspection: Introspection) -> Result<tokio::task::JoinHandle<()>, std::io::Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+    Ok(tokio::spawn(async move {
+        let mut draining = false;
+        let mut poll = tokio::time::interval(DRAIN_POLL);
+        loop {
+            tokio::select! {
+                received = usr1.recv() => {
+                    if received.is_none() {
+                        break;
+                    }
+                    if control.settings().paused {
+                        eprintln!("⏸️  Already paused; send SIGUSR2 to resume");
+                        continue;
+                    }
+                    control.pause();
+                    let in_flight = introspection.snapshot().in_flight.len();
+                    info!("Paused by SIGUSR1");
+                    eprintln!("⏸️  Paused by SIGUSR1: starting no new files, waiting for {} in flight to finish; send SIGUSR2 to resume", in_flight);
+                    draining = true;
+                }
+                received = usr2.recv() => {
+                    if received.is_none() {
+                        break;
+                    }
+                    if !control.settings().paused {
+                        eprintln!("▶️  Not paused; SIGUSR2 ignored");
+                        continue;
+                    }
+                    control.resume();
+                    draining = false;
+                    info!("Resumed by SIGUSR2");
+                    eprintln!("▶️  Resumed by SIGUSR2");
+                }
+                _ = poll.tick(), if draining => {
+                    if !control.settings().paused {
+                        // Resumed through the API
+                        draining = false;
+                    } else if introspection.snapshot().in_flight.is_empty() {
+                        eprintln!("⏸️  Paused: no reads in flight until SIGUSR2");
+                        draining = false;
+                    }
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn listen_for_pause_signals(_control: Control, _introspection: Introspection) -> Result<tokio::task::JoinHandle<()>, std::io::Error> {
+    Ok(tokio::spawn(async {}))
+}
+
+/// Bind the API's listener, so a bad address fails the run before warming starts
+pub async fn bind(addr: SocketAddr) -> Result<TcpListener, std::io::Error> {
+    TcpListener::bind(addr).await
+}
+
+/// Answer requests on `listener` until the event stream ends, with status built from
+/// `progress`, `introspection` and the totals in `events`
+pub async fn serve(
+    listener: TcpListener,
+    control: Control,
+    progress: ByteProgress,
+    introspection: Introspection,
+    mut events: mpsc::UnboundedReceiver<WarmEvent>,
+) {
+    let stats = Arc::new(Mutex::new(LiveStats::new()));
+    let mut ticker = tokio::time::interval(TICK);
+    ticker.tick().await; // the first tick completes immediately
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => stats.lock().unwrap().record(&event),
+                None => break,
+            },
+            _ = ticker.tick() => stats.lock().unwrap().sample(Instant::now()),
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    let api = Api { control: control.clone(), progress: progress.clone(), introspection: introspection.clone(), stats: Arc::clone(&stats) };
+                    tokio::spawn(async move {
+                        if let Err(e) = api.handle(stream).await {
+                            debug!("Control API connection from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => debug!("Control API accept failed: {}", e),
+            },
+        }
+    }
+}
+
+/// What one connection needs to answer
+struct Api {
+    control: Control,
+    progress: ByteProgress,
+    introspection: Introspection,
+    stats: Arc<Mutex<LiveStats>>,
+}
+
+impl Api {
+    async fn handle(&self, mut stream: TcpStream) -> Result<(), std::io::Error> {
+        let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+            Ok(head) => head?,
+            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "no request received")),
+        };
+        let (status, body) = match head.as_deref().and_then(parse_request_line) {
+            Some((method, target)) => self.route(method, target),
+            None => (400, json!({ "error": "malformed request" })),
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            reason(status),
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    fn route(&self, method: &str, target: &str) -> (u16, Value) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let expected = match path {
+            "/status" => "GET",
+            "/pause" | "/resume" | "/throttle" => "POST",
+            _ => return (404, json!({ "error": format!("no endpoint {}", path) })),
+        };
+        if method != expected {
+            return (405, json!({ "error": format!("{} needs {}", path, expected) }));
+        }
+        match path {
+            "/status" => return (200, self.status()),
+            "/pause" => {
+                info!("Paused through the control API");
+                self.control.pause();
+            }
+            "/resume" => {
+                info!("Resumed through the control API");
+                self.control.resume();
+            }
+            _ => {
+                let mbps = query.split('&').find_map(|pair| pair.strip_prefix("mbps="));
+                match mbps.map(str::parse::<f64>) {
+                    Some(Ok(mbps)) if mbps.is_finite() && mbps >= 0.0 => {
+                        info!("Throttle set to {} through the control API", if mbps > 0.0 { format!("{} MB/s", mbps) } else { "none".to_string() });
+                        self.control.set_throttle(Some(mbps));
+                    }
+                    _ => return (400, json!({ "error": "expected ?mbps=N with N a number of at least 0" })),
+                }
+            }
+        }
+        (200, settings_json(self.control.settings()))
+    }
+
+    fn status(&self) -> Value {
+        let live = self.stats.lock().unwrap().snapshot();
+        let stages = self.introspection.snapshot();
+        let (done, total) = (self.progress.done_bytes(), self.progress.total().max(self.progress.done_bytes()));
+        let mut status = settings_json(self.control.settings());
+        status["uptime_secs"] = json!(live.uptime.as_secs_f64());
+        status["files"] = json!({
+            "discovered": stages.discovered,
+            "warmed": live.warmed,
+            "failed": live.failed,
+            "skipped": live.skipped,
+            "queued": stages.queued,
+            "in_flight": stages.in_flight.len(),
+        });
+        status["discovery_done"] = json!(stages.discovery_done);
+        status["bytes"] = json!({
+            "done": done,
+            "total": total,
+            "total_exact": self.progress.is_exact(),
+            "percent": if total == 0 { 0.0 } else { done as f64 * 100.0 / total as f64 },
+        });
+        status["throughput_mbps"] = json!(live.throughput_ewma / MB);
+        status["in_flight"] = stages
+            .in_flight
+            .iter()
+            .take(STATUS_SLOWEST)
+            .map(|file| json!({ "path": anonymize::display(&file.path).to_string(), "size": file.size, "elapsed_secs": file.elapsed.as_secs_f64() }))
+            .collect();
+        status
+    }
+}
+
+fn settings_json(settings: Settings) -> Value {
+    json!({
+        "state": if settings.paused { "paused" } else { "running" },
+        "throttle_mbps": settings.throttle.map(|rate| rate / MB),
+    })
+}
+
+/// Read up to the blank line ending the request head; `None` if it's too long or the
+/// connection closes first
+async fn read_head(stream: &mut TcpStream) -> Result<Option<String>, std::io::Error> {
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || head.len() + n > MAX_REQUEST {
+            return Ok(None);
+        }
+        head.extend_from_slice(&chunk[..n]);
+    }
+    Ok(String::from_utf8(head).ok())
+}
+
+/// Method and target of a request line such as `GET /status HTTP/1.1`
+fn parse_request_line(head: &str) -> Option<(&str, &str)> {
+    let mut parts = head.lines().next()?.split_whitespace();
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+    version.starts_with("HTTP/1.").then_some((method, target))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Error",
+    }
+}
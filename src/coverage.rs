@@ -0,0 +1,150 @@
+//! Strict coverage accounting (`--strict-coverage`).
+//!
+//! Restore runbooks that must guarantee hydration can't accept "warmed" for a file that
+//! only got `fadvise` advice, was sampled in sparse mode, was skipped for its size or
+//! stopped reading after an error. [`CoverageAudit`] follows the event stream and sorts
+//! every file into fully read or one of those shortfalls, keeping a few example paths
+//! of each so the report says exactly what wasn't hydrated.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use tokio::sync::mpsc;
+
+use crate::anonymize;
+use crate::events::WarmEvent;
+use crate::stats::FileStatus;
+use crate::warming::Coverage;
+
+/// Example paths kept per shortfall
+const EXAMPLES: usize = 10;
+
+/// Why a file doesn't count as fully hydrated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Shortfall {
+    Failed,
+    Skipped,
+    /// Reading stopped early after an error
+    Partial,
+    /// Only sampled blocks were read
+    Sampled,
+    /// Only advice was given to the kernel
+    Advisory,
+}
+
+impl Shortfall {
+    fn describe(self) -> &'static str {
+        match self {
+            Shortfall::Failed => "failed to warm",
+            Shortfall::Skipped => "skipped (e.g. over --max-file-size)",
+            Shortfall::Partial => "only partly read after a read error",
+            Shortfall::Sampled => "only sampled (--sparse-large-files)",
+            Shortfall::Advisory => "only given advisory hints (fadvise/madvise), not read",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Tally {
+    files: u64,
+    bytes: u64,
+    examples: Vec<PathBuf>,
+}
+
+/// Per-file coverage of a run; see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct CoverageAudit {
+    covered_files: u64,
+    covered_bytes: u64,
+    shortfalls: BTreeMap<Shortfall, Tally>,
+    discovery_errors: u64,
+}
+
+impl CoverageAudit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &WarmEvent) {
+        let WarmEvent::FileFinished { path, bytes, status, coverage, .. } = event;
+        let shortfall = match (status, coverage) {
+            (FileStatus::Failed, _) => Shortfall::Failed,
+            (FileStatus::Skipped, _) => Shortfall::Skipped,
+            (FileStatus::Warmed, Some(Coverage::Full)) => {
+                self.covered_files += 1;
+                self.covered_bytes += bytes;
+                return;
+            }
+            (FileStatus::Warmed, Some(Coverage::Sampled)) => Shortfall::Sampled,
+            (FileStatus::Warmed, Some(Coverage::Advisory)) => Shortfall::Advisory,
+            // A warmed file without coverage information can't be vouched for either
+            (FileStatus::Warmed, Some(Coverage::Partial) | None) => Shortfall::Partial,
+        };
+        let tally = self.shortfalls.entry(shortfall).or_default();
+        tally.files += 1;
+        tally.bytes += bytes;
+        if tally.examples.len() < EXAMPLES {
+            tally.examples.push(path.clone());
+        }
+    }
+
+    /// Directory entries the walk couldn't read: whatever was under them went unwarmed
+    pub fn add_discovery_errors(&mut self, errors: u64) {
+        self.discovery_errors += errors;
+    }
+
+    /// Every discovered file was fully read and nothing was missed during discovery
+    pub fn is_complete(&self) -> bool {
+        self.shortfalls.is_empty() && self.discovery_errors == 0
+    }
+
+    pub fn covered_files(&self) -> u64 {
+        self.covered_files
+    }
+
+    /// Files counted against `shortfall`
+    pub fn shortfall_files(&self, shortfall: Shortfall) -> u64 {
+        self.shortfalls.get(&shortfall).map_or(0, |tally| tally.files)
+    }
+}
+
+impl fmt::Display for CoverageAudit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MB: f64 = 1024.0 * 1024.0;
+        let uncovered: u64 = self.shortfalls.values().map(|tally| tally.files).sum();
+        writeln!(
+            f,
+            "Strict coverage: {} files ({:.2} MB) fully read, {} files not fully read",
+            self.covered_files,
+            self.covered_bytes as f64 / MB,
+            uncovered
+        )?;
+        for (shortfall, tally) in &self.shortfalls {
+            writeln!(f, "  {} files ({:.2} MB) {}", tally.files, tally.bytes as f64 / MB, shortfall.describe())?;
+            for path in &tally.examples {
+                writeln!(f, "    {}", anonymize::display(path))?;
+            }
+            if tally.files > tally.examples.len() as u64 {
+                writeln!(f, "    ... and {} more", tally.files - tally.examples.len() as u64)?;
+            }
+        }
+        if self.discovery_errors > 0 {
+            writeln!(
+                f,
+                "  {} directory entries couldn't be read during discovery; files under them were never warmed",
+                self.discovery_errors
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Audit `events` until the stream ends
+pub async fn run_audit(mut events: mpsc::UnboundedReceiver<WarmEvent>) -> CoverageAudit {
+    let mut audit = CoverageAudit::new();
+    while let Some(event) = events.recv().await {
+        audit.record(&event);
+    }
+    audit
+}
@@ -0,0 +1,423 @@
+//! Live terminal dashboard (`--tui`).
+//!
+//! Two progress bars say how far a run has got, not how it's going. On a warm that takes
+//! hours the questions are different: what each strategy is achieving, how read latency
+//! is spread, which errors are piling up, which files are holding slots, and whether the
+//! devices' queues are full or starved. [`DashboardState`] gathers the per-file part from
+//! the pipeline's events, [`DeviceSampler`] reads each target device's requests in flight
+//! and read rate from sysfs, and with the `tui` feature [`spawn`] draws it all with
+//! ratatui on the terminal's alternate screen a few times a second until the run ends.
+//! `q` or Ctrl-C stops the run as SIGINT would; the summary prints once the terminal is
+//! restored.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+use crate::events::WarmEvent;
+use crate::introspect::Introspection;
+use crate::progress::ByteProgress;
+use crate::shutdown::Shutdown;
+use crate::stats::FileStatus;
+
+/// Upper bounds of the latency histogram's buckets; a last bucket takes anything slower
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000];
+/// Finished files kept as the slowest
+pub const SLOWEST_FILES: usize = 10;
+/// Throughput is averaged over this much of the recent past
+pub const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// How often the dashboard is redrawn
+#[cfg(feature = "tui")]
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// Bytes finished over the last [`RATE_WINDOW`]
+#[derive(Debug, Clone, Default)]
+pub struct Throughput {
+    recent: VecDeque<(Instant, u64)>,
+}
+
+impl Throughput {
+    pub fn add(&mut self, now: Instant, bytes: u64) {
+        self.recent.push_back((now, bytes));
+        while self.recent.front().is_some_and(|(at, _)| now.duration_since(*at) > RATE_WINDOW) {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Bytes per second over the window ending at `now`
+    pub fn rate(&self, now: Instant) -> f64 {
+        let bytes: u64 = self.recent.iter().filter(|(at, _)| now.duration_since(*at) <= RATE_WINDOW).map(|(_, bytes)| bytes).sum();
+        bytes as f64 / RATE_WINDOW.as_secs_f64()
+    }
+}
+
+/// Files one warming method finished
+#[derive(Debug, Clone, Default)]
+pub struct MethodStats {
+    pub files: u64,
+    pub bytes: u64,
+    pub throughput: Throughput,
+}
+
+/// A finished file among the slowest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowFile {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub latency: Duration,
+    pub status: FileStatus,
+}
+
+/// What the dashboard shows about finished files, built from the pipeline's events
+#[derive(Debug, Clone, Default)]
+pub struct DashboardState {
+    /// By the method that warmed the file (e.g. `readahead_full`)
+    pub methods: HashMap<&'static str, MethodStats>,
+    /// Warmed files per [`LATENCY_BUCKETS_MS`] bucket, then the slower ones
+    pub latency: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    /// Failed files by error message
+    pub errors: HashMap<String, u64>,
+    pub skipped: u64,
+    /// Slowest first
+    pub slowest: Vec<SlowFile>,
+    pub throughput: Throughput,
+}
+
+impl DashboardState {
+    pub fn record(&mut self, event: &WarmEvent) {
+        self.record_at(event, Instant::now());
+    }
+
+    /// Count a finished file as of `now`
+    pub fn record_at(&mut self, event: &WarmEvent, now: Instant) {
+        let WarmEvent::FileFinished { path, bytes, latency, status, method, error, .. } = event;
+        match status {
+            FileStatus::Warmed => {
+                let method = self.methods.entry(method.unwrap_or("unknown")).or_default();
+                method.files += 1;
+                method.bytes += bytes;
+                method.throughput.add(now, *bytes);
+                self.throughput.add(now, *bytes);
+                self.latency[latency_bucket(*latency)] += 1;
+            }
+            FileStatus::Failed => *self.errors.entry(error.clone().unwrap_or_else(|| "unknown error".to_string())).or_default() += 1,
+            FileStatus::Skipped => self.skipped += 1,
+        }
+        if *status != FileStatus::Skipped && (self.slowest.len() < SLOWEST_FILES || self.slowest.last().is_some_and(|slow| slow.latency < *latency)) {
+            let at = self.slowest.partition_point(|slow| slow.latency >= *latency);
+            self.slowest.insert(at, SlowFile { path: path.clone(), bytes: *bytes, latency: *latency, status: *status });
+            self.slowest.truncate(SLOWEST_FILES);
+        }
+    }
+
+    /// Methods by bytes warmed, most first
+    pub fn methods_by_bytes(&self) -> Vec<(&'static str, &MethodStats)> {
+        let mut methods: Vec<_> = self.methods.iter().map(|(name, stats)| (*name, stats)).collect();
+        methods.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        methods
+    }
+
+    /// Error messages by how often they occurred, most first
+    pub fn errors_by_count(&self) -> Vec<(&str, u64)> {
+        let mut errors: Vec<_> = self.errors.iter().map(|(message, count)| (message.as_str(), *count)).collect();
+        errors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        errors
+    }
+}
+
+/// The histogram bucket `latency` falls in
+pub fn latency_bucket(latency: Duration) -> usize {
+    let ms = latency.as_millis();
+    LATENCY_BUCKETS_MS.iter().position(|&bound| ms < u128::from(bound)).unwrap_or(LATENCY_BUCKETS_MS.len())
+}
+
+/// Labels of the histogram's buckets: `<1ms` … `<1s`, `≥1s`
+pub fn latency_labels() -> Vec<String> {
+    let label = |ms: u64| if ms >= 1000 { format!("{}s", ms / 1000) } else { format!("{}ms", ms) };
+    let mut labels: Vec<String> = LATENCY_BUCKETS_MS.iter().map(|&ms| format!("<{}", label(ms))).collect();
+    labels.push(format!("≥{}", label(LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1])));
+    labels
+}
+
+/// Feed the pipeline's events into `state` until the pipeline drops its event bus
+pub fn collect(state: Arc<Mutex<DashboardState>>, mut events: mpsc::UnboundedReceiver<WarmEvent>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            state.lock().unwrap().record(&event);
+        }
+    })
+}
+
+/// Counters from a block device's sysfs `stat` file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskStat {
+    pub reads: u64,
+    /// 512-byte sectors read
+    pub sectors_read: u64,
+    /// Requests issued to the device and not yet completed
+    pub in_flight: u64,
+    /// Milliseconds the device had requests in flight
+    pub io_ticks: u64,
+}
+
+/// Parse `/sys/block/<dev>/stat` (see the kernel's `Documentation/block/stat.rst`)
+pub fn parse_disk_stat(text: &str) -> Option<DiskStat> {
+    let fields: Vec<u64> = text.split_whitespace().map(|field| field.parse().ok()).collect::<Option<_>>()?;
+    Some(DiskStat { reads: *fields.first()?, sectors_read: *fields.get(2)?, in_flight: *fields.get(8)?, io_ticks: *fields.get(9)? })
+}
+
+/// A device's queue and read rate between two samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSample {
+    pub name: String,
+    pub in_flight: u64,
+    pub reads_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// Share of the time the device was busy, 0 to 1
+    pub utilization: f64,
+}
+
+/// Samples one block device's sysfs counters
+#[derive(Debug, Clone)]
+pub struct DeviceSampler {
+    pub name: String,
+    /// The device's sysfs directory, holding `stat`
+    dir: PathBuf,
+    last: Option<(Instant, DiskStat)>,
+}
+
+impl DeviceSampler {
+    pub fn new(name: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        Self { name: name.into(), dir: dir.into(), last: None }
+    }
+
+    /// One sampler for each distinct device behind `directories`
+    #[cfg(target_os = "linux")]
+    pub fn for_directories(directories: &[PathBuf]) -> Vec<DeviceSampler> {
+        let mut samplers: Vec<DeviceSampler> = Vec::new();
+        for dir in directories {
+            let Some(sys) = crate::disk::sys_block_link(dir).ok().and_then(|link| std::fs::canonicalize(link).ok()) else { continue };
+            let name = sys.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            if !samplers.iter().any(|sampler| sampler.name == name) {
+                samplers.push(DeviceSampler::new(name, sys));
+            }
+        }
+        samplers
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn for_directories(_directories: &[PathBuf]) -> Vec<DeviceSampler> {
+        Vec::new()
+    }
+
+    /// Read the counters; rates cover the time since the previous sample and are zero
+    /// on the first
+    pub fn sample(&mut self, now: Instant) -> Option<DeviceSample> {
+        let stat = parse_disk_stat(&std::fs::read_to_string(self.dir.join("stat")).ok()?)?;
+        let (reads_per_sec, bytes_per_sec, utilization) = match self.last {
+            Some((at, last)) if now > at => {
+                let secs = now.duration_since(at).as_secs_f64();
+                (
+                    stat.reads.saturating_sub(last.reads) as f64 / secs,
+                    (stat.sectors_read.saturating_sub(last.sectors_read) * 512) as f64 / secs,
+                    (stat.io_ticks.saturating_sub(last.io_ticks) as f64 / 1000.0 / secs).min(1.0),
+                )
+            }
+            _ => (0.0, 0.0, 0.0),
+        };
+        self.last = Some((now, stat));
+        Some(DeviceSample { name: self.name.clone(), in_flight: stat.in_flight, reads_per_sec, bytes_per_sec, utilization })
+    }
+}
+
+/// Everything the dashboard reads while it's up
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    pub state: Arc<Mutex<DashboardState>>,
+    pub introspection: Introspection,
+    pub progress: ByteProgress,
+    pub devices: Vec<DeviceSampler>,
+    /// Triggered by `q` and Ctrl-C, which the terminal no longer turns into SIGINT
+    pub shutdown: Shutdown,
+}
+
+/// A running dashboard; [`DashboardHandle::finish`] takes it down and restores the terminal
+#[derive(Debug)]
+pub struct DashboardHandle {
+    done: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<std::io::Result<()>>,
+}
+
+impl DashboardHandle {
+    pub fn finish(self) -> std::io::Result<()> {
+        self.done.store(true, Ordering::Relaxed);
+        self.thread.join().unwrap_or_else(|_| Err(std::io::Error::other("the dashboard thread panicked")))
+    }
+}
+
+/// Take over the terminal and redraw the dashboard until [`DashboardHandle::finish`]
+#[cfg(feature = "tui")]
+pub fn spawn(mut dashboard: Dashboard) -> DashboardHandle {
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+    let done = Arc::new(AtomicBool::new(false));
+    let finished = Arc::clone(&done);
+    let thread = std::thread::Builder::new()
+        .name("dashboard".to_string())
+        .spawn(move || {
+            let mut terminal = ratatui::try_init()?;
+            let mut draw = || -> std::io::Result<()> {
+                while !finished.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    let devices: Vec<DeviceSample> = dashboard.devices.iter_mut().filter_map(|device| device.sample(now)).collect();
+                    let pipeline = dashboard.introspection.snapshot();
+                    let state = dashboard.state.lock().unwrap().clone();
+                    terminal.draw(|frame| render::draw(frame, &state, &pipeline, &devices, &dashboard.progress, now))?;
+                    if event::poll(REFRESH)? {
+                        if let Event::Key(key) = event::read()? {
+                            let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+                            if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+                                dashboard.shutdown.trigger(libc::SIGINT);
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            };
+            let result = draw();
+            ratatui::restore();
+            result
+        })
+        .expect("failed to start the dashboard thread");
+    DashboardHandle { done, thread }
+}
+
+/// Without the 'tui' feature, `--tui` is downgraded at startup (see [`crate::support`])
+#[cfg(not(feature = "tui"))]
+pub fn spawn(_dashboard: Dashboard) -> DashboardHandle {
+    let thread = std::thread::spawn(|| Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "built without the 'tui' feature")));
+    DashboardHandle { done: Arc::new(AtomicBool::new(true)), thread }
+}
+
+#[cfg(feature = "tui")]
+mod render {
+    use std::time::Instant;
+
+    use indicatif::HumanBytes;
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::widgets::{BarChart, Block, Gauge, Row, Table};
+    use ratatui::Frame;
+
+    use super::{latency_labels, DashboardState, DeviceSample, SLOWEST_FILES};
+    use crate::anonymize;
+    use crate::introspect::IntrospectionSnapshot;
+    use crate::progress::ByteProgress;
+
+    pub(super) fn draw(frame: &mut Frame, state: &DashboardState, pipeline: &IntrospectionSnapshot, devices: &[DeviceSample], progress: &ByteProgress, now: Instant) {
+        let [top, middle, bottom] = Layout::vertical([Constraint::Length(3), Constraint::Percentage(40), Constraint::Fill(1)]).areas(frame.area());
+        let [methods_area, latency_area] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(middle);
+        let [slowest_area, side] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(bottom);
+        let [queues_area, errors_area] = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(side);
+        let header = Style::default().add_modifier(Modifier::BOLD);
+
+        // Overall progress
+        let (done, total) = (progress.done_bytes(), progress.total());
+        let ratio = if total > 0 { (done as f64 / total as f64).min(1.0) } else { 0.0 };
+        let label = format!(
+            "{} of {}{} · {} files · {}/s",
+            HumanBytes(done),
+            if progress.is_exact() { "" } else { "~" },
+            HumanBytes(total),
+            progress.done_files(),
+            HumanBytes(state.throughput.rate(now) as u64)
+        );
+        let title = format!(" rust-cache-warmer · {:.0?} · q to stop ", pipeline.uptime);
+        frame.render_widget(Gauge::default().block(Block::bordered().title(title)).gauge_style(Style::default().fg(Color::Green)).ratio(ratio).label(label), top);
+
+        // Throughput per method
+        let rows: Vec<Row> = state
+            .methods_by_bytes()
+            .into_iter()
+            .map(|(name, stats)| {
+                let share = if done > 0 { stats.bytes as f64 * 100.0 / done as f64 } else { 0.0 };
+                Row::new(vec![
+                    name.to_string(),
+                    stats.files.to_string(),
+                    HumanBytes(stats.bytes).to_string(),
+                    format!("{}/s", HumanBytes(stats.throughput.rate(now) as u64)),
+                    format!("{:.0}%", share),
+                ])
+            })
+            .collect();
+        let widths = [Constraint::Fill(2), Constraint::Length(9), Constraint::Length(11), Constraint::Length(13), Constraint::Length(5)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["Method", "Files", "Bytes", "Last 10s", "Share"]).style(header))
+            .block(Block::bordered().title(" Throughput by method "));
+        frame.render_widget(table, methods_area);
+
+        // Latency histogram of warmed files
+        let labels = latency_labels();
+        let bars: Vec<(&str, u64)> = labels.iter().map(String::as_str).zip(state.latency.iter().copied()).collect();
+        let chart = BarChart::default()
+            .block(Block::bordered().title(" Latency of warmed files "))
+            .bar_width(5)
+            .bar_gap(1)
+            .bar_style(Style::default().fg(Color::Cyan))
+            .data(bars.as_slice());
+        frame.render_widget(chart, latency_area);
+
+        // Slowest files: in flight now, then finished
+        let mut slow: Vec<(std::time::Duration, u64, String, &str)> = pipeline
+            .in_flight
+            .iter()
+            .map(|file| (file.elapsed, file.size, anonymize::display(&file.path).to_string(), "reading"))
+            .chain(state.slowest.iter().map(|file| (file.latency, file.bytes, anonymize::display(&file.path).to_string(), file.status.name())))
+            .collect();
+        slow.sort_by(|a, b| b.0.cmp(&a.0));
+        let rows: Vec<Row> = slow
+            .into_iter()
+            .take(SLOWEST_FILES)
+            .map(|(latency, bytes, path, status)| Row::new(vec![format!("{:.1?}", latency), HumanBytes(bytes).to_string(), status.to_string(), path]))
+            .collect();
+        let widths = [Constraint::Length(9), Constraint::Length(11), Constraint::Length(8), Constraint::Fill(1)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["Time", "Size", "Status", "File"]).style(header))
+            .block(Block::bordered().title(" Slowest files "));
+        frame.render_widget(table, slowest_area);
+
+        // Pipeline stages and device queues
+        let mut rows = vec![
+            Row::new(vec!["queued".to_string(), pipeline.queued.to_string(), String::new(), String::new()]),
+            Row::new(vec!["waiting for a slot".to_string(), pipeline.waiting.to_string(), String::new(), String::new()]),
+            Row::new(vec!["reading".to_string(), pipeline.in_flight.len().to_string(), String::new(), String::new()]),
+        ];
+        rows.extend(devices.iter().map(|device| {
+            Row::new(vec![
+                device.name.clone(),
+                device.in_flight.to_string(),
+                format!("{:.0} r/s {}/s", device.reads_per_sec, HumanBytes(device.bytes_per_sec as u64)),
+                format!("{:.0}%", device.utilization * 100.0),
+            ])
+        }));
+        let widths = [Constraint::Fill(1), Constraint::Length(9), Constraint::Length(22), Constraint::Length(5)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["Queue", "In flight", "Reads", "Busy"]).style(header))
+            .block(Block::bordered().title(" Queues "));
+        frame.render_widget(table, queues_area);
+
+        // Errors
+        let mut rows: Vec<Row> = state.errors_by_count().into_iter().map(|(message, count)| Row::new(vec![count.to_string(), message.to_string()])).collect();
+        if state.skipped > 0 {
+            rows.push(Row::new(vec![state.skipped.to_string(), "skipped".to_string()]));
+        }
+        let table = Table::new(rows, [Constraint::Length(8), Constraint::Fill(1)])
+            .header(Row::new(vec!["Files", "Error"]).style(header))
+            .block(Block::bordered().title(" Errors "));
+        frame.render_widget(table, errors_area);
+    }
+}
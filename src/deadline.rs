@@ -0,0 +1,87 @@
+//! Time-boxed warming (`--max-duration`).
+//!
+//! A maintenance window is a fixed length of wall-clock time, however much of the tree
+//! fits in it. Once the [`Deadline`] passes, discovery stops and no new file is started;
+//! files already being read finish, so every file counted as warmed was read in full.
+//! Files that metadata hooks give a higher priority are started first within each batch,
+//! so they are the likeliest to make it into the window.
+//!
+//! The deadline is wall-clock time from the start of warming, pauses included: a window
+//! ends on time even if the process was stopped for part of it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// When warming stops starting new files, if ever. Clones share whether the deadline
+/// has cut the run short.
+#[derive(Debug, Clone, Default)]
+pub struct Deadline {
+    at: Option<Instant>,
+    cut_short: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    /// A deadline `limit` from now, or none
+    pub fn after(limit: Option<Duration>) -> Self {
+        Self { at: limit.map(|limit| Instant::now() + limit), cut_short: Arc::default() }
+    }
+
+    /// Whether the deadline has passed. Called before starting work, so a true result
+    /// means that work is left undone and the run counts as cut short.
+    pub fn stops(&self) -> bool {
+        let passed = self.at.is_some_and(|at| Instant::now() >= at);
+        if passed {
+            self.cut_short.store(true, Ordering::Relaxed);
+        }
+        passed
+    }
+
+    /// Resolves once the deadline has passed, counting the run as cut short; never
+    /// without a deadline
+    pub async fn reached(&self) {
+        match self.at {
+            Some(at) => tokio::time::sleep_until(at.into()).await,
+            None => std::future::pending().await,
+        }
+        self.cut_short.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the deadline stopped any work from starting
+    pub fn cut_short(&self) -> bool {
+        self.cut_short.load(Ordering::Relaxed)
+    }
+}
+
+/// Parse a duration such as `90`, `45s`, `30m`, `2h`, `1h30m` or `7d`. A bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    if let Ok(seconds) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86_400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("invalid duration '{}' (expected e.g. 90s, 30m, 2h or 1h30m)", s)),
+        };
+        let value: u64 = digits.parse().map_err(|_| format!("invalid duration '{}': '{}' has no number", s, c))?;
+        total += value * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(format!("invalid duration '{}': '{}' has no unit", s, digits));
+    }
+    Ok(Duration::from_secs(total))
+}
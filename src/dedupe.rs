@@ -0,0 +1,195 @@
+//! Skipping files that were already reached by another path.
+//!
+//! Backup snapshots and pnpm's `node_modules` hard-link the same inode into many
+//! directories, and a bind mount inside a target shows a whole subtree twice. Reading
+//! each path would warm the same blocks once per link. Discovery records the
+//! `(device, inode)` of every file with more than one link, and of every directory, in an
+//! [`InodeSet`] shared by all discovery tasks, and skips entries that were seen before:
+//! a repeated file is left out, a repeated directory isn't descended into.
+//!
+//! Files with a single link can only be reached twice through a repeated directory,
+//! so they are never recorded; the set stays small even for trees of millions of files.
+//!
+//! Target directories go into the same set before they're walked, so overlapping
+//! targets (`/data /data/hot`) and a target given twice are walked once, whichever
+//! order they come in. With `--follow-symlinks`, a link back to a directory being
+//! walked is a repeat too, so cycles end at the first one. A directory at
+//! `--max-depth` isn't descended into, so it isn't recorded, and a target below it is
+//! still walked.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use ignore::DirEntry;
+
+/// Independently locked parts of the set, so concurrent discovery tasks rarely contend
+const SHARDS: usize = 64;
+
+/// Entries skipped because their inode was already seen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupeSummary {
+    /// Hard links to files already discovered
+    pub files: u64,
+    /// Directories already walked by another path, such as bind mounts
+    pub directories: u64,
+}
+
+impl fmt::Display for DedupeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Skipped {} hard links and {} repeated directories already reached by another path",
+            self.files, self.directories
+        )
+    }
+}
+
+/// Concurrent set of `(device, inode)` pairs seen during discovery
+#[derive(Debug)]
+pub struct InodeSet {
+    shards: Vec<Mutex<HashSet<(u64, u64)>>>,
+    /// Depth below a root at which walks stop descending
+    max_depth: Option<usize>,
+    files: AtomicU64,
+    directories: AtomicU64,
+}
+
+impl Default for InodeSet {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashSet::new())).collect(),
+            max_depth: None,
+            files: AtomicU64::new(0),
+            directories: AtomicU64::new(0),
+        }
+    }
+}
+
+impl InodeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A set for walks that stop descending at `max_depth`
+    pub fn with_max_depth(max_depth: Option<usize>) -> Self {
+        Self { max_depth, ..Self::default() }
+    }
+
+    /// Record `(dev, ino)`; returns false if it was already recorded
+    pub fn insert(&self, dev: u64, ino: u64) -> bool {
+        self.shards[(ino as usize) % SHARDS].lock().unwrap().insert((dev, ino))
+    }
+
+    /// Whether a discovered file is new. Files with a single link are always new and
+    /// aren't recorded.
+    pub fn first_file(&self, dev: u64, ino: u64, nlink: u64) -> bool {
+        if nlink <= 1 || self.insert(dev, ino) {
+            return true;
+        }
+        self.files.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Whether a directory hasn't been walked yet by another path
+    pub fn first_directory(&self, dev: u64, ino: u64) -> bool {
+        if self.insert(dev, ino) {
+            return true;
+        }
+        self.directories.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Count a directory the walker itself found repeated, such as a symlink cycle
+    pub fn repeated_directory(&self) {
+        self.directories.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether a walked file or directory is reached for the first time. Entries that
+    /// can't be stat'ed, and anything on platforms without inode numbers, count as new.
+    #[cfg(unix)]
+    pub fn first_visit(&self, entry: &DirEntry) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let Some(file_type) = entry.file_type() else { return true };
+        if !file_type.is_file() && !file_type.is_dir() {
+            return true;
+        }
+        // Not descended into here, so another path may still have to walk it
+        if file_type.is_dir() && self.max_depth.is_some_and(|max_depth| entry.depth() >= max_depth) {
+            return true;
+        }
+        let Ok(metadata) = entry.metadata() else { return true };
+        if file_type.is_dir() {
+            self.first_directory(metadata.dev(), metadata.ino())
+        } else {
+            self.first_file(metadata.dev(), metadata.ino(), metadata.nlink())
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn first_visit(&self, _entry: &DirEntry) -> bool {
+        true
+    }
+
+    /// Whether a target directory hasn't been walked yet, as a target or under one.
+    /// Targets that can't be stat'ed count as new; the walk reports the error.
+    #[cfg(unix)]
+    pub fn first_root(&self, root: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        match std::fs::metadata(root) {
+            Ok(metadata) if metadata.is_dir() => self.first_directory(metadata.dev(), metadata.ino()),
+            _ => true,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn first_root(&self, _root: &Path) -> bool {
+        true
+    }
+
+    pub fn summary(&self) -> DedupeSummary {
+        DedupeSummary {
+            files: self.files.load(Ordering::Relaxed),
+            directories: self.directories.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A target that another target already covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NestedRoot {
+    pub root: PathBuf,
+    pub containing: PathBuf,
+    /// The two are the same directory, rather than one inside the other
+    pub same: bool,
+}
+
+impl fmt::Display for NestedRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let relation = if self.same { "the same directory as" } else { "inside" };
+        write!(f, "{} is {} {}", self.root.display(), relation, self.containing.display())
+    }
+}
+
+/// Targets that are the same directory as, or inside, another target, after resolving
+/// symlinks. Of a directory given twice, the second is reported.
+pub fn nested_roots(roots: &[PathBuf]) -> Vec<NestedRoot> {
+    let canonical: Vec<Option<PathBuf>> = roots.iter().map(|root| std::fs::canonicalize(root).ok()).collect();
+    let mut nested = Vec::new();
+    for (i, root) in roots.iter().enumerate() {
+        let Some(path) = &canonical[i] else { continue };
+        let containing = canonical.iter().enumerate().find_map(|(j, other)| {
+            let other = other.as_ref()?;
+            let same = other == path;
+            ((same && j < i) || (!same && path.starts_with(other))).then_some((j, same))
+        });
+        if let Some((j, same)) = containing {
+            nested.push(NestedRoot { root: root.clone(), containing: roots[j].clone(), same });
+        }
+    }
+    nested
+}
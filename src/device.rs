@@ -0,0 +1,343 @@
+//! Block device introspection and auto-tuning.
+//!
+//! The right queue depth and read size depend on what's behind the target directories: a
+//! 16,000 IOPS io2 volume wants far more reads in flight than a 3,000 IOPS gp3, and st1/sc1
+//! throughput volumes want a few large sequential reads rather than many small ones.
+//! [`profile`] finds each target's block device in sysfs (its `nr_requests`, whether it's
+//! rotational, and for EBS NVMe devices the volume ID from the controller serial), and
+//! with the `aws` feature looks up the volume's type, IOPS and throughput with
+//! `DescribeVolumes` (region and credentials come from the instance profile via IMDS;
+//! IMDS itself doesn't expose volume types). [`Tuning::for_profiles`] turns that into
+//! settings, which `main` applies to whatever the user didn't set explicitly.
+//!
+//! Directories on network filesystems have no block device; their profile is the mount
+//! (named by its source, e.g. `fs-0123.efs.us-east-1.amazonaws.com:/`), sized by the data
+//! stored on it, and tuned for many large buffered reads.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::disk::{self, DiskKind};
+use crate::warming::Strategy;
+
+/// Read size for SSD-backed EBS volumes, which count I/O in 256 KiB units
+pub const SSD_CHUNK_SIZE: usize = 256 * 1024;
+/// Read size for HDD-backed volumes (st1/sc1 count I/O in 1 MiB units)
+pub const HDD_CHUNK_SIZE: usize = 1024 * 1024;
+/// Reads in flight on HDD-backed volumes; AWS recommends at least 4 for 1 MiB sequential I/O
+pub const HDD_QUEUE_DEPTH: usize = 8;
+/// Queue depth bounds for SSD volumes
+pub const MIN_QUEUE_DEPTH: usize = 16;
+pub const MAX_QUEUE_DEPTH: usize = 256;
+/// Assumed latency of a first read from a snapshot-restored volume. By Little's law the
+/// reads in flight needed to use all provisioned IOPS are IOPS × latency, i.e. IOPS / 100.
+const COLD_READ_LATENCY_MS: u32 = 10;
+/// Reads in flight on network filesystems. Each read waits a network round trip on top
+/// of the server's own latency, and EFS spreads a file system over many servers, so it
+/// takes far more parallel reads than a disk to reach its throughput.
+pub const NETWORK_QUEUE_DEPTH: usize = 64;
+
+/// EBS volume type, as `DescribeVolumes` reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VolumeType {
+    Gp2,
+    Gp3,
+    Io1,
+    Io2,
+    St1,
+    Sc1,
+    /// Previous-generation magnetic
+    Standard,
+    Other(String),
+}
+
+impl VolumeType {
+    /// Backed by spinning disks
+    pub fn is_hdd(&self) -> bool {
+        matches!(self, VolumeType::St1 | VolumeType::Sc1 | VolumeType::Standard)
+    }
+}
+
+impl From<&str> for VolumeType {
+    fn from(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gp2" => VolumeType::Gp2,
+            "gp3" => VolumeType::Gp3,
+            "io1" => VolumeType::Io1,
+            "io2" => VolumeType::Io2,
+            "st1" => VolumeType::St1,
+            "sc1" => VolumeType::Sc1,
+            "standard" => VolumeType::Standard,
+            other => VolumeType::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for VolumeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VolumeType::Gp2 => "gp2",
+            VolumeType::Gp3 => "gp3",
+            VolumeType::Io1 => "io1",
+            VolumeType::Io2 => "io2",
+            VolumeType::St1 => "st1",
+            VolumeType::Sc1 => "sc1",
+            VolumeType::Standard => "standard",
+            VolumeType::Other(name) => name,
+        })
+    }
+}
+
+/// Provisioned performance of an EBS volume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EbsVolume {
+    pub volume_type: VolumeType,
+    pub iops: Option<u32>,
+    pub throughput_mbps: Option<u32>,
+    pub size_gib: Option<u32>,
+}
+
+/// What's known about the device behind one or more target directories
+#[derive(Debug, Clone)]
+pub struct DeviceProfile {
+    /// Kernel name of the disk, e.g. `nvme1n1`, or the source of a network mount
+    pub device: String,
+    pub kind: DiskKind,
+    pub rotational: Option<bool>,
+    /// Request queue size (`queue/nr_requests`)
+    pub nr_requests: Option<u32>,
+    /// Capacity of the whole disk; for network filesystems, the bytes stored on it
+    pub size_bytes: Option<u64>,
+    /// EBS volume ID, from the NVMe controller serial
+    pub volume_id: Option<String>,
+    pub ebs: Option<EbsVolume>,
+    pub directories: Vec<PathBuf>,
+}
+
+impl DeviceProfile {
+    /// Settings this device calls for; fields stay `None` when there's too little to go on
+    pub fn tuning(&self) -> Tuning {
+        if self.kind.is_network() {
+            return Tuning {
+                queue_depth: Some(NETWORK_QUEUE_DEPTH),
+                chunk_size: Some(disk::NETWORK_CHUNK_SIZE),
+                strategy: Some(Strategy::Tokio),
+            };
+        }
+        let hdd = Tuning { queue_depth: Some(HDD_QUEUE_DEPTH), chunk_size: Some(HDD_CHUNK_SIZE), strategy: Some(Strategy::Tokio) };
+        if let Some(ebs) = &self.ebs {
+            if ebs.volume_type.is_hdd() {
+                return hdd;
+            }
+            let queue_depth = ebs.iops.map(|iops| ((iops * COLD_READ_LATENCY_MS / 1000) as usize).clamp(MIN_QUEUE_DEPTH, MAX_QUEUE_DEPTH));
+            return Tuning { queue_depth, chunk_size: Some(SSD_CHUNK_SIZE), strategy: None };
+        }
+        match self.rotational {
+            Some(true) => hdd,
+            // Local SSDs: as many reads as the device queue takes, within bounds
+            Some(false) if self.kind.is_non_ebs() => Tuning {
+                queue_depth: self.nr_requests.map(|n| (n as usize).clamp(MIN_QUEUE_DEPTH, MAX_QUEUE_DEPTH / 4)),
+                chunk_size: None,
+                strategy: None,
+            },
+            _ => Tuning::default(),
+        }
+    }
+}
+
+impl fmt::Display for DeviceProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        let mut details = Vec::new();
+        if let Some(id) = &self.volume_id {
+            details.push(id.clone());
+        }
+        match &self.ebs {
+            Some(ebs) => {
+                details.push(ebs.volume_type.to_string());
+                if let Some(iops) = ebs.iops {
+                    details.push(format!("{} IOPS", iops));
+                }
+                if let Some(throughput) = ebs.throughput_mbps {
+                    details.push(format!("{} MB/s", throughput));
+                }
+            }
+            None if self.volume_id.is_some() => details.push("volume type unknown".to_string()),
+            None => {}
+        }
+        match self.rotational {
+            Some(true) => details.push("rotational".to_string()),
+            Some(false) => details.push("non-rotational".to_string()),
+            None => {}
+        }
+        if let Some(n) = self.nr_requests {
+            details.push(format!("nr_requests {}", n));
+        }
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings picked from device profiles
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tuning {
+    pub queue_depth: Option<usize>,
+    pub chunk_size: Option<usize>,
+    pub strategy: Option<Strategy>,
+}
+
+impl Tuning {
+    /// Combined settings for targets spread over `profiles`. Each device has its own
+    /// queue, so queue depths add up; the smallest read size wins; a strategy is only
+    /// picked if every device asks for the same one. A setting is left alone unless
+    /// every device could be tuned for it.
+    pub fn for_profiles(profiles: &[DeviceProfile]) -> Tuning {
+        if profiles.is_empty() {
+            return Tuning::default();
+        }
+        let tunings: Vec<Tuning> = profiles.iter().map(DeviceProfile::tuning).collect();
+        let strategies: Option<Vec<Strategy>> = tunings.iter().map(|t| t.strategy).collect();
+        Tuning {
+            queue_depth: tunings.iter().map(|t| t.queue_depth).sum::<Option<usize>>().map(|qd| qd.min(MAX_QUEUE_DEPTH)),
+            chunk_size: tunings.iter().map(|t| t.chunk_size).collect::<Option<Vec<usize>>>().and_then(|sizes| sizes.into_iter().min()),
+            strategy: strategies.filter(|s| s.windows(2).all(|w| w[0] == w[1])).and_then(|s| s.first().copied()),
+        }
+    }
+}
+
+/// Profile the devices behind `directories`, one profile per device
+pub async fn profile(directories: &[PathBuf]) -> Vec<DeviceProfile> {
+    let mut profiles: Vec<DeviceProfile> = Vec::new();
+    for dir in directories {
+        let Some(mut profile) = probe(dir) else { continue };
+        match profiles.iter_mut().find(|p| p.device == profile.device) {
+            Some(existing) => existing.directories.push(dir.clone()),
+            None => {
+                profile.directories.push(dir.clone());
+                profiles.push(profile);
+            }
+        }
+    }
+    describe_volumes(&mut profiles).await;
+    profiles
+}
+
+/// Profile the device behind `path` from sysfs, or the network mount it's on
+#[cfg(target_os = "linux")]
+pub fn probe(path: &Path) -> Option<DeviceProfile> {
+    let kind = disk::detect(path);
+    if kind.is_network() {
+        return Some(probe_network(path, kind));
+    }
+    let sys = match disk::sys_block_link(path) {
+        Ok(sys) => sys,
+        Err(why) => {
+            debug!("No device profile for {}: {}", path.display(), why);
+            return None;
+        }
+    };
+    let mut dir = std::fs::canonicalize(&sys).ok()?;
+    if dir.join("partition").exists() {
+        dir.pop();
+    }
+    // Device-mapper and md devices: profile the first disk underneath
+    while let Some(slave) = std::fs::read_dir(dir.join("slaves")).ok().and_then(|mut entries| entries.next()).and_then(Result::ok) {
+        dir = std::fs::canonicalize(slave.path()).ok()?;
+        if dir.join("partition").exists() {
+            dir.pop();
+        }
+    }
+    let mut profile = probe_sys(&dir);
+    profile.kind = kind;
+    Some(profile)
+}
+
+/// Profile the network mount `path` is on
+#[cfg(target_os = "linux")]
+fn probe_network(path: &Path, kind: DiskKind) -> DeviceProfile {
+    let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let source = crate::layers::MountTable::current().ok().and_then(|mounts| mounts.mount_of(&resolved).map(|mount| mount.source.clone()));
+    DeviceProfile {
+        device: source.unwrap_or_else(|| kind.to_string()),
+        rotational: None,
+        nr_requests: None,
+        size_bytes: disk::used_bytes(path),
+        volume_id: None,
+        ebs: None,
+        directories: Vec::new(),
+        kind,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn probe(_path: &Path) -> Option<DeviceProfile> {
+    None
+}
+
+/// Profile the disk whose sysfs directory (`/sys/block/<name>`) is `dir`
+pub fn probe_sys(dir: &Path) -> DeviceProfile {
+    let device = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok().map(|s| s.trim().to_string());
+    let model = read("device/model");
+    DeviceProfile {
+        kind: match &model {
+            Some(model) if device.starts_with("nvme") => disk::classify_nvme(&device, model),
+            _ => DiskKind::Unknown(device.clone()),
+        },
+        rotational: read("queue/rotational").map(|r| r == "1"),
+        nr_requests: read("queue/nr_requests").and_then(|n| n.parse().ok()),
+        // sysfs counts 512-byte sectors whatever the logical block size
+        size_bytes: read("size").and_then(|n| n.parse::<u64>().ok()).map(|sectors| sectors * 512),
+        volume_id: read("device/serial").and_then(|serial| volume_id(&serial)),
+        ebs: None,
+        directories: Vec::new(),
+        device,
+    }
+}
+
+/// EBS NVMe controllers report the volume ID without its dash (`vol0123...`) as their serial
+pub fn volume_id(serial: &str) -> Option<String> {
+    let id = serial.trim().strip_prefix("vol")?;
+    let id = id.strip_prefix('-').unwrap_or(id);
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit())).then(|| format!("vol-{}", id))
+}
+
+/// Fill in `ebs` for profiles with a volume ID. Failures (no credentials, no
+/// `ec2:DescribeVolumes` permission) only leave the type unknown.
+#[cfg(feature = "aws")]
+async fn describe_volumes(profiles: &mut [DeviceProfile]) {
+    let ids: Vec<String> = profiles.iter().filter_map(|p| p.volume_id.clone()).collect();
+    if ids.is_empty() {
+        return;
+    }
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let client = aws_sdk_ec2::Client::new(&config);
+    let output = match client.describe_volumes().set_volume_ids(Some(ids)).send().await {
+        Ok(output) => output,
+        Err(e) => {
+            log::warn!("Couldn't look up EBS volume types, so they won't be used for tuning: {}", e);
+            return;
+        }
+    };
+    for volume in output.volumes() {
+        let Some(profile) = profiles.iter_mut().find(|p| p.volume_id.as_deref() == volume.volume_id()) else { continue };
+        let Some(volume_type) = volume.volume_type() else { continue };
+        profile.ebs = Some(EbsVolume {
+            volume_type: VolumeType::from(volume_type.as_str()),
+            iops: volume.iops().and_then(|iops| u32::try_from(iops).ok()),
+            throughput_mbps: volume.throughput().and_then(|mbps| u32::try_from(mbps).ok()),
+            size_gib: volume.size().and_then(|gib| u32::try_from(gib).ok()),
+        });
+    }
+}
+
+#[cfg(not(feature = "aws"))]
+async fn describe_volumes(profiles: &mut [DeviceProfile]) {
+    if profiles.iter().any(|p| p.volume_id.is_some()) {
+        debug!("EBS volume types need the 'aws' feature; tuning from sysfs only");
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-device concurrency limits (`--per-device-queue-depth`).
+//!
+//! `--queue-depth` caps the files in flight across the whole run. When the targets sit
+//! on devices with very different queues, such as a local NVMe scratch volume next to an
+//! sc1 volume, no single number suits both: what keeps the NVMe device busy just piles
+//! up in the sc1 volume's kernel queue, and what suits sc1 leaves the NVMe device idle.
+//! With per-device limits every filesystem, told apart by `st_dev`, gets a semaphore of
+//! its own: a file is only read once its device has a free slot as well as the run.
+//! Fair sharing (`--fair-share mount`) then also skips a volume whose queue is full when
+//! handing out batches, so its waiting files don't hold slots another volume could use.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// `--per-device-queue-depth`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerDeviceDepth {
+    /// Each device's own tuned queue depth, from its profile
+    Auto,
+    /// The same depth for every device
+    Fixed(usize),
+}
+
+impl FromStr for PerDeviceDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(PerDeviceDepth::Auto),
+            n => n
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .map(PerDeviceDepth::Fixed)
+                .ok_or_else(|| format!("invalid per-device queue depth '{}' (expected 'auto' or a number of at least 1)", s)),
+        }
+    }
+}
+
+impl fmt::Display for PerDeviceDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerDeviceDepth::Auto => write!(f, "auto"),
+            PerDeviceDepth::Fixed(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Files each device may have in flight, by `st_dev`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceDepths {
+    /// Depth of devices not in `devices`
+    pub default: usize,
+    pub devices: HashMap<u64, usize>,
+}
+
+impl DeviceDepths {
+    pub fn depth(&self, dev: u64) -> usize {
+        self.devices.get(&dev).copied().unwrap_or(self.default).max(1)
+    }
+}
+
+/// One semaphore per device, created the first time a file on it is warmed
+#[derive(Debug)]
+pub struct DeviceQueues {
+    depths: DeviceDepths,
+    queues: Mutex<HashMap<u64, Arc<Semaphore>>>,
+}
+
+impl DeviceQueues {
+    pub fn new(depths: DeviceDepths) -> Self {
+        Self { depths, queues: Mutex::new(HashMap::new()) }
+    }
+
+    fn queue(&self, dev: u64) -> Arc<Semaphore> {
+        let mut queues = self.queues.lock().unwrap();
+        Arc::clone(queues.entry(dev).or_insert_with(|| Arc::new(Semaphore::new(self.depths.depth(dev)))))
+    }
+
+    /// Wait for a slot on device `dev`; it's freed when the permit is dropped
+    pub async fn acquire(&self, dev: u64) -> OwnedSemaphorePermit {
+        self.queue(dev).acquire_owned().await.expect("device queues are never closed")
+    }
+
+    /// Whether every slot on device `dev` is taken
+    pub fn is_full(&self, dev: u64) -> bool {
+        self.queues.lock().unwrap().get(&dev).is_some_and(|queue| queue.available_permits() == 0)
+    }
+}
+
+/// `st_dev` of `path`, if it can be read
+#[cfg(unix)]
+pub fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device_of(_path: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,260 @@
+//! What kind of disk a directory lives on.
+//!
+//! Warming only pays off on EBS volumes restored from snapshots, whose blocks are
+//! fetched from S3 on first read. NVMe instance store and other local disks have no
+//! such first-read penalty, so reading them with O_DIRECT or dropping the pages
+//! afterwards is pointless I/O. [`detect`] classifies a path's backing device from
+//! sysfs (EC2 exposes both EBS and instance store as NVMe devices with distinct model
+//! strings) and `--non-ebs` ([`NonEbsPolicy`]) decides what to do about the non-EBS ones.
+//!
+//! Network filesystems (EFS and other NFS or SMB shares) are told apart by their statfs
+//! magic. The server, not this host, fetches their data, so `posix_fadvise` hints are
+//! no-ops and O_DIRECT only bypasses the client cache; what helps is many large
+//! buffered reads in flight, which [`network_warming`] sets up.
+
+use std::path::Path;
+
+use crate::warming::{DropCaches, FallbackPolicy, Strategy, StrategyMap, WarmingOptions};
+
+const EBS_MODEL: &str = "Amazon Elastic Block Store";
+const INSTANCE_STORE_MODEL: &str = "Amazon EC2 NVMe Instance Storage";
+/// statfs(2) f_type of ramfs, which libc doesn't define
+#[cfg(target_os = "linux")]
+const RAMFS_MAGIC: libc::__fsword_t = 0x8584_58f6;
+/// statfs(2) f_type of NFS (EFS mounts are NFSv4.1)
+pub const NFS_SUPER_MAGIC: u32 = 0x6969;
+/// statfs(2) f_type of SMB2/3 shares mounted with the cifs driver
+pub const SMB2_MAGIC: u32 = 0xfe53_4d42;
+/// statfs(2) f_type of SMB1 (CIFS) shares
+pub const CIFS_MAGIC: u32 = 0xff53_4d42;
+/// Read size on network filesystems: large enough that each request to the server
+/// moves a full NFS `rsize` (1 MiB on EFS)
+pub const NETWORK_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskKind {
+    /// An EBS volume (the device name)
+    Ebs(String),
+    /// EC2 NVMe instance store (the device name)
+    InstanceStore(String),
+    /// Some other disk known not to be EBS: a non-Amazon NVMe drive, or memory
+    Local(String),
+    /// A network filesystem: EFS or another NFS export, or an SMB share (the filesystem type)
+    Network(String),
+    /// Couldn't tell (Xen `xvd*` and virtio disks, overlay filesystems, ...);
+    /// treated like EBS
+    Unknown(String),
+}
+
+impl DiskKind {
+    /// Whether `--non-ebs` applies
+    pub fn is_non_ebs(&self) -> bool {
+        matches!(self, DiskKind::InstanceStore(_) | DiskKind::Local(_))
+    }
+
+    /// Whether the data is on a network filesystem, warmed with [`network_warming`]
+    pub fn is_network(&self) -> bool {
+        matches!(self, DiskKind::Network(_))
+    }
+}
+
+impl std::fmt::Display for DiskKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiskKind::Ebs(device) => write!(f, "EBS volume {}", device),
+            DiskKind::InstanceStore(device) => write!(f, "instance store {}", device),
+            DiskKind::Local(what) => write!(f, "local disk {}", what),
+            DiskKind::Network(fs) => write!(f, "network filesystem {}", fs),
+            DiskKind::Unknown(why) => write!(f, "unknown disk ({})", why),
+        }
+    }
+}
+
+/// What to do with target directories on non-EBS disks (`--non-ebs`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonEbsPolicy {
+    /// Leave them out of the run
+    Skip,
+    /// Read them into the page cache and keep them there, without O_DIRECT
+    #[default]
+    PageCache,
+    /// Warm them like EBS volumes
+    Force,
+}
+
+impl std::str::FromStr for NonEbsPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(NonEbsPolicy::Skip),
+            "pagecache" | "page-cache" => Ok(NonEbsPolicy::PageCache),
+            "force" => Ok(NonEbsPolicy::Force),
+            other => Err(format!("unknown non-EBS policy '{}' (expected skip, pagecache or force)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for NonEbsPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NonEbsPolicy::Skip => "skip",
+            NonEbsPolicy::PageCache => "pagecache",
+            NonEbsPolicy::Force => "force",
+        })
+    }
+}
+
+/// Classify an NVMe device by the model string it reports
+pub fn classify_nvme(device: &str, model: &str) -> DiskKind {
+    let model = model.trim();
+    if model.contains(EBS_MODEL) {
+        DiskKind::Ebs(device.to_string())
+    } else if model.contains(INSTANCE_STORE_MODEL) {
+        DiskKind::InstanceStore(device.to_string())
+    } else {
+        DiskKind::Local(format!("{} ({})", device, model))
+    }
+}
+
+/// Warming options for directories handled in page-cache mode: buffered reads whose
+/// pages stay cached. Snapshot range selection only makes sense on EBS, and direct-I/O
+/// strategies can't fill the page cache, so those choices (and `--strategy-map`) are
+/// reset too.
+pub fn page_cache_warming(options: &WarmingOptions) -> WarmingOptions {
+    WarmingOptions {
+        strategy: Strategy::Auto,
+        fallback: FallbackPolicy::Auto,
+        use_direct_io: false,
+        drop_caches: DropCaches::None,
+        range_selector: None,
+        strategy_map: StrategyMap::default(),
+        ..options.clone()
+    }
+}
+
+/// Warming options for directories on network filesystems: buffered reads of
+/// [`NETWORK_CHUNK_SIZE`] or more through Tokio, whose thread pool keeps many reads in
+/// flight. fadvise, io_uring and O_DIRECT paths are all skipped: hints never reach the
+/// server, and direct reads only lose the client's own readahead. Page-cache probes
+/// and snapshot range selection assume a local block device, so they're off too.
+pub fn network_warming(options: &WarmingOptions) -> WarmingOptions {
+    WarmingOptions {
+        strategy: Strategy::Tokio,
+        fallback: FallbackPolicy::None,
+        use_direct_io: false,
+        range_selector: None,
+        compressed_extents: false,
+        nowait_precheck: false,
+        chunk_size: options.chunk_size.max(NETWORK_CHUNK_SIZE),
+        strategy_chunk_sizes: Vec::new(),
+        strategy_map: StrategyMap::default(),
+        ..options.clone()
+    }
+}
+
+/// Classify a filesystem by its statfs(2) `f_type`, if it says anything by itself
+pub fn classify_filesystem(magic: u32) -> Option<DiskKind> {
+    match magic {
+        NFS_SUPER_MAGIC => Some(DiskKind::Network("nfs".to_string())),
+        SMB2_MAGIC => Some(DiskKind::Network("smb2".to_string())),
+        CIFS_MAGIC => Some(DiskKind::Network("cifs".to_string())),
+        _ => None,
+    }
+}
+
+/// Kind of disk backing `path`
+#[cfg(target_os = "linux")]
+pub fn detect(path: &Path) -> DiskKind {
+    if let Some(kind) = by_filesystem(path) {
+        return kind;
+    }
+    match sys_block_link(path) {
+        Ok(sys) => block_device(&sys),
+        Err(why) => DiskKind::Unknown(why),
+    }
+}
+
+/// The `/sys/dev/block/M:m` link of the block device holding `path`, or why there isn't one
+#[cfg(target_os = "linux")]
+pub(crate) fn sys_block_link(path: &Path) -> Result<std::path::PathBuf, String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).map_err(|e| e.to_string())?.dev();
+    let (major, minor) = (libc::major(dev), libc::minor(dev));
+    if major == 0 {
+        return Err("no backing block device".to_string());
+    }
+    Ok(Path::new("/sys/dev/block").join(format!("{}:{}", major, minor)))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect(_path: &Path) -> DiskKind {
+    DiskKind::Unknown("detection is only supported on Linux".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn by_filesystem(path: &Path) -> Option<DiskKind> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    match stat.f_type {
+        libc::TMPFS_MAGIC => Some(DiskKind::Local("tmpfs".to_string())),
+        RAMFS_MAGIC => Some(DiskKind::Local("ramfs".to_string())),
+        // Magic numbers are 32 bits, whatever the width of f_type
+        other => classify_filesystem(other as u32),
+    }
+}
+
+/// Bytes in use on the filesystem holding `path`
+#[cfg(target_os = "linux")]
+pub(crate) fn used_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some((stat.f_blocks as u64).saturating_sub(stat.f_bfree as u64) * stat.f_frsize as u64)
+}
+
+/// Classify the block device at `sys` (a `/sys/dev/block/M:m` or `/sys/class/block/X`
+/// link), looking through partitions to their disk and through device-mapper/md
+/// devices to the disks underneath
+#[cfg(target_os = "linux")]
+fn block_device(sys: &Path) -> DiskKind {
+    let Ok(mut dir) = std::fs::canonicalize(sys) else {
+        return DiskKind::Unknown(format!("{} not found", sys.display()));
+    };
+    if dir.join("partition").exists() {
+        dir.pop();
+    }
+    let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let slaves: Vec<DiskKind> = std::fs::read_dir(dir.join("slaves"))
+        .map(|entries| entries.flatten().map(|entry| block_device(&entry.path())).collect())
+        .unwrap_or_default();
+    if let Some(first) = slaves.first() {
+        if slaves.iter().all(|kind| std::mem::discriminant(kind) == std::mem::discriminant(first)) {
+            return first.clone();
+        }
+        return DiskKind::Unknown(format!("{} spans different kinds of disk", name));
+    }
+
+    if name.starts_with("nvme") {
+        return match std::fs::read_to_string(dir.join("device/model")) {
+            Ok(model) => classify_nvme(&name, &model),
+            Err(e) => DiskKind::Unknown(format!("{}: {}", name, e)),
+        };
+    }
+    if name.starts_with("zram") || name.starts_with("ram") {
+        return DiskKind::Local(format!("{} (memory)", name));
+    }
+    DiskKind::Unknown(name)
+}
@@ -0,0 +1,188 @@
+//! Snapshot-aware warming via the EBS direct APIs.
+//!
+//! A volume restored from a snapshot only has to fetch the blocks the snapshot actually
+//! holds; everything else reads back as zeros without touching S3. `ListSnapshotBlocks`
+//! (or `ListChangedBlocks` against a base snapshot) tells us exactly which blocks those
+//! are, and FIEMAP maps each file onto device offsets, so only file ranges backed by
+//! snapshot data get read. [`SnapshotRangeSelector`] hands those ranges to the selected
+//! strategy like any other [`RangeSelector`].
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use aws_sdk_ebs::Client;
+use log::info;
+
+use crate::fiemap;
+use crate::warming::RangeSelector;
+use crate::warnings::{self, Category};
+
+/// Which snapshot blocks hold data, as a bitmap over block indexes
+#[derive(Debug, Clone)]
+pub struct SnapshotBlockMap {
+    pub block_size: u64,
+    bits: Vec<u64>,
+    populated: u64,
+}
+
+impl SnapshotBlockMap {
+    fn new(block_size: u64) -> Self {
+        Self { block_size, bits: Vec::new(), populated: 0 }
+    }
+
+    fn insert(&mut self, index: u64) {
+        let (word, bit) = ((index / 64) as usize, index % 64);
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        if self.bits[word] & (1 << bit) == 0 {
+            self.bits[word] |= 1 << bit;
+            self.populated += 1;
+        }
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        let (word, bit) = ((index / 64) as usize, index % 64);
+        self.bits.get(word).is_some_and(|w| w & (1 << bit) != 0)
+    }
+
+    /// Number of blocks with data
+    pub fn populated_blocks(&self) -> u64 {
+        self.populated
+    }
+
+    /// Parts of the device range `[offset, offset + length)` that fall in populated
+    /// blocks, as `(device_offset, length)` pairs
+    fn populated_parts(&self, offset: u64, length: u64) -> Vec<(u64, u64)> {
+        let mut parts: Vec<(u64, u64)> = Vec::new();
+        let end = offset + length;
+        let mut block = offset / self.block_size;
+        while block * self.block_size < end {
+            if self.contains(block) {
+                let start = std::cmp::max(offset, block * self.block_size);
+                let stop = std::cmp::min(end, (block + 1) * self.block_size);
+                match parts.last_mut() {
+                    Some((s, l)) if *s + *l == start => *l += stop - start,
+                    _ => parts.push((start, stop - start)),
+                }
+            }
+            block += 1;
+        }
+        parts
+    }
+}
+
+/// Load the populated blocks of `snapshot_id`. With a `base_snapshot_id`, only blocks
+/// that changed between the two snapshots are included (the base is assumed warm).
+pub async fn load_block_map(snapshot_id: &str, base_snapshot_id: Option<&str>) -> Result<SnapshotBlockMap> {
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let client = Client::new(&config);
+
+    let mut map: Option<SnapshotBlockMap> = None;
+    let mut next_token: Option<String> = None;
+    loop {
+        let (block_size, indexes, token) = match base_snapshot_id {
+            None => {
+                let page = client
+                    .list_snapshot_blocks()
+                    .snapshot_id(snapshot_id)
+                    .set_next_token(next_token.take())
+                    .send()
+                    .await
+                    .with_context(|| format!("ListSnapshotBlocks failed for {}", snapshot_id))?;
+                let indexes: Vec<i32> = page.blocks().iter().filter_map(|b| b.block_index()).collect();
+                (page.block_size(), indexes, page.next_token().map(str::to_string))
+            }
+            Some(base) => {
+                let page = client
+                    .list_changed_blocks()
+                    .first_snapshot_id(base)
+                    .second_snapshot_id(snapshot_id)
+                    .set_next_token(next_token.take())
+                    .send()
+                    .await
+                    .with_context(|| format!("ListChangedBlocks failed for {} against {}", snapshot_id, base))?;
+                let indexes: Vec<i32> = page
+                    .changed_blocks()
+                    .iter()
+                    .filter(|b| b.second_block_token().is_some())
+                    .filter_map(|b| b.block_index())
+                    .collect();
+                (page.block_size(), indexes, page.next_token().map(str::to_string))
+            }
+        };
+
+        let block_size = block_size.context("EBS direct API response is missing BlockSize")? as u64;
+        let map = map.get_or_insert_with(|| SnapshotBlockMap::new(block_size));
+        for index in indexes {
+            map.insert(index as u64);
+        }
+
+        match token {
+            Some(token) => next_token = Some(token),
+            None => break,
+        }
+    }
+
+    let map = map.unwrap_or_else(|| SnapshotBlockMap::new(512 * 1024));
+    info!(
+        "Snapshot {} has {} populated blocks ({:.2} GB of data)",
+        snapshot_id,
+        map.populated_blocks(),
+        (map.populated_blocks() * map.block_size) as f64 / 1_000_000_000.0
+    );
+    Ok(map)
+}
+
+/// Selects the file ranges whose device blocks hold snapshot data
+#[derive(Debug, Clone)]
+pub struct SnapshotRangeSelector {
+    map: Arc<SnapshotBlockMap>,
+}
+
+impl SnapshotRangeSelector {
+    pub fn new(map: SnapshotBlockMap) -> Self {
+        Self { map: Arc::new(map) }
+    }
+}
+
+impl RangeSelector for SnapshotRangeSelector {
+    fn select(&self, path: &Path, file_size: u64) -> Result<Option<Vec<(u64, u64)>>, std::io::Error> {
+        let extents = match fiemap::extents(path) {
+            Ok(extents) => extents,
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                warnings::report(Category::Fallback, format_args!("FIEMAP unavailable for {}, warming whole file: {}", path.display(), e));
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        // The snapshot covers the whole disk; filesystem offsets are relative to the partition
+        let partition_offset = fiemap::partition_offset(path)?;
+
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for extent in extents {
+            if extent.unwritten || extent.logical >= file_size {
+                continue;
+            }
+            let length = std::cmp::min(extent.length, file_size - extent.logical);
+            let Some(physical) = extent.physical else {
+                // No usable device offset (inline, delalloc, ...): read it to be safe
+                push_range(&mut ranges, extent.logical, length);
+                continue;
+            };
+            let device_offset = partition_offset + physical;
+            for (start, len) in self.map.populated_parts(device_offset, length) {
+                push_range(&mut ranges, extent.logical + (start - device_offset), len);
+            }
+        }
+        Ok(Some(ranges))
+    }
+}
+
+fn push_range(ranges: &mut Vec<(u64, u64)>, offset: u64, length: u64) {
+    match ranges.last_mut() {
+        Some((o, l)) if *o + *l == offset => *l += length,
+        _ => ranges.push((offset, length)),
+    }
+}
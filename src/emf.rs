@@ -0,0 +1,107 @@
+//! CloudWatch Embedded Metric Format output.
+//!
+//! Rather than one line per file, counters are aggregated and flushed as a single
+//! EMF JSON line per interval (plus a final line), so environments that forward
+//! stdout to CloudWatch Logs get metrics without flooding the log group.
+
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::events::WarmEvent;
+use crate::stats::FileStatus;
+
+/// Settings for the EMF emitter
+#[derive(Debug, Clone)]
+pub struct EmfOptions {
+    pub namespace: String,
+    pub interval: Duration,
+    /// Value of the `Target` dimension attached to every metric
+    pub target: String,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    files_warmed: u64,
+    files_failed: u64,
+    files_skipped: u64,
+    bytes_warmed: u64,
+}
+
+impl Counters {
+    fn is_empty(&self) -> bool {
+        self.files_warmed == 0 && self.files_failed == 0 && self.files_skipped == 0
+    }
+}
+
+/// Build one EMF record for the counters accumulated since the previous flush
+fn emf_line(options: &EmfOptions, counters: &Counters, is_final: bool) -> String {
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    json!({
+        "_aws": {
+            "Timestamp": timestamp_ms,
+            "CloudWatchMetrics": [{
+                "Namespace": options.namespace,
+                "Dimensions": [["Target"]],
+                "Metrics": [
+                    {"Name": "BytesWarmed", "Unit": "Bytes"},
+                    {"Name": "FilesWarmed", "Unit": "Count"},
+                    {"Name": "FilesSkipped", "Unit": "Count"},
+                    {"Name": "Errors", "Unit": "Count"},
+                ],
+            }],
+        },
+        "Target": options.target,
+        "BytesWarmed": counters.bytes_warmed,
+        "FilesWarmed": counters.files_warmed,
+        "FilesSkipped": counters.files_skipped,
+        "Errors": counters.files_failed,
+        "Final": is_final,
+    })
+    .to_string()
+}
+
+/// Consume pipeline events and print EMF lines to stdout until the event stream ends.
+pub async fn run_emitter(options: EmfOptions, mut events: mpsc::UnboundedReceiver<WarmEvent>) {
+    let mut pending = Counters::default();
+    let mut ticker = tokio::time::interval(options.interval);
+    ticker.tick().await; // the first tick completes immediately
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                match event {
+                    Some(WarmEvent::FileFinished { bytes, status, .. }) => match status {
+                        FileStatus::Warmed => {
+                            pending.files_warmed += 1;
+                            pending.bytes_warmed += bytes;
+                        }
+                        FileStatus::Failed => pending.files_failed += 1,
+                        FileStatus::Skipped => pending.files_skipped += 1,
+                    },
+                    None => break,
+                }
+            }
+            _ = ticker.tick() => {
+                if !pending.is_empty() {
+                    print_line(&emf_line(&options, &pending, false));
+                    pending = Counters::default();
+                }
+            }
+        }
+    }
+
+    // Always emit a final record so dashboards see the run complete, even if it was empty
+    print_line(&emf_line(&options, &pending, true));
+}
+
+fn print_line(line: &str) {
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    let _ = writeln!(handle, "{}", line);
+    let _ = handle.flush();
+}
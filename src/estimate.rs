@@ -0,0 +1,238 @@
+//! Cold-fraction estimation (the `estimate` subcommand).
+//!
+//! Whether a volume restored from a snapshot still needs warming is a question about a
+//! proportion: what share of its blocks would read cold. `verify` lists the cold
+//! samples it happened to hit; this reads the same kind of uncached one-block samples
+//! (see [`crate::verify`]) and treats them as a random sample, reporting the cold
+//! fraction with a Wilson score interval. A thousand samples pin the fraction to within
+//! about three percentage points either way, however large the volume.
+//!
+//! Samples are drawn uniformly over bytes by default, estimating the share of the data
+//! that is cold. `--sample-by files` picks a file uniformly and then an offset in it,
+//! estimating the share of a typical file that is cold, so many small files count as
+//! much as one huge one.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+
+use crate::pipeline::PipelineOptions;
+use crate::verify::{self, LatencyPercentiles, Rng, SAMPLE_SIZE};
+
+/// What each sample is drawn uniformly over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleBy {
+    /// Every byte equally likely: estimates the cold share of the data
+    #[default]
+    Bytes,
+    /// Every file equally likely, then an offset in it: estimates the cold share of a typical file
+    Files,
+}
+
+impl FromStr for SampleBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bytes" => Ok(SampleBy::Bytes),
+            "files" => Ok(SampleBy::Files),
+            other => Err(format!("unknown sampling '{}' (expected bytes or files)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EstimateOptions {
+    /// Number of random offsets to read
+    pub samples: usize,
+    /// Samples slower than this count as cold
+    pub cold_threshold: Duration,
+    /// Concurrent sample reads
+    pub concurrency: usize,
+    pub seed: u64,
+    /// Confidence level of the interval, between 0 and 1
+    pub confidence: f64,
+    pub sample_by: SampleBy,
+}
+
+impl Default for EstimateOptions {
+    fn default() -> Self {
+        let verify = verify::VerifyOptions::default();
+        Self {
+            samples: verify.samples,
+            cold_threshold: verify.cold_threshold,
+            concurrency: verify.concurrency,
+            seed: verify.seed,
+            confidence: 0.95,
+            sample_by: SampleBy::Bytes,
+        }
+    }
+}
+
+/// Estimated cold fraction of the sampled directories
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub sample_by: SampleBy,
+    pub cold_threshold: Duration,
+    pub confidence: f64,
+    pub bytes_covered: u64,
+    pub files_covered: u64,
+    /// Samples that were read and timed
+    pub measured: usize,
+    /// Samples that couldn't be read (file vanished, permissions, ...)
+    pub failed: usize,
+    /// Measured samples slower than the threshold
+    pub cold: usize,
+    pub latency: LatencyPercentiles,
+    /// Lower and upper bound of the cold fraction at [`Self::confidence`]
+    pub interval: (f64, f64),
+}
+
+impl Estimate {
+    /// Share of the measured samples that read cold
+    pub fn cold_fraction(&self) -> f64 {
+        if self.measured == 0 {
+            0.0
+        } else {
+            self.cold as f64 / self.measured as f64
+        }
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Estimate: {} samples by {} over {} files ({:.2} GB), {} failed",
+            self.measured + self.failed,
+            match self.sample_by {
+                SampleBy::Bytes => "byte",
+                SampleBy::Files => "file",
+            },
+            self.files_covered,
+            self.bytes_covered as f64 / 1_000_000_000.0,
+            self.failed
+        )?;
+        if self.measured == 0 {
+            return writeln!(f, "  nothing could be sampled");
+        }
+        writeln!(
+            f,
+            "  latency p50 {:.2?}  p90 {:.2?}  p99 {:.2?}  max {:.2?}",
+            self.latency.p50, self.latency.p90, self.latency.p99, self.latency.max
+        )?;
+        let (low, high) = self.interval;
+        writeln!(
+            f,
+            "  {} of {} samples slower than {:.2?}: {:.1}% cold ({:.0}% confidence interval {:.1}% to {:.1}%)",
+            self.cold,
+            self.measured,
+            self.cold_threshold,
+            self.cold_fraction() * 100.0,
+            self.confidence * 100.0,
+            low * 100.0,
+            high * 100.0
+        )?;
+        if self.sample_by == SampleBy::Bytes {
+            let gb = |fraction: f64| fraction * self.bytes_covered as f64 / 1_000_000_000.0;
+            writeln!(f, "  about {:.2} GB still cold ({:.2} to {:.2} GB)", gb(self.cold_fraction()), gb(low), gb(high))?;
+        }
+        Ok(())
+    }
+}
+
+/// Two-sided standard normal quantile for `confidence`, e.g. 1.96 for 0.95
+/// (Abramowitz and Stegun 26.2.23, accurate to about 5e-4)
+pub fn z_score(confidence: f64) -> f64 {
+    let tail = ((1.0 - confidence) / 2.0).clamp(1e-12, 0.5);
+    let t = (-2.0 * tail.ln()).sqrt();
+    t - (2.515517 + 0.802853 * t + 0.010328 * t * t) / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+}
+
+/// Wilson score interval for `successes` out of `trials` at `confidence`. Unlike the
+/// normal approximation it stays inside [0, 1] and doesn't collapse to a point when no
+/// sample (or every sample) read cold.
+pub fn wilson_interval(successes: usize, trials: usize, confidence: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let (n, p, z) = (trials as f64, successes as f64 / trials as f64, z_score(confidence));
+    let denominator = 1.0 + z * z / n;
+    let center = (p + z * z / (2.0 * n)) / denominator;
+    let half = z / denominator * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    // Exact at the ends, where rounding would otherwise leave e.g. 0.9999999999999999
+    let low = if successes == 0 { 0.0 } else { (center - half).max(0.0) };
+    let high = if successes == trials { 1.0 } else { (center + half).min(1.0) };
+    (low, high)
+}
+
+/// Pick `count` files uniformly, with replacement, and a block-aligned offset in each.
+/// Like [`verify::pick_offsets`], two walks keep memory proportional to the sample count.
+fn pick_file_offsets(options: &PipelineOptions, count: usize, seed: u64) -> (Vec<(PathBuf, u64)>, u64, u64) {
+    let (mut total_bytes, mut total_files) = (0u64, 0u64);
+    verify::for_each_candidate(options, |_, size| {
+        total_bytes += size;
+        total_files += 1;
+    });
+    if total_files == 0 {
+        return (Vec::new(), 0, 0);
+    }
+
+    let mut rng = Rng::new(seed);
+    let mut indices: Vec<u64> = (0..count).map(|_| rng.below(total_files)).collect();
+    indices.sort_unstable();
+
+    let mut picked = Vec::with_capacity(count);
+    let (mut next, mut index) = (0, 0u64);
+    verify::for_each_candidate(options, |path, size| {
+        while next < indices.len() && indices[next] == index {
+            picked.push((path.to_path_buf(), rng.below(size) / SAMPLE_SIZE * SAMPLE_SIZE));
+            next += 1;
+        }
+        index += 1;
+    });
+    (picked, total_bytes, total_files)
+}
+
+/// Sample the directories of `options` and estimate their cold fraction
+pub async fn estimate(options: Arc<PipelineOptions>, estimate: &EstimateOptions) -> Result<Estimate> {
+    let (count, seed, sample_by) = (estimate.samples, estimate.seed, estimate.sample_by);
+    let (offsets, bytes_covered, files_covered) = tokio::task::spawn_blocking(move || match sample_by {
+        SampleBy::Bytes => verify::pick_offsets(&options, count, seed),
+        SampleBy::Files => pick_file_offsets(&options, count, seed),
+    })
+    .await?;
+    debug!("Estimating from {} offsets over {} files ({} bytes)", offsets.len(), files_covered, bytes_covered);
+
+    let mut latencies = Vec::with_capacity(offsets.len());
+    let mut failed = 0;
+    for result in verify::measure(offsets, estimate.concurrency).await {
+        match result {
+            Ok(sample) => latencies.push(sample.latency),
+            Err(e) => {
+                debug!("Estimate read failed: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    latencies.sort_unstable();
+    let cold = latencies.iter().filter(|latency| **latency > estimate.cold_threshold).count();
+
+    Ok(Estimate {
+        sample_by,
+        cold_threshold: estimate.cold_threshold,
+        confidence: estimate.confidence,
+        bytes_covered,
+        files_covered,
+        measured: latencies.len(),
+        failed,
+        cold,
+        latency: LatencyPercentiles::from_sorted(&latencies),
+        interval: wilson_interval(cold, latencies.len(), estimate.confidence),
+    })
+}
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::stats::FileStatus;
+use crate::warming::Coverage;
+
+/// Structured events published by the pipeline as files are processed
+#[derive(Debug, Clone)]
+pub enum WarmEvent {
+    FileFinished {
+        path: PathBuf,
+        bytes: u64,
+        latency: Duration,
+        status: FileStatus,
+        method: Option<&'static str>,
+        /// Bytes the strategy actually read, which is less than `bytes` for sparse,
+        /// cache-aware or advisory warming
+        bytes_read: u64,
+        /// Why a failed file failed
+        error: Option<String>,
+        /// How much of a warmed file was actually read
+        coverage: Option<Coverage>,
+    },
+}
+
+/// Fan-out of pipeline events to any number of subscribers (report writers,
+/// metric emitters, ...). Publishing is a no-op when nobody has subscribed.
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+    subscribers: Vec<mpsc::UnboundedSender<WarmEvent>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new subscriber. Its receiver ends once the bus (and all clones) are dropped.
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<WarmEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// Publish an event. The event is built lazily so callers pay nothing
+    /// (not even a path clone) when there are no subscribers.
+    pub fn publish(&self, make_event: impl FnOnce() -> WarmEvent) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let event = make_event();
+        for subscriber in &self.subscribers {
+            let _ = subscriber.send(event.clone());
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Process exit codes.
+//!
+//! Automation runs the warmer before putting a volume into service, so the exit status
+//! has to say whether the data was actually warmed:
+//!
+//! | code    | meaning                                                          |
+//! |---------|------------------------------------------------------------------|
+//! | 0       | every attempted file was warmed (or failures stayed in budget)   |
+//! | 1       | fatal setup error: bad options, no usable strategy, I/O failure   |
+//! | 2       | more than `--max-error-percent` of files failed, or `--fail-fast` |
+//! | 3       | `--strict-coverage` and some file wasn't fully read, or           |
+//! |         | `estimate --warm-below` couldn't rule out more cold data          |
+//! | 128 + N | stopped by signal N                                              |
+
+use crate::stats::GroupStats;
+
+pub const SUCCESS: i32 = 0;
+/// Also what `main` returns for an `Err`
+pub const FATAL: i32 = 1;
+pub const ERROR_BUDGET_EXCEEDED: i32 = 2;
+pub const COVERAGE_INCOMPLETE: i32 = 3;
+
+/// Share of attempted files (skipped ones don't count) that failed, in percent
+pub fn error_percent(totals: &GroupStats) -> f64 {
+    let attempted = totals.files - totals.skipped;
+    if attempted == 0 {
+        0.0
+    } else {
+        totals.failed as f64 * 100.0 / attempted as f64
+    }
+}
+
+/// Whether `totals` spend more than `max_error_percent` of the attempted files on failures
+pub fn error_budget_exceeded(totals: &GroupStats, max_error_percent: f64) -> bool {
+    totals.failed > 0 && error_percent(totals) > max_error_percent
+}
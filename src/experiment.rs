@@ -0,0 +1,253 @@
+//! A/B strategy experiments (`--ab-test`).
+//!
+//! Each file is assigned to one of several strategies ("arms") at random, in proportion
+//! to the arm weights (`--split 70/30`), and warmed with that strategy alone, without
+//! falling back. Per-arm latency and per-file throughput are accumulated as running
+//! statistics, and the report gives their means with 95% confidence intervals, so an
+//! operator can tell whether one strategy is actually faster on their fleet or whether
+//! the difference is noise.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+/// z-score for a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// Arm weights for `--split`, e.g. `50/50` or `70/20/10`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split(pub Vec<u32>);
+
+impl FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let weights = s
+            .split('/')
+            .map(|part| part.trim().parse::<u32>().map_err(|_| format!("invalid split '{}' (expected weights such as 50/50)", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if weights.iter().all(|&w| w == 0) {
+            return Err(format!("split '{}' gives every strategy a weight of zero", s));
+        }
+        Ok(Split(weights))
+    }
+}
+
+/// Count, mean and variance of a series, updated one value at a time (Welford's method)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunningStats {
+    pub count: u64,
+    pub mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Sample standard deviation
+    pub fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+    }
+
+    /// Half-width of the 95% confidence interval for the mean
+    pub fn ci95(&self) -> f64 {
+        if self.count < 2 {
+            f64::INFINITY
+        } else {
+            Z_95 * self.std_dev() / (self.count as f64).sqrt()
+        }
+    }
+}
+
+/// What one arm saw
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ArmStats {
+    pub files: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    pub busy: Duration,
+    /// Per-file warm latency, in milliseconds
+    pub latency_ms: RunningStats,
+    /// Per-file throughput in MB/s, for files with data
+    pub throughput_mbps: RunningStats,
+}
+
+#[derive(Debug)]
+struct Arm {
+    strategy: Strategy,
+    weight: u32,
+    options: WarmingOptions,
+    stats: Mutex<ArmStats>,
+}
+
+/// A running experiment, shared by all warming tasks
+#[derive(Debug)]
+pub struct Experiment {
+    arms: Vec<Arm>,
+    total_weight: u64,
+    seed: u64,
+}
+
+impl Experiment {
+    /// One arm per strategy, weighted by `split` (equal weights if empty). Each arm
+    /// warms with `base` options but only its own strategy.
+    pub fn new(strategies: &[Strategy], split: &[u32], base: &WarmingOptions, seed: u64) -> Result<Self, String> {
+        if strategies.len() < 2 {
+            return Err("an A/B test needs at least two strategies".to_string());
+        }
+        if let Some(auto) = strategies.iter().find(|s| **s == Strategy::Auto) {
+            return Err(format!("'{}' can't be an A/B test arm; name concrete strategies", auto));
+        }
+        if !split.is_empty() && split.len() != strategies.len() {
+            return Err(format!("--split has {} weights for {} strategies", split.len(), strategies.len()));
+        }
+        let arms: Vec<Arm> = strategies
+            .iter()
+            .enumerate()
+            .map(|(i, &strategy)| Arm {
+                strategy,
+                weight: split.get(i).copied().unwrap_or(1),
+                options: WarmingOptions { strategy, fallback: FallbackPolicy::None, ..base.clone() },
+                stats: Mutex::new(ArmStats::default()),
+            })
+            .collect();
+        let total_weight = arms.iter().map(|arm| arm.weight as u64).sum();
+        if total_weight == 0 {
+            return Err("--split gives every strategy a weight of zero".to_string());
+        }
+        Ok(Self { arms, total_weight, seed })
+    }
+
+    pub fn strategies(&self) -> impl Iterator<Item = (Strategy, u32)> + '_ {
+        self.arms.iter().map(|arm| (arm.strategy, arm.weight))
+    }
+
+    /// Warming options of every arm, in order
+    pub fn arm_options(&self) -> impl Iterator<Item = &WarmingOptions> {
+        self.arms.iter().map(|arm| &arm.options)
+    }
+
+    /// Arm for `path`. A hash of the path and the seed rather than a shared random
+    /// stream, so concurrent tasks need no coordination and a given seed always splits
+    /// a tree the same way.
+    pub fn assign(&self, path: &Path) -> usize {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64 ^ self.seed;
+        for byte in path.as_os_str().as_encoded_bytes() {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // Finish with splitmix64 so similar paths don't land in similar buckets
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+
+        let mut ticket = hash % self.total_weight;
+        for (i, arm) in self.arms.iter().enumerate() {
+            if ticket < arm.weight as u64 {
+                return i;
+            }
+            ticket -= arm.weight as u64;
+        }
+        self.arms.len() - 1
+    }
+
+    pub fn options(&self, arm: usize) -> &WarmingOptions {
+        &self.arms[arm].options
+    }
+
+    /// Record a warmed (`success`) or failed file for `arm`
+    pub fn record(&self, arm: usize, bytes: u64, latency: Duration, success: bool) {
+        let mut stats = self.arms[arm].stats.lock().unwrap();
+        stats.files += 1;
+        if !success {
+            stats.failed += 1;
+            return;
+        }
+        stats.bytes += bytes;
+        stats.busy += latency;
+        stats.latency_ms.push(latency.as_secs_f64() * 1000.0);
+        if bytes > 0 && !latency.is_zero() {
+            stats.throughput_mbps.push(bytes as f64 / (1024.0 * 1024.0) / latency.as_secs_f64());
+        }
+    }
+
+    pub fn report(&self) -> ExperimentReport {
+        ExperimentReport {
+            arms: self.arms.iter().map(|arm| (arm.strategy, arm.weight, *arm.stats.lock().unwrap())).collect(),
+        }
+    }
+}
+
+/// Per-arm results of an experiment
+#[derive(Debug, Clone)]
+pub struct ExperimentReport {
+    pub arms: Vec<(Strategy, u32, ArmStats)>,
+}
+
+impl ExperimentReport {
+    /// The arm with the lowest mean latency, if its confidence interval doesn't overlap
+    /// any other arm's
+    pub fn clear_winner(&self) -> Option<Strategy> {
+        let measured: Vec<_> = self.arms.iter().filter(|(_, _, stats)| stats.latency_ms.count >= 2).collect();
+        if measured.len() < 2 || measured.len() < self.arms.len() {
+            return None;
+        }
+        let best = measured
+            .iter()
+            .min_by(|a, b| a.2.latency_ms.mean.total_cmp(&b.2.latency_ms.mean))?;
+        let best_upper = best.2.latency_ms.mean + best.2.latency_ms.ci95();
+        let separated = measured
+            .iter()
+            .filter(|arm| arm.0 != best.0)
+            .all(|arm| arm.2.latency_ms.mean - arm.2.latency_ms.ci95() > best_upper);
+        separated.then_some(best.0)
+    }
+}
+
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "A/B test results (means with 95% confidence intervals):")?;
+        writeln!(
+            f,
+            "  {:<10} {:>6} {:>10} {:>7} {:>12} {:>24} {:>24}",
+            "strategy", "weight", "files", "failed", "MB", "latency ms/file", "MB/s per file"
+        )?;
+        for (strategy, weight, stats) in &self.arms {
+            writeln!(
+                f,
+                "  {:<10} {:>6} {:>10} {:>7} {:>12.2} {:>24} {:>24}",
+                strategy.name(),
+                weight,
+                stats.files,
+                stats.failed,
+                stats.bytes as f64 / (1024.0 * 1024.0),
+                format_interval(&stats.latency_ms),
+                format_interval(&stats.throughput_mbps),
+            )?;
+        }
+        match self.clear_winner() {
+            Some(strategy) => writeln!(f, "  {} has the lowest per-file latency, beyond the margin of error", strategy),
+            None => writeln!(f, "  No strategy is faster beyond the margin of error; warm more files for a clearer result"),
+        }
+    }
+}
+
+fn format_interval(stats: &RunningStats) -> String {
+    match stats.count {
+        0 => "-".to_string(),
+        1 => format!("{:.3}", stats.mean),
+        _ => format!("{:.3} ± {:.3}", stats.mean, stats.ci95()),
+    }
+}
@@ -0,0 +1,202 @@
+//! Fair sharing of warming concurrency between target directories.
+//!
+//! With several target directories on different volumes, a single discovery walk feeding
+//! a single queue gives all of the queue depth to whichever directory yields files
+//! fastest (and doesn't reach the next directory until the first is done). Instead the
+//! directories are grouped into shares (by volume by default, see [`ShareBy`]), each
+//! discovered concurrently into its own queue. Whenever a warming slot frees up, the next
+//! batch comes from the ready share with the fewest in-flight batches per unit of
+//! weight, so busy shares split the queue depth in proportion to their weights while a
+//! share with nothing ready doesn't hold anyone back. `--share-queue-depth` additionally
+//! caps the batches any one share may have in flight.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::device_queue::device_of;
+
+/// How target directories are grouped into shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShareBy {
+    /// One share for everything: directories are walked one after another
+    None,
+    /// One share per target directory
+    Directory,
+    /// One share per volume (directories on the same device share)
+    #[default]
+    Mount,
+}
+
+impl FromStr for ShareBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(ShareBy::None),
+            "dir" | "directory" => Ok(ShareBy::Directory),
+            "mount" | "volume" => Ok(ShareBy::Mount),
+            other => Err(format!("unknown fair-share grouping '{}' (expected none, dir or mount)", other)),
+        }
+    }
+}
+
+/// `DIR=WEIGHT` from `--share-weight`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareWeight {
+    pub dir: PathBuf,
+    pub weight: u32,
+}
+
+impl FromStr for ShareWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (dir, weight) = s.rsplit_once('=').ok_or_else(|| format!("invalid share weight '{}' (expected DIR=WEIGHT)", s))?;
+        let weight = weight
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|&w| w > 0)
+            .ok_or_else(|| format!("invalid share weight '{}' (the weight must be a whole number of at least 1)", s))?;
+        Ok(ShareWeight { dir: PathBuf::from(dir), weight })
+    }
+}
+
+impl ShareWeight {
+    /// Whether the weighted directory is one of `directories`
+    pub fn is_target(&self, directories: &[PathBuf]) -> bool {
+        directories.iter().any(|dir| same_dir(&self.dir, dir))
+    }
+}
+
+/// Fair-sharing settings for a run
+#[derive(Debug, Clone, Default)]
+pub struct FairShareOptions {
+    pub by: ShareBy,
+    /// Weights of individual target directories; unlisted directories weigh 1
+    pub weights: Vec<ShareWeight>,
+    /// Most batches one share may have in flight; 0 means only the overall queue depth applies
+    pub queue_depth: usize,
+}
+
+impl FairShareOptions {
+    /// Weight of target directory `dir`
+    pub fn weight_of(&self, dir: &Path) -> u32 {
+        self.weights.iter().find(|w| same_dir(&w.dir, dir)).map_or(1, |w| w.weight)
+    }
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// A group of target directories that is discovered together and competes for warming
+/// slots as one
+#[derive(Debug)]
+pub struct Share {
+    pub name: String,
+    pub roots: Vec<PathBuf>,
+    pub weight: u32,
+    /// `st_dev` of the roots, if they're all on one device
+    pub device: Option<u64>,
+    in_flight: AtomicUsize,
+    batches: AtomicU64,
+    files: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Share {
+    pub fn new(roots: Vec<PathBuf>, weight: u32) -> Self {
+        let name = roots.iter().map(|root| root.display().to_string()).collect::<Vec<_>>().join(",");
+        let device = roots
+            .iter()
+            .map(|root| device_of(root))
+            .collect::<Option<Vec<u64>>>()
+            .filter(|devices| devices.windows(2).all(|w| w[0] == w[1]))
+            .and_then(|devices| devices.first().copied());
+        Self { name, roots, weight: weight.max(1), device, in_flight: AtomicUsize::new(0), batches: AtomicU64::new(0), files: AtomicU64::new(0), bytes: AtomicU64::new(0) }
+    }
+
+    /// A batch from this share was handed to a warming slot
+    pub fn batch_started(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.batches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn batch_finished(&self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn file_warmed(&self, bytes: u64) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn summary(&self) -> ShareSummary {
+        ShareSummary {
+            name: self.name.clone(),
+            weight: self.weight,
+            batches: self.batches.load(Ordering::Relaxed),
+            files: self.files.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// What one share warmed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareSummary {
+    pub name: String,
+    pub weight: u32,
+    /// Batches handed to warming slots
+    pub batches: u64,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Group `directories` into shares, keeping their order. A volume's weight is the
+/// largest weight of its directories.
+pub fn shares(directories: &[PathBuf], options: &FairShareOptions) -> Vec<Share> {
+    let mut groups: Vec<(Option<u64>, Vec<PathBuf>, u32)> = Vec::new();
+    for (i, dir) in directories.iter().enumerate() {
+        let key = match options.by {
+            ShareBy::None => Some(0),
+            ShareBy::Directory => Some(i as u64),
+            // Directories whose device can't be read get a share of their own
+            ShareBy::Mount => device_of(dir),
+        };
+        let weight = options.weight_of(dir);
+        match groups.iter_mut().find(|(k, _, _)| key.is_some() && *k == key) {
+            Some((_, roots, w)) => {
+                roots.push(dir.clone());
+                *w = (*w).max(weight);
+            }
+            None => groups.push((key, vec![dir.clone()], weight)),
+        }
+    }
+    groups.into_iter().map(|(_, roots, weight)| Share::new(roots, weight)).collect()
+}
+
+/// Indices of the shares allowed another batch, best first: fewest in-flight batches
+/// per unit of weight, then fewest bytes warmed per unit of weight. Shares at
+/// `queue_depth` (if non-zero) are left out.
+pub fn dispatch_order(shares: &[Arc<Share>], queue_depth: usize) -> Vec<usize> {
+    let key = |share: &Share| {
+        let weight = share.weight as f64;
+        (share.in_flight() as f64 / weight, share.bytes.load(Ordering::Relaxed) as f64 / weight)
+    };
+    let mut order: Vec<usize> = (0..shares.len())
+        .filter(|&i| queue_depth == 0 || shares[i].in_flight() < queue_depth)
+        .collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (key(&shares[a]), key(&shares[b]));
+        a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+    });
+    order
+}
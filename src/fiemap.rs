@@ -0,0 +1,260 @@
+//! File extent mapping.
+//!
+//! [`data_ranges`] finds the allocated parts of sparse files (VM images, preallocated
+//! database files) so holes, which never touch the device, aren't read. [`extents`]
+//! uses the FIEMAP ioctl to translate byte ranges of a file into offsets on the
+//! underlying block device, e.g. to check them against which blocks of an EBS snapshot
+//! actually hold data, and flags the compressed ones. [`first_physical`] finds where a file's data starts on the
+//! device, for warming files in on-disk order.
+
+use std::path::Path;
+
+/// One mapped extent of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// Offset within the file
+    pub logical: u64,
+    /// Offset on the filesystem's block device, if the filesystem reports a stable one
+    pub physical: Option<u64>,
+    pub length: u64,
+    /// Preallocated but never written; reads return zeros without touching the device
+    pub unwritten: bool,
+    /// Stored compressed (btrfs `compress=`): reading any of it reads and decodes all of it
+    pub encoded: bool,
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    /// _IOWR('f', 11, struct fiemap)
+    pub const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+    pub const FIEMAP_FLAG_SYNC: u32 = 0x1;
+    pub const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    /// Any of these mean fe_physical can't be used to locate the data on the device
+    pub const FIEMAP_EXTENT_NO_PHYSICAL: u32 = 0x2 /* UNKNOWN */
+        | 0x4 /* DELALLOC */
+        | 0x8 /* ENCODED */
+        | 0x80 /* DATA_ENCRYPTED */
+        | 0x100 /* NOT_ALIGNED */
+        | 0x200 /* DATA_INLINE */
+        | 0x400 /* DATA_TAIL */;
+    pub const FIEMAP_EXTENT_ENCODED: u32 = 0x8;
+    pub const FIEMAP_EXTENT_UNWRITTEN: u32 = 0x800;
+
+    /// Extents fetched per ioctl call
+    pub const EXTENTS_PER_CALL: usize = 256;
+    /// _IO(0x00, 1): map a logical block of the file to a physical block (needs CAP_SYS_RAWIO)
+    pub const FIBMAP: libc::c_ulong = 1;
+    /// _IO(0x00, 2): the filesystem block size FIBMAP counts in
+    pub const FIGETBSZ: libc::c_ulong = 2;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    pub struct FiemapExtent {
+        pub fe_logical: u64,
+        pub fe_physical: u64,
+        pub fe_length: u64,
+        pub fe_reserved64: [u64; 2],
+        pub fe_flags: u32,
+        pub fe_reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    pub struct Fiemap {
+        pub fm_start: u64,
+        pub fm_length: u64,
+        pub fm_flags: u32,
+        pub fm_mapped_extents: u32,
+        pub fm_extent_count: u32,
+        pub fm_reserved: u32,
+        pub fm_extents: [FiemapExtent; EXTENTS_PER_CALL],
+    }
+
+    /// A FIEMAP request for just the first extent
+    #[repr(C)]
+    pub struct FiemapFirst {
+        pub fm_start: u64,
+        pub fm_length: u64,
+        pub fm_flags: u32,
+        pub fm_mapped_extents: u32,
+        pub fm_extent_count: u32,
+        pub fm_reserved: u32,
+        pub fm_extents: [FiemapExtent; 1],
+    }
+}
+
+/// Map every allocated extent of `path`. Holes are simply absent from the result.
+/// Fails with `Unsupported` on filesystems without FIEMAP.
+#[cfg(target_os = "linux")]
+pub fn extents(path: &Path) -> Result<Vec<Extent>, std::io::Error> {
+    use std::os::unix::prelude::AsRawFd;
+    use sys::*;
+
+    let file = std::fs::File::open(path)?;
+    let mut request = Box::new(Fiemap {
+        fm_start: 0,
+        fm_length: u64::MAX,
+        fm_flags: FIEMAP_FLAG_SYNC,
+        fm_mapped_extents: 0,
+        fm_extent_count: EXTENTS_PER_CALL as u32,
+        fm_reserved: 0,
+        fm_extents: [FiemapExtent::default(); EXTENTS_PER_CALL],
+    });
+
+    let mut extents = Vec::new();
+    loop {
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut *request as *mut Fiemap) };
+        if result < 0 {
+            let err = std::io::Error::last_os_error();
+            return Err(match err.raw_os_error() {
+                Some(libc::ENOTTY) | Some(libc::EOPNOTSUPP) => std::io::Error::new(std::io::ErrorKind::Unsupported, err),
+                _ => err,
+            });
+        }
+
+        let mapped = request.fm_mapped_extents as usize;
+        if mapped == 0 {
+            return Ok(extents);
+        }
+        let mut last = false;
+        for raw in &request.fm_extents[..mapped] {
+            extents.push(Extent {
+                logical: raw.fe_logical,
+                physical: (raw.fe_flags & FIEMAP_EXTENT_NO_PHYSICAL == 0).then_some(raw.fe_physical),
+                length: raw.fe_length,
+                unwritten: raw.fe_flags & FIEMAP_EXTENT_UNWRITTEN != 0,
+                encoded: raw.fe_flags & FIEMAP_EXTENT_ENCODED != 0,
+            });
+            last |= raw.fe_flags & FIEMAP_EXTENT_LAST != 0;
+        }
+        if last {
+            return Ok(extents);
+        }
+        let tail = request.fm_extents[mapped - 1];
+        request.fm_start = tail.fe_logical + tail.fe_length;
+        request.fm_length = u64::MAX - request.fm_start;
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn extents(_path: &Path) -> Result<Vec<Extent>, std::io::Error> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "FIEMAP is only supported on Linux"))
+}
+
+/// Offset on the filesystem's block device where the data of `path` starts, from one
+/// FIEMAP extent, or from FIBMAP on filesystems without FIEMAP. `None` for empty files,
+/// data not yet allocated, inline data, and when neither ioctl can say.
+#[cfg(target_os = "linux")]
+pub fn first_physical(path: &Path) -> Result<Option<u64>, std::io::Error> {
+    use std::os::unix::prelude::AsRawFd;
+    use sys::*;
+
+    let file = std::fs::File::open(path)?;
+    let fd = file.as_raw_fd();
+    // No FIEMAP_FLAG_SYNC: flushing every file's dirty pages costs more than a
+    // not-yet-allocated extent sorting last
+    let mut request = FiemapFirst {
+        fm_start: 0,
+        fm_length: u64::MAX,
+        fm_flags: 0,
+        fm_mapped_extents: 0,
+        fm_extent_count: 1,
+        fm_reserved: 0,
+        fm_extents: [FiemapExtent::default()],
+    };
+    if unsafe { libc::ioctl(fd, FS_IOC_FIEMAP as _, &mut request as *mut FiemapFirst) } == 0 {
+        let extent = request.fm_extents[0];
+        let mapped = request.fm_mapped_extents > 0 && extent.fe_flags & FIEMAP_EXTENT_NO_PHYSICAL == 0;
+        return Ok(mapped.then_some(extent.fe_physical));
+    }
+    let err = std::io::Error::last_os_error();
+    if !matches!(err.raw_os_error(), Some(libc::ENOTTY) | Some(libc::EOPNOTSUPP)) {
+        return Err(err);
+    }
+
+    let mut block_size: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, FIGETBSZ as _, &mut block_size as *mut libc::c_int) } < 0 || block_size <= 0 {
+        return Ok(None);
+    }
+    // In: logical block 0. Out: its physical block, 0 for a hole.
+    let mut block: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, FIBMAP as _, &mut block as *mut libc::c_int) } < 0 || block <= 0 {
+        return Ok(None);
+    }
+    Ok(Some(block as u64 * block_size as u64))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn first_physical(_path: &Path) -> Result<Option<u64>, std::io::Error> {
+    Ok(None)
+}
+
+/// Byte ranges of `path` that hold data, as `(offset, length)`. Returns `None` when the
+/// file has no holes (the common case, detected from its block count without any extra
+/// syscalls beyond a stat) or when the filesystem can't report them.
+#[cfg(unix)]
+pub fn data_ranges(path: &Path, file_size: u64) -> Result<Option<Vec<(u64, u64)>>, std::io::Error> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::prelude::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    // st_blocks is always in 512-byte units; a fully allocated file can't have holes
+    if file.metadata()?.blocks() * 512 >= file_size {
+        return Ok(None);
+    }
+
+    let fd = file.as_raw_fd();
+    let mut ranges = Vec::new();
+    let mut pos = 0u64;
+    while pos < file_size {
+        let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENXIO) => Ok(Some(ranges)), // no data past `pos`
+                Some(libc::EINVAL) => Ok(None),        // SEEK_DATA unsupported here
+                _ => Err(err),
+            };
+        }
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let (start, end) = (data as u64, std::cmp::min(hole as u64, file_size));
+        if start >= end {
+            break;
+        }
+        ranges.push((start, end - start));
+        pos = end;
+    }
+    Ok(Some(ranges))
+}
+
+#[cfg(not(unix))]
+pub fn data_ranges(_path: &Path, _file_size: u64) -> Result<Option<Vec<(u64, u64)>>, std::io::Error> {
+    Ok(None)
+}
+
+/// Byte offset of the partition holding `path` within its whole disk, read from
+/// `/sys/dev/block/<major>:<minor>/start`. Whole-disk filesystems have offset 0.
+#[cfg(target_os = "linux")]
+pub fn partition_offset(path: &Path) -> Result<u64, std::io::Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path)?.dev();
+    let (major, minor) = (libc::major(dev), libc::minor(dev));
+    match std::fs::read_to_string(format!("/sys/dev/block/{}:{}/start", major, minor)) {
+        // sysfs reports partition starts in 512-byte sectors regardless of the device's block size
+        Ok(start) => start
+            .trim()
+            .parse::<u64>()
+            .map(|sectors| sectors * 512)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn partition_offset(_path: &Path) -> Result<u64, std::io::Error> {
+    Ok(0)
+}
@@ -0,0 +1,205 @@
+//! Keeping discovery to the filesystems a run is meant to warm.
+//!
+//! Warming `/` also walks the tmpfs, NFS and other mounts below it, whose files aren't on
+//! the volume being hydrated: reading them is wasted effort, and a slow NFS server holds
+//! up discovery. `--one-file-system` (like `du -x` and `rsync -x`) doesn't descend into
+//! directories on a different filesystem from their target directory. `--only-device`
+//! keeps only files on one block device or its partitions, wherever it's mounted below
+//! the targets: a directory on another filesystem is walked only when the device is
+//! mounted somewhere beneath it.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::layers::MountInfo;
+
+/// A block device given to `--only-device`, as `/dev/NAME` or `MAJOR:MINOR`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDevice {
+    /// As given on the command line
+    pub spec: String,
+    /// Device number
+    pub dev: u64,
+}
+
+impl FromStr for BlockDevice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = s.trim();
+        if let Some((major, minor)) = spec.split_once(':') {
+            if let (Ok(major), Ok(minor)) = (major.parse::<u32>(), minor.parse::<u32>()) {
+                return Ok(Self { spec: spec.to_string(), dev: makedev(major, minor) });
+            }
+        }
+        block_device_number(Path::new(spec))
+            .map(|dev| Self { spec: spec.to_string(), dev })
+            .map_err(|why| format!("'{}' is not a block device ({}); give /dev/NAME or MAJOR:MINOR", spec, why))
+    }
+}
+
+impl fmt::Display for BlockDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (major, minor) = split_dev(self.dev);
+        if self.spec == format!("{}:{}", major, minor) {
+            write!(f, "{}", self.spec)
+        } else {
+            write!(f, "{} ({}:{})", self.spec, major, minor)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn block_device_number(path: &Path) -> Result<u64, String> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let metadata = std::fs::metadata(path).map_err(|e| e.to_string())?;
+    if !metadata.file_type().is_block_device() {
+        return Err("not a block special file".to_string());
+    }
+    Ok(metadata.rdev())
+}
+
+#[cfg(not(unix))]
+fn block_device_number(_path: &Path) -> Result<u64, String> {
+    Err("device paths are only supported on Unix".to_string())
+}
+
+fn makedev(major: u32, minor: u32) -> u64 {
+    libc::makedev(major, minor) as u64
+}
+
+fn split_dev(dev: u64) -> (u32, u32) {
+    (libc::major(dev as libc::dev_t) as u32, libc::minor(dev as libc::dev_t) as u32)
+}
+
+/// The partitions of the disk numbered `dev`, from sysfs; none if it isn't a whole disk
+#[cfg(target_os = "linux")]
+fn partitions(dev: u64) -> Vec<u64> {
+    let (major, minor) = split_dev(dev);
+    let Ok(disk) = std::fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(&disk) else { return Vec::new() };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().join("partition").exists())
+        .filter_map(|entry| {
+            let number = std::fs::read_to_string(entry.path().join("dev")).ok()?;
+            let (major, minor) = number.trim().split_once(':')?;
+            Some(makedev(major.parse().ok()?, minor.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn partitions(_dev: u64) -> Vec<u64> {
+    Vec::new()
+}
+
+/// Where discovery may go: within each target's own filesystem, onto one device, or both
+#[derive(Debug, Clone, Default)]
+pub struct FilesystemScope {
+    one_file_system: bool,
+    only: Option<OnlyDevice>,
+}
+
+#[derive(Debug, Clone)]
+struct OnlyDevice {
+    /// The device and its partitions
+    devices: Vec<u64>,
+    /// Where any of them is mounted
+    mount_points: Vec<PathBuf>,
+}
+
+impl FilesystemScope {
+    /// `--one-file-system` and `--only-device`, finding where the device is mounted from
+    /// `/proc/self/mountinfo`
+    pub fn new(one_file_system: bool, only_device: Option<&BlockDevice>) -> std::io::Result<Self> {
+        let mounts = match only_device {
+            Some(_) => crate::layers::parse_mountinfo(&std::fs::read_to_string("/proc/self/mountinfo")?),
+            None => Vec::new(),
+        };
+        Ok(Self::with_mounts(one_file_system, only_device, &mounts))
+    }
+
+    /// Like [`FilesystemScope::new`], with the device's mounts taken from `mounts`
+    pub fn with_mounts(one_file_system: bool, only_device: Option<&BlockDevice>, mounts: &[MountInfo]) -> Self {
+        let only = only_device.map(|device| {
+            let mut devices = vec![device.dev];
+            devices.extend(partitions(device.dev));
+            let mount_points = mounts
+                .iter()
+                .filter(|mount| {
+                    let Some((major, minor)) = mount.device.split_once(':') else { return false };
+                    matches!((major.parse(), minor.parse()), (Ok(major), Ok(minor)) if devices.contains(&makedev(major, minor)))
+                })
+                .map(|mount| mount.mount_point.clone())
+                .collect();
+            OnlyDevice { devices, mount_points }
+        });
+        Self { one_file_system, only }
+    }
+
+    /// Don't descend into directories on another filesystem than the target's
+    pub fn one_file_system(&self) -> bool {
+        self.one_file_system
+    }
+
+    /// Whether entries have to be checked one by one (`--only-device`)
+    pub fn checks_entries(&self) -> bool {
+        self.only.is_some()
+    }
+
+    /// Whether a walked entry on device `dev` is kept: with `--only-device`, a file on the
+    /// device, or a directory either on it or with the device mounted beneath it.
+    /// `path` should be absolute for the mount points to be found.
+    pub fn keeps(&self, path: &Path, dev: u64, is_dir: bool) -> bool {
+        let Some(only) = &self.only else { return true };
+        if only.devices.contains(&dev) {
+            return true;
+        }
+        is_dir && only.mount_points.iter().any(|mount_point| mount_point.starts_with(path))
+    }
+
+    /// Whether anything under `root` can be kept; false for a target that is neither on
+    /// the `--only-device` device nor above one of its mounts
+    #[cfg(unix)]
+    pub fn reaches(&self, root: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let Ok(metadata) = std::fs::metadata(root) else { return true };
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+        self.keeps(&root, metadata.dev(), true)
+    }
+
+    #[cfg(not(unix))]
+    pub fn reaches(&self, _root: &Path) -> bool {
+        true
+    }
+
+    /// Whether a file found outside of a walk (e.g. by watch mode) at `path`, under
+    /// target `root`, is in scope. Files that can't be stat'ed are left for warming to report.
+    #[cfg(unix)]
+    pub fn contains_file(&self, path: &Path, root: Option<&Path>) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        if !self.one_file_system && self.only.is_none() {
+            return true;
+        }
+        let Ok(metadata) = std::fs::metadata(path) else { return true };
+        if self.one_file_system {
+            let root_dev = root.and_then(|root| std::fs::metadata(root).ok()).map(|root| root.dev());
+            if root_dev.is_some_and(|root_dev| root_dev != metadata.dev()) {
+                return false;
+            }
+        }
+        self.keeps(path, metadata.dev(), false)
+    }
+
+    #[cfg(not(unix))]
+    pub fn contains_file(&self, _path: &Path, _root: Option<&Path>) -> bool {
+        true
+    }
+}
@@ -0,0 +1,402 @@
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use log::debug;
+use regex::Regex;
+
+use crate::anonymize;
+use crate::changed::{self, ChangeFilter};
+use crate::deadline;
+use crate::dedupe::InodeSet;
+use crate::filesystems::FilesystemScope;
+
+/// Include/exclude rules applied while discovering files.
+///
+/// Globs are compiled into the `ignore` crate's overrides (one set per root, since
+/// override globs are matched relative to the directory being walked). Regexes are
+/// matched against the full path and applied through the walker's entry filter, as are
+/// the [`Shard`] restriction, the [`FilesystemScope`], the [`FileCriteria`] and the
+/// [`ChangeFilter`].
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryFilters {
+    roots: Vec<PathBuf>,
+    root_overrides: Vec<(PathBuf, Override)>,
+    include_regexes: Vec<Regex>,
+    exclude_regexes: Vec<Regex>,
+    shard: Option<Shard>,
+    filesystems: FilesystemScope,
+    criteria: Option<Arc<FileCriteria>>,
+    changed: Option<Arc<ChangeFilter>>,
+}
+
+/// A kind of file `--file-type` selects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// `f`: a regular file reached by its own path
+    Regular,
+    /// `l`: a file reached through a symbolic link (with `--follow-symlinks`, or listed)
+    Symlink,
+    /// `s`: a sparse file, with fewer bytes allocated than its size
+    Sparse,
+}
+
+impl FromStr for FileKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "f" | "file" => Ok(FileKind::Regular),
+            "l" | "symlink" => Ok(FileKind::Symlink),
+            "s" | "sparse" => Ok(FileKind::Sparse),
+            other => Err(format!("unknown file type '{}' (expected f, l or s)", other)),
+        }
+    }
+}
+
+/// Size, age and type conditions a file must meet (`--min-file-size`, `--newer-than`,
+/// `--older-than`, `--file-type`). Files that can't be stat'ed meet them, for warming
+/// to report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileCriteria {
+    /// Smallest size in bytes; 0 for any
+    pub min_size: u64,
+    /// Only files modified at or after this time
+    pub newer_than: Option<SystemTime>,
+    /// Only files modified before this time
+    pub older_than: Option<SystemTime>,
+    /// Only files of one of these kinds; empty for any
+    pub kinds: Vec<FileKind>,
+}
+
+impl FileCriteria {
+    pub fn is_empty(&self) -> bool {
+        *self == FileCriteria::default()
+    }
+
+    /// Whether a file with `metadata` (of the file itself, links followed) meets the
+    /// criteria. `through_link` says whether it was reached through a symbolic link.
+    pub fn matches(&self, metadata: &Metadata, through_link: bool) -> bool {
+        if metadata.len() < self.min_size {
+            return false;
+        }
+        if self.newer_than.is_some() || self.older_than.is_some() {
+            let Ok(modified) = metadata.modified() else { return true };
+            if self.newer_than.is_some_and(|newer| modified < newer) || self.older_than.is_some_and(|older| modified >= older) {
+                return false;
+            }
+        }
+        self.kinds.is_empty()
+            || self.kinds.iter().any(|kind| match kind {
+                FileKind::Regular => !through_link,
+                FileKind::Symlink => through_link,
+                FileKind::Sparse => is_sparse(metadata),
+            })
+    }
+}
+
+/// Parse `--newer-than`/`--older-than`: a timestamp as for `--changed-since`, or a
+/// duration before now such as `7d` or `12h`
+pub fn parse_time_bound(s: &str) -> Result<SystemTime, String> {
+    if let Some(time) = changed::parse_timestamp(s) {
+        return Ok(time);
+    }
+    let ago = deadline::parse_duration(s).map_err(|_| format!("invalid time '{}' (expected a duration such as 7d or 12h, or a date such as 2026-10-17)", s.trim()))?;
+    SystemTime::now().checked_sub(ago).ok_or_else(|| format!("'{}' is before the epoch", s.trim()))
+}
+
+#[cfg(unix)]
+fn is_sparse(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.blocks().saturating_mul(512) < metadata.len()
+}
+
+#[cfg(not(unix))]
+fn is_sparse(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// One of `count` disjoint subsets of the files, for splitting a run between processes
+/// (`--shards`/`--shard-index`). A file belongs to the shard its path relative to the
+/// target directory hashes to, so every process agrees on the split without talking to
+/// the others, even where the volume is mounted at different paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Shard {
+    pub fn new(index: u32, count: u32) -> Result<Self> {
+        if count == 0 || index >= count {
+            anyhow::bail!("shard index {} is out of range for {} shards (expected 0..{})", index, count, count);
+        }
+        Ok(Self { index, count })
+    }
+
+    /// The shard a file belongs to, from its path relative to the target directory
+    pub fn of(relative: &Path, count: u32) -> u32 {
+        (hash_path(relative) % count as u64) as u32
+    }
+
+    pub fn contains(&self, relative: &Path) -> bool {
+        Self::of(relative, self.count) == self.index
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// FNV-1a over the path's bytes with a final avalanche step: unlike `DefaultHasher`, the
+/// same on every platform and Rust version, so separately built warmers agree
+fn hash_path(path: &Path) -> u64 {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in path.as_os_str().as_bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^ (hash >> 33)
+}
+
+impl DiscoveryFilters {
+    pub fn new(
+        roots: &[PathBuf],
+        include_globs: &[String],
+        exclude_globs: &[String],
+        include_regexes: &[String],
+        exclude_regexes: &[String],
+    ) -> Result<Self> {
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>> {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p).with_context(|| format!("Invalid regex: {}", p)))
+                .collect()
+        };
+
+        let mut root_overrides = Vec::new();
+        if !include_globs.is_empty() || !exclude_globs.is_empty() {
+            for root in roots {
+                root_overrides.push((root.clone(), build_overrides(root, include_globs, exclude_globs)?));
+            }
+        }
+
+        Ok(Self {
+            roots: roots.to_vec(),
+            root_overrides,
+            include_regexes: compile(include_regexes)?,
+            exclude_regexes: compile(exclude_regexes)?,
+            shard: None,
+            filesystems: FilesystemScope::default(),
+            criteria: None,
+            changed: None,
+        })
+    }
+
+    /// Only discover the files in `shard`
+    pub fn with_shard(mut self, shard: Shard) -> Self {
+        self.shard = Some(shard);
+        self
+    }
+
+    pub fn shard(&self) -> Option<Shard> {
+        self.shard
+    }
+
+    /// Only discover files within `filesystems`
+    pub fn with_filesystems(mut self, filesystems: FilesystemScope) -> Self {
+        self.filesystems = filesystems;
+        self
+    }
+
+    pub fn filesystems(&self) -> &FilesystemScope {
+        &self.filesystems
+    }
+
+    /// Only discover files that meet `criteria`
+    pub fn with_criteria(mut self, criteria: FileCriteria) -> Self {
+        self.criteria = (!criteria.is_empty()).then(|| Arc::new(criteria));
+        self
+    }
+
+    pub fn criteria(&self) -> Option<&Arc<FileCriteria>> {
+        self.criteria.as_ref()
+    }
+
+    /// Only discover files that changed as `changed` sees it (`--changed-since`)
+    pub fn with_changed(mut self, changed: Arc<ChangeFilter>) -> Self {
+        self.changed = Some(changed);
+        self
+    }
+
+    pub fn changed(&self) -> Option<&Arc<ChangeFilter>> {
+        self.changed.as_ref()
+    }
+
+    fn overrides_for(&self, root: &Path) -> Option<&Override> {
+        self.root_overrides
+            .iter()
+            .find(|(r, _)| r == root)
+            .map(|(_, overrides)| overrides)
+    }
+
+    /// Attach these filters to a walker rooted at `root`. With `inodes`, entries that
+    /// pass them are also dropped if their inode was already reached by another path.
+    pub fn apply(&self, walker_builder: &mut WalkBuilder, root: &Path, inodes: Option<Arc<InodeSet>>) {
+        if let Some(overrides) = self.overrides_for(root) {
+            walker_builder.overrides(overrides.clone());
+        }
+        walker_builder.same_file_system(self.filesystems.one_file_system());
+
+        let filesystems = self.filesystems.checks_entries().then(|| self.filesystems.clone());
+        if !self.include_regexes.is_empty() || !self.exclude_regexes.is_empty() || self.shard.is_some() || inodes.is_some() || filesystems.is_some() || self.criteria.is_some() || self.changed.is_some() {
+            let include = self.include_regexes.clone();
+            let exclude = self.exclude_regexes.clone();
+            let shard = self.shard;
+            let criteria = self.criteria.clone();
+            let changed = self.changed.clone();
+            let root = root.to_path_buf();
+            // Mount points are absolute, so entries under a relative root are checked against where it really is
+            let absolute_root = std::path::absolute(&root).unwrap_or_else(|_| root.clone());
+            // The walker keeps a single entry filter, so inode deduplication has to live in this one
+            walker_builder.filter_entry(move |entry| {
+                let path = entry.path().to_string_lossy();
+                if exclude.iter().any(|re| re.is_match(&path)) {
+                    return false;
+                }
+                if let Some(filesystems) = &filesystems {
+                    if !on_filesystems(filesystems, entry, &root, &absolute_root) {
+                        return false;
+                    }
+                }
+                // Include regexes and shards only restrict files; directories must still be descended into
+                let is_file = entry.file_type().is_some_and(|ft| ft.is_file());
+                if is_file {
+                    if let Some(shard) = shard {
+                        if !shard.contains(entry.path().strip_prefix(&root).unwrap_or(entry.path())) {
+                            return false;
+                        }
+                    }
+                    if !include.is_empty() && !include.iter().any(|re| re.is_match(&path)) {
+                        return false;
+                    }
+                    if let Some(criteria) = &criteria {
+                        if entry.metadata().is_ok_and(|metadata| !criteria.matches(&metadata, entry.path_is_symlink())) {
+                            return false;
+                        }
+                    }
+                    // Last, so only files every other filter keeps are counted as unchanged
+                    if let Some(changed) = &changed {
+                        if entry.metadata().is_ok_and(|metadata| !changed.changed(entry.path(), &metadata)) {
+                            return false;
+                        }
+                    }
+                }
+                inodes.as_ref().is_none_or(|inodes| inodes.first_visit(entry))
+            });
+        }
+    }
+
+    /// Check a single file path found outside of a walk (e.g. by watch mode).
+    pub fn matches_file(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+        if self.exclude_regexes.iter().any(|re| re.is_match(&path_str)) {
+            return false;
+        }
+        if !self.include_regexes.is_empty() && !self.include_regexes.iter().any(|re| re.is_match(&path_str)) {
+            return false;
+        }
+        let root = self.roots.iter().filter(|root| path.starts_with(root)).max_by_key(|root| root.components().count());
+        if let Some(shard) = self.shard {
+            if !shard.contains(root.and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path)) {
+                return false;
+            }
+        }
+        if !self.filesystems.contains_file(path, root.map(PathBuf::as_path)) {
+            return false;
+        }
+        if let Some(criteria) = &self.criteria {
+            let through_link = std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink());
+            if std::fs::metadata(path).is_ok_and(|metadata| !criteria.matches(&metadata, through_link)) {
+                return false;
+            }
+        }
+        if let Some(changed) = &self.changed {
+            if std::fs::metadata(path).is_ok_and(|metadata| !changed.changed(path, &metadata)) {
+                return false;
+            }
+        }
+
+        let Some((root, overrides)) = self
+            .root_overrides
+            .iter()
+            .find(|(root, _)| path.starts_with(root))
+        else {
+            return true;
+        };
+
+        // An excluded ancestor directory excludes everything below it
+        let mut ancestor = path.parent();
+        while let Some(dir) = ancestor {
+            if dir == root.as_path() {
+                break;
+            }
+            if overrides.matched(dir, true).is_ignore() {
+                return false;
+            }
+            ancestor = dir.parent();
+        }
+
+        !overrides.matched(path, false).is_ignore()
+    }
+}
+
+/// Whether a walked entry is within `filesystems`. Entries that can't be stat'ed are kept,
+/// for the walk or warming to report.
+#[cfg(unix)]
+fn on_filesystems(filesystems: &FilesystemScope, entry: &ignore::DirEntry, root: &Path, absolute_root: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(metadata) = entry.metadata() else { return true };
+    let is_dir = metadata.is_dir();
+    let path = match entry.path().strip_prefix(root) {
+        Ok(relative) if is_dir => absolute_root.join(relative),
+        _ => entry.path().to_path_buf(),
+    };
+    let kept = filesystems.keeps(&path, metadata.dev(), is_dir);
+    if !kept && is_dir {
+        debug!("Not descending into {}: not on the --only-device device", anonymize::display(entry.path()));
+    }
+    kept
+}
+
+#[cfg(not(unix))]
+fn on_filesystems(_filesystems: &FilesystemScope, _entry: &ignore::DirEntry, _root: &Path, _absolute_root: &Path) -> bool {
+    true
+}
+
+fn build_overrides(root: &Path, include_globs: &[String], exclude_globs: &[String]) -> Result<Override> {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in include_globs {
+        builder
+            .add(glob)
+            .with_context(|| format!("Invalid include glob: {}", glob))?;
+    }
+    for glob in exclude_globs {
+        builder
+            .add(&format!("!{}", glob))
+            .with_context(|| format!("Invalid exclude glob: {}", glob))?;
+    }
+    Ok(builder.build()?)
+}
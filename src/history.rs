@@ -0,0 +1,325 @@
+//! Run history and regression detection (`history` subcommand).
+//!
+//! Each warm run appends one line of summary metrics to `history.jsonl` in the state
+//! directory, keyed by its set of target directories. `history` prints the trend of
+//! each target set and flags the latest run when it warmed markedly slower than the
+//! median of the runs before it. Runs record the kernel release, strategy, EBS volumes and
+//! snapshot, so a regression can be lined up with whatever changed: a new snapshot, a
+//! kernel upgrade, a different strategy.
+//!
+//! Throughput is compared whatever the run's size; duration only when the run read
+//! about as many bytes as its baseline did, since more data taking longer is no
+//! regression. Interrupted runs are listed but never compared.
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// History file inside the state directory
+pub const HISTORY_FILE: &str = "history.jsonl";
+/// Default `--regression-threshold`, in percent
+pub const DEFAULT_REGRESSION_PERCENT: f64 = 25.0;
+/// Earlier complete runs the latest one is compared against, at most
+const BASELINE_RUNS: usize = 5;
+/// Earlier complete runs needed before anything is flagged
+const MIN_BASELINE_RUNS: usize = 2;
+
+/// `$XDG_STATE_HOME/rust-cache-warmer`, `~/.local/state/rust-cache-warmer`, or
+/// `/var/lib/rust-cache-warmer` without a home directory (as under systemd)
+pub fn default_state_dir() -> PathBuf {
+    let env = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    match (env("XDG_STATE_HOME"), env("HOME")) {
+        (Some(state), _) => state.join("rust-cache-warmer"),
+        (None, Some(home)) => home.join(".local/state/rust-cache-warmer"),
+        (None, None) => PathBuf::from("/var/lib/rust-cache-warmer"),
+    }
+}
+
+/// Identifies a set of target directories whatever their order or spelling
+pub fn targets_key(directories: &[PathBuf]) -> String {
+    let mut canonical: Vec<PathBuf> = directories.iter().map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone())).collect();
+    canonical.sort();
+    canonical.dedup();
+    let mut hasher = Sha256::new();
+    for dir in &canonical {
+        hasher.update(dir.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().iter().take(8).map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Summary metrics of one run, one JSON line in the history file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Unix time in milliseconds at the end of the run
+    pub timestamp_ms: u64,
+    /// [`targets_key`] of the run's target directories
+    pub targets_key: String,
+    pub targets: Vec<String>,
+    pub version: String,
+    pub kernel: Option<String>,
+    /// First strategy of the resolved chain
+    pub strategy: String,
+    pub files: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    pub duration_secs: f64,
+    /// EBS volume IDs behind the targets, where known
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+    /// Stopped before every file was warmed: by a signal, `--max-duration` or `--fail-fast`
+    pub interrupted: bool,
+}
+
+impl RunRecord {
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+    }
+
+    /// Bytes per second
+    pub fn throughput(&self) -> f64 {
+        if self.duration_secs > 0.0 {
+            self.bytes as f64 / self.duration_secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Append `record` to the history in `state_dir`, creating the directory if needed
+pub fn record(state_dir: &Path, record: &RunRecord) -> std::io::Result<()> {
+    std::fs::create_dir_all(state_dir)?;
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(state_dir.join(HISTORY_FILE))?;
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+/// Every run recorded in `state_dir`, oldest first; lines that can't be parsed (say,
+/// cut short by a crash) are skipped
+pub fn load(state_dir: &Path) -> std::io::Result<Vec<RunRecord>> {
+    let file = match std::fs::File::open(state_dir.join(HISTORY_FILE)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => debug!("Skipping line {} of the run history: {}", number + 1, e),
+        }
+    }
+    Ok(records)
+}
+
+/// The runs of one set of target directories
+#[derive(Debug, Clone)]
+pub struct TargetHistory {
+    pub targets_key: String,
+    /// As the latest run named them
+    pub targets: Vec<String>,
+    /// Oldest first
+    pub runs: Vec<RunRecord>,
+    /// Runs shown by `Display`, the latest ones; 0 shows all
+    pub show: usize,
+}
+
+/// Group `records` by target set, in the order each set was last run
+pub fn by_targets(records: Vec<RunRecord>) -> Vec<TargetHistory> {
+    let mut histories: Vec<TargetHistory> = Vec::new();
+    for record in records {
+        match histories.iter_mut().find(|history| history.targets_key == record.targets_key) {
+            Some(history) => {
+                history.targets = record.targets.clone();
+                history.runs.push(record);
+            }
+            None => histories.push(TargetHistory {
+                targets_key: record.targets_key.clone(),
+                targets: record.targets.clone(),
+                runs: vec![record],
+                show: 0,
+            }),
+        }
+    }
+    histories.sort_by_key(|history| history.runs.last().map_or(0, |run| run.timestamp_ms));
+    histories
+}
+
+/// A metric of the latest run that's worse than its baseline by more than the threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub metric: Metric,
+    pub latest: f64,
+    /// Median of the earlier runs compared against
+    pub baseline: f64,
+    /// How much worse, in percent
+    pub percent: f64,
+    pub baseline_runs: usize,
+    /// What differs between the latest run and the one before it
+    pub changes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Throughput,
+    Duration,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.metric {
+            Metric::Throughput => write!(
+                f,
+                "throughput {:.1} MB/s is {:.0}% below the median of the previous {} runs ({:.1} MB/s)",
+                self.latest / MB,
+                self.percent,
+                self.baseline_runs,
+                self.baseline / MB
+            )?,
+            Metric::Duration => write!(
+                f,
+                "duration {:.1?} is {:.0}% above the median of the previous {} runs ({:.1?})",
+                Duration::from_secs_f64(self.latest),
+                self.percent,
+                self.baseline_runs,
+                Duration::from_secs_f64(self.baseline)
+            )?,
+        }
+        if !self.changes.is_empty() {
+            write!(f, "; changed since the run before: {}", self.changes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+const MB: f64 = 1024.0 * 1024.0;
+
+impl TargetHistory {
+    /// How the latest complete run compares with the median of up to five complete runs
+    /// before it; empty unless it's worse by more than `threshold_percent`
+    pub fn regressions(&self, threshold_percent: f64) -> Vec<Regression> {
+        let complete: Vec<&RunRecord> = self.runs.iter().filter(|run| !run.interrupted).collect();
+        let Some((latest, earlier)) = complete.split_last() else { return Vec::new() };
+        let baseline = &earlier[earlier.len().saturating_sub(BASELINE_RUNS)..];
+        if baseline.len() < MIN_BASELINE_RUNS {
+            return Vec::new();
+        }
+        let changes = earlier.last().map(|previous| changes(previous, latest)).unwrap_or_default();
+        let baseline_runs = baseline.len();
+        let regression = |metric, latest: f64, baseline: f64, percent: f64| Regression { metric, latest, baseline, percent, baseline_runs, changes: changes.clone() };
+
+        let mut regressions = Vec::new();
+        let throughput = median(baseline.iter().map(|run| run.throughput()));
+        if throughput > 0.0 {
+            let drop = (throughput - latest.throughput()) * 100.0 / throughput;
+            if drop > threshold_percent {
+                regressions.push(regression(Metric::Throughput, latest.throughput(), throughput, drop));
+            }
+        }
+        let bytes = median(baseline.iter().map(|run| run.bytes as f64));
+        let duration = median(baseline.iter().map(|run| run.duration_secs));
+        let same_size = bytes > 0.0 && ((latest.bytes as f64 - bytes).abs() * 100.0 / bytes) <= threshold_percent;
+        if same_size && duration > 0.0 {
+            let rise = (latest.duration_secs - duration) * 100.0 / duration;
+            if rise > threshold_percent {
+                regressions.push(regression(Metric::Duration, latest.duration_secs, duration, rise));
+            }
+        }
+        regressions
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values: Vec<f64> = values.collect();
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// What differs between two runs of the same targets that could explain a change in speed
+fn changes(before: &RunRecord, after: &RunRecord) -> Vec<String> {
+    let mut changes = Vec::new();
+    let or_unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".to_string());
+    if before.kernel != after.kernel {
+        changes.push(format!("kernel {} → {}", or_unknown(&before.kernel), or_unknown(&after.kernel)));
+    }
+    if before.snapshot != after.snapshot {
+        changes.push(format!("snapshot {} → {}", or_unknown(&before.snapshot), or_unknown(&after.snapshot)));
+    }
+    if before.volumes != after.volumes && !after.volumes.is_empty() {
+        changes.push(format!("volumes {}", after.volumes.join(",")));
+    }
+    if before.strategy != after.strategy {
+        changes.push(format!("strategy {} → {}", before.strategy, after.strategy));
+    }
+    if before.version != after.version {
+        changes.push(format!("warmer {} → {}", before.version, after.version));
+    }
+    changes
+}
+
+impl fmt::Display for TargetHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = if self.show == 0 { self.runs.len() } else { self.show.min(self.runs.len()) };
+        writeln!(f, "{} ({} runs{})", self.targets.join(", "), self.runs.len(), if shown < self.runs.len() { format!(", latest {}", shown) } else { String::new() })?;
+        writeln!(f, "  {:<16}  {:>10}  {:>10}  {:>10}  {:>11}  {:<10}  changes", "finished (UTC)", "duration", "files", "bytes", "throughput", "strategy")?;
+        let first = self.runs.len() - shown;
+        for (i, run) in self.runs.iter().enumerate().skip(first) {
+            let mut notes = if i > 0 { changes(&self.runs[i - 1], run) } else { Vec::new() };
+            if run.interrupted {
+                notes.insert(0, "interrupted".to_string());
+            }
+            if run.failed > 0 {
+                notes.insert(0, format!("{} failed", run.failed));
+            }
+            let line = format!(
+                "  {:<16}  {:>10}  {:>10}  {:>10}  {:>6.1} MB/s  {:<10}  {}",
+                utc(run.timestamp_ms),
+                format!("{:.1?}", Duration::from_secs_f64(run.duration_secs.max(0.0))),
+                run.files,
+                indicatif::HumanBytes(run.bytes).to_string(),
+                run.throughput() / MB,
+                run.strategy,
+                notes.join(", ")
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC
+pub(crate) fn utc(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let (days, rest) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rest / 3600, rest % 3600 / 60)
+}
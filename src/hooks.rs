@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Metadata attached to a discovered file by embedder-supplied resolvers.
+///
+/// `priority` controls warming order within a batch (higher first), while `tenant`
+/// and `shard` are used to break down the final report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub tenant: Option<String>,
+    pub shard: Option<String>,
+    pub priority: i32,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Maps a path to metadata. Resolvers run during discovery, so they should be cheap
+/// (e.g. prefix lookups) rather than doing I/O of their own.
+pub trait MetadataResolver: Send + Sync {
+    fn resolve(&self, path: &Path, metadata: &mut FileMetadata);
+}
+
+impl<F> MetadataResolver for F
+where
+    F: Fn(&Path, &mut FileMetadata) + Send + Sync,
+{
+    fn resolve(&self, path: &Path, metadata: &mut FileMetadata) {
+        self(path, metadata)
+    }
+}
+
+/// Ordered set of metadata resolvers registered by a library user
+#[derive(Clone, Default)]
+pub struct MetadataHooks {
+    resolvers: Vec<Arc<dyn MetadataResolver>>,
+}
+
+impl MetadataHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a resolver. Resolvers run in registration order and may overwrite
+    /// values set by earlier ones.
+    pub fn register<R: MetadataResolver + 'static>(&mut self, resolver: R) -> &mut Self {
+        self.resolvers.push(Arc::new(resolver));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resolvers.is_empty()
+    }
+
+    /// Run all resolvers for `path`. Returns `None` when no resolvers are registered
+    /// so the common CLI case carries no per-file metadata at all.
+    pub fn resolve(&self, path: &Path) -> Option<FileMetadata> {
+        if self.resolvers.is_empty() {
+            return None;
+        }
+        let mut metadata = FileMetadata::default();
+        for resolver in &self.resolvers {
+            resolver.resolve(path, &mut metadata);
+        }
+        Some(metadata)
+    }
+}
+
+impl fmt::Debug for MetadataHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataHooks")
+            .field("resolvers", &self.resolvers.len())
+            .finish()
+    }
+}
+
+/// Files and bytes warmed for one metadata group
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupTotals {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Per-tenant and per-shard totals built from resolved metadata
+#[derive(Debug, Clone, Default)]
+pub struct MetadataReport {
+    pub by_tenant: BTreeMap<String, GroupTotals>,
+    pub by_shard: BTreeMap<String, GroupTotals>,
+}
+
+impl MetadataReport {
+    pub fn record(&mut self, metadata: &FileMetadata, bytes: u64) {
+        if let Some(tenant) = &metadata.tenant {
+            let totals = self.by_tenant.entry(tenant.clone()).or_default();
+            totals.files += 1;
+            totals.bytes += bytes;
+        }
+        if let Some(shard) = &metadata.shard {
+            let totals = self.by_shard.entry(shard.clone()).or_default();
+            totals.files += 1;
+            totals.bytes += bytes;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_tenant.is_empty() && self.by_shard.is_empty()
+    }
+}
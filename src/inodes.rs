@@ -0,0 +1,370 @@
+//! Warming the blocks of given inodes straight off the device (`rust-cache-warmer inodes`).
+//!
+//! On a filesystem with hundreds of millions of files, walking the tree to find the few
+//! thousand an application needs first can take longer than reading them. Applications
+//! that keep their own catalog often know those files by inode number, so here discovery
+//! is skipped altogether: the inodes' data blocks are looked up with the filesystem's own
+//! debugging tool (`debugfs` for ext2/3/4, `xfs_db` for XFS), which reads the inode
+//! tables directly, and the resulting ranges are read from the block device in on-disk
+//! order. No path is ever resolved, so the files don't even need to be reachable through
+//! a mount.
+//!
+//! Both tools read the device as it is on disk: on a mounted filesystem, blocks of files
+//! written since the last flush may not be reflected yet.
+
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use indicatif::ProgressBar;
+use log::{debug, warn};
+
+use crate::anonymize;
+use crate::shutdown::Shutdown;
+use crate::warming::buffers::{self, BufferPool};
+
+/// Device reads are aligned to this, as O_DIRECT requires
+pub const ALIGNMENT: u64 = buffers::ALIGNMENT as u64;
+/// Default largest single read
+pub const DEFAULT_READ_SIZE: u64 = 1024 * 1024;
+/// Inodes resolved per `debugfs` or `xfs_db` invocation
+const INODES_PER_CALL: usize = 1000;
+
+/// Where the superblock says what the filesystem is
+const EXT_MAGIC_OFFSET: usize = 1024 + 56;
+const EXT_LOG_BLOCK_SIZE_OFFSET: usize = 1024 + 24;
+const EXT_MAGIC: u16 = 0xEF53;
+const XFS_MAGIC: &[u8; 4] = b"XFSB";
+
+/// Tool that maps inodes to blocks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Resolver {
+    /// Whichever suits the filesystem on the device
+    #[default]
+    Auto,
+    Debugfs,
+    XfsDb,
+}
+
+impl std::str::FromStr for Resolver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(Resolver::Auto),
+            "debugfs" => Ok(Resolver::Debugfs),
+            "xfs_db" | "xfs-db" => Ok(Resolver::XfsDb),
+            other => Err(format!("unknown resolver '{}' (expected auto, debugfs or xfs_db)", other)),
+        }
+    }
+}
+
+impl fmt::Display for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Resolver::Auto => "auto",
+            Resolver::Debugfs => "debugfs",
+            Resolver::XfsDb => "xfs_db",
+        })
+    }
+}
+
+/// Filesystem layout, from its superblock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Geometry {
+    Ext { block_size: u64 },
+    /// XFS block numbers are per allocation group, of `ag_blocks` blocks each
+    Xfs { block_size: u64, ag_blocks: u64 },
+}
+
+impl Geometry {
+    /// Read the superblock at the start of `device`
+    pub fn probe(device: &Path) -> std::io::Result<Self> {
+        use std::io::Read;
+
+        let mut superblock = vec![0u8; 4096];
+        let mut file = std::fs::File::open(device)?;
+        file.read_exact(&mut superblock)?;
+        Self::parse(&superblock).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::Unsupported, format!("{} holds neither an ext2/3/4 nor an XFS filesystem", device.display()))
+        })
+    }
+
+    /// Recognize the first 4KiB of a device
+    pub fn parse(superblock: &[u8]) -> Option<Self> {
+        if superblock.get(..4) == Some(XFS_MAGIC) {
+            let be32 = |offset: usize| u32::from_be_bytes(superblock[offset..offset + 4].try_into().unwrap()) as u64;
+            return Some(Geometry::Xfs { block_size: be32(4), ag_blocks: be32(84) });
+        }
+        let magic = superblock.get(EXT_MAGIC_OFFSET..EXT_MAGIC_OFFSET + 2)?;
+        if u16::from_le_bytes([magic[0], magic[1]]) != EXT_MAGIC {
+            return None;
+        }
+        let log = u32::from_le_bytes(superblock[EXT_LOG_BLOCK_SIZE_OFFSET..EXT_LOG_BLOCK_SIZE_OFFSET + 4].try_into().unwrap());
+        Some(Geometry::Ext { block_size: 1024 << log.min(6) })
+    }
+
+    /// The resolver `requested` means for this filesystem, or why it can't be used
+    pub fn resolver(&self, requested: Resolver) -> Result<Resolver, String> {
+        match (self, requested) {
+            (Geometry::Ext { .. }, Resolver::Auto | Resolver::Debugfs) => Ok(Resolver::Debugfs),
+            (Geometry::Xfs { .. }, Resolver::Auto | Resolver::XfsDb) => Ok(Resolver::XfsDb),
+            (_, requested) => Err(format!("{} can't read {}", requested, self)),
+        }
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Geometry::Ext { block_size } => write!(f, "ext2/3/4 ({} byte blocks)", block_size),
+            Geometry::Xfs { block_size, ag_blocks } => write!(f, "XFS ({} byte blocks, {} per allocation group)", block_size, ag_blocks),
+        }
+    }
+}
+
+/// Where one inode's data is on the device, as `(offset, length)` byte ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InodeExtents {
+    pub inode: u64,
+    pub ranges: Vec<(u64, u64)>,
+}
+
+/// Parse an inode list: numbers separated by whitespace, commas or newlines, with `#`
+/// starting a comment
+pub fn parse_inode_list(text: &str) -> Result<Vec<u64>, String> {
+    let mut inodes = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        for token in line.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty()) {
+            match token.parse::<u64>() {
+                Ok(inode) if inode > 0 => inodes.push(inode),
+                _ => return Err(format!("line {}: '{}' isn't an inode number", number + 1, token)),
+            }
+        }
+    }
+    Ok(inodes)
+}
+
+/// Parse the output of `debugfs -f -` running `blocks <N>` for each inode: the echoed
+/// command names the inode, and the next line lists its data blocks
+pub fn parse_debugfs(output: &str, block_size: u64) -> Vec<InodeExtents> {
+    let mut inodes: Vec<InodeExtents> = Vec::new();
+    for line in output.lines() {
+        if let Some(command) = line.strip_prefix("debugfs:") {
+            let inode = command.trim().strip_prefix("blocks").and_then(|rest| rest.trim().trim_start_matches('<').trim_end_matches('>').parse().ok());
+            if let Some(inode) = inode {
+                inodes.push(InodeExtents { inode, ranges: Vec::new() });
+            }
+            continue;
+        }
+        let Some(current) = inodes.last_mut() else { continue };
+        for block in line.split_whitespace().filter_map(|block| block.parse::<u64>().ok()) {
+            let offset = block * block_size;
+            match current.ranges.last_mut() {
+                Some((start, length)) if *start + *length == offset => *length += block_size,
+                _ => current.ranges.push((offset, block_size)),
+            }
+        }
+    }
+    inodes
+}
+
+/// Parse the output of `xfs_db -r` running `echo ino N`, `inode N` and `bmap` for each
+/// inode. Unwritten extents read back as zeros without touching the device, so they're
+/// left out.
+pub fn parse_xfs_bmap(output: &str, block_size: u64, ag_blocks: u64) -> Vec<InodeExtents> {
+    let mut inodes: Vec<InodeExtents> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(inode) = line.strip_prefix("ino ").and_then(|inode| inode.trim().parse().ok()) {
+            inodes.push(InodeExtents { inode, ranges: Vec::new() });
+            continue;
+        }
+        // data offset 0 startblock 24 (0/24) count 8 flag 0
+        let (Some(current), Some(extent)) = (inodes.last_mut(), line.strip_prefix("data ")) else { continue };
+        let words: Vec<&str> = extent.split_whitespace().collect();
+        let field = |name: &str| words.iter().position(|word| *word == name).and_then(|i| words.get(i + 1));
+        let location = words.iter().find(|word| word.starts_with('(')).map(|word| word.trim_matches(|c| c == '(' || c == ')'));
+        let (Some((ag, ag_block)), Some(count)) = (location.and_then(|location| location.split_once('/')), field("count").and_then(|count| count.parse::<u64>().ok())) else {
+            continue;
+        };
+        if field("flag").is_some_and(|flag| *flag != "0") {
+            continue;
+        }
+        let (Ok(ag), Ok(ag_block)) = (ag.parse::<u64>(), ag_block.parse::<u64>()) else { continue };
+        current.ranges.push(((ag * ag_blocks + ag_block) * block_size, count * block_size));
+    }
+    inodes
+}
+
+/// Look up the data blocks of `inodes` on `device`. Inodes the tool reports nothing for
+/// (unused, inline data, or an error) come back with no ranges.
+pub fn resolve(device: &Path, geometry: &Geometry, resolver: Resolver, inodes: &[u64]) -> std::io::Result<Vec<InodeExtents>> {
+    let resolver = geometry.resolver(resolver).map_err(std::io::Error::other)?;
+    let mut resolved = Vec::with_capacity(inodes.len());
+    for chunk in inodes.chunks(INODES_PER_CALL) {
+        let output = match resolver {
+            Resolver::XfsDb => {
+                let mut command = Command::new("xfs_db");
+                command.arg("-r");
+                for inode in chunk {
+                    command.arg("-c").arg(format!("echo ino {}", inode)).arg("-c").arg(format!("inode {}", inode)).arg("-c").arg("bmap");
+                }
+                run(command.arg(device), None)?
+            }
+            _ => {
+                let script: String = chunk.iter().map(|inode| format!("blocks <{}>\n", inode)).collect();
+                let mut command = Command::new("debugfs");
+                // Catastrophic mode: skip loading the allocation bitmaps, which only writes need
+                run(command.arg("-c").arg("-f").arg("-").arg(device), Some(script))?
+            }
+        };
+        let found = match *geometry {
+            Geometry::Ext { block_size } => parse_debugfs(&output, block_size),
+            Geometry::Xfs { block_size, ag_blocks } => parse_xfs_bmap(&output, block_size, ag_blocks),
+        };
+        // Keep the requested order, and an entry for every inode even if the tool skipped it
+        for &inode in chunk {
+            resolved.push(found.iter().find(|extents| extents.inode == inode).cloned().unwrap_or(InodeExtents { inode, ranges: Vec::new() }));
+        }
+    }
+    Ok(resolved)
+}
+
+/// Run a resolver, feeding it `stdin`, and return what it printed
+fn run(command: &mut Command, stdin: Option<String>) -> std::io::Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    debug!("Resolving inodes with {}", program);
+    let mut child = command
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => std::io::Error::new(e.kind(), format!("{} not found; install {}", program, if program == "xfs_db" { "xfsprogs" } else { "e2fsprogs" })),
+            _ => e,
+        })?;
+    if let (Some(script), Some(mut input)) = (stdin, child.stdin.take()) {
+        input.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for line in stderr.lines().filter(|line| !line.trim().is_empty()) {
+        debug!("{}: {}", program, line);
+    }
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!("{} failed ({}): {}", program, output.status, stderr.lines().last().unwrap_or(""))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Turn resolved extents into device reads: aligned to [`ALIGNMENT`], in on-disk order,
+/// overlapping and adjacent ranges merged, and split into reads of at most `read_size`
+pub fn plan_reads(inodes: &[InodeExtents], read_size: u64) -> Vec<(u64, u64)> {
+    let read_size = (read_size / ALIGNMENT).max(1) * ALIGNMENT;
+    let mut ranges: Vec<(u64, u64)> = inodes
+        .iter()
+        .flat_map(|inode| inode.ranges.iter())
+        .filter(|(_, length)| *length > 0)
+        .map(|&(offset, length)| (offset / ALIGNMENT * ALIGNMENT, (offset + length).div_ceil(ALIGNMENT) * ALIGNMENT))
+        .collect();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+        .into_iter()
+        .flat_map(|(start, end)| (start..end).step_by(read_size as usize).map(move |offset| (offset, read_size.min(end - offset))))
+        .collect()
+}
+
+/// What an inode run covered
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InodeSummary {
+    pub inodes: u64,
+    /// Inodes with no data blocks found: unused, inline data, or the resolver failed on them
+    pub unresolved: Vec<u64>,
+    pub reads: u64,
+    pub bytes: u64,
+    /// Reads that failed
+    pub errors: u64,
+    pub duration: Duration,
+    /// Stopped by a shutdown request before every range was read
+    pub interrupted: bool,
+}
+
+impl fmt::Display for InodeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Inode warming{}: {} of {} inodes, {} in {} reads in {:.2?} ({}/s), {} errors",
+            if self.interrupted { " (interrupted)" } else { "" },
+            self.inodes - self.unresolved.len() as u64,
+            self.inodes,
+            indicatif::HumanBytes(self.bytes),
+            self.reads,
+            self.duration,
+            indicatif::HumanBytes((self.bytes as f64 / self.duration.as_secs_f64().max(0.001)) as u64),
+            self.errors
+        )
+    }
+}
+
+/// Read `reads` from `device`, `queue_depth` at a time, with O_DIRECT unless `buffered`.
+/// `progress` counts bytes.
+pub async fn warm(device: &Path, reads: Vec<(u64, u64)>, queue_depth: usize, buffered: bool, shutdown: &Shutdown, progress: &ProgressBar) -> std::io::Result<(u64, u64)> {
+    let file = Arc::new(open(device, buffered)?);
+    let bytes = AtomicU64::new(0);
+    let errors = AtomicU64::new(0);
+    let mut pending = stream::iter(reads)
+        .take_while(|_| std::future::ready(!shutdown.is_triggered()))
+        .map(|(offset, length)| {
+            let file = Arc::clone(&file);
+            async move { tokio::task::spawn_blocking(move || read(&file, offset, length)).await.map_err(std::io::Error::other)? }
+        })
+        .buffer_unordered(queue_depth.max(1));
+    while let Some(result) = pending.next().await {
+        match result {
+            Ok(n) => {
+                bytes.fetch_add(n, Ordering::Relaxed);
+                progress.inc(n);
+            }
+            Err(e) => {
+                if errors.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Failed to read {}: {}", anonymize::display(device), e);
+                }
+            }
+        }
+    }
+    Ok((bytes.into_inner(), errors.into_inner()))
+}
+
+fn open(device: &Path, buffered: bool) -> std::io::Result<std::fs::File> {
+    #[cfg(target_os = "linux")]
+    if !buffered {
+        use std::os::unix::fs::OpenOptionsExt;
+        return std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(device);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = buffered;
+    std::fs::File::open(device)
+}
+
+/// One aligned read; short at the end of the device
+fn read(file: &std::fs::File, offset: u64, length: u64) -> std::io::Result<u64> {
+    use std::os::unix::fs::FileExt;
+
+    let mut buffer = BufferPool::global().get_blocking(length as usize)?;
+    Ok(file.read_at(buffer.as_mut_slice(), offset)? as u64)
+}
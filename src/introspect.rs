@@ -0,0 +1,206 @@
+//! Runtime introspection.
+//!
+//! The pipeline keeps per-stage file counts and the set of files currently being warmed
+//! in an [`Introspection`]. Its snapshot is the second part of the `SIGQUIT` dump of
+//! [`crate::live`], after the running totals, so a run that looks stuck can be
+//! diagnosed in production without a debugger: where files are piling up, and which
+//! operations have been running the longest.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::anonymize;
+
+/// Shared pipeline state; cloning is cheap and all clones see the same counters
+#[derive(Debug, Clone)]
+pub struct Introspection {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    started: Instant,
+    discovered: AtomicU64,
+    discovery_done: AtomicBool,
+    /// Discovered files in batches not yet picked up by a warming task
+    queued: AtomicU64,
+    /// Files in batches waiting for a queue-depth permit
+    waiting: AtomicU64,
+    /// Files in batches holding a permit that haven't been started yet
+    active: AtomicU64,
+    /// Files that left the pipeline: warmed, failed, skipped or cancelled
+    finished: AtomicU64,
+    next_id: AtomicU64,
+    in_flight: Mutex<HashMap<u64, (PathBuf, u64, Instant)>>,
+}
+
+impl Default for Introspection {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                started: Instant::now(),
+                discovered: AtomicU64::new(0),
+                discovery_done: AtomicBool::new(false),
+                queued: AtomicU64::new(0),
+                waiting: AtomicU64::new(0),
+                active: AtomicU64::new(0),
+                finished: AtomicU64::new(0),
+                next_id: AtomicU64::new(0),
+                in_flight: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+/// Counts its file as finished when dropped
+#[derive(Debug)]
+pub struct Processing {
+    inner: Arc<Inner>,
+}
+
+impl Drop for Processing {
+    fn drop(&mut self) {
+        self.inner.finished.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Removes its file from the in-flight set when dropped, including when the warm is cancelled
+#[derive(Debug)]
+pub struct InFlight {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.inner.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Introspection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `files` discovered files are about to be handed to the warming stage
+    pub fn enqueued(&self, files: u64) {
+        self.inner.discovered.fetch_add(files, Ordering::Relaxed);
+        self.inner.queued.fetch_add(files, Ordering::Relaxed);
+    }
+
+    /// A warming task picked up a batch and is waiting for a permit
+    pub fn dequeued(&self, files: u64) {
+        self.inner.queued.fetch_sub(files, Ordering::Relaxed);
+        self.inner.waiting.fetch_add(files, Ordering::Relaxed);
+    }
+
+    pub fn permit_acquired(&self, files: u64) {
+        self.inner.waiting.fetch_sub(files, Ordering::Relaxed);
+        self.inner.active.fetch_add(files, Ordering::Relaxed);
+    }
+
+    /// Take the next file of an active batch; it counts as finished once the guard drops
+    pub fn process(&self) -> Processing {
+        self.inner.active.fetch_sub(1, Ordering::Relaxed);
+        Processing { inner: Arc::clone(&self.inner) }
+    }
+
+    /// `files` files of an active batch won't be processed (the run is shutting down)
+    pub fn abandoned(&self, files: u64) {
+        self.inner.active.fetch_sub(files, Ordering::Relaxed);
+    }
+
+    pub fn discovery_done(&self) {
+        self.inner.discovery_done.store(true, Ordering::Relaxed);
+    }
+
+    /// Track `path` as being warmed until the returned guard is dropped
+    pub fn begin(&self, path: &Path, size: u64) -> InFlight {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.inner.in_flight.lock().unwrap().insert(id, (path.to_path_buf(), size, Instant::now()));
+        InFlight { inner: Arc::clone(&self.inner), id }
+    }
+
+    pub fn snapshot(&self) -> IntrospectionSnapshot {
+        let now = Instant::now();
+        let mut in_flight: Vec<InFlightFile> = self
+            .inner
+            .in_flight
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(path, size, started)| InFlightFile { path: path.clone(), size: *size, elapsed: now - *started })
+            .collect();
+        in_flight.sort_by_key(|file| std::cmp::Reverse(file.elapsed));
+        IntrospectionSnapshot {
+            uptime: now - self.inner.started,
+            discovered: self.inner.discovered.load(Ordering::Relaxed),
+            discovery_done: self.inner.discovery_done.load(Ordering::Relaxed),
+            queued: self.inner.queued.load(Ordering::Relaxed),
+            waiting: self.inner.waiting.load(Ordering::Relaxed),
+            active: self.inner.active.load(Ordering::Relaxed),
+            finished: self.inner.finished.load(Ordering::Relaxed),
+            in_flight,
+        }
+    }
+}
+
+/// A file being warmed right now
+#[derive(Debug, Clone)]
+pub struct InFlightFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub elapsed: Duration,
+}
+
+/// Point-in-time view of the pipeline, per stage
+#[derive(Debug, Clone)]
+pub struct IntrospectionSnapshot {
+    pub uptime: Duration,
+    pub discovered: u64,
+    pub discovery_done: bool,
+    pub queued: u64,
+    pub waiting: u64,
+    pub active: u64,
+    pub finished: u64,
+    /// Slowest first
+    pub in_flight: Vec<InFlightFile>,
+}
+
+/// In-flight files listed in a dump
+const DUMP_SLOWEST: usize = 20;
+
+impl fmt::Display for IntrospectionSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== rust-cache-warmer state after {:.1?} ===", self.uptime)?;
+        writeln!(
+            f,
+            "  discovery:            {} files found ({})",
+            self.discovered,
+            if self.discovery_done { "done" } else { "running" }
+        )?;
+        writeln!(f, "  queued for warming:   {} files", self.queued)?;
+        writeln!(f, "  waiting for a permit: {} files", self.waiting)?;
+        writeln!(f, "  waiting in a batch:   {} files", self.active)?;
+        writeln!(f, "  finished:             {} files", self.finished)?;
+        writeln!(f, "  in flight:            {} files, slowest first:", self.in_flight.len())?;
+        for file in self.in_flight.iter().take(DUMP_SLOWEST) {
+            writeln!(
+                f,
+                "    {:>10.2?} {:>12.2} MB  {}",
+                file.elapsed,
+                file.size as f64 / (1024.0 * 1024.0),
+                anonymize::display(&file.path)
+            )?;
+        }
+        if self.in_flight.len() > DUMP_SLOWEST {
+            writeln!(f, "    ... and {} more", self.in_flight.len() - DUMP_SLOWEST)?;
+        }
+        Ok(())
+    }
+}
+
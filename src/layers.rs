@@ -0,0 +1,258 @@
+//! Resolving targets on overlayfs and bind mounts to the host paths behind them.
+//!
+//! A container's merged root is an overlay mount: reading through it does reach the EBS
+//! volume, but only via the layer directories underneath, which other containers built
+//! from the same image share. Warming every merged view would read those shared layers
+//! once per container. [`MountTable::resolve`] maps a target to the directories that
+//! actually hold its files (the overlay's upperdir and lowerdirs, or for a bind mount the
+//! same directory where its filesystem is mounted whole), and [`roots`] merges the results
+//! so that each directory is walked once, however many targets lead to it.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Overlays on bind mounts on overlays: stop following after this many hops
+const MAX_DEPTH: usize = 8;
+
+/// One line of `/proc/self/mountinfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountInfo {
+    /// `major:minor` of the filesystem
+    pub device: String,
+    /// Directory of the filesystem that is mounted here; not `/` for bind mounts of a
+    /// subdirectory
+    pub root: PathBuf,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub source: String,
+    pub super_options: String,
+}
+
+/// Parse `/proc/self/mountinfo`, skipping malformed lines
+pub fn parse_mountinfo(text: &str) -> Vec<MountInfo> {
+    text.lines()
+        .filter_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mut fields = mount.split(' ');
+            let device = fields.nth(2)?.to_string();
+            let root = PathBuf::from(unescape(fields.next()?));
+            let mount_point = PathBuf::from(unescape(fields.next()?));
+            let mut fields = filesystem.split(' ');
+            Some(MountInfo {
+                device,
+                root,
+                mount_point,
+                fs_type: fields.next()?.to_string(),
+                source: unescape(fields.next()?),
+                super_options: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Undo the kernel's octal escapes (`\040` for a space)
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).filter(|digits| bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)));
+        match octal {
+            Some(digits) => {
+                out.push(digits.iter().fold(0u8, |value, d| value.wrapping_mul(8) + (d - b'0')));
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The upperdir and lowerdirs of an overlay mount, from its super options. Handles both
+/// `lowerdir=a:b` and the `lowerdir+=a,lowerdir+=b` form of newer kernels; data-only
+/// layers (`datadir+=`) count as the lowest lowerdirs.
+pub fn overlay_layers(super_options: &str) -> (Option<PathBuf>, Vec<PathBuf>) {
+    let mut upper = None;
+    let mut lowers = Vec::new();
+    for option in super_options.split(',') {
+        if let Some(dirs) = option.strip_prefix("lowerdir=") {
+            lowers.extend(split_unescaped(dirs, ':').map(|dir| PathBuf::from(unescape(&dir))));
+        } else if let Some(dir) = option.strip_prefix("lowerdir+=").or_else(|| option.strip_prefix("datadir+=")) {
+            lowers.push(PathBuf::from(unescape(dir)));
+        } else if let Some(dir) = option.strip_prefix("upperdir=") {
+            upper = Some(PathBuf::from(unescape(dir)));
+        }
+    }
+    (upper, lowers)
+}
+
+/// Split on `separator` except where it's escaped with a backslash
+fn split_unescaped(text: &str, separator: char) -> impl Iterator<Item = String> + '_ {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.clone().next() == Some(separator) => part.push(chars.next().unwrap()),
+            c if c == separator => parts.push(std::mem::take(&mut part)),
+            c => part.push(c),
+        }
+    }
+    parts.push(part);
+    parts.into_iter().filter(|part| !part.is_empty())
+}
+
+/// Where a resolved directory sits relative to the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerRole {
+    /// The target itself, not on an overlay or bind mount
+    Direct,
+    /// An overlay's writable layer
+    Upper,
+    /// An overlay's read-only layer, 1 being the topmost
+    Lower(usize),
+    /// The directory a bind mount shows, where its filesystem is mounted whole
+    BindSource,
+}
+
+impl fmt::Display for LayerRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayerRole::Direct => write!(f, "direct"),
+            LayerRole::Upper => write!(f, "upper"),
+            LayerRole::Lower(n) => write!(f, "lower {}", n),
+            LayerRole::BindSource => write!(f, "bind source"),
+        }
+    }
+}
+
+/// A directory holding (some of) a target's files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub path: PathBuf,
+    pub role: LayerRole,
+}
+
+/// Where a target's files really live
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// The target as given
+    pub target: PathBuf,
+    pub layers: Vec<Layer>,
+}
+
+impl Resolution {
+    /// Whether the target is warmed through other paths than its own
+    pub fn is_resolved(&self) -> bool {
+        self.layers.iter().any(|layer| layer.role != LayerRole::Direct)
+    }
+}
+
+/// The mounts visible to this process
+#[derive(Debug, Clone, Default)]
+pub struct MountTable {
+    mounts: Vec<MountInfo>,
+}
+
+impl MountTable {
+    pub fn new(mounts: Vec<MountInfo>) -> Self {
+        Self { mounts }
+    }
+
+    /// Mounts of this process's mount namespace
+    pub fn current() -> std::io::Result<Self> {
+        Ok(Self::new(parse_mountinfo(&std::fs::read_to_string("/proc/self/mountinfo")?)))
+    }
+
+    /// The mount `path` is on: the deepest mount point above it, and of mounts stacked on
+    /// the same point the last one, which hides the others
+    pub(crate) fn mount_of(&self, path: &Path) -> Option<&MountInfo> {
+        self.mounts
+            .iter()
+            .filter(|mount| path.starts_with(&mount.mount_point))
+            .max_by_key(|mount| mount.mount_point.components().count())
+    }
+
+    /// Directories that hold the files of `target`. Targets that aren't on an overlay or
+    /// bind mount (or can't be resolved) come back unchanged.
+    pub fn resolve(&self, target: &Path) -> Resolution {
+        let path = std::fs::canonicalize(target).unwrap_or_else(|_| target.to_path_buf());
+        let mut layers = Vec::new();
+        self.sources(&path, LayerRole::Direct, 0, &mut layers);
+        if layers.iter().all(|layer| layer.role == LayerRole::Direct) {
+            layers = vec![Layer { path: target.to_path_buf(), role: LayerRole::Direct }];
+        }
+        Resolution { target: target.to_path_buf(), layers }
+    }
+
+    fn sources(&self, path: &Path, role: LayerRole, depth: usize, layers: &mut Vec<Layer>) {
+        let Some(mount) = self.mount_of(path).filter(|_| depth < MAX_DEPTH) else {
+            return layers.push(Layer { path: path.to_path_buf(), role });
+        };
+        // Path within the mounted filesystem, relative to its root
+        let relative = path.strip_prefix(&mount.mount_point).unwrap_or(Path::new(""));
+        let in_fs = mount.root.join(relative);
+        let in_fs = in_fs.strip_prefix("/").unwrap_or(&in_fs);
+
+        if mount.fs_type == "overlay" {
+            let (upper, lowers) = overlay_layers(&mount.super_options);
+            let stack = upper.into_iter().map(|dir| (dir, LayerRole::Upper)).chain(lowers.into_iter().enumerate().map(|(i, dir)| (dir, LayerRole::Lower(i + 1))));
+            let before = layers.len();
+            for (dir, layer_role) in stack {
+                let dir = join(&dir, in_fs);
+                if dir.is_dir() {
+                    let role = if role == LayerRole::Direct { layer_role } else { role };
+                    self.sources(&dir, role, depth + 1, layers);
+                }
+            }
+            if layers.len() == before {
+                layers.push(Layer { path: path.to_path_buf(), role });
+            }
+            return;
+        }
+
+        // The same filesystem mounted whole (or nearer its root) elsewhere
+        let source = self
+            .mounts
+            .iter()
+            .filter(|other| other.device == mount.device && Path::new("/").join(in_fs).starts_with(&other.root))
+            .min_by_key(|other| other.root.components().count());
+        match source {
+            Some(source) if source != mount => {
+                let host = join(&source.mount_point, Path::new("/").join(in_fs).strip_prefix(&source.root).unwrap_or(in_fs));
+                if host.is_dir() {
+                    let role = if role == LayerRole::Direct { LayerRole::BindSource } else { role };
+                    return layers.push(Layer { path: host, role });
+                }
+                layers.push(Layer { path: path.to_path_buf(), role });
+            }
+            _ => layers.push(Layer { path: path.to_path_buf(), role }),
+        }
+    }
+}
+
+/// `base/relative`, without the trailing slash `Path::join` leaves for an empty `relative`
+fn join(base: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(relative)
+    }
+}
+
+/// Directories to walk for `resolutions`: every layer once, in order, leaving out layers
+/// inside another one that is already walked
+pub fn roots(resolutions: &[Resolution]) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for layer in resolutions.iter().flat_map(|resolution| &resolution.layers) {
+        if roots.iter().any(|root| layer.path.starts_with(root)) {
+            continue;
+        }
+        roots.retain(|root| !root.starts_with(&layer.path));
+        roots.push(layer.path.clone());
+    }
+    roots
+}
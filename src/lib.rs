@@ -0,0 +1,77 @@
+//! Library interface for the cache warmer.
+//!
+//! The `rust-cache-warmer` binary is a thin CLI over [`pipeline::run`]. Embedders can
+//! drive the same pipeline directly and register [`hooks::MetadataResolver`]s to tag
+//! files with tenant/shard/priority information that feeds batch ordering and the
+//! returned [`pipeline::PipelineSummary`].
+
+pub mod anonymize;
+pub mod bench;
+pub mod bloom;
+pub mod bundle;
+pub mod changed;
+pub mod checkpoint;
+pub mod clock;
+pub mod config;
+pub mod control;
+pub mod dashboard;
+pub mod coverage;
+pub mod deadline;
+pub mod dedupe;
+pub mod device;
+pub mod device_queue;
+pub mod disk;
+#[cfg(feature = "aws")]
+pub mod ebs;
+pub mod emf;
+pub mod estimate;
+pub mod events;
+pub mod exit;
+pub mod experiment;
+pub mod fair;
+pub mod fiemap;
+pub mod filesystems;
+pub mod filters;
+pub mod history;
+pub mod hooks;
+pub mod inodes;
+pub mod introspect;
+pub mod layers;
+pub mod live;
+pub mod logfile;
+pub mod metadata_pass;
+pub mod open_files;
+pub mod pace;
+pub mod paths;
+pub mod phases;
+pub mod pipeline;
+pub mod plan;
+pub mod preflight;
+pub mod priority;
+pub mod probe;
+pub mod progress;
+pub mod progress_file;
+pub mod report;
+pub mod result_log;
+#[cfg(feature = "aws")]
+pub mod sqs;
+pub mod shutdown;
+pub mod sources;
+pub mod stall;
+pub mod stats;
+pub mod supervisor;
+pub mod support;
+pub mod symlinks;
+pub mod systemd;
+pub mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod textfile;
+pub mod vanished;
+pub mod verify;
+pub mod volumes;
+pub mod warming;
+pub mod warnings;
+pub mod watch;
+pub mod watchdog;
+pub mod working_set;
@@ -0,0 +1,214 @@
+//! Live statistics.
+//!
+//! [`LiveStats`] follows the pipeline's event stream and keeps running totals, a
+//! per-strategy breakdown and a smoothed throughput. Sending the process `SIGQUIT`
+//! (Ctrl-\ on a terminal, `kill -QUIT <pid>` otherwise) prints a snapshot to stderr,
+//! followed by the pipeline state of [`crate::introspect`], without interrupting the
+//! warm. This is the only SIGQUIT handler, and the easiest way to check on a long run
+//! whose output isn't a TTY and so has no progress bars.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+use crate::clock::{PauseClock, Stopwatch};
+use crate::events::WarmEvent;
+use crate::introspect::Introspection;
+use crate::stats::FileStatus;
+use crate::warnings::{WarningCount, Warnings};
+
+/// How often the throughput average is updated
+const TICK: Duration = Duration::from_secs(1);
+/// Time constant of the throughput EWMA: older samples fade out over roughly this long
+const EWMA_WINDOW: Duration = Duration::from_secs(10);
+
+/// Files and bytes warmed by one strategy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MethodStats {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Running totals built from [`WarmEvent`]s
+#[derive(Debug, Clone)]
+pub struct LiveStats {
+    started: Stopwatch,
+    warmed: u64,
+    failed: u64,
+    skipped: u64,
+    bytes: u64,
+    by_method: BTreeMap<&'static str, MethodStats>,
+    /// Bytes warmed since the last throughput sample
+    window_bytes: u64,
+    last_sample: Instant,
+    /// Total paused time as of the last sample; a pause isn't a stall in throughput
+    paused_at_sample: Duration,
+    /// Smoothed bytes per second, `None` until the first sample
+    ewma: Option<f64>,
+}
+
+impl Default for LiveStats {
+    fn default() -> Self {
+        let started = Stopwatch::start();
+        Self {
+            started,
+            warmed: 0,
+            failed: 0,
+            skipped: 0,
+            bytes: 0,
+            by_method: BTreeMap::new(),
+            window_bytes: 0,
+            last_sample: Instant::now(),
+            paused_at_sample: PauseClock::global().paused(),
+            ewma: None,
+        }
+    }
+}
+
+impl LiveStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &WarmEvent) {
+        let WarmEvent::FileFinished { bytes, status, method, .. } = event;
+        match status {
+            FileStatus::Warmed => {
+                self.warmed += 1;
+                self.bytes += bytes;
+                self.window_bytes += bytes;
+                if let Some(method) = method {
+                    let entry = self.by_method.entry(method).or_default();
+                    entry.files += 1;
+                    entry.bytes += bytes;
+                }
+            }
+            FileStatus::Failed => self.failed += 1,
+            FileStatus::Skipped => self.skipped += 1,
+        }
+    }
+
+    /// Fold the bytes warmed since the previous sample into the throughput average
+    pub fn sample(&mut self, now: Instant) {
+        let paused = PauseClock::global().paused();
+        let elapsed = now.saturating_duration_since(self.last_sample).saturating_sub(paused.saturating_sub(self.paused_at_sample));
+        self.paused_at_sample = paused;
+        if elapsed.is_zero() {
+            self.last_sample = now;
+            return;
+        }
+        let rate = self.window_bytes as f64 / elapsed.as_secs_f64();
+        self.ewma = Some(match self.ewma {
+            None => rate,
+            Some(previous) => {
+                let alpha = 1.0 - (-elapsed.as_secs_f64() / EWMA_WINDOW.as_secs_f64()).exp();
+                previous + alpha * (rate - previous)
+            }
+        });
+        self.window_bytes = 0;
+        self.last_sample = now;
+    }
+
+    pub fn snapshot(&self) -> LiveSnapshot {
+        LiveSnapshot {
+            uptime: self.started.active(),
+            paused: self.started.paused(),
+            warmed: self.warmed,
+            failed: self.failed,
+            skipped: self.skipped,
+            bytes: self.bytes,
+            throughput_ewma: self.ewma.unwrap_or(0.0),
+            by_method: self.by_method.iter().map(|(method, stats)| (method.to_string(), *stats)).collect(),
+            warnings: Warnings::global().counts(),
+        }
+    }
+}
+
+/// Point-in-time view of a run's statistics
+#[derive(Debug, Clone)]
+pub struct LiveSnapshot {
+    /// Running time, excluding pauses
+    pub uptime: Duration,
+    /// Time the process was stopped
+    pub paused: Duration,
+    pub warmed: u64,
+    pub failed: u64,
+    pub skipped: u64,
+    pub bytes: u64,
+    /// Bytes per second, smoothed over roughly the last ten seconds
+    pub throughput_ewma: f64,
+    pub by_method: BTreeMap<String, MethodStats>,
+    pub warnings: Vec<WarningCount>,
+}
+
+impl fmt::Display for LiveSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MB: f64 = 1024.0 * 1024.0;
+        let average = if self.uptime.is_zero() { 0.0 } else { self.bytes as f64 / self.uptime.as_secs_f64() };
+        if self.paused.is_zero() {
+            writeln!(f, "=== rust-cache-warmer stats after {:.1?} ===", self.uptime)?;
+        } else {
+            writeln!(f, "=== rust-cache-warmer stats after {:.1?} running ({:.1?} paused) ===", self.uptime, self.paused)?;
+        }
+        writeln!(f, "  files:       {} warmed, {} failed, {} skipped", self.warmed, self.failed, self.skipped)?;
+        writeln!(f, "  bytes:       {:.2} MB", self.bytes as f64 / MB)?;
+        writeln!(
+            f,
+            "  throughput:  {:.2} MB/s recent, {:.2} MB/s average",
+            self.throughput_ewma / MB,
+            average / MB
+        )?;
+        if !self.by_method.is_empty() {
+            writeln!(f, "  by strategy:")?;
+            for (method, stats) in &self.by_method {
+                writeln!(f, "    {:<24} {:>10} files {:>12.2} MB", method, stats.files, stats.bytes as f64 / MB)?;
+            }
+        }
+        if !self.warnings.is_empty() {
+            writeln!(f, "  warnings:")?;
+            for count in &self.warnings {
+                writeln!(f, "    {:<24} {:>10} ({} not logged)", count.category, count.total, count.suppressed)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Follow `events` and print a snapshot to stderr, followed by the pipeline state of
+/// `introspection`, every time the process receives SIGQUIT. Keeps answering with the
+/// final totals after the event stream ends.
+#[cfg(unix)]
+pub fn listen_for_stats_signal(mut events: mpsc::UnboundedReceiver<WarmEvent>, introspection: Introspection) -> Result<tokio::task::JoinHandle<()>, std::io::Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut quit = signal(SignalKind::quit())?;
+    Ok(tokio::spawn(async move {
+        let mut stats = LiveStats::new();
+        let mut ticker = tokio::time::interval(TICK);
+        ticker.tick().await; // the first tick completes immediately
+        let mut streaming = true;
+        loop {
+            tokio::select! {
+                event = events.recv(), if streaming => match event {
+                    Some(event) => stats.record(&event),
+                    None => streaming = false,
+                },
+                _ = ticker.tick(), if streaming => stats.sample(Instant::now()),
+                received = quit.recv() => {
+                    if received.is_none() {
+                        break;
+                    }
+                    // One write, so the two parts can't interleave with other output
+                    eprint!("{}{}", stats.snapshot(), introspection.snapshot());
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn listen_for_stats_signal(_events: mpsc::UnboundedReceiver<WarmEvent>, _introspection: Introspection) -> Result<tokio::task::JoinHandle<()>, std::io::Error> {
+    Ok(tokio::spawn(async {}))
+}
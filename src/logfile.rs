@@ -0,0 +1,481 @@
+//! On-disk record logs: the checkpoint journal and the result log.
+//!
+//! With a record per file, a 50M-file run writes several GB of logs, often onto the
+//! very volume being warmed. A log whose name ends in `.gz` or `.zst` is compressed as
+//! it's written, and with a size limit it's rotated into numbered segments
+//! (`results.ndjson.zst`, then `results.ndjson.1.zst`, `results.ndjson.2.zst`, ...).
+//! [`LogReader`] reads every segment of a log in order and decompresses transparently.
+//!
+//! Compressed segments are flushed in self-contained blocks, so a crash loses at most
+//! the records written since the last flush and the segment still reads up to there.
+//! Since nothing can follow a cut-off compressed stream, reopening a compressed log
+//! always starts a new segment; plain logs are appended to in place.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use miniz_oxide::deflate::core::{create_comp_flags_from_zip_params, CompressorOxide};
+use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+/// Compression level for gzip segments
+const GZIP_LEVEL: i32 = 6;
+/// Compression level for zstd segments; zstd's default, fast enough to keep up with warming
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+/// Gzip member header: magic, deflate, no flags, no mtime, no extra flags, unknown OS
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+
+/// How a log is compressed, going by its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Gzip for `*.gz`, zstd for `*.zst`/`*.zstd`, none otherwise
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("gz") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Whether this build can read and write it
+    pub fn is_supported(&self) -> bool {
+        *self != Compression::Zstd || cfg!(feature = "zstd")
+    }
+}
+
+/// File holding segment `index` of the log at `path`: `path` itself for the first,
+/// then the index inserted before a compression extension (`a.ndjson.zst` →
+/// `a.ndjson.1.zst`) or appended (`a.ndjson` → `a.ndjson.1`)
+pub fn segment_path(path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match (Compression::for_path(path), path.extension()) {
+        (Compression::None, _) | (_, None) => format!("{}.{}", name, index),
+        (_, Some(ext)) => {
+            let ext = ext.to_string_lossy();
+            format!("{}.{}.{}", &name[..name.len() - ext.len() - 1], index, ext)
+        }
+    };
+    path.with_file_name(name)
+}
+
+/// Existing segments of the log at `path`, in order
+pub fn segments(path: &Path) -> Vec<PathBuf> {
+    (0..).map(|index| segment_path(path, index)).take_while(|segment| segment.exists()).collect()
+}
+
+/// Delete every segment of the log at `path`
+pub fn remove(path: &Path) -> Result<(), std::io::Error> {
+    for segment in segments(path) {
+        std::fs::remove_file(segment)?;
+    }
+    Ok(())
+}
+
+fn unsupported(path: &Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("{}: zstd logs need a build with the 'zstd' feature", path.display()),
+    )
+}
+
+/// Counts the bytes that reach the file, for rotation
+/// Streaming gzip encoder; every flush ends a deflate block, so the output decodes up
+/// to that point even if the member is never finished
+struct GzipWriter<W: Write> {
+    inner: W,
+    compressor: Box<CompressorOxide>,
+    crc: u32,
+    size: u32,
+    out: Vec<u8>,
+}
+
+impl<W: Write> GzipWriter<W> {
+    fn new(mut inner: W) -> std::io::Result<Self> {
+        inner.write_all(&GZIP_HEADER)?;
+        // Zero window bits: a raw deflate stream, which the gzip header and trailer wrap
+        let compressor = Box::new(CompressorOxide::new(create_comp_flags_from_zip_params(GZIP_LEVEL, 0, 0)));
+        Ok(Self { inner, compressor, crc: 0, size: 0, out: vec![0; 64 * 1024] })
+    }
+
+    fn deflate(&mut self, mut input: &[u8], flush: MZFlush) -> std::io::Result<()> {
+        if input.is_empty() && flush == MZFlush::None {
+            return Ok(());
+        }
+        loop {
+            let result = miniz_oxide::deflate::stream::deflate(&mut self.compressor, input, &mut self.out, flush);
+            let status = result.status.map_err(|e| std::io::Error::other(format!("deflate failed: {:?}", e)))?;
+            self.inner.write_all(&self.out[..result.bytes_written])?;
+            input = &input[result.bytes_consumed..];
+            let done = match flush {
+                MZFlush::Finish => status == MZStatus::StreamEnd,
+                MZFlush::None => input.is_empty(),
+                _ => input.is_empty() && result.bytes_written < self.out.len(),
+            };
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    fn finish(mut self) -> std::io::Result<W> {
+        self.deflate(&[], MZFlush::Finish)?;
+        self.inner.write_all(&self.crc.to_le_bytes())?;
+        self.inner.write_all(&self.size.to_le_bytes())?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.deflate(buf, MZFlush::None)?;
+        self.crc = crc32(self.crc, buf);
+        self.size = self.size.wrapping_add(buf.len() as u32);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.deflate(&[], MZFlush::Sync)?;
+        self.inner.flush()
+    }
+}
+
+/// CRC-32 (IEEE) as gzip's trailer needs it, continuing from `crc` (0 to start)
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+type SegmentFile = BufWriter<File>;
+
+/// The open segment of a [`LogWriter`]
+enum Sink {
+    Plain(SegmentFile),
+    Gzip(GzipWriter<SegmentFile>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, SegmentFile>),
+}
+
+impl Sink {
+    fn new(file: File, compression: Compression) -> std::io::Result<Self> {
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Sink::Plain(file),
+            Compression::Gzip => Sink::Gzip(GzipWriter::new(file)?),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Sink::Zstd(zstd::stream::write::Encoder::new(file, ZSTD_LEVEL)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "built without zstd support")),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(file) => file,
+            Sink::Gzip(encoder) => encoder,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder,
+        }
+    }
+
+    /// Complete the segment (gzip trailer, zstd frame end) and flush it to the file
+    fn finish(self) -> std::io::Result<()> {
+        let mut file = match self {
+            Sink::Plain(file) => file,
+            Sink::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+/// Writes newline-terminated records to a log, compressing and rotating as configured
+pub struct LogWriter {
+    path: PathBuf,
+    compression: Compression,
+    /// Segment size that triggers rotation, counted before compression (compressors
+    /// hold back their output, so the size on disk lags behind); 0 never rotates
+    rotate_bytes: u64,
+    /// Uncompressed bytes in the current segment
+    segment_bytes: u64,
+    /// Written at the start of every segment, e.g. a CSV header
+    header: Option<Vec<u8>>,
+    segment: usize,
+    sink: Option<Sink>,
+}
+
+impl std::fmt::Debug for LogWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogWriter")
+            .field("path", &self.path)
+            .field("compression", &self.compression)
+            .field("rotate_bytes", &self.rotate_bytes)
+            .field("segment", &self.segment)
+            .finish()
+    }
+}
+
+impl LogWriter {
+    /// Start the log at `path` afresh, deleting any earlier segments
+    pub fn create(path: &Path, rotate_bytes: u64, header: Option<&str>) -> Result<Self, std::io::Error> {
+        let compression = Compression::for_path(path);
+        if !compression.is_supported() {
+            return Err(unsupported(path));
+        }
+        remove(path)?;
+        let mut writer = Self::new(path, compression, rotate_bytes, header, 0);
+        writer.open_segment(false)?;
+        Ok(writer)
+    }
+
+    /// Continue the log at `path`: plain logs are appended to, compressed ones get a new
+    /// segment
+    pub fn append(path: &Path, rotate_bytes: u64) -> Result<Self, std::io::Error> {
+        let compression = Compression::for_path(path);
+        if !compression.is_supported() {
+            return Err(unsupported(path));
+        }
+        let existing = segments(path).len();
+        let segment = match compression {
+            Compression::None => existing.saturating_sub(1),
+            _ => existing,
+        };
+        let mut writer = Self::new(path, compression, rotate_bytes, None, segment);
+        writer.open_segment(true)?;
+        Ok(writer)
+    }
+
+    fn new(path: &Path, compression: Compression, rotate_bytes: u64, header: Option<&str>, segment: usize) -> Self {
+        let header = header.map(|header| format!("{}\n", header).into_bytes());
+        Self { path: path.to_path_buf(), compression, rotate_bytes, segment_bytes: 0, header, segment, sink: None }
+    }
+
+    /// The file currently written to
+    pub fn current_segment(&self) -> PathBuf {
+        segment_path(&self.path, self.segment)
+    }
+
+    fn open_segment(&mut self, append: bool) -> Result<(), std::io::Error> {
+        let path = self.current_segment();
+        let mut file = OpenOptions::new().read(true).write(true).create(true).append(append).truncate(!append).open(&path)?;
+        let len = file.seek(SeekFrom::End(0))?;
+        // A process killed mid-write leaves a truncated last line; start on a fresh one
+        if len > 0 && self.compression == Compression::None {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        let mut sink = Sink::new(file, self.compression)?;
+        self.segment_bytes = len;
+        if len == 0 {
+            if let Some(header) = &self.header {
+                sink.writer().write_all(header)?;
+                self.segment_bytes = header.len() as u64;
+            }
+        }
+        self.sink = Some(sink);
+        Ok(())
+    }
+
+    /// Append one record, which must end in a newline, rotating first if the segment
+    /// has reached the size limit
+    pub fn write_record(&mut self, record: &[u8]) -> Result<(), std::io::Error> {
+        if self.rotate_bytes > 0 && self.sink.is_some() && self.segment_bytes >= self.rotate_bytes {
+            self.finish()?;
+            self.segment += 1;
+            debug!("Rotating log to {}", self.current_segment().display());
+        }
+        if self.sink.is_none() {
+            if self.compression != Compression::None && self.current_segment().exists() {
+                self.segment += 1;
+            }
+            let append = self.compression == Compression::None;
+            self.open_segment(append)?;
+        }
+        self.sink.as_mut().expect("segment was just opened").writer().write_all(record)?;
+        self.segment_bytes += record.len() as u64;
+        Ok(())
+    }
+
+    /// Push buffered records to the file. Compressed records become readable too.
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        match &mut self.sink {
+            Some(sink) => sink.writer().flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Complete the current segment. Writing again afterwards starts a new segment for
+    /// compressed logs and appends for plain ones.
+    pub fn finish(&mut self) -> Result<(), std::io::Error> {
+        match self.sink.take() {
+            Some(sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Streaming gzip decoder for (possibly multi-member) gzip data
+struct GzipReader<R: BufRead> {
+    inner: R,
+    state: Box<InflateState>,
+    in_member: bool,
+}
+
+impl<R: BufRead> GzipReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, state: InflateState::new_boxed(DataFormat::Raw), in_member: false }
+    }
+
+    /// Read a member header; false at the end of the data
+    fn start_member(&mut self) -> std::io::Result<bool> {
+        if self.inner.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let mut header = [0u8; 10];
+        self.inner.read_exact(&mut header)?;
+        if header[..3] != GZIP_HEADER[..3] {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "not gzip data"));
+        }
+        let flags = header[3];
+        if flags & 0x04 != 0 {
+            let mut len = [0u8; 2];
+            self.inner.read_exact(&mut len)?;
+            std::io::copy(&mut (&mut self.inner).take(u64::from(u16::from_le_bytes(len))), &mut std::io::sink())?;
+        }
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                self.inner.read_until(0, &mut Vec::new())?;
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.inner.read_exact(&mut [0u8; 2])?;
+        }
+        self.state = InflateState::new_boxed(DataFormat::Raw);
+        self.in_member = true;
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if !self.in_member && !self.start_member()? {
+                return Ok(0);
+            }
+            let input = self.inner.fill_buf()?;
+            let at_eof = input.is_empty();
+            let result = miniz_oxide::inflate::stream::inflate(&mut self.state, input, buf, MZFlush::None);
+            self.inner.consume(result.bytes_consumed);
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    // CRC and size; the records are checked line by line anyway
+                    self.inner.read_exact(&mut [0u8; 8])?;
+                    self.in_member = false;
+                }
+                Ok(_) | Err(MZError::Buf) if result.bytes_written > 0 => {}
+                Ok(_) | Err(MZError::Buf) if at_eof => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "gzip stream ends early"));
+                }
+                Ok(_) if result.bytes_consumed > 0 => {}
+                status => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("corrupt gzip data: {:?}", status))),
+            }
+            if result.bytes_written > 0 {
+                return Ok(result.bytes_written);
+            }
+        }
+    }
+}
+
+/// Reads the records of every segment of a log, in order. A segment that ends early
+/// (cut off by a crash) yields what was flushed, and the next segment continues on a
+/// fresh line.
+pub struct LogReader {
+    compression: Compression,
+    segments: VecDeque<PathBuf>,
+    current: Option<(PathBuf, Box<dyn Read>)>,
+    /// Whether the output so far ends a line
+    at_line_start: bool,
+}
+
+impl LogReader {
+    /// Reader over the log at `path`; a log with no segments reads as empty
+    pub fn open(path: &Path) -> Result<Self, std::io::Error> {
+        let compression = Compression::for_path(path);
+        if !compression.is_supported() {
+            return Err(unsupported(path));
+        }
+        Ok(Self { compression, segments: segments(path).into(), current: None, at_line_start: true })
+    }
+
+    fn decoder(&self, file: File) -> std::io::Result<Box<dyn Read>> {
+        let file = BufReader::new(file);
+        Ok(match self.compression {
+            Compression::None => Box::new(file),
+            Compression::Gzip => Box::new(GzipReader::new(file)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "built without zstd support")),
+        })
+    }
+}
+
+impl Read for LogReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let Some((path, current)) = &mut self.current else {
+                let Some(path) = self.segments.pop_front() else { return Ok(0) };
+                let decoder = self.decoder(File::open(&path)?)?;
+                self.current = Some((path, decoder));
+                continue;
+            };
+            match current.read(buf) {
+                Ok(0) => {}
+                Ok(n) => {
+                    self.at_line_start = buf[n - 1] == b'\n';
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    debug!("{} ends early (cut off by a crash?): {}", path.display(), e);
+                }
+                Err(e) => warn!("Skipping the rest of {}: {}", path.display(), e),
+            }
+            self.current = None;
+            if !self.at_line_start {
+                buf[0] = b'\n';
+                self.at_line_start = true;
+                return Ok(1);
+            }
+        }
+    }
+}
@@ -1,26 +1,120 @@
-use anyhow::Result;
-use clap::Parser;
-use futures::stream::{self, StreamExt};
-use ignore::WalkBuilder;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use log::{debug, info, warn};
-use std::time::{Instant, Duration};
-use tokio::sync::{Semaphore, mpsc};
-
-mod warming;
-use warming::{WarmingOptions, warm_file};
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use log::{debug, error, info, warn};
+use std::time::{Duration, Instant, SystemTime};
+
+use rust_cache_warmer::anonymize::{self, AnonymizeMode, PathAnonymizer, DEFAULT_MAP_FILE};
+use rust_cache_warmer::bench::{self, BenchOptions};
+use rust_cache_warmer::bundle::{self, BundleOptions};
+use rust_cache_warmer::changed::{ChangeFilter, ChangedSince};
+use rust_cache_warmer::checkpoint::Checkpoint;
+use rust_cache_warmer::clock::{self, Stopwatch};
+use rust_cache_warmer::config::ConfigFile;
+use rust_cache_warmer::control::{self, Control};
+use rust_cache_warmer::dashboard::{self, Dashboard, DashboardState, DeviceSampler};
+use rust_cache_warmer::coverage;
+use rust_cache_warmer::deadline;
+use rust_cache_warmer::dedupe;
+use rust_cache_warmer::device::{self, Tuning};
+use rust_cache_warmer::device_queue::{self, DeviceDepths, PerDeviceDepth};
+use rust_cache_warmer::disk::{self, NonEbsPolicy};
+use rust_cache_warmer::emf::{self, EmfOptions};
+use rust_cache_warmer::estimate::{self, EstimateOptions, SampleBy};
+use rust_cache_warmer::events::EventBus;
+use rust_cache_warmer::exit;
+use rust_cache_warmer::experiment::{Experiment, Split};
+use rust_cache_warmer::fair::{self, FairShareOptions, ShareBy, ShareWeight};
+use rust_cache_warmer::filesystems::{BlockDevice, FilesystemScope};
+use rust_cache_warmer::filters::{self, DiscoveryFilters, FileCriteria, FileKind, Shard};
+use rust_cache_warmer::history::{self, RunRecord};
+use rust_cache_warmer::inodes::{self, InodeSummary, Resolver};
+use rust_cache_warmer::introspect::Introspection;
+use rust_cache_warmer::layers::{self, MountTable};
+use rust_cache_warmer::live;
+use rust_cache_warmer::logfile;
+use rust_cache_warmer::metadata_pass;
+use rust_cache_warmer::open_files;
+use rust_cache_warmer::pipeline::{
+    self, PipelineContext, PipelineOptions, PipelineProgress, DEFAULT_BATCH_BYTES, DEFAULT_DISCOVERY_BUFFER, LOW_MEMORY_BATCH_SIZE, LOW_MEMORY_PENDING_BATCHES, LOW_MEMORY_QUEUE_DEPTH, PHYSICAL_ORDER_WINDOW,
+};
+use rust_cache_warmer::preflight::{self, Cause, PreflightOptions};
+use rust_cache_warmer::priority::{self, CgroupIoLimit, IoClass, IoMax};
+use rust_cache_warmer::probe::{self, Capability, DirectIoProbe};
+use rust_cache_warmer::pace::{PaceMode, Pacing};
+use rust_cache_warmer::phases;
+use rust_cache_warmer::plan::{self, Balance};
+use rust_cache_warmer::progress::{self, ByteProgress, ProgressMode};
+use rust_cache_warmer::progress_file;
+use rust_cache_warmer::systemd::{self, JournalLogger, Notifier};
+use rust_cache_warmer::report::{ErrorSamples, RunReport};
+use rust_cache_warmer::result_log::{self, ResultLog};
+use rust_cache_warmer::shutdown::{self, Shutdown};
+use rust_cache_warmer::sources::{FileSource, PathList, S3Inventory};
+use rust_cache_warmer::stall::{self, StallMonitor};
+use rust_cache_warmer::stats::{StatsDimension, StatsSnapshot};
+use rust_cache_warmer::supervisor::{self, SupervisorOptions};
+use rust_cache_warmer::support::{self, Platform, RunRequests};
+use rust_cache_warmer::symlinks::{self, SymlinkRoots};
+use rust_cache_warmer::textfile::{self, TextfileOptions};
+use rust_cache_warmer::verify::{self, VerifyOptions};
+use rust_cache_warmer::warming::buffers::{self, BufferPool};
+use rust_cache_warmer::warming::fds::{self, FdBudget};
+use rust_cache_warmer::warming::compressed::{self, Compressing};
+use rust_cache_warmer::warming::{self, DropCaches, FallbackPolicy, RangeSelector, Strategy, StrategyMap, StrategyRegistry, StrategySize, WarmingOptions, SMALL_FILE_MAX_SIZE};
+use rust_cache_warmer::warnings::Warnings;
+use rust_cache_warmer::volumes;
+use rust_cache_warmer::watch::{self, WatchOptions};
+use rust_cache_warmer::watchdog;
+use rust_cache_warmer::working_set::WorkingSet;
 
 #[derive(Parser, Debug)]
 #[clap(
     name = "rust-cache-warmer",
     version = "1.2.0",
     author = "Shubham Kanodia",
-    about = "A high-performance, concurrent file cache warmer written in Rust."
+    about = "A high-performance, concurrent file cache warmer written in Rust.",
+    after_help = "Without a subcommand, the arguments are those of `warm`: `rust-cache-warmer /data` is `rust-cache-warmer warm /data`."
 )]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[clap(about = "Warm the files under the target directories (the default).")]
+    Warm(Box<Opts>),
+    #[clap(about = "Check how hydrated the target directories are without warming them.", long_about = "Read one block at random offsets across the target directories, bypassing the page cache, and report latency percentiles and how much still reads cold. Nothing is warmed.")]
+    Verify(VerifyOpts),
+    #[clap(about = "Estimate how much of the target directories is still cold before warming them.", long_about = "Read one block at random offsets across the target directories, bypassing the page cache, and estimate the share of the data that still reads cold, with a confidence interval. Nothing is warmed. With --warm-below, exit 3 unless the volume is known to be warmer than that.")]
+    Estimate(EstimateOpts),
+    #[clap(about = "Compare the warming strategies on this host and recommend one.", long_about = "Warm a sample of the target files with each available strategy, compare throughput and latency, and recommend a --strategy for this host. No file is read by more than one strategy, so on a volume restored from a snapshot each strategy reads cold data.")]
+    Bench(BenchOpts),
+    #[clap(about = "Collect host details for a bug report.", long_about = "Write the host details a bug report needs (capability probes, kernel, mounts, rlimits, cgroup limits) and the given config and state files to a .tar.gz.")]
+    DebugBundle(DebugBundleOpts),
+    #[clap(about = "Warm the data blocks of given inodes straight off the block device.", long_about = "Look up the data blocks of the given inode numbers with debugfs (ext2/3/4) or xfs_db (XFS) and read them from the block device in on-disk order, without walking any directory. For very large filesystems whose applications know their hot files by inode number.")]
+    Inodes(InodesOpts),
+    #[clap(about = "Show how warming the same targets performed across runs.", long_about = "Print the duration, throughput and bytes of earlier runs of each set of target directories, as recorded in the state directory, and flag the latest run if it was markedly slower than the runs before it. Kernel, snapshot, volume and strategy changes between runs are shown alongside.")]
+    History(HistoryOpts),
+    #[clap(about = "Split the target directories into shards for a fleet of instances to warm.", long_about = "Walk the target directories and write N manifests of about equal bytes, one per instance, for `warm --files-from`, plus a plan.json summarizing them. By default each shard is a contiguous run of the files' on-disk order, so instances warming a multi-attach volume or clones of one snapshot each read their own stretch of it.")]
+    ExportPlan(ExportPlanOpts),
+}
+
+/// `rust-cache-warmer warm`: discover and warm the target directories
+#[derive(Args, Debug)]
 struct Opts {
+    #[clap(long, value_name = "FILE", help = "Read option defaults from a TOML file. Keys are option names (e.g. queue_depth = 64, direct_io = true, include = [\"*.db\"]); options given on the command line take precedence.")]
+    config: Option<PathBuf>,
+
+    #[clap(long, value_name = "NAME", requires = "config", help = "Apply the [profile.NAME] section of the --config file on top of its top-level settings.")]
+    config_profile: Option<String>,
+
     #[clap(
         short,
         long,
@@ -36,8 +130,8 @@ struct Opts {
     )]
     threads: Option<usize>,
 
+    // Required unless --sqs-queue-url is given; checked after parsing since --config can supply it
     #[clap(
-        required = true,
         help = "One or more directory paths to warm.",
         num_args = 1..
     )]
@@ -59,6 +153,21 @@ struct Opts {
     )]
     max_depth: Option<usize>,
 
+    #[clap(long, help = "Warm every mounted filesystem on an EBS volume, found from /proc/self/mountinfo, in addition to any DIRECTORIES. The disk holding / is left out unless --include-root. Implies --one-file-system.")]
+    all_ebs_volumes: bool,
+
+    #[clap(long, requires = "all_ebs_volumes", help = "With --all-ebs-volumes, also warm the root volume and any other filesystems on its disk.")]
+    include_root: bool,
+
+    #[clap(long, requires = "all_ebs_volumes", help = "With --all-ebs-volumes, also count disks the instance metadata service's block-device mapping lists as EBS, for Xen instances whose xvd* disks can't be told apart from sysfs.")]
+    imds_block_devices: bool,
+
+    #[clap(short = 'x', long, help = "Don't descend into directories on a different filesystem from their target directory (like du -x), e.g. tmpfs or NFS mounts below /.")]
+    one_file_system: bool,
+
+    #[clap(long, value_name = "DEVICE", help = "Only warm files on this block device (/dev/NAME or MAJOR:MINOR) or its partitions, wherever it is mounted below the targets. Directories on other filesystems are only walked to reach a mount of the device.")]
+    only_device: Option<BlockDevice>,
+
     #[clap(long, help = "Print detailed debug information.")]
     debug: bool,
     
@@ -71,25 +180,1231 @@ struct Opts {
     #[clap(long, default_value = "0", help = "Skip files larger than this size in bytes (0 means no limit).")]
     max_file_size: u64,
 
-    #[clap(long, default_value = "0", help = "Use sparse reading for files larger than this size in bytes (0 means disabled). Reads 1 byte every 4096 bytes to warm cache efficiently.")]
+    #[clap(long, default_value = "0", help = "Use sparse reading for files larger than this size in bytes (0 means disabled): a 4096-byte block every 64KiB (Tokio buffered reads: a byte every 4096 bytes), or every --sparse-interval.")]
     sparse_large_files: u64,
 
-    #[clap(long, default_value = "1000", help = "Number of files to process per async task batch. Higher values reduce coordination overhead for small files.")]
+    #[clap(long, default_value = "1000", help = "Number of files to process per async task batch. Higher values reduce coordination overhead for small files. Batches also close at --batch-bytes.")]
     batch_size: usize,
 
+    #[clap(long, default_value_t = DEFAULT_BATCH_BYTES, value_name = "BYTES", help = "Close a batch before its files add up to more than this many bytes, so a batch of huge files doesn't hold a queue slot for hours while batches of tiny ones fly by; a larger file gets a batch of its own. --batch-size still caps the file count. 0 batches by file count alone, sparing discovery a stat per file.")]
+    batch_bytes: u64,
+
+    #[clap(long, default_value_t = DEFAULT_DISCOVERY_BUFFER, value_name = "FILES", help = "Discovered files allowed to wait for warming, per share, before the discovery threads pause; keeps memory flat on huge trees when warming is the bottleneck. 0 never pauses discovery. --low-memory lowers the default to two batches.")]
+    discovery_buffer: usize,
+
     #[clap(long, help = "Use direct I/O (O_DIRECT) to bypass OS page cache. Ideal for EBS warming from S3 where you don't want data cached in memory.")]
     direct_io: bool,
 
-    #[clap(long, help = "Use io_uring for high-performance async I/O (requires Linux 5.1+ and container support). Can achieve much higher queue depths than regular async I/O.")]
+    #[clap(long, help = "Deprecated: use --strategy uring.")]
     io_uring: bool,
 
-    #[clap(long, help = "Use Linux AIO (libaio) for high-performance async I/O. More widely supported than io_uring but slightly lower performance.")]
+    #[clap(long, help = "Deprecated: use --strategy libaio.")]
     libaio: bool,
+
+    #[clap(long, value_name = "GLOB", help = "Only warm files matching this glob (e.g. '*.parquet'). Can be repeated.")]
+    include: Vec<String>,
+
+    #[clap(long, value_name = "GLOB", help = "Skip files and directories matching this glob (e.g. 'tmp/'). Can be repeated.")]
+    exclude: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Only warm files whose full path matches this regex. Can be repeated.")]
+    include_regex: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Skip files and directories whose full path matches this regex. Can be repeated.")]
+    exclude_regex: Vec<String>,
+
+    #[clap(long, value_name = "TIME|LOG", help = "Only warm files modified (or moved in, by ctime) after TIME, given as seconds since the epoch or e.g. 2026-10-17T02:00:00Z, or after the run that wrote the --result-log LOG started. With a LOG, files it shows as failed or at another size are warmed again too.")]
+    changed_since: Option<ChangedSince>,
+
+    #[clap(long, default_value = "0", value_name = "BYTES", help = "Skip files smaller than this size in bytes (0 means no limit).")]
+    min_file_size: u64,
+
+    #[clap(long, value_name = "AGE|TIME", value_parser = filters::parse_time_bound, help = "Only warm files modified within AGE (e.g. 7d, 12h or 1h30m) or after TIME (e.g. 2026-10-17 or 2026-10-17T02:00:00Z, or seconds since the epoch).")]
+    newer_than: Option<SystemTime>,
+
+    #[clap(long, value_name = "AGE|TIME", value_parser = filters::parse_time_bound, help = "Only warm files last modified more than AGE ago or before TIME; takes the same values as --newer-than.")]
+    older_than: Option<SystemTime>,
+
+    #[clap(long, value_name = "TYPE", value_delimiter = ',', help = "Only warm files of these types: f (regular files), l (files reached through a symlink, with --follow-symlinks), s (sparse files, with holes). Can be repeated or comma-separated.")]
+    file_type: Vec<FileKind>,
+
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["precompute_total", "metadata_first", "metadata_only"], help = "Warm the files listed in FILE ('-' for stdin), one path per line, relative to the target directories or absolute, instead of walking them. Filters still apply; listed files that don't exist are counted and skipped.")]
+    files_from: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["files_from", "precompute_total", "metadata_first", "metadata_only"], help = "Warm the objects of an S3 Inventory report at the same paths under the target directories, instead of walking them. FILE is a CSV data file (optionally .gz) or the report's manifest.json. Can be repeated.")]
+    s3_inventory: Vec<PathBuf>,
+
+    #[clap(long, value_name = "PID", value_delimiter = ',', conflicts_with_all = ["files_from", "s3_inventory", "ebs_snapshot_id", "precompute_total", "metadata_first", "metadata_only"], help = "Warm only the working set of running processes (e.g. a freshly restored database), instead of walking the target directories: the files under them the processes map, in the ranges mapped (from /proc/PID/maps), and the files they hold open, whole. Add --skip-cached to leave out pages already cached. Can be repeated or comma-separated.")]
+    process: Vec<u32>,
+
+    #[clap(long, value_name = "PREFIX", default_value = "", requires = "s3_inventory", help = "Key prefix that maps to the target directories in --s3-inventory; objects outside it are skipped.")]
+    s3_inventory_prefix: String,
+
+    #[clap(long, value_name = "DIMS", value_delimiter = ',', help = "Break down the final summary by 'ext' (file extension) and/or 'dir' (top-level directory), e.g. --stats-by ext,dir.")]
+    stats_by: Vec<StatsDimension>,
+
+    #[clap(long, help = "Print CloudWatch Embedded Metric Format (EMF) JSON lines with bytes/files/error counts to stdout.")]
+    emit_emf: bool,
+
+    #[clap(long, default_value = "RustCacheWarmer", value_name = "NAMESPACE", help = "CloudWatch metric namespace used for --emit-emf.")]
+    emf_namespace: String,
+
+    #[clap(long, default_value = "60", value_name = "SECONDS", help = "How often to flush aggregated EMF metrics.")]
+    emf_interval: u64,
+
+    #[clap(long, value_name = "DIR", help = "Write warming metrics to DIR/rust_cache_warmer.prom for node_exporter's textfile collector (e.g. /var/lib/node_exporter/textfile_collector), updated periodically and when the run ends.")]
+    textfile_dir: Option<PathBuf>,
+
+    #[clap(long, default_value = "15", value_name = "SECONDS", help = "How often to rewrite the --textfile-dir metrics file.")]
+    textfile_interval: u64,
+
+    #[clap(long, value_name = "FILE", help = "Write one record per processed file (path, size, status, strategy, duration, bytes actually read, error) to FILE as warming proceeds. NDJSON, or CSV if FILE ends in .csv; compressed if it also ends in .gz or .zst. FILE is overwritten.")]
+    result_log: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE", help = "Rewrite FILE every second with one line, \"<percent> <bytes done> <bytes total> <state>\" (state is running, done or interrupted), for dialog --gauge, whiptail and other scripts. The total is an estimate unless --precompute-total is set.")]
+    progress_file: Option<PathBuf>,
+
+    #[clap(long, value_name = "MODE", help = "How to show progress: 'bar' for progress bars, 'plain' for one line with files, bytes, rate and ETA every --progress-interval (for CloudWatch, CI and other logs), 'none' for just the summary. Defaults to bar when stdout and stderr are terminals, plain otherwise.")]
+    progress: Option<ProgressMode>,
+
+    #[clap(long, default_value = "10", value_name = "SECONDS", help = "How often --progress plain prints a line.")]
+    progress_interval: u64,
+
+    #[clap(long, conflicts_with = "progress", help = "Show a live dashboard instead of progress bars: throughput by warming method, a latency histogram, errors, the slowest files and the devices' queues. Press q to stop the run. Needs the 'tui' feature and a terminal; log lines are silenced while it's up, though failed files still appear in its errors panel and in the summary.")]
+    tui: bool,
+
+    #[clap(long, value_name = "ADDR", help = "Serve a status and control API on ADDR (e.g. 127.0.0.1:9876): GET /status for progress as JSON, POST /pause and /resume to stop and restart starting new files, POST /throttle?mbps=N to cap the read rate (0 lifts the cap). There is no authentication; prefer a loopback address.")]
+    status_listen: Option<SocketAddr>,
+
+    #[clap(long, help = "Integrate with systemd when run as a unit: report READY=1 and live progress in STATUS= (use Type=notify), ping the watchdog if WatchdogSec= is set, and log to the journal with FILES_WARMED, BYTES_WARMED and PERCENT fields.")]
+    systemd: bool,
+
+    #[clap(long, value_name = "FILE", help = "When the run ends, write a JSON report to FILE: totals, warning counts per category, and the first --error-samples errors of each category with path, errno, stage, strategy and time.")]
+    json_report: Option<PathBuf>,
+
+    #[clap(long, value_name = "DIR", help = "Where run history is kept for the history subcommand. Defaults to $XDG_STATE_HOME/rust-cache-warmer, ~/.local/state/rust-cache-warmer, or /var/lib/rust-cache-warmer without a home directory.")]
+    state_dir: Option<PathBuf>,
+
+    #[clap(long, help = "Don't record this run's summary in the run history.")]
+    no_history: bool,
+
+    #[clap(long, default_value = "5", value_name = "N", help = "Errors kept per category for --json-report.")]
+    error_samples: usize,
+
+    #[clap(long, value_name = "MODE", help = "Anonymize file paths in log lines, the result log, the JSON report and the summary, so they can be shipped off the host: 'hash' replaces every path component with a salted hash, 'strip-prefix' replaces the target directory with its position (<0>, <1>, ...). Checkpoints keep real paths.")]
+    anonymize_paths: Option<AnonymizeMode>,
+
+    #[clap(long, default_value = DEFAULT_MAP_FILE, value_name = "FILE", help = "With --anonymize-paths, append each anonymized path and its original to FILE (created readable by the owner only) for de-anonymizing reports. Its salt is reused, so hashes stay the same between runs that share FILE.")]
+    anonymize_map: PathBuf,
+
+    #[clap(long, value_name = "URL", help = "Export tracing spans for the run, discovery, batches, files and strategy attempts over OTLP/gRPC to URL (e.g. http://localhost:4317), continuing the trace in TRACEPARENT if set. Log lines become events on the spans. Requires the 'otel' feature.")]
+    otlp_endpoint: Option<String>,
+
+    #[clap(long, default_value = "auto", value_name = "STRATEGY", help = "Warming strategy: auto, uring, libaio, fadvise, readahead, mmap, sendfile or tokio. 'auto' walks the default fallback chain for the I/O mode (readahead, mmap, sendfile, fadvise, tokio; or uring, libaio, tokio with --direct-io).")]
+    strategy: Strategy,
+
+    #[clap(long, default_value = "auto", value_name = "POLICY", help = "What to try when the selected strategy can't warm a file: 'auto' (default chain), 'none' (fail instead), or a comma-separated list of strategies, e.g. 'libaio,tokio'.")]
+    strategy_fallback: FallbackPolicy,
+
+    #[clap(long, help = "With buffered reads by the tokio or uring strategy, try each read with preadv2 RWF_NOWAIT first, so data already in the page cache is taken from there at once and only the rest waits on the device. Makes re-warming a mostly warm tree much cheaper.")]
+    nowait_precheck: bool,
+
+    #[clap(long, help = "Deprecated: use --nowait-precheck.")]
+    uring_nowait: bool,
+
+    #[clap(long, default_value = "4096", value_name = "BYTES", help = "With --strategy auto or uring, read files smaller than this (at most 4096) with one linked io_uring open/read/close chain each, batching many files per submission. 0 disables.")]
+    small_file_size: u64,
+
+    #[clap(long, default_value = "1", value_name = "PAGES", help = "The mmap strategy touches every this-many pages after MADV_WILLNEED, so they are known to have been fetched rather than only advised. 1 touches every page; larger strides sample the file; 0 only gives the advice.")]
+    mmap_touch_stride: u64,
+
+    #[clap(long, value_name = "STRATEGIES", value_delimiter = ',', conflicts_with = "strategy", help = "A/B test: randomly assign each file to one of these strategies (e.g. uring,fadvise), warm it with that strategy alone, and report per-strategy latency and throughput with 95% confidence intervals.")]
+    ab_test: Vec<Strategy>,
+
+    #[clap(long, value_name = "WEIGHTS", requires = "ab_test", help = "Share of files per --ab-test strategy, e.g. 50/50 or 70/30. Defaults to an even split.")]
+    split: Option<Split>,
+
+    #[clap(long, help = "Check page-cache residency (cachestat/mincore) first and only read pages that aren't already cached. Cuts re-runs on partially warmed volumes; fully cached files are skipped.")]
+    skip_cached: bool,
+
+    #[clap(long, help = "Also read holes in sparse files. By default only allocated extents of files over 1MB are read (found via SEEK_DATA/SEEK_HOLE), since holes never touch the device.")]
+    read_holes: bool,
+
+    #[clap(long, conflicts_with_all = ["skip_cached", "ebs_snapshot_id", "process"], help = "Read files stored compressed on btrfs (compress=zstd and others) or ZFS by extent: one page of each compressed extent or ZFS record, which makes the filesystem fetch all of it, and uncompressed extents in full. Every block on the device is read once without decompressing whole files into the page cache. Other files, and other filesystems, are read as usual.")]
+    compressed_extents: bool,
+
+    #[clap(long, default_value = "file", value_name = "POLICY", help = "What to do with page-cache pages pulled in while warming: 'none' leaves them cached, 'file' drops each file's pages once it's warmed, 'global' drops the whole page cache at the end of the run (requires root).")]
+    drop_caches_after: DropCaches,
+
+    #[clap(long, requires = "hot_include", conflicts_with = "metadata_only", help = "Warm in two phases: first hydrate every file with direct I/O (or dropping its pages, where direct I/O isn't possible) so the page cache is left alone, then advise the files matching --hot-include into the page cache with fadvise WILLNEED.")]
+    two_phase: bool,
+
+    #[clap(long, value_name = "GLOB", requires = "two_phase", help = "Glob of files (matched like --include) for the page-cache phase of --two-phase to cache. Can be repeated. --exclude and the regex filters still apply.")]
+    hot_include: Vec<String>,
+
+    #[clap(long, default_value = "pagecache", value_name = "POLICY", help = "What to do with directories on NVMe instance store or other non-EBS local disks, which have no first-read penalty: 'skip' leaves them out, 'pagecache' reads them into the page cache without direct I/O and keeps them cached, 'force' warms them like EBS.")]
+    non_ebs: NonEbsPolicy,
+
+    #[clap(long, help = "Warm directories on network filesystems (EFS, NFS, SMB) like local disks. By default they get buffered 1 MiB reads through the Tokio backend, skipping fadvise hints and direct I/O, which don't reach the server.")]
+    no_network_mode: bool,
+
+    #[clap(long, default_value = "mount", value_name = "GROUPING", help = "How queue depth is shared between target directories: 'mount' gives each volume a fair share, 'dir' each target directory, 'none' walks the directories one after another. A busy volume can't starve the others, and one with nothing ready doesn't hold them back.")]
+    fair_share: ShareBy,
+
+    #[clap(long, value_name = "DIR=WEIGHT", help = "Give target directory DIR (and so its volume) WEIGHT times the share of queue depth of an unweighted one, e.g. /data=3. Can be repeated.")]
+    share_weight: Vec<ShareWeight>,
+
+    #[clap(long, default_value = "0", value_name = "N", help = "Most batches a single volume (or directory, per --fair-share) may have in flight, to protect volumes with low IOPS limits. 0 applies only --queue-depth.")]
+    share_queue_depth: usize,
+
+    #[clap(long, value_name = "N|auto", help = "Give every device (st_dev) its own queue of N files in flight, within --queue-depth, so a slow volume's reads don't take slots a fast one could use. 'auto' sizes each device's queue from its profile, as auto-tuning would size --queue-depth for it alone.")]
+    per_device_queue_depth: Option<PerDeviceDepth>,
+
+    #[clap(long, default_value = "1", value_name = "N", help = "Split the files between N warmer processes (e.g. one per NUMA node, or one per host on a multi-attach volume) by a hash of their path relative to the target directory. Each process warms the disjoint subset given by --shard-index.")]
+    shards: u32,
+
+    #[clap(long, default_value = "0", value_name = "I", help = "Which of the --shards subsets this process warms, from 0 to N-1.")]
+    shard_index: u32,
+
+    #[clap(long, help = "Warm files in the order their data lies on disk (found with FIEMAP, or FIBMAP where FIEMAP isn't supported) instead of directory order, sorting up to 65536 discovered files at a time. Turns random reads into mostly sequential ones on st1/sc1 and other throughput-bound volumes.")]
+    physical_order: bool,
+
+    #[clap(long, conflicts_with_all = ["max_duration", "file_timeout", "pace", "prioritize_open_files"], help = "Make two runs over identical volumes do identical I/O, for benchmarking volume configurations: discovery walks each directory on one thread in sorted order, --verify and --ab-test sample with --seed (0 if not given), and auto-tuning is off so settings don't follow the device. Options driven by the clock or by other processes are refused.")]
+    deterministic: bool,
+
+    #[clap(long, value_name = "SEED", help = "Seed for --verify's sampled offsets and --ab-test's split, to repeat a run's sampling.")]
+    seed: Option<u64>,
+
+    #[clap(long, help = "Warm the files other processes already have open (read from /proc/*/fd at startup), and the directories holding them, before walking the targets; most widely held first. Reading other users' descriptors needs root.")]
+    prioritize_open_files: bool,
+
+    #[clap(long, default_value = "none", value_name = "MODE", help = "Pace reads: 'none' reads as fast as the queue depth allows; 'ebs-burst' reads at the volume's burst rate while --burst-budget of its burst credits last, then at its baseline, for gp2, st1 and sc1 volumes (the volume type comes from auto-tuning and needs the 'aws' feature); 'efs-burst' does the same per mount for EFS file systems in Bursting Throughput mode, sized by the data stored on them.")]
+    pace: PaceMode,
+
+    #[clap(long, default_value = "50", value_name = "PERCENT", help = "Share of each volume's burst credit bucket --pace ebs-burst or efs-burst may spend, assuming the bucket starts full; the rest is left for the workload.")]
+    burst_budget: f64,
+
+    #[clap(long, allow_hyphen_values = true, value_name = "N", help = "Run at this CPU nice value, from -20 to 19 (e.g. 10 or 19 to yield to the workload). Going below the current value needs CAP_SYS_NICE.")]
+    nice: Option<i32>,
+
+    #[clap(long, value_name = "CLASS", help = "Run in this I/O scheduling class, as with ionice: 'idle' only reads when no other process wants the disk, 'best-effort' takes a level from --ionice-level, 'realtime' needs CAP_SYS_ADMIN. Only honoured by I/O schedulers that support priorities (bfq, mq-deadline); 'none', the default for NVMe and so for EBS on Nitro, ignores it.")]
+    ionice_class: Option<IoClass>,
+
+    #[clap(long, default_value = "4", value_name = "LEVEL", requires = "ionice_class", help = "Level within the best-effort or realtime --ionice-class, from 0 (highest) to 7 (lowest).")]
+    ionice_level: u8,
+
+    #[clap(long, value_name = "BYTES", help = "Have the kernel cap reads from the target disks at this many bytes per second, by writing rbps to io.max of the warmer's own cgroup (cgroup v2 with the io controller enabled, e.g. under systemd-run --scope -p IOAccounting=yes). The previous limits are put back at exit.")]
+    cgroup_read_bps: Option<u64>,
+
+    #[clap(long, value_name = "N", help = "Like --cgroup-read-bps, for read operations per second (riops in io.max).")]
+    cgroup_read_iops: Option<u64>,
+
+    #[clap(long, help = "Warm every path, even hard links to a file already discovered and directories reached twice (e.g. through a bind mount). By default each inode is warmed once.")]
+    no_dedupe_inodes: bool,
+
+    #[clap(long, default_value = "1", value_name = "N", help = "Split files of at least 128MB into up to N ranges (of 64MB or more) read concurrently, so a single huge file can use the whole queue depth. Uses io_uring under --direct-io when it's the selected strategy, positional reads otherwise. 1 reads every file sequentially.")]
+    intra_file_parallelism: usize,
+
+    #[clap(long, help = "Run within a small memory budget (e.g. initramfs or 512MiB sidecars): caps concurrency, batch size and I/O buffers, uses few runtime threads, and applies backpressure to discovery instead of queueing every discovered path.")]
+    low_memory: bool,
+
+    #[clap(long, default_value_t = stall::DEFAULT_THRESHOLD.as_millis() as u64, value_name = "MS", help = "Warn when every async runtime worker stays busy for longer than this, which freezes progress bars, the control socket and metrics. 0 turns the check off.")]
+    stall_threshold_ms: u64,
+
+    #[clap(long, help = "Warm targets on overlayfs (e.g. a container's merged root) or bind mounts through the mount as given, instead of through the upper/lower layer and source directories behind it.")]
+    no_resolve_layers: bool,
+
+    #[clap(long, default_value = "resolve", value_name = "MODE", help = "What to do with a target directory whose path goes through a symlink (e.g. /data -> /mnt/vol1): 'resolve' warms the real tree and records files under its canonical path, so checkpoints resume whichever spelling is given; 'keep' walks through the link as given.")]
+    symlinked_roots: SymlinkRoots,
+
+    #[clap(long, help = "Don't tune queue depth, read size and strategy to the devices behind the target directories. Settings given explicitly are never tuned.")]
+    no_auto_tune: bool,
+
+    #[clap(long, value_name = "BYTES", help = "Read size for full reads, a multiple of 4096. Defaults to the strategy's own size, or to what suits the device when auto-tuning.")]
+    chunk_size: Option<usize>,
+
+    #[clap(long, value_name = "BYTES", help = "Bytes between samples for --sparse-large-files, a multiple of 4096. Larger intervals finish sooner and warm less; st1/sc1 volumes, which count I/O in 1MiB units, gain little from intervals under 1MiB.")]
+    sparse_interval: Option<u64>,
+
+    #[clap(long, value_name = "STRATEGY=BYTES", help = "--chunk-size for one strategy, e.g. uring=262144 or tokio=4194304; repeatable. Wins over --chunk-size and auto-tuning for that strategy.")]
+    strategy_chunk_size: Vec<StrategySize>,
+
+    #[clap(long, value_name = "STRATEGY=BYTES", help = "--sparse-interval for one strategy, e.g. readahead=1048576; repeatable.")]
+    strategy_sparse_interval: Vec<StrategySize>,
+
+    #[clap(long, value_name = "CLASS=STRATEGY,...", conflicts_with = "ab_test", help = "Pick the strategy by file size, e.g. 'tiny=fadvise,medium=uring,huge=uring_sparse'. Classes: tiny (up to 4KiB), small (64KiB), medium (1MiB), large (100MiB) and huge; unnamed classes keep --strategy. A _sparse suffix samples every file of the class as --sparse-large-files would. Strategies that read through the page cache (fadvise, readahead, mmap, sendfile) run without --direct-io.")]
+    strategy_map: Option<StrategyMap>,
+
+    #[clap(long, default_value_t = buffers::DEFAULT_MAX_BUFFER_MEMORY, value_name = "BYTES", help = "Most memory held by aligned read buffers for --direct-io reads (Tokio, libaio, io_uring and range-parallel reads share them). Buffers are reused between files; at the cap, reads wait for a buffer to be returned. 0 for no cap. --low-memory lowers the default to 16MiB.")]
+    max_buffer_memory: usize,
+
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), help = "Most files held open at once. Files past it wait for a descriptor to be returned instead of failing with 'Too many open files'. At startup the soft `ulimit -n` is raised as far as the hard limit allows to fit it; defaults to what the limit leaves after a reserve for logs, sockets and directories.")]
+    max_open_files: Option<u64>,
+
+    #[clap(long, value_name = "FILE", help = "Record every finished file in FILE. Rerunning with the same FILE skips those files and includes their results in the summary; delete it to start over. Compressed if FILE ends in .gz or .zst.")]
+    checkpoint: Option<PathBuf>,
+
+    #[clap(long, default_value = "0", value_name = "BYTES", help = "Continue the --checkpoint and --result-log files in a new segment (FILE.1, FILE.2, ...; before a .gz/.zst extension) once the current one holds this many bytes of records (counted before compression). 0 never rotates.")]
+    log_rotate_size: u64,
+
+    #[clap(long, help = "Run the warmer in a child process and restart it from a checkpoint if it crashes or is OOM-killed. The final summary covers all attempts.")]
+    supervised: bool,
+
+    #[clap(long, default_value = "3", value_name = "N", help = "With --supervised, how many times to restart a crashed warmer before giving up.")]
+    max_restarts: u32,
+
+    #[clap(long, default_value = "0", value_name = "PERCENT", help = "Exit with status 2 if more than this percentage of the attempted files failed to warm. The default fails the run on any failure.")]
+    max_error_percent: f64,
+
+    #[clap(long, help = "Stop at the first file that fails to warm (files already being read still finish) and exit with status 2.")]
+    fail_fast: bool,
+
+    #[clap(long, value_name = "DURATION", value_parser = deadline::parse_duration, help = "Stop starting new files this long after warming starts, e.g. 90s, 30m, 2h or 1h30m: discovery stops, files already being read finish, and the summary reports how much was covered. Use with --precompute-total to get the covered share in bytes.")]
+    max_duration: Option<Duration>,
+
+    #[clap(long, value_name = "DURATION", value_parser = deadline::parse_duration, help = "Give up on a file that isn't warmed within this long, e.g. 30s or 2m, per started GiB of the file: it's counted as failed with cause 'timeout', listed at the end, and the run moves on. Guards against reads that hang on a degraded volume.")]
+    file_timeout: Option<Duration>,
+
+    #[clap(long, default_value = "60s", value_name = "DURATION", value_parser = deadline::parse_duration, help = "Warn, listing the longest-running reads, when no file has finished for this long while files are being read. 0 turns the check off.")]
+    hung_io_warning: Duration,
+
+    #[clap(long, default_value = "200", value_name = "MS", help = "When a file is gone by the time it's warmed (ENOENT), wait this long and look again: a file back at the same path (atomically replaced or recreated) is warmed, as is one renamed within its directory, e.g. by log rotation. 0 fails such files right away.")]
+    vanished_grace_ms: u64,
+
+    #[clap(long, help = "Before warming, open and read a byte of a few files in every directory under the current security context (SELinux, AppArmor) and report unreadable subtrees, so denials show up in seconds rather than hours into the run.")]
+    preflight: bool,
+
+    #[clap(long, help = "Walk the directories once before warming to add up file sizes, so the progress bar shows exact percent complete and ETA by bytes instead of an estimate.")]
+    precompute_total: bool,
+
+    #[clap(long, help = "Only run the --preflight access check, then exit with status 2 if any sampled file or directory was unreadable.")]
+    preflight_only: bool,
+
+    #[clap(long, default_value = "5000", value_name = "N", help = "Most files the --preflight check tries (it also stops after 10 seconds).")]
+    preflight_files: usize,
+
+    #[clap(long, help = "Before warming file data, walk the targets with --queue-depth threads, listing every directory and stat'ing every entry, so directory and inode blocks are hydrated first.")]
+    metadata_first: bool,
+
+    #[clap(long, conflicts_with = "metadata_first", help = "Only run the metadata pass of --metadata-first, then exit without reading file data.")]
+    metadata_only: bool,
+
+    #[clap(long, help = "Require every discovered file to be fully read: files that fail, are skipped, are only sampled (--sparse-large-files) or only get advisory hints (fadvise), and unreadable directories, are listed in a coverage report and make the run exit with status 3.")]
+    strict_coverage: bool,
+
+    #[clap(long, help = "After warming, read one block at random offsets across the target directories (bypassing the page cache) and report latency percentiles, flagging samples slow enough to still be cold on EBS.")]
+    verify: bool,
+
+    #[clap(long, conflicts_with = "verify", help = "Deprecated: use `rust-cache-warmer verify`.")]
+    verify_only: bool,
+
+    #[clap(long, conflicts_with = "verify_only", help = "After warming, verify as with --verify, then re-warm the region around every cold sample with plain reads and check it reads warm. Catches strategies that report success without hydrating; fails if a region stays cold.")]
+    verify_rewarm: bool,
+
+    #[clap(long, default_value = "1000", value_name = "N", help = "Number of random offsets sampled by --verify.")]
+    verify_samples: usize,
+
+    #[clap(long, default_value = "20", value_name = "MS", help = "With --verify, samples slower than this many milliseconds are flagged as probably cold.")]
+    verify_cold_ms: u64,
+
+    #[clap(long, help = "Keep running after the initial pass and warm files that are created or modified under the target directories.")]
+    watch: bool,
+
+    #[clap(long, default_value = "500", value_name = "MS", help = "In watch mode, wait until a changed file has been quiet for this many milliseconds before warming it.")]
+    watch_debounce_ms: u64,
+
+    #[clap(long, default_value = "1000000", value_name = "N", help = "In watch mode, remember the path, mtime and size of up to N warmed files (in a bloom filter of about 1.8 bytes per file, kept in two generations) and ignore events for files that haven't changed since they were warmed. 0 re-warms a file on every event.")]
+    watch_dedupe_capacity: usize,
+
+    #[clap(long, value_name = "URL", help = "Long-poll this SQS queue for paths/ranges to warm (requires the 'aws' feature). Messages are deleted only after successful warming.")]
+    sqs_queue_url: Option<String>,
+
+    #[clap(long, default_value = "10", value_name = "N", help = "Maximum number of SQS messages to receive per poll (1-10).")]
+    sqs_batch_size: i32,
+
+    #[clap(long, default_value = "300", value_name = "SECONDS", help = "SQS visibility timeout for received messages; extended while warming is still in progress.")]
+    sqs_visibility_timeout: u64,
+
+    #[clap(long, value_name = "SNAPSHOT_ID", help = "Only warm file blocks that hold data in this EBS snapshot (the one the volume was restored from), using the EBS direct APIs and FIEMAP. Requires the 'aws' feature.")]
+    ebs_snapshot_id: Option<String>,
+
+    #[clap(long, value_name = "SNAPSHOT_ID", requires = "ebs_snapshot_id", help = "With --ebs-snapshot-id, only warm blocks that changed since this earlier snapshot.")]
+    ebs_base_snapshot_id: Option<String>,
+
+    /// Options set on the command line or in the config file rather than left at their defaults
+    #[clap(skip)]
+    explicit: Vec<String>,
+}
+
+impl Opts {
+    fn is_explicit(&self, id: &str) -> bool {
+        self.explicit.iter().any(|explicit| explicit == id)
+    }
+}
+
+/// `rust-cache-warmer verify`: sample the target directories without warming them
+#[derive(Args, Debug)]
+struct VerifyOpts {
+    #[clap(value_name = "DIRECTORIES", required = true, num_args = 1.., help = "Directories to sample.")]
+    directories: Vec<PathBuf>,
+
+    #[clap(long, default_value = "1000", value_name = "N", help = "Number of random offsets sampled.")]
+    samples: usize,
+
+    #[clap(long, default_value = "20", value_name = "MS", help = "Samples slower than this many milliseconds are flagged as probably cold.")]
+    cold_ms: u64,
+
+    #[clap(short, long, default_value_t = 32, help = "Samples read at once.")]
+    queue_depth: usize,
+
+    #[clap(long, value_name = "GLOB", help = "Only sample files matching this glob (e.g. '*.parquet'). Can be repeated.")]
+    include: Vec<String>,
+
+    #[clap(long, value_name = "GLOB", help = "Skip files and directories matching this glob (e.g. 'tmp/'). Can be repeated.")]
+    exclude: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Only sample files whose full path matches this regex. Can be repeated.")]
+    include_regex: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Skip files and directories whose full path matches this regex. Can be repeated.")]
+    exclude_regex: Vec<String>,
+
+    #[clap(long, help = "Follow symbolic links.")]
+    follow_symlinks: bool,
+
+    #[clap(long, help = "Ignore hidden files and directories (those starting with '.').")]
+    ignore_hidden: bool,
+
+    #[clap(long, value_name = "SEED", help = "Seed for the sampled offsets, to repeat a check on the same blocks.")]
+    seed: Option<u64>,
+}
+
+/// Walk settings for sampling `directories` without warming, as `verify` and `estimate` do.
+/// With a seed the walk is sorted too, so the seed picks the same blocks on another volume.
+fn sampling_options(directories: Vec<PathBuf>, filters: DiscoveryFilters, queue_depth: usize, follow_symlinks: bool, ignore_hidden: bool, seeded: bool) -> Arc<PipelineOptions> {
+    Arc::new(PipelineOptions {
+        directories,
+        queue_depth,
+        threads: None,
+        follow_symlinks,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden,
+        max_file_size: 0,
+        batch_size: 1,
+        batch_bytes: 0,
+        filters,
+        warming: WarmingOptions::default(),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: seeded,
+    })
+}
+
+async fn verify(args: VerifyOpts) -> Result<()> {
+    let filters = DiscoveryFilters::new(&args.directories, &args.include, &args.exclude, &args.include_regex, &args.exclude_regex)?;
+    let pipeline_options = sampling_options(args.directories, filters, args.queue_depth, args.follow_symlinks, args.ignore_hidden, args.seed.is_some());
+    let mut options = VerifyOptions {
+        samples: args.samples,
+        cold_threshold: Duration::from_millis(args.cold_ms),
+        concurrency: args.queue_depth,
+        ..Default::default()
+    };
+    if let Some(seed) = args.seed {
+        options.seed = seed;
+    }
+    let shutdown = Shutdown::new();
+    shutdown::listen_for_signals(shutdown.clone())?;
+    run_verification(&pipeline_options, &options, &shutdown, false).await?;
+    if let Some(code) = shutdown.exit_code() {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// `rust-cache-warmer estimate`: estimate the cold fraction of the target directories
+#[derive(Args, Debug)]
+struct EstimateOpts {
+    #[clap(value_name = "DIRECTORIES", required = true, num_args = 1.., help = "Directories to sample.")]
+    directories: Vec<PathBuf>,
+
+    #[clap(long, default_value = "1000", value_name = "N", help = "Number of random offsets sampled; the interval narrows with the square root of N.")]
+    samples: usize,
+
+    #[clap(long, default_value = "20", value_name = "MS", help = "Samples slower than this many milliseconds count as cold.")]
+    cold_ms: u64,
+
+    #[clap(long, default_value = "95", value_name = "PERCENT", help = "Confidence level of the reported interval.")]
+    confidence: f64,
+
+    #[clap(long, default_value = "bytes", value_name = "UNIT", help = "Draw samples uniformly over 'bytes' (the cold share of the data) or 'files' (the cold share of a typical file, so small files count as much as large ones).")]
+    sample_by: SampleBy,
+
+    #[clap(long, value_name = "PERCENT", help = "Exit 3 unless the upper end of the interval is below PERCENT, i.e. the volume is known to be at most that cold. For scripts deciding whether to run a full warm.")]
+    warm_below: Option<f64>,
+
+    #[clap(short, long, default_value_t = 32, help = "Samples read at once.")]
+    queue_depth: usize,
+
+    #[clap(long, value_name = "GLOB", help = "Only sample files matching this glob (e.g. '*.parquet'). Can be repeated.")]
+    include: Vec<String>,
+
+    #[clap(long, value_name = "GLOB", help = "Skip files and directories matching this glob (e.g. 'tmp/'). Can be repeated.")]
+    exclude: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Only sample files whose full path matches this regex. Can be repeated.")]
+    include_regex: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Skip files and directories whose full path matches this regex. Can be repeated.")]
+    exclude_regex: Vec<String>,
+
+    #[clap(long, help = "Follow symbolic links.")]
+    follow_symlinks: bool,
+
+    #[clap(long, help = "Ignore hidden files and directories (those starting with '.').")]
+    ignore_hidden: bool,
+
+    #[clap(long, value_name = "SEED", help = "Seed for the sampled offsets, to repeat an estimate on the same blocks.")]
+    seed: Option<u64>,
+}
+
+async fn estimate(args: EstimateOpts) -> Result<()> {
+    if !(args.confidence > 0.0 && args.confidence < 100.0) {
+        anyhow::bail!("--confidence must be between 0 and 100, got {}", args.confidence);
+    }
+    if args.samples == 0 {
+        anyhow::bail!("--samples must be at least 1");
+    }
+    let filters = DiscoveryFilters::new(&args.directories, &args.include, &args.exclude, &args.include_regex, &args.exclude_regex)?;
+    let pipeline_options = sampling_options(args.directories, filters, args.queue_depth, args.follow_symlinks, args.ignore_hidden, args.seed.is_some());
+    let mut options = EstimateOptions {
+        samples: args.samples,
+        cold_threshold: Duration::from_millis(args.cold_ms),
+        concurrency: args.queue_depth,
+        confidence: args.confidence / 100.0,
+        sample_by: args.sample_by,
+        ..Default::default()
+    };
+    if let Some(seed) = args.seed {
+        options.seed = seed;
+    }
+    let shutdown = Shutdown::new();
+    shutdown::listen_for_signals(shutdown.clone())?;
+    info!("Estimating the cold fraction from {} random samples...", options.samples);
+    let estimate = tokio::select! {
+        estimate = estimate::estimate(pipeline_options, &options) => estimate?,
+        _ = shutdown.triggered() => {
+            warn!("Estimate interrupted");
+            if let Some(code) = shutdown.exit_code() {
+                std::process::exit(code);
+            }
+            return Ok(());
+        }
+    };
+    println!("{}", estimate);
+    if let Some(limit) = args.warm_below {
+        let high = estimate.interval.1 * 100.0;
+        if estimate.measured == 0 || high >= limit {
+            warn!("Can't rule out {:.1}% or more of the data being cold (limit {}%)", high, limit);
+            std::process::exit(exit::COVERAGE_INCOMPLETE);
+        }
+        info!("At most {:.1}% cold at {}% confidence, below the {}% limit", high, args.confidence, limit);
+    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Opts::parse();
+/// `rust-cache-warmer debug-bundle`: collect what a bug report needs into one archive
+#[derive(Args, Debug)]
+struct DebugBundleOpts {
+    #[clap(value_name = "OUTPUT", help = "Archive to write, e.g. debug.tar.gz.")]
+    output: PathBuf,
+
+    #[clap(value_name = "DIRECTORIES", help = "Target directories of the run being reported; their disk types are recorded.")]
+    directories: Vec<PathBuf>,
+
+    #[clap(long, value_name = "FILE", help = "Config file of the run; included and checked for errors.")]
+    config: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE", help = "Checkpoint journal of the run to include.")]
+    checkpoint: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE", help = "Result log of the run to include.")]
+    result_log: Option<PathBuf>,
+
+    #[clap(long, value_name = "DIR", help = "Include the textfile metrics the run wrote to DIR.")]
+    textfile_dir: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE", help = "Any other file to include, such as a saved log. Can be repeated.")]
+    file: Vec<PathBuf>,
+}
+
+fn debug_bundle(args: DebugBundleOpts) -> Result<()> {
+    // Every segment of a rotated log; a missing log is listed so the bundle notes it
+    let logs = [args.checkpoint, args.result_log].into_iter().flatten().flat_map(|path| {
+        let segments = logfile::segments(&path);
+        if segments.is_empty() { vec![path] } else { segments }
+    });
+    let files = logs
+        .chain(args.textfile_dir.map(|dir| dir.join(textfile::FILE_NAME)))
+        .chain(args.file)
+        .collect();
+    let options = BundleOptions { config: args.config, directories: args.directories, files };
+    let environment = bundle::create(&args.output, &options)
+        .with_context(|| format!("failed to write debug bundle {}", args.output.display()))?;
+    for note in &environment.notes {
+        warn!("Not included: {}", note);
+    }
+    info!("Wrote debug bundle {} ({} files included)", args.output.display(), environment.files.len());
+    Ok(())
+}
+
+/// `rust-cache-warmer bench`: compare the strategies on this host
+#[derive(Args, Debug)]
+struct BenchOpts {
+    #[clap(value_name = "DIRECTORIES", required = true, num_args = 1.., help = "Directories to sample files from; with --synthetic-size, the test file is written to the first.")]
+    directories: Vec<PathBuf>,
+
+    #[clap(long, default_value_t = bench::DEFAULT_FILES, value_name = "N", help = "Files each strategy warms, or times it reads the --synthetic-size file.")]
+    files: usize,
+
+    #[clap(long, value_name = "STRATEGIES", value_delimiter = ',', help = "Strategies to compare, e.g. uring,readahead. Defaults to every strategy available on this host.")]
+    strategy: Vec<Strategy>,
+
+    #[clap(short, long, default_value_t = 8, help = "Files warmed at once.")]
+    queue_depth: usize,
+
+    #[clap(long, help = "Benchmark with direct I/O (O_DIRECT), as --direct-io would warm.")]
+    direct_io: bool,
+
+    #[clap(long, value_name = "BYTES", help = "Read size for full reads, a multiple of 4096. Defaults to each strategy's own size.")]
+    chunk_size: Option<usize>,
+
+    #[clap(long, default_value = "0", value_name = "BYTES", help = "Instead of sampling files, write a synthetic file of this size and have every strategy read it, one read at a time with its pages dropped in between. Written blocks aren't cold on EBS, so this compares read paths rather than hydration. 0 samples files.")]
+    synthetic_size: u64,
+
+    #[clap(long, value_name = "SEED", help = "Seed for the file sample, to repeat a run on the same files.")]
+    seed: Option<u64>,
+}
+
+/// `rust-cache-warmer history`: trends and regressions across runs
+#[derive(Args, Debug)]
+struct HistoryOpts {
+    #[clap(value_name = "DIRECTORIES", help = "Only show runs of exactly this set of target directories. Shows every set by default.")]
+    directories: Vec<PathBuf>,
+
+    #[clap(long, value_name = "DIR", help = "State directory the runs were recorded in (see warm --state-dir).")]
+    state_dir: Option<PathBuf>,
+
+    #[clap(long, default_value_t = 10, value_name = "N", help = "Latest runs to list per set of targets; 0 lists all. All recorded runs count towards the baseline.")]
+    runs: usize,
+
+    #[clap(long, default_value_t = history::DEFAULT_REGRESSION_PERCENT, value_name = "PERCENT", help = "Flag the latest run when its throughput is this much below, or its duration this much above, the median of up to five runs before it.")]
+    regression_threshold: f64,
+
+    #[clap(long, help = "Exit with status 1 if any latest run is flagged as a regression.")]
+    fail_on_regression: bool,
+}
+
+fn show_history(args: HistoryOpts) -> Result<()> {
+    let state_dir = args.state_dir.unwrap_or_else(history::default_state_dir);
+    let records = history::load(&state_dir).with_context(|| format!("failed to read run history in {}", state_dir.display()))?;
+    let mut histories = history::by_targets(records);
+    if !args.directories.is_empty() {
+        let key = history::targets_key(&args.directories);
+        histories.retain(|history| history.targets_key == key);
+    }
+    if histories.is_empty() {
+        info!("No runs recorded in {}{}", state_dir.display(), if args.directories.is_empty() { "" } else { " for these targets" });
+        return Ok(());
+    }
+    let mut regressed = 0;
+    for mut history in histories {
+        history.show = args.runs;
+        println!("{}", history);
+        let regressions = history.regressions(args.regression_threshold);
+        for regression in &regressions {
+            warn!("Regression in the latest run of {}: {}", history.targets.join(", "), regression);
+        }
+        regressed += usize::from(!regressions.is_empty());
+    }
+    if regressed > 0 && args.fail_on_regression {
+        anyhow::bail!("the latest run of {} target sets regressed", regressed);
+    }
+    Ok(())
+}
+
+/// `rust-cache-warmer export-plan`: split the target directories into byte-balanced shards
+#[derive(Args, Debug)]
+struct ExportPlanOpts {
+    #[clap(value_name = "DIRECTORIES", required = true, num_args = 1.., help = "Directories to plan.")]
+    directories: Vec<PathBuf>,
+
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), help = "Number of shards, one per instance.")]
+    shards: u64,
+
+    #[clap(short, long, value_name = "DIR", help = "Directory to write the shard manifests and plan.json to; created if needed.")]
+    output: PathBuf,
+
+    #[clap(long, default_value = "layout", value_name = "MODE", help = "Split the files into contiguous runs of their on-disk order ('layout'), so each instance reads its own stretch of the volume, or deal them largest first onto the lightest shard ('bytes') for the most even byte counts.")]
+    balance: Balance,
+
+    #[clap(long, value_name = "GLOB", help = "Only plan files matching this glob (e.g. '*.parquet'). Can be repeated.")]
+    include: Vec<String>,
+
+    #[clap(long, value_name = "GLOB", help = "Skip files and directories matching this glob (e.g. 'tmp/'). Can be repeated.")]
+    exclude: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Only plan files whose full path matches this regex. Can be repeated.")]
+    include_regex: Vec<String>,
+
+    #[clap(long, value_name = "REGEX", help = "Skip files and directories whose full path matches this regex. Can be repeated.")]
+    exclude_regex: Vec<String>,
+
+    #[clap(long, help = "Follow symbolic links.")]
+    follow_symlinks: bool,
+
+    #[clap(long, help = "Ignore hidden files and directories (those starting with '.').")]
+    ignore_hidden: bool,
+}
+
+fn export_plan(args: ExportPlanOpts) -> Result<()> {
+    let filters = DiscoveryFilters::new(&args.directories, &args.include, &args.exclude, &args.include_regex, &args.exclude_regex)?;
+    let directories = args.directories.clone();
+    let pipeline_options = sampling_options(args.directories, filters, 1, args.follow_symlinks, args.ignore_hidden, true);
+    let started = Instant::now();
+    let files = plan::collect(&pipeline_options, args.balance);
+    if args.balance == Balance::Layout && !files.is_empty() && files.iter().all(|file| file.physical.is_none()) {
+        warn!("No file's on-disk location could be read (FIEMAP/FIBMAP); shards follow path order");
+    }
+    let planned = plan::plan(files, args.shards as usize, args.balance);
+    let manifests = plan::write(&args.output, &planned, &directories, args.balance)?;
+    let (files, bytes) = planned.iter().fold((0, 0), |(files, bytes), shard| (files + shard.files.len(), bytes + shard.bytes));
+    println!("   📋 Planned {} files ({}) into {} shards balanced by {} in {:.1?}", files, HumanBytes(bytes), planned.len(), args.balance, started.elapsed());
+    for (manifest, shard) in manifests.iter().zip(&planned) {
+        println!("      {}: {} files, {}", manifest.display(), shard.files.len(), HumanBytes(shard.bytes));
+    }
+    let target = if directories.len() == 1 { directories[0].display().to_string() } else { "<directories>".to_string() };
+    println!("   Warm shard I on instance I with: rust-cache-warmer --files-from {} {}", args.output.join(format!("shard-I-of-{}.txt", planned.len())).display(), target);
+    Ok(())
+}
+
+/// `rust-cache-warmer inodes`: warm given inodes' blocks without discovery
+#[derive(Args, Debug)]
+struct InodesOpts {
+    #[clap(value_name = "DEVICE", help = "Block device holding the filesystem, e.g. /dev/nvme1n1.")]
+    device: PathBuf,
+
+    #[clap(value_name = "INODES", help = "Inode numbers to warm.")]
+    inodes: Vec<u64>,
+
+    #[clap(long, value_name = "FILE", help = "Read inode numbers from FILE ('-' for stdin): separated by whitespace, commas or newlines, '#' starts a comment. Can be combined with INODES.")]
+    inode_file: Option<PathBuf>,
+
+    #[clap(long, default_value = "auto", value_name = "TOOL", help = "How inodes are mapped to blocks: auto (by filesystem), debugfs or xfs_db.")]
+    resolver: Resolver,
+
+    #[clap(short, long, default_value_t = 32, help = "Device reads in flight.")]
+    queue_depth: usize,
+
+    #[clap(long, default_value_t = inodes::DEFAULT_READ_SIZE, value_name = "BYTES", help = "Largest single read; contiguous blocks are read in pieces of this size.")]
+    read_size: u64,
+
+    #[clap(long, help = "Read through the page cache instead of with O_DIRECT. Pages cached for the block device aren't the files' pages, so this only costs memory.")]
+    buffered: bool,
+
+    #[clap(long, help = "Print the device ranges that would be read, then exit.")]
+    dry_run: bool,
+}
+
+async fn warm_inodes(args: InodesOpts) -> Result<()> {
+    let mut numbers = args.inodes;
+    if let Some(path) = &args.inode_file {
+        let text = if path.as_os_str() == "-" { std::io::read_to_string(std::io::stdin())? } else { std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))? };
+        numbers.extend(inodes::parse_inode_list(&text).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?);
+    }
+    numbers.sort_unstable();
+    numbers.dedup();
+    if numbers.is_empty() {
+        anyhow::bail!("no inodes given; pass inode numbers or --inode-file");
+    }
+
+    let start = Instant::now();
+    let geometry = inodes::Geometry::probe(&args.device).with_context(|| format!("failed to read the superblock of {}", args.device.display()))?;
+    info!("{} is {}; resolving {} inodes with {}", args.device.display(), geometry, numbers.len(), geometry.resolver(args.resolver).map_err(anyhow::Error::msg)?);
+    let extents = tokio::task::spawn_blocking({
+        let device = args.device.clone();
+        move || inodes::resolve(&device, &geometry, args.resolver, &numbers)
+    })
+    .await??;
+    let reads = inodes::plan_reads(&extents, args.read_size);
+    let total: u64 = reads.iter().map(|(_, length)| length).sum();
+    if args.dry_run {
+        for (offset, length) in &reads {
+            println!("{}\t{}", offset, length);
+        }
+        return Ok(());
+    }
+
+    let shutdown = Shutdown::new();
+    shutdown::listen_for_signals(shutdown.clone())?;
+    let progress = ProgressBar::new(total);
+    progress.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, ETA {eta})").unwrap());
+    let read_count = reads.len() as u64;
+    let (bytes, errors) = inodes::warm(&args.device, reads, args.queue_depth, args.buffered, &shutdown, &progress).await?;
+    progress.finish_and_clear();
+
+    let summary = InodeSummary {
+        inodes: extents.len() as u64,
+        unresolved: extents.iter().filter(|inode| inode.ranges.is_empty()).map(|inode| inode.inode).collect(),
+        reads: read_count,
+        bytes,
+        errors,
+        duration: start.elapsed(),
+        interrupted: shutdown.is_triggered(),
+    };
+    println!("{}", summary);
+    if !summary.unresolved.is_empty() {
+        let listed: Vec<String> = summary.unresolved.iter().take(20).map(|inode| inode.to_string()).collect();
+        warn!("No data blocks found for {} inodes (unused, inline data, or unreadable): {}{}", summary.unresolved.len(), listed.join(", "), if summary.unresolved.len() > 20 { ", ..." } else { "" });
+    }
+    if let Some(code) = shutdown.exit_code() {
+        std::process::exit(code);
+    }
+    if summary.errors > 0 {
+        std::process::exit(exit::ERROR_BUDGET_EXCEEDED);
+    }
+    Ok(())
+}
+
+async fn bench(args: BenchOpts) -> Result<()> {
+    let seed = args.seed.unwrap_or_else(|| std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64));
+    let options = BenchOptions {
+        directories: args.directories,
+        strategies: args.strategy,
+        files: args.files,
+        queue_depth: args.queue_depth,
+        synthetic_size: args.synthetic_size,
+        warming: WarmingOptions { use_direct_io: args.direct_io, chunk_size: args.chunk_size.unwrap_or(0), ..Default::default() },
+        seed,
+    };
+    debug!("Benchmark seed {}", seed);
+    let report = bench::run(&options, StrategyRegistry::global()).await.context("benchmark failed")?;
+    print!("{}", report);
+    Ok(())
+}
+
+/// Parse the command line. Arguments that don't start with a subcommand are those of
+/// `warm`, whose settings from `--config` (and `--config-profile`) are used as defaults
+/// so that flags given explicitly still win.
+fn parse_args() -> Result<Command> {
+    let mut argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let mut command = Cli::command();
+    let first = argv.get(1).map(|arg| arg.to_string_lossy().into_owned());
+    let top_level = |arg: &str| command.find_subcommand(arg).is_some() || matches!(arg, "help" | "-h" | "--help" | "-V" | "--version");
+    if first.as_deref().is_some_and(|arg| !top_level(arg)) {
+        argv.insert(1, "warm".into());
+    }
+
+    let mut explicit = Vec::new();
+    if let Some(path) = flag_value(&argv, "--config").filter(|_| argv[1] == "warm") {
+        let config = ConfigFile::load(std::path::Path::new(&path))?;
+        let profile = flag_value(&argv, "--config-profile");
+        for (key, values) in config.resolve(profile.as_deref())? {
+            let warm = command.find_subcommand("warm").expect("warm is a subcommand");
+            if !warm.get_arguments().any(|arg| arg.get_id() == key.as_str()) {
+                anyhow::bail!("unknown option '{}' in config file {}", key, path);
+            }
+            command = command.mut_subcommand("warm", |warm| warm.mut_arg(&key, |arg| arg.default_values(values)));
+            explicit.push(key);
+        }
+    }
+    let matches = command.get_matches_from(argv);
+    let Cli { command } = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let Command::Warm(mut opts) = command else { return Ok(command) };
+    let matches = matches.subcommand_matches("warm").expect("parsed as warm");
+    explicit.extend(
+        matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(clap::parser::ValueSource::CommandLine))
+            .map(|id| id.to_string()),
+    );
+    opts.explicit = explicit;
+    if opts.directories.is_empty() && opts.sqs_queue_url.is_none() && !opts.all_ebs_volumes {
+        let mut cli = Cli::command();
+        cli.build();
+        cli.find_subcommand_mut("warm")
+            .expect("warm is a subcommand")
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the following required arguments were not provided:\n  <DIRECTORIES>...",
+            )
+            .exit();
+    }
+    Ok(Command::Warm(opts))
+}
+
+/// Value of `--flag VALUE` or `--flag=VALUE`, looked up before the real parse
+fn flag_value(argv: &[std::ffi::OsString], flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+    let mut args = argv.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return None;
+        }
+        if arg == flag {
+            return args.next().map(|value| value.into_owned());
+        }
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// Tokio worker threads in `--low-memory` mode (each carries its own stack and allocator arena)
+const LOW_MEMORY_WORKER_THREADS: usize = 2;
+/// Cap on the blocking pool (readahead and direct I/O reads) in `--low-memory` mode
+const LOW_MEMORY_BLOCKING_THREADS: usize = 8;
+/// Timed-out files listed in the summary; the JSON report has all of them
+const TIMED_OUT_LISTED: usize = 10;
+
+fn main() -> Result<()> {
+    let args = match parse_args()? {
+        Command::Warm(args) => *args,
+        other => {
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+            let runtime = || tokio::runtime::Builder::new_multi_thread().enable_all().build();
+            return match other {
+                Command::Verify(args) => runtime()?.block_on(verify(args)),
+                Command::Estimate(args) => runtime()?.block_on(estimate(args)),
+                Command::Bench(args) => runtime()?.block_on(bench(args)),
+                Command::DebugBundle(args) => debug_bundle(args),
+                Command::Inodes(args) => runtime()?.block_on(warm_inodes(args)),
+                Command::History(args) => show_history(args),
+                Command::ExportPlan(args) => export_plan(args),
+                Command::Warm(_) => unreachable!(),
+            };
+        }
+    };
+
+    // Initialize logger; with --otlp-endpoint the tracing subscriber takes over logging once the runtime is up
+    let traced = args.otlp_endpoint.is_some() && Platform::current().otel && !args.supervised;
+    if !traced {
+        let level = if args.debug { "debug" } else { "info" };
+        let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level));
+        if args.systemd && systemd::stderr_is_journal() {
+            JournalLogger::new(builder.build()).init()?;
+        } else {
+            builder.init();
+        }
+    }
+
+    // The supervisor only spawns and waits, so it never starts a runtime of its own
+    if args.supervised {
+        return run_supervised(&args);
+    }
+
+    // Before the runtime starts, so every worker and blocking-pool thread inherits them
+    if let Some(nice) = args.nice {
+        if !(-20..=19).contains(&nice) {
+            anyhow::bail!("--nice must be between -20 and 19, got {}", nice);
+        }
+        priority::set_nice(nice).with_context(|| format!("failed to set nice value {}", nice))?;
+    }
+    if let Some(class) = args.ionice_class {
+        if args.ionice_level > priority::MAX_IO_LEVEL {
+            anyhow::bail!("--ionice-level must be between 0 and {}, got {}", priority::MAX_IO_LEVEL, args.ionice_level);
+        }
+        priority::set_io_priority(class, args.ionice_level).with_context(|| format!("failed to set I/O class {}", class))?;
+    }
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all().thread_name_fn(|| {
+        // Workers and blocking-pool threads share the runtime; number them so dumps and `top -H` tell them apart
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        format!("warmer-rt-{}", NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+    });
+    if args.low_memory {
+        runtime
+            .worker_threads(LOW_MEMORY_WORKER_THREADS)
+            .max_blocking_threads(LOW_MEMORY_BLOCKING_THREADS);
+    }
+    match args.otlp_endpoint.clone() {
+        Some(endpoint) => runtime.build()?.block_on(run_traced(&endpoint, args)),
+        None => runtime.build()?.block_on(run(args)),
+    }
+}
+
+/// Report the priority `main` set, and cap reads from the disks behind `directories` in
+/// the warmer's cgroup with --cgroup-read-bps/--cgroup-read-iops
+fn limit_priority(args: &Opts, directories: &[PathBuf]) -> Result<()> {
+    let mut disks: Vec<priority::Disk> = directories.iter().filter_map(|dir| priority::disk_of(dir)).collect();
+    disks.sort_by(|a, b| a.dev.cmp(&b.dev));
+    disks.dedup();
+
+    let mut settings = Vec::new();
+    if let Some(nice) = args.nice {
+        settings.push(format!("nice {}", nice));
+    }
+    if let Some(class) = args.ionice_class {
+        settings.push(match class {
+            IoClass::Idle => "I/O class idle".to_string(),
+            class => format!("I/O class {} level {}", class, args.ionice_level),
+        });
+        for disk in disks.iter().filter(|disk| disk.ignores_io_priority()) {
+            warn!(
+                "--ionice-class has no effect on {}: its I/O scheduler is 'none'. Select mq-deadline or bfq in /sys/block/{}/queue/scheduler, or use --cgroup-read-bps",
+                disk.name, disk.name
+            );
+        }
+    }
+    if !settings.is_empty() {
+        println!("   🐢 Running at {}", settings.join(", "));
+    }
+
+    let io_max = IoMax { read_bps: args.cgroup_read_bps, read_iops: args.cgroup_read_iops };
+    if io_max.is_empty() {
+        return Ok(());
+    }
+    if disks.is_empty() {
+        warn!("--cgroup-read-bps/--cgroup-read-iops: no block device found behind the target directories; reads aren't capped");
+        return Ok(());
+    }
+    let cgroup = priority::own_cgroup(Path::new(priority::CGROUP_ROOT)).context("--cgroup-read-bps/--cgroup-read-iops need cgroup v2")?;
+    let devs: Vec<String> = disks.iter().map(|disk| disk.dev.clone()).collect();
+    let limit = CgroupIoLimit::apply(&cgroup, &devs, io_max).context("failed to set --cgroup-read-bps/--cgroup-read-iops")?;
+    let mut caps = Vec::new();
+    if let Some(bps) = io_max.read_bps {
+        caps.push(format!("{}/s", HumanBytes(bps)));
+    }
+    if let Some(iops) = io_max.read_iops {
+        caps.push(format!("{} IOPS", iops));
+    }
+    let names: Vec<&str> = disks.iter().map(|disk| disk.name.as_str()).collect();
+    println!("   🚧 Kernel read cap of {} on {} ({})", caps.join(" and "), names.join(", "), limit.file().display());
+    limit.keep_until_exit();
+    Ok(())
+}
+
+#[cfg(feature = "otel")]
+async fn run_traced(endpoint: &str, args: Opts) -> Result<()> {
+    use rust_cache_warmer::telemetry::{self, Instrument, Telemetry};
+
+    let telemetry = Telemetry::init(endpoint, if args.debug { "debug" } else { "info" })?;
+    let target = match &args.sqs_queue_url {
+        Some(queue_url) => queue_url.clone(),
+        None => args.directories.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(","),
+    };
+    let result = run(args).instrument(telemetry::run_span(&target)).await;
+    telemetry.shutdown();
+    result
+}
+
+/// Without the 'otel' feature, `--otlp-endpoint` is downgraded at startup (see [`support`])
+#[cfg(not(feature = "otel"))]
+async fn run_traced(_endpoint: &str, args: Opts) -> Result<()> {
+    run(args).await
+}
+
+async fn run(mut args: Opts) -> Result<()> {
+    if !(0.0..=100.0).contains(&args.max_error_percent) {
+        anyhow::bail!("--max-error-percent must be between 0 and 100, got {}", args.max_error_percent);
+    }
+    if args.max_duration.is_some_and(|limit| limit.is_zero()) {
+        anyhow::bail!("--max-duration must be longer than zero");
+    }
+    if args.file_timeout.is_some_and(|limit| limit.is_zero()) {
+        anyhow::bail!("--file-timeout must be longer than zero");
+    }
+    if args.deterministic {
+        // Tuned settings would differ between the volume configurations being compared
+        args.no_auto_tune = true;
+    }
+    if let Some(dir) = args.textfile_dir.as_ref().filter(|dir| !dir.is_dir()) {
+        anyhow::bail!("--textfile-dir {} is not a directory", dir.display());
+    }
+    if let Some(weight) = args.share_weight.iter().find(|weight| !weight.is_target(&args.directories)) {
+        anyhow::bail!("--share-weight {} is not one of the target directories", weight.dir.display());
+    }
+    // Ready as soon as the arguments check out: the warm itself is reported through STATUS=
+    let notifier = if args.systemd { Notifier::from_env() } else { None };
+    match &notifier {
+        Some(notifier) => {
+            notifier.notify_quietly("READY=1\nSTATUS=Starting");
+            if let Some(interval) = Notifier::watchdog_interval() {
+                debug!("Pinging the systemd watchdog every {:?}", interval);
+                systemd::spawn_watchdog(notifier.clone(), interval);
+            }
+        }
+        None if args.systemd => debug!("--systemd: NOTIFY_SOCKET is not set, so not reporting to a service manager"),
+        None => {}
+    }
+    let (include_root, imds) = (args.include_root, args.imds_block_devices);
+    let ebs_volumes = if args.all_ebs_volumes {
+        let found = tokio::task::spawn_blocking(move || volumes::discover(include_root, imds))
+            .await?
+            .context("--all-ebs-volumes: failed to read the mount table")?;
+        if found.volumes.is_empty() && args.directories.is_empty() {
+            anyhow::bail!("--all-ebs-volumes found no EBS volumes to warm{}", if found.root.is_empty() { "" } else { " besides the root volume (add --include-root)" });
+        }
+        for volume in &found.volumes {
+            if !args.directories.contains(&volume.mount_point) {
+                args.directories.push(volume.mount_point.clone());
+            }
+        }
+        args.one_file_system = true;
+        Some(found)
+    } else {
+        None
+    };
+    // A target given through a symlink is warmed, deduplicated and checkpointed under its
+    // real path, whichever spelling was used
+    let linked_roots = match args.symlinked_roots {
+        SymlinkRoots::Resolve => {
+            let (directories, linked) = symlinks::resolve_roots(&args.directories);
+            args.directories = directories;
+            linked
+        }
+        SymlinkRoots::Keep => Vec::new(),
+    };
+    // Overlay layers and bind-mount sources are warmed where they live, once each even
+    // when several targets share them
+    let resolutions = match MountTable::current() {
+        Ok(mounts) if !args.no_resolve_layers => args.directories.iter().map(|dir| mounts.resolve(dir)).collect(),
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            debug!("Not resolving overlay and bind mounts: {}", e);
+            Vec::new()
+        }
+    };
+    if resolutions.iter().any(|resolution| resolution.is_resolved()) {
+        for resolution in &resolutions {
+            debug!("{} resolves to {:?}", resolution.target.display(), resolution.layers);
+        }
+        args.share_weight = args
+            .share_weight
+            .iter()
+            .flat_map(|weight| match resolutions.iter().find(|resolution| weight.is_target(std::slice::from_ref(&resolution.target))) {
+                Some(resolution) => resolution.layers.iter().map(|layer| ShareWeight { dir: layer.path.clone(), weight: weight.weight }).collect(),
+                None => vec![weight.clone()],
+            })
+            .collect();
+        args.directories = layers::roots(&resolutions);
+    }
+    if let Some(mode) = args.anonymize_paths {
+        let anonymizer = PathAnonymizer::open(mode, &args.directories, &args.anonymize_map)
+            .with_context(|| format!("failed to open path map {}", args.anonymize_map.display()))?;
+        anonymize::install(anonymizer);
+    }
+
+    if args.chunk_size.is_some_and(|size| size == 0 || size % 4096 != 0) {
+        anyhow::bail!("--chunk-size must be a non-zero multiple of 4096");
+    }
+    if args.sparse_interval.is_some_and(|interval| interval == 0 || interval % 4096 != 0) {
+        anyhow::bail!("--sparse-interval must be a non-zero multiple of 4096");
+    }
+
+    if !(0.0..=100.0).contains(&args.burst_budget) {
+        anyhow::bail!("--burst-budget must be between 0 and 100, got {}", args.burst_budget);
+    }
+
+    // Fit the settings nobody chose explicitly to the devices being warmed; pacing and
+    // per-device queues need the volume model even without auto-tuning
+    let profile_devices = !args.no_auto_tune || args.pace != PaceMode::None || args.per_device_queue_depth == Some(PerDeviceDepth::Auto);
+    let profiles = if profile_devices { device::profile(&args.directories).await } else { Vec::new() };
+    let tuning = if args.no_auto_tune { Tuning::default() } else { Tuning::for_profiles(&profiles) };
+    let mut tuned = Vec::new();
+    if let Some(queue_depth) = tuning.queue_depth.filter(|_| !args.is_explicit("queue_depth")) {
+        args.queue_depth = queue_depth;
+        tuned.push(format!("queue depth {}", queue_depth));
+    }
+    if let Some(chunk_size) = tuning.chunk_size.filter(|_| args.chunk_size.is_none()) {
+        args.chunk_size = Some(chunk_size);
+        tuned.push(format!("{}KiB reads", chunk_size / 1024));
+    }
+    // The hydrate phase keeps out of the page cache; the strategies that can't do direct
+    // I/O are negotiated away below, or drop each file's pages if fallback allows nothing else
+    if args.two_phase {
+        if args.drop_caches_after == DropCaches::None {
+            anyhow::bail!("--two-phase keeps the hydrate phase out of the page cache, which --drop-caches-after none contradicts");
+        }
+        args.direct_io = true;
+    }
+    // Only replaces the default chain for buffered reads; any strategy choice wins
+    let strategy_chosen = args.is_explicit("strategy") || args.io_uring || args.libaio || !args.ab_test.is_empty() || args.direct_io;
+    if let Some(strategy) = tuning.strategy.filter(|_| args.strategy == Strategy::Auto && !strategy_chosen) {
+        args.strategy = strategy;
+        tuned.push(format!("{} strategy", strategy));
+    }
+
+    if args.low_memory {
+        args.queue_depth = args.queue_depth.clamp(1, LOW_MEMORY_QUEUE_DEPTH);
+        args.batch_size = args.batch_size.clamp(1, LOW_MEMORY_BATCH_SIZE);
+        args.threads = Some(1);
+        if !args.is_explicit("discovery_buffer") {
+            args.discovery_buffer = LOW_MEMORY_PENDING_BATCHES * args.batch_size;
+        }
+        if !args.is_explicit("max_buffer_memory") {
+            args.max_buffer_memory = buffers::LOW_MEMORY_MAX_BUFFER_MEMORY;
+        }
+    }
+    BufferPool::global().set_limit(args.max_buffer_memory);
+
+    // Room for two descriptors per file in flight (sendfile and residency checks open a
+    // second), so deep queues fit; whatever doesn't fit waits rather than hitting EMFILE
+    let wanted = args.max_open_files.unwrap_or(args.queue_depth as u64 * 2);
+    match fds::raise_limit(wanted + fds::FD_RESERVE) {
+        Ok(limit) => {
+            if limit.raised() {
+                debug!("Raised the soft open file limit from {} to {} (hard limit {})", limit.before, limit.soft, limit.hard);
+            }
+            let budget = match args.max_open_files {
+                Some(max) if max > limit.available() => {
+                    warn!(
+                        "--max-open-files {} is more than the {} descriptors `ulimit -n` {} leaves (hard limit {}); keeping to {}",
+                        max,
+                        limit.available(),
+                        limit.soft,
+                        limit.hard,
+                        limit.available()
+                    );
+                    limit.available()
+                }
+                Some(max) => max,
+                None => limit.available(),
+            };
+            if budget < args.queue_depth as u64 {
+                println!("   🗃️  At most {} files open at once (ulimit -n {}); the rest of --queue-depth {} waits for a descriptor", budget, limit.soft, args.queue_depth);
+            }
+            FdBudget::global().set_limit(budget as usize);
+        }
+        Err(e) => debug!("Not budgeting file descriptors: {}", e),
+    }
+
+    // Each device's own queue; devices nothing is known about get the overall depth
+    let mut device_queue_lines = Vec::new();
+    let device_depths = args.per_device_queue_depth.map(|depth| match depth {
+        PerDeviceDepth::Fixed(n) => DeviceDepths { default: n, devices: HashMap::new() },
+        PerDeviceDepth::Auto => {
+            let mut devices = HashMap::new();
+            for profile in &profiles {
+                let depth = profile.tuning().queue_depth.unwrap_or(args.queue_depth);
+                devices.extend(profile.directories.iter().filter_map(|dir| device_queue::device_of(dir)).map(|dev| (dev, depth)));
+                device_queue_lines.push(format!("{} {}", profile.device, depth));
+            }
+            DeviceDepths { default: args.queue_depth, devices }
+        }
+    });
 
     // Start the profiler if the --profile flag is passed
     let guard = if args.profile {
@@ -102,17 +1417,28 @@ async fn main() -> Result<()> {
         None
     };
     
-    // Initialize logger
-    if args.debug {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).init();
-    } else {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    }
-
-    let total_start = Instant::now();
+    clock::start_monitor();
+    let total_start = Stopwatch::start();
     debug!("Configuration: {:?}", args);
 
-    let multi_progress = MultiProgress::new();
+    let shutdown = Shutdown::new();
+    shutdown::listen_for_signals(shutdown.clone())?;
+    let introspection = Introspection::new();
+    let control = Control::new();
+    control::listen_for_pause_signals(control.clone(), introspection.clone())?;
+    let stalls = (args.stall_threshold_ms > 0).then(|| StallMonitor::start(Duration::from_millis(args.stall_threshold_ms)));
+
+    // The dashboard takes over the terminal, so nothing else draws on it
+    let dashboard = args.tui && Platform::current().tui && std::io::stdout().is_terminal();
+    if args.tui && Platform::current().tui && !dashboard {
+        warn!("--tui needs a terminal on stdout; showing progress as usual");
+    }
+    let progress_mode = if dashboard { ProgressMode::None } else { args.progress.unwrap_or_else(ProgressMode::detect) };
+    let multi_progress = match progress_mode {
+        ProgressMode::Bar => MultiProgress::new(),
+        // The bars still keep count (the plain lines, the control API and --progress-file read them); they're just never drawn
+        ProgressMode::Plain | ProgressMode::None => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+    };
     let discovery_style = ProgressStyle::with_template(
         "{spinner:.green} [{elapsed_precise}] Processing files: {pos}",
     )
@@ -131,207 +1457,630 @@ async fn main() -> Result<()> {
     let warming_bar = multi_progress.add(ProgressBar::new_spinner());
     warming_bar.set_style(warming_style);
 
+    let mut filters = DiscoveryFilters::new(
+        &args.directories,
+        &args.include,
+        &args.exclude,
+        &args.include_regex,
+        &args.exclude_regex,
+    )?;
+    let shard = Shard::new(args.shard_index, args.shards)?;
+    if shard.count > 1 {
+        filters = filters.with_shard(shard);
+    }
+    if args.one_file_system || args.only_device.is_some() {
+        let filesystems = FilesystemScope::new(args.one_file_system, args.only_device.as_ref())
+            .context("failed to read /proc/self/mountinfo for --only-device")?;
+        for dir in args.directories.iter().filter(|dir| !filesystems.reaches(dir)) {
+            warn!(
+                "{} is not on {} and has no mount of it below; nothing under it will be warmed",
+                dir.display(),
+                args.only_device.as_ref().map_or(String::new(), |device| device.to_string())
+            );
+        }
+        filters = filters.with_filesystems(filesystems);
+    }
+    if let (Some(newer), Some(older)) = (args.newer_than, args.older_than) {
+        if newer >= older {
+            anyhow::bail!("--newer-than is not earlier than --older-than, so no file could match");
+        }
+    }
+    filters = filters.with_criteria(FileCriteria {
+        min_size: args.min_file_size,
+        newer_than: args.newer_than,
+        older_than: args.older_than,
+        kinds: args.file_type.clone(),
+    });
+    if let Some(since) = &args.changed_since {
+        filters = filters.with_changed(Arc::new(ChangeFilter::load(since)?));
+    }
+    // The page-cache phase of --two-phase: the same scope, but every file it matches,
+    // changed or not, since none of them are cached after a restore
+    let hot_filters = if args.two_phase {
+        let hot = DiscoveryFilters::new(&args.directories, &args.hot_include, &args.exclude, &args.include_regex, &args.exclude_regex)?
+            .with_filesystems(filters.filesystems().clone())
+            .with_criteria(filters.criteria().map(|criteria| (**criteria).clone()).unwrap_or_default());
+        Some(match filters.shard() {
+            Some(shard) => hot.with_shard(shard),
+            None => hot,
+        })
+    } else {
+        None
+    };
+    let working_set = match args.process.as_slice() {
+        [] => None,
+        pids => Some(Arc::new(WorkingSet::read(Path::new("/proc"), pids, &args.directories)?)),
+    };
+    let source: Option<Arc<dyn FileSource>> = if let Some(path) = &args.files_from {
+        Some(Arc::new(PathList::open(path)?))
+    } else if !args.s3_inventory.is_empty() {
+        Some(Arc::new(S3Inventory::open(&args.s3_inventory, &args.s3_inventory_prefix)?))
+    } else if let Some(working_set) = &working_set {
+        Some(Arc::clone(working_set) as Arc<dyn FileSource>)
+    } else {
+        None
+    };
+
     let args = Arc::new(args);
     
+    if args.uring_nowait {
+        warn!("--uring-nowait is deprecated; use --nowait-precheck instead");
+    }
+    // The old boolean flags are kept as aliases for --strategy
+    let mut strategy = args.strategy;
+    if args.io_uring || args.libaio {
+        warn!("--io-uring and --libaio are deprecated; use --strategy uring|libaio instead");
+        if strategy == Strategy::Auto {
+            strategy = if args.io_uring { Strategy::Uring } else { Strategy::Libaio };
+        }
+    }
+
     // Convert CLI options to WarmingOptions
-    let warming_options = WarmingOptions {
-        use_io_uring: args.io_uring,
-        use_libaio: args.libaio,
+    let mut warming_options = WarmingOptions {
+        strategy,
+        fallback: args.strategy_fallback.clone(),
         use_direct_io: args.direct_io,
         sparse_large_files: args.sparse_large_files,
+        low_memory: args.low_memory,
+        skip_cached: args.skip_cached,
+        read_holes: args.read_holes,
+        drop_caches: args.drop_caches_after,
+        intra_file_parallelism: args.intra_file_parallelism,
+        chunk_size: args.chunk_size.unwrap_or(0),
+        nowait_precheck: args.nowait_precheck || args.uring_nowait,
+        small_file_size: args.small_file_size,
+        mmap_touch_stride: args.mmap_touch_stride,
+        range_selector: working_set.clone().map(|working_set| working_set as Arc<dyn RangeSelector>),
+        file_timeout: args.file_timeout,
+        compressed_extents: args.compressed_extents,
+        sparse_interval: args.sparse_interval.unwrap_or(0),
+        strategy_chunk_sizes: args.strategy_chunk_size.clone(),
+        strategy_sparse_intervals: args.strategy_sparse_interval.clone(),
+        strategy_map: args.strategy_map.clone().unwrap_or_default(),
+        // Set per file by the registry
+        ranges: None,
     };
-    
+
+    // Probe backends once up front and downgrade whatever this host or build can't do, so
+    // the banner reflects what will actually run
+    let registry = StrategyRegistry::global();
+    let mut requests = RunRequests {
+        physical_order: args.physical_order,
+        tracing: args.otlp_endpoint.is_some(),
+        snapshot_ranges: args.ebs_snapshot_id.is_some(),
+        small_files: args.is_explicit("small_file_size"),
+        dashboard: args.tui,
+    };
+    // O_DIRECT is tried on a file of each target, so a filesystem that refuses it turns
+    // direct I/O off once instead of failing every file
+    let direct_io_probes: Vec<DirectIoProbe> = if warming_options.use_direct_io { args.directories.iter().map(|dir| probe::direct_io(dir)).collect() } else { Vec::new() };
+    let platform = Platform { direct_io: !direct_io_probes.iter().any(|probe| matches!(probe.capability, Capability::Unavailable(_))), ..Platform::current() };
+    let mut support = support::negotiate(registry, &mut warming_options, &mut requests, &platform)
+        .map_err(|why| anyhow::anyhow!("No usable warming strategy: {}", why))?;
+    support.direct_io = direct_io_probes;
+    if let Some(snapshot_id) = args.ebs_snapshot_id.as_ref().filter(|_| requests.snapshot_ranges) {
+        warming_options.range_selector = Some(snapshot_range_selector(snapshot_id, args.ebs_base_snapshot_id.as_deref()).await?);
+    }
+    let physical_order = requests.physical_order;
+    let plan = registry.plan(&warming_options);
+
     // Display strategy selection at startup
-    if warming_options.use_io_uring || warming_options.use_libaio {
-        println!("🔧 Cache Warming Strategy:");
-        if warming_options.use_io_uring {
-            #[cfg(target_os = "linux")]
-            println!("   📡 io_uring requested - will attempt for maximum performance");
-            #[cfg(not(target_os = "linux"))]
-            println!("   ⚠️  io_uring requested but not available on this platform");
-        }
-        if warming_options.use_libaio {
-            #[cfg(target_os = "linux")]
-            println!("   🚀 libaio requested - will attempt for high performance");
-            #[cfg(not(target_os = "linux"))]
-            println!("   ⚠️  libaio requested but not available on this platform");
-        }
-        if warming_options.use_direct_io {
-            println!("   💾 Direct I/O enabled - bypassing OS page cache");
-        }
-        println!("   🔄 Will fall back to OS hints and Tokio async I/O if needed");
-        println!();
-    } else {
-        println!("🔧 Cache Warming Strategy: Using OS hints and Tokio async I/O");
-        if warming_options.use_direct_io {
-            println!("   💾 Direct I/O enabled");
+    let plan_names: Vec<&str> = plan.iter().map(|s| s.name()).collect();
+    println!("🔧 Cache Warming Strategy: {}", plan_names.join(" → "));
+    for line in support.lines() {
+        println!("   🧭 {}", line);
+    }
+    if let Some(notice) = support.notice() {
+        warn!("{}", notice);
+    }
+    if !warming_options.strategy_map.is_empty() {
+        let mut classes = Vec::new();
+        for (class, mapped) in warming_options.strategy_map.classes() {
+            let options = warming_options.for_class(class);
+            let class_plan = registry.plan(&options);
+            if class_plan.is_empty() {
+                anyhow::bail!("--strategy-map {}={}: strategy '{}' {}", class, mapped, mapped.strategy, unusable_reason(registry, &options));
+            }
+            let names: Vec<&str> = class_plan.iter().map(|s| s.name()).collect();
+            classes.push(format!("{} {}{}", class, names.join(" → "), if mapped.sparse { " (sampled)" } else { "" }));
         }
-        println!();
+        println!("   📐 By size: {}", classes.join(", "));
     }
-    
-    // Use a channel-based approach for batch file processing
-    let (tx, rx) = mpsc::unbounded_channel::<Vec<PathBuf>>();
-    
-    // Spawn file discovery task
-    let discovery_args = Arc::clone(&args);
-    let discovery_handle = tokio::spawn(async move {
-        let mut file_count = 0u64;
-        let mut current_batch = Vec::with_capacity(discovery_args.batch_size);
-        
-        for path in &discovery_args.directories {
-            debug!("Walking directory: {}", path.display());
-            let mut walker_builder = WalkBuilder::new(path);
-            let walker = walker_builder
-                .threads(discovery_args.threads.unwrap_or_else(num_cpus::get))
-                .follow_links(discovery_args.follow_symlinks)
-                .max_depth(discovery_args.max_depth)
-                .git_ignore(!discovery_args.respect_gitignore)
-                .hidden(discovery_args.ignore_hidden)
-                .build();
-
-            for result in walker {
-                match result {
-                    Ok(entry) => {
-                        if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                            current_batch.push(entry.into_path());
-                            file_count += 1;
-                            
-                            // Send batch when it reaches the configured size
-                            if current_batch.len() >= discovery_args.batch_size {
-                                if tx.send(current_batch.clone()).is_err() {
-                                    debug!("Receiver dropped, stopping file discovery");
-                                    return file_count;
-                                }
-                                current_batch.clear();
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        debug!("Failed to process directory entry: {}", err);
-                    }
-                }
+    if let Some(found) = &ebs_volumes {
+        let mounts: Vec<String> = found.volumes.iter().map(|volume| format!("{} ({})", volume.mount_point.display(), volume.disk)).collect();
+        println!("   🗺️  Found {} EBS volumes: {}", found.volumes.len(), if mounts.is_empty() { "none".to_string() } else { mounts.join(", ") });
+        if !found.root.is_empty() {
+            let mounts: Vec<String> = found.root.iter().map(|volume| volume.mount_point.display().to_string()).collect();
+            println!("   🗺️  Leaving out the root volume {} ({}); --include-root warms it too", found.root[0].disk, mounts.join(", "));
+        }
+    }
+    for linked in &linked_roots {
+        println!("   🔗 {} → {}", linked.target.display(), linked.resolved.display());
+    }
+    for resolution in resolutions.iter().filter(|resolution| resolution.is_resolved()) {
+        let sources: Vec<String> = resolution.layers.iter().map(|layer| format!("{} {}", layer.role, layer.path.display())).collect();
+        println!("   🧅 {} → {}", resolution.target.display(), sources.join(", "));
+    }
+    if args.one_file_system {
+        println!("   📌 Staying on each target's filesystem");
+    }
+    if let Some(device) = &args.only_device {
+        println!("   📌 Only files on {}", device);
+    }
+    let layer_count: usize = resolutions.iter().map(|resolution| resolution.layers.len()).sum();
+    if resolutions.iter().any(|resolution| resolution.is_resolved()) && layer_count > args.directories.len() {
+        println!("   🧅 {} resolved directories are shared between targets or nested in others; each file is warmed once", layer_count - args.directories.len());
+    }
+    for nested in dedupe::nested_roots(&args.directories) {
+        if args.no_dedupe_inodes {
+            warn!("{}; with --no-dedupe-inodes its files are warmed twice", nested);
+        } else {
+            println!("   🔁 {}; its files are warmed once", nested);
+        }
+    }
+    for profile in &profiles {
+        let dirs: Vec<String> = profile.directories.iter().map(|dir| dir.display().to_string()).collect();
+        println!("   💽 {} on {}: {}", dirs.join(", "), profile.device, profile);
+    }
+    if !tuned.is_empty() {
+        println!("   🎛️  Auto-tuned: {}", tuned.join(", "));
+    }
+    match args.per_device_queue_depth {
+        Some(PerDeviceDepth::Fixed(n)) => println!("   🚥 At most {} files in flight per device", n),
+        Some(PerDeviceDepth::Auto) if device_queue_lines.is_empty() => println!("   🚥 No device could be profiled; each gets queue depth {}", args.queue_depth),
+        Some(PerDeviceDepth::Auto) => println!("   🚥 Files in flight per device: {} (others {})", device_queue_lines.join(", "), args.queue_depth),
+        None => {}
+    }
+    let pacing = match args.pace {
+        PaceMode::None => None,
+        mode => {
+            let (pacing, unpaced) = Pacing::from_profiles(&profiles, mode, args.burst_budget / 100.0);
+            for (device, why) in &unpaced {
+                warn!("--pace {}: not pacing {} because {}", mode, device, why);
+            }
+            for paced in &pacing.devices {
+                println!(
+                    "   🚦 Pacing {} ({}): burst while {:.0} GB of credit lasts",
+                    paced.device,
+                    paced.pacer.model(),
+                    paced.pacer.credits() / (1024.0 * 1024.0 * 1024.0)
+                );
+            }
+            (!pacing.is_empty()).then(|| Arc::new(pacing))
+        }
+    };
+    if warming_options.use_direct_io {
+        println!("   💾 Direct I/O enabled - bypassing OS page cache");
+    } else if plan.first() == Some(&Strategy::Uring) {
+        println!("   🌀 Buffered io_uring reads{}", if warming_options.nowait_precheck { ", cached data first via RWF_NOWAIT" } else { "" });
+    } else if plan.first() == Some(&Strategy::Tokio) && warming_options.nowait_precheck {
+        println!("   🌀 Buffered reads, cached data first via preadv2 RWF_NOWAIT");
+    }
+    if registry.small_file(1, &warming_options) {
+        println!("   🪶 Files under {} bytes: linked io_uring open/read/close", warming_options.small_file_size.min(SMALL_FILE_MAX_SIZE + 1));
+    }
+    if plan.first() == Some(&Strategy::Mmap) && warming_options.mmap_touch_stride != 1 {
+        match warming_options.mmap_touch_stride {
+            0 => println!("   🗺️  mmap: MADV_WILLNEED advice only, pages are not touched"),
+            stride => println!("   🗺️  mmap: touching one page in every {} after MADV_WILLNEED", stride),
+        }
+    }
+    if warming_options.drop_caches != DropCaches::File {
+        println!("   🧹 Page cache policy: {}", warming_options.drop_caches);
+    }
+    if warming_options.intra_file_parallelism > 1 {
+        println!("   🧵 Files of 128MB or more are read in up to {} parallel ranges", warming_options.intra_file_parallelism);
+    }
+    let experiment = if args.ab_test.is_empty() {
+        None
+    } else {
+        let split = args.split.as_ref().map_or(&[][..], |split| &split.0[..]);
+        let experiment = Experiment::new(&args.ab_test, split, &warming_options, sampling_seed(&args)).map_err(anyhow::Error::msg)?;
+        for options in experiment.arm_options() {
+            if registry.plan(options).is_empty() {
+                anyhow::bail!("A/B test strategy '{}' {}", options.strategy, unusable_reason(registry, options));
             }
         }
-        
-        // Send any remaining files in the final batch
-        if !current_batch.is_empty() {
-            if tx.send(current_batch).is_err() {
-                debug!("Receiver dropped during final batch send");
+        let total_weight: u32 = experiment.strategies().map(|(_, weight)| weight).sum();
+        let arms: Vec<String> = experiment
+            .strategies()
+            .map(|(strategy, weight)| format!("{} {:.0}%", strategy, 100.0 * weight as f64 / total_weight as f64))
+            .collect();
+        println!("   🧪 A/B test: {}, each without fallback", arms.join(" vs "));
+        Some(Arc::new(experiment))
+    };
+    if warming_options.skip_cached {
+        println!("   ⏭️  Skipping pages already in the page cache");
+    }
+    if args.strict_coverage {
+        let mapped_fadvise = warming_options.strategy_map.classes().any(|(_, mapped)| mapped.strategy == Strategy::Fadvise);
+        if warming_options.strategy == Strategy::Fadvise || args.ab_test.contains(&Strategy::Fadvise) || mapped_fadvise {
+            anyhow::bail!("--strict-coverage requires reading files; the fadvise strategy only gives the kernel hints");
+        }
+        println!("   🔒 Strict coverage: every file must be fully read");
+        if args.sparse_large_files > 0 {
+            println!("   ⚠️  --sparse-large-files only samples files over {} bytes; they will count as not covered", args.sparse_large_files);
+        }
+        let sampled: Vec<String> = warming_options.strategy_map.classes().filter(|(_, mapped)| mapped.sparse).map(|(class, _)| class.to_string()).collect();
+        if !sampled.is_empty() {
+            println!("   ⚠️  --strategy-map samples {} files; they will count as not covered", sampled.join(" and "));
+        }
+        if args.max_file_size > 0 {
+            println!("   ⚠️  --max-file-size skips files over {} bytes; they will count as not covered", args.max_file_size);
+        }
+    }
+    if let Some(snapshot_id) = args.ebs_snapshot_id.as_ref().filter(|_| warming_options.range_selector.is_some()) {
+        println!("   🧊 Only warming blocks with data in snapshot {}", snapshot_id);
+    }
+    let checkpoint = match &args.checkpoint {
+        Some(path) => {
+            let mut checkpoint = Checkpoint::open_rotating(path, args.log_rotate_size)
+                .with_context(|| format!("failed to open checkpoint {}", path.display()))?;
+            checkpoint.resolve_links(&linked_roots);
+            println!("   📌 Checkpointing to {} ({} files already done)", path.display(), checkpoint.completed_files());
+            Some(Arc::new(checkpoint))
+        }
+        None => None,
+    };
+    let result_log = match &args.result_log {
+        Some(path) => {
+            let log = ResultLog::create_rotating(path, args.log_rotate_size).with_context(|| format!("failed to create result log {}", path.display()))?;
+            println!("   📝 Logging per-file results to {}", path.display());
+            Some(log)
+        }
+        None => None,
+    };
+    if let Some(anonymizer) = anonymize::global() {
+        println!("   🕶️  Anonymizing paths ({}); originals in {}", anonymizer.mode(), anonymizer.map_path().display());
+    }
+    // Instance store and other local disks have no first-read penalty to warm away
+    let mut directories = Vec::new();
+    let mut page_cache_only = Vec::new();
+    let mut network = Vec::new();
+    for dir in &args.directories {
+        let kind = disk::detect(dir);
+        debug!("{} is on {}", dir.display(), kind);
+        if kind.is_network() && !args.no_network_mode {
+            println!("   🌐 {} is on {}: buffered {} KiB reads, no OS hints", dir.display(), kind, disk::network_warming(&warming_options).chunk_size / 1024);
+            network.push(dir.clone());
+        }
+        if !kind.is_non_ebs() {
+            directories.push(dir.clone());
+            continue;
+        }
+        match args.non_ebs {
+            NonEbsPolicy::Skip => println!("   ⏭️  Skipping {}: on {}, not EBS", dir.display(), kind),
+            NonEbsPolicy::PageCache => {
+                println!("   📥 {} is on {}: warming into the page cache only", dir.display(), kind);
+                page_cache_only.push(dir.clone());
+                directories.push(dir.clone());
+            }
+            NonEbsPolicy::Force => {
+                println!("   ⚠️  {} is on {}, not EBS; warming it anyway", dir.display(), kind);
+                directories.push(dir.clone());
             }
         }
-        
-        debug!("File discovery complete. {} files found.", file_count);
-        file_count
+    }
+    let fair_share = FairShareOptions {
+        by: args.fair_share,
+        weights: args.share_weight.clone(),
+        queue_depth: args.share_queue_depth,
+    };
+    let shares = fair::shares(&directories, &fair_share);
+    if shares.len() > 1 {
+        let names: Vec<String> = shares.iter().map(|share| format!("{} (weight {})", share.name, share.weight)).collect();
+        println!("   ⚖️  Sharing queue depth between {} groups: {}", shares.len(), names.join(", "));
+        if fair_share.queue_depth > 0 {
+            println!("   ⚖️  At most {} batches in flight per group", fair_share.queue_depth);
+        }
+    }
+    if let Some(changed) = filters.changed() {
+        println!("   🕰️  Warming only files {}", changed);
+    }
+    if let Some(source) = &source {
+        println!("   📜 Warming {} instead of walking the target directories", source);
+    }
+    if let Some(working_set) = working_set.as_ref().filter(|working_set| working_set.mapped() > 0) {
+        println!("   🧠 {} of them mapped: warming only the {} mapped", working_set.mapped(), HumanBytes(working_set.mapped_bytes()));
+    }
+    if let Some(shard) = filters.shard() {
+        println!("   🧩 Shard {} of {}: warming only the files whose relative path hashes to it", shard.index, shard.count);
+    }
+    if args.nice.is_some() || args.ionice_class.is_some() || args.cgroup_read_bps.is_some() || args.cgroup_read_iops.is_some() {
+        limit_priority(&args, &directories)?;
+    }
+    if args.metadata_only {
+        println!("   🗂️  Metadata only: listing every directory and stat'ing every entry, no file data");
+    } else if args.metadata_first {
+        println!("   🗂️  Listing every directory and stat'ing every entry before warming file data");
+    }
+    let open_files = args.prioritize_open_files.then(|| Arc::new(open_files::snapshot(Path::new("/proc"), &directories)));
+    if let Some(open_files) = &open_files {
+        println!("   📂 Warming {} files open in {} processes first", open_files.len(), open_files.processes);
+        if open_files.unreadable > 0 {
+            warn!("--prioritize-open-files: couldn't read the open files of {} processes; run as root to include them", open_files.unreadable);
+        }
+    }
+    if args.two_phase {
+        println!("   🔥 Two phases: hydrating without the page cache, then caching files matching {}", args.hot_include.join(", "));
+    }
+    if args.deterministic {
+        println!("   🎯 Deterministic run: sorted discovery, sampling seed {}, no auto-tuning", sampling_seed(&args));
+    }
+    if physical_order {
+        println!("   💿 Warming files in on-disk order, {} at a time", if args.low_memory { args.batch_size } else { PHYSICAL_ORDER_WINDOW.max(args.batch_size) });
+    }
+    if let Some(limit) = args.max_duration {
+        println!("   ⏱️  Starting no new files after {:?}; files being read then still finish", limit);
+    }
+    if let Some(limit) = args.file_timeout {
+        println!("   ⌛ Giving up on files not warmed within {:?} per GiB", limit);
+    }
+    if args.compressed_extents {
+        let mut compressing: Vec<&str> = directories
+            .iter()
+            .filter_map(|dir| compressed::filesystem(dir))
+            .map(|fs| match fs {
+                Compressing::Btrfs => "btrfs",
+                Compressing::Zfs => "ZFS",
+            })
+            .collect();
+        compressing.sort_unstable();
+        compressing.dedup();
+        if compressing.is_empty() {
+            warn!("--compressed-extents: no target directory is on btrfs or ZFS; files are read as usual unless a compressed filesystem is mounted below one");
+        } else {
+            println!("   🗜️  Reading compressed files on {} by extent, one page per compressed extent", compressing.join(" and "));
+        }
+    }
+    if args.low_memory {
+        println!("   🪶 Low-memory mode: queue depth {}, batches of {}, small I/O buffers", args.queue_depth, args.batch_size);
+    }
+    println!();
+
+    if directories.is_empty() && !args.directories.is_empty() && args.sqs_queue_url.is_none() {
+        multi_progress.clear().unwrap();
+        discovery_bar.finish_and_clear();
+        warming_bar.finish_and_clear();
+        info!("Nothing to warm: every target directory is on a non-EBS disk (--non-ebs skip)");
+        return Ok(());
+    }
+
+    let pipeline_options = Arc::new(PipelineOptions {
+        directories,
+        queue_depth: args.queue_depth,
+        threads: args.threads,
+        follow_symlinks: args.follow_symlinks,
+        respect_gitignore: args.respect_gitignore,
+        max_depth: args.max_depth,
+        ignore_hidden: args.ignore_hidden,
+        max_file_size: args.max_file_size,
+        batch_size: args.batch_size,
+        batch_bytes: args.batch_bytes,
+        filters: filters.clone(),
+        warming: warming_options.clone(),
+        stats_by: args.stats_by.clone(),
+        low_memory: args.low_memory,
+        page_cache_only,
+        network,
+        fail_fast: args.fail_fast,
+        fair_share,
+        vanished_grace: Duration::from_millis(args.vanished_grace_ms),
+        physical_order,
+        dedupe_inodes: !args.no_dedupe_inodes,
+        max_duration: args.max_duration,
+        discovery_buffer: args.discovery_buffer,
+        per_device_queue_depth: device_depths,
+        deterministic: args.deterministic,
     });
 
-    let semaphore = Arc::new(Semaphore::new(args.queue_depth));
-    let total_bytes_warmed = Arc::new(AtomicU64::new(0));
-    let processed_files = Arc::new(AtomicU64::new(0));
+    if args.preflight || args.preflight_only {
+        let options = PreflightOptions { max_files: args.preflight_files, ..Default::default() };
+        let report = preflight::preflight(Arc::clone(&pipeline_options), options).await?;
+        multi_progress.suspend(|| println!("{}", report));
+        if report.subtrees.iter().any(|subtree| subtree.cause == Cause::SecurityPolicy) {
+            let policy = report.security_context.as_ref().map_or("security", |context| context.lsm);
+            warn!("Some files are denied although their permissions allow reading them; check the {} policy (or ACLs) this warmer runs under", policy);
+        }
+        if args.preflight_only {
+            multi_progress.clear().unwrap();
+            discovery_bar.finish_and_clear();
+            warming_bar.finish_and_clear();
+            std::process::exit(if report.is_clean() { exit::SUCCESS } else { exit::ERROR_BUDGET_EXCEEDED });
+        }
+    }
+
+    if args.metadata_first || args.metadata_only {
+        let spinner = multi_progress.add(ProgressBar::new_spinner());
+        spinner.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] Stat'ing entries: {pos} ({per_sec})").unwrap());
+        spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+        let summary = metadata_pass::warm_metadata(Arc::clone(&pipeline_options), args.queue_depth, shutdown.clone(), spinner.clone()).await?;
+        spinner.finish_and_clear();
+        info!("{}", summary);
+        if args.metadata_only || summary.interrupted {
+            multi_progress.clear().unwrap();
+            discovery_bar.finish_and_clear();
+            warming_bar.finish_and_clear();
+            if let Some(code) = shutdown.exit_code() {
+                std::process::exit(code);
+            }
+            return Ok(());
+        }
+    }
+
+    if args.verify_only {
+        warn!("--verify-only is deprecated; use `rust-cache-warmer verify` instead");
+        multi_progress.clear().unwrap();
+        discovery_bar.finish_and_clear();
+        warming_bar.finish_and_clear();
+        run_verification(&pipeline_options, &verify_options(&args), &shutdown, false).await?;
+        if let Some(code) = shutdown.exit_code() {
+            std::process::exit(code);
+        }
+        return Ok(());
+    }
+
+    let bytes_style = ProgressStyle::with_template(
+        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes}{msg} ({percent}%, {binary_bytes_per_sec}, ETA {eta})",
+    )
+    .unwrap()
+    .progress_chars("#>-");
+    let bytes_bar = multi_progress.add(ProgressBar::new(0));
+    bytes_bar.set_style(bytes_style);
+    let byte_progress = ByteProgress::new(bytes_bar.clone());
+    if args.precompute_total {
+        let scan_options = Arc::clone(&pipeline_options);
+        let totals = tokio::task::spawn_blocking(move || progress::scan_totals(&scan_options)).await?;
+        multi_progress.suspend(|| println!("   📏 Pre-scan: {} files, {} to warm", totals.files, HumanBytes(totals.bytes)));
+        byte_progress.set_total(totals.bytes);
+    } else {
+        bytes_bar.set_message(" (estimated)");
+    }
+
+    let progress = PipelineProgress {
+        discovery: discovery_bar.clone(),
+        warming: warming_bar.clone(),
+        bytes: byte_progress.clone(),
+    };
 
-    debug!("Starting concurrent file warming");
-    let warming_start = Instant::now();
+    let mut events = EventBus::new();
+    live::listen_for_stats_signal(events.subscribe(), introspection.clone())?;
+    let target = args.directories.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(",");
+    let emf_handle = if args.emit_emf {
+        let emf_options = EmfOptions {
+            namespace: args.emf_namespace.clone(),
+            interval: Duration::from_secs(args.emf_interval.max(1)),
+            target: target.clone(),
+        };
+        Some(tokio::spawn(emf::run_emitter(emf_options, events.subscribe())))
+    } else {
+        None
+    };
+    let textfile_handle = args.textfile_dir.as_ref().map(|dir| {
+        let textfile_options = TextfileOptions {
+            dir: dir.clone(),
+            interval: Duration::from_secs(args.textfile_interval.max(1)),
+            target,
+        };
+        tokio::spawn(textfile::run_writer(textfile_options, events.subscribe()))
+    });
+    let coverage_handle = args.strict_coverage.then(|| tokio::spawn(coverage::run_audit(events.subscribe())));
+    let result_log_handle = result_log.map(|log| tokio::spawn(result_log::run_writer(log, events.subscribe())));
+    let progress_file_handle = args.progress_file.clone().map(|path| {
+        multi_progress.suspend(|| println!("   📟 Writing progress to {} every second", path.display()));
+        tokio::spawn(progress_file::run_writer(path, byte_progress.clone(), shutdown.clone(), events.subscribe()))
+    });
+    let control_handle = match args.status_listen {
+        Some(addr) => {
+            let listener = control::bind(addr).await.with_context(|| format!("--status-listen: failed to listen on {}", addr))?;
+            let addr = listener.local_addr()?;
+            multi_progress.suspend(|| println!("   🎛️  Status and control API on http://{}/status", addr));
+            Some(tokio::spawn(control::serve(listener, control.clone(), byte_progress.clone(), introspection.clone(), events.subscribe())))
+        }
+        None => None,
+    };
+    let systemd_handle = args.systemd.then(|| tokio::spawn(systemd::run_status(notifier.clone(), byte_progress.clone(), events.subscribe())));
+    let watchdog_handle = (!args.hung_io_warning.is_zero()).then(|| watchdog::spawn(introspection.clone(), args.hung_io_warning));
+    let plain_progress_handle =
+        (progress_mode == ProgressMode::Plain).then(|| progress::spawn_plain(byte_progress.clone(), Duration::from_secs(args.progress_interval.max(1))));
 
-    // Process file batches as they're discovered using a stream with controlled concurrency
-    let batch_stream = stream::unfold(rx, |mut rx| async move {
-        rx.recv().await.map(|batch| (batch, rx))
+    let dashboard = dashboard.then(|| {
+        let state = Arc::new(Mutex::new(DashboardState::default()));
+        let collector = dashboard::collect(Arc::clone(&state), events.subscribe());
+        // Log lines would tear through the dashboard; failures still show in its errors panel
+        let level = log::max_level();
+        log::set_max_level(log::LevelFilter::Off);
+        let handle = dashboard::spawn(Dashboard {
+            state,
+            introspection: introspection.clone(),
+            progress: byte_progress.clone(),
+            devices: DeviceSampler::for_directories(&args.directories),
+            shutdown: shutdown.clone(),
+        });
+        (handle, collector, level)
     });
 
-    batch_stream
-        .for_each_concurrent(args.queue_depth, |file_batch| {
-            let semaphore = semaphore.clone();
-            let warming_bar = warming_bar.clone();
-            let discovery_bar = discovery_bar.clone();
-            let total_bytes_warmed = total_bytes_warmed.clone();
-            let processed_files = processed_files.clone();
-            let args_clone = Arc::clone(&args);
-            let warming_options = warming_options.clone();
-
-            async move {
-                let batch_start = Instant::now();
-                let batch_size = file_batch.len();
-                
-                // Acquire semaphore once per batch
-                let acquire_start = Instant::now();
-                let _permit = semaphore.acquire().await.unwrap();
-                let wait_time = acquire_start.elapsed();
-                if wait_time > Duration::from_millis(10) {
-                    debug!("High semaphore wait time: {:?} for batch of {} files", wait_time, batch_size);
-                }
-                
-                // Process each file in the batch
-                for path in file_batch {
-                    let task_start = Instant::now();
-                    discovery_bar.inc(1);
-
-                    // Get file metadata
-                    let file_size = match tokio::fs::metadata(&path).await {
-                        Ok(metadata) => metadata.len(),
-                        Err(e) => {
-                            debug!("Failed to get metadata for {}: {}", path.display(), e);
-                            processed_files.fetch_add(1, Ordering::SeqCst);
-                            warming_bar.inc(1);
-                            continue;
-                        }
-                    };
-
-                    // Log file size category for distribution analysis
-                    let size_category = match file_size {
-                        0..=4096 => "tiny",
-                        4097..=65536 => "small", 
-                        65537..=1048576 => "medium",
-                        1048577..=104857600 => "large",
-                        _ => "huge"
-                    };
-                    debug!("Processing {} file: {} ({} bytes)", size_category, path.display(), file_size);
-
-                    if args_clone.max_file_size > 0 && file_size > args_clone.max_file_size {
-                        debug!("Skipping large file: {} (size: {} > max: {})", path.display(), file_size, args_clone.max_file_size);
-                        processed_files.fetch_add(1, Ordering::SeqCst);
-                        warming_bar.inc(1);
-                        continue;
-                    }
-
-                    // Use the modular warming interface
-                    let _warming_start = Instant::now();
-                    match warm_file(&path, file_size, &warming_options).await {
-                        Ok(result) => {
-                            debug!("File {} warming completed: method={}, success={}, duration={:?}, size={}", 
-                                   path.display(), result.method, result.success, result.duration, file_size);
-                            
-                            // Log performance warnings for slow operations
-                            if result.duration > Duration::from_millis(100) {
-                                warn!("Slow warming operation: {} took {:?} for {} bytes", 
-                                      path.display(), result.duration, file_size);
-                            }
-                        }
-                        Err(e) => {
-                            debug!("Failed to warm file {}: {}", path.display(), e);
-                        }
-                    }
-
-                    total_bytes_warmed.fetch_add(file_size, Ordering::SeqCst);
-                    processed_files.fetch_add(1, Ordering::SeqCst);
-                    warming_bar.inc(1);
-                    
-                    let total_task_time = task_start.elapsed();
-                    debug!("Total task time for {}: {:?}", path.display(), total_task_time);
-                }
-                
-                let batch_duration = batch_start.elapsed();
-                debug!("Completed batch of {} files in {:?}", batch_size, batch_duration);
-            }
-        })
-        .await;
+    let errors = Arc::new(ErrorSamples::new(if args.json_report.is_some() { args.error_samples } else { 0 }));
+    let context = PipelineContext {
+        progress,
+        events,
+        checkpoint: checkpoint.clone(),
+        shutdown: shutdown.clone(),
+        introspection,
+        experiment: experiment.clone(),
+        pacing: pacing.clone(),
+        errors: Arc::clone(&errors),
+        open_files,
+        control: Some(control.clone()),
+        source: source.clone(),
+        ..Default::default()
+    };
+    let summary = pipeline::run(Arc::clone(&pipeline_options), context).await;
+    if let Some((handle, collector, level)) = dashboard {
+        collector.await?;
+        let finished = handle.finish();
+        log::set_max_level(level);
+        if let Err(e) = finished {
+            warn!("The --tui dashboard failed: {}", e);
+        }
+    }
+    if let Some(handle) = watchdog_handle {
+        handle.abort();
+    }
+    if let Some(handle) = plain_progress_handle {
+        handle.abort();
+    }
+    if let Some(handle) = emf_handle {
+        handle.await?;
+    }
+    if let Some(handle) = textfile_handle {
+        handle.await?;
+    }
+    if let Some(handle) = result_log_handle {
+        handle.await?;
+    }
+    if let Some(handle) = progress_file_handle {
+        handle.await?;
+    }
+    if let Some(handle) = systemd_handle {
+        handle.await?;
+    }
+    if let Some(handle) = control_handle {
+        handle.await?;
+    }
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.finish();
+    }
+    let coverage_audit = match coverage_handle {
+        Some(handle) => Some(handle.await?),
+        None => None,
+    };
+    let total_files_discovered = summary.files_discovered;
+    let warming_duration = summary.duration;
 
-    // Wait for discovery to complete and get final count
-    let total_files_discovered = discovery_handle.await.unwrap();
-    
-    debug!("File warming phase complete");
-    let warming_duration = warming_start.elapsed();
-    
     // Enhanced performance statistics
-    let total_bytes = total_bytes_warmed.load(Ordering::SeqCst);
-    let total_files = processed_files.load(Ordering::SeqCst);
+    let total_bytes = summary.bytes_warmed;
+    let total_files = summary.files_processed;
     let throughput_mbps = if warming_duration.as_secs_f64() > 0.0 {
         (total_bytes as f64) / (1024.0 * 1024.0) / warming_duration.as_secs_f64()
     } else {
@@ -354,19 +2103,258 @@ async fn main() -> Result<()> {
     debug!("  Average file size: {} bytes", avg_file_size);
     debug!("  Queue depth: {}", args.queue_depth);
     debug!("  Concurrency efficiency: {:.1}%", (total_files as f64 / warming_duration.as_secs_f64() / args.queue_depth as f64) * 100.0);
+    let path_memory = summary.path_memory;
+    debug!(
+        "  Path storage: {} bytes for {} files in {} directories ({:.1}x smaller than full paths, {} bytes saved)",
+        path_memory.interned_bytes,
+        path_memory.files,
+        path_memory.directories,
+        path_memory.ratio(),
+        path_memory.bytes_saved()
+    );
+    let pool = BufferPool::global().stats();
+    debug!("  Direct I/O buffers: {} at peak, {} waits for a free buffer", HumanBytes(pool.peak as u64), pool.waits);
+    let descriptors = FdBudget::global().stats();
+    debug!("  File descriptors: {} at peak of {}, {} waits for a free descriptor", descriptors.peak, descriptors.limit, descriptors.waits);
+    if descriptors.exhausted > 0 {
+        warn!(
+            "Ran out of file descriptors {} times; files were retried with at most {} open (raise `ulimit -n` or lower --queue-depth)",
+            descriptors.exhausted, descriptors.limit
+        );
+    }
     
     discovery_bar.finish_with_message(format!("Discovered {} files", total_files_discovered));
-    warming_bar.finish_with_message(format!("Warmed {} files", processed_files.load(Ordering::SeqCst)));
+    warming_bar.finish_with_message(format!("Warmed {} files", total_files));
+    bytes_bar.finish_and_clear();
     multi_progress.clear().unwrap();
     
+    if summary.interrupted {
+        warn!(
+            "Interrupted: stopped after {} of {} discovered files; the totals below cover only finished files",
+            total_files, total_files_discovered
+        );
+        if let Some(path) = &args.checkpoint {
+            info!("Progress is saved in {}; rerun with --checkpoint {} to resume", path.display(), path.display());
+        }
+    } else if summary.deadline_reached {
+        let limit = args.max_duration.unwrap_or_default();
+        if byte_progress.is_exact() {
+            let total = byte_progress.total();
+            warn!(
+                "--max-duration {:?} reached: warmed {} of {} ({:.1}%) before the deadline",
+                limit,
+                HumanBytes(total_bytes),
+                HumanBytes(total),
+                if total > 0 { total_bytes as f64 * 100.0 / total as f64 } else { 100.0 }
+            );
+        } else {
+            warn!(
+                "--max-duration {:?} reached: warmed {} files ({}) before the deadline; discovery stopped with it, so the share of the tree is unknown (use --precompute-total to measure it)",
+                limit,
+                total_files,
+                HumanBytes(total_bytes)
+            );
+        }
+        if let Some(path) = &args.checkpoint {
+            info!("Progress is saved in {}; rerun with --checkpoint {} to warm the rest", path.display(), path.display());
+        }
+    }
     info!(
-        "Cache warming complete. Warmed {} bytes ({:.2} MB) across {} files in {:.2?} at {:.2} MB/s.",
+        "Cache warming {}. Warmed {} bytes ({:.2} MB) across {} files in {:.2?} at {:.2} MB/s.",
+        if summary.interrupted {
+            "interrupted"
+        } else if summary.deadline_reached {
+            "stopped at --max-duration"
+        } else {
+            "complete"
+        },
         total_bytes,
         total_bytes as f64 / (1024.0 * 1024.0),
         total_files,
         warming_duration,
         throughput_mbps
     );
+    if total_bytes > 0 || summary.bytes_read > 0 {
+        // What the warm vouches for vs. what the volume bills: sampled and advised files
+        // cover less than their size, cached pages and holes are covered without a read
+        info!(
+            "  Logical data covered: {} of {} ({:.1}%); physical bytes read: {}",
+            HumanBytes(summary.bytes_covered),
+            HumanBytes(total_bytes),
+            summary.bytes_covered as f64 * 100.0 / total_bytes.max(1) as f64,
+            HumanBytes(summary.bytes_read)
+        );
+    }
+    if let Some(notifier) = &notifier {
+        notifier.notify_quietly(&format!(
+            "STATUS={}: {} files, {:.1} MB in {:.0?}",
+            if summary.interrupted {
+                "Interrupted"
+            } else if summary.deadline_reached {
+                "Deadline reached"
+            } else {
+                "Finished"
+            },
+            total_files,
+            total_bytes as f64 / (1024.0 * 1024.0),
+            warming_duration
+        ));
+    }
+    if summary.shares.len() > 1 {
+        for share in &summary.shares {
+            info!(
+                "  {} (weight {}): {} files, {:.2} MB",
+                share.name,
+                share.weight,
+                share.files,
+                share.bytes as f64 / (1024.0 * 1024.0)
+            );
+        }
+    }
+    if !summary.paused.is_zero() {
+        info!("  Paused for {:.2?} (stopped or frozen); durations and throughput cover running time only", summary.paused);
+    }
+    if summary.vanished.vanished > 0 {
+        info!("  {}", summary.vanished);
+    }
+    if summary.duplicates.files + summary.duplicates.directories > 0 {
+        info!("  {}", summary.duplicates);
+    }
+    if let Some(changed) = filters.changed().filter(|changed| changed.unchanged() > 0) {
+        info!("  Left out {} unchanged files (--changed-since)", changed.unchanged());
+    }
+    if summary.missing > 0 {
+        info!("  {} listed files don't exist under the target directories", summary.missing);
+    }
+    if let Some(stalls) = stalls.as_ref().map(StallMonitor::summary).filter(|summary| summary.stalls > 0) {
+        info!("  {}", stalls);
+    }
+    if !summary.timed_out.is_empty() {
+        warn!("  {} files timed out and were left cold; rerun to retry them:", summary.timed_out.len());
+        for path in summary.timed_out.iter().take(TIMED_OUT_LISTED) {
+            warn!("    {}", anonymize::display(path));
+        }
+        if summary.timed_out.len() > TIMED_OUT_LISTED {
+            warn!("    ... and {} more", summary.timed_out.len() - TIMED_OUT_LISTED);
+        }
+    }
+    if !summary.failures_by_cause.is_empty() {
+        let failed: u64 = summary.failures_by_cause.iter().map(|(_, count)| count).sum();
+        info!("  Error breakdown ({} files failed):", failed);
+        for (cause, count) in &summary.failures_by_cause {
+            info!("    {:<18} {}", cause, count);
+        }
+    }
+    let warning_counts = Warnings::global().counts();
+    for count in &warning_counts {
+        if count.suppressed > 0 {
+            warn!("{}", count);
+        }
+    }
+    if let Some(path) = &args.json_report {
+        let mut report = RunReport::new(&summary, &args.directories, &warning_counts, &errors);
+        report.symlinked_targets = linked_roots
+            .iter()
+            .map(|linked| (anonymize::display(&linked.target).to_string(), anonymize::display(&linked.resolved).to_string()))
+            .collect();
+        report.support = Some(support);
+        match report.write(path) {
+            Ok(()) => info!("Wrote JSON report to {}", path.display()),
+            Err(e) => error!("Failed to write JSON report {}: {}", path.display(), e),
+        }
+    }
+    if !args.no_history {
+        let record = RunRecord {
+            timestamp_ms: RunRecord::now(),
+            targets_key: history::targets_key(&args.directories),
+            targets: args.directories.iter().map(|dir| anonymize::display(dir).to_string()).collect(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            kernel: bundle::kernel().map(|kernel| kernel.release),
+            strategy: plan.first().map_or("none", |strategy| strategy.name()).to_string(),
+            files: total_files,
+            failed: summary.stats.totals.failed,
+            bytes: total_bytes,
+            duration_secs: warming_duration.as_secs_f64(),
+            volumes: profiles.iter().filter_map(|profile| profile.volume_id.clone()).collect(),
+            snapshot: args.ebs_snapshot_id.clone(),
+            interrupted: summary.interrupted || summary.deadline_reached || summary.failed_fast,
+        };
+        let state_dir = args.state_dir.clone().unwrap_or_else(history::default_state_dir);
+        match history::record(&state_dir, &record) {
+            Ok(()) => debug!("Recorded this run in {}", state_dir.join(history::HISTORY_FILE).display()),
+            Err(e) => warn!("Couldn't record this run in the history in {}: {}", state_dir.display(), e),
+        }
+    }
+    let totals = &summary.stats.totals;
+    let error_budget_exceeded = summary.failed_fast || exit::error_budget_exceeded(totals, args.max_error_percent);
+    if summary.failed_fast {
+        error!("Stopped early: a file failed to warm and --fail-fast is set");
+    } else if error_budget_exceeded {
+        error!(
+            "{} of {} attempted files ({:.2}%) failed to warm, more than --max-error-percent {}",
+            totals.failed,
+            totals.files - totals.skipped,
+            exit::error_percent(totals),
+            args.max_error_percent
+        );
+    } else if totals.failed > 0 {
+        warn!(
+            "{} files ({:.2}%) failed to warm, within --max-error-percent {}",
+            totals.failed,
+            exit::error_percent(totals),
+            args.max_error_percent
+        );
+    }
+
+    match warming_options.drop_caches {
+        DropCaches::None => info!("Page cache policy: none (warmed pages left cached)"),
+        DropCaches::File => debug!("Page cache policy: file (pages dropped after each file)"),
+        DropCaches::Global => match warming::drop_global_page_cache() {
+            Ok(()) => info!("Page cache policy: global (dropped the system page cache)"),
+            Err(e) => warn!("Page cache policy: global, but dropping the system page cache failed ({}); warmed pages remain cached", e),
+        },
+    }
+
+    if let Some(hot) = hot_filters.filter(|_| !summary.interrupted && !summary.deadline_reached && !summary.failed_fast) {
+        let mut options = phases::page_cache_options(&pipeline_options, hot);
+        options.max_duration = args.max_duration.map(|limit| limit.saturating_sub(warming_duration));
+        let context = PipelineContext { shutdown: shutdown.clone(), pacing, control: Some(control), source: source.clone(), ..Default::default() };
+        let hot = pipeline::run(Arc::new(options), context).await;
+        info!(
+            "Page-cache phase: advised {} hot files ({}) into the page cache in {:.2?}",
+            hot.files_processed,
+            HumanBytes(hot.bytes_warmed),
+            hot.duration
+        );
+        if hot.interrupted || hot.deadline_reached {
+            warn!("The page-cache phase was cut short; {} of {} hot files were advised", hot.files_processed, hot.files_discovered);
+        }
+    }
+
+    if args.stats_by.contains(&StatsDimension::Ext) {
+        println!("{}", StatsSnapshot::format_breakdown("By extension:", &summary.stats.by_ext, 25));
+    }
+    if args.stats_by.contains(&StatsDimension::Dir) {
+        println!("{}", StatsSnapshot::format_breakdown("By top-level directory:", &summary.stats.by_dir, 25));
+    }
+    if let Some(experiment) = &experiment {
+        println!("{}", experiment.report());
+    }
+    let coverage_incomplete = match coverage_audit {
+        Some(mut audit) => {
+            audit.add_discovery_errors(summary.discovery_errors);
+            println!("{}", audit);
+            if !audit.is_complete() {
+                error!("--strict-coverage: not every file was fully read; see the coverage report above");
+            }
+            !audit.is_complete()
+        }
+        None => false,
+    };
+
+    if (args.verify || args.verify_rewarm) && !summary.interrupted {
+        run_verification(&pipeline_options, &verify_options(&args), &shutdown, args.verify_rewarm).await?;
+    }
     
     // If profiling was enabled, generate the report.
     if let Some(guard) = guard {
@@ -377,11 +2365,201 @@ async fn main() -> Result<()> {
         };
     }
 
-    debug!("All phases complete. Exiting.");
-    let total_duration = total_start.elapsed();
+    let total_duration = total_start.wall();
     if !args.debug {
-        println!("Total execution time: {:.2?}", total_duration);
+        match total_start.paused() {
+            paused if paused.is_zero() => println!("Total execution time: {:.2?}", total_duration),
+            paused => println!("Total execution time: {:.2?} ({:.2?} of it paused)", total_duration, paused),
+        }
+    }
+
+    if let Some(code) = shutdown.exit_code() {
+        std::process::exit(code);
+    }
+    if summary.failed_fast {
+        std::process::exit(exit::ERROR_BUDGET_EXCEEDED);
+    }
+
+    if args.watch {
+        let watch_options = WatchOptions {
+            directories: pipeline_options.directories.clone(),
+            queue_depth: args.queue_depth,
+            max_file_size: args.max_file_size,
+            debounce: Duration::from_millis(args.watch_debounce_ms),
+            filters,
+            page_cache_only: pipeline_options.page_cache_only.clone(),
+            network: pipeline_options.network.clone(),
+            dedupe_capacity: args.watch_dedupe_capacity,
+        };
+        tokio::select! {
+            result = watch::watch_and_warm(watch_options, warming_options.clone()) => result?,
+            _ = shutdown.triggered() => info!("Stopped watching"),
+        }
+    }
+
+    // Unfinished SQS messages aren't deleted, so they become visible again for the next poller
+    if let Some(queue_url) = &args.sqs_queue_url {
+        if !shutdown.is_triggered() {
+            tokio::select! {
+                result = run_sqs_mode(queue_url, &args, warming_options) => result?,
+                _ = shutdown.triggered() => info!("Stopped polling {}", queue_url),
+            }
+        }
+    }
+
+    if let Err(e) = anonymize::flush() {
+        error!("Failed to write path map {}: {}", args.anonymize_map.display(), e);
+    }
+    if let Some(code) = shutdown.exit_code() {
+        std::process::exit(code);
+    }
+    if error_budget_exceeded {
+        std::process::exit(exit::ERROR_BUDGET_EXCEEDED);
+    }
+    if coverage_incomplete {
+        std::process::exit(exit::COVERAGE_INCOMPLETE);
+    }
+
+    debug!("All phases complete. Exiting.");
+
+    Ok(())
+}
+
+/// Why `options` leaves the registry with nothing to run
+fn unusable_reason(registry: &StrategyRegistry, options: &WarmingOptions) -> &'static str {
+    if registry.is_available(options.strategy) {
+        if options.use_direct_io { "doesn't support --direct-io" } else { "requires --direct-io" }
+    } else {
+        "is unavailable on this host"
+    }
+}
+
+/// Settings of `--verify` and `--verify-rewarm`
+fn verify_options(args: &Opts) -> VerifyOptions {
+    VerifyOptions {
+        samples: args.verify_samples,
+        cold_threshold: Duration::from_millis(args.verify_cold_ms),
+        concurrency: args.queue_depth,
+        seed: sampling_seed(args),
+    }
+}
+
+/// `--seed`, or a fixed seed under `--deterministic` and the clock otherwise
+fn sampling_seed(args: &Opts) -> u64 {
+    match args.seed {
+        Some(seed) => seed,
+        None if args.deterministic => 0,
+        None => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64),
+    }
+}
+
+/// Sample the target directories and print how hydrated they look. With `rewarm`, cold
+/// regions are re-warmed and checked again, failing if any stays cold.
+async fn run_verification(pipeline_options: &Arc<PipelineOptions>, options: &VerifyOptions, shutdown: &Shutdown, rewarm: bool) -> Result<()> {
+    info!("Verifying hydration with {} random samples...", options.samples);
+    let report = tokio::select! {
+        report = verify::verify(Arc::clone(pipeline_options), options) => report?,
+        _ = shutdown.triggered() => {
+            warn!("Verification interrupted");
+            return Ok(());
+        }
+    };
+    println!("{}", report);
+    if !rewarm {
+        return Ok(());
     }
 
+    let rewarmed = tokio::select! {
+        rewarmed = verify::rewarm_cold(&report, &pipeline_options.warming, options.seed.wrapping_add(1)) => rewarmed,
+        _ = shutdown.triggered() => {
+            warn!("Re-warm check interrupted");
+            return Ok(());
+        }
+    };
+    println!("{}", rewarmed);
+    if !rewarmed.regions.is_empty() {
+        warn!(
+            "Strategy '{}' reported success for files that still read cold; consider a read-based strategy (e.g. --strategy tokio)",
+            pipeline_options.warming.strategy
+        );
+    }
+    let still_cold = rewarmed.still_cold().len();
+    if still_cold > 0 {
+        anyhow::bail!("{} regions still read cold after being re-warmed", still_cold);
+    }
     Ok(())
 }
+
+/// Re-run this binary with the same arguments under the supervisor
+fn run_supervised(args: &Opts) -> Result<()> {
+    // Without an explicit --checkpoint the supervisor keeps one of its own for the run
+    let (checkpoint, temporary) = match &args.checkpoint {
+        Some(path) => (path.clone(), false),
+        None => (std::env::temp_dir().join(format!("rust-cache-warmer-{}.checkpoint", std::process::id())), true),
+    };
+    let mut child_args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).filter(|arg| arg != "--supervised").collect();
+    if !temporary {
+        // Passed through as given; supervise() appends the checkpoint itself
+        strip_flag_with_value(&mut child_args, "--checkpoint");
+    }
+
+    let options = SupervisorOptions {
+        program: std::env::current_exe().context("can't locate the warmer executable to supervise")?,
+        args: child_args,
+        checkpoint: checkpoint.clone(),
+        max_restarts: args.max_restarts,
+    };
+    let status = supervisor::supervise(&options)?;
+    if status.success() {
+        if temporary {
+            let _ = logfile::remove(&checkpoint);
+        }
+        return Ok(());
+    }
+    std::process::exit(supervisor::exit_code(&status));
+}
+
+/// Remove `--flag VALUE` and `--flag=VALUE` from an argument list
+fn strip_flag_with_value(args: &mut Vec<std::ffi::OsString>, flag: &str) {
+    let prefix = format!("{}=", flag);
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == flag {
+            args.drain(i..std::cmp::min(i + 2, args.len()));
+        } else if arg.starts_with(&prefix) {
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+#[cfg(feature = "aws")]
+async fn run_sqs_mode(queue_url: &str, args: &Opts, warming_options: WarmingOptions) -> Result<()> {
+    let sqs_options = rust_cache_warmer::sqs::SqsOptions {
+        queue_url: queue_url.to_string(),
+        queue_depth: args.queue_depth,
+        max_file_size: args.max_file_size,
+        batch_size: args.sqs_batch_size,
+        visibility_timeout: Duration::from_secs(args.sqs_visibility_timeout),
+        wait_time: Duration::from_secs(20),
+    };
+    rust_cache_warmer::sqs::poll_and_warm(sqs_options, warming_options).await
+}
+
+#[cfg(not(feature = "aws"))]
+async fn run_sqs_mode(_queue_url: &str, _args: &Opts, _warming_options: WarmingOptions) -> Result<()> {
+    anyhow::bail!("--sqs-queue-url requires building with the 'aws' feature (cargo build --release --features aws)")
+}
+
+#[cfg(feature = "aws")]
+async fn snapshot_range_selector(snapshot_id: &str, base_snapshot_id: Option<&str>) -> Result<Arc<dyn RangeSelector>> {
+    let map = rust_cache_warmer::ebs::load_block_map(snapshot_id, base_snapshot_id).await?;
+    Ok(Arc::new(rust_cache_warmer::ebs::SnapshotRangeSelector::new(map)))
+}
+
+#[cfg(not(feature = "aws"))]
+async fn snapshot_range_selector(_snapshot_id: &str, _base_snapshot_id: Option<&str>) -> Result<Arc<dyn RangeSelector>> {
+    anyhow::bail!("--ebs-snapshot-id requires building with the 'aws' feature (cargo build --release --features aws)")
+}
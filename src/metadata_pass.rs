@@ -0,0 +1,121 @@
+//! Warming file system metadata ahead of file data (`--metadata-first`, `--metadata-only`).
+//!
+//! On a volume restored from a snapshot, inode tables and directory blocks are as cold as
+//! the data: the first `find`, `ls -l` or `git status` over millions of files pays a
+//! first-read penalty per block, and so does the data pass's own discovery, one stat at a
+//! time. This pass walks the targets with many threads at once, listing every directory
+//! (which reads its blocks) and stat'ing every entry (which reads the block holding its
+//! inode), so both are hydrated before any file is opened. It honours the same filters,
+//! depth and filesystem restrictions as discovery.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ignore::WalkState;
+use indicatif::ProgressBar;
+use log::debug;
+
+use crate::anonymize;
+use crate::pipeline::{self, PipelineOptions};
+use crate::shutdown::Shutdown;
+use crate::warnings::{self, Category};
+
+/// What the metadata pass covered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetadataSummary {
+    /// Directories listed
+    pub directories: u64,
+    /// Entries stat'ed, directories included
+    pub entries: u64,
+    /// Directories that couldn't be listed and entries that couldn't be stat'ed
+    pub errors: u64,
+    pub duration: Duration,
+    /// Stopped by a shutdown request before the walk finished
+    pub interrupted: bool,
+}
+
+impl MetadataSummary {
+    pub fn entries_per_second(&self) -> f64 {
+        self.entries as f64 / self.duration.as_secs_f64().max(0.001)
+    }
+}
+
+impl fmt::Display for MetadataSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Metadata pass{}: stat'ed {} entries in {} directories in {:.2?} ({:.0} entries/s), {} errors",
+            if self.interrupted { " (interrupted)" } else { "" },
+            self.entries,
+            self.directories,
+            self.duration,
+            self.entries_per_second(),
+            self.errors
+        )
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    directories: AtomicU64,
+    entries: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Walk every target directory with `concurrency` threads, stat'ing each entry.
+/// `progress` counts entries.
+pub async fn warm_metadata(options: Arc<PipelineOptions>, concurrency: usize, shutdown: Shutdown, progress: ProgressBar) -> anyhow::Result<MetadataSummary> {
+    Ok(tokio::task::spawn_blocking(move || walk(&options, concurrency, &shutdown, &progress)).await?)
+}
+
+/// The blocking body of [`warm_metadata`]
+pub fn walk(options: &PipelineOptions, concurrency: usize, shutdown: &Shutdown, progress: &ProgressBar) -> MetadataSummary {
+    let start = Instant::now();
+    let counters = Counters::default();
+    for root in &options.directories {
+        if shutdown.is_triggered() {
+            break;
+        }
+        debug!("Warming metadata under {}", anonymize::display(root));
+        pipeline::parallel_walker(options, root, concurrency.max(1)).run(|| {
+            let counters = &counters;
+            Box::new(move |result| {
+                if shutdown.is_triggered() {
+                    return WalkState::Quit;
+                }
+                match result {
+                    // The walker lists each directory it yields, reading its blocks
+                    Ok(entry) => match entry.metadata() {
+                        Ok(metadata) => {
+                            if metadata.is_dir() {
+                                counters.directories.fetch_add(1, Ordering::Relaxed);
+                            }
+                            counters.entries.fetch_add(1, Ordering::Relaxed);
+                            progress.inc(1);
+                        }
+                        Err(e) => {
+                            counters.errors.fetch_add(1, Ordering::Relaxed);
+                            debug!("Metadata pass couldn't stat {}: {}", anonymize::display(entry.path()), e);
+                        }
+                    },
+                    Err(e) => {
+                        counters.errors.fetch_add(1, Ordering::Relaxed);
+                        warnings::report(Category::Discovery, format_args!("Metadata pass failed to process directory entry: {}", e));
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+    }
+    let summary = MetadataSummary {
+        directories: counters.directories.load(Ordering::Relaxed),
+        entries: counters.entries.load(Ordering::Relaxed),
+        errors: counters.errors.load(Ordering::Relaxed),
+        duration: start.elapsed(),
+        interrupted: shutdown.is_triggered(),
+    };
+    debug!("{}", summary);
+    summary
+}
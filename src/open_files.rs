@@ -0,0 +1,118 @@
+//! Warming files that are already open first (`--prioritize-open-files`).
+//!
+//! On a replica restored from a snapshot, the files an application already holds open
+//! (its database files, indexes, logs) are the ones it is about to read, yet a walk
+//! reaches them wherever they happen to lie in the tree, often last. [`snapshot`] reads
+//! every process's open descriptors from `/proc/<pid>/fd` once, before warming starts,
+//! and keeps the regular files under the target directories. Discovery then lists the
+//! directories they're in and sends the files themselves, most widely held first, ahead
+//! of its walk, which leaves them out.
+//!
+//! Other users' descriptors can only be read as root (or with `CAP_SYS_PTRACE`); the
+//! processes that couldn't be read are counted so the banner can say so.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+/// A file open in at least one process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFile {
+    /// Under the target directory it was found in, as discovery would name it
+    pub path: PathBuf,
+    /// Descriptors open on it across all processes
+    pub descriptors: u32,
+}
+
+/// Files under the targets that were open when the snapshot was taken
+#[derive(Debug, Clone, Default)]
+pub struct OpenFiles {
+    /// Most descriptors first
+    files: Vec<OpenFile>,
+    paths: HashSet<PathBuf>,
+    /// Processes holding at least one of `files`
+    pub processes: usize,
+    /// Processes whose descriptors couldn't be read
+    pub unreadable: usize,
+}
+
+impl OpenFiles {
+    pub fn files(&self) -> &[OpenFile] {
+        &self.files
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Whether `path` is one of the open files
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(path)
+    }
+
+    /// The open files under any of `roots`, most descriptors first
+    pub fn under<'a>(&'a self, roots: &'a [PathBuf]) -> impl Iterator<Item = &'a OpenFile> + 'a {
+        self.files.iter().filter(move |file| roots.iter().any(|root| file.path.starts_with(root)))
+    }
+}
+
+/// Read the open descriptors of every process in `proc` (normally `/proc`) other than
+/// this one and keep the regular files under `roots`
+pub fn snapshot(proc: &Path, roots: &[PathBuf]) -> OpenFiles {
+    // Descriptors name files by their canonical path; discovery names them under the root as given
+    let canonical: Vec<(PathBuf, &PathBuf)> = roots.iter().filter_map(|root| Some((root.canonicalize().ok()?, root))).collect();
+    let own_pid = std::process::id().to_string();
+    let mut descriptors: HashMap<PathBuf, u32> = HashMap::new();
+    let (mut processes, mut unreadable) = (0, 0);
+
+    let Ok(entries) = std::fs::read_dir(proc) else {
+        debug!("Can't list {}, so no open files are prioritized", proc.display());
+        return OpenFiles::default();
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name.to_str().filter(|name| name.bytes().all(|b| b.is_ascii_digit())) else { continue };
+        if pid == own_pid {
+            continue;
+        }
+        let fds = match std::fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            // Exited since the listing
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(_) => {
+                unreadable += 1;
+                continue;
+            }
+        };
+        let mut holds = false;
+        for fd in fds.flatten() {
+            // Pipes, sockets and anonymous inodes read as `pipe:[…]` and the like, deleted files end in " (deleted)"
+            let Ok(target) = std::fs::read_link(fd.path()) else { continue };
+            let Some(path) = under_root(&target, &canonical) else { continue };
+            *descriptors.entry(path).or_default() += 1;
+            holds = true;
+        }
+        processes += usize::from(holds);
+    }
+
+    let mut files: Vec<OpenFile> = descriptors
+        .into_iter()
+        .filter(|(path, _)| std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file()))
+        .map(|(path, descriptors)| OpenFile { path, descriptors })
+        .collect();
+    files.sort_by(|a, b| b.descriptors.cmp(&a.descriptors).then_with(|| a.path.cmp(&b.path)));
+    debug!("{} open files under the targets in {} processes ({} unreadable)", files.len(), processes, unreadable);
+    OpenFiles { paths: files.iter().map(|file| file.path.clone()).collect(), files, processes, unreadable }
+}
+
+/// `target` named under the deepest root (given as `(canonical, as given)`) containing it
+pub(crate) fn under_root(target: &Path, roots: &[(PathBuf, &PathBuf)]) -> Option<PathBuf> {
+    let (canonical, root) = roots.iter().filter(|(canonical, _)| target.starts_with(canonical)).max_by_key(|(canonical, _)| canonical.components().count())?;
+    let relative = target.strip_prefix(canonical).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| root.join(relative))
+}
@@ -0,0 +1,293 @@
+//! Read pacing shaped to EBS burst buckets (`--pace ebs-burst`).
+//!
+//! gp2, st1 and sc1 volumes run at a burst rate while they hold credits and drop to a
+//! baseline that depends on their size once the bucket is empty. Warming flat out can
+//! empty the bucket that the application needs after boot. A constant-rate throttle
+//! keeps credits, but the right rate depends on the tree's size, which isn't known up
+//! front. `ebs-burst` pacing mirrors the volume instead. It reads at the burst rate
+//! until it has spent its share of the bucket (`--burst-budget`), then holds the
+//! baseline, which costs no credits.
+//!
+//! Every byte above baseline comes out of the bucket, so for a given credit spend no
+//! schedule finishes sooner. A constant rate matches it only if it was picked knowing
+//! the total size, and it warms less of the tree early.
+//!
+//! The model comes from the device profile (volume type from `DescribeVolumes`, size
+//! from there or sysfs) and assumes the bucket is full when the run starts, as it is for
+//! a new volume. Volumes without a burst bucket (gp3, io1, io2) aren't paced.
+//!
+//! EFS file systems in Bursting Throughput mode have the same shape: a baseline that
+//! grows with the data stored, bursts up to 100 MiB/s per TiB, and a credit bucket that a
+//! new file system starts with 2.1 TiB of. `--pace efs-burst` paces each NFS mount with
+//! that model, so one host warming a shared file system doesn't drain the credits every
+//! other client of it bursts on.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::device::{DeviceProfile, VolumeType};
+
+const KIB: f64 = 1024.0;
+const MIB: f64 = 1024.0 * KIB;
+const GIB: f64 = 1024.0 * MIB;
+const TIB: f64 = 1024.0 * GIB;
+/// gp2 counts reads of up to 256 KiB as one I/O
+const GP2_IO_SIZE: f64 = 256.0 * KIB;
+/// gp2 burst IOPS and credit bucket
+const GP2_BURST_IOPS: f64 = 3000.0;
+const GP2_BUCKET_IOS: f64 = 5_400_000.0;
+/// EFS baseline and burst throughput per TiB stored, the floors for small file
+/// systems, and credits per TiB (the bucket of a new file system)
+const EFS_BASELINE_PER_TIB: f64 = 50.0 * MIB;
+const EFS_MIN_BASELINE: f64 = MIB;
+const EFS_BURST_PER_TIB: f64 = 100.0 * MIB;
+const EFS_MIN_BURST: f64 = 100.0 * MIB;
+const EFS_BUCKET_PER_TIB: f64 = 2.1 * TIB;
+/// EFS meters reads at a third of their size
+const EFS_READ_METERING: f64 = 3.0;
+
+/// How reads are paced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaceMode {
+    /// As fast as the queue depth allows
+    #[default]
+    None,
+    /// Burst rate while the budgeted credits last, then the volume's baseline
+    EbsBurst,
+    /// Like `EbsBurst`, for EFS and other NFS mounts in bursting throughput mode
+    EfsBurst,
+}
+
+impl FromStr for PaceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(PaceMode::None),
+            "ebs-burst" => Ok(PaceMode::EbsBurst),
+            "efs-burst" => Ok(PaceMode::EfsBurst),
+            other => Err(format!("unknown pacing mode '{}' (expected 'none', 'ebs-burst' or 'efs-burst')", other)),
+        }
+    }
+}
+
+impl fmt::Display for PaceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaceMode::None => write!(f, "none"),
+            PaceMode::EbsBurst => write!(f, "ebs-burst"),
+            PaceMode::EfsBurst => write!(f, "efs-burst"),
+        }
+    }
+}
+
+/// Burst and baseline throughput of a volume, and the credits that separate them, in
+/// bytes per second and bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BurstModel {
+    pub baseline: f64,
+    pub burst: f64,
+    /// Bytes that can be read above baseline before the bucket is empty
+    pub bucket: f64,
+}
+
+impl BurstModel {
+    /// The model for a volume of `volume_type` and `size_gib`, or why there is none
+    pub fn for_volume(volume_type: &VolumeType, size_gib: f64) -> Result<Self, String> {
+        let model = match volume_type {
+            VolumeType::Gp2 => {
+                let cap = if size_gib <= 170.0 { 128.0 * MIB } else { 250.0 * MIB };
+                let baseline_iops = (3.0 * size_gib).clamp(100.0, 16_000.0);
+                BurstModel {
+                    baseline: (baseline_iops * GP2_IO_SIZE).min(cap),
+                    burst: (GP2_BURST_IOPS.max(baseline_iops) * GP2_IO_SIZE).min(cap),
+                    bucket: GP2_BUCKET_IOS * GP2_IO_SIZE,
+                }
+            }
+            VolumeType::St1 | VolumeType::Sc1 => {
+                let tib = size_gib / 1024.0;
+                // Baseline and burst MiB/s per TiB, and their caps
+                let (baseline, burst, baseline_cap, burst_cap) =
+                    if *volume_type == VolumeType::St1 { (40.0, 250.0, 500.0, 500.0) } else { (12.0, 80.0, 192.0, 250.0) };
+                BurstModel {
+                    baseline: (baseline * tib).min(baseline_cap) * MIB,
+                    burst: (burst * tib).min(burst_cap) * MIB,
+                    bucket: tib * TIB,
+                }
+            }
+            other => return Err(format!("{} volumes have no burst bucket", other)),
+        };
+        if model.burst <= model.baseline {
+            return Err(format!("a {:.0} GiB {} volume's baseline is already its burst rate", size_gib, volume_type));
+        }
+        Ok(model)
+    }
+
+    /// The model for an EFS file system in Bursting Throughput mode storing
+    /// `stored_gib`, in bytes read: reads cost a third of their size in credits
+    pub fn for_efs(stored_gib: f64) -> Self {
+        let tib = stored_gib.max(0.0) / 1024.0;
+        BurstModel {
+            baseline: (EFS_BASELINE_PER_TIB * tib).max(EFS_MIN_BASELINE) * EFS_READ_METERING,
+            burst: (EFS_BURST_PER_TIB * tib).max(EFS_MIN_BURST) * EFS_READ_METERING,
+            bucket: EFS_BUCKET_PER_TIB * tib.max(1.0) * EFS_READ_METERING,
+        }
+    }
+
+    /// The model for a profiled device, or why there is none
+    pub fn for_profile(profile: &DeviceProfile) -> Result<Self, String> {
+        if profile.kind.is_network() {
+            return match profile.size_bytes {
+                Some(bytes) => Ok(Self::for_efs(bytes as f64 / GIB)),
+                None => Err("the size of the data stored on it is unknown".to_string()),
+            };
+        }
+        let Some(ebs) = &profile.ebs else {
+            return Err(if profile.volume_id.is_some() {
+                "its volume type is unknown (needs the 'aws' feature and ec2:DescribeVolumes)".to_string()
+            } else {
+                "it isn't an EBS volume".to_string()
+            });
+        };
+        let size_gib = match (ebs.size_gib, profile.size_bytes) {
+            (Some(gib), _) => gib as f64,
+            (None, Some(bytes)) => bytes as f64 / GIB,
+            (None, None) => return Err("its size is unknown".to_string()),
+        };
+        Self::for_volume(&ebs.volume_type, size_gib)
+    }
+}
+
+impl fmt::Display for BurstModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} MB/s burst, {:.0} MB/s baseline, {:.0} GB bucket",
+            self.burst / MIB,
+            self.baseline / MIB,
+            self.bucket / GIB
+        )
+    }
+}
+
+/// Schedules reads on one volume: at the burst rate while budgeted credits remain, then
+/// at the baseline rate
+#[derive(Debug)]
+pub struct Pacer {
+    model: BurstModel,
+    state: Mutex<PacerState>,
+}
+
+#[derive(Debug)]
+struct PacerState {
+    /// When the bandwidth reserved so far runs out
+    next: Instant,
+    /// Budgeted bytes above baseline still to spend
+    credits: f64,
+}
+
+impl Pacer {
+    /// A pacer that may spend `budget` (0.0 to 1.0) of the model's bucket
+    pub fn new(model: BurstModel, budget: f64) -> Self {
+        Self { model, state: Mutex::new(PacerState { next: Instant::now(), credits: model.bucket * budget.clamp(0.0, 1.0) }) }
+    }
+
+    pub fn model(&self) -> BurstModel {
+        self.model
+    }
+
+    /// Budgeted burst credits left, in bytes
+    pub fn credits(&self) -> f64 {
+        self.state.lock().unwrap().credits.max(0.0)
+    }
+
+    /// Reserve bandwidth for reading `bytes`, as of `now`. Returns how long to wait
+    /// before starting the read. Time the volume sat idle isn't saved up.
+    pub fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let start = state.next.max(now);
+        let rate = if state.credits > 0.0 { self.model.burst } else { self.model.baseline };
+        let seconds = bytes as f64 / rate;
+        if state.credits > 0.0 {
+            state.credits -= (rate - self.model.baseline) * seconds;
+        }
+        state.next = start + Duration::from_secs_f64(seconds);
+        start - now
+    }
+
+    /// Wait for the turn of a read of `bytes`
+    pub async fn pace(&self, bytes: u64) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// A paced device and the target directories on it
+#[derive(Debug)]
+pub struct PacedDevice {
+    pub device: String,
+    pub directories: Vec<PathBuf>,
+    pub pacer: Pacer,
+}
+
+/// Pacers for every device that has a burst model
+#[derive(Debug, Default)]
+pub struct Pacing {
+    pub devices: Vec<PacedDevice>,
+    /// Target directories on devices without a model, which may be nested in paced ones
+    unpaced: Vec<PathBuf>,
+}
+
+impl Pacing {
+    /// Pace every profiled device `mode` applies to (EBS volumes for `ebs-burst`, network
+    /// mounts for `efs-burst`) that has a burst model, spending `budget` of each bucket.
+    /// Also returns the devices left unpaced and why; those `mode` doesn't apply to go
+    /// unmentioned.
+    pub fn from_profiles(profiles: &[DeviceProfile], mode: PaceMode, budget: f64) -> (Self, Vec<(String, String)>) {
+        let mut pacing = Self::default();
+        let mut reasons = Vec::new();
+        for profile in profiles {
+            let applies = match mode {
+                PaceMode::None => false,
+                PaceMode::EbsBurst => !profile.kind.is_network(),
+                PaceMode::EfsBurst => profile.kind.is_network(),
+            };
+            if !applies {
+                pacing.unpaced.extend(profile.directories.iter().cloned());
+                continue;
+            }
+            match BurstModel::for_profile(profile) {
+                Ok(model) => pacing.devices.push(PacedDevice {
+                    device: profile.device.clone(),
+                    directories: profile.directories.clone(),
+                    pacer: Pacer::new(model, budget),
+                }),
+                Err(why) => {
+                    pacing.unpaced.extend(profile.directories.iter().cloned());
+                    reasons.push((profile.device.clone(), why));
+                }
+            }
+        }
+        (pacing, reasons)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// The pacer of the device holding `path`, if it's paced
+    pub fn for_path(&self, path: &Path) -> Option<&Pacer> {
+        let paced = self.devices.iter().flat_map(|device| device.directories.iter().map(move |dir| (dir, Some(&device.pacer))));
+        let unpaced = self.unpaced.iter().map(|dir| (dir, None));
+        paced
+            .chain(unpaced)
+            .filter(|(dir, _)| path.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .and_then(|(_, pacer)| pacer)
+    }
+}
@@ -0,0 +1,95 @@
+//! Interned storage for discovered paths.
+//!
+//! Deep trees repeat the same long directory prefix for every file in them. Discovery
+//! stores each file as a (directory id, file name) pair against a shared table holding
+//! every directory path once, and the full path is only rebuilt when the file is warmed.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Index of a directory in a [`DirTable`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DirId(u32);
+
+/// Memory used by discovered paths, compared with storing every full path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathMemoryStats {
+    pub files: u64,
+    pub directories: u64,
+    /// Bytes the paths would take as full `PathBuf`s
+    pub full_path_bytes: u64,
+    /// Bytes actually held: each directory once plus every file name
+    pub interned_bytes: u64,
+}
+
+impl PathMemoryStats {
+    pub fn bytes_saved(&self) -> u64 {
+        self.full_path_bytes.saturating_sub(self.interned_bytes)
+    }
+
+    /// How many times smaller the interned form is (1.0 when nothing was saved)
+    pub fn ratio(&self) -> f64 {
+        if self.interned_bytes == 0 {
+            1.0
+        } else {
+            self.full_path_bytes as f64 / self.interned_bytes as f64
+        }
+    }
+}
+
+/// Append-only table of directory paths
+#[derive(Debug, Default)]
+pub struct DirTable {
+    dirs: Vec<Arc<Path>>,
+    ids: HashMap<Arc<Path>, DirId>,
+    /// Most recently interned directory; walkers yield siblings together
+    last: Option<(Arc<Path>, DirId)>,
+    stats: PathMemoryStats,
+}
+
+impl DirTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split `path` into an interned parent directory and its file name
+    pub fn intern(&mut self, path: &Path) -> (DirId, Box<OsStr>) {
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let name: Box<OsStr> = path.file_name().unwrap_or(path.as_os_str()).into();
+
+        let dir = match &self.last {
+            Some((last, id)) if **last == *parent => *id,
+            _ => {
+                let id = match self.ids.get(parent) {
+                    Some(id) => *id,
+                    None => {
+                        let id = DirId(self.dirs.len() as u32);
+                        let parent: Arc<Path> = Arc::from(parent);
+                        self.dirs.push(Arc::clone(&parent));
+                        self.ids.insert(Arc::clone(&parent), id);
+                        self.stats.directories += 1;
+                        self.stats.interned_bytes += parent.as_os_str().len() as u64;
+                        id
+                    }
+                };
+                self.last = Some((Arc::clone(&self.dirs[id.0 as usize]), id));
+                id
+            }
+        };
+
+        self.stats.files += 1;
+        self.stats.full_path_bytes += path.as_os_str().len() as u64;
+        self.stats.interned_bytes += name.len() as u64;
+        (dir, name)
+    }
+
+    pub fn dir(&self, id: DirId) -> &Path {
+        &self.dirs[id.0 as usize]
+    }
+
+    pub fn stats(&self) -> PathMemoryStats {
+        self.stats
+    }
+}
@@ -0,0 +1,39 @@
+//! Two-phase warming (`--two-phase`).
+//!
+//! Warming has two goals that pull against each other. Hydrating a restored volume means
+//! reading every block once, and keeping those pages would fill memory with data the
+//! application will never touch. But for the few files it reads first (a database's hot
+//! tables, a model's weights) being in the page cache is what counts, and a run that
+//! drops everything leaves them as cold to the application as the rest.
+//!
+//! With `--two-phase` the run proper is the hydrate phase: direct I/O where the strategy
+//! and filesystem allow it, each file's pages dropped otherwise, so the page cache is left
+//! alone. Then [`page_cache_options`] describes a second, much shorter run over just the
+//! files the `--hot-include` globs match, advising each into the page cache with
+//! `POSIX_FADV_WILLNEED`. Their blocks are already hydrated by then, so the kernel's
+//! readahead is served at the volume's full speed. A run interrupted or stopped at
+//! `--max-duration` during the first phase skips the second.
+
+use crate::filters::DiscoveryFilters;
+use crate::pipeline::PipelineOptions;
+use crate::warming::{DropCaches, FallbackPolicy, Strategy, WarmingOptions};
+
+/// The page-cache phase of a run whose hydrate phase ran with `hydrate`: the same
+/// targets and queue settings, restricted to the files `hot` lets through
+pub fn page_cache_options(hydrate: &PipelineOptions, hot: DiscoveryFilters) -> PipelineOptions {
+    PipelineOptions {
+        filters: hot,
+        warming: WarmingOptions {
+            strategy: Strategy::Fadvise,
+            fallback: FallbackPolicy::None,
+            drop_caches: DropCaches::None,
+            low_memory: hydrate.warming.low_memory,
+            file_timeout: hydrate.warming.file_timeout,
+            ..Default::default()
+        },
+        page_cache_only: Vec::new(),
+        // Nothing is read through the strategies, so there's no seeking to save
+        physical_order: false,
+        ..hydrate.clone()
+    }
+}
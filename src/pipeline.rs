@@ -0,0 +1,1215 @@
+use futures::stream::{self, StreamExt};
+use ignore::{ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkState};
+use indicatif::ProgressBar;
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::anonymize;
+use crate::checkpoint::Checkpoint;
+use crate::clock::Stopwatch;
+use crate::control::Control;
+use crate::deadline::Deadline;
+use crate::dedupe::{DedupeSummary, InodeSet};
+use crate::device_queue::{self, DeviceDepths, DeviceQueues};
+use crate::disk;
+use crate::events::{EventBus, WarmEvent};
+use crate::experiment::Experiment;
+use crate::fair::{self, FairShareOptions, Share, ShareSummary};
+use crate::fiemap;
+use crate::filters::DiscoveryFilters;
+use crate::hooks::{FileMetadata, MetadataHooks, MetadataReport};
+use crate::introspect::Introspection;
+use crate::open_files::OpenFiles;
+use crate::pace::Pacing;
+use crate::paths::{DirId, DirTable, PathMemoryStats};
+use crate::preflight;
+use crate::progress::ByteProgress;
+use crate::report::{ErrorSamples, Stage};
+use crate::shutdown::Shutdown;
+use crate::sources::FileSource;
+use crate::telemetry::{self, Instrument};
+use crate::stats::{FileOutcome, FileStatus, StatsCollector, StatsDimension, StatsSnapshot};
+use crate::vanished::{VanishedFiles, VanishedSummary};
+use crate::warming::fds::{self, FdBudget};
+use crate::warming::{ErrorCounts, SizeClass, StrategyRegistry, WarmingError, WarmingOptions};
+use crate::warnings::{self, Category};
+
+/// Concurrency cap applied by `--low-memory`
+pub const LOW_MEMORY_QUEUE_DEPTH: usize = 4;
+/// Files per batch in `--low-memory` mode
+pub const LOW_MEMORY_BATCH_SIZE: usize = 64;
+/// Discovered batches allowed to wait for warming in `--low-memory` mode before discovery blocks
+pub const LOW_MEMORY_PENDING_BATCHES: usize = 2;
+/// Default `--batch-bytes`: batches close before their files add up to more than this
+pub const DEFAULT_BATCH_BYTES: u64 = 1024 * 1024 * 1024;
+/// Default `--discovery-buffer`: files discovered ahead of warming before discovery waits
+pub const DEFAULT_DISCOVERY_BUFFER: usize = 100_000;
+/// Files sorted together by `physical_order`; a larger window is more sequential but
+/// holds back the first batch longer. In low-memory mode the window is one batch.
+pub const PHYSICAL_ORDER_WINDOW: usize = 65_536;
+
+/// Discovery and warming settings for a single run
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub directories: Vec<PathBuf>,
+    pub queue_depth: usize,
+    pub threads: Option<usize>,
+    pub follow_symlinks: bool,
+    pub respect_gitignore: bool,
+    pub max_depth: Option<usize>,
+    pub ignore_hidden: bool,
+    pub max_file_size: u64,
+    pub batch_size: usize,
+    /// Close each batch before its files add up to more than this many bytes, so that
+    /// one batch isn't a thousand tiny files and the next a thousand huge ones
+    /// (`--batch-bytes`); a file larger than this gets a batch of its own. Discovery then
+    /// stats every file it finds. 0 batches by `batch_size` alone.
+    pub batch_bytes: u64,
+    pub filters: DiscoveryFilters,
+    pub warming: WarmingOptions,
+    pub stats_by: Vec<StatsDimension>,
+    /// Apply backpressure to discovery instead of queueing every discovered path
+    pub low_memory: bool,
+    /// Directories on non-EBS disks, warmed into the page cache only (`--non-ebs pagecache`)
+    pub page_cache_only: Vec<PathBuf>,
+    /// Directories on network filesystems, warmed with [`disk::network_warming`]
+    pub network: Vec<PathBuf>,
+    /// Stop discovering and starting files after the first failure (`--fail-fast`)
+    pub fail_fast: bool,
+    /// How queue depth is shared between target directories or volumes
+    pub fair_share: FairShareOptions,
+    /// How long to wait before looking again for a file that vanished after discovery;
+    /// zero fails such files right away
+    pub vanished_grace: Duration,
+    /// Warm files in the order their data lies on disk (`--physical-order`): discovery
+    /// sorts each window of up to [`PHYSICAL_ORDER_WINDOW`] files by where their data
+    /// starts before batching them
+    pub physical_order: bool,
+    /// Discover each inode once: skip further hard links to a file and directories
+    /// already walked by another path, such as bind mounts (see [`crate::dedupe`])
+    pub dedupe_inodes: bool,
+    /// Stop starting new files this long after warming starts (`--max-duration`); files
+    /// already being read finish (see [`crate::deadline`])
+    pub max_duration: Option<Duration>,
+    /// Most discovered files each share queues for warming before its discovery threads
+    /// wait (`--discovery-buffer`), rounded up to whole batches; 0 for no limit. Each
+    /// discovery thread also holds the batch it is filling.
+    pub discovery_buffer: usize,
+    /// Files each device may have in flight, on top of `queue_depth`
+    /// (`--per-device-queue-depth`, see [`crate::device_queue`]); `None` for no limit
+    pub per_device_queue_depth: Option<DeviceDepths>,
+    /// Walk each root on one thread with directory entries sorted by name, so two runs
+    /// over identical trees queue the same files in the same order (`--deterministic`)
+    pub deterministic: bool,
+}
+
+/// Discovery → warming hand-off. Bounded by `discovery_buffer` so that on huge trees
+/// discovered paths can't pile up in memory while warming catches up.
+enum BatchSender {
+    Unbounded(mpsc::UnboundedSender<Vec<DiscoveredFile>>),
+    Bounded(mpsc::Sender<Vec<DiscoveredFile>>),
+}
+
+enum BatchReceiver {
+    Unbounded(mpsc::UnboundedReceiver<Vec<DiscoveredFile>>),
+    Bounded(mpsc::Receiver<Vec<DiscoveredFile>>),
+}
+
+fn batch_channel(options: &PipelineOptions) -> (BatchSender, BatchReceiver) {
+    match pending_batches(options) {
+        Some(capacity) => {
+            let (tx, rx) = mpsc::channel(capacity);
+            (BatchSender::Bounded(tx), BatchReceiver::Bounded(rx))
+        }
+        None => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (BatchSender::Unbounded(tx), BatchReceiver::Unbounded(rx))
+        }
+    }
+}
+
+/// Batches a share may have waiting for warming; `None` for no limit
+fn pending_batches(options: &PipelineOptions) -> Option<usize> {
+    let buffered = (options.discovery_buffer > 0).then(|| options.discovery_buffer.div_ceil(options.batch_size.max(1)));
+    match buffered {
+        Some(batches) if options.low_memory => Some(batches.clamp(1, LOW_MEMORY_PENDING_BATCHES)),
+        None if options.low_memory => Some(LOW_MEMORY_PENDING_BATCHES),
+        batches => batches,
+    }
+}
+
+impl BatchSender {
+    /// Queue a batch from a discovery thread, waiting while the channel is full. Returns
+    /// false once the receiver has gone away.
+    fn send_blocking(&self, batch: Vec<DiscoveredFile>) -> bool {
+        match self {
+            BatchSender::Unbounded(tx) => tx.send(batch).is_ok(),
+            BatchSender::Bounded(tx) => tx.blocking_send(batch).is_ok(),
+        }
+    }
+}
+
+impl BatchReceiver {
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Vec<DiscoveredFile>>> {
+        match self {
+            BatchReceiver::Unbounded(rx) => rx.poll_recv(cx),
+            BatchReceiver::Bounded(rx) => rx.poll_recv(cx),
+        }
+    }
+}
+
+/// A file found during discovery, with any metadata resolved for it. The path is
+/// stored as its interned parent directory plus file name; see [`crate::paths`].
+#[derive(Debug)]
+pub struct DiscoveredFile {
+    pub dir: DirId,
+    pub name: Box<OsStr>,
+    pub metadata: Option<Box<FileMetadata>>,
+    /// Inode at discovery (0 if unknown), to find the file again if it's renamed
+    pub ino: u64,
+    /// Size at discovery when batching by bytes, otherwise 0
+    pub size: u64,
+}
+
+impl DiscoveredFile {
+    pub fn path(&self, dirs: &DirTable) -> PathBuf {
+        dirs.dir(self.dir).join(&*self.name)
+    }
+}
+
+/// Progress bars updated while the pipeline runs. Hidden unless the caller supplies its own.
+#[derive(Debug, Clone)]
+pub struct PipelineProgress {
+    pub discovery: ProgressBar,
+    pub warming: ProgressBar,
+    /// Bytes finished, against a pre-scanned or estimated total
+    pub bytes: ByteProgress,
+}
+
+impl Default for PipelineProgress {
+    fn default() -> Self {
+        Self {
+            discovery: ProgressBar::hidden(),
+            warming: ProgressBar::hidden(),
+            bytes: ByteProgress::default(),
+        }
+    }
+}
+
+/// Everything a run needs besides its configuration: embedder hooks, progress
+/// reporting, event subscribers and (optionally) a custom warming backend registry
+#[derive(Debug, Clone, Default)]
+pub struct PipelineContext {
+    pub hooks: Arc<MetadataHooks>,
+    pub progress: PipelineProgress,
+    pub events: EventBus,
+    /// Backends to warm with; `None` uses the builtin registry
+    pub registry: Option<Arc<StrategyRegistry>>,
+    /// Journal of finished files; files it already lists are skipped and their
+    /// earlier outcomes included in the summary
+    pub checkpoint: Option<Arc<Checkpoint>>,
+    /// Stops discovery and cancels in-flight files when triggered
+    pub shutdown: Shutdown,
+    /// Per-stage counters and in-flight files, for `SIGQUIT` dumps
+    pub introspection: Introspection,
+    /// Splits files between strategies and records per-strategy results (`--ab-test`)
+    pub experiment: Option<Arc<Experiment>>,
+    /// Keeps the first errors of each category, for the JSON report
+    pub errors: Arc<ErrorSamples>,
+    /// Paces reads to each volume's burst and baseline rates (`--pace ebs-burst`)
+    pub pacing: Option<Arc<Pacing>>,
+    /// Files other processes had open at startup, warmed ahead of each share's walk
+    /// (`--prioritize-open-files`)
+    pub open_files: Option<Arc<OpenFiles>>,
+    /// Pause and throttle set through the status API (`--status-listen`)
+    pub control: Option<Control>,
+    /// Lists the files to warm in place of walking the target directories
+    /// (`--files-from`, `--s3-inventory`)
+    pub source: Option<Arc<dyn FileSource>>,
+}
+
+/// Totals for a completed run
+#[derive(Debug, Clone)]
+pub struct PipelineSummary {
+    pub files_discovered: u64,
+    pub files_processed: u64,
+    /// Size of the files warmed
+    pub bytes_warmed: u64,
+    /// Logical data the warms covered; below `bytes_warmed` where files were sampled,
+    /// read partially, or only advised
+    pub bytes_covered: u64,
+    /// Bytes requested from storage
+    pub bytes_read: u64,
+    /// Time spent warming, excluding pauses (see [`crate::clock`])
+    pub duration: Duration,
+    /// Time the process was stopped while warming
+    pub paused: Duration,
+    pub metadata: MetadataReport,
+    pub stats: StatsSnapshot,
+    pub path_memory: PathMemoryStats,
+    /// The run was stopped by a shutdown request; totals cover only finished files
+    pub interrupted: bool,
+    /// The run was stopped by `fail_fast` after a file failed
+    pub failed_fast: bool,
+    /// The run was stopped at `max_duration`; files not yet started were left cold
+    pub deadline_reached: bool,
+    /// Directory entries the walk couldn't read; files under them were never discovered
+    pub discovery_errors: u64,
+    /// Files the source listed that don't exist under the target directories
+    pub missing: u64,
+    /// What each fair-share group warmed, in target directory order
+    pub shares: Vec<ShareSummary>,
+    /// Files that vanished between discovery and warming, and where they were found
+    pub vanished: VanishedSummary,
+    /// Hard links and repeated directories left out by `dedupe_inodes`
+    pub duplicates: DedupeSummary,
+    /// Files that failed to stat or warm, per [`WarmingError`] cause, most frequent first
+    pub failures_by_cause: Vec<(&'static str, u64)>,
+    /// Files given up on after `--file-timeout` (or that timed out in the kernel), in the
+    /// order they timed out. Each one held a queue slot that long, so there are never many.
+    pub timed_out: Vec<PathBuf>,
+}
+
+/// Shared state of the discovery tasks, one per share
+struct Discovery {
+    options: Arc<PipelineOptions>,
+    hooks: Arc<MetadataHooks>,
+    dirs: Arc<RwLock<DirTable>>,
+    shutdown: Shutdown,
+    introspection: Introspection,
+    bytes: ByteProgress,
+    errors: Arc<ErrorSamples>,
+    failed_fast: Arc<AtomicBool>,
+    deadline: Deadline,
+    /// Inodes discovered so far by any share, with `dedupe_inodes`
+    inodes: Option<Arc<InodeSet>>,
+    /// Sent ahead of the walks, which then leave them out
+    open_files: Option<Arc<OpenFiles>>,
+    /// Lists the files in place of the walks
+    source: Option<Arc<dyn FileSource>>,
+    /// Discovery tasks still walking; the last one to finish reports discovery done
+    running: AtomicUsize,
+}
+
+impl Discovery {
+    /// Walk `roots` one after another into `tx`, each with the walker's parallel visitor
+    /// on `threads` threads (or, when `deterministic`, in sorted order on this thread),
+    /// after the open files under them; with a source, queue what it lists instead.
+    /// Blocks; run it off the runtime. Returns the files found, the directory entries
+    /// that couldn't be read and the listed files that don't exist.
+    fn walk(&self, roots: &[PathBuf], tx: BatchSender) -> (u64, u64, u64) {
+        // A physical-order window is split by bytes once it's sorted
+        let (window, window_bytes) = match (self.options.physical_order, self.options.low_memory) {
+            (true, false) => (PHYSICAL_ORDER_WINDOW.max(self.options.batch_size), 0),
+            _ => (self.options.batch_size, self.options.batch_bytes),
+        };
+        let totals = WalkTotals::default();
+        if !self.send_open_files(roots, &tx, &totals) {
+            totals.disconnected.store(true, Ordering::Relaxed);
+        }
+        if let Some(source) = &self.source {
+            if !totals.disconnected.load(Ordering::Relaxed) {
+                let mut visitor = Visitor { discovery: self, tx: &tx, window, window_bytes, totals: &totals, batch: Vec::new(), bytes: 0 };
+                self.list(source.as_ref(), roots, &mut visitor);
+            }
+        }
+        for path in roots.iter().filter(|_| self.source.is_none()) {
+            if self.stopped() || totals.disconnected.load(Ordering::Relaxed) {
+                break;
+            }
+            if !self.inodes.as_ref().is_none_or(|inodes| inodes.first_root(path)) {
+                debug!("Not walking {} again: it was reached through another target", anonymize::display(path));
+                continue;
+            }
+            debug!("Walking directory: {}", anonymize::display(path));
+            let mut visitors = VisitorBuilder { discovery: self, tx: &tx, window, window_bytes, totals: &totals };
+            if self.options.deterministic {
+                // The same visitor, fed one entry at a time; dropping it sends the last batch
+                let mut visitor = visitors.build();
+                for entry in deduplicating_walker(&self.options, path, self.inodes.clone()) {
+                    if let WalkState::Quit = visitor.visit(entry) {
+                        break;
+                    }
+                }
+            } else {
+                parallel_walker_deduplicating(&self.options, path, self.inodes.clone()).visit(&mut visitors);
+            }
+        }
+        self.finished();
+
+        let (file_count, errors) = (totals.files.load(Ordering::Relaxed), totals.errors.load(Ordering::Relaxed));
+        debug!("File discovery complete. {} files found.", file_count);
+        (file_count, errors, totals.missing.load(Ordering::Relaxed))
+    }
+
+    /// Queue the files `source` lists under `roots` that exist as regular files and pass
+    /// the discovery filters, batched by `visitor` as walked files are
+    fn list(&self, source: &dyn FileSource, roots: &[PathBuf], visitor: &mut Visitor<'_>) {
+        debug!("Listing {}", source);
+        let result = source.list(roots, &mut |path| {
+            if visitor.stopping() {
+                return false;
+            }
+            match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => {
+                    let skip = self.open_files.as_ref().is_some_and(|open| open.contains(&path))
+                        || !self.options.filters.matches_file(&path)
+                        || !self.inodes.as_ref().is_none_or(|inodes| first_listing(inodes, &metadata));
+                    skip || matches!(visitor.found(&path, inode(&metadata), metadata.len()), WalkState::Continue)
+                }
+                // Directories and special files aren't warmed
+                Ok(_) => true,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    debug!("Listed but not found: {}", anonymize::display(&path));
+                    visitor.totals.missing.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(e) => {
+                    visitor.totals.errors.fetch_add(1, Ordering::Relaxed);
+                    let category = if e.kind() == std::io::ErrorKind::PermissionDenied { Category::PermissionDenied } else { Category::Discovery };
+                    warnings::report(category, format_args!("Failed to stat {}: {}", anonymize::display(&path), e));
+                    self.errors.record(category, &path, &e, Stage::Discovery, None);
+                    true
+                }
+            }
+        });
+        if let Err(e) = result {
+            visitor.totals.errors.fetch_add(1, Ordering::Relaxed);
+            warnings::report(Category::Discovery, format_args!("Failed to read {}: {}", source, e));
+            self.errors.record(Category::Discovery, Path::new(""), &e, Stage::Discovery, None);
+        }
+    }
+
+    /// List the directories holding the open files under `roots`, then queue the files
+    /// themselves in the snapshot's order, most widely held first. Returns false once the
+    /// receiver has gone away.
+    fn send_open_files(&self, roots: &[PathBuf], tx: &BatchSender, totals: &WalkTotals) -> bool {
+        let Some(open_files) = &self.open_files else { return true };
+        let files: Vec<&Path> = open_files
+            .under(roots)
+            .map(|file| file.path.as_path())
+            .filter(|path| self.options.filters.matches_file(path))
+            .collect();
+        if files.is_empty() {
+            return true;
+        }
+        let mut listed = HashSet::new();
+        for dir in files.iter().filter_map(|path| path.parent()) {
+            if listed.insert(dir) {
+                if let Ok(entries) = std::fs::read_dir(dir) {
+                    entries.for_each(drop);
+                }
+            }
+        }
+        debug!("Warming {} open files first, from {} directories", files.len(), listed.len());
+        let files = files.into_iter().map(|path| {
+            let metadata = self.hooks.resolve(path).map(Box::new);
+            let (dir, name) = self.dirs.write().unwrap().intern(path);
+            DiscoveredFile { dir, name, metadata, ino: 0, size: self.size_for_batching(path) }
+        });
+        for batch in batches(files, self.options.batch_size, self.options.batch_bytes) {
+            if self.stopped() {
+                break;
+            }
+            totals.files.fetch_add(batch.len() as u64, Ordering::Relaxed);
+            self.introspection.enqueued(batch.len() as u64);
+            self.bytes.discovered(batch.len() as u64);
+            if !tx.send_blocking(batch) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether discovery should stop: shutdown, `--fail-fast` or `--max-duration`
+    fn stopped(&self) -> bool {
+        self.shutdown.is_triggered() || self.failed_fast.load(Ordering::Relaxed) || self.deadline.stops()
+    }
+
+    /// Queue discovered files for warming, as one batch or, with `physical_order`, as
+    /// batches in on-disk order. Returns false once the receiver has gone away.
+    fn send(&self, files: Vec<DiscoveredFile>, tx: &BatchSender) -> bool {
+        self.introspection.enqueued(files.len() as u64);
+        self.bytes.discovered(files.len() as u64);
+        if !self.options.physical_order {
+            return tx.send_blocking(prioritize(files));
+        }
+        for batch in physical_order(files, &self.dirs, self.options.batch_size, self.options.batch_bytes) {
+            if !tx.send_blocking(batch) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The size of a file found outside the walk, with `batch_bytes`
+    fn size_for_batching(&self, path: &Path) -> u64 {
+        if self.options.batch_bytes == 0 {
+            return 0;
+        }
+        std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+    }
+
+    fn finished(&self) {
+        if self.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.introspection.discovery_done();
+        }
+    }
+}
+
+/// Counts shared by the discovery threads of one walk
+#[derive(Default)]
+struct WalkTotals {
+    files: AtomicU64,
+    errors: AtomicU64,
+    /// Files the source listed that don't exist
+    missing: AtomicU64,
+    /// The warming side has gone away
+    disconnected: AtomicBool,
+}
+
+struct VisitorBuilder<'a> {
+    discovery: &'a Discovery,
+    tx: &'a BatchSender,
+    window: usize,
+    window_bytes: u64,
+    totals: &'a WalkTotals,
+}
+
+impl<'s> ParallelVisitorBuilder<'s> for VisitorBuilder<'s> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(Visitor {
+            discovery: self.discovery,
+            tx: self.tx,
+            window: self.window,
+            window_bytes: self.window_bytes,
+            totals: self.totals,
+            batch: Vec::new(),
+            bytes: 0,
+        })
+    }
+}
+
+/// One discovery thread: fills its own batch and sends it when full, so threads only
+/// meet at the directory table and the channel
+struct Visitor<'a> {
+    discovery: &'a Discovery,
+    tx: &'a BatchSender,
+    window: usize,
+    /// Byte budget of a batch (0 for none)
+    window_bytes: u64,
+    totals: &'a WalkTotals,
+    batch: Vec<DiscoveredFile>,
+    /// Bytes in `batch`
+    bytes: u64,
+}
+
+impl Visitor<'_> {
+    /// Whether discovery should stop: shutdown, `--fail-fast`, `--max-duration` or the
+    /// warming side gone away
+    fn stopping(&self) -> bool {
+        let discovery = self.discovery;
+        if discovery.shutdown.is_triggered() {
+            debug!("Shutdown requested, stopping file discovery");
+            return true;
+        }
+        if discovery.failed_fast.load(Ordering::Relaxed) {
+            debug!("A file failed under --fail-fast, stopping file discovery");
+            return true;
+        }
+        if discovery.deadline.stops() {
+            debug!("--max-duration reached, stopping file discovery");
+            return true;
+        }
+        self.totals.disconnected.load(Ordering::Relaxed)
+    }
+
+    /// Add a file of `size` bytes to this thread's batch, sending the batch once it's full
+    fn found(&mut self, path: &Path, ino: u64, size: u64) -> WalkState {
+        let discovery = self.discovery;
+        // A file that would take the batch over its byte budget starts the next one
+        if full_before(self.batch.len(), self.bytes, size, self.window_bytes) && !self.flush() {
+            return WalkState::Quit;
+        }
+        let metadata = discovery.hooks.resolve(path).map(Box::new);
+        let (dir, name) = discovery.dirs.write().unwrap().intern(path);
+        if self.batch.capacity() == 0 {
+            self.batch.reserve_exact(self.window);
+        }
+        self.batch.push(DiscoveredFile { dir, name, metadata, ino, size });
+        self.bytes += size;
+        self.totals.files.fetch_add(1, Ordering::Relaxed);
+
+        // Send batch when it reaches the configured size
+        if full(self.batch.len(), self.bytes, self.window, self.window_bytes) && !self.flush() {
+            return WalkState::Quit;
+        }
+        WalkState::Continue
+    }
+
+    /// Send the batch. Returns false once the receiver has gone away.
+    fn flush(&mut self) -> bool {
+        self.bytes = 0;
+        if self.discovery.send(std::mem::take(&mut self.batch), self.tx) {
+            return true;
+        }
+        debug!("Receiver dropped, stopping file discovery");
+        self.totals.disconnected.store(true, Ordering::Relaxed);
+        false
+    }
+}
+
+impl ParallelVisitor for Visitor<'_> {
+    fn visit(&mut self, result: Result<ignore::DirEntry, ignore::Error>) -> WalkState {
+        let discovery = self.discovery;
+        if self.stopping() {
+            return WalkState::Quit;
+        }
+        match result {
+            Ok(entry) => {
+                // Open files were sent ahead of the walk
+                if entry.file_type().is_some_and(|ft| ft.is_file()) && !discovery.open_files.as_ref().is_some_and(|open| open.contains(entry.path())) {
+                    let size = if discovery.options.batch_bytes > 0 { entry.metadata().map_or(0, |metadata| metadata.len()) } else { 0 };
+                    return self.found(entry.path(), entry.ino().unwrap_or(0), size);
+                }
+            }
+            // A followed link back to a directory being walked is just another repeat
+            Err(err) if is_loop(&err) && discovery.inodes.is_some() => {
+                debug!("Not following a symlink cycle: {}", err);
+                if let Some(inodes) = &discovery.inodes {
+                    inodes.repeated_directory();
+                }
+            }
+            Err(err) => {
+                self.totals.errors.fetch_add(1, Ordering::Relaxed);
+                let category = match err.io_error() {
+                    Some(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Category::PermissionDenied,
+                    _ => Category::Discovery,
+                };
+                warnings::report(category, format_args!("Failed to process directory entry: {}", err));
+                let path = preflight::error_path(&err).unwrap_or(Path::new("")).to_path_buf();
+                let error = match err.into_io_error() {
+                    Some(e) => e,
+                    None => std::io::Error::other("directory walk error"),
+                };
+                discovery.errors.record(category, &path, &error, Stage::Discovery, None);
+            }
+        }
+        WalkState::Continue
+    }
+}
+
+impl Drop for Visitor<'_> {
+    /// Send what's left of this thread's batch once the walk is done
+    fn drop(&mut self) {
+        if self.batch.is_empty() || self.discovery.stopped() || self.totals.disconnected.load(Ordering::Relaxed) {
+            return;
+        }
+        if !self.discovery.send(std::mem::take(&mut self.batch), self.tx) {
+            debug!("Receiver dropped during final batch send");
+            self.totals.disconnected.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Discover files under the configured directories and warm them with bounded concurrency.
+pub async fn run(options: Arc<PipelineOptions>, context: PipelineContext) -> PipelineSummary {
+    let PipelineContext { hooks, progress, events, registry, checkpoint, shutdown, introspection, experiment, errors, pacing, open_files, control, source } = context;
+
+    let dirs = Arc::new(RwLock::new(DirTable::new()));
+    // Set on the first failure under fail_fast; unlike a shutdown, files already in flight finish
+    let failed_fast = Arc::new(AtomicBool::new(false));
+    // Like fail_fast, a deadline only stops new files from starting
+    let deadline = Deadline::after(options.max_duration);
+
+    // One discovery task and batch channel per share, so every share has batches ready
+    // when a warming slot frees up
+    let shares: Vec<Arc<Share>> = fair::shares(&options.directories, &options.fair_share).into_iter().map(Arc::new).collect();
+    if shares.len() > 1 {
+        debug!("Sharing queue depth between {} groups of directories", shares.len());
+    }
+    let discovery = Arc::new(Discovery {
+        options: Arc::clone(&options),
+        hooks: Arc::clone(&hooks),
+        dirs: Arc::clone(&dirs),
+        shutdown: shutdown.clone(),
+        introspection: introspection.clone(),
+        bytes: progress.bytes.clone(),
+        errors: Arc::clone(&errors),
+        failed_fast: Arc::clone(&failed_fast),
+        deadline: deadline.clone(),
+        inodes: options.dedupe_inodes.then(|| Arc::new(InodeSet::with_max_depth(options.max_depth))),
+        open_files,
+        source,
+        running: AtomicUsize::new(shares.len()),
+    });
+    if shares.is_empty() {
+        introspection.discovery_done();
+    }
+    let mut receivers = Vec::with_capacity(shares.len());
+    let mut discovery_handles = Vec::with_capacity(shares.len());
+    for share in &shares {
+        let (tx, rx) = batch_channel(&options);
+        receivers.push(Some(rx));
+        let discovery = Arc::clone(&discovery);
+        let share = Arc::clone(share);
+        let span = telemetry::discovery_span(&share.name);
+        let walk = tokio::task::spawn_blocking(move || discovery.walk(&share.roots, tx));
+        discovery_handles.push(tokio::spawn(async move { walk.await.unwrap() }.instrument(span)));
+    }
+
+    let semaphore = Arc::new(Semaphore::new(options.queue_depth));
+    let device_queues = options.per_device_queue_depth.clone().map(|depths| Arc::new(DeviceQueues::new(depths)));
+    let stats = Arc::new(StatsCollector::new(&options.directories, &options.stats_by));
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.replay(&stats);
+        progress.bytes.resume(checkpoint.completed_files() as u64, checkpoint.completed_bytes());
+    }
+    let metadata_report = Arc::new(Mutex::new(MetadataReport::default()));
+    let page_cache_warming = Arc::new(disk::page_cache_warming(&options.warming));
+    let network_warming = Arc::new(disk::network_warming(&options.warming));
+    let vanished = Arc::new(VanishedFiles::new(options.vanished_grace));
+    let failures = Arc::new(ErrorCounts::default());
+    let timed_out = Arc::new(Mutex::new(Vec::new()));
+
+    debug!("Starting concurrent file warming");
+    let warming_start = Stopwatch::start();
+
+    // Process file batches as they're discovered using a stream with controlled concurrency.
+    // Each time a slot frees up the stream is polled again and takes the next batch from
+    // the most under-served share that has one ready.
+    let share_queue_depth = options.fair_share.queue_depth;
+    let batch_stream = stream::poll_fn(|cx| {
+        for index in fair::dispatch_order(&shares, share_queue_depth) {
+            let Some(rx) = &mut receivers[index] else { continue };
+            // A batch from a volume with no free slot would only hold a slot while it waits
+            if device_queues.as_ref().zip(shares[index].device).is_some_and(|(queues, dev)| queues.is_full(dev)) {
+                continue;
+            }
+            match rx.poll_recv(cx) {
+                Poll::Ready(Some(batch)) => {
+                    shares[index].batch_started();
+                    return Poll::Ready(Some((index, batch)));
+                }
+                Poll::Ready(None) => receivers[index] = None,
+                Poll::Pending => {}
+            }
+        }
+        // Shares held back by the per-share cap or a full device queue are polled again once
+        // one of their batches or files finishes
+        if receivers.iter().any(Option::is_some) {
+            Poll::Pending
+        } else {
+            Poll::Ready(None)
+        }
+    });
+
+    batch_stream
+        .for_each_concurrent(options.queue_depth, |(share_index, file_batch)| {
+            let semaphore = semaphore.clone();
+            let device_queues = device_queues.clone();
+            let warming_bar = progress.warming.clone();
+            let discovery_bar = progress.discovery.clone();
+            let byte_progress = progress.bytes.clone();
+            let stats = stats.clone();
+            let metadata_report = metadata_report.clone();
+            let events = events.clone();
+            let dirs = Arc::clone(&dirs);
+            let registry = registry.clone();
+            let checkpoint = checkpoint.clone();
+            let shutdown = shutdown.clone();
+            let introspection = introspection.clone();
+            let options = Arc::clone(&options);
+            let page_cache_warming = Arc::clone(&page_cache_warming);
+            let network_warming = Arc::clone(&network_warming);
+            let experiment = experiment.clone();
+            let failed_fast = Arc::clone(&failed_fast);
+            let deadline = deadline.clone();
+            let share = Arc::clone(&shares[share_index]);
+            let vanished = Arc::clone(&vanished);
+            let errors = Arc::clone(&errors);
+            let failures = Arc::clone(&failures);
+            let timed_out = Arc::clone(&timed_out);
+            let pacing = pacing.clone();
+            let control = control.clone();
+            let span = telemetry::batch_span(share_index, file_batch.len(), file_batch.iter().map(|file| file.size).sum());
+
+            async move {
+                let batch_start = Instant::now();
+                let batch_size = file_batch.len();
+                let batch_bytes: u64 = file_batch.iter().map(|file| file.size).sum();
+                introspection.dequeued(batch_size as u64);
+
+                // Acquire semaphore once per batch
+                let acquire_start = Instant::now();
+                let _permit = semaphore.acquire().await.unwrap();
+                introspection.permit_acquired(batch_size as u64);
+                let wait_time = acquire_start.elapsed();
+                if wait_time > Duration::from_millis(10) {
+                    debug!("High semaphore wait time: {:?} for batch of {} files", wait_time, batch_size);
+                }
+
+                // Process each file in the batch
+                let mut remaining = batch_size as u64;
+                'files: for file in file_batch {
+                    // Files skipped by the checkpoint or size limit never reach an await that
+                    // yields; spend the task's budget so a batch of them can't hold the worker
+                    tokio::task::consume_budget().await;
+                    if shutdown.is_triggered() || failed_fast.load(Ordering::Relaxed) || deadline.stops() {
+                        break;
+                    }
+                    let _processing = introspection.process();
+                    remaining -= 1;
+                    let task_start = Instant::now();
+                    let mut path = file.path(&dirs.read().unwrap());
+                    let metadata = file.metadata;
+                    discovery_bar.inc(1);
+
+                    if checkpoint.as_ref().is_some_and(|c| c.is_completed(&path)) {
+                        debug!("Already warmed per checkpoint: {}", anonymize::display(&path));
+                        warming_bar.inc(1);
+                        byte_progress.already_done();
+                        continue;
+                    }
+                    let record = |path: &PathBuf, outcome: FileOutcome| {
+                        stats.record(path, outcome);
+                        if let Some(checkpoint) = &checkpoint {
+                            checkpoint.record(path, &outcome);
+                        }
+                    };
+
+                    // Get file metadata, looking again for a file that vanished since discovery
+                    let mut chased = false;
+                    let stat = match tokio::fs::metadata(&path).await {
+                        Err(e) if vanished.applies(&e) => match vanished.relocate(&path, file.ino).await {
+                            Some((new_path, size)) => {
+                                path = new_path;
+                                chased = true;
+                                Ok(size)
+                            }
+                            None => Err(e),
+                        },
+                        stat => stat.map(|metadata| metadata.len()),
+                    };
+                    let mut file_size = match stat {
+                        Ok(size) => size,
+                        Err(e) => {
+                            warnings::report(Category::of(&e), format_args!("Failed to get metadata for {}: {}", anonymize::display(&path), e));
+                            errors.record(Category::of(&e), &path, &e, Stage::Metadata, None);
+                            let e = WarmingError::from(e);
+                            failures.record(&e);
+                            record(&path, FileOutcome { bytes: 0, bytes_covered: 0, bytes_read: 0, latency: Duration::ZERO, status: FileStatus::Failed });
+                            events.publish(|| WarmEvent::FileFinished {
+                                path: path.clone(),
+                                bytes: 0,
+                                latency: Duration::ZERO,
+                                status: FileStatus::Failed,
+                                method: None,
+                                bytes_read: 0,
+                                error: Some(e.to_string()),
+                                coverage: None,
+                            });
+                            if options.fail_fast && !failed_fast.swap(true, Ordering::Relaxed) {
+                                warn!("Stopping after the first failure ({}) because of --fail-fast", anonymize::display(&path));
+                            }
+                            warming_bar.inc(1);
+                            byte_progress.finished(0);
+                            continue;
+                        }
+                    };
+
+                    debug!("Processing {} file: {} ({} bytes)", SizeClass::of(file_size), anonymize::display(&path), file_size);
+
+                    if options.max_file_size > 0 && file_size > options.max_file_size {
+                        debug!("Skipping large file: {} (size: {} > max: {})", anonymize::display(&path), file_size, options.max_file_size);
+                        record(&path, FileOutcome { bytes: 0, bytes_covered: 0, bytes_read: 0, latency: Duration::ZERO, status: FileStatus::Skipped });
+                        events.publish(|| WarmEvent::FileFinished {
+                            path: path.clone(),
+                            bytes: file_size,
+                            latency: Duration::ZERO,
+                            status: FileStatus::Skipped,
+                            method: None,
+                            bytes_read: 0,
+                            error: None,
+                            coverage: None,
+                        });
+                        warming_bar.inc(1);
+                        byte_progress.finished(file_size);
+                        continue;
+                    }
+
+                    // Wait out a pause or throttle from the status API, also before the clock starts
+                    if let Some(control) = &control {
+                        tokio::select! {
+                            _ = control.wait_turn(file_size) => {}
+                            _ = shutdown.triggered() => {
+                                debug!("Cancelled warming {} while paused or throttled", anonymize::display(&path));
+                                break 'files;
+                            }
+                            _ = deadline.reached() => {
+                                debug!("--max-duration reached while {} was paused or throttled", anonymize::display(&path));
+                                break 'files;
+                            }
+                        }
+                    }
+
+                    // Wait for the volume's pacer before the clock starts, so latencies stay the read's own
+                    if let Some(pacer) = pacing.as_deref().and_then(|pacing| pacing.for_path(&path)) {
+                        tokio::select! {
+                            _ = pacer.pace(file_size) => {}
+                            _ = shutdown.triggered() => {
+                                debug!("Cancelled warming {} while paced", anonymize::display(&path));
+                                break 'files;
+                            }
+                            _ = deadline.reached() => {
+                                debug!("--max-duration reached while {} was paced", anonymize::display(&path));
+                                break 'files;
+                            }
+                        }
+                    }
+
+                    // Wait for a slot on the file's own device; the inode was just stat'ed, so
+                    // looking up its device again is served from cache
+                    let _device_slot = match device_queues.as_deref().zip(device_queue::device_of(&path)) {
+                        Some((queues, dev)) => tokio::select! {
+                            permit = queues.acquire(dev) => Some(permit),
+                            _ = shutdown.triggered() => {
+                                debug!("Cancelled warming {} while waiting for its device", anonymize::display(&path));
+                                break 'files;
+                            }
+                            _ = deadline.reached() => {
+                                debug!("--max-duration reached while {} waited for its device", anonymize::display(&path));
+                                break 'files;
+                            }
+                        },
+                        None => None,
+                    };
+
+                    // And for a descriptor to open it with, once --max-open-files are open
+                    let fd_budget = FdBudget::global();
+                    let mut fd = tokio::select! {
+                        permit = fd_budget.acquire() => permit,
+                        _ = shutdown.triggered() => {
+                            debug!("Cancelled warming {} while waiting for a file descriptor", anonymize::display(&path));
+                            break 'files;
+                        }
+                        _ = deadline.reached() => {
+                            debug!("--max-duration reached while {} waited for a file descriptor", anonymize::display(&path));
+                            break 'files;
+                        }
+                    };
+                    let mut fd_retries = 0;
+
+                    // Use the modular warming interface
+                    let registry = registry.as_deref().unwrap_or_else(|| StrategyRegistry::global());
+                    let warm_start = Stopwatch::start();
+                    let _in_flight = introspection.begin(&path, file_size);
+                    // Experiments only cover EBS-backed files; page-cache and network warming aren't what's being compared
+                    let page_cache_only = options.page_cache_only.iter().any(|root| path.starts_with(root));
+                    let network = options.network.iter().any(|root| path.starts_with(root));
+                    let arm = experiment.as_deref().filter(|_| !page_cache_only && !network).map(|experiment| (experiment, experiment.assign(&path)));
+                    let warming = match arm {
+                        Some((experiment, arm)) => experiment.options(arm),
+                        None if page_cache_only => &*page_cache_warming,
+                        None if network => &*network_warming,
+                        None => &options.warming,
+                    };
+                    // Cancelled files aren't recorded, so a resumed run warms them again
+                    let file_span = telemetry::file_span(&path, file_size);
+                    let warmed = loop {
+                        let result = tokio::select! {
+                            result = registry.warm(&path, file_size, warming).instrument(file_span.clone()) => result,
+                            _ = shutdown.triggered() => {
+                                debug!("Cancelled warming {}", anonymize::display(&path));
+                                break 'files;
+                            }
+                        };
+                        // Out of descriptors after all: wait for one under a smaller budget
+                        if let Err(e) = &result {
+                            if fd_retries < fds::EXHAUSTED_RETRIES && fds::is_exhaustion(e.io()) {
+                                fd_retries += 1;
+                                if let Some(limit) = fd_budget.exhausted(fd) {
+                                    warn!(
+                                        "Ran out of file descriptors opening {}; warming at most {} files at once from now on (raise `ulimit -n` or set --max-open-files)",
+                                        anonymize::display(&path),
+                                        limit
+                                    );
+                                }
+                                fd = tokio::select! {
+                                    permit = fd_budget.acquire() => permit,
+                                    _ = shutdown.triggered() => break 'files,
+                                };
+                                continue;
+                            }
+                        }
+                        // Vanished between the stat and the open
+                        if let Err(e) = &result {
+                            if !chased && vanished.applies(e.io()) {
+                                chased = true;
+                                if let Some((new_path, size)) = vanished.relocate(&path, file.ino).await {
+                                    (path, file_size) = (new_path, size);
+                                    continue;
+                                }
+                            }
+                        }
+                        break result;
+                    };
+                    let (status, method, bytes_read, coverage, error) = match warmed {
+                        Ok(result) => {
+                            debug!("File {} warming completed: method={}, success={}, duration={:?}, size={}",
+                                   anonymize::display(&path), result.method, result.success, result.duration, file_size);
+
+                            // Log performance warnings for slow operations
+                            if result.duration > Duration::from_millis(100) {
+                                warnings::report(
+                                    Category::SlowOperation,
+                                    format_args!("Slow warming operation: {} took {:?} for {} bytes", anonymize::display(&path), result.duration, file_size),
+                                );
+                            }
+                            (FileStatus::Warmed, Some(result.method), result.bytes_read, Some(result.coverage), None)
+                        }
+                        Err(e) => {
+                            warnings::report(Category::of(e.io()), format_args!("Failed to warm file {}: {}", anonymize::display(&path), e));
+                            let strategy = registry.plan(warming).first().map(|strategy| strategy.name());
+                            errors.record(Category::of(e.io()), &path, e.io(), Stage::Warm, strategy);
+                            failures.record(&e);
+                            if let WarmingError::Timeout(_) = e {
+                                timed_out.lock().unwrap().push(path.clone());
+                            }
+                            (FileStatus::Failed, None, 0, None, Some(e))
+                        }
+                    };
+
+                    file_span.record("status", status.name()).record("method", method.unwrap_or("none")).record("bytes_read", bytes_read);
+                    let latency = warm_start.active();
+                    if let Some((experiment, arm)) = arm {
+                        experiment.record(arm, file_size, latency, status == FileStatus::Warmed);
+                    }
+                    let bytes_covered = coverage.map_or(0, |coverage| coverage.bytes_covered(file_size, bytes_read));
+                    record(&path, FileOutcome { bytes: file_size, bytes_covered, bytes_read, latency, status });
+                    events.publish(|| WarmEvent::FileFinished {
+                        path: path.clone(),
+                        bytes: file_size,
+                        latency,
+                        status,
+                        method,
+                        bytes_read,
+                        error: error.as_ref().map(|e| e.to_string()),
+                        coverage,
+                    });
+                    if options.fail_fast && status == FileStatus::Failed && !failed_fast.swap(true, Ordering::Relaxed) {
+                        warn!("Stopping after the first failure ({}) because of --fail-fast", anonymize::display(&path));
+                    }
+                    if status == FileStatus::Warmed {
+                        share.file_warmed(file_size);
+                    }
+                    warming_bar.inc(1);
+                    byte_progress.finished(file_size);
+                    if let Some(metadata) = metadata {
+                        metadata_report.lock().unwrap().record(&metadata, file_size);
+                    }
+
+                    let total_task_time = task_start.elapsed();
+                    debug!("Total task time for {}: {:?}", anonymize::display(&path), total_task_time);
+                }
+
+                // Files left behind by a shutdown
+                introspection.abandoned(remaining);
+                share.batch_finished();
+                if let Some(checkpoint) = &checkpoint {
+                    checkpoint.flush();
+                }
+
+                let batch_duration = batch_start.elapsed();
+                if batch_bytes > 0 {
+                    debug!("Completed batch of {} files ({} bytes) in {:?}", batch_size, batch_bytes, batch_duration);
+                } else {
+                    debug!("Completed batch of {} files in {:?}", batch_size, batch_duration);
+                }
+            }
+            .instrument(span)
+        })
+        .await;
+
+    // Wait for discovery to complete and get final count
+    let (mut files_discovered, mut discovery_errors, mut missing) = (0, 0, 0);
+    for handle in discovery_handles {
+        let (files, errors, not_found) = handle.await.unwrap();
+        files_discovered += files;
+        discovery_errors += errors;
+        missing += not_found;
+    }
+    debug!("File warming phase complete");
+
+    let metadata = metadata_report.lock().unwrap().clone();
+    let stats = stats.snapshot();
+    let path_memory = dirs.read().unwrap().stats();
+    let timed_out = std::mem::take(&mut *timed_out.lock().unwrap());
+    PipelineSummary {
+        files_discovered,
+        files_processed: stats.totals.files,
+        bytes_warmed: stats.totals.bytes,
+        bytes_covered: stats.totals.bytes_covered,
+        bytes_read: stats.totals.bytes_read,
+        duration: warming_start.active(),
+        paused: warming_start.paused(),
+        metadata,
+        stats,
+        path_memory,
+        interrupted: shutdown.is_triggered(),
+        failed_fast: failed_fast.load(Ordering::Relaxed),
+        deadline_reached: deadline.cut_short(),
+        discovery_errors,
+        missing,
+        shares: shares.iter().map(|share| share.summary()).collect(),
+        vanished: vanished.summary(),
+        duplicates: discovery.inodes.as_ref().map(|inodes| inodes.summary()).unwrap_or_default(),
+        failures_by_cause: failures.snapshot(),
+        timed_out,
+    }
+}
+
+/// Whether a walk error is `--follow-symlinks` finding a link to one of its ancestors
+fn is_loop(err: &ignore::Error) -> bool {
+    match err {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. } | ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => is_loop(err),
+        _ => false,
+    }
+}
+
+/// Whether a listed file hasn't been discovered yet through another link to it
+#[cfg(unix)]
+fn first_listing(inodes: &InodeSet, metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    inodes.first_file(metadata.dev(), metadata.ino(), metadata.nlink())
+}
+
+#[cfg(not(unix))]
+fn first_listing(_inodes: &InodeSet, _metadata: &std::fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn inode(metadata: &std::fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &std::fs::Metadata) -> u64 {
+    0
+}
+
+/// Walk `root` with the run's traversal settings and discovery filters
+pub(crate) fn walker(options: &PipelineOptions, root: &Path) -> ignore::Walk {
+    deduplicating_walker(options, root, None)
+}
+
+/// [`walker`] walking with `threads` threads at once
+pub(crate) fn parallel_walker(options: &PipelineOptions, root: &Path, threads: usize) -> ignore::WalkParallel {
+    let mut walker_builder = walk_builder(options, root, None);
+    walker_builder.threads(threads);
+    walker_builder.build_parallel()
+}
+
+/// [`deduplicating_walker`] walking with the run's discovery threads
+fn parallel_walker_deduplicating(options: &PipelineOptions, root: &Path, inodes: Option<Arc<InodeSet>>) -> ignore::WalkParallel {
+    walk_builder(options, root, inodes).build_parallel()
+}
+
+/// [`walker`] that also leaves out entries already recorded in `inodes`
+fn deduplicating_walker(options: &PipelineOptions, root: &Path, inodes: Option<Arc<InodeSet>>) -> ignore::Walk {
+    walk_builder(options, root, inodes).build()
+}
+
+fn walk_builder(options: &PipelineOptions, root: &Path, inodes: Option<Arc<InodeSet>>) -> WalkBuilder {
+    let mut walker_builder = WalkBuilder::new(root);
+    walker_builder
+        .threads(options.threads.unwrap_or_else(num_cpus::get))
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth)
+        .git_ignore(!options.respect_gitignore)
+        .hidden(options.ignore_hidden);
+    if options.deterministic {
+        // Only the sequential walker sorts
+        walker_builder.sort_by_file_name(|a, b| a.cmp(b));
+    }
+    options.filters.apply(&mut walker_builder, root, inodes);
+    walker_builder
+}
+
+/// Sort `files` by priority and then by where their data starts on disk, and split
+/// them into batches. Files whose location can't be found keep their discovery order
+/// after the others of the same priority.
+fn physical_order(files: Vec<DiscoveredFile>, dirs: &RwLock<DirTable>, batch_size: usize, batch_bytes: u64) -> Vec<Vec<DiscoveredFile>> {
+    let paths: Vec<PathBuf> = {
+        let dirs = dirs.read().unwrap();
+        files.iter().map(|file| file.path(&dirs)).collect()
+    };
+    let mut located = 0usize;
+    let mut keyed: Vec<(u64, DiscoveredFile)> = paths
+        .iter()
+        .zip(files)
+        .map(|(path, file)| match fiemap::first_physical(path) {
+            Ok(Some(offset)) => {
+                located += 1;
+                (offset, file)
+            }
+            _ => (u64::MAX, file),
+        })
+        .collect();
+    keyed.sort_by_key(|(offset, file)| (std::cmp::Reverse(file.metadata.as_ref().map_or(0, |m| m.priority)), *offset));
+    debug!("Ordered {} files by physical offset ({} without a known location)", keyed.len(), keyed.len() - located);
+
+    batches(keyed.into_iter().map(|(_, file)| file), batch_size, batch_bytes)
+}
+
+/// Split `files` into batches of at most `batch_size` files and, with `batch_bytes`,
+/// at most that many bytes unless a single file is larger
+fn batches(files: impl IntoIterator<Item = DiscoveredFile>, batch_size: usize, batch_bytes: u64) -> Vec<Vec<DiscoveredFile>> {
+    let mut batches = Vec::new();
+    let (mut batch, mut bytes) = (Vec::new(), 0);
+    for file in files {
+        if full_before(batch.len(), bytes, file.size, batch_bytes) {
+            batches.push(std::mem::take(&mut batch));
+            bytes = 0;
+        }
+        bytes += file.size;
+        batch.push(file);
+        if full(batch.len(), bytes, batch_size, batch_bytes) {
+            batches.push(std::mem::take(&mut batch));
+            bytes = 0;
+        }
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Whether a batch of `files` files and `bytes` bytes must be sent before a file of
+/// `size` bytes would take it over `batch_bytes`
+fn full_before(files: usize, bytes: u64, size: u64, batch_bytes: u64) -> bool {
+    files > 0 && batch_bytes > 0 && bytes.saturating_add(size) > batch_bytes
+}
+
+/// Whether a batch of `files` files and `bytes` bytes is complete
+fn full(files: usize, bytes: u64, batch_size: usize, batch_bytes: u64) -> bool {
+    files >= batch_size.max(1) || (batch_bytes > 0 && bytes >= batch_bytes)
+}
+
+/// Order a batch by resolved priority (highest first), keeping discovery order for ties.
+fn prioritize(mut batch: Vec<DiscoveredFile>) -> Vec<DiscoveredFile> {
+    if batch.iter().any(|file| file.metadata.is_some()) {
+        batch.sort_by_key(|file| std::cmp::Reverse(file.metadata.as_ref().map_or(0, |m| m.priority)));
+    }
+    batch
+}
@@ -0,0 +1,213 @@
+//! Sharded work plans for a fleet of warmers (the `export-plan` subcommand).
+//!
+//! `--shards N --shard-index I` splits a run by hashing paths, which needs no
+//! coordination but balances file counts, not bytes: one shard can draw the few huge
+//! files and finish hours after the rest. When a fleet of instances each warms part of
+//! a volume (readers of a multi-attach io2 volume, or clones of one snapshot), it is
+//! better to plan the split once, up front, from the sizes the walk sees.
+//!
+//! [`collect`] walks the target directories as a warm run would and [`plan`] deals the
+//! files into N shards:
+//!
+//! - [`Balance::Bytes`] hands the largest remaining file to the lightest shard, which
+//!   evens out the bytes to within the size of the largest file.
+//! - [`Balance::Layout`] orders files by where their data starts on the device and cuts
+//!   that order into N runs of about equal bytes, so each instance reads one contiguous
+//!   stretch of the volume. On EBS that keeps each instance's reads within its own
+//!   range of snapshot blocks instead of every instance seeking across all of them.
+//!
+//! [`write`] stores each shard as a manifest `--files-from` reads, with a `plan.json`
+//! summarizing them.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::fiemap;
+use crate::pipeline::PipelineOptions;
+use crate::verify;
+
+/// How [`plan`] deals files into shards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Balance {
+    /// Largest file first onto the lightest shard: the most even bytes
+    Bytes,
+    /// Contiguous runs of the files' on-disk order, of about equal bytes
+    #[default]
+    Layout,
+}
+
+impl FromStr for Balance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bytes" => Ok(Balance::Bytes),
+            "layout" => Ok(Balance::Layout),
+            other => Err(format!("unknown balance '{}' (expected bytes or layout)", other)),
+        }
+    }
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Balance::Bytes => "bytes",
+            Balance::Layout => "layout",
+        })
+    }
+}
+
+/// A file the plan assigns to a shard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Offset on the block device where the file's data starts, if known
+    pub physical: Option<u64>,
+}
+
+/// The files one instance warms
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Shard {
+    pub files: Vec<PlannedFile>,
+    pub bytes: u64,
+}
+
+impl Shard {
+    fn push(&mut self, file: PlannedFile) {
+        self.bytes += file.size;
+        self.files.push(file);
+    }
+}
+
+/// The files a warm run with `options` would discover, with their physical offsets
+/// under [`Balance::Layout`]
+pub fn collect(options: &PipelineOptions, balance: Balance) -> Vec<PlannedFile> {
+    let mut files = Vec::new();
+    verify::for_each_candidate(options, |path, size| {
+        let physical = match balance {
+            Balance::Layout => fiemap::first_physical(path).ok().flatten(),
+            Balance::Bytes => None,
+        };
+        files.push(PlannedFile { path: path.to_path_buf(), size, physical });
+    });
+    files
+}
+
+/// Deal `files` into `shards` shards (at least one)
+pub fn plan(files: Vec<PlannedFile>, shards: usize, balance: Balance) -> Vec<Shard> {
+    let shards = shards.max(1);
+    match balance {
+        Balance::Bytes => by_bytes(files, shards),
+        Balance::Layout => by_layout(files, shards),
+    }
+}
+
+fn by_bytes(mut files: Vec<PlannedFile>, shards: usize) -> Vec<Shard> {
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    let mut planned = vec![Shard::default(); shards];
+    // Lightest shard first, the lowest index among equals
+    let mut lightest: BinaryHeap<Reverse<(u64, usize)>> = (0..shards).map(|i| Reverse((0, i))).collect();
+    for file in files {
+        let Reverse((bytes, i)) = lightest.pop().expect("at least one shard");
+        lightest.push(Reverse((bytes + file.size, i)));
+        planned[i].push(file);
+    }
+    for shard in &mut planned {
+        shard.files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    planned
+}
+
+fn by_layout(mut files: Vec<PlannedFile>, shards: usize) -> Vec<Shard> {
+    // Files whose data can't be located go last, in path order
+    files.sort_by(|a, b| match (a.physical, b.physical) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.path.cmp(&b.path)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.path.cmp(&b.path),
+    });
+    let total: u64 = files.iter().map(|file| file.size).sum();
+    let mut planned = vec![Shard::default(); shards];
+    let mut before = 0u64;
+    for file in files {
+        // The shard whose share of the bytes the file's midpoint falls in
+        let midpoint = before as u128 + file.size as u128 / 2;
+        let i = (midpoint * shards as u128 / total.max(1) as u128).min(shards as u128 - 1) as usize;
+        before += file.size;
+        planned[i].push(file);
+    }
+    planned
+}
+
+/// Manifest file name of shard `index` of `shards`
+pub fn manifest_name(index: usize, shards: usize) -> String {
+    format!("shard-{}-of-{}.txt", index, shards)
+}
+
+#[derive(Serialize)]
+struct PlanSummary<'a> {
+    balance: Balance,
+    directories: &'a [PathBuf],
+    files: usize,
+    bytes: u64,
+    shards: Vec<ShardSummary>,
+}
+
+#[derive(Serialize)]
+struct ShardSummary {
+    manifest: String,
+    files: usize,
+    bytes: u64,
+}
+
+/// Write a manifest per shard and `plan.json` to `output`, creating it if needed.
+/// With a single target directory, manifests list paths relative to it, so they can
+/// be used wherever an instance mounts the volume; with several, absolute paths.
+/// Returns the manifests written.
+pub fn write(output: &Path, planned: &[Shard], directories: &[PathBuf], balance: Balance) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(output).with_context(|| format!("failed to create {}", output.display()))?;
+    let root = match directories {
+        [root] => Some(root.as_path()),
+        _ => None,
+    };
+    let mut manifests = Vec::with_capacity(planned.len());
+    for (index, shard) in planned.iter().enumerate() {
+        let path = output.join(manifest_name(index, planned.len()));
+        let file = fs::File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "# Shard {} of {}: {} files, {} bytes, balanced by {}", index, planned.len(), shard.files.len(), shard.bytes, balance)?;
+        for file in &shard.files {
+            let listed = root.and_then(|root| file.path.strip_prefix(root).ok()).unwrap_or(&file.path);
+            writeln!(out, "{}", listed.display())?;
+        }
+        out.flush().with_context(|| format!("failed to write {}", path.display()))?;
+        manifests.push(path);
+    }
+
+    let summary = PlanSummary {
+        balance,
+        directories,
+        files: planned.iter().map(|shard| shard.files.len()).sum(),
+        bytes: planned.iter().map(|shard| shard.bytes).sum(),
+        shards: planned
+            .iter()
+            .enumerate()
+            .map(|(index, shard)| ShardSummary { manifest: manifest_name(index, planned.len()), files: shard.files.len(), bytes: shard.bytes })
+            .collect(),
+    };
+    let path = output.join("plan.json");
+    let mut json = serde_json::to_string_pretty(&summary)?;
+    json.push('\n');
+    fs::write(&path, json).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(manifests)
+}
@@ -0,0 +1,375 @@
+//! Preflight access check.
+//!
+//! A warmer running under an SELinux or AppArmor policy (a confined container, a
+//! systemd unit with a restrictive profile) can be denied files that its uid and the
+//! file modes would allow. Without a check those denials only show up as failures hours
+//! into a large warm. `--preflight` opens and reads a byte of a few files in every
+//! directory first (and notes directories that can't be listed), and summarizes which
+//! subtrees are unreadable and whether file permissions or the security policy is the
+//! likely cause.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::anonymize;
+use crate::pipeline::{self, PipelineOptions};
+
+/// Subtrees listed in the report
+const REPORT_SUBTREES: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct PreflightOptions {
+    /// Files tried in each directory
+    pub files_per_dir: usize,
+    /// Stop sampling after this many files
+    pub max_files: usize,
+    /// ... or after this long, so the check stays quick on huge trees
+    pub time_budget: Duration,
+}
+
+impl Default for PreflightOptions {
+    fn default() -> Self {
+        Self { files_per_dir: 2, max_files: 5000, time_budget: Duration::from_secs(10) }
+    }
+}
+
+/// The mandatory access control confining this process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityContext {
+    /// `selinux` or `apparmor`
+    pub lsm: &'static str,
+    /// SELinux context or AppArmor profile, e.g. `system_u:system_r:container_t:s0`
+    pub label: String,
+    /// Whether denials are enforced rather than only logged; `None` if unknown
+    pub enforcing: Option<bool>,
+}
+
+impl SecurityContext {
+    /// The security context of this process, if SELinux or AppArmor is active
+    pub fn current() -> Option<Self> {
+        let read = |path: &str| std::fs::read_to_string(path).ok().map(|text| text.trim_end_matches(['\0', '\n']).trim().to_string());
+        if Path::new("/sys/fs/selinux/enforce").exists() {
+            let label = read("/proc/self/attr/current")?;
+            let enforcing = read("/sys/fs/selinux/enforce").map(|value| value == "1");
+            return Some(Self { lsm: "selinux", label, enforcing });
+        }
+        if Path::new("/sys/module/apparmor").exists() {
+            let label = read("/proc/self/attr/apparmor/current").or_else(|| read("/proc/self/attr/current"))?;
+            return Some(Self::apparmor(&label));
+        }
+        None
+    }
+
+    /// An AppArmor label such as `docker-default (enforce)` or `unconfined`
+    pub fn apparmor(label: &str) -> Self {
+        let enforcing = if label == "unconfined" {
+            Some(false)
+        } else if label.ends_with("(enforce)") {
+            Some(true)
+        } else if label.ends_with("(complain)") {
+            Some(false)
+        } else {
+            None
+        };
+        Self { lsm: "apparmor", label: label.to_string(), enforcing }
+    }
+}
+
+impl fmt::Display for SecurityContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.enforcing {
+            Some(true) => ", enforcing",
+            Some(false) => ", not enforcing",
+            None => "",
+        };
+        write!(f, "{} {}{}", self.lsm, self.label, mode)
+    }
+}
+
+/// Why access was probably denied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    /// The file's owner, group and mode bits don't allow this process to read it
+    Permissions,
+    /// The mode bits allow it, so a security policy (SELinux, AppArmor) or an ACL denied it
+    SecurityPolicy,
+    /// The file couldn't even be looked at, or denials in a subtree differ
+    Unknown,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cause::Permissions => "file permissions",
+            Cause::SecurityPolicy => "security policy",
+            Cause::Unknown => "unknown or mixed causes",
+        })
+    }
+}
+
+/// Who this process is, for checking mode bits
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gids: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn current() -> Self {
+        let mut gids = vec![unsafe { libc::getegid() }];
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        if count > 0 {
+            let mut groups = vec![0 as libc::gid_t; count as usize];
+            let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+            groups.truncate(count.max(0) as usize);
+            gids.extend(groups);
+        }
+        Self { uid: unsafe { libc::geteuid() }, gids }
+    }
+
+    /// Whether owner/group/other mode bits grant read (and for directories, search)
+    /// access. Root bypasses them, as with CAP_DAC_OVERRIDE.
+    pub fn mode_allows(&self, mode: u32, owner: u32, group: u32, is_dir: bool) -> bool {
+        if self.uid == 0 {
+            return true;
+        }
+        let shift = if owner == self.uid {
+            6
+        } else if self.gids.contains(&group) {
+            3
+        } else {
+            0
+        };
+        let wanted = if is_dir { 0o5 } else { 0o4 };
+        (mode >> shift) & wanted == wanted
+    }
+
+    /// Likely cause of a denied access to `path`
+    pub fn cause(&self, path: &Path, is_dir: bool) -> Cause {
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if self.mode_allows(metadata.mode(), metadata.uid(), metadata.gid(), is_dir) => Cause::SecurityPolicy,
+            Ok(_) => Cause::Permissions,
+            Err(_) => Cause::Unknown,
+        }
+    }
+}
+
+/// A file or directory this process isn't allowed to read
+#[derive(Debug, Clone)]
+pub struct Denial {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub cause: Cause,
+    pub error: String,
+}
+
+/// Denials under one directory, with the first one in the directory itself as an example
+#[derive(Debug, Clone)]
+pub struct DeniedSubtree {
+    pub path: PathBuf,
+    /// Files tried under it
+    pub sampled: usize,
+    pub denied_files: usize,
+    pub denied_dirs: usize,
+    pub cause: Cause,
+    pub example: Denial,
+}
+
+#[derive(Debug, Clone)]
+pub struct PreflightReport {
+    pub security_context: Option<SecurityContext>,
+    pub files_sampled: usize,
+    pub dirs_seen: usize,
+    /// Sampling stopped at the file limit or time budget
+    pub truncated: bool,
+    pub duration: Duration,
+    /// Most denied first
+    pub subtrees: Vec<DeniedSubtree>,
+}
+
+impl PreflightReport {
+    pub fn is_clean(&self) -> bool {
+        self.subtrees.is_empty()
+    }
+
+    pub fn denied_files(&self) -> usize {
+        self.subtrees.iter().map(|subtree| subtree.denied_files).sum()
+    }
+
+    pub fn denied_dirs(&self) -> usize {
+        self.subtrees.iter().map(|subtree| subtree.denied_dirs).sum()
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Preflight: tried {} files in {} directories in {:.2?}{}",
+            self.files_sampled,
+            self.dirs_seen,
+            self.duration,
+            if self.truncated { " (stopped early; the rest of the tree wasn't checked)" } else { "" }
+        )?;
+        match &self.security_context {
+            Some(context) => writeln!(f, "  security context: {}", context)?,
+            None => writeln!(f, "  security context: no SELinux or AppArmor confinement detected")?,
+        }
+        if self.is_clean() {
+            return writeln!(f, "  every sampled file and directory is readable");
+        }
+        writeln!(f, "  {} files and {} directories unreadable:", self.denied_files(), self.denied_dirs())?;
+        for subtree in self.subtrees.iter().take(REPORT_SUBTREES) {
+            writeln!(
+                f,
+                "    {}: {} of {} sampled files, {} directories denied ({}), e.g. {}: {}",
+                anonymize::display(&subtree.path),
+                subtree.denied_files,
+                subtree.sampled,
+                subtree.denied_dirs,
+                subtree.cause,
+                anonymize::display(&subtree.example.path),
+                subtree.example.error
+            )?;
+        }
+        if self.subtrees.len() > REPORT_SUBTREES {
+            writeln!(f, "    ... and {} more", self.subtrees.len() - REPORT_SUBTREES)?;
+        }
+        Ok(())
+    }
+}
+
+/// Group denials by the directory they're in (a denied directory counts as its own
+/// subtree), then fold each group into the highest denied ancestor. `sampled` counts the
+/// files tried per directory.
+pub fn summarize(sampled: &BTreeMap<PathBuf, usize>, denials: Vec<Denial>) -> Vec<DeniedSubtree> {
+    let mut groups: BTreeMap<PathBuf, DeniedSubtree> = BTreeMap::new();
+    for denial in denials {
+        let dir = if denial.is_dir { denial.path.clone() } else { denial.path.parent().unwrap_or(&denial.path).to_path_buf() };
+        let subtree = groups.entry(dir.clone()).or_insert_with(|| DeniedSubtree {
+            sampled: sampled.get(&dir).copied().unwrap_or(0),
+            path: dir,
+            denied_files: 0,
+            denied_dirs: 0,
+            cause: denial.cause,
+            example: denial.clone(),
+        });
+        if denial.is_dir {
+            subtree.denied_dirs += 1;
+        } else {
+            subtree.denied_files += 1;
+        }
+        if subtree.cause != denial.cause {
+            subtree.cause = Cause::Unknown;
+        }
+    }
+
+    // BTreeMap order puts ancestors before their descendants
+    let mut subtrees: Vec<DeniedSubtree> = Vec::new();
+    for (path, group) in groups {
+        match subtrees.iter_mut().find(|subtree| path.starts_with(&subtree.path)) {
+            Some(ancestor) => {
+                ancestor.sampled += group.sampled;
+                ancestor.denied_files += group.denied_files;
+                ancestor.denied_dirs += group.denied_dirs;
+                if ancestor.cause != group.cause {
+                    ancestor.cause = Cause::Unknown;
+                }
+            }
+            None => subtrees.push(group),
+        }
+    }
+    subtrees.sort_by(|a, b| (b.denied_files + b.denied_dirs).cmp(&(a.denied_files + a.denied_dirs)).then_with(|| a.path.cmp(&b.path)));
+    subtrees
+}
+
+/// The path an `ignore` walk error is about
+pub(crate) fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => error_path(err),
+        _ => None,
+    }
+}
+
+fn is_denied(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::PermissionDenied
+}
+
+/// Open and read the first byte of `path`
+fn try_read(path: &Path) -> std::io::Result<()> {
+    let file = std::fs::File::open(path)?;
+    file.read_at(&mut [0u8; 1], 0)?;
+    Ok(())
+}
+
+fn sample(options: &PipelineOptions, preflight: &PreflightOptions) -> PreflightReport {
+    let start = Instant::now();
+    let credentials = Credentials::current();
+    let mut sampled: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    let mut denials = Vec::new();
+    let mut files_sampled = 0;
+    let mut truncated = false;
+
+    'walk: for root in &options.directories {
+        for result in pipeline::walker(options, root) {
+            if files_sampled >= preflight.max_files || start.elapsed() >= preflight.time_budget {
+                truncated = true;
+                break 'walk;
+            }
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(err) => {
+                    if let (Some(error), Some(path)) = (err.io_error(), error_path(&err)) {
+                        if is_denied(error) {
+                            let cause = credentials.cause(path, true);
+                            denials.push(Denial { path: path.to_path_buf(), is_dir: true, cause, error: error.to_string() });
+                        }
+                    }
+                    continue;
+                }
+            };
+            if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                sampled.entry(entry.path().to_path_buf()).or_insert(0);
+                continue;
+            }
+            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+                continue;
+            }
+            let dir = entry.path().parent().unwrap_or(root).to_path_buf();
+            let tried = sampled.entry(dir).or_insert(0);
+            if *tried >= preflight.files_per_dir {
+                continue;
+            }
+            *tried += 1;
+            files_sampled += 1;
+            if let Err(error) = try_read(entry.path()) {
+                debug!("Preflight couldn't read {}: {}", anonymize::display(entry.path()), error);
+                if is_denied(&error) {
+                    let cause = credentials.cause(entry.path(), false);
+                    denials.push(Denial { path: entry.path().to_path_buf(), is_dir: false, cause, error: error.to_string() });
+                }
+            }
+        }
+    }
+
+    PreflightReport {
+        security_context: SecurityContext::current(),
+        files_sampled,
+        dirs_seen: sampled.len(),
+        truncated,
+        duration: start.elapsed(),
+        subtrees: summarize(&sampled, denials),
+    }
+}
+
+/// Try reading a sample of the files the run would warm
+pub async fn preflight(options: Arc<PipelineOptions>, preflight: PreflightOptions) -> anyhow::Result<PreflightReport> {
+    Ok(tokio::task::spawn_blocking(move || sample(&options, &preflight)).await?)
+}
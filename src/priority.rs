@@ -0,0 +1,277 @@
+//! Kernel-level self-deprioritization (`--nice`, `--ionice-class`, `--cgroup-read-bps`).
+//!
+//! `--pace` and the control API's throttle keep the warmer's own demand down, but they
+//! can't see what else is queued on the device. These settings tell the kernel instead,
+//! so the production workload on the same volume wins whenever both want the disk:
+//!
+//! - [`set_nice`] lowers the CPU priority of the process, and [`set_io_priority`] sets
+//!   its I/O scheduling class. Both are applied before the runtime starts so every
+//!   worker and blocking-pool thread inherits them. The I/O class only matters under a
+//!   scheduler that honours it (BFQ, or mq-deadline's priority aging); `none`, the
+//!   default for NVMe devices and so for EBS on Nitro, ignores it, which
+//!   [`Disk::ignores_io_priority`] lets `main` warn about.
+//! - [`CgroupIoLimit`] writes a read limit for the target disks into the `io.max` of the
+//!   cgroup the warmer runs in (cgroup v2 only), and puts back what was there when it's
+//!   dropped or the process exits. The kernel enforces it whatever the read path, page cache misses and
+//!   readahead included.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, Once};
+
+use log::{debug, warn};
+
+/// Where the cgroup v2 hierarchy is mounted
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// I/O scheduling class, as `ionice -c` takes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    /// Served before anything else; needs CAP_SYS_ADMIN
+    Realtime,
+    /// The default class, with levels 0 (highest) to 7
+    BestEffort,
+    /// Only served when no other process wants the disk
+    Idle,
+}
+
+impl IoClass {
+    /// `IOPRIO_CLASS_*`
+    fn number(self) -> u16 {
+        match self {
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+}
+
+impl FromStr for IoClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "realtime" | "rt" | "1" => Ok(IoClass::Realtime),
+            "best-effort" | "be" | "2" => Ok(IoClass::BestEffort),
+            "idle" | "3" => Ok(IoClass::Idle),
+            other => Err(format!("unknown I/O class '{}' (expected idle, best-effort or realtime)", other)),
+        }
+    }
+}
+
+impl fmt::Display for IoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IoClass::Realtime => "realtime",
+            IoClass::BestEffort => "best-effort",
+            IoClass::Idle => "idle",
+        })
+    }
+}
+
+/// Highest (least favoured) level within the realtime and best-effort classes
+pub const MAX_IO_LEVEL: u8 = 7;
+
+/// The `ioprio` value for `class` at `level` (0 to 7; the idle class has no levels)
+pub fn ioprio_value(class: IoClass, level: u8) -> u16 {
+    let level = if class == IoClass::Idle { 0 } else { u16::from(level.min(MAX_IO_LEVEL)) };
+    class.number() << 13 | level
+}
+
+/// Set the nice value of the whole process (-20 to 19; going below the current value
+/// needs CAP_SYS_NICE)
+#[cfg(target_os = "linux")]
+pub fn set_nice(nice: i32) -> io::Result<()> {
+    // On Linux PRIO_PROCESS with pid 0 only covers the calling thread, so this must run
+    // before any other thread is started for them to inherit it
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--nice is only supported on Linux"))
+}
+
+/// Set the I/O scheduling class of the calling thread, inherited by threads it starts
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(class: IoClass, level: u8) -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    let value = libc::c_int::from(ioprio_value(class, level));
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_priority(_class: IoClass, _level: u8) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--ionice-class is only supported on Linux"))
+}
+
+/// The scheduler marked active in a `queue/scheduler` file, e.g. `none` in
+/// `[none] mq-deadline kyber`
+pub fn active_scheduler(text: &str) -> Option<&str> {
+    text.split_whitespace().find_map(|name| name.strip_prefix('[')?.strip_suffix(']'))
+}
+
+/// The whole disk behind a target directory, as cgroup `io.max` wants it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disk {
+    /// Kernel name, e.g. `nvme1n1`
+    pub name: String,
+    /// `MAJ:MIN` of the whole disk (io.max rejects partitions)
+    pub dev: String,
+    /// Active I/O scheduler
+    pub scheduler: Option<String>,
+}
+
+impl Disk {
+    /// Whether the disk's scheduler drops I/O priorities on the floor
+    pub fn ignores_io_priority(&self) -> bool {
+        self.scheduler.as_deref() == Some("none")
+    }
+
+    /// Describe the disk whose sysfs directory (`/sys/block/<name>`) is `dir`
+    pub fn from_sys(dir: &Path) -> Option<Disk> {
+        let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok().map(|s| s.trim().to_string());
+        Some(Disk {
+            name: dir.file_name()?.to_string_lossy().into_owned(),
+            dev: read("dev")?,
+            scheduler: read("queue/scheduler").as_deref().and_then(active_scheduler).map(str::to_string),
+        })
+    }
+}
+
+/// The disk holding `path`, looking through a partition to its disk
+#[cfg(target_os = "linux")]
+pub fn disk_of(path: &Path) -> Option<Disk> {
+    let sys = match crate::disk::sys_block_link(path) {
+        Ok(sys) => sys,
+        Err(why) => {
+            debug!("No disk for {}: {}", path.display(), why);
+            return None;
+        }
+    };
+    let mut dir = std::fs::canonicalize(sys).ok()?;
+    if dir.join("partition").exists() {
+        dir.pop();
+    }
+    Disk::from_sys(&dir)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn disk_of(_path: &Path) -> Option<Disk> {
+    None
+}
+
+/// Read limits for one disk in `io.max`; `None` leaves that limit off
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoMax {
+    pub read_bps: Option<u64>,
+    pub read_iops: Option<u64>,
+}
+
+impl IoMax {
+    pub fn is_empty(&self) -> bool {
+        self.read_bps.is_none() && self.read_iops.is_none()
+    }
+
+    /// The `io.max` line setting these limits on `dev`
+    pub fn line(&self, dev: &str) -> String {
+        let limit = |value: Option<u64>| value.map_or("max".to_string(), |value| value.to_string());
+        format!("{} rbps={} riops={}", dev, limit(self.read_bps), limit(self.read_iops))
+    }
+}
+
+/// The cgroup v2 path (relative to the hierarchy root) in `/proc/self/cgroup` text, from
+/// its `0::` line
+pub fn parse_cgroup_v2_path(text: &str) -> Option<&str> {
+    text.lines().find_map(|line| line.strip_prefix("0::")).map(str::trim)
+}
+
+/// Directory of the cgroup the process runs in, under `root`
+pub fn own_cgroup(root: &Path) -> io::Result<PathBuf> {
+    let text = std::fs::read_to_string("/proc/self/cgroup")?;
+    let path = parse_cgroup_v2_path(&text).ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "not on the cgroup v2 unified hierarchy"))?;
+    Ok(root.join(path.trim_start_matches('/')))
+}
+
+/// Read limits written to a cgroup's `io.max`, undone when dropped
+#[derive(Debug)]
+pub struct CgroupIoLimit {
+    file: PathBuf,
+    /// Lines putting each limited disk back as it was
+    restore: Vec<String>,
+}
+
+impl CgroupIoLimit {
+    /// Write `limit` for each of `devs` (`MAJ:MIN`) into the `io.max` of `cgroup`
+    pub fn apply(cgroup: &Path, devs: &[String], limit: IoMax) -> io::Result<CgroupIoLimit> {
+        let file = cgroup.join("io.max");
+        let before = match std::fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let why = if cgroup.join("cgroup.controllers").exists() && cgroup.parent().is_some_and(|parent| parent.join("cgroup.controllers").exists()) {
+                    "the io controller isn't enabled for it; add +io to its parent's cgroup.subtree_control"
+                } else {
+                    "it's the root cgroup or not a cgroup v2 directory; run the warmer in a cgroup of its own, e.g. with systemd-run --scope"
+                };
+                return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} has no io.max: {}", cgroup.display(), why)));
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut applied = CgroupIoLimit { file, restore: Vec::new() };
+        for dev in devs {
+            let previous = before
+                .lines()
+                .find(|line| line.split_whitespace().next() == Some(dev.as_str()))
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{} rbps=max wbps=max riops=max wiops=max", dev));
+            // io.max takes one disk per write
+            std::fs::write(&applied.file, limit.line(dev))?;
+            debug!("Set {}: {}", applied.file.display(), limit.line(dev));
+            applied.restore.push(previous);
+        }
+        Ok(applied)
+    }
+
+    /// The `io.max` file written to
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Keep the limits until the process exits, however it exits short of a fatal signal:
+    /// `main` leaves through `std::process::exit` in many places, which skips destructors
+    /// but runs `atexit` handlers
+    pub fn keep_until_exit(self) {
+        static REGISTER: Once = Once::new();
+        REGISTER.call_once(|| unsafe {
+            libc::atexit(restore_at_exit);
+        });
+        HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(self);
+    }
+}
+
+/// Limits handed to [`CgroupIoLimit::keep_until_exit`]
+static HELD: Mutex<Vec<CgroupIoLimit>> = Mutex::new(Vec::new());
+
+extern "C" fn restore_at_exit() {
+    let held = std::mem::take(&mut *HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    drop(held);
+}
+
+impl Drop for CgroupIoLimit {
+    fn drop(&mut self) {
+        for line in &self.restore {
+            if let Err(e) = std::fs::write(&self.file, line) {
+                warn!("Couldn't restore {} to '{}': {}", self.file.display(), line, e);
+            }
+        }
+    }
+}
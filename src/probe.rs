@@ -0,0 +1,190 @@
+//! Host capability probes, run once before warming starts.
+//!
+//! What a host allows often differs from what its kernel version suggests: container
+//! runtimes block io_uring and AIO with seccomp, `kernel.io_uring_disabled` switches
+//! io_uring off, sandboxes implement only some io_uring opcodes, and tmpfs, some FUSE
+//! and overlay filesystems refuse O_DIRECT. Finding that out per file would fail (and
+//! fall back) millions of times, so these probes are run once and their answers, with
+//! the reason for each "no", feed [`crate::support::negotiate`] and the startup banner.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+/// Directory entries looked at while searching a target for a file to test O_DIRECT on
+const SAMPLE_SEARCH_LIMIT: usize = 1000;
+
+/// The answer to one probe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
+pub enum Capability {
+    Available,
+    Unavailable(String),
+    /// The probe couldn't tell, e.g. there was no file to try
+    Unknown(String),
+}
+
+impl Capability {
+    pub fn is_available(&self) -> bool {
+        matches!(self, Capability::Available)
+    }
+
+    /// Why it isn't available, if it isn't
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Capability::Available => None,
+            Capability::Unavailable(reason) | Capability::Unknown(reason) => Some(reason),
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Available => f.write_str("✓"),
+            Capability::Unavailable(reason) => write!(f, "✗ ({})", reason),
+            Capability::Unknown(reason) => write!(f, "? ({})", reason),
+        }
+    }
+}
+
+/// Whether io_uring can be set up here and can do plain reads (cached after the first call)
+pub fn io_uring() -> &'static Capability {
+    static RESULT: OnceLock<Capability> = OnceLock::new();
+    RESULT.get_or_init(probe_io_uring)
+}
+
+/// Whether Linux AIO (io_setup(2)) is allowed here (cached after the first call)
+pub fn libaio() -> &'static Capability {
+    static RESULT: OnceLock<Capability> = OnceLock::new();
+    RESULT.get_or_init(probe_libaio)
+}
+
+#[cfg(target_os = "linux")]
+fn probe_io_uring() -> Capability {
+    use io_uring::{opcode, IoUring, Probe};
+
+    let ring = match IoUring::new(2) {
+        Ok(ring) => ring,
+        Err(e) => {
+            let reason = match e.raw_os_error() {
+                Some(libc::ENOSYS) => "kernel built without io_uring".to_string(),
+                Some(libc::EPERM) => match std::fs::read_to_string("/proc/sys/kernel/io_uring_disabled").map(|value| value.trim().to_string()) {
+                    Ok(value) if value != "0" => format!("disabled by kernel.io_uring_disabled={}", value),
+                    _ => "blocked by seccomp or container policy".to_string(),
+                },
+                _ => format!("io_uring_setup failed: {}", e),
+            };
+            return Capability::Unavailable(reason);
+        }
+    };
+    let mut probe = Probe::new();
+    if let Err(e) = ring.submitter().register_probe(&mut probe) {
+        // Opcode probing and IORING_OP_READ both arrived in 5.6
+        return Capability::Unavailable(format!("kernel too old for io_uring reads (opcode probe failed: {})", e));
+    }
+    if !probe.is_supported(opcode::Read::CODE) {
+        return Capability::Unavailable("IORING_OP_READ not supported, as in some sandboxes".to_string());
+    }
+    Capability::Available
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_io_uring() -> Capability {
+    Capability::Unavailable("io_uring is Linux-only".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn probe_libaio() -> Capability {
+    let mut ctx: libc::c_ulong = 0;
+    let result = unsafe { libc::syscall(libc::SYS_io_setup, 1 as libc::c_long, &mut ctx as *mut libc::c_ulong) };
+    if result < 0 {
+        let e = std::io::Error::last_os_error();
+        return Capability::Unavailable(match e.raw_os_error() {
+            Some(libc::ENOSYS) => "kernel built without AIO".to_string(),
+            Some(libc::EPERM) => "blocked by seccomp or container policy".to_string(),
+            Some(libc::EAGAIN) => "fs.aio-max-nr exhausted".to_string(),
+            _ => format!("io_setup failed: {}", e),
+        });
+    }
+    unsafe { libc::syscall(libc::SYS_io_destroy, ctx) };
+    Capability::Available
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_libaio() -> Capability {
+    Capability::Unavailable("Linux AIO is Linux-only".to_string())
+}
+
+/// Whether O_DIRECT works under one target directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectIoProbe {
+    pub root: PathBuf,
+    #[serde(flatten)]
+    pub capability: Capability,
+}
+
+/// Open a file under `root` with O_DIRECT and read its first block. Filesystems can
+/// refuse the open or only the read, and directories can't be opened with O_DIRECT even
+/// where files can, so a real file is needed: the first non-empty one found.
+pub fn direct_io(root: &Path) -> DirectIoProbe {
+    let capability = match sample_file(root) {
+        Some(file) => try_direct_read(&file),
+        None => Capability::Unknown("no readable file to test".to_string()),
+    };
+    DirectIoProbe { root: root.to_path_buf(), capability }
+}
+
+/// The first non-empty regular file under `root`, breadth first, looking at no more than
+/// [`SAMPLE_SEARCH_LIMIT`] entries
+fn sample_file(root: &Path) -> Option<PathBuf> {
+    if std::fs::metadata(root).is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0) {
+        return Some(root.to_path_buf());
+    }
+    let mut pending = std::collections::VecDeque::from([root.to_path_buf()]);
+    let mut seen = 0;
+    while let Some(dir) = pending.pop_front() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            seen += 1;
+            if seen > SAMPLE_SEARCH_LIMIT {
+                return None;
+            }
+            match entry.file_type() {
+                Ok(kind) if kind.is_file() && entry.metadata().is_ok_and(|metadata| metadata.len() > 0) => return Some(entry.path()),
+                Ok(kind) if kind.is_dir() => pending.push_back(entry.path()),
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn try_direct_read(path: &Path) -> Capability {
+    use std::os::unix::fs::{FileExt, OpenOptionsExt};
+
+    use crate::warming::buffers::{BufferPool, ALIGNMENT};
+
+    let file = match std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path) {
+        Ok(file) => file,
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return Capability::Unavailable("the filesystem refuses O_DIRECT".to_string()),
+        Err(e) => return Capability::Unknown(format!("couldn't open a file to test: {}", e)),
+    };
+    let mut buffer = match BufferPool::global().try_get(ALIGNMENT) {
+        Ok(Some(buffer)) => buffer,
+        _ => return Capability::Unknown("no buffer to test with".to_string()),
+    };
+    match file.read_at(buffer.as_mut_slice(), 0) {
+        Ok(_) => Capability::Available,
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Capability::Unavailable("O_DIRECT reads fail with EINVAL".to_string()),
+        Err(e) => Capability::Unknown(format!("test read failed: {}", e)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn try_direct_read(_path: &Path) -> Capability {
+    Capability::Unavailable("O_DIRECT is Linux-only here".to_string())
+}
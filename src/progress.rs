@@ -0,0 +1,289 @@
+//! Byte-based progress and ETA.
+//!
+//! For EBS hydration what matters is bytes, not files: a tree of a million tiny files
+//! and one of a few huge images can take the same time with wildly different file
+//! counts. [`ByteProgress`] drives a bar by bytes finished. Its length is either the
+//! exact total from a pre-scan (`--precompute-total`), or a rolling estimate: bytes
+//! finished so far plus every discovered-but-unfinished file at the mean size of the
+//! finished ones. A resumed run starts from the bytes its checkpoint already covers.
+//!
+//! Bars only make sense on a terminal; written to CloudWatch or a CI log they come out as
+//! escape-code garbage, or not at all. [`ProgressMode::Plain`] hides them and prints one
+//! status line per interval instead (see [`PlainProgress`]), and is what
+//! [`ProgressMode::detect`] picks when output isn't a terminal.
+
+use std::fmt;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use indicatif::{HumanBytes, HumanDuration, ProgressBar};
+use log::debug;
+
+use crate::clock::PauseClock;
+use crate::pipeline::{walker, PipelineOptions};
+
+/// How progress is shown (`--progress`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// Redrawn progress bars
+    Bar,
+    /// A status line every `--progress-interval`
+    Plain,
+    /// Nothing until the summary
+    None,
+}
+
+impl ProgressMode {
+    /// Bars when both stdout and stderr are terminals, plain lines otherwise
+    pub fn detect() -> Self {
+        if std::io::stdout().is_terminal() && std::io::stderr().is_terminal() {
+            ProgressMode::Bar
+        } else {
+            ProgressMode::Plain
+        }
+    }
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bar" => Ok(ProgressMode::Bar),
+            "plain" => Ok(ProgressMode::Plain),
+            "none" => Ok(ProgressMode::None),
+            other => Err(format!("unknown progress mode '{}' (expected bar, plain or none)", other)),
+        }
+    }
+}
+
+impl fmt::Display for ProgressMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProgressMode::Bar => "bar",
+            ProgressMode::Plain => "plain",
+            ProgressMode::None => "none",
+        })
+    }
+}
+
+/// Files and bytes found by a pre-scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanTotals {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Walk the configured directories with the run's filters and add up file sizes.
+/// Blocking; files that can't be stat'ed count as empty.
+pub fn scan_totals(options: &PipelineOptions) -> ScanTotals {
+    let mut totals = ScanTotals::default();
+    for root in &options.directories {
+        for entry in walker(options, root).flatten() {
+            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+                continue;
+            }
+            totals.files += 1;
+            totals.bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+        }
+    }
+    debug!("Pre-scan found {} files, {} bytes", totals.files, totals.bytes);
+    totals
+}
+
+/// Bytes-finished progress bar with a precomputed or estimated total. Cheap to clone;
+/// clones update the same bar.
+#[derive(Debug, Clone)]
+pub struct ByteProgress {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    bar: ProgressBar,
+    /// Exact total from a pre-scan; the estimate is used until one is set
+    exact: AtomicBool,
+    discovered: AtomicU64,
+    /// Files and bytes finished, including those finished by earlier attempts
+    done_files: AtomicU64,
+    done_bytes: AtomicU64,
+    /// Files finished by earlier attempts
+    prior_files: AtomicU64,
+    /// Discovered files that were skipped because an earlier attempt finished them
+    resumed: AtomicU64,
+    /// Pauses already accounted for; after a new one the bar's rate estimate restarts
+    pauses: AtomicU64,
+}
+
+impl Default for ByteProgress {
+    fn default() -> Self {
+        Self::new(ProgressBar::hidden())
+    }
+}
+
+impl ByteProgress {
+    pub fn new(bar: ProgressBar) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                bar,
+                exact: AtomicBool::new(false),
+                discovered: AtomicU64::new(0),
+                done_files: AtomicU64::new(0),
+                done_bytes: AtomicU64::new(0),
+                prior_files: AtomicU64::new(0),
+                resumed: AtomicU64::new(0),
+                pauses: AtomicU64::new(PauseClock::global().pauses()),
+            }),
+        }
+    }
+
+    /// Use `bytes` as the bar's length from now on instead of the estimate
+    pub fn set_total(&self, bytes: u64) {
+        self.inner.exact.store(true, Ordering::Relaxed);
+        self.inner.bar.set_length(bytes);
+    }
+
+    /// Start from what earlier attempts finished, as loaded from a checkpoint
+    pub fn resume(&self, files: u64, bytes: u64) {
+        self.inner.prior_files.fetch_add(files, Ordering::Relaxed);
+        self.inner.done_files.fetch_add(files, Ordering::Relaxed);
+        self.inner.done_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.refresh();
+    }
+
+    pub fn discovered(&self, files: u64) {
+        self.inner.discovered.fetch_add(files, Ordering::Relaxed);
+        self.refresh();
+    }
+
+    /// A file finished (warmed, failed or skipped) with `bytes` accounted for
+    pub fn finished(&self, bytes: u64) {
+        self.inner.done_files.fetch_add(1, Ordering::Relaxed);
+        self.inner.done_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.refresh();
+    }
+
+    /// A discovered file already counted by [`ByteProgress::resume`]
+    pub fn already_done(&self) {
+        self.inner.resumed.fetch_add(1, Ordering::Relaxed);
+        self.refresh();
+    }
+
+    /// Whether the length is an exact pre-scanned total rather than an estimate
+    pub fn is_exact(&self) -> bool {
+        self.inner.exact.load(Ordering::Relaxed)
+    }
+
+    pub fn done_bytes(&self) -> u64 {
+        self.inner.done_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Files finished, including those finished by earlier attempts
+    pub fn done_files(&self) -> u64 {
+        self.inner.done_files.load(Ordering::Relaxed)
+    }
+
+    /// The bar's length: the pre-scanned total if there is one, else the estimate
+    pub fn total(&self) -> u64 {
+        if self.is_exact() {
+            self.inner.bar.length().unwrap_or(0)
+        } else {
+            self.estimated_total()
+        }
+    }
+
+    /// Total bytes the run is expected to cover: finished bytes plus the files still
+    /// pending at the mean size of the finished ones
+    pub fn estimated_total(&self) -> u64 {
+        let inner = &self.inner;
+        let (done_files, done_bytes) = (inner.done_files.load(Ordering::Relaxed), inner.done_bytes.load(Ordering::Relaxed));
+        if done_files == 0 {
+            return done_bytes;
+        }
+        // Discovered files not yet finished by this run or an earlier one
+        let finished = done_files - inner.prior_files.load(Ordering::Relaxed);
+        let pending = inner.discovered.load(Ordering::Relaxed).saturating_sub(finished + inner.resumed.load(Ordering::Relaxed));
+        done_bytes + pending * (done_bytes / done_files)
+    }
+
+    fn refresh(&self) {
+        let inner = &self.inner;
+        // The rate across a pause says nothing about the rate after it
+        let pauses = PauseClock::global().pauses();
+        if inner.pauses.swap(pauses, Ordering::Relaxed) != pauses {
+            inner.bar.reset_eta();
+        }
+        if !inner.exact.load(Ordering::Relaxed) {
+            inner.bar.set_length(self.estimated_total());
+        }
+        inner.bar.set_position(self.done_bytes());
+    }
+}
+
+/// The status lines of `--progress plain`. The rate is measured over the time since the
+/// previous line, so it follows the run as it speeds up and slows down; a pause starts
+/// it over.
+#[derive(Debug, Default)]
+pub struct PlainProgress {
+    /// Bytes done when the previous line was made, and when that was
+    last: Option<(Instant, u64)>,
+    pauses: u64,
+}
+
+impl PlainProgress {
+    pub fn new() -> Self {
+        Self { last: None, pauses: PauseClock::global().pauses() }
+    }
+
+    /// The line for `progress` at `now`
+    pub fn line(&mut self, progress: &ByteProgress, now: Instant) -> String {
+        let done = progress.done_bytes();
+        let pauses = PauseClock::global().pauses();
+        let rate = match self.last {
+            Some((then, before)) if pauses == self.pauses && now > then => Some(done.saturating_sub(before) as f64 / (now - then).as_secs_f64()),
+            _ => None,
+        };
+        (self.last, self.pauses) = (Some((now, done)), pauses);
+        plain_line(progress.done_files(), done, progress.total(), progress.is_exact(), rate)
+    }
+}
+
+/// One `--progress plain` line: files and bytes finished, the share of `total` (`~` when
+/// it's an estimate), the rate in bytes per second and the ETA at that rate
+pub fn plain_line(files: u64, done: u64, total: u64, exact: bool, rate: Option<f64>) -> String {
+    let mut line = format!("Progress: {} files, {}", files, HumanBytes(done));
+    if total > 0 {
+        line += &format!(
+            " of {}{} ({}%)",
+            if exact { "" } else { "~" },
+            HumanBytes(total),
+            (done.saturating_mul(100) / total).min(100)
+        );
+    }
+    match rate {
+        Some(rate) if rate >= 1.0 => {
+            line += &format!(", {}/s", HumanBytes(rate as u64));
+            if total > done {
+                line += &format!(", ETA {}", HumanDuration(Duration::from_secs_f64((total - done) as f64 / rate)));
+            }
+        }
+        Some(_) => line += ", stalled",
+        None => {}
+    }
+    line
+}
+
+/// Print a plain progress line every `interval` until the returned task is aborted
+pub fn spawn_plain(progress: ByteProgress, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut plain = PlainProgress::new();
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            ticker.tick().await;
+            println!("{}", plain.line(&progress, Instant::now()));
+        }
+    })
+}
@@ -0,0 +1,90 @@
+//! Plain progress file for shell wrappers.
+//!
+//! `dialog --gauge`, whiptail and deploy scripts want a number they can `cut`, not JSON
+//! or Prometheus text. With `--progress-file` the warmer rewrites a one-line file every
+//! second, and once more when the run ends:
+//!
+//! ```text
+//! <percent> <bytes done> <bytes total> <state>
+//! ```
+//!
+//! Fields are separated by single spaces and the line ends in `\n`. `percent` is a whole
+//! number from 0 to 100; it stays below 100 until the run is over, even when the
+//! estimated total is briefly overtaken. `bytes total` is exact with
+//! `--precompute-total` and a rolling estimate otherwise (see [`ByteProgress`]). `state`
+//! is `running`, `done` or `interrupted`. The file is written to a temporary name and
+//! renamed into place, so readers always see a complete line.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::warn;
+use tokio::sync::mpsc;
+
+use crate::events::WarmEvent;
+use crate::progress::ByteProgress;
+use crate::shutdown::Shutdown;
+
+/// How often the file is rewritten while the run is going
+pub const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a run stands, as the last field of the progress line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Done,
+    Interrupted,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::Running => "running",
+            State::Done => "done",
+            State::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// The progress line for `done` of `total` bytes
+pub fn render(done: u64, total: u64, state: State) -> String {
+    let percent = match state {
+        State::Done => 100,
+        _ if total == 0 => 0,
+        _ => (done.saturating_mul(100) / total).min(99),
+    };
+    format!("{} {} {} {}\n", percent, done, total.max(done), state.as_str())
+}
+
+/// Replace `path` with `contents` atomically, via a temporary file next to it
+pub fn write_atomically(path: &Path, contents: &str) -> Result<(), std::io::Error> {
+    let name = path.file_name().map_or_else(|| "progress".into(), |name| name.to_string_lossy());
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// Rewrite `path` every [`INTERVAL`] from `progress` until the event stream ends, then a
+/// final time as `done`, or `interrupted` if `shutdown` was triggered.
+pub async fn run_writer(path: PathBuf, progress: ByteProgress, shutdown: Shutdown, mut events: mpsc::UnboundedReceiver<WarmEvent>) {
+    let mut ticker = tokio::time::interval(INTERVAL);
+    // The events themselves aren't needed, only the end of the stream
+    loop {
+        tokio::select! {
+            event = events.recv() => if event.is_none() {
+                break;
+            },
+            _ = ticker.tick() => write(&path, &progress, State::Running),
+        }
+    }
+    write(&path, &progress, if shutdown.is_triggered() { State::Interrupted } else { State::Done });
+}
+
+fn write(path: &Path, progress: &ByteProgress, state: State) {
+    let contents = render(progress.done_bytes(), progress.total(), state);
+    if let Err(e) = write_atomically(path, &contents) {
+        warn!("Failed to write {}: {}", path.display(), e);
+    }
+}
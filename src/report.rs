@@ -0,0 +1,194 @@
+//! JSON run report (`--json-report`).
+//!
+//! A single JSON document written when the run ends: the totals from the summary, the
+//! per-category warning counts, and for each category the first few errors as
+//! structured samples (path, errno, stage, strategy, time), so automation that files
+//! tickets for failed runs has something actionable to put in them. Field names are
+//! stable; new fields may be added, existing ones are never renamed or removed without
+//! bumping [`REPORT_VERSION`].
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::anonymize;
+use crate::pipeline::PipelineSummary;
+use crate::support::SupportMatrix;
+use crate::warnings::{Category, WarningCount};
+
+/// Version of the report's schema
+pub const REPORT_VERSION: u32 = 1;
+
+/// Error samples kept per category unless configured otherwise
+pub const DEFAULT_ERROR_SAMPLES: usize = 5;
+
+/// Where in the run an error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Walking the directories
+    Discovery,
+    /// Stat'ing a discovered file
+    Metadata,
+    /// Reading the file
+    Warm,
+}
+
+/// One error, as recorded in the report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorSample {
+    pub path: String,
+    /// OS error number, if the error came from a system call
+    pub errno: Option<i32>,
+    pub error: String,
+    pub stage: Stage,
+    /// The strategy the file was offered to first, for warm errors
+    pub strategy: Option<&'static str>,
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+}
+
+/// Keeps the first errors of each category for the report
+#[derive(Debug)]
+pub struct ErrorSamples {
+    limit: usize,
+    samples: Mutex<BTreeMap<Category, Vec<ErrorSample>>>,
+}
+
+impl Default for ErrorSamples {
+    fn default() -> Self {
+        Self::new(DEFAULT_ERROR_SAMPLES)
+    }
+}
+
+impl ErrorSamples {
+    /// Keep up to `limit` samples per category; 0 keeps none
+    pub fn new(limit: usize) -> Self {
+        Self { limit, samples: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn record(&self, category: Category, path: &Path, error: &std::io::Error, stage: Stage, strategy: Option<&'static str>) {
+        if self.limit == 0 {
+            return;
+        }
+        let mut samples = self.samples.lock().unwrap();
+        let samples = samples.entry(category).or_default();
+        if samples.len() >= self.limit {
+            return;
+        }
+        samples.push(ErrorSample {
+            path: anonymize::display(path).to_string(),
+            errno: error.raw_os_error(),
+            error: error.to_string(),
+            stage,
+            strategy,
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+        });
+    }
+
+    /// Samples so far, keyed by category name
+    pub fn snapshot(&self) -> BTreeMap<&'static str, Vec<ErrorSample>> {
+        self.samples
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(category, samples)| (category.name(), samples.clone()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WarningRecord {
+    pub category: &'static str,
+    pub total: u64,
+    pub suppressed: u64,
+}
+
+/// The report document
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub version: u32,
+    /// `complete`, `interrupted`, `failed_fast` or `deadline_reached`
+    pub status: &'static str,
+    pub directories: Vec<String>,
+    /// Target directories given through a symlink → the directory warmed in their place
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub symlinked_targets: BTreeMap<String, String>,
+    pub files_discovered: u64,
+    pub files_processed: u64,
+    pub files_failed: u64,
+    pub files_skipped: u64,
+    pub bytes_warmed: u64,
+    /// Logical data covered: short of `bytes_warmed` for sampled, partial and advised files
+    pub bytes_covered: u64,
+    /// Bytes requested from storage
+    pub bytes_read: u64,
+    pub discovery_errors: u64,
+    /// Hard links to already-discovered files that weren't warmed again
+    pub hard_links_skipped: u64,
+    /// Directories reached by a second path, e.g. a bind mount, that weren't walked again
+    pub duplicate_dirs_skipped: u64,
+    /// Running time, excluding time the process was stopped or frozen
+    pub duration_ms: u64,
+    pub paused_ms: u64,
+    pub warnings: Vec<WarningRecord>,
+    /// Failure cause (see [`crate::warming::WarmingError`]) → files that failed with it
+    pub failures_by_cause: BTreeMap<&'static str, u64>,
+    /// Files given up on after `--file-timeout`
+    pub timed_out_files: Vec<String>,
+    /// Category name (as in `warnings`) → the first errors of that category
+    pub error_samples: BTreeMap<&'static str, Vec<ErrorSample>>,
+    /// What was requested and what this host and build provided in its place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub support: Option<SupportMatrix>,
+}
+
+impl RunReport {
+    pub fn new(summary: &PipelineSummary, directories: &[PathBuf], warnings: &[WarningCount], errors: &ErrorSamples) -> Self {
+        let totals = &summary.stats.totals;
+        Self {
+            version: REPORT_VERSION,
+            status: if summary.interrupted {
+                "interrupted"
+            } else if summary.failed_fast {
+                "failed_fast"
+            } else if summary.deadline_reached {
+                "deadline_reached"
+            } else {
+                "complete"
+            },
+            directories: directories.iter().map(|dir| anonymize::display(dir).to_string()).collect(),
+            symlinked_targets: BTreeMap::new(),
+            files_discovered: summary.files_discovered,
+            files_processed: summary.files_processed,
+            files_failed: totals.failed,
+            files_skipped: totals.skipped,
+            bytes_warmed: summary.bytes_warmed,
+            bytes_covered: summary.bytes_covered,
+            bytes_read: summary.bytes_read,
+            discovery_errors: summary.discovery_errors,
+            hard_links_skipped: summary.duplicates.files,
+            duplicate_dirs_skipped: summary.duplicates.directories,
+            duration_ms: summary.duration.as_millis() as u64,
+            paused_ms: summary.paused.as_millis() as u64,
+            warnings: warnings
+                .iter()
+                .map(|count| WarningRecord { category: count.category.name(), total: count.total, suppressed: count.suppressed })
+                .collect(),
+            failures_by_cause: summary.failures_by_cause.iter().copied().collect(),
+            timed_out_files: summary.timed_out.iter().map(|path| anonymize::display(path).to_string()).collect(),
+            error_samples: errors.snapshot(),
+            support: None,
+        }
+    }
+
+    /// Write the report to `path` as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> Result<(), std::io::Error> {
+        let mut json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        json.push('\n');
+        std::fs::write(path, json)
+    }
+}
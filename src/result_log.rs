@@ -0,0 +1,173 @@
+//! Per-file result log (`--result-log`).
+//!
+//! One record per processed file (path, size, outcome, the strategy that warmed it,
+//! how long it took, the bytes actually read and how much of the file that covered,
+//! and any error), streamed to a file as the
+//! run proceeds, for auditing which files were touched and for offline analysis. The
+//! log is NDJSON, or CSV when its name ends in `.csv`; either may be compressed by
+//! adding `.gz` or `.zst` (see [`crate::logfile`]).
+
+use std::path::Path;
+use std::time::Duration;
+
+use log::warn;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::anonymize;
+use crate::events::WarmEvent;
+use crate::logfile::{Compression, LogWriter};
+use crate::stats::FileStatus;
+use crate::warming::Coverage;
+
+/// How often buffered records are flushed to the file
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const CSV_HEADER: &str = "path,size,status,strategy,duration_us,bytes_read,coverage,error";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultFormat {
+    Ndjson,
+    Csv,
+}
+
+impl ResultFormat {
+    /// CSV for `*.csv` (or `*.csv.gz`, ...), NDJSON otherwise
+    pub fn for_path(path: &Path) -> Self {
+        let name = match Compression::for_path(path) {
+            Compression::None => path.as_os_str(),
+            _ => path.file_stem().unwrap_or_default(),
+        };
+        match Path::new(name).extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ResultFormat::Csv,
+            _ => ResultFormat::Ndjson,
+        }
+    }
+}
+
+/// One line of the log
+#[derive(Debug, Serialize)]
+pub struct ResultRecord<'a> {
+    pub path: String,
+    pub size: u64,
+    pub status: &'static str,
+    pub strategy: Option<&'static str>,
+    pub duration_us: u64,
+    pub bytes_read: u64,
+    /// `full`, `sampled`, `partial` or `advisory` for warmed files
+    pub coverage: Option<&'static str>,
+    pub error: Option<&'a str>,
+}
+
+impl<'a> ResultRecord<'a> {
+    pub fn from_event(event: &'a WarmEvent) -> Self {
+        let WarmEvent::FileFinished { path, bytes, latency, status, method, bytes_read, error, coverage } = event;
+        Self {
+            path: anonymize::display(path).to_string(),
+            size: *bytes,
+            status: match status {
+                FileStatus::Warmed => "warmed",
+                FileStatus::Failed => "failed",
+                FileStatus::Skipped => "skipped",
+            },
+            strategy: *method,
+            duration_us: latency.as_micros() as u64,
+            bytes_read: *bytes_read,
+            coverage: coverage.map(Coverage::name),
+            error: error.as_deref(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&self.path),
+            self.size,
+            self.status,
+            self.strategy.unwrap_or(""),
+            self.duration_us,
+            self.bytes_read,
+            self.coverage.unwrap_or(""),
+            csv_field(self.error.unwrap_or(""))
+        )
+    }
+}
+
+/// Quote a CSV field if it needs it (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writer for the result log
+#[derive(Debug)]
+pub struct ResultLog {
+    format: ResultFormat,
+    writer: LogWriter,
+}
+
+impl ResultLog {
+    /// Create (or truncate) the log at `path`, in the format its extension implies
+    pub fn create(path: &Path) -> Result<Self, std::io::Error> {
+        Self::create_rotating(path, 0)
+    }
+
+    /// Like [`ResultLog::create`], starting a new segment whenever the current one
+    /// reaches `rotate_bytes` (0 never rotates). CSV segments each get the header.
+    pub fn create_rotating(path: &Path, rotate_bytes: u64) -> Result<Self, std::io::Error> {
+        let format = ResultFormat::for_path(path);
+        let header = (format == ResultFormat::Csv).then_some(CSV_HEADER);
+        Ok(Self { format, writer: LogWriter::create(path, rotate_bytes, header)? })
+    }
+
+    pub fn write(&mut self, event: &WarmEvent) -> Result<(), std::io::Error> {
+        let record = ResultRecord::from_event(event);
+        let mut line = match self.format {
+            ResultFormat::Ndjson => serde_json::to_string(&record)?,
+            ResultFormat::Csv => record.to_csv(),
+        };
+        line.push('\n');
+        self.writer.write_record(line.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush()
+    }
+
+    /// Flush and complete the log file, e.g. write the trailer of a compressed one
+    pub fn finish(&mut self) -> Result<(), std::io::Error> {
+        self.writer.finish()
+    }
+}
+
+/// Consume pipeline events and append a record per file until the event stream ends.
+/// Records are flushed every second, so the log can be tailed during a run.
+pub async fn run_writer(mut log: ResultLog, mut events: mpsc::UnboundedReceiver<WarmEvent>) {
+    let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+    ticker.tick().await; // the first tick completes immediately
+    let mut failed = false;
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    if let Err(e) = log.write(&event) {
+                        if !failed {
+                            warn!("Failed to write to the result log: {}", e);
+                            failed = true;
+                        }
+                    }
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                let _ = log.flush();
+            }
+        }
+    }
+    if let Err(e) = log.finish() {
+        warn!("Failed to flush the result log: {}", e);
+    }
+}
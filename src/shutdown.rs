@@ -0,0 +1,99 @@
+//! Graceful shutdown.
+//!
+//! The first SIGINT/SIGTERM triggers a [`Shutdown`]: discovery stops, files still being
+//! warmed are cancelled (and, not being journaled, warmed again on resume), and the
+//! pipeline returns a partial summary. A second signal exits immediately.
+
+use std::sync::Arc;
+
+use log::warn;
+use tokio::sync::watch;
+
+/// Cloneable handle shared by everything that needs to notice a shutdown request
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    /// The signal that requested the shutdown, once one has
+    state: Arc<watch::Sender<Option<i32>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self { state: Arc::new(watch::channel(None).0) }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request a shutdown on behalf of `signal`. Only the first request is kept.
+    pub fn trigger(&self, signal: i32) {
+        self.state.send_if_modified(|state| {
+            if state.is_some() {
+                return false;
+            }
+            *state = Some(signal);
+            true
+        });
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.state.borrow().is_some()
+    }
+
+    pub fn signal(&self) -> Option<i32> {
+        *self.state.borrow()
+    }
+
+    /// Shell-style exit status for an interrupted run (128 + signal number)
+    pub fn exit_code(&self) -> Option<i32> {
+        self.signal().map(|signal| 128 + signal)
+    }
+
+    /// Resolves once a shutdown has been requested
+    pub async fn triggered(&self) {
+        let mut rx = self.state.subscribe();
+        let _ = rx.wait_for(Option::is_some).await;
+    }
+}
+
+/// Trigger `shutdown` on the first SIGINT or SIGTERM and exit on the second
+#[cfg(unix)]
+pub fn listen_for_signals(shutdown: Shutdown) -> Result<tokio::task::JoinHandle<()>, std::io::Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    Ok(tokio::spawn(async move {
+        loop {
+            let received = tokio::select! {
+                _ = interrupt.recv() => libc::SIGINT,
+                _ = terminate.recv() => libc::SIGTERM,
+            };
+            if shutdown.is_triggered() {
+                warn!("Second signal received, exiting immediately");
+                std::process::exit(128 + received);
+            }
+            warn!("Signal {} received: stopping discovery and cancelling in-flight files (signal again to exit immediately)", received);
+            shutdown.trigger(received);
+        }
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn listen_for_signals(shutdown: Shutdown) -> Result<tokio::task::JoinHandle<()>, std::io::Error> {
+    Ok(tokio::spawn(async move {
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            if shutdown.is_triggered() {
+                warn!("Second Ctrl-C received, exiting immediately");
+                std::process::exit(130);
+            }
+            warn!("Ctrl-C received: stopping discovery and cancelling in-flight files (press again to exit immediately)");
+            shutdown.trigger(2);
+        }
+    }))
+}
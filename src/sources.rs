@@ -0,0 +1,276 @@
+//! Discovery from a list instead of a walk (`--files-from`, `--s3-inventory`).
+//!
+//! By default discovery walks every target directory. A volume restored from a snapshot
+//! often comes with a better account of what's on it: the paths an application is known
+//! to read, or the S3 inventory of the bucket its data was synced from. Walking millions
+//! of directories to find files such a list already names costs a metadata read per
+//! directory, each one a block fetch on a fresh volume, before the first byte of file
+//! data is warmed.
+//!
+//! A [`FileSource`] replaces the walk. Discovery asks it for the files under each share's
+//! target directories, stats each one, drops those that don't exist here or aren't
+//! regular files, and queues the rest exactly as walked files are: the discovery filters,
+//! open files, checkpoint and inode dedupe all apply. Directory-level walk settings
+//! (`--max-depth`, hidden files, ignore files) don't, as nothing is walked.
+
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::logfile::LogReader;
+
+/// Lists the files discovery queues, in place of walking the target directories.
+/// Discovery walks them itself, in parallel, when a run has no source.
+pub trait FileSource: fmt::Debug + fmt::Display + Send + Sync {
+    /// Pass every file listed under `roots` to `found`, in the source's order, until it
+    /// returns false. The paths needn't exist; discovery checks. Called once per share,
+    /// off the runtime, so it may block.
+    fn list(&self, roots: &[PathBuf], found: &mut dyn FnMut(PathBuf) -> bool) -> std::io::Result<()>;
+}
+
+/// The paths `listed` names under `roots`: a relative path under each of them, an absolute
+/// one only if it lies under one. Relative paths that climb out with `..` name nothing.
+pub fn resolve<'a>(listed: &'a Path, roots: &'a [PathBuf]) -> impl Iterator<Item = PathBuf> + 'a {
+    let escapes = listed.is_relative() && listed.components().any(|component| component == Component::ParentDir);
+    roots.iter().filter(move |_| !escapes).filter_map(move |root| {
+        if listed.is_absolute() {
+            listed.starts_with(root).then(|| listed.to_path_buf())
+        } else {
+            Some(root.join(listed))
+        }
+    })
+}
+
+/// Paths one per line (`--files-from`), relative to each target directory or absolute.
+/// Empty lines and lines starting with `#` are skipped. A list file may be gzip or zstd
+/// compressed, going by its extension; `-` reads the list from stdin.
+#[derive(Debug)]
+pub struct PathList {
+    origin: PathBuf,
+    /// The list read from stdin, which can only be read once but is listed by every share
+    stdin: Option<Vec<String>>,
+}
+
+impl PathList {
+    pub fn open(path: &Path) -> Result<PathList> {
+        if path.as_os_str() == "-" {
+            let lines = std::io::stdin().lock().lines().collect::<Result<Vec<_>, _>>().context("failed to read the file list from stdin")?;
+            return Ok(PathList { origin: path.to_path_buf(), stdin: Some(lines) });
+        }
+        // Fail before warming starts rather than in every share's discovery
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Ok(PathList { origin: path.to_path_buf(), stdin: None })
+    }
+}
+
+impl FileSource for PathList {
+    fn list(&self, roots: &[PathBuf], found: &mut dyn FnMut(PathBuf) -> bool) -> std::io::Result<()> {
+        let mut send = |line: &str| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() || line.starts_with('#') {
+                return true;
+            }
+            resolve(Path::new(line), roots).all(&mut *found)
+        };
+        match &self.stdin {
+            Some(lines) => {
+                for line in lines {
+                    if !send(line) {
+                        break;
+                    }
+                }
+            }
+            None => {
+                for line in BufReader::new(LogReader::open(&self.origin)?).lines() {
+                    if !send(&line?) {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PathList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.stdin {
+            Some(lines) => write!(f, "the {} paths listed on stdin", lines.len()),
+            None => write!(f, "the paths listed in {}", self.origin.display()),
+        }
+    }
+}
+
+/// The parts of an inventory's `manifest.json` needed to read it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InventoryManifest {
+    file_format: String,
+    file_schema: String,
+    files: Vec<InventoryFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InventoryFile {
+    key: String,
+}
+
+/// Column positions in an inventory's CSV files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Columns {
+    key: usize,
+    is_latest: Option<usize>,
+    is_delete_marker: Option<usize>,
+}
+
+impl Columns {
+    /// Bucket and key always come first
+    const DEFAULT: Columns = Columns { key: 1, is_latest: None, is_delete_marker: None };
+
+    /// The columns a manifest's `fileSchema` lists, e.g. `Bucket, Key, Size, IsLatest`
+    fn from_schema(schema: &str) -> Option<Columns> {
+        let fields: Vec<&str> = schema.split(',').map(str::trim).collect();
+        let position = |name: &str| fields.iter().position(|field| field.eq_ignore_ascii_case(name));
+        Some(Columns { key: position("Key")?, is_latest: position("IsLatest"), is_delete_marker: position("IsDeleteMarker") })
+    }
+}
+
+/// An Amazon S3 Inventory report in CSV format (`--s3-inventory`): the objects of the
+/// bucket a volume's data was synced from. Each object key, less `prefix`, is a path
+/// relative to the target directories; keys outside `prefix`, folder markers, delete
+/// markers and noncurrent versions are left out.
+#[derive(Debug)]
+pub struct S3Inventory {
+    /// CSV data files, each optionally gzip compressed (`*.csv.gz`, as S3 writes them)
+    files: Vec<PathBuf>,
+    columns: Columns,
+    prefix: String,
+}
+
+impl S3Inventory {
+    /// Read the inventory given as its data files, or as the `manifest.json` S3 writes
+    /// alongside them. A manifest's data files are found by name next to it or in a `data`
+    /// directory beside it or its parent, which is where copying the report's destination
+    /// prefix puts them.
+    pub fn open(paths: &[PathBuf], prefix: &str) -> Result<S3Inventory> {
+        let mut files = Vec::new();
+        let mut columns = Columns::DEFAULT;
+        for path in paths {
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let (manifest_files, manifest_columns) = read_manifest(path)?;
+                files.extend(manifest_files);
+                columns = manifest_columns;
+            } else {
+                std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+                files.push(path.clone());
+            }
+        }
+        Ok(S3Inventory { files, columns, prefix: prefix.trim_start_matches('/').to_string() })
+    }
+
+    /// The path relative to the target directories that a CSV record names, if it's a
+    /// current object under the prefix
+    fn relative_path(&self, record: &str) -> Option<PathBuf> {
+        let fields = csv_fields(record);
+        let flag = |column: Option<usize>| column.and_then(|column| fields.get(column)).map(|value| value.eq_ignore_ascii_case("true"));
+        if flag(self.columns.is_latest) == Some(false) || flag(self.columns.is_delete_marker) == Some(true) {
+            return None;
+        }
+        let key = decode_key(fields.get(self.columns.key)?)?;
+        let relative = key.strip_prefix(&self.prefix)?.trim_start_matches('/');
+        if relative.is_empty() || relative.ends_with('/') {
+            return None;
+        }
+        Some(PathBuf::from(relative))
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<(Vec<PathBuf>, Columns)> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let manifest: InventoryManifest = serde_json::from_str(&text).with_context(|| format!("{} is not an S3 Inventory manifest", path.display()))?;
+    if !manifest.file_format.eq_ignore_ascii_case("CSV") {
+        bail!("{}: the inventory is in {} format; only CSV inventories can be read", path.display(), manifest.file_format);
+    }
+    let columns = Columns::from_schema(&manifest.file_schema)
+        .with_context(|| format!("{}: the inventory schema has no Key column: {}", path.display(), manifest.file_schema))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut files = Vec::with_capacity(manifest.files.len());
+    for file in &manifest.files {
+        let name = Path::new(&file.key).file_name().with_context(|| format!("{}: bad data file key {}", path.display(), file.key))?;
+        let candidates = [Some(dir.join(name)), Some(dir.join("data").join(name)), dir.parent().map(|parent| parent.join("data").join(name))];
+        let found = candidates.into_iter().flatten().find(|candidate| candidate.is_file());
+        files.push(found.with_context(|| format!("{}: data file {} not found next to it or in a data directory", path.display(), name.to_string_lossy()))?);
+    }
+    Ok((files, columns))
+}
+
+impl FileSource for S3Inventory {
+    fn list(&self, roots: &[PathBuf], found: &mut dyn FnMut(PathBuf) -> bool) -> std::io::Result<()> {
+        let mut previous: Option<PathBuf> = None;
+        for file in &self.files {
+            for record in BufReader::new(LogReader::open(file)?).lines() {
+                let Some(relative) = self.relative_path(&record?) else { continue };
+                // Versions of an object are listed together
+                if previous.as_ref() == Some(&relative) {
+                    continue;
+                }
+                if !resolve(&relative, roots).all(&mut *found) {
+                    return Ok(());
+                }
+                previous = Some(relative);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for S3Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the objects in an S3 inventory of {} files", self.files.len())?;
+        if !self.prefix.is_empty() {
+            write!(f, " under {}", self.prefix)?;
+        }
+        Ok(())
+    }
+}
+
+/// The fields of a CSV record; quoted fields may hold commas and doubled quotes
+pub fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// An object key as S3 Inventory writes it in CSV: URL-encoded, with `+` for spaces
+pub fn decode_key(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut input = encoded.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let mut digit = || char::from(input.next()?).to_digit(16);
+                bytes.push((digit()? * 16 + digit()?) as u8);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,240 @@
+//! Warm targets received from an SQS queue.
+//!
+//! Each message names one or more files (optionally a byte range within them) to warm,
+//! typically emitted by an upstream restore pipeline. Messages are deleted only after
+//! every target in them has been warmed; anything that fails is left to reappear after
+//! its visibility timeout so it is retried.
+
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use aws_sdk_sqs::types::{ChangeMessageVisibilityBatchRequestEntry, DeleteMessageBatchRequestEntry, Message};
+use aws_sdk_sqs::Client;
+use futures::stream::{self, StreamExt};
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::warming::{warm_file, WarmingOptions};
+use crate::warnings::{self, Category};
+
+/// SQS long-polling settings
+#[derive(Debug, Clone)]
+pub struct SqsOptions {
+    pub queue_url: String,
+    pub queue_depth: usize,
+    pub max_file_size: u64,
+    /// Messages requested per receive call (SQS caps this at 10)
+    pub batch_size: i32,
+    pub visibility_timeout: Duration,
+    pub wait_time: Duration,
+}
+
+/// One warming target inside a message
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct SqsTarget {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub length: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MessageBody {
+    Many { targets: Vec<SqsTarget> },
+    One(SqsTarget),
+}
+
+/// Parse a message body. Accepts `{"path": ..., "offset": ..., "length": ...}`,
+/// `{"targets": [...]}`, or plain text with one path per line.
+pub fn parse_targets(body: &str) -> Vec<SqsTarget> {
+    match serde_json::from_str::<MessageBody>(body) {
+        Ok(MessageBody::Many { targets }) => targets,
+        Ok(MessageBody::One(target)) => vec![target],
+        Err(_) => body
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| SqsTarget { path: PathBuf::from(line), offset: None, length: None })
+            .collect(),
+    }
+}
+
+/// Long-poll the queue until Ctrl-C, warming the targets in each message.
+pub async fn poll_and_warm(options: SqsOptions, warming_options: WarmingOptions) -> Result<()> {
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let client = Client::new(&config);
+
+    info!("Polling SQS queue {} for warming targets. Press Ctrl-C to stop.", options.queue_url);
+
+    let mut messages_acked = 0u64;
+    loop {
+        let receive = client
+            .receive_message()
+            .queue_url(&options.queue_url)
+            .max_number_of_messages(options.batch_size.clamp(1, 10))
+            .wait_time_seconds(options.wait_time.as_secs().min(20) as i32)
+            .visibility_timeout(options.visibility_timeout.as_secs() as i32)
+            .send();
+
+        let response = tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopping SQS mode. Acknowledged {} messages.", messages_acked);
+                return Ok(());
+            }
+            response = receive => response.context("Failed to receive messages from SQS")?,
+        };
+
+        let messages = response.messages();
+        if messages.is_empty() {
+            continue;
+        }
+        debug!("Received {} SQS messages", messages.len());
+
+        let warmed = warm_messages_with_heartbeat(&client, &options, &warming_options, messages).await;
+        messages_acked += acknowledge(&client, &options.queue_url, &warmed).await?;
+    }
+}
+
+/// Warm every message while periodically extending their visibility so that slow
+/// warms are not redelivered to another consumer mid-flight.
+async fn warm_messages_with_heartbeat<'a>(
+    client: &Client,
+    options: &SqsOptions,
+    warming_options: &WarmingOptions,
+    messages: &'a [Message],
+) -> Vec<&'a Message> {
+    // Which messages are still being warmed, so the heartbeat only extends those
+    let in_flight: Vec<Cell<bool>> = messages.iter().map(|_| Cell::new(true)).collect();
+    let work = stream::iter(messages.iter().zip(&in_flight))
+        .map(|(message, in_flight)| async move {
+            let targets = parse_targets(message.body().unwrap_or_default());
+            if targets.is_empty() {
+                warn!("Ignoring SQS message {} with no targets", message.message_id().unwrap_or("?"));
+                in_flight.set(false);
+                // Nothing to retry, so acknowledge it to keep the queue moving
+                return Some(message);
+            }
+            let mut all_ok = true;
+            for target in &targets {
+                if let Err(e) = warm_target(target, options, warming_options).await {
+                    warnings::report(Category::of(&e), format_args!("Failed to warm SQS target {}: {}", target.path.display(), e));
+                    all_ok = false;
+                }
+            }
+            in_flight.set(false);
+            all_ok.then_some(message)
+        })
+        .buffer_unordered(options.queue_depth)
+        .collect::<Vec<_>>();
+    tokio::pin!(work);
+
+    // Extend visibility at half the timeout so messages stay hidden while we work
+    let heartbeat_period = std::cmp::max(options.visibility_timeout / 2, Duration::from_secs(1));
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + heartbeat_period, heartbeat_period);
+
+    loop {
+        tokio::select! {
+            results = &mut work => return results.into_iter().flatten().collect(),
+            _ = heartbeat.tick() => {
+                let pending = messages.iter().zip(&in_flight).filter(|(_, in_flight)| in_flight.get()).map(|(message, _)| message);
+                extend_visibility(client, options, pending).await;
+            }
+        }
+    }
+}
+
+/// Extend the visibility timeout of `messages` with one batch call (a receive returns
+/// at most 10, the batch limit)
+async fn extend_visibility<'a>(client: &Client, options: &SqsOptions, messages: impl Iterator<Item = &'a Message>) {
+    let entries = messages
+        .enumerate()
+        .filter_map(|(i, message)| {
+            ChangeMessageVisibilityBatchRequestEntry::builder()
+                .id(i.to_string())
+                .receipt_handle(message.receipt_handle()?)
+                .visibility_timeout(options.visibility_timeout.as_secs() as i32)
+                .build()
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return;
+    }
+
+    match client.change_message_visibility_batch().queue_url(&options.queue_url).set_entries(Some(entries)).send().await {
+        Ok(response) => {
+            for failure in response.failed() {
+                warn!("Failed to extend SQS visibility timeout: {} ({})", failure.id(), failure.code());
+            }
+        }
+        Err(e) => warn!("Failed to extend SQS visibility timeout: {}", e),
+    }
+}
+
+async fn warm_target(target: &SqsTarget, options: &SqsOptions, warming_options: &WarmingOptions) -> std::io::Result<()> {
+    let file_size = tokio::fs::metadata(&target.path).await?.len();
+    if options.max_file_size > 0 && file_size > options.max_file_size {
+        debug!("Skipping large SQS target: {} (size: {} > max: {})", target.path.display(), file_size, options.max_file_size);
+        return Ok(());
+    }
+
+    // A target naming a range is read through the same strategy chain, limited to it
+    let ranged;
+    let warming_options = if target.offset.is_some() || target.length.is_some() {
+        let offset = target.offset.unwrap_or(0);
+        let length = target.length.unwrap_or(u64::MAX).min(file_size.saturating_sub(offset));
+        if length == 0 {
+            debug!("SQS target {} has nothing in range {}+{:?}", target.path.display(), offset, target.length);
+            return Ok(());
+        }
+        let mut with_range = warming_options.clone();
+        with_range.ranges = Some(Arc::from([(offset, length)]));
+        ranged = with_range;
+        &ranged
+    } else {
+        warming_options
+    };
+
+    let result = warm_file(&target.path, file_size, warming_options).await?;
+    debug!("SQS target {} warmed: method={}, duration={:?}", target.path.display(), result.method, result.duration);
+    Ok(())
+}
+
+/// Delete warmed messages in batches of up to 10 (the SQS limit). Returns how many were deleted.
+async fn acknowledge(client: &Client, queue_url: &str, messages: &[&Message]) -> Result<u64> {
+    let mut deleted = 0u64;
+    for chunk in messages.chunks(10) {
+        let entries = chunk
+            .iter()
+            .enumerate()
+            .filter_map(|(i, message)| {
+                DeleteMessageBatchRequestEntry::builder()
+                    .id(i.to_string())
+                    .receipt_handle(message.receipt_handle()?)
+                    .build()
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            continue;
+        }
+
+        let response = client
+            .delete_message_batch()
+            .queue_url(queue_url)
+            .set_entries(Some(entries))
+            .send()
+            .await
+            .context("Failed to delete messages from SQS")?;
+        for failure in response.failed() {
+            warn!("Failed to delete SQS message: {} ({})", failure.id(), failure.code());
+        }
+        deleted += response.successful().len() as u64;
+    }
+    Ok(deleted)
+}
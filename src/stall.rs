@@ -0,0 +1,112 @@
+//! Runtime stall detection (`--stall-threshold-ms`).
+//!
+//! Progress bars, the control socket, signal handlers and metrics all run as tasks on
+//! the same Tokio workers as warming. A task that runs for long without reaching an
+//! `.await` (or a blocking call that slipped onto a worker) holds its worker, and with
+//! the few workers of `--low-memory` mode that freezes everything else. The heavy loops
+//! already run on blocking threads and the per-file loop spends Tokio's cooperative
+//! budget, but regressions are easy to miss: [`StallMonitor`] keeps a probe task that
+//! wakes at a fixed interval and measures how late it was woken. A late wake-up means
+//! every worker was busy for that long, so it's reported as a `runtime-stall` warning
+//! along with what was queued at the time.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::time::Instant;
+
+use crate::warnings::{self, Category};
+
+/// Default `--stall-threshold-ms`
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_millis(200);
+/// How often the probe wakes
+const PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Default)]
+struct Counters {
+    probes: AtomicU64,
+    stalls: AtomicU64,
+    worst_lag_us: AtomicU64,
+}
+
+/// What the probe saw
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StallSummary {
+    /// Wake-ups measured
+    pub probes: u64,
+    /// Wake-ups later than the threshold
+    pub stalls: u64,
+    /// Latest wake-up seen, stalled or not
+    pub worst_lag: Duration,
+}
+
+impl fmt::Display for StallSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Runtime stalled {} times in {} probes (worst {:.2?})", self.stalls, self.probes, self.worst_lag)
+    }
+}
+
+/// Watches the runtime it was started on for stalls; the probe stops when the last
+/// clone is dropped
+#[derive(Debug, Clone)]
+pub struct StallMonitor {
+    counters: Arc<Counters>,
+    /// Aborts the probe when the last clone goes
+    _probe: Arc<ProbeHandle>,
+}
+
+#[derive(Debug)]
+struct ProbeHandle(tokio::task::JoinHandle<()>);
+
+impl Drop for ProbeHandle {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl StallMonitor {
+    /// Start probing the current runtime, warning about wake-ups later than `threshold`
+    pub fn start(threshold: Duration) -> Self {
+        let counters = Arc::new(Counters::default());
+        let probe = tokio::spawn(probe(Arc::clone(&counters), threshold));
+        Self { counters, _probe: Arc::new(ProbeHandle(probe)) }
+    }
+
+    pub fn summary(&self) -> StallSummary {
+        StallSummary {
+            probes: self.counters.probes.load(Ordering::Relaxed),
+            stalls: self.counters.stalls.load(Ordering::Relaxed),
+            worst_lag: Duration::from_micros(self.counters.worst_lag_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+async fn probe(counters: Arc<Counters>, threshold: Duration) {
+    let mut due = Instant::now() + PROBE_INTERVAL;
+    loop {
+        tokio::time::sleep_until(due).await;
+        let now = Instant::now();
+        let lag = now.saturating_duration_since(due);
+        counters.probes.fetch_add(1, Ordering::Relaxed);
+        counters.worst_lag_us.fetch_max(lag.as_micros() as u64, Ordering::Relaxed);
+        if lag > threshold {
+            counters.stalls.fetch_add(1, Ordering::Relaxed);
+            let metrics = Handle::current().metrics();
+            warnings::report(
+                Category::RuntimeStall,
+                format_args!(
+                    "Tokio workers were busy for {:.2?} (threshold {:?}) with {} tasks alive and {} queued; progress, the control socket and metrics were unresponsive meanwhile",
+                    lag,
+                    threshold,
+                    metrics.num_alive_tasks(),
+                    metrics.global_queue_depth()
+                ),
+            );
+        }
+        // Measure from now, so one stall isn't counted again as a run of late ticks
+        due = now + PROBE_INTERVAL;
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::anonymize;
+
+/// Breakdown dimensions that can be requested with `--stats-by`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatsDimension {
+    Ext,
+    Dir,
+}
+
+impl FromStr for StatsDimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ext" | "extension" => Ok(StatsDimension::Ext),
+            "dir" | "directory" => Ok(StatsDimension::Dir),
+            other => Err(format!("unknown stats dimension '{}' (expected 'ext' or 'dir')", other)),
+        }
+    }
+}
+
+impl fmt::Display for StatsDimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatsDimension::Ext => write!(f, "ext"),
+            StatsDimension::Dir => write!(f, "dir"),
+        }
+    }
+}
+
+/// Aggregated counters for one group of files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    pub files: u64,
+    /// Size of the files warmed
+    pub bytes: u64,
+    /// Of `bytes`, the logical data the warms covered; less where files were sampled,
+    /// read partially, or only advised into the page cache
+    pub bytes_covered: u64,
+    /// Bytes requested from storage, which is what the volume bills as I/O
+    pub bytes_read: u64,
+    pub failed: u64,
+    pub skipped: u64,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl GroupStats {
+    fn add(&mut self, outcome: &FileOutcome) {
+        self.files += 1;
+        self.bytes_read += outcome.bytes_read;
+        match outcome.status {
+            FileStatus::Warmed => {
+                self.bytes += outcome.bytes;
+                self.bytes_covered += outcome.bytes_covered;
+            }
+            FileStatus::Failed => self.failed += 1,
+            FileStatus::Skipped => self.skipped += 1,
+        }
+        self.total_latency += outcome.latency;
+        self.max_latency = self.max_latency.max(outcome.latency);
+    }
+
+    /// Mean per-file warm latency, counting only files that were actually attempted
+    pub fn avg_latency(&self) -> Duration {
+        let attempted = self.files - self.skipped;
+        if attempted == 0 {
+            Duration::ZERO
+        } else {
+            self.total_latency / attempted as u32
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Warmed,
+    Failed,
+    Skipped,
+}
+
+impl FileStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileStatus::Warmed => "warmed",
+            FileStatus::Failed => "failed",
+            FileStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// What happened to a single file, as reported to the collector
+#[derive(Debug, Clone, Copy)]
+pub struct FileOutcome {
+    /// Size of the file
+    pub bytes: u64,
+    /// Logical bytes the warm covered (see [`crate::warming::Coverage::bytes_covered`])
+    pub bytes_covered: u64,
+    /// Bytes requested from storage
+    pub bytes_read: u64,
+    pub latency: Duration,
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Default)]
+struct StatsInner {
+    totals: GroupStats,
+    by_ext: HashMap<String, GroupStats>,
+    by_dir: HashMap<String, GroupStats>,
+}
+
+/// Thread-safe collector fed by the warming loop for every processed file
+#[derive(Debug)]
+pub struct StatsCollector {
+    roots: Vec<PathBuf>,
+    by_ext: bool,
+    by_dir: bool,
+    inner: Mutex<StatsInner>,
+}
+
+impl StatsCollector {
+    pub fn new(roots: &[PathBuf], dimensions: &[StatsDimension]) -> Self {
+        Self {
+            roots: roots.to_vec(),
+            by_ext: dimensions.contains(&StatsDimension::Ext),
+            by_dir: dimensions.contains(&StatsDimension::Dir),
+            inner: Mutex::new(StatsInner::default()),
+        }
+    }
+
+    pub fn record(&self, path: &Path, outcome: FileOutcome) {
+        // Compute group keys before taking the lock to keep the critical section short
+        let ext_key = self.by_ext.then(|| extension_key(path));
+        let dir_key = self.by_dir.then(|| self.top_level_dir(path));
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.totals.add(&outcome);
+        if let Some(key) = ext_key {
+            inner.by_ext.entry(key).or_default().add(&outcome);
+        }
+        if let Some(key) = dir_key {
+            inner.by_dir.entry(key).or_default().add(&outcome);
+        }
+    }
+
+    pub fn totals(&self) -> GroupStats {
+        self.inner.lock().unwrap().totals.clone()
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let inner = self.inner.lock().unwrap();
+        StatsSnapshot {
+            totals: inner.totals.clone(),
+            by_ext: inner.by_ext.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            by_dir: inner.by_dir.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        }
+    }
+
+    /// The first path component below the walk root the file was found under
+    fn top_level_dir(&self, path: &Path) -> String {
+        let Some(root) = self.roots.iter().find(|root| path.starts_with(root)) else {
+            return path.parent().map_or_else(|| ".".to_string(), |p| anonymize::display(p).to_string());
+        };
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let mut components = relative.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(dir)), Some(_)) => anonymize::display(&root.join(dir)).to_string(),
+            _ => anonymize::display(root).to_string(),
+        }
+    }
+}
+
+fn extension_key(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_else(|| "(none)".to_string())
+}
+
+/// Point-in-time copy of everything the collector has aggregated
+#[derive(Debug, Clone, Default)]
+pub struct StatsSnapshot {
+    pub totals: GroupStats,
+    pub by_ext: BTreeMap<String, GroupStats>,
+    pub by_dir: BTreeMap<String, GroupStats>,
+}
+
+impl StatsSnapshot {
+    /// Render one breakdown as a table, largest groups by bytes first
+    pub fn format_breakdown(title: &str, groups: &BTreeMap<String, GroupStats>, limit: usize) -> String {
+        let mut rows: Vec<_> = groups.iter().collect();
+        rows.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+
+        let mut out = format!(
+            "{}\n  {:<40} {:>10} {:>12} {:>8} {:>12} {:>12}\n",
+            title, "group", "files", "MB", "failed", "avg latency", "max latency"
+        );
+        for (name, stats) in rows.iter().take(limit) {
+            out.push_str(&format!(
+                "  {:<40} {:>10} {:>12.2} {:>8} {:>12.2?} {:>12.2?}\n",
+                name,
+                stats.files,
+                stats.bytes as f64 / (1024.0 * 1024.0),
+                stats.failed,
+                stats.avg_latency(),
+                stats.max_latency
+            ));
+        }
+        if rows.len() > limit {
+            out.push_str(&format!("  ... and {} more\n", rows.len() - limit));
+        }
+        out
+    }
+}
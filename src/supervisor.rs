@@ -0,0 +1,213 @@
+//! `--supervised` mode.
+//!
+//! Long warms on memory-constrained hosts can be OOM-killed hours in. The supervisor is
+//! a small parent process that runs the actual warmer as a child with a checkpoint (see
+//! [`crate::checkpoint`]) and, when the child crashes, starts it again from that
+//! checkpoint. Because resumed runs replay earlier outcomes, the last child's summary
+//! already covers every attempt.
+//!
+//! The child runs in its own process group, so a terminal Ctrl-C reaches only the
+//! supervisor, which forwards SIGINT/SIGTERM to the child exactly once per signal and
+//! lets it shut down gracefully. An interrupted child is never restarted. SIGUSR1,
+//! SIGUSR2 and SIGQUIT are forwarded too, so pausing, resuming and state dumps work the
+//! same with or without a supervisor.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+
+/// Exit code of a Rust process that panicked
+const PANIC_EXIT_CODE: i32 = 101;
+/// Pause before restart `n` is `n` times this, so a crash loop doesn't spin
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+/// How often the supervisor checks on the child and for signals to forward
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Last SIGINT/SIGTERM received by the supervisor and not yet forwarded
+static PENDING_SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// A SIGUSR1 (pause request) is waiting to be forwarded
+static PENDING_PAUSE: AtomicBool = AtomicBool::new(false);
+/// A SIGUSR2 (resume request) is waiting to be forwarded
+static PENDING_RESUME: AtomicBool = AtomicBool::new(false);
+/// A SIGQUIT (state and stats dump request) is waiting to be forwarded
+static PENDING_STATS: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn remember_signal(signal: libc::c_int) {
+    if signal == libc::SIGUSR1 {
+        PENDING_PAUSE.store(true, Ordering::SeqCst);
+    } else if signal == libc::SIGUSR2 {
+        PENDING_RESUME.store(true, Ordering::SeqCst);
+    } else if signal == libc::SIGQUIT {
+        PENDING_STATS.store(true, Ordering::SeqCst);
+    } else {
+        PENDING_SIGNAL.store(signal, Ordering::SeqCst);
+    }
+}
+
+#[cfg(unix)]
+fn install_forwarding() {
+    let handler = remember_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGUSR1, handler);
+        libc::signal(libc::SIGUSR2, handler);
+        libc::signal(libc::SIGQUIT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_forwarding() {}
+
+fn spawn(options: &SupervisorOptions) -> std::io::Result<Child> {
+    let mut command = Command::new(&options.program);
+    command.args(&options.args).arg("--checkpoint").arg(&options.checkpoint);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command.spawn()
+}
+
+/// Wait for the child, forwarding signals to it. Returns its status and whether a
+/// SIGINT/SIGTERM was forwarded.
+fn wait(child: &mut Child) -> std::io::Result<(ExitStatus, bool)> {
+    let mut forwarded = false;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, forwarded));
+        }
+        let signal = PENDING_SIGNAL.swap(0, Ordering::SeqCst);
+        if signal != 0 {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, signal);
+            }
+            forwarded = true;
+        }
+        if PENDING_PAUSE.swap(false, Ordering::SeqCst) {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGUSR1);
+            }
+        }
+        if PENDING_RESUME.swap(false, Ordering::SeqCst) {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGUSR2);
+            }
+        }
+        if PENDING_STATS.swap(false, Ordering::SeqCst) {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGQUIT);
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SupervisorOptions {
+    /// Executable to run as the child, normally the current binary
+    pub program: PathBuf,
+    /// Arguments for the child; `--checkpoint` is appended
+    pub args: Vec<OsString>,
+    pub checkpoint: PathBuf,
+    /// Restarts allowed after the first attempt
+    pub max_restarts: u32,
+}
+
+/// Whether the child died rather than finishing with an error of its own. Clean error
+/// exits (bad flags, missing directories) would fail the same way again, so only
+/// crashes are retried.
+fn is_crash(status: &ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if status.signal().is_some() {
+            return true;
+        }
+    }
+    status.code() == Some(PANIC_EXIT_CODE)
+}
+
+fn describe(status: &ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        match status.signal() {
+            Some(libc::SIGKILL) => return "was killed by SIGKILL (likely the OOM killer)".to_string(),
+            Some(signal) => return format!("was killed by signal {}", signal),
+            None => {}
+        }
+    }
+    match status.code() {
+        Some(PANIC_EXIT_CODE) => "panicked".to_string(),
+        Some(code) => format!("exited with status {}", code),
+        None => "exited abnormally".to_string(),
+    }
+}
+
+/// Exit code for the supervisor to pass on: the child's own, or 128 + signal if it was killed
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Run the child until it finishes, restarting it after crashes. Returns the status of
+/// the last attempt: success, a non-crash failure, or the crash that used up the restarts.
+pub fn supervise(options: &SupervisorOptions) -> Result<ExitStatus> {
+    install_forwarding();
+    let mut restarts = 0u32;
+    loop {
+        let mut child = spawn(options).with_context(|| format!("failed to start {}", options.program.display()))?;
+        let (status, interrupted) = wait(&mut child).context("failed to wait for the warmer")?;
+
+        if status.success() {
+            if restarts > 0 {
+                info!("Supervisor: warming finished after {} restart(s)", restarts);
+            }
+            return Ok(status);
+        }
+        if interrupted || PENDING_SIGNAL.load(Ordering::SeqCst) != 0 {
+            info!("Supervisor: warmer {} after being interrupted; not restarting", describe(&status));
+            return Ok(status);
+        }
+        if !is_crash(&status) {
+            warn!("Supervisor: warmer {}; not restarting", describe(&status));
+            return Ok(status);
+        }
+        if restarts >= options.max_restarts {
+            warn!(
+                "Supervisor: warmer {} and all {} restart(s) are used up; progress is kept in {}",
+                describe(&status),
+                options.max_restarts,
+                options.checkpoint.display()
+            );
+            return Ok(status);
+        }
+
+        restarts += 1;
+        warn!(
+            "Supervisor: warmer {}; restarting from checkpoint ({}/{})",
+            describe(&status),
+            restarts,
+            options.max_restarts
+        );
+        std::thread::sleep(RESTART_BACKOFF * restarts);
+    }
+}
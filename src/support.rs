@@ -0,0 +1,327 @@
+//! What was asked for, against what this host and build can do.
+//!
+//! Strategies are probed at startup, several options only work on Linux, and tracing and
+//! snapshot-aware warming need optional cargo features. Rather than failing on (or
+//! quietly ignoring) each of these in its own place, [`negotiate`] goes through them
+//! once: anything requested that can't be provided is downgraded to the nearest thing
+//! that can, and the resulting [`SupportMatrix`] is printed at startup, summarized in one
+//! notice for every downgrade, and recorded in the JSON report. Only a request that
+//! leaves nothing to run at all is an error.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::anonymize;
+use crate::probe::DirectIoProbe;
+use crate::warming::{DropCaches, FallbackPolicy, Strategy, StrategyRegistry, WarmingOptions};
+
+/// What the platform and build provide, beyond the strategies' own probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Platform {
+    /// Residency checks, FIEMAP and global cache drops are Linux-only
+    pub linux: bool,
+    /// Built with the `otel` feature (`--otlp-endpoint`)
+    pub otel: bool,
+    /// Built with the `aws` feature (`--ebs-snapshot-id`, `--sqs-queue-url`)
+    pub aws: bool,
+    /// Built with the `zstd` feature (`*.zst` logs)
+    pub zstd: bool,
+    /// Built with the `tui` feature (`--tui`)
+    pub tui: bool,
+    /// O_DIRECT works on every target filesystem, as far as [`crate::probe::direct_io`]
+    /// could tell
+    pub direct_io: bool,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        Self {
+            linux: cfg!(target_os = "linux"),
+            otel: cfg!(feature = "otel"),
+            aws: cfg!(feature = "aws"),
+            zstd: cfg!(feature = "zstd"),
+            tui: cfg!(feature = "tui"),
+            direct_io: true,
+        }
+    }
+}
+
+/// Requested options outside [`WarmingOptions`] that depend on the platform or build;
+/// [`negotiate`] switches off the ones that can't be provided
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunRequests {
+    /// `--physical-order`
+    pub physical_order: bool,
+    /// `--otlp-endpoint`
+    pub tracing: bool,
+    /// `--ebs-snapshot-id`
+    pub snapshot_ranges: bool,
+    /// `--small-file-size` given explicitly
+    pub small_files: bool,
+    /// `--tui`
+    pub dashboard: bool,
+}
+
+/// One registered strategy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StrategySupport {
+    pub strategy: &'static str,
+    /// Passed its probe on this host
+    pub available: bool,
+    /// Can read with O_DIRECT
+    pub direct_io: bool,
+    /// Can read through the page cache
+    pub buffered: bool,
+    /// Named by `--strategy`
+    pub requested: bool,
+    /// Part of the plan each file is offered to
+    pub planned: bool,
+    /// Why it's unavailable, from its probe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// One requested option and what it was negotiated to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Negotiated {
+    /// The option, as on the command line
+    pub option: &'static str,
+    pub requested: String,
+    pub granted: String,
+    /// Why it was downgraded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Negotiated {
+    fn granted(option: &'static str, value: impl fmt::Display) -> Self {
+        Self { option, requested: value.to_string(), granted: value.to_string(), reason: None }
+    }
+
+    fn downgraded(option: &'static str, requested: impl fmt::Display, granted: impl fmt::Display, reason: impl Into<String>) -> Self {
+        Self { option, requested: requested.to_string(), granted: granted.to_string(), reason: Some(reason.into()) }
+    }
+
+    pub fn is_downgraded(&self) -> bool {
+        self.reason.is_some()
+    }
+}
+
+impl fmt::Display for Negotiated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            None => write!(f, "{} {} ✓", self.option, self.requested),
+            Some(reason) => write!(f, "{} {} → {} ({})", self.option, self.requested, self.granted, reason),
+        }
+    }
+}
+
+/// The negotiated configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SupportMatrix {
+    pub platform: Platform,
+    pub strategies: Vec<StrategySupport>,
+    /// Platform-dependent options that were requested, in command-line terms
+    pub options: Vec<Negotiated>,
+    /// O_DIRECT probes of the targets, when direct I/O was requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub direct_io: Vec<DirectIoProbe>,
+}
+
+impl SupportMatrix {
+    pub fn downgrades(&self) -> impl Iterator<Item = &Negotiated> {
+        self.options.iter().filter(|option| option.is_downgraded())
+    }
+
+    /// Every downgrade in one line, or `None` if everything requested was granted
+    pub fn notice(&self) -> Option<String> {
+        let downgrades: Vec<String> = self.downgrades().map(|option| option.to_string()).collect();
+        (!downgrades.is_empty()).then(|| format!("Not supported here, so downgraded: {}", downgrades.join("; ")))
+    }
+
+    /// The startup banner's lines
+    pub fn lines(&self) -> Vec<String> {
+        let strategies: Vec<String> = self
+            .strategies
+            .iter()
+            .map(|support| {
+                let modes = match (support.available, support.buffered, support.direct_io) {
+                    (false, _, _) => match &support.reason {
+                        Some(reason) => format!(" ✗ ({})", reason),
+                        None => " ✗".to_string(),
+                    },
+                    (true, true, false) => " ✓ (buffered)".to_string(),
+                    (true, false, true) => " ✓ (direct)".to_string(),
+                    _ => " ✓".to_string(),
+                };
+                format!("{}{}{}", support.strategy, modes, if support.requested { " ←" } else { "" })
+            })
+            .collect();
+        let yes_no = |enabled: bool| if enabled { "✓" } else { "✗" };
+        let mut lines = vec![
+            format!("Strategies: {}", strategies.join(", ")),
+            format!(
+                "Platform: linux {}, otel {}, aws {}, zstd {}, tui {}",
+                yes_no(self.platform.linux),
+                yes_no(self.platform.otel),
+                yes_no(self.platform.aws),
+                yes_no(self.platform.zstd),
+                yes_no(self.platform.tui)
+            ),
+        ];
+        if !self.direct_io.is_empty() {
+            let targets: Vec<String> = self.direct_io.iter().map(|probe| format!("{} {}", anonymize::display(&probe.root), probe.capability)).collect();
+            lines.push(format!("O_DIRECT: {}", targets.join(", ")));
+        }
+        if !self.options.is_empty() {
+            let options: Vec<String> = self.options.iter().map(|option| option.to_string()).collect();
+            lines.push(format!("Requested: {}", options.join(", ")));
+        }
+        lines
+    }
+}
+
+/// Fit `warming` and `run` to what `registry` and `platform` provide, downgrading what
+/// they can't. Fails only if no strategy could warm a file, e.g. an unavailable
+/// `--strategy` with `--strategy-fallback none`.
+pub fn negotiate(registry: &StrategyRegistry, warming: &mut WarmingOptions, run: &mut RunRequests, platform: &Platform) -> Result<SupportMatrix, String> {
+    let mut options = Vec::new();
+
+    // Before the strategy, whose plan depends on the I/O mode
+    if warming.use_direct_io && !platform.direct_io {
+        warming.use_direct_io = false;
+        options.push(Negotiated::downgraded("--direct-io", "on", "off", "a target filesystem refuses O_DIRECT"));
+    }
+
+    // The strategy: the plan already skips what can't run, so only the reason is recorded
+    if warming.strategy != Strategy::Auto {
+        let requested = warming.strategy;
+        let supports = |direct_io| registry.backend(requested).is_some_and(|backend| backend.supports(direct_io));
+        if !registry.is_available(requested) {
+            if warming.fallback == FallbackPolicy::None {
+                return Err(format!("'{}' is unavailable on this host and --strategy-fallback none allows nothing else", requested));
+            }
+            let plan = registry.plan(warming);
+            let granted = plan.first().map_or("nothing", |strategy| strategy.name());
+            let reason = registry.probe().iter().find(|probe| probe.strategy == requested).and_then(|probe| probe.reason.clone());
+            let reason = reason.map_or_else(|| "unavailable on this host".to_string(), |reason| format!("unavailable on this host: {}", reason));
+            options.push(Negotiated::downgraded("--strategy", requested, granted, reason));
+        } else if !supports(warming.use_direct_io) {
+            if warming.fallback == FallbackPolicy::None || registry.plan(warming).is_empty() {
+                // The strategy was pinned, so it's the I/O mode that gives way
+                let (requested_mode, granted_mode) = if warming.use_direct_io { ("on", "off") } else { ("off", "on") };
+                let reason = format!("{} {}", requested, if warming.use_direct_io { "doesn't support it" } else { "requires it" });
+                warming.use_direct_io = !warming.use_direct_io;
+                options.push(Negotiated::granted("--strategy", requested));
+                options.push(Negotiated::downgraded("--direct-io", requested_mode, granted_mode, reason));
+            } else {
+                let plan = registry.plan(warming);
+                let mode = if warming.use_direct_io { "--direct-io" } else { "buffered reads" };
+                options.push(Negotiated::downgraded("--strategy", requested, plan[0], format!("doesn't support {}", mode)));
+            }
+        } else {
+            options.push(Negotiated::granted("--strategy", requested));
+        }
+    }
+    if registry.plan(warming).is_empty() {
+        return Err(format!("no registered strategy can warm files with strategy '{}' and --strategy-fallback {:?}", warming.strategy, warming.fallback));
+    }
+
+    let uring = registry.is_available(Strategy::Uring);
+    if warming.nowait_precheck {
+        let reads_buffered = registry.plan(warming).iter().any(|strategy| matches!(strategy, Strategy::Uring | Strategy::Tokio));
+        let why_not = if !platform.linux {
+            Some("preadv2 RWF_NOWAIT needs Linux")
+        } else if warming.use_direct_io {
+            Some("direct I/O bypasses the page cache")
+        } else if !reads_buffered {
+            Some("only the tokio and uring strategies read with it")
+        } else {
+            None
+        };
+        match why_not {
+            None => options.push(Negotiated::granted("--nowait-precheck", "on")),
+            Some(why) => {
+                warming.nowait_precheck = false;
+                options.push(Negotiated::downgraded("--nowait-precheck", "on", "off", why));
+            }
+        }
+    }
+    if run.small_files && warming.small_file_size > 0 {
+        if uring {
+            options.push(Negotiated::granted("--small-file-size", warming.small_file_size));
+        } else {
+            options.push(Negotiated::downgraded("--small-file-size", warming.small_file_size, 0, "needs io_uring"));
+            warming.small_file_size = 0;
+            run.small_files = false;
+        }
+    }
+    if warming.skip_cached {
+        if platform.linux {
+            options.push(Negotiated::granted("--skip-cached", "on"));
+        } else {
+            warming.skip_cached = false;
+            options.push(Negotiated::downgraded("--skip-cached", "on", "off", "page-cache residency checks need Linux"));
+        }
+    }
+    if warming.drop_caches == DropCaches::Global {
+        if platform.linux {
+            options.push(Negotiated::granted("--drop-caches-after", DropCaches::Global));
+        } else {
+            warming.drop_caches = DropCaches::File;
+            options.push(Negotiated::downgraded("--drop-caches-after", DropCaches::Global, DropCaches::File, "dropping the whole page cache needs Linux"));
+        }
+    }
+    if run.physical_order {
+        if platform.linux {
+            options.push(Negotiated::granted("--physical-order", "on"));
+        } else {
+            run.physical_order = false;
+            options.push(Negotiated::downgraded("--physical-order", "on", "off", "FIEMAP needs Linux"));
+        }
+    }
+    if run.tracing {
+        if platform.otel {
+            options.push(Negotiated::granted("--otlp-endpoint", "on"));
+        } else {
+            run.tracing = false;
+            options.push(Negotiated::downgraded("--otlp-endpoint", "on", "off", "built without the 'otel' feature"));
+        }
+    }
+    if run.snapshot_ranges {
+        if platform.aws {
+            options.push(Negotiated::granted("--ebs-snapshot-id", "on"));
+        } else {
+            run.snapshot_ranges = false;
+            options.push(Negotiated::downgraded("--ebs-snapshot-id", "on", "off (every block)", "built without the 'aws' feature"));
+        }
+    }
+    if run.dashboard {
+        if platform.tui {
+            options.push(Negotiated::granted("--tui", "on"));
+        } else {
+            run.dashboard = false;
+            options.push(Negotiated::downgraded("--tui", "on", "off (progress bars)", "built without the 'tui' feature"));
+        }
+    }
+
+    let plan = registry.plan(warming);
+    let strategies = registry
+        .probe()
+        .iter()
+        .map(|probe| {
+            let backend = registry.backend(probe.strategy);
+            StrategySupport {
+                strategy: probe.strategy.name(),
+                available: probe.available,
+                direct_io: backend.is_some_and(|backend| backend.supports(true)),
+                buffered: backend.is_some_and(|backend| backend.supports(false)),
+                requested: warming.strategy == probe.strategy,
+                planned: plan.contains(&probe.strategy),
+                reason: probe.reason.clone(),
+            }
+        })
+        .collect();
+    Ok(SupportMatrix { platform: *platform, strategies, options, direct_io: Vec::new() })
+}
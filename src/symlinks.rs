@@ -0,0 +1,93 @@
+//! Target directories reached through symlinks.
+//!
+//! `/data` is often a symlink to wherever the volume is mounted (`/mnt/vol1`). Walking
+//! it as given works, but every discovered path is then spelled through the link, so a
+//! checkpoint written by `rust-cache-warmer /data` means nothing to a resumed run given
+//! `/mnt/vol1`, and two targets naming the same tree both ways aren't recognized as one.
+//! With [`SymlinkRoots::Resolve`], a target whose path goes through a symlink is
+//! replaced by its canonical path before anything else looks at it: files are
+//! discovered, deduplicated, checkpointed and logged under the real tree, and the
+//! spelling the user gave is kept alongside for display and the report.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// What to do with a target directory whose path goes through a symlink
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkRoots {
+    /// Warm the real tree, keying everything on its canonical path
+    #[default]
+    Resolve,
+    /// Walk through the link as given
+    Keep,
+}
+
+impl FromStr for SymlinkRoots {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "resolve" => Ok(SymlinkRoots::Resolve),
+            "keep" => Ok(SymlinkRoots::Keep),
+            other => Err(format!("unknown symlinked root handling '{}' (expected 'resolve' or 'keep')", other)),
+        }
+    }
+}
+
+impl fmt::Display for SymlinkRoots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymlinkRoots::Resolve => write!(f, "resolve"),
+            SymlinkRoots::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+/// A target directory given through a symlink, and the directory it leads to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedRoot {
+    /// As given on the command line
+    pub target: PathBuf,
+    /// Canonical path of the directory
+    pub resolved: PathBuf,
+}
+
+/// The canonical path of `target` if getting there goes through a symlink (or `..`).
+/// Targets that don't exist, or that are already canonical apart from `.` components
+/// and trailing slashes, give `None`.
+pub fn resolve_root(target: &Path) -> Option<PathBuf> {
+    let resolved = std::fs::canonicalize(target).ok()?;
+    let absolute = std::path::absolute(target).ok()?;
+    let as_given: PathBuf = absolute.components().filter(|component| *component != Component::CurDir).collect();
+    (as_given != resolved).then_some(resolved)
+}
+
+/// Resolve every target that goes through a symlink, returning the directories to warm,
+/// each once however many spellings of it were given, and the targets that were replaced
+pub fn resolve_roots(targets: &[PathBuf]) -> (Vec<PathBuf>, Vec<LinkedRoot>) {
+    let mut linked = Vec::new();
+    let mut directories: Vec<PathBuf> = Vec::new();
+    for target in targets {
+        let directory = match resolve_root(target) {
+            Some(resolved) => {
+                linked.push(LinkedRoot { target: target.clone(), resolved: resolved.clone() });
+                resolved
+            }
+            None => target.clone(),
+        };
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+    (directories, linked)
+}
+
+/// `path` spelled through the link of whichever of `linked` it lies under, rewritten to
+/// the resolved directory; `None` if it isn't under any of them
+pub fn rebase(path: &Path, linked: &[LinkedRoot]) -> Option<PathBuf> {
+    linked.iter().find_map(|root| {
+        let relative = path.strip_prefix(&root.target).ok()?;
+        Some(if relative.as_os_str().is_empty() { root.resolved.clone() } else { root.resolved.join(relative) })
+    })
+}
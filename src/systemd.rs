@@ -0,0 +1,256 @@
+//! systemd integration (`--systemd`).
+//!
+//! Run as a `Type=notify` unit, the warmer tells the service manager it's ready as soon
+//! as it has started, keeps `STATUS=` up to date with files, bytes and percent complete
+//! (shown by `systemctl status`), and pings the watchdog when the unit sets
+//! `WatchdogSec=`. Without this a boot-time run of half an hour looks hung.
+//!
+//! When stderr is connected to the journal, log records are sent with journald's native
+//! protocol instead, so each entry has its priority and carries the run's progress as
+//! structured fields: `FILES_WARMED`, `BYTES_WARMED` and `PERCENT`. These can be
+//! queried with e.g. `journalctl -u warmer -o json`.
+//!
+//! Both protocols are plain datagrams on a Unix socket, so no libsystemd is needed.
+//! Outside a unit (no `NOTIFY_SOCKET`, no `JOURNAL_STREAM`) `--systemd` does nothing.
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use log::{debug, Level, Log, Metadata, Record};
+use tokio::sync::mpsc;
+
+use crate::events::WarmEvent;
+use crate::live::LiveStats;
+use crate::progress::ByteProgress;
+
+/// How often `STATUS=` is updated while warming
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where journald listens for native-protocol entries
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Progress attached to journal entries, updated by [`run_status`]
+static FILES_WARMED: AtomicU64 = AtomicU64::new(0);
+static BYTES_WARMED: AtomicU64 = AtomicU64::new(0);
+static PERCENT: AtomicU64 = AtomicU64::new(0);
+
+/// Sends `sd_notify` messages to the service manager
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    socket: String,
+}
+
+impl Notifier {
+    /// The notifier for `$NOTIFY_SOCKET`, if the process was started by systemd with one
+    pub fn from_env() -> Option<Self> {
+        std::env::var("NOTIFY_SOCKET").ok().filter(|socket| !socket.is_empty()).map(|socket| Self { socket })
+    }
+
+    /// Send newline-separated `KEY=value` assignments, e.g. `READY=1\nSTATUS=...`
+    pub fn notify(&self, state: &str) -> Result<(), std::io::Error> {
+        send_datagram(&self.socket, state.as_bytes())
+    }
+
+    /// Like [`Notifier::notify`], logging failures at debug level rather than returning them
+    pub fn notify_quietly(&self, state: &str) {
+        if let Err(e) = self.notify(state) {
+            debug!("sd_notify to {} failed: {}", self.socket, e);
+        }
+    }
+
+    /// How often to send `WATCHDOG=1`: half of `$WATCHDOG_USEC`, if the watchdog is
+    /// enabled for this process
+    pub fn watchdog_interval() -> Option<Duration> {
+        if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+            if pid.parse::<u32>().ok() != Some(std::process::id()) {
+                return None;
+            }
+        }
+        let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        (usec > 0).then(|| Duration::from_micros(usec / 2))
+    }
+}
+
+#[cfg(unix)]
+fn send_datagram(socket: &str, data: &[u8]) -> Result<(), std::io::Error> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(data, &address)?;
+        }
+        _ => {
+            sender.send_to(data, socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_datagram(_socket: &str, _data: &[u8]) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "systemd sockets need a Unix platform"))
+}
+
+/// Ping the watchdog every `interval` until the task is dropped with the runtime
+pub fn spawn_watchdog(notifier: Notifier, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notifier.notify_quietly("WATCHDOG=1");
+        }
+    })
+}
+
+/// The `STATUS=` text for a run at this point
+pub fn status_line(stats: &LiveStats, progress: &ByteProgress) -> String {
+    let snapshot = stats.snapshot();
+    let mut status = format!(
+        "Warming: {} files, {:.1} MB warmed",
+        snapshot.warmed,
+        snapshot.bytes as f64 / (1024.0 * 1024.0)
+    );
+    if let Some(percent) = percent(progress) {
+        let _ = write!(status, ", {}%{}", percent, if progress.is_exact() { "" } else { " (estimated)" });
+    }
+    if snapshot.failed > 0 {
+        let _ = write!(status, ", {} failed", snapshot.failed);
+    }
+    status
+}
+
+fn percent(progress: &ByteProgress) -> Option<u64> {
+    let total = progress.total();
+    (total > 0).then(|| (progress.done_bytes().saturating_mul(100) / total).min(100))
+}
+
+/// Follow `events`, keeping the journal fields current and sending `STATUS=` every
+/// [`STATUS_INTERVAL`], until the event stream ends
+pub async fn run_status(notifier: Option<Notifier>, progress: ByteProgress, mut events: mpsc::UnboundedReceiver<WarmEvent>) {
+    let mut stats = LiveStats::new();
+    let mut ticker = tokio::time::interval(STATUS_INTERVAL);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    stats.record(&event);
+                    let snapshot = stats.snapshot();
+                    FILES_WARMED.store(snapshot.warmed, Ordering::Relaxed);
+                    BYTES_WARMED.store(snapshot.bytes, Ordering::Relaxed);
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                stats.sample(Instant::now());
+                PERCENT.store(percent(&progress).unwrap_or(0), Ordering::Relaxed);
+                if let Some(notifier) = &notifier {
+                    notifier.notify_quietly(&format!("STATUS={}", status_line(&stats, &progress)));
+                }
+            }
+        }
+    }
+    PERCENT.store(percent(&progress).unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Whether stderr is the journal, going by `$JOURNAL_STREAM`
+#[cfg(unix)]
+pub fn stderr_is_journal() -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some((device, inode)) = std::env::var("JOURNAL_STREAM").ok().and_then(|stream| {
+        let (device, inode) = stream.split_once(':')?;
+        Some((device.parse::<u64>().ok()?, inode.parse::<u64>().ok()?))
+    }) else {
+        return false;
+    };
+    // Compare against fd 2 itself: a unit's stderr can be redirected away from the journal
+    std::fs::metadata("/proc/self/fd/2")
+        .or_else(|_| std::fs::metadata("/dev/stderr"))
+        .is_ok_and(|metadata| metadata.dev() == device && metadata.ino() == inode)
+}
+
+#[cfg(not(unix))]
+pub fn stderr_is_journal() -> bool {
+    false
+}
+
+/// Encode one journal entry in the native protocol. Values containing a newline use the
+/// length-prefixed form.
+pub fn encode_entry(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    for (key, value) in fields {
+        entry.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+/// journald priority for a log level
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+/// `log` backend that writes native journal entries, filtered like env_logger. Entries
+/// that can't be sent (an oversized message, journald restarting) go to stderr instead.
+pub struct JournalLogger {
+    filter: env_logger::Logger,
+}
+
+impl JournalLogger {
+    pub fn new(filter: env_logger::Logger) -> Self {
+        Self { filter }
+    }
+
+    /// Install as the global logger
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.filter.filter());
+        log::set_boxed_logger(Box::new(self))
+    }
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let message = record.args().to_string();
+        let files = FILES_WARMED.load(Ordering::Relaxed).to_string();
+        let bytes = BYTES_WARMED.load(Ordering::Relaxed).to_string();
+        let percent = PERCENT.load(Ordering::Relaxed).to_string();
+        let entry = encode_entry(&[
+            ("MESSAGE", &message),
+            ("PRIORITY", priority(record.level())),
+            ("SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME")),
+            ("TARGET", record.target()),
+            ("FILES_WARMED", &files),
+            ("BYTES_WARMED", &bytes),
+            ("PERCENT", &percent),
+        ]);
+        if send_datagram(JOURNAL_SOCKET, &entry).is_err() {
+            eprintln!("[{} {}] {}", record.level(), record.target(), message);
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,174 @@
+//! Distributed tracing (`--otlp-endpoint`, `otel` feature).
+//!
+//! With the `otel` feature, a run can export `tracing` spans over OTLP: one for the run,
+//! one per discovery walk, per batch, per file and per strategy attempt. Everything
+//! logged through `log` is bridged into the same subscriber, so log lines show up as
+//! events on the span they were emitted in. When `TRACEPARENT` (and optionally
+//! `TRACESTATE`) is set, as a deployment pipeline can do for the steps it runs, the run
+//! span joins that trace instead of starting a new one.
+//!
+//! Without the feature the span helpers return a zero-sized [`Span`] and `instrument`
+//! is a no-op, so call sites don't need `cfg` attributes of their own.
+
+use std::path::Path;
+
+/// Service name reported unless `OTEL_SERVICE_NAME` is set
+pub const SERVICE_NAME: &str = "rust-cache-warmer";
+
+#[cfg(feature = "otel")]
+pub use tracing::{Instrument, Span};
+
+#[cfg(not(feature = "otel"))]
+pub use noop::{Instrument, Span};
+
+#[cfg(feature = "otel")]
+pub use otel::Telemetry;
+
+/// Span covering a whole run
+#[cfg(feature = "otel")]
+pub fn run_span(target: &str) -> Span {
+    use opentelemetry::propagation::TextMapPropagator;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let span = tracing::info_span!("warm_run", target = %target);
+    if let Ok(traceparent) = std::env::var("TRACEPARENT") {
+        let mut carrier = std::collections::HashMap::new();
+        carrier.insert("traceparent".to_string(), traceparent);
+        if let Ok(tracestate) = std::env::var("TRACESTATE") {
+            carrier.insert("tracestate".to_string(), tracestate);
+        }
+        span.set_parent(TraceContextPropagator::new().extract(&carrier));
+    }
+    span
+}
+
+/// Span for the discovery walk of one fair-share group
+#[cfg(feature = "otel")]
+pub fn discovery_span(share: &str) -> Span {
+    tracing::info_span!("discover", share = %share)
+}
+
+/// Span for one batch of files
+#[cfg(feature = "otel")]
+pub fn batch_span(share: usize, files: usize, bytes: u64) -> Span {
+    tracing::info_span!("batch", share, files, bytes)
+}
+
+/// Span for warming one file; `status`, `method` and `bytes_read` are recorded when it
+/// finishes
+#[cfg(feature = "otel")]
+pub fn file_span(path: &Path, size: u64) -> Span {
+    tracing::info_span!(
+        "warm_file",
+        path = %crate::anonymize::display(path),
+        size,
+        status = tracing::field::Empty,
+        method = tracing::field::Empty,
+        bytes_read = tracing::field::Empty,
+    )
+}
+
+/// Span for one strategy's attempt at a file
+#[cfg(feature = "otel")]
+pub fn strategy_span(strategy: &'static str) -> Span {
+    tracing::debug_span!("strategy", strategy)
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn run_span(_target: &str) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn discovery_span(_share: &str) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn batch_span(_share: usize, _files: usize, _bytes: u64) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn file_span(_path: &Path, _size: u64) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn strategy_span(_strategy: &'static str) -> Span {
+    Span
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use opentelemetry_sdk::{runtime, Resource};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::EnvFilter;
+
+    use super::SERVICE_NAME;
+
+    /// The installed exporter; call [`Telemetry::shutdown`] before exiting so buffered
+    /// spans are sent
+    #[derive(Debug)]
+    pub struct Telemetry {
+        provider: TracerProvider,
+    }
+
+    impl Telemetry {
+        /// Install a `tracing` subscriber that logs to stderr (filtered by `RUST_LOG`, or
+        /// `default_level`) and exports spans over OTLP/gRPC to `endpoint`. Must be
+        /// called inside the Tokio runtime.
+        pub fn init(endpoint: &str, default_level: &str) -> anyhow::Result<Self> {
+            let exporter = opentelemetry_otlp::SpanExporter::builder().with_tonic().with_endpoint(endpoint).build()?;
+            let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| SERVICE_NAME.to_string());
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_resource(Resource::new([KeyValue::new("service.name", service)]))
+                .build();
+            let tracer = provider.tracer(SERVICE_NAME);
+            let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+            // `init` also routes the `log` macros into the subscriber
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .try_init()?;
+            Ok(Self { provider })
+        }
+
+        /// Flush buffered spans and stop the exporter
+        pub fn shutdown(self) {
+            if let Err(e) = self.provider.shutdown() {
+                eprintln!("Failed to flush OTLP spans: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+mod noop {
+    /// Stand-in for `tracing::Span` when built without the `otel` feature
+    #[derive(Debug, Clone, Default)]
+    pub struct Span;
+
+    impl Span {
+        pub fn record<V>(&self, _field: &str, _value: V) -> &Self {
+            self
+        }
+    }
+
+    /// Stand-in for `tracing::Instrument`: returns the future unchanged
+    pub trait Instrument: Sized {
+        fn instrument(self, _span: Span) -> Self {
+            self
+        }
+    }
+
+    impl<F: std::future::Future> Instrument for F {}
+}
@@ -0,0 +1,295 @@
+//! A simulated cold block device for testing warming strategies (the `testing` feature).
+//!
+//! What a warm is for — every block of an EBS volume restored from a snapshot is slow
+//! the first time it's read — can't be reproduced on a developer's disk, and whether a
+//! strategy read the offsets it should have is invisible once it reports success.
+//! [`ColdDevice`] simulates both on top of ordinary files:
+//!
+//! - [`ColdDevice::create`] writes a file, syncs it and evicts it from the page cache. The
+//!   device counts every block of it as cold, like a snapshot block not yet fetched.
+//! - [`ColdDevice::registry`] wraps each builtin backend in a [`SlowBackend`]. Before a
+//!   warm of a device file it evicts the file again, so the pages resident afterwards
+//!   (found with `mincore(2)`) are the ones the warm read. Those blocks become hydrated
+//!   for good, as on EBS, and the warm is held back by the configured latency for each
+//!   one that was cold. Every warm is recorded as an [`Access`].
+//!
+//! Direct I/O leaves nothing in the page cache, so direct reads can't be located; the
+//! device charges them by the bytes the strategy reports reading. `readahead(2)` and
+//! advisory strategies read asynchronously, so the device waits for the resident pages
+//! to show up and stop changing (up to [`SETTLE_TIMEOUT`]) before it looks. Paths that
+//! bypass the backends (the small-file ring path, range-parallel reads, compressed
+//! extents and range selection) aren't seen at all.
+//!
+//! Eviction needs `posix_fadvise(POSIX_FADV_DONTNEED)` to drop clean pages, which tmpfs
+//! and some overlay setups don't do; [`ColdDevice::create`] fails there, so tests can
+//! skip.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+
+use crate::warming::{residency, DropCaches, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+/// Block size of a device unless given: one page, so offsets can be checked exactly
+pub const DEFAULT_BLOCK_SIZE: u64 = 4096;
+/// Longest wait for reads a strategy queued to land in the page cache
+pub const SETTLE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Pause between residency scans while they settle
+const SETTLE_POLL: Duration = Duration::from_millis(5);
+
+/// One warm of a device file, as the device saw it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub strategy: Strategy,
+    pub direct_io: bool,
+    /// Blocks the warm read, in order; empty for direct reads, which can't be located
+    pub blocks: Vec<u64>,
+    /// Blocks that were cold before this warm
+    pub cold_blocks: u64,
+    /// Latency charged for them
+    pub latency: Duration,
+}
+
+#[derive(Debug, Default)]
+struct FileState {
+    size: u64,
+    hydrated: BTreeSet<u64>,
+    /// Blocks charged for direct reads, which can't be told apart
+    unlocated: u64,
+    accesses: Vec<Access>,
+}
+
+/// A directory of files that are cold until read, with a first-read penalty per block
+#[derive(Debug)]
+pub struct ColdDevice {
+    root: PathBuf,
+    block_size: u64,
+    latency: Duration,
+    files: Mutex<HashMap<PathBuf, FileState>>,
+}
+
+impl ColdDevice {
+    /// An empty device in `root`, which is removed and created again. Reading a cold
+    /// block of `block_size` bytes costs `latency`.
+    pub fn new(root: impl Into<PathBuf>, block_size: u64, latency: Duration) -> io::Result<Arc<Self>> {
+        let root = root.into();
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root)?;
+        Ok(Arc::new(Self { root, block_size: block_size.max(1), latency, files: Mutex::new(HashMap::new()) }))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Blocks a file of `size` bytes spans
+    pub fn blocks_of(&self, size: u64) -> u64 {
+        size.div_ceil(self.block_size)
+    }
+
+    /// Write `name` (`size` bytes, every block different) under the device root, all cold
+    pub fn create(&self, name: &str, size: u64) -> io::Result<PathBuf> {
+        use std::io::Write;
+
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let mut written = 0u64;
+        while written < size {
+            let block = (written / self.block_size).to_le_bytes();
+            let len = (size - written).min(block.len() as u64) as usize;
+            file.write_all(&block[..len])?;
+            written += len as u64;
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        self.evict(&path, size)?;
+        self.files.lock().unwrap().insert(path.clone(), FileState { size, ..Default::default() });
+        Ok(path)
+    }
+
+    /// Drop `path`'s pages from the page cache; fails if any stay resident
+    pub fn evict(&self, path: &Path, size: u64) -> io::Result<()> {
+        evict(path)?;
+        if size > 0 && residency::scan(path, size)?.resident_bytes != 0 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} can't be evicted from the page cache here", path.display())));
+        }
+        Ok(())
+    }
+
+    /// Blocks of `path` in the page cache now
+    pub fn resident_blocks(&self, path: &Path) -> io::Result<BTreeSet<u64>> {
+        let size = self.size(path).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not on the device", path.display())))?;
+        let residency = residency::scan(path, size)?;
+        let all: BTreeSet<u64> = (0..self.blocks_of(size)).collect();
+        let cold: BTreeSet<u64> = residency
+            .cold_ranges
+            .iter()
+            // A block is resident only if every page of it is
+            .flat_map(|&(offset, len)| offset / self.block_size..(offset + len).div_ceil(self.block_size))
+            .collect();
+        Ok(all.difference(&cold).copied().collect())
+    }
+
+    fn size(&self, path: &Path) -> Option<u64> {
+        self.files.lock().unwrap().get(path).map(|file| file.size)
+    }
+
+    /// Blocks of `path` read at least once
+    pub fn hydrated(&self, path: &Path) -> BTreeSet<u64> {
+        self.files.lock().unwrap().get(path).map(|file| file.hydrated.clone()).unwrap_or_default()
+    }
+
+    /// Blocks of `path` never read; direct reads count against them without saying which
+    pub fn cold_blocks(&self, path: &Path) -> u64 {
+        let files = self.files.lock().unwrap();
+        let Some(file) = files.get(path) else { return 0 };
+        self.blocks_of(file.size).saturating_sub(file.hydrated.len() as u64 + file.unlocated)
+    }
+
+    /// Every warm of `path` so far
+    pub fn accesses(&self, path: &Path) -> Vec<Access> {
+        self.files.lock().unwrap().get(path).map(|file| file.accesses.clone()).unwrap_or_default()
+    }
+
+    /// Total latency charged across the device
+    pub fn latency_charged(&self) -> Duration {
+        self.files.lock().unwrap().values().flat_map(|file| &file.accesses).map(|access| access.latency).sum()
+    }
+
+    /// The builtin backends, each behind a [`SlowBackend`] on this device
+    pub fn registry(self: &Arc<Self>) -> StrategyRegistry {
+        let builtin = StrategyRegistry::builtin();
+        let mut registry = StrategyRegistry::new();
+        for strategy in Strategy::ALL {
+            if let Some(inner) = builtin.backend(strategy) {
+                registry.register(Arc::new(SlowBackend { inner: Arc::clone(inner), device: Arc::clone(self) }));
+            }
+        }
+        registry
+    }
+
+    /// Hydrate the blocks a warm of `path` read and record it; returns the latency owed
+    fn record(&self, path: &Path, strategy: Strategy, direct_io: bool, blocks: Vec<u64>, direct_bytes: u64) -> Duration {
+        let mut files = self.files.lock().unwrap();
+        let Some(file) = files.get_mut(path) else { return Duration::ZERO };
+        let total = file.size.div_ceil(self.block_size);
+        let cold_blocks = if direct_io {
+            let cold = total.saturating_sub(file.hydrated.len() as u64 + file.unlocated);
+            let charged = direct_bytes.div_ceil(self.block_size).min(cold);
+            file.unlocated += charged;
+            charged
+        } else {
+            blocks.iter().filter(|&&block| file.hydrated.insert(block)).count() as u64
+        };
+        let latency = self.latency.saturating_mul(cold_blocks.min(u32::MAX as u64) as u32);
+        file.accesses.push(Access { strategy, direct_io, blocks, cold_blocks, latency });
+        latency
+    }
+}
+
+/// A backend whose warms of [`ColdDevice`] files are traced and slowed down by the device
+pub struct SlowBackend {
+    inner: Arc<dyn WarmingBackend>,
+    device: Arc<ColdDevice>,
+}
+
+impl SlowBackend {
+    pub fn new(inner: Arc<dyn WarmingBackend>, device: Arc<ColdDevice>) -> Self {
+        Self { inner, device }
+    }
+
+    /// The resident blocks of `path` once reads still in flight have landed: pages under
+    /// read only show up in `mincore(2)` when their I/O completes
+    async fn settled_blocks(&self, path: &Path) -> io::Result<BTreeSet<u64>> {
+        let mut blocks = self.device.resident_blocks(path)?;
+        if !(self.inner.is_advisory() || self.strategy() == Strategy::Readahead) {
+            return Ok(blocks);
+        }
+        let deadline = tokio::time::Instant::now() + SETTLE_TIMEOUT;
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(SETTLE_POLL).await;
+            let now = self.device.resident_blocks(path)?;
+            // Nothing resident may just mean the I/O hasn't started
+            if now == blocks && !now.is_empty() {
+                break;
+            }
+            blocks = now;
+        }
+        Ok(blocks)
+    }
+}
+
+impl WarmingBackend for SlowBackend {
+    fn strategy(&self) -> Strategy {
+        self.inner.strategy()
+    }
+
+    fn probe(&self) -> bool {
+        self.inner.probe()
+    }
+
+    fn unavailable_reason(&self) -> Option<String> {
+        self.inner.unavailable_reason()
+    }
+
+    fn supports(&self, use_direct_io: bool) -> bool {
+        self.inner.supports(use_direct_io)
+    }
+
+    fn is_advisory(&self) -> bool {
+        self.inner.is_advisory()
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            if self.device.size(path).is_none() {
+                return self.inner.warm(path, file_size, options).await;
+            }
+            evict(path)?;
+            // Pages dropped by the strategy itself couldn't be seen, so drop them afterwards
+            let traced = WarmingOptions { drop_caches: DropCaches::None, ..options.clone() };
+            let mut result = self.inner.warm(path, file_size, &traced).await?;
+            let blocks = if options.use_direct_io { Vec::new() } else { self.settled_blocks(path).await?.into_iter().collect() };
+            let latency = self.device.record(path, self.strategy(), options.use_direct_io, blocks, result.bytes_read);
+            if !latency.is_zero() {
+                tokio::time::sleep(latency).await;
+                result.duration += latency;
+            }
+            if options.drop_caches.per_file() {
+                evict(path)?;
+            }
+            Ok(result)
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn evict(path: &Path) -> io::Result<()> {
+    use std::os::unix::prelude::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn evict(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "evicting files from the page cache needs Linux"))
+}
@@ -0,0 +1,197 @@
+//! node_exporter textfile output.
+//!
+//! Most fleets already run node_exporter, whose textfile collector exports any `*.prom`
+//! file in a directory. Rather than serve metrics itself, the warmer keeps cumulative
+//! counters from the event stream and periodically rewrites `rust_cache_warmer.prom`
+//! there (plus once more when the run ends), so a run's progress and result show up in
+//! Prometheus with no extra moving parts.
+//!
+//! The file is written to a temporary name and renamed into place, so the collector
+//! never scrapes a half-written file.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use tokio::sync::mpsc;
+
+use crate::events::WarmEvent;
+use crate::stats::FileStatus;
+
+/// Name of the file written to `--textfile-dir`
+pub const FILE_NAME: &str = "rust_cache_warmer.prom";
+
+/// Upper bounds of the per-file latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 9] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0];
+
+/// Settings for the textfile writer
+#[derive(Debug, Clone)]
+pub struct TextfileOptions {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    /// Value of the `target` label attached to every metric
+    pub target: String,
+}
+
+/// Cumulative metrics for one run
+#[derive(Debug, Clone)]
+pub struct TextfileMetrics {
+    started: SystemTime,
+    warmed: u64,
+    failed: u64,
+    skipped: u64,
+    bytes: u64,
+    bytes_covered: u64,
+    bytes_read: u64,
+    /// Files and bytes per warming method
+    by_method: BTreeMap<&'static str, (u64, u64)>,
+    /// Non-cumulative counts per [`LATENCY_BUCKETS`] entry, plus one for +Inf
+    latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: f64,
+}
+
+impl Default for TextfileMetrics {
+    fn default() -> Self {
+        Self {
+            started: SystemTime::now(),
+            warmed: 0,
+            failed: 0,
+            skipped: 0,
+            bytes: 0,
+            bytes_covered: 0,
+            bytes_read: 0,
+            by_method: BTreeMap::new(),
+            latency_buckets: [0; LATENCY_BUCKETS.len() + 1],
+            latency_sum: 0.0,
+        }
+    }
+}
+
+impl TextfileMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &WarmEvent) {
+        let WarmEvent::FileFinished { bytes, latency, status, method, bytes_read, coverage, .. } = event;
+        self.bytes_read += bytes_read;
+        match status {
+            FileStatus::Warmed => {
+                self.warmed += 1;
+                self.bytes += bytes;
+                self.bytes_covered += coverage.map_or(0, |coverage| coverage.bytes_covered(*bytes, *bytes_read));
+                if let Some(method) = method {
+                    let entry = self.by_method.entry(method).or_default();
+                    entry.0 += 1;
+                    entry.1 += bytes;
+                }
+            }
+            FileStatus::Failed => self.failed += 1,
+            // Skipped files were never read, so they stay out of the latency histogram
+            FileStatus::Skipped => {
+                self.skipped += 1;
+                return;
+            }
+        }
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket] += 1;
+        self.latency_sum += seconds;
+    }
+
+    /// The metrics in Prometheus text format, as of `now`. `complete` marks the final write.
+    pub fn render(&self, target: &str, now: SystemTime, complete: bool) -> String {
+        let target = escape_label(target);
+        let mut out = String::new();
+        write_family(&mut out, "files_total", "counter", "Files processed, by outcome.");
+        for (status, count) in [("warmed", self.warmed), ("failed", self.failed), ("skipped", self.skipped)] {
+            let _ = writeln!(out, "rust_cache_warmer_files_total{{target=\"{}\",status=\"{}\"}} {}", target, status, count);
+        }
+
+        write_family(&mut out, "bytes_warmed_total", "counter", "Bytes of warmed files.");
+        let _ = writeln!(out, "rust_cache_warmer_bytes_warmed_total{{target=\"{}\"}} {}", target, self.bytes);
+        write_family(&mut out, "bytes_covered_total", "counter", "Logical bytes of warmed files covered; less than bytes_warmed_total for sampled and advised files.");
+        let _ = writeln!(out, "rust_cache_warmer_bytes_covered_total{{target=\"{}\"}} {}", target, self.bytes_covered);
+        write_family(&mut out, "bytes_read_total", "counter", "Bytes requested from storage.");
+        let _ = writeln!(out, "rust_cache_warmer_bytes_read_total{{target=\"{}\"}} {}", target, self.bytes_read);
+
+        write_family(&mut out, "method_files_total", "counter", "Files warmed, by warming method.");
+        for (method, (files, _)) in &self.by_method {
+            let _ = writeln!(out, "rust_cache_warmer_method_files_total{{target=\"{}\",method=\"{}\"}} {}", target, escape_label(method), files);
+        }
+        write_family(&mut out, "method_bytes_total", "counter", "Bytes warmed, by warming method.");
+        for (method, (_, bytes)) in &self.by_method {
+            let _ = writeln!(out, "rust_cache_warmer_method_bytes_total{{target=\"{}\",method=\"{}\"}} {}", target, escape_label(method), bytes);
+        }
+
+        write_family(&mut out, "file_latency_seconds", "histogram", "Time to warm a file, for files that were attempted.");
+        let mut cumulative = 0u64;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "rust_cache_warmer_file_latency_seconds_bucket{{target=\"{}\",le=\"{}\"}} {}", target, bound, cumulative);
+        }
+        cumulative += self.latency_buckets[LATENCY_BUCKETS.len()];
+        let _ = writeln!(out, "rust_cache_warmer_file_latency_seconds_bucket{{target=\"{}\",le=\"+Inf\"}} {}", target, cumulative);
+        let _ = writeln!(out, "rust_cache_warmer_file_latency_seconds_sum{{target=\"{}\"}} {}", target, self.latency_sum);
+        let _ = writeln!(out, "rust_cache_warmer_file_latency_seconds_count{{target=\"{}\"}} {}", target, cumulative);
+
+        write_family(&mut out, "start_time_seconds", "gauge", "Unix time the run started.");
+        let _ = writeln!(out, "rust_cache_warmer_start_time_seconds{{target=\"{}\"}} {}", target, unix_seconds(self.started));
+        write_family(&mut out, "last_update_time_seconds", "gauge", "Unix time this file was written.");
+        let _ = writeln!(out, "rust_cache_warmer_last_update_time_seconds{{target=\"{}\"}} {}", target, unix_seconds(now));
+        write_family(&mut out, "run_complete", "gauge", "1 once the warming pass has finished, 0 while it is running.");
+        let _ = writeln!(out, "rust_cache_warmer_run_complete{{target=\"{}\"}} {}", target, u8::from(complete));
+        out
+    }
+}
+
+fn write_family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP rust_cache_warmer_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE rust_cache_warmer_{} {}", name, kind);
+}
+
+/// Escape a label value per the text exposition format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).map_or(0.0, |d| (d.as_millis() as f64) / 1000.0)
+}
+
+/// Replace `dir/`[`FILE_NAME`] with `contents` atomically. The temporary name doesn't
+/// end in `.prom`, so the collector ignores it.
+pub fn write_atomically(dir: &Path, contents: &str) -> Result<(), std::io::Error> {
+    let tmp = dir.join(format!(".{}.{}.tmp", FILE_NAME, std::process::id()));
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, dir.join(FILE_NAME)).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// Consume pipeline events and rewrite the textfile every interval, then a final time
+/// once the event stream ends.
+pub async fn run_writer(options: TextfileOptions, mut events: mpsc::UnboundedReceiver<WarmEvent>) {
+    let mut metrics = TextfileMetrics::new();
+    let mut ticker = tokio::time::interval(options.interval);
+    // The first tick completes immediately, so the file appears as soon as the run starts
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => metrics.record(&event),
+                None => break,
+            },
+            _ = ticker.tick() => write(&options, &metrics, false),
+        }
+    }
+    write(&options, &metrics, true);
+}
+
+fn write(options: &TextfileOptions, metrics: &TextfileMetrics, complete: bool) {
+    let contents = metrics.render(&options.target, SystemTime::now(), complete);
+    if let Err(e) = write_atomically(&options.dir, &contents) {
+        warn!("Failed to write {}: {}", options.dir.join(FILE_NAME).display(), e);
+    }
+}
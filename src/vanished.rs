@@ -0,0 +1,124 @@
+//! Chasing files that vanish between discovery and warming.
+//!
+//! In live directories files come and go while a run is walking: a log rotation renames
+//! `app.log` to `app.log.1` and creates a new `app.log`, and writers that replace files
+//! atomically (write a temporary file, rename it over the old one) briefly leave nothing
+//! at the path. A file that fails with ENOENT is therefore looked for again after a
+//! grace period, first at the same path and then, by inode, among its siblings.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::anonymize;
+
+/// Where a vanished file turned up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Found {
+    /// Something exists at the path again (it was replaced or recreated)
+    Replaced,
+    /// The discovered file now lives under another name in the same directory
+    Renamed(PathBuf),
+}
+
+/// Counts of vanished files and where they were found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VanishedSummary {
+    /// Files that were gone when warming reached them
+    pub vanished: u64,
+    /// ... and were back at their path after the grace period
+    pub replaced: u64,
+    /// ... or were found renamed in the same directory
+    pub renamed: u64,
+    /// ... or were gone for good
+    pub lost: u64,
+}
+
+impl fmt::Display for VanishedSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files vanished before warming: {} replaced at the same path, {} followed to a new name, {} gone",
+            self.vanished, self.replaced, self.renamed, self.lost
+        )
+    }
+}
+
+/// Looks for vanished files and counts the results for a run
+#[derive(Debug, Default)]
+pub struct VanishedFiles {
+    grace: Duration,
+    vanished: AtomicU64,
+    replaced: AtomicU64,
+    renamed: AtomicU64,
+    lost: AtomicU64,
+}
+
+impl VanishedFiles {
+    /// Wait `grace` before looking again; zero disables chasing
+    pub fn new(grace: Duration) -> Self {
+        Self { grace, ..Default::default() }
+    }
+
+    /// Whether a file that failed with `error` should be looked for again
+    pub fn applies(&self, error: &std::io::Error) -> bool {
+        !self.grace.is_zero() && error.kind() == std::io::ErrorKind::NotFound
+    }
+
+    /// After the grace period, where the file discovered at `path` (with inode `ino`, 0 if
+    /// unknown) is now, and its size there
+    pub async fn relocate(&self, path: &Path, ino: u64) -> Option<(PathBuf, u64)> {
+        self.vanished.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(self.grace).await;
+        let found = find(path, ino).await;
+        debug!("Vanished file {} after {:?}: {:?}", anonymize::display(path), self.grace, found);
+        let (counter, new_path) = match found {
+            Some(Found::Replaced) => (&self.replaced, path.to_path_buf()),
+            Some(Found::Renamed(new_path)) => (&self.renamed, new_path),
+            None => {
+                self.lost.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        match tokio::fs::metadata(&new_path).await {
+            Ok(metadata) if metadata.is_file() => {
+                counter.fetch_add(1, Ordering::Relaxed);
+                Some((new_path, metadata.len()))
+            }
+            _ => {
+                self.lost.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn summary(&self) -> VanishedSummary {
+        VanishedSummary {
+            vanished: self.vanished.load(Ordering::Relaxed),
+            replaced: self.replaced.load(Ordering::Relaxed),
+            renamed: self.renamed.load(Ordering::Relaxed),
+            lost: self.lost.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Look for the file discovered at `path` with inode `ino`: at the path itself, then
+/// under another name in its directory
+pub async fn find(path: &Path, ino: u64) -> Option<Found> {
+    if tokio::fs::symlink_metadata(path).await.is_ok() {
+        return Some(Found::Replaced);
+    }
+    if ino == 0 {
+        return None;
+    }
+    let mut entries = tokio::fs::read_dir(path.parent()?).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.ino() == ino {
+            return Some(Found::Renamed(entry.path()));
+        }
+    }
+    None
+}
@@ -0,0 +1,453 @@
+//! Hydration verification.
+//!
+//! Warming reports what it read, not whether EBS actually has the blocks locally. A
+//! block restored from a snapshot that hasn't been fetched yet takes tens to hundreds of
+//! milliseconds on first read; a hydrated one takes about a millisecond. `--verify`
+//! reads one block at a number of random offsets (uniform over the bytes under the
+//! target directories, bypassing the page cache), reports latency percentiles and flags
+//! samples slow enough to still be cold.
+//!
+//! `--verify-rewarm` goes one step further: every cold sample's surrounding region is
+//! re-warmed with plain reads and a different block of it is timed again. A region that
+//! read cold after warming claimed success means the strategy (e.g. advisory fadvise
+//! hints the kernel ignored) didn't hydrate it; one that is still cold after the
+//! re-warm means something is wrong beyond the strategy.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use log::debug;
+
+use crate::anonymize;
+use crate::pipeline::{self, PipelineOptions};
+use crate::warming::{self, WarmingOptions};
+
+/// Bytes read per sample; one filesystem block, and the O_DIRECT alignment
+pub const SAMPLE_SIZE: u64 = 4096;
+/// Cold samples listed in the report
+const REPORT_COLDEST: usize = 20;
+/// Bytes re-warmed around each cold sample by `--verify-rewarm`
+pub const REWARM_REGION: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Number of random offsets to read
+    pub samples: usize,
+    /// Samples slower than this are flagged as probably still cold
+    pub cold_threshold: Duration,
+    /// Concurrent sample reads
+    pub concurrency: usize,
+    /// Seed for offset selection, so a run can be reproduced
+    pub seed: u64,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            samples: 1000,
+            cold_threshold: Duration::from_millis(20),
+            concurrency: 8,
+            seed: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64),
+        }
+    }
+}
+
+/// One timed read
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub latency: Duration,
+}
+
+/// Latency distribution of the successful samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyPercentiles {
+    /// Nearest-rank percentiles of `latencies`, which must be sorted
+    pub(crate) fn from_sorted(latencies: &[Duration]) -> Self {
+        let rank = |p: f64| {
+            if latencies.is_empty() {
+                return Duration::ZERO;
+            }
+            let index = ((p / 100.0) * latencies.len() as f64).ceil() as usize;
+            latencies[index.clamp(1, latencies.len()) - 1]
+        };
+        Self { p50: rank(50.0), p90: rank(90.0), p99: rank(99.0), max: rank(100.0) }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub cold_threshold: Duration,
+    /// Bytes the offsets were drawn from
+    pub bytes_covered: u64,
+    pub files_covered: u64,
+    pub samples: usize,
+    /// Samples that couldn't be read (file vanished, permissions, ...)
+    pub failed: usize,
+    pub latency: LatencyPercentiles,
+    /// Samples slower than the threshold, slowest first
+    pub cold: Vec<Sample>,
+}
+
+impl VerifyReport {
+    /// No sample looked cold
+    pub fn is_hydrated(&self) -> bool {
+        self.cold.is_empty()
+    }
+
+    pub fn cold_fraction(&self) -> f64 {
+        let measured = self.samples - self.failed;
+        if measured == 0 {
+            0.0
+        } else {
+            self.cold.len() as f64 / measured as f64
+        }
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Verification: {} samples over {} files ({:.2} GB), {} failed",
+            self.samples,
+            self.files_covered,
+            self.bytes_covered as f64 / 1_000_000_000.0,
+            self.failed
+        )?;
+        writeln!(
+            f,
+            "  latency p50 {:.2?}  p90 {:.2?}  p99 {:.2?}  max {:.2?}",
+            self.latency.p50, self.latency.p90, self.latency.p99, self.latency.max
+        )?;
+        if self.is_hydrated() {
+            return writeln!(f, "  no samples slower than {:.2?}; the sampled data looks hydrated", self.cold_threshold);
+        }
+        writeln!(
+            f,
+            "  {} samples ({:.1}%) slower than {:.2?}, probably still cold:",
+            self.cold.len(),
+            self.cold_fraction() * 100.0,
+            self.cold_threshold
+        )?;
+        for sample in self.cold.iter().take(REPORT_COLDEST) {
+            writeln!(f, "    {:>10.2?}  {} @ {}", sample.latency, anonymize::display(&sample.path), sample.offset)?;
+        }
+        if self.cold.len() > REPORT_COLDEST {
+            writeln!(f, "    ... and {} more", self.cold.len() - REPORT_COLDEST)?;
+        }
+        Ok(())
+    }
+}
+
+/// splitmix64; plenty for picking sample offsets without another dependency
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, bound)`
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+/// Files the pipeline would warm, in walk order
+pub(crate) fn for_each_candidate(options: &PipelineOptions, mut f: impl FnMut(&Path, u64)) {
+    for root in &options.directories {
+        for entry in pipeline::walker(options, root).flatten() {
+            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else { continue };
+            let size = metadata.len();
+            if size == 0 || (options.max_file_size > 0 && size > options.max_file_size) {
+                continue;
+            }
+            f(entry.path(), size);
+        }
+    }
+}
+
+/// Pick `count` block-aligned offsets uniformly over all candidate bytes. Two walks keep
+/// memory proportional to the sample count rather than the number of files.
+pub(crate) fn pick_offsets(options: &PipelineOptions, count: usize, seed: u64) -> (Vec<(PathBuf, u64)>, u64, u64) {
+    let (mut total_bytes, mut total_files) = (0u64, 0u64);
+    for_each_candidate(options, |_, size| {
+        total_bytes += size;
+        total_files += 1;
+    });
+    if total_bytes == 0 {
+        return (Vec::new(), 0, 0);
+    }
+
+    let mut rng = Rng::new(seed);
+    let mut global: Vec<u64> = (0..count).map(|_| rng.below(total_bytes)).collect();
+    global.sort_unstable();
+
+    let mut picked = Vec::with_capacity(count);
+    let mut next = 0;
+    let mut file_start = 0u64;
+    for_each_candidate(options, |path, size| {
+        let file_end = file_start + size;
+        while next < global.len() && global[next] < file_end {
+            // A file that grew between the walks can't shift an offset past its own end
+            let local = std::cmp::min(global[next].saturating_sub(file_start), size - 1);
+            picked.push((path.to_path_buf(), local / SAMPLE_SIZE * SAMPLE_SIZE));
+            next += 1;
+        }
+        file_start = file_end;
+    });
+    (picked, total_bytes, total_files)
+}
+
+/// Time one block read that goes to the device: O_DIRECT where the filesystem allows it,
+/// otherwise a buffered read of a range that was just evicted
+fn timed_read(path: &Path, offset: u64) -> Result<Duration, std::io::Error> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::{FileExt, OpenOptionsExt};
+
+        if let Ok(file) = std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path) {
+            let layout = std::alloc::Layout::from_size_align(SAMPLE_SIZE as usize, SAMPLE_SIZE as usize).unwrap();
+            let ptr = unsafe { std::alloc::alloc(layout) };
+            if ptr.is_null() {
+                return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "failed to allocate sample buffer"));
+            }
+            let buffer = unsafe { std::slice::from_raw_parts_mut(ptr, SAMPLE_SIZE as usize) };
+            let start = Instant::now();
+            let result = file.read_at(buffer, offset);
+            let latency = start.elapsed();
+            unsafe { std::alloc::dealloc(ptr, layout) };
+            match result {
+                Ok(_) => return Ok(latency),
+                // tmpfs and some FUSE filesystems refuse O_DIRECT reads
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    buffered_timed_read(path, offset)
+}
+
+fn buffered_timed_read(path: &Path, offset: u64) -> Result<Duration, std::io::Error> {
+    let file = std::fs::File::open(path)?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::prelude::AsRawFd;
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as i64, SAMPLE_SIZE as i64, libc::POSIX_FADV_DONTNEED) };
+    }
+    let mut buffer = vec![0u8; SAMPLE_SIZE as usize];
+    let start = Instant::now();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.read_at(&mut buffer, offset)?;
+    }
+    #[cfg(not(unix))]
+    {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut file = file;
+        file.seek(SeekFrom::Start(offset))?;
+        let _ = file.read(&mut buffer)?;
+    }
+    Ok(start.elapsed())
+}
+
+/// Time `offsets` with bounded concurrency. Returns each sample or its error.
+pub(crate) async fn measure(offsets: Vec<(PathBuf, u64)>, concurrency: usize) -> Vec<Result<Sample, std::io::Error>> {
+    stream::iter(offsets)
+        .map(|(path, offset)| async move {
+            tokio::task::spawn_blocking(move || timed_read(&path, offset).map(|latency| Sample { path, offset, latency }))
+                .await
+                .map_err(std::io::Error::other)?
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// A region around a cold sample, re-warmed and measured again
+#[derive(Debug, Clone)]
+pub struct RewarmedRegion {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub length: u64,
+    /// Latency of the sample that found the region cold
+    pub before: Duration,
+    /// Latency of a different block of the region after re-warming it
+    pub after: Result<Duration, String>,
+}
+
+impl RewarmedRegion {
+    fn still_cold(&self, threshold: Duration) -> bool {
+        match &self.after {
+            Ok(latency) => *latency > threshold,
+            Err(_) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RewarmReport {
+    pub cold_threshold: Duration,
+    /// Regions that read cold after warming, in the order of the verification report
+    pub regions: Vec<RewarmedRegion>,
+}
+
+impl RewarmReport {
+    /// Regions that still read cold (or failed) after being re-warmed
+    pub fn still_cold(&self) -> Vec<&RewarmedRegion> {
+        self.regions.iter().filter(|region| region.still_cold(self.cold_threshold)).collect()
+    }
+}
+
+impl fmt::Display for RewarmReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.regions.is_empty() {
+            return writeln!(f, "Re-warm check: nothing read cold after warming");
+        }
+        writeln!(
+            f,
+            "Re-warm check: {} regions read cold even though warming reported success; re-warmed them:",
+            self.regions.len()
+        )?;
+        for region in self.regions.iter().take(REPORT_COLDEST) {
+            let after = match &region.after {
+                Ok(latency) if *latency > self.cold_threshold => format!("{:.2?} STILL COLD", latency),
+                Ok(latency) => format!("{:.2?}", latency),
+                Err(e) => format!("failed: {}", e),
+            };
+            writeln!(
+                f,
+                "    {} [{}..{}): {:.2?} -> {}",
+                anonymize::display(&region.path),
+                region.offset,
+                region.offset + region.length,
+                region.before,
+                after
+            )?;
+        }
+        if self.regions.len() > REPORT_COLDEST {
+            writeln!(f, "    ... and {} more", self.regions.len() - REPORT_COLDEST)?;
+        }
+        Ok(())
+    }
+}
+
+/// Drop a range from the page cache so the next buffered read goes to the device
+fn evict(path: &Path, offset: u64, length: u64) {
+    #[cfg(target_os = "linux")]
+    if let Ok(file) = std::fs::File::open(path) {
+        use std::os::unix::prelude::AsRawFd;
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as i64, length as i64, libc::POSIX_FADV_DONTNEED) };
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (path, offset, length);
+}
+
+/// Re-warm the region around every cold sample of `report` and time a block of each
+/// region that the verification pass didn't read (that read hydrated its own block)
+pub async fn rewarm_cold(report: &VerifyReport, warming_options: &WarmingOptions, seed: u64) -> RewarmReport {
+    let mut rng = Rng::new(seed);
+    let mut regions: Vec<RewarmedRegion> = Vec::new();
+    for sample in &report.cold {
+        let offset = sample.offset / REWARM_REGION * REWARM_REGION;
+        if regions.iter().any(|region| region.path == sample.path && region.offset == offset) {
+            continue;
+        }
+        let size = match std::fs::metadata(&sample.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                let after = Err(e.to_string());
+                regions.push(RewarmedRegion { path: sample.path.clone(), offset, length: 0, before: sample.latency, after });
+                continue;
+            }
+        };
+        let length = std::cmp::min(REWARM_REGION, size.saturating_sub(offset));
+        evict(&sample.path, offset, length);
+        let after = match warming::warm_range(&sample.path, offset, length, warming_options).await {
+            Ok(_) => {
+                let blocks = length.div_ceil(SAMPLE_SIZE);
+                let sampled_block = (sample.offset - offset) / SAMPLE_SIZE;
+                let mut block = rng.below(blocks);
+                if blocks > 1 && block == sampled_block {
+                    block = (block + 1) % blocks;
+                }
+                let path = sample.path.clone();
+                let probe = offset + block * SAMPLE_SIZE;
+                tokio::task::spawn_blocking(move || timed_read(&path, probe))
+                    .await
+                    .map_err(std::io::Error::other)
+                    .and_then(|result| result)
+                    .map_err(|e| e.to_string())
+            }
+            Err(e) => Err(format!("re-warm failed: {}", e)),
+        };
+        regions.push(RewarmedRegion { path: sample.path.clone(), offset, length, before: sample.latency, after });
+    }
+    RewarmReport { cold_threshold: report.cold_threshold, regions }
+}
+
+/// Sample the directories of `options` and report how hydrated they look
+pub async fn verify(options: Arc<PipelineOptions>, verify: &VerifyOptions) -> Result<VerifyReport> {
+    let (count, seed) = (verify.samples, verify.seed);
+    let walk_options = Arc::clone(&options);
+    let (offsets, bytes_covered, files_covered) =
+        tokio::task::spawn_blocking(move || pick_offsets(&walk_options, count, seed)).await?;
+    debug!("Verifying {} offsets over {} files ({} bytes)", offsets.len(), files_covered, bytes_covered);
+
+    let samples = offsets.len();
+    let mut latencies = Vec::with_capacity(samples);
+    let mut cold = Vec::new();
+    let mut failed = 0;
+    for result in measure(offsets, verify.concurrency).await {
+        match result {
+            Ok(sample) => {
+                latencies.push(sample.latency);
+                if sample.latency > verify.cold_threshold {
+                    cold.push(sample);
+                }
+            }
+            Err(e) => {
+                debug!("Verification read failed: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    latencies.sort_unstable();
+    cold.sort_by_key(|sample| std::cmp::Reverse(sample.latency));
+
+    Ok(VerifyReport {
+        cold_threshold: verify.cold_threshold,
+        bytes_covered,
+        files_covered,
+        samples,
+        failed,
+        latency: LatencyPercentiles::from_sorted(&latencies),
+        cold,
+    })
+}
@@ -0,0 +1,163 @@
+//! Finding every EBS volume to warm (`--all-ebs-volumes`).
+//!
+//! A user-data script that hydrates a new instance would otherwise have to list each mount
+//! point and keep the list in step with the launch template. [`discover`] reads
+//! `/proc/self/mountinfo` instead and keeps the filesystems whose disk is EBS, as
+//! [`disk::detect`] tells from the NVMe model string on Nitro instances. Xen instances
+//! name EBS and instance-store disks alike (`xvdf`), so there `--imds-block-devices` asks
+//! the instance metadata service for the block-device mapping, which lists the EBS ones.
+//!
+//! Each filesystem is warmed once, through its shallowest mount of the whole tree (a bind
+//! mount of a subdirectory only if nothing mounts the whole tree). The disk holding `/`,
+//! including its other partitions such as `/boot/efi`, is left out unless
+//! `--include-root`: it's usually the AMI's root volume, which booting has largely read
+//! already.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::disk::{self, DiskKind};
+use crate::layers::{self, MountInfo};
+
+/// Where the instance metadata service answers, unless `AWS_EC2_METADATA_SERVICE_ENDPOINT` says otherwise
+pub const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+/// Connect and read timeout of each metadata request; off EC2 nothing answers at all
+const IMDS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A filesystem on an EBS volume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EbsMount {
+    pub mount_point: PathBuf,
+    /// Kernel name of the disk, e.g. `nvme1n1`
+    pub disk: String,
+}
+
+/// What [`select`] found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Discovered {
+    /// Sorted by mount point
+    pub volumes: Vec<EbsMount>,
+    /// EBS filesystems on the root disk, left out without `--include-root`
+    pub root: Vec<EbsMount>,
+}
+
+/// Every EBS filesystem mounted on this host. With `imds`, disks the metadata service's
+/// block-device mapping lists as EBS count too.
+pub fn discover(include_root: bool, imds: bool) -> Result<Discovered, std::io::Error> {
+    let mounts = layers::parse_mountinfo(&std::fs::read_to_string("/proc/self/mountinfo")?);
+    let mapped = if imds {
+        let endpoint = std::env::var("AWS_EC2_METADATA_SERVICE_ENDPOINT").unwrap_or_else(|_| IMDS_ENDPOINT.to_string());
+        imds_ebs_devices(&endpoint).unwrap_or_else(|why| {
+            debug!("No block-device mapping from the instance metadata service: {}", why);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    Ok(select(&mounts, disk::detect, &mapped, include_root))
+}
+
+/// The EBS filesystems among `mounts`, classifying each by the disk `disk_of` finds behind
+/// its mount point. `mapped` names disks known to be EBS from the block-device mapping.
+pub fn select(mounts: &[MountInfo], disk_of: impl Fn(&Path) -> DiskKind, mapped: &[String], include_root: bool) -> Discovered {
+    let mapped: Vec<String> = mapped.iter().map(|name| mapping_name(name)).collect();
+    // One mount per filesystem: the whole tree if it's mounted anywhere, at the shallowest point
+    let mut filesystems: HashMap<&str, &MountInfo> = HashMap::new();
+    for mount in mounts.iter().filter(|mount| !mount.device.starts_with("0:")) {
+        let rank = |mount: &MountInfo| (mount.root != Path::new("/"), mount.mount_point.components().count());
+        filesystems
+            .entry(mount.device.as_str())
+            .and_modify(|best| {
+                if rank(mount) < rank(best) {
+                    *best = mount;
+                }
+            })
+            .or_insert(mount);
+    }
+
+    let root_disk = match disk_of(Path::new("/")) {
+        DiskKind::Ebs(disk) | DiskKind::InstanceStore(disk) | DiskKind::Local(disk) | DiskKind::Network(disk) | DiskKind::Unknown(disk) => disk,
+    };
+    let mut found = Discovered::default();
+    for mount in filesystems.into_values() {
+        let disk = match disk_of(&mount.mount_point) {
+            DiskKind::Ebs(disk) => disk,
+            DiskKind::Unknown(disk) if mapped.contains(&mapping_name(&disk)) => disk,
+            kind => {
+                debug!("--all-ebs-volumes: skipping {} on {}", mount.mount_point.display(), kind);
+                continue;
+            }
+        };
+        let volume = EbsMount { mount_point: mount.mount_point.clone(), disk };
+        if volume.disk == root_disk && !include_root {
+            found.root.push(volume);
+        } else {
+            found.volumes.push(volume);
+        }
+    }
+    found.volumes.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    found.root.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    found
+}
+
+/// A device as the block-device mapping names it: `/dev/sdf`, `xvdf` and `sdf1` are all `sdf`
+pub fn mapping_name(device: &str) -> String {
+    let name = device.trim().strip_prefix("/dev/").unwrap_or(device.trim());
+    let name = match name.strip_prefix("xvd") {
+        Some(rest) => format!("sd{}", rest),
+        None => name.to_string(),
+    };
+    // Partitions of the disk, as the root device is often given
+    if name.starts_with("sd") {
+        name.trim_end_matches(|c: char| c.is_ascii_digit()).to_string()
+    } else {
+        name
+    }
+}
+
+/// Devices the block-device mapping at `endpoint` lists as EBS: the root device and every
+/// `ebsN` entry. Uses an IMDSv2 token when the service hands one out.
+pub fn imds_ebs_devices(endpoint: &str) -> Result<Vec<String>, String> {
+    let host = endpoint.trim_end_matches('/').strip_prefix("http://").ok_or_else(|| format!("{} is not an http:// URL", endpoint))?;
+    let token = match imds_request(host, "PUT", "/latest/api/token", Some(("X-aws-ec2-metadata-token-ttl-seconds", "60"))) {
+        Ok((200, token)) => Some(token),
+        // IMDSv1 only
+        _ => None,
+    };
+    let get = |path: &str| match imds_request(host, "GET", path, token.as_deref().map(|token| ("X-aws-ec2-metadata-token", token))) {
+        Ok((200, body)) => Ok(body),
+        Ok((status, _)) => Err(format!("{} answered {}", path, status)),
+        Err(e) => Err(format!("{}: {}", path, e)),
+    };
+    const MAPPING: &str = "/latest/meta-data/block-device-mapping/";
+    let mut devices = Vec::new();
+    for entry in get(MAPPING)?.lines().map(str::trim).filter(|entry| *entry == "root" || entry.starts_with("ebs")) {
+        devices.push(get(&format!("{}{}", MAPPING, entry))?.trim().to_string());
+    }
+    Ok(devices)
+}
+
+/// One request to the metadata service; returns the status and body
+fn imds_request(host: &str, method: &str, path: &str, header: Option<(&str, &str)>) -> Result<(u16, String), std::io::Error> {
+    let authority = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let addr = authority.to_socket_addrs()?.next().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, IMDS_TIMEOUT)?;
+    stream.set_read_timeout(Some(IMDS_TIMEOUT))?;
+    stream.set_write_timeout(Some(IMDS_TIMEOUT))?;
+    let header = header.map(|(name, value)| format!("{}: {}\r\n", name, value)).unwrap_or_default();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n", method, path, host, header)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed HTTP response"))?;
+    Ok((status, body.to_string()))
+}
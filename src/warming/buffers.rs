@@ -0,0 +1,259 @@
+//! Aligned read buffers shared by every direct-I/O path under one memory ceiling.
+//!
+//! O_DIRECT reads need buffers aligned to the logical block size, which `Vec` doesn't
+//! guarantee. Allocating one per file (or per read, in the shared ring) costs an
+//! allocator round trip each time and, with many files in flight, has no upper bound.
+//! Tokio direct reads, libaio, the shared io_uring and range-parallel reads all take
+//! their buffers from the [`BufferPool`] instead: returned buffers are kept for reuse,
+//! and once `--max-buffer-memory` is checked out, callers wait for a buffer to come back
+//! (the ring leaves requests queued) rather than allocating more.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+/// O_DIRECT requires buffers aligned to the logical block size
+pub const ALIGNMENT: usize = 4096;
+
+/// Default `--max-buffer-memory`: a 1MiB read in every slot of the shared ring
+pub const DEFAULT_MAX_BUFFER_MEMORY: usize = 256 * 1024 * 1024;
+
+/// `--max-buffer-memory` in `--low-memory` mode
+pub const LOW_MEMORY_MAX_BUFFER_MEMORY: usize = 16 * 1024 * 1024;
+
+/// Returned buffers kept for reuse when there is no ceiling
+const UNBOUNDED_IDLE_BYTES: usize = 64 * 1024 * 1024;
+
+pub(crate) struct AlignedBuffer {
+    ptr: *mut u8,
+    layout: std::alloc::Layout,
+}
+
+// The buffer is only touched by the reader that checked it out, or by the kernel on its behalf
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub(crate) fn new(len: usize) -> std::io::Result<Self> {
+        let layout = std::alloc::Layout::from_size_align(aligned_len(len), ALIGNMENT)
+            .map_err(|_| std::io::Error::other("Failed to create aligned memory layout"))?;
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        if ptr.is_null() {
+            return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
+        }
+        Ok(Self { ptr, layout })
+    }
+
+    fn len(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// Size of the buffer handed out for a `len`-byte read
+fn aligned_len(len: usize) -> usize {
+    len.max(1).next_multiple_of(ALIGNMENT)
+}
+
+/// Point-in-time usage of a [`BufferPool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Bytes of buffers checked out
+    pub in_use: usize,
+    /// Bytes of returned buffers kept for reuse
+    pub idle: usize,
+    /// Most bytes ever checked out at once
+    pub peak: usize,
+    /// Times a caller had to wait for a buffer to be returned
+    pub waits: u64,
+}
+
+#[derive(Default)]
+struct PoolState {
+    in_use: usize,
+    idle: Vec<AlignedBuffer>,
+    idle_bytes: usize,
+    peak: usize,
+}
+
+/// Aligned buffers handed out and taken back under a ceiling on checked-out bytes
+pub struct BufferPool {
+    /// Most bytes checked out at once; 0 for no ceiling
+    limit: AtomicUsize,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+    returned_async: Notify,
+    waits: AtomicU64,
+}
+
+impl BufferPool {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit: AtomicUsize::new(limit),
+            state: Mutex::new(PoolState::default()),
+            returned: Condvar::new(),
+            returned_async: Notify::new(),
+            waits: AtomicU64::new(0),
+        })
+    }
+
+    /// The pool every backend draws from, limited by `--max-buffer-memory`
+    pub fn global() -> &'static Arc<BufferPool> {
+        static POOL: OnceLock<Arc<BufferPool>> = OnceLock::new();
+        POOL.get_or_init(|| BufferPool::new(DEFAULT_MAX_BUFFER_MEMORY))
+    }
+
+    /// Change the ceiling; buffers already checked out are unaffected
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+        let mut state = self.lock();
+        state.idle.clear();
+        state.idle_bytes = 0;
+        drop(state);
+        self.returned.notify_all();
+        self.returned_async.notify_waiters();
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.lock();
+        PoolStats { in_use: state.in_use, idle: state.idle_bytes, peak: state.peak, waits: self.waits.load(Ordering::Relaxed) }
+    }
+
+    /// A buffer of at least `len` bytes, or `None` if it would take the pool over its ceiling
+    pub fn try_get(self: &Arc<Self>, len: usize) -> std::io::Result<Option<PooledBuffer>> {
+        let buffer = self.take(&mut self.lock(), len)?;
+        Ok(buffer.map(|buffer| PooledBuffer { buffer: Some(buffer), len, pool: Arc::clone(self) }))
+    }
+
+    /// A buffer of at least `len` bytes, blocking the thread until one is returned if
+    /// the pool is at its ceiling
+    pub fn get_blocking(self: &Arc<Self>, len: usize) -> std::io::Result<PooledBuffer> {
+        let mut state = self.lock();
+        let mut waited = false;
+        loop {
+            if let Some(buffer) = self.take(&mut state, len)? {
+                return Ok(PooledBuffer { buffer: Some(buffer), len, pool: Arc::clone(self) });
+            }
+            if !waited {
+                self.waits.fetch_add(1, Ordering::Relaxed);
+                waited = true;
+            }
+            state = self.returned.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Like [`BufferPool::get_blocking`], waiting without holding up the runtime
+    pub async fn get(self: &Arc<Self>, len: usize) -> std::io::Result<PooledBuffer> {
+        let mut waited = false;
+        loop {
+            let returned = self.returned_async.notified();
+            tokio::pin!(returned);
+            // Registered before looking, so a buffer returned in between still wakes us
+            returned.as_mut().enable();
+            if let Some(buffer) = self.try_get(len)? {
+                return Ok(buffer);
+            }
+            if !waited {
+                self.waits.fetch_add(1, Ordering::Relaxed);
+                waited = true;
+            }
+            returned.await;
+        }
+    }
+
+    /// Block until a buffer is returned or `timeout` passes, for a caller that polls
+    /// [`BufferPool::try_get`] and has nothing else to wait on
+    pub fn wait_for_return(&self, timeout: Duration) {
+        let state = self.lock();
+        let _ = self.returned.wait_timeout(state, timeout);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take(&self, state: &mut PoolState, len: usize) -> std::io::Result<Option<AlignedBuffer>> {
+        let size = aligned_len(len);
+        let limit = self.limit();
+        let buffer = match state.idle.iter().position(|buffer| buffer.len() == size) {
+            Some(index) => {
+                state.idle_bytes -= size;
+                state.idle.swap_remove(index)
+            }
+            // A read larger than the whole ceiling still gets a buffer, alone
+            None if limit > 0 && state.in_use > 0 && state.in_use + size > limit => return Ok(None),
+            None => {
+                // Make room by freeing returned buffers of other sizes
+                while limit > 0 && state.in_use + state.idle_bytes + size > limit {
+                    let Some(buffer) = state.idle.pop() else { break };
+                    state.idle_bytes -= buffer.len();
+                }
+                AlignedBuffer::new(size)?
+            }
+        };
+        state.in_use += size;
+        state.peak = state.peak.max(state.in_use);
+        Ok(Some(buffer))
+    }
+
+    fn put(&self, buffer: AlignedBuffer) {
+        let mut state = self.lock();
+        let size = buffer.len();
+        state.in_use -= size;
+        let limit = self.limit();
+        let keep = if limit > 0 { state.in_use + state.idle_bytes + size <= limit } else { state.idle_bytes + size <= UNBOUNDED_IDLE_BYTES };
+        if keep {
+            state.idle_bytes += size;
+            state.idle.push(buffer);
+        }
+        drop(state);
+        self.returned.notify_all();
+        self.returned_async.notify_waiters();
+    }
+}
+
+/// A buffer checked out of a [`BufferPool`], returned to it when dropped
+pub struct PooledBuffer {
+    buffer: Option<AlignedBuffer>,
+    /// Bytes asked for; the buffer itself is rounded up to [`ALIGNMENT`]
+    len: usize,
+    pool: Arc<BufferPool>,
+}
+
+impl PooledBuffer {
+    /// The `len` bytes asked for
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        let buffer = self.buffer.as_ref().expect("buffer is present until dropped");
+        unsafe { std::slice::from_raw_parts_mut(buffer.ptr, self.len) }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_slice().as_mut_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.put(buffer);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Intra-file parallelism for huge files (`--intra-file-parallelism`).
+//!
+//! The strategies read a file with a single sequential reader, so one 200 GB file
+//! leaves most of the volume's queue depth idle while it's being warmed. Here a large
+//! file is split into up to N contiguous ranges that are read concurrently: through the
+//! shared io_uring when that's the selected strategy under `--direct-io`, otherwise with
+//! positional reads on blocking threads (O_DIRECT with `--direct-io`). With
+//! [`WarmingOptions::ranges`] set, each range reads only the selected parts of it.
+
+use std::path::Path;
+use std::time::Instant;
+
+use futures::future::try_join_all;
+use log::debug;
+
+use crate::anonymize;
+
+use super::{Coverage, WarmingOptions, WarmingResult, SPARSE_INTERVAL};
+
+/// Ranges are never smaller than this, so only genuinely large files are split
+pub const MIN_RANGE_SIZE: u64 = 64 * 1024 * 1024;
+/// Range boundaries fall on multiples of this, which keeps O_DIRECT reads aligned
+const RANGE_ALIGNMENT: u64 = 1024 * 1024;
+const CHUNK_SIZE: u64 = 1024 * 1024;
+/// Sparse mode reads one block every [`SPARSE_INTERVAL`] (or `--sparse-interval`), like the sparse strategies
+const SPARSE_SAMPLE: u64 = 4096;
+/// Reads each range keeps queued in the shared io_uring
+#[cfg(target_os = "linux")]
+const RING_READS_PER_RANGE: usize = 4;
+
+/// Split a file into up to `parallelism` contiguous `(offset, length)` ranges of at
+/// least [`MIN_RANGE_SIZE`]. Files too small to split come back as a single range.
+pub fn split(file_size: u64, parallelism: usize) -> Vec<(u64, u64)> {
+    let count = std::cmp::min(parallelism as u64, file_size / MIN_RANGE_SIZE);
+    if count < 2 {
+        return vec![(0, file_size)];
+    }
+    // Round down so every range keeps at least its share; the last one also takes the remainder
+    let step = std::cmp::max(file_size / count / RANGE_ALIGNMENT * RANGE_ALIGNMENT, RANGE_ALIGNMENT);
+    (0..count)
+        .map(|i| {
+            let offset = i * step;
+            let end = if i + 1 == count { file_size } else { offset + step };
+            (offset, end - offset)
+        })
+        .collect()
+}
+
+/// Warm `path` in parallel ranges. Returns `None` when the file is too small to split
+/// or parallelism is off, so the caller uses the normal strategy chain.
+pub(crate) async fn warm(
+    path: &Path,
+    file_size: u64,
+    options: &WarmingOptions,
+    use_ring: bool,
+) -> Result<Option<WarmingResult>, std::io::Error> {
+    let ranges = split(file_size, options.intra_file_parallelism);
+    if ranges.len() < 2 {
+        return Ok(None);
+    }
+
+    let start = Instant::now();
+    let sparse = options.is_sparse(file_size);
+    let chunk = options.read_size(CHUNK_SIZE as usize) as u64;
+    let (read_size, stride) = if sparse { (SPARSE_SAMPLE, Some(options.sample_interval(SPARSE_INTERVAL))) } else { (chunk, None) };
+    debug!("Warming {} ({} bytes) in {} parallel ranges", anonymize::display(path), file_size, ranges.len());
+    let selected = options.ranges_to_read(file_size, options.use_direct_io);
+    let ranges: Vec<Vec<(u64, u64)>> = ranges.into_iter().map(|range| super::intersect_ranges(&[range], &selected)).collect();
+
+    #[cfg(target_os = "linux")]
+    if options.use_direct_io && use_ring {
+        let bytes_read = read_ranges_with_ring(path, ranges, read_size, stride).await?;
+        return Ok(Some(WarmingResult {
+            method: if sparse { "chunked_io_uring_direct_sparse" } else { "chunked_io_uring_direct_full" },
+            success: true,
+            duration: start.elapsed(),
+            bytes_read,
+            coverage: if sparse { Coverage::Sampled } else { Coverage::Full },
+        }));
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = use_ring;
+
+    let direct = options.use_direct_io;
+    let drop_pages = options.drop_caches.per_file();
+    let reads = ranges.into_iter().map(|range| {
+        let path = path.to_path_buf();
+        async move {
+            tokio::task::spawn_blocking(move || read_range(&path, range, read_size, stride, direct, drop_pages))
+                .await
+                .map_err(std::io::Error::other)?
+        }
+    });
+    let bytes_read = try_join_all(reads).await?.into_iter().sum();
+
+    let method = match (direct && cfg!(target_os = "linux"), sparse) {
+        (true, true) => "chunked_pread_direct_sparse",
+        (true, false) => "chunked_pread_direct_full",
+        (false, true) => "chunked_pread_sparse",
+        (false, false) => "chunked_pread_full",
+    };
+    let coverage = if sparse { Coverage::Sampled } else { Coverage::Full };
+    Ok(Some(WarmingResult { method, success: true, duration: start.elapsed(), bytes_read, coverage }))
+}
+
+/// Read the selected parts of one range with its own file handle, in `read_size` reads
+/// (every `stride` bytes when sampling)
+fn read_range(
+    path: &Path,
+    parts: Vec<(u64, u64)>,
+    read_size: u64,
+    stride: Option<u64>,
+    direct: bool,
+    drop_pages: bool,
+) -> Result<u64, std::io::Error> {
+    #[cfg(target_os = "linux")]
+    if direct {
+        use std::os::unix::fs::{FileExt, OpenOptionsExt};
+
+        let file = std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path)?;
+        let mut buffer = super::buffers::BufferPool::global().get_blocking(read_size as usize)?;
+        let mut bytes_read = 0u64;
+        for (pos, _) in super::reads_in(parts, read_size, stride) {
+            // Always a full aligned block; range ends are aligned except at EOF, where the read comes back short
+            let n = file.read_at(buffer.as_mut_slice(), pos)?;
+            if n == 0 {
+                break;
+            }
+            bytes_read += n as u64;
+        }
+        return Ok(bytes_read);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = direct;
+
+    let file = std::fs::File::open(path)?;
+    let mut buffer = vec![0u8; read_size as usize];
+    let mut bytes_read = 0u64;
+    for (pos, len) in super::reads_in(parts, read_size, stride) {
+        bytes_read += super::read_range_blocking(&file, pos, len, &mut buffer, drop_pages)?;
+    }
+    Ok(bytes_read)
+}
+
+#[cfg(target_os = "linux")]
+async fn read_ranges_with_ring(path: &Path, ranges: Vec<Vec<(u64, u64)>>, read_size: u64, stride: Option<u64>) -> Result<u64, std::io::Error> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::sync::Arc;
+
+    use futures::stream::{self, StreamExt};
+
+    let ring = super::ring::SharedRing::global()?;
+    let file = Arc::new(std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path)?);
+    let reads = ranges.into_iter().map(|parts| {
+        let file = Arc::clone(&file);
+        async move {
+            // Full aligned blocks, as in `read_range`
+            let mut reads = stream::iter(super::reads_in(parts, read_size, stride))
+                .map(|(pos, _)| ring.read(Arc::clone(&file), pos, read_size as usize))
+                .buffer_unordered(RING_READS_PER_RANGE);
+            let mut bytes_read = 0u64;
+            while let Some(result) = reads.next().await {
+                bytes_read += result? as u64;
+            }
+            Ok::<u64, std::io::Error>(bytes_read)
+        }
+    });
+    Ok(try_join_all(reads).await?.into_iter().sum())
+}
@@ -0,0 +1,176 @@
+//! Extent-aware warming of compressed files (`--compressed-extents`).
+//!
+//! On btrfs mounted with `compress=zstd` (or zlib, lzo) and on ZFS datasets with
+//! compression, a file's size says little about the blocks behind it: 128 KiB of file may
+//! be 20 KiB on the device, and the filesystem always reads and decodes a compressed
+//! extent (a ZFS record) as a whole. Reading the file byte for byte decompresses all of
+//! it into the page cache, which for hydration is wasted CPU and cache. Sampling by file
+//! offset (`--sparse-large-files`) hits large extents several times and can step over
+//! short ones entirely, leaving their blocks cold.
+//!
+//! [`plan`] reads by extent instead: one page of each compressed extent, which makes the
+//! filesystem fetch all of its blocks, and the whole of each uncompressed one, so every
+//! block holding the file's data is read once. btrfs flags compressed extents in FIEMAP
+//! (`FIEMAP_EXTENT_ENCODED`). ZFS has no FIEMAP, so there a page is read from each record,
+//! whose size the dataset reports as the file's `st_blksize`.
+
+use std::path::Path;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::anonymize;
+use crate::fiemap::{self, Extent};
+
+use super::{read_range_blocking, Coverage, WarmingOptions, WarmingResult};
+
+/// Bytes read from each compressed extent or record; any part of one brings in all of it
+pub const TOUCH_SIZE: u64 = 4096;
+
+/// statfs(2) f_type of ZFS, which libc doesn't define
+#[cfg(target_os = "linux")]
+const ZFS_SUPER_MAGIC: libc::__fsword_t = 0x2fc1_2fc1;
+
+/// Filesystems that store file data compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compressing {
+    Btrfs,
+    Zfs,
+}
+
+/// The compressing filesystem `path` is on, if it is on one
+#[cfg(target_os = "linux")]
+pub fn filesystem(path: &Path) -> Option<Compressing> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    match stat.f_type {
+        libc::BTRFS_SUPER_MAGIC => Some(Compressing::Btrfs),
+        ZFS_SUPER_MAGIC => Some(Compressing::Zfs),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn filesystem(_path: &Path) -> Option<Compressing> {
+    None
+}
+
+/// What to read of a file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// `(offset, length)` reads in file order
+    pub reads: Vec<(u64, u64)>,
+    /// Compressed extents (btrfs) or records (ZFS) read with a single page
+    pub touched: usize,
+}
+
+impl Plan {
+    /// Bytes the reads ask for
+    pub fn bytes(&self) -> u64 {
+        self.reads.iter().map(|(_, length)| length).sum()
+    }
+}
+
+/// A page of each compressed extent, uncompressed extents whole and nothing of unwritten
+/// ones. `None` when no extent is compressed, so the file is best read as usual.
+pub fn plan_extents(extents: &[Extent], file_size: u64) -> Option<Plan> {
+    if !extents.iter().any(|extent| extent.encoded) {
+        return None;
+    }
+    let mut plan = Plan::default();
+    for extent in extents.iter().filter(|extent| !extent.unwritten && extent.logical < file_size) {
+        // The last extent is rounded up to the block size, past the end of the file
+        let length = extent.length.min(file_size - extent.logical);
+        if extent.encoded {
+            plan.reads.push((extent.logical, length.min(TOUCH_SIZE)));
+            plan.touched += 1;
+        } else {
+            plan.reads.push((extent.logical, length));
+        }
+    }
+    Some(plan)
+}
+
+/// A page at the start of every `record_size` record overlapping the `(offset, length)`
+/// ranges in `data`
+pub fn plan_records(data: &[(u64, u64)], record_size: u64) -> Plan {
+    let mut plan = Plan::default();
+    let mut next = 0;
+    for &(offset, length) in data {
+        let end = offset + length;
+        let mut record = (offset / record_size * record_size).max(next);
+        while record < end {
+            let start = record.max(offset);
+            plan.reads.push((start, TOUCH_SIZE.min(end - start)));
+            plan.touched += 1;
+            record += record_size;
+        }
+        next = record;
+    }
+    plan
+}
+
+/// Plan the reads for `path`, or `None` to read it as usual: it isn't on btrfs or ZFS,
+/// or nothing in it is stored compressed. Blocking.
+pub fn plan(path: &Path, file_size: u64) -> Result<Option<Plan>, std::io::Error> {
+    match filesystem(path) {
+        Some(Compressing::Btrfs) => match fiemap::extents(path) {
+            Ok(extents) => Ok(plan_extents(&extents, file_size)),
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => Ok(None),
+            Err(e) => Err(e),
+        },
+        Some(Compressing::Zfs) => {
+            use std::os::unix::fs::MetadataExt;
+
+            let record_size = std::fs::metadata(path)?.blksize();
+            // A file within one record is read whole anyway
+            if record_size <= TOUCH_SIZE || file_size <= record_size {
+                return Ok(None);
+            }
+            let data = fiemap::data_ranges(path, file_size)?.unwrap_or_else(|| vec![(0, file_size)]);
+            Ok(Some(plan_records(&data, record_size)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Warm `path` by extent if it's stored compressed. `None` means the file should go
+/// through the strategy chain as usual.
+pub async fn warm(path: &Path, file_size: u64, options: &WarmingOptions) -> Result<Option<WarmingResult>, std::io::Error> {
+    let start = Instant::now();
+    let chunk_size = options.read_size(1024 * 1024);
+    let drop_pages = options.drop_caches.per_file();
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let Some(plan) = plan(&path, file_size)? else {
+            return Ok(None);
+        };
+        debug!(
+            "{}: {} reads of {} bytes, {} compressed extents read by one page",
+            anonymize::display(&path),
+            plan.reads.len(),
+            plan.bytes(),
+            plan.touched
+        );
+        let file = std::fs::File::open(&path)?;
+        let mut buffer = vec![0u8; chunk_size];
+        let mut bytes_read = 0;
+        for &(offset, length) in &plan.reads {
+            bytes_read += read_range_blocking(&file, offset, length, &mut buffer, drop_pages)?;
+        }
+        Ok(Some(WarmingResult {
+            method: "compressed_extents",
+            success: true,
+            duration: start.elapsed(),
+            bytes_read,
+            coverage: Coverage::Full,
+        }))
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
@@ -0,0 +1,144 @@
+//! Why a file couldn't be warmed.
+//!
+//! Backends fail with whatever `std::io::Error` their system calls returned, which says
+//! what happened but not what to do about it. [`WarmingError`] sorts those errors into
+//! the few causes an operator handles differently: permission problems need another
+//! user or a filter, vanished files need nothing, device errors mean the volume needs
+//! looking at. The pipeline counts failures per cause in [`ErrorCounts`], and the run
+//! ends with the breakdown.
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A failure to warm a file, by cause. Each variant keeps the underlying I/O error.
+#[derive(Debug)]
+pub enum WarmingError {
+    /// EACCES or EPERM opening or reading the file
+    PermissionDenied(io::Error),
+    /// The file was deleted or replaced since discovery (ENOENT, ESTALE)
+    Vanished(io::Error),
+    /// The device failed the read (EIO, ENXIO, ENODEV, EREMOTEIO)
+    DeviceError(io::Error),
+    /// No strategy could handle the file, or its filesystem rejected the operation
+    Unsupported(io::Error),
+    /// The read timed out (ETIMEDOUT), as it can on network filesystems
+    Timeout(io::Error),
+    /// The host ran out of memory or file descriptors
+    Resource(io::Error),
+    Other(io::Error),
+}
+
+impl WarmingError {
+    /// Names of the causes, in variant order
+    pub const NAMES: [&'static str; 7] =
+        ["permission-denied", "vanished", "device-error", "unsupported", "timeout", "resource", "other"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.index()]
+    }
+
+    /// The I/O error behind this one
+    pub fn io(&self) -> &io::Error {
+        match self {
+            WarmingError::PermissionDenied(e)
+            | WarmingError::Vanished(e)
+            | WarmingError::DeviceError(e)
+            | WarmingError::Unsupported(e)
+            | WarmingError::Timeout(e)
+            | WarmingError::Resource(e)
+            | WarmingError::Other(e) => e,
+        }
+    }
+
+    pub fn into_io(self) -> io::Error {
+        match self {
+            WarmingError::PermissionDenied(e)
+            | WarmingError::Vanished(e)
+            | WarmingError::DeviceError(e)
+            | WarmingError::Unsupported(e)
+            | WarmingError::Timeout(e)
+            | WarmingError::Resource(e)
+            | WarmingError::Other(e) => e,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            WarmingError::PermissionDenied(_) => 0,
+            WarmingError::Vanished(_) => 1,
+            WarmingError::DeviceError(_) => 2,
+            WarmingError::Unsupported(_) => 3,
+            WarmingError::Timeout(_) => 4,
+            WarmingError::Resource(_) => 5,
+            WarmingError::Other(_) => 6,
+        }
+    }
+}
+
+impl From<io::Error> for WarmingError {
+    fn from(error: io::Error) -> Self {
+        // The errno is more specific than the kind, which lumps e.g. EIO in with everything else
+        match error.raw_os_error() {
+            Some(libc::EACCES | libc::EPERM) => return WarmingError::PermissionDenied(error),
+            Some(libc::ENOENT | libc::ESTALE) => return WarmingError::Vanished(error),
+            Some(libc::EIO | libc::ENXIO | libc::ENODEV) => return WarmingError::DeviceError(error),
+            #[cfg(target_os = "linux")]
+            Some(libc::EREMOTEIO) => return WarmingError::DeviceError(error),
+            Some(libc::EOPNOTSUPP | libc::ENOSYS) => return WarmingError::Unsupported(error),
+            Some(libc::ETIMEDOUT) => return WarmingError::Timeout(error),
+            Some(libc::ENOMEM | libc::EMFILE | libc::ENFILE) => return WarmingError::Resource(error),
+            _ => {}
+        }
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => WarmingError::PermissionDenied(error),
+            io::ErrorKind::NotFound => WarmingError::Vanished(error),
+            io::ErrorKind::Unsupported => WarmingError::Unsupported(error),
+            io::ErrorKind::TimedOut => WarmingError::Timeout(error),
+            io::ErrorKind::OutOfMemory => WarmingError::Resource(error),
+            _ => WarmingError::Other(error),
+        }
+    }
+}
+
+impl From<WarmingError> for io::Error {
+    fn from(error: WarmingError) -> Self {
+        error.into_io()
+    }
+}
+
+impl fmt::Display for WarmingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.io().fmt(f)
+    }
+}
+
+impl std::error::Error for WarmingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.io().source()
+    }
+}
+
+/// Failures of a run per cause
+#[derive(Debug, Default)]
+pub struct ErrorCounts {
+    counts: [AtomicU64; WarmingError::NAMES.len()],
+}
+
+impl ErrorCounts {
+    pub fn record(&self, error: &WarmingError) {
+        self.counts[error.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The causes that occurred and how often, most frequent first
+    pub fn snapshot(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = WarmingError::NAMES
+            .iter()
+            .zip(&self.counts)
+            .map(|(name, count)| (*name, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::Path;
 use std::os::unix::prelude::AsRawFd;
 use std::ptr::NonNull;
 use std::time::Instant;
@@ -10,11 +10,25 @@ use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
 #[cfg(target_os = "macos")]
 use nix::sys::mman::{madvise, MmapAdvise};
 
-use crate::warming::WarmingResult;
+use crate::warming::{Coverage, WarmingResult};
 
+/// Warm via OS read-ahead hints. With `drop_pages`, the pages are advised away again
+/// straight after (we only wanted the device reads, not the cache).
 pub async fn warm_with_os_hints(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
+    drop_pages: bool,
+) -> Result<WarmingResult, std::io::Error> {
+    warm_ranges_with_os_hints(path, file_size, &[(0, file_size)], drop_pages).await
+}
+
+/// [`warm_with_os_hints`] for just the `(offset, length)` `ranges` of the file, which
+/// start on page boundaries
+pub async fn warm_ranges_with_os_hints(
+    path: &Path,
+    file_size: u64,
+    ranges: &[(u64, u64)],
+    drop_pages: bool,
 ) -> Result<WarmingResult, std::io::Error> {
     let start = Instant::now();
     
@@ -23,7 +37,7 @@ pub async fn warm_with_os_hints(
     let (method, success) = if cfg!(target_os = "linux") {
         #[cfg(target_os = "linux")]
         {
-            let result = warm_with_fadvise(&file, file_size);
+            let result = ranges.iter().all(|&(offset, length)| warm_with_fadvise(&file, offset, length.min(file_size.saturating_sub(offset)), drop_pages));
             ("linux_fadvise", result)
         }
         #[cfg(not(target_os = "linux"))]
@@ -31,7 +45,7 @@ pub async fn warm_with_os_hints(
     } else if cfg!(target_os = "macos") {
         #[cfg(target_os = "macos")]
         {
-            let result = warm_with_madvise(&file, file_size);
+            let result = warm_with_madvise(&file, file_size, ranges, drop_pages);
             ("macos_madvise", result)
         }
         #[cfg(not(target_os = "macos"))]
@@ -44,20 +58,25 @@ pub async fn warm_with_os_hints(
         method,
         success,
         duration: start.elapsed(),
+        bytes_read: 0,
+        coverage: Coverage::Advisory,
     })
 }
 
 #[cfg(target_os = "linux")]
-fn warm_with_fadvise(file: &File, file_size: u64) -> bool {
+fn warm_with_fadvise(file: &File, offset: u64, length: u64, drop_pages: bool) -> bool {
     let start = Instant::now();
     let fd = file.as_raw_fd();
     
     // Step 1: Tell OS to read data (triggers EBS fetch from S3)
-    let warm_result = posix_fadvise(fd, 0, file_size as i64, PosixFadviseAdvice::POSIX_FADV_WILLNEED).is_ok();
+    let warm_result = posix_fadvise(fd, offset as i64, length as i64, PosixFadviseAdvice::POSIX_FADV_WILLNEED).is_ok();
     
-    if warm_result {
+    if warm_result && !drop_pages {
+        debug!("fadvise WILLNEED took {:?}, leaving pages cached", start.elapsed());
+        true
+    } else if warm_result {
         // Step 2: Immediately drop from cache (we only wanted EBS warming, not OS caching)
-        let drop_result = posix_fadvise(fd, 0, file_size as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED).is_ok();
+        let drop_result = posix_fadvise(fd, offset as i64, length as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED).is_ok();
         debug!("fadvise WILLNEED+DONTNEED took {:?}, warm: {}, drop: {}", start.elapsed(), warm_result, drop_result);
         
         // Success if we managed to warm (drop is less critical)
@@ -69,24 +88,32 @@ fn warm_with_fadvise(file: &File, file_size: u64) -> bool {
 }
 
 #[cfg(target_os = "macos")]
-fn warm_with_madvise(file: &File, file_size: u64) -> bool {
+fn warm_with_madvise(file: &File, file_size: u64, ranges: &[(u64, u64)], drop_pages: bool) -> bool {
     let start = Instant::now();
     let fd = file.as_raw_fd();
     let ptr = unsafe { nix::libc::mmap(std::ptr::null_mut(), file_size as usize, nix::libc::PROT_NONE, nix::libc::MAP_SHARED, fd, 0) };
     if ptr != nix::libc::MAP_FAILED {
-        let nn_ptr = NonNull::new(ptr).expect("mmap returned non-null but failed to create NonNull");
-        
-        // Step 1: Tell OS to read data (triggers EBS fetch from S3)
-        let warm_result = unsafe { madvise(nn_ptr, file_size as usize, MmapAdvise::MADV_WILLNEED) };
-        
-        if warm_result.is_ok() {
-            // Step 2: Immediately drop from cache (we only wanted EBS warming, not OS caching)
-            let drop_result = unsafe { madvise(nn_ptr, file_size as usize, MmapAdvise::MADV_FREE) };
-            debug!("madvise WILLNEED+FREE took {:?}, warm: {}, drop: {}", start.elapsed(), warm_result.is_ok(), drop_result.is_ok());
+        let mut warmed = true;
+        for &(offset, length) in ranges {
+            let length = length.min(file_size.saturating_sub(offset)) as usize;
+            if length == 0 {
+                continue;
+            }
+            let nn_ptr = NonNull::new(unsafe { ptr.cast::<u8>().add(offset as usize) }.cast()).expect("mmap returned non-null but failed to create NonNull");
+
+            // Step 1: Tell OS to read data (triggers EBS fetch from S3)
+            let warm_result = unsafe { madvise(nn_ptr, length, MmapAdvise::MADV_WILLNEED) };
+
+            if warm_result.is_ok() && drop_pages {
+                // Step 2: Immediately drop from cache (we only wanted EBS warming, not OS caching)
+                let drop_result = unsafe { madvise(nn_ptr, length, MmapAdvise::MADV_FREE) };
+                debug!("madvise WILLNEED+FREE took {:?}, warm: {}, drop: {}", start.elapsed(), warm_result.is_ok(), drop_result.is_ok());
+            }
+            warmed &= warm_result.is_ok();
         }
         
         unsafe { nix::libc::munmap(ptr, file_size as usize) };
-        warm_result.is_ok()
+        warmed
     } else {
         debug!("mmap failed for madvise operation");
         false
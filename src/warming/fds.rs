@@ -0,0 +1,215 @@
+//! A budget of open file descriptors for the files being warmed.
+//!
+//! Every file in flight holds a descriptor, and with a deep queue that can be more than
+//! `ulimit -n` allows: 1024 is still a common soft limit. Opens past it fail with EMFILE,
+//! which used to fail (and so skip) the file. Startup now raises the soft limit towards the
+//! hard one ([`raise_limit`]), and the pipeline checks out a descriptor from the
+//! [`FdBudget`] before warming each file, waiting for one to be returned once the budget
+//! (`--max-open-files`, or what the limit leaves after [`reserve`]) is checked out. Should
+//! an open still run out, because something else in the process holds more than
+//! expected, the budget shrinks to what was open at the time ([`FdBudget::exhausted`])
+//! and the file waits its turn to be tried again.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use tokio::sync::Notify;
+
+/// Most descriptors [`reserve`] keeps back from the budget
+pub const FD_RESERVE: u64 = 128;
+
+/// Times a file is tried again after running out of descriptors before it's failed
+pub const EXHAUSTED_RETRIES: u32 = 3;
+
+/// Descriptors left for everything but target files (logs, sockets, io_uring rings, the
+/// walker's directories) under a soft limit of `soft`: a quarter of it, at most
+/// [`FD_RESERVE`]
+pub fn reserve(soft: u64) -> u64 {
+    (soft / 4).min(FD_RESERVE)
+}
+
+/// Whether `error` is the process or the system running out of descriptors
+pub fn is_exhaustion(error: &std::io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+}
+
+/// `RLIMIT_NOFILE` before and after [`raise_limit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NofileLimit {
+    pub before: u64,
+    pub soft: u64,
+    pub hard: u64,
+}
+
+impl NofileLimit {
+    pub fn raised(&self) -> bool {
+        self.soft > self.before
+    }
+
+    /// Descriptors the soft limit leaves for files being warmed
+    pub fn available(&self) -> u64 {
+        self.soft.saturating_sub(reserve(self.soft)).max(1)
+    }
+}
+
+/// Raise the soft `RLIMIT_NOFILE` to `wanted`, or as close as the hard limit allows. A
+/// soft limit already at or above `wanted` is left alone.
+#[cfg(unix)]
+pub fn raise_limit(wanted: u64) -> std::io::Result<NofileLimit> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let (before, hard) = (limit.rlim_cur, limit.rlim_max);
+    let target = wanted.min(hard);
+    if target <= before {
+        return Ok(NofileLimit { before, soft: before, hard });
+    }
+    limit.rlim_cur = target;
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(NofileLimit { before, soft: target, hard })
+}
+
+#[cfg(not(unix))]
+pub fn raise_limit(_wanted: u64) -> std::io::Result<NofileLimit> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "descriptor limits need Unix"))
+}
+
+/// Point-in-time usage of an [`FdBudget`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FdStats {
+    /// Descriptors that may be checked out at once; 0 for no limit
+    pub limit: usize,
+    pub in_use: usize,
+    /// Most descriptors ever checked out at once
+    pub peak: usize,
+    /// Times a file had to wait for a descriptor to be returned
+    pub waits: u64,
+    /// Times an open still ran out of descriptors and the budget shrank
+    pub exhausted: u64,
+}
+
+#[derive(Default)]
+struct BudgetState {
+    in_use: usize,
+    peak: usize,
+}
+
+/// Descriptors checked out and returned under a ceiling
+pub struct FdBudget {
+    /// Most descriptors checked out at once; 0 for no ceiling
+    limit: AtomicUsize,
+    state: Mutex<BudgetState>,
+    returned: Notify,
+    waits: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl FdBudget {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit: AtomicUsize::new(limit),
+            state: Mutex::new(BudgetState::default()),
+            returned: Notify::new(),
+            waits: AtomicU64::new(0),
+            exhausted: AtomicU64::new(0),
+        })
+    }
+
+    /// The budget the pipeline draws from, set at startup from `--max-open-files`
+    pub fn global() -> &'static Arc<FdBudget> {
+        static BUDGET: OnceLock<Arc<FdBudget>> = OnceLock::new();
+        BUDGET.get_or_init(|| FdBudget::new(0))
+    }
+
+    /// Change the ceiling; descriptors already checked out are unaffected
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+        self.returned.notify_waiters();
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> FdStats {
+        let state = self.lock();
+        FdStats {
+            limit: self.limit(),
+            in_use: state.in_use,
+            peak: state.peak,
+            waits: self.waits.load(Ordering::Relaxed),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// A descriptor, or `None` if the budget is checked out
+    pub fn try_acquire(self: &Arc<Self>) -> Option<FdPermit> {
+        let mut state = self.lock();
+        let limit = self.limit();
+        if limit > 0 && state.in_use >= limit {
+            return None;
+        }
+        state.in_use += 1;
+        state.peak = state.peak.max(state.in_use);
+        Some(FdPermit { budget: Arc::clone(self) })
+    }
+
+    /// A descriptor, waiting for one to be returned if the budget is checked out
+    pub async fn acquire(self: &Arc<Self>) -> FdPermit {
+        let mut waited = false;
+        loop {
+            let returned = self.returned.notified();
+            tokio::pin!(returned);
+            // Registered before looking, so a descriptor returned in between still wakes us
+            returned.as_mut().enable();
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            if !waited {
+                self.waits.fetch_add(1, Ordering::Relaxed);
+                waited = true;
+            }
+            returned.await;
+        }
+    }
+
+    /// An open failed for want of descriptors while `permit` was held: shrink the budget
+    /// to the descriptors checked out besides it, which are evidently all the process has
+    /// room for. Returns the new limit if it shrank.
+    pub fn exhausted(&self, permit: FdPermit) -> Option<usize> {
+        let state = self.lock();
+        let limit = state.in_use.saturating_sub(1).max(1);
+        let current = self.limit();
+        let shrank = current == 0 || limit < current;
+        if shrank {
+            self.limit.store(limit, Ordering::Relaxed);
+        }
+        drop(state);
+        self.exhausted.fetch_add(1, Ordering::Relaxed);
+        drop(permit);
+        shrank.then_some(limit)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn release(&self) {
+        self.lock().in_use -= 1;
+        self.returned.notify_waiters();
+    }
+}
+
+/// A descriptor checked out of an [`FdBudget`], returned to it when dropped
+pub struct FdPermit {
+    budget: Arc<FdBudget>,
+}
+
+impl Drop for FdPermit {
+    fn drop(&mut self) {
+        self.budget.release();
+    }
+}
@@ -1,175 +1,188 @@
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::Instant;
 use log::debug;
 
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use futures::stream::{self, StreamExt};
 #[cfg(target_os = "linux")]
 use libc;
 
-use crate::warming::{WarmingResult, WarmingOptions};
+#[cfg(target_os = "linux")]
+use super::ring::SharedRing;
+
+use crate::anonymize;
+use crate::warming::{Coverage, WarmingResult, WarmingOptions, SPARSE_INTERVAL};
 
-/// Warm file using io_uring with optional direct I/O
+/// Warm file with reads submitted through the process-wide shared ring: direct I/O
+/// with `--direct-io`, buffered reads that also fill the page cache otherwise
 #[cfg(target_os = "linux")]
 pub async fn warm_file(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
     options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
-    debug!("Using io_uring + direct I/O for maximum EBS warming performance: {}", path.display());
-    
     if options.use_direct_io {
-        warm_with_io_uring_direct(path, file_size, options.sparse_large_files).await
+        debug!("Using io_uring + direct I/O for maximum EBS warming performance: {}", anonymize::display(path));
+        warm_with_io_uring_direct(path, file_size, options).await
     } else {
-        // For now, if not using direct I/O, fall back to standard approach
-        debug!("io_uring without direct I/O not yet implemented, falling back");
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Unsupported,
-            "io_uring without direct I/O not yet implemented"
-        ))
+        debug!("Using io_uring buffered reads: {}", anonymize::display(path));
+        warm_with_io_uring_buffered(path, file_size, options).await
     }
 }
 
+/// Warm a file smaller than a block with one linked open/read/close chain through the
+/// shared ring, so many small files share each submission and no descriptor ever
+/// reaches userspace. `None` if the kernel can't run such chains.
 #[cfg(target_os = "linux")]
-async fn warm_with_io_uring_direct(
-    path: &PathBuf,
-    file_size: u64,
-    sparse_large_files: u64,
-) -> Result<WarmingResult, std::io::Error> {
-    // For now, use libc direct I/O instead of complex io_uring setup
-    // This provides the same EBS warming benefits with simpler implementation
+pub async fn warm_small_file(path: &Path, options: &WarmingOptions) -> Result<Option<WarmingResult>, std::io::Error> {
     let start = Instant::now();
-    
-    // Open file with O_DIRECT
-    let fd = unsafe {
-        libc::open(
-            std::ffi::CString::new(path.to_string_lossy().as_ref()).unwrap().as_ptr(),
-            libc::O_RDONLY | libc::O_DIRECT,
-            0,
-        )
-    };
-    
-    if fd < 0 {
-        return Err(std::io::Error::last_os_error());
-    }
-    
-    let result = if sparse_large_files > 0 && file_size > sparse_large_files {
-        warm_sparse_io_uring_direct(fd, file_size).await
-    } else {
-        warm_full_io_uring_direct(fd).await
+    let ring = match SharedRing::global() {
+        Ok(ring) if ring.supports_small_files() => ring,
+        _ => return Ok(None),
     };
-    
-    unsafe { libc::close(fd) };
-    result
+    let bytes_read = ring.read_small_file(path, options.use_direct_io, options.drop_caches.per_file()).await? as u64;
+    debug!("io_uring linked small-file read of {}: {} bytes in {:?}", anonymize::display(path), bytes_read, start.elapsed());
+    Ok(Some(WarmingResult {
+        method: "io_uring_small_linked",
+        success: true,
+        duration: start.elapsed(),
+        bytes_read,
+        coverage: Coverage::Full,
+    }))
 }
 
+/// Reads each file keeps queued in the shared ring; enough to keep the device busy
+/// without one large file crowding out everyone else's small ones
 #[cfg(target_os = "linux")]
-async fn warm_sparse_io_uring_direct(
-    fd: libc::c_int,
+const READS_PER_FILE: usize = 8;
+
+/// Default read size for full reads
+#[cfg(target_os = "linux")]
+const FULL_READ_SIZE: usize = 65536;
+
+#[cfg(target_os = "linux")]
+async fn warm_with_io_uring_direct(
+    path: &Path,
     file_size: u64,
+    options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
+    use std::os::unix::fs::OpenOptionsExt;
+
     let start = Instant::now();
-    
-    let block_size = 4096u64; // Standard block size
-    let stride = 65536u64; // Read every 64KB
+    let ring = SharedRing::global()?;
+    let file = Arc::new(
+        std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)?,
+    );
+
+    let sparse = options.is_sparse(file_size);
+    let (read_size, stride, method) = if sparse {
+        (4096u64, Some(options.sample_interval(SPARSE_INTERVAL)), "io_uring_direct_sparse") // Read one block every interval
+    } else {
+        (options.read_size(FULL_READ_SIZE) as u64, None, "io_uring_direct_full")
+    };
+
+    let mut reads = stream::iter(options.reads(file_size, read_size, stride, true))
+        .map(|(offset, len)| ring.read(Arc::clone(&file), offset, len as usize))
+        .buffer_unordered(READS_PER_FILE);
     let mut bytes_read = 0u64;
-    
-    // Allocate aligned buffer for direct I/O
-    let layout = std::alloc::Layout::from_size_align(block_size as usize, block_size as usize)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to create aligned memory layout"))?;
-    let buffer = unsafe { std::alloc::alloc(layout) };
-    if buffer.is_null() {
-        return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
+    while let Some(result) = reads.next().await {
+        bytes_read += result? as u64;
     }
-    
-    let mut offset = 0;
-    while offset < file_size {
-        // Use pread for direct I/O (io_uring would do similar but with async queuing)
-        let result = unsafe {
-            libc::pread(fd, buffer.cast(), block_size as usize, offset as libc::off_t)
-        };
-        
-        if result > 0 {
-            bytes_read += result as u64;
-        } else if result == 0 {
-            break; // EOF
-        } else {
-            debug!("io_uring read error at offset {}: {}", offset, std::io::Error::last_os_error());
-            // Continue with next block on error
-        }
-        
-        offset += stride;
-        
-        // Yield to allow other tasks to run (simulating async behavior)
-        tokio::task::yield_now().await;
-    }
-    
-    unsafe { 
-        std::alloc::dealloc(buffer, layout);
-    }
-    
-    debug!("Sparse io_uring + direct I/O completed: {} bytes read in {:?}", bytes_read, start.elapsed());
+
+    debug!("io_uring + direct I/O ({}) completed: {} bytes read in {:?}", method, bytes_read, start.elapsed());
     Ok(WarmingResult {
-        method: "io_uring_direct_sparse",
+        method,
         success: true,
         duration: start.elapsed(),
+        bytes_read,
+        coverage: if sparse { Coverage::Sampled } else { Coverage::Full },
     })
 }
 
+/// Buffered reads through the shared ring. Pages stay cached unless `--drop-caches-after
+/// file`, in which case they're dropped with POSIX_FADV_DONTNEED once the file is read.
 #[cfg(target_os = "linux")]
-async fn warm_full_io_uring_direct(
-    fd: libc::c_int,
+async fn warm_with_io_uring_buffered(
+    path: &Path,
+    file_size: u64,
+    options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
     let start = Instant::now();
-    
-    let block_size = 65536; // 64KB blocks for efficient reading
-    let mut total_bytes_read = 0u64;
-    let mut offset = 0;
-    
-    // Allocate aligned buffer for direct I/O
-    let layout = std::alloc::Layout::from_size_align(block_size, block_size)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to create aligned memory layout"))?;
-    let buffer = unsafe { std::alloc::alloc(layout) };
-    if buffer.is_null() {
-        return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
-    }
-    
-    loop {
-        // Use pread for direct I/O (io_uring would do similar but with async queuing)
-        let result = unsafe {
-            libc::pread(fd, buffer.cast(), block_size, offset as libc::off_t)
-        };
-        
-        if result > 0 {
-            total_bytes_read += result as u64;
-            offset += result;
-        } else if result == 0 {
-            break; // EOF
-        } else {
-            unsafe { 
-                std::alloc::dealloc(buffer, layout);
-            }
-            return Err(std::io::Error::last_os_error());
-        }
-        
-        // Yield to allow other tasks to run (simulating async behavior)
-        tokio::task::yield_now().await;
+    let ring = SharedRing::global()?;
+    let file = Arc::new(std::fs::File::open(path)?);
+
+    let sparse = options.is_sparse(file_size);
+    let (read_size, stride, method) = if sparse {
+        (4096u64, Some(options.sample_interval(SPARSE_INTERVAL)), "io_uring_buffered_sparse")
+    } else {
+        (options.read_size(FULL_READ_SIZE) as u64, None, "io_uring_buffered_full")
+    };
+
+    let nowait = options.nowait_precheck;
+    let mut reads = stream::iter(options.reads(file_size, read_size, stride, false))
+        .map(|(offset, len)| read_buffered(ring, Arc::clone(&file), offset, len as usize, nowait))
+        .buffer_unordered(READS_PER_FILE);
+    let (mut bytes_read, mut cached) = (0u64, 0u64);
+    while let Some(result) = reads.next().await {
+        let (read, already_cached) = result?;
+        bytes_read += read;
+        cached += already_cached;
     }
-    
-    unsafe { 
-        std::alloc::dealloc(buffer, layout);
+
+    if options.drop_caches.per_file() {
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
     }
-    
-    debug!("Full io_uring + direct I/O completed: {} bytes read in {:?}", total_bytes_read, start.elapsed());
+    debug!(
+        "io_uring buffered ({}) completed: {} bytes read ({} already cached) in {:?}",
+        method,
+        bytes_read,
+        cached,
+        start.elapsed()
+    );
     Ok(WarmingResult {
-        method: "io_uring_direct_full",
+        method,
         success: true,
         duration: start.elapsed(),
+        bytes_read,
+        coverage: if sparse { Coverage::Sampled } else { Coverage::Full },
     })
 }
 
+/// One buffered read; returns the bytes read and how many of them were already cached.
+/// With `nowait` the read is first tried with RWF_NOWAIT, so cached data is served inline
+/// and only the rest goes to the device.
+#[cfg(target_os = "linux")]
+async fn read_buffered(ring: &SharedRing, file: Arc<std::fs::File>, offset: u64, len: usize, nowait: bool) -> Result<(u64, u64), std::io::Error> {
+    let mut cached = 0;
+    if nowait {
+        match ring.read_with_flags(Arc::clone(&file), offset, len, libc::RWF_NOWAIT).await {
+            // All of it was cached, or the file ends here
+            Ok(n) if n == len || n == 0 => return Ok((n as u64, n as u64)),
+            Ok(n) => cached = n,
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EAGAIN) | Some(libc::EOPNOTSUPP)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let n = ring.read(file, offset + cached as u64, len - cached).await?;
+    Ok(((cached + n) as u64, cached as u64))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn warm_small_file(_path: &Path, _options: &WarmingOptions) -> Result<Option<WarmingResult>, std::io::Error> {
+    Ok(None)
+}
+
 // Stub implementation for non-Linux systems
 #[cfg(not(target_os = "linux"))]
 pub async fn warm_file(
-    _path: &PathBuf,
+    _path: &Path,
     _file_size: u64,
     _options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
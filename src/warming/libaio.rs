@@ -1,38 +1,53 @@
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::Instant;
 use log::debug;
 
 #[cfg(target_os = "linux")]
 use libc;
 
-use crate::warming::{WarmingResult, WarmingOptions};
+use crate::anonymize;
+#[cfg(target_os = "linux")]
+use crate::warming::buffers::BufferPool;
+use crate::warming::{Coverage, WarmingResult, WarmingOptions, SPARSE_INTERVAL};
+use crate::warnings::{self, Category};
+
+/// Reads kept in flight per file by buffered AIO
+#[cfg(target_os = "linux")]
+const AIO_DEPTH: usize = 16;
+
+/// Per-file AIO depth in low-memory mode, keeping buffers under a MiB
+#[cfg(target_os = "linux")]
+const LOW_MEMORY_AIO_DEPTH: usize = 2;
+
+/// Read size for full buffered AIO reads
+#[cfg(target_os = "linux")]
+const FULL_READ_SIZE: usize = 65536;
+
+#[cfg(target_os = "linux")]
+const IOCB_CMD_PREAD: u16 = 0;
 
-/// Warm file using Linux AIO (libaio) with optional direct I/O
+/// Warm file using Linux AIO (libaio): direct I/O with `--direct-io`, buffered reads
+/// that also fill the page cache otherwise
 #[cfg(target_os = "linux")]
 pub async fn warm_file(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
     options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
-    debug!("Using libaio + direct I/O for high-performance EBS warming: {}", path.display());
-    
     if options.use_direct_io {
-        warm_with_libaio_direct(path, file_size, options.sparse_large_files).await
+        debug!("Using libaio + direct I/O for high-performance EBS warming: {}", anonymize::display(path));
+        warm_with_libaio_direct(path, file_size, options).await
     } else {
-        // For now, if not using direct I/O, fall back to standard approach
-        debug!("libaio without direct I/O not yet implemented, falling back");
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Unsupported,
-            "libaio without direct I/O not yet implemented"
-        ))
+        debug!("Using libaio buffered reads: {}", anonymize::display(path));
+        warm_with_libaio_buffered(path, file_size, options).await
     }
 }
 
 #[cfg(target_os = "linux")]
 async fn warm_with_libaio_direct(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
-    sparse_large_files: u64,
+    options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
     let start = Instant::now();
     
@@ -49,10 +64,11 @@ async fn warm_with_libaio_direct(
         return Err(std::io::Error::last_os_error());
     }
     
-    let result = if sparse_large_files > 0 && file_size > sparse_large_files {
-        warm_sparse_libaio_direct(fd, file_size).await
+    let result = if options.is_sparse(file_size) {
+        warm_sparse_libaio_direct(fd, options.reads(file_size, 4096, Some(options.sample_interval(SPARSE_INTERVAL)), true)).await
     } else {
-        warm_full_libaio_direct(fd).await
+        let block_size = options.read_size(FULL_READ_SIZE);
+        warm_full_libaio_direct(fd, block_size, options.reads(file_size, block_size as u64, None, true)).await
     };
     
     unsafe { libc::close(fd) };
@@ -62,27 +78,20 @@ async fn warm_with_libaio_direct(
 #[cfg(target_os = "linux")]
 async fn warm_sparse_libaio_direct(
     fd: libc::c_int,
-    file_size: u64,
+    samples: impl Iterator<Item = (u64, u64)>,
 ) -> Result<WarmingResult, std::io::Error> {
     let start = Instant::now();
     
     let block_size = 4096u64; // Standard block size
-    let stride = 65536u64; // Read every 64KB
     let mut bytes_read = 0u64;
     
-    // Allocate aligned buffer for direct I/O
-    let layout = std::alloc::Layout::from_size_align(block_size as usize, block_size as usize)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to create aligned memory layout"))?;
-    let buffer = unsafe { std::alloc::alloc(layout) };
-    if buffer.is_null() {
-        return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
-    }
+    // Aligned buffer for direct I/O
+    let mut buffer = BufferPool::global().get(block_size as usize).await?;
     
-    let mut offset = 0;
-    while offset < file_size {
+    for (offset, len) in samples {
         // Use pread for aligned direct I/O reads
         let result = unsafe {
-            libc::pread(fd, buffer.cast(), block_size as usize, offset as libc::off_t)
+            libc::pread(fd, buffer.as_mut_ptr().cast(), len as usize, offset as libc::off_t)
         };
         
         if result > 0 {
@@ -90,78 +99,241 @@ async fn warm_sparse_libaio_direct(
         } else if result == 0 {
             break; // EOF
         } else {
-            debug!("read error at offset {}: {}", offset, std::io::Error::last_os_error());
+            let e = std::io::Error::last_os_error();
+            warnings::report(Category::of(&e), format_args!("libaio read error at offset {}: {}", offset, e));
             // Continue with next block on error
         }
-        
-        offset += stride;
-    }
-    
-    unsafe { 
-        std::alloc::dealloc(buffer, layout);
     }
     
     debug!("Sparse libaio + direct I/O completed: {} bytes read in {:?}", bytes_read, start.elapsed());
     Ok(WarmingResult {
         method: "libaio_direct_sparse",
+        coverage: Coverage::Sampled,
         success: true,
         duration: start.elapsed(),
+        bytes_read,
     })
 }
 
 #[cfg(target_os = "linux")]
 async fn warm_full_libaio_direct(
     fd: libc::c_int,
+    block_size: usize,
+    reads: impl Iterator<Item = (u64, u64)>,
 ) -> Result<WarmingResult, std::io::Error> {
     let start = Instant::now();
     
-    let block_size = 65536; // 64KB blocks for efficient reading
     let mut total_bytes_read = 0u64;
-    let mut offset = 0;
     
-    // Allocate aligned buffer for direct I/O
-    let layout = std::alloc::Layout::from_size_align(block_size, block_size)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to create aligned memory layout"))?;
-    let buffer = unsafe { std::alloc::alloc(layout) };
-    if buffer.is_null() {
-        return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
-    }
+    // Aligned buffer for direct I/O
+    let mut buffer = BufferPool::global().get(block_size).await?;
     
-    loop {
+    for (offset, len) in reads {
         // Use pread for aligned direct I/O reads
         let result = unsafe {
-            libc::pread(fd, buffer.cast(), block_size, offset as libc::off_t)
+            libc::pread(fd, buffer.as_mut_ptr().cast(), len as usize, offset as libc::off_t)
         };
         
         if result > 0 {
             total_bytes_read += result as u64;
-            offset += result;
         } else if result == 0 {
             break; // EOF
         } else {
-            unsafe { 
-                std::alloc::dealloc(buffer, layout);
-            }
             return Err(std::io::Error::last_os_error());
         }
     }
     
-    unsafe { 
-        std::alloc::dealloc(buffer, layout);
-    }
-    
     debug!("Full libaio + direct I/O completed: {} bytes read in {:?}", total_bytes_read, start.elapsed());
     Ok(WarmingResult {
         method: "libaio_direct_full",
+        coverage: Coverage::Full,
         success: true,
         duration: start.elapsed(),
+        bytes_read: total_bytes_read,
+    })
+}
+
+/// `struct iocb` from linux/aio_abi.h
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct Iocb {
+    aio_data: u64,
+    #[cfg(target_endian = "little")]
+    aio_key: u32,
+    aio_rw_flags: i32,
+    #[cfg(target_endian = "big")]
+    aio_key: u32,
+    aio_lio_opcode: u16,
+    aio_reqprio: i16,
+    aio_fildes: u32,
+    aio_buf: u64,
+    aio_nbytes: u64,
+    aio_offset: i64,
+    aio_reserved2: u64,
+    aio_flags: u32,
+    aio_resfd: u32,
+}
+
+/// `struct io_event` from linux/aio_abi.h
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct IoEvent {
+    data: u64,
+    obj: u64,
+    res: i64,
+    res2: i64,
+}
+
+/// An AIO context, destroyed (cancelling anything still in flight) on drop
+#[cfg(target_os = "linux")]
+struct AioContext(libc::c_ulong);
+
+#[cfg(target_os = "linux")]
+impl AioContext {
+    fn new(depth: usize) -> Result<Self, std::io::Error> {
+        let mut ctx: libc::c_ulong = 0;
+        let result = unsafe { libc::syscall(libc::SYS_io_setup, depth as libc::c_long, &mut ctx as *mut libc::c_ulong) };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self(ctx))
+    }
+
+    /// Submit `iocbs`, returning how many the kernel took
+    fn submit(&self, iocbs: &mut [*mut Iocb]) -> Result<usize, std::io::Error> {
+        let result = unsafe { libc::syscall(libc::SYS_io_submit, self.0, iocbs.len() as libc::c_long, iocbs.as_mut_ptr()) };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(result as usize)
+    }
+
+    /// Wait for at least one completion
+    fn reap(&self, events: &mut [IoEvent]) -> Result<usize, std::io::Error> {
+        loop {
+            let result = unsafe {
+                libc::syscall(libc::SYS_io_getevents, self.0, 1 as libc::c_long, events.len() as libc::c_long, events.as_mut_ptr(), std::ptr::null_mut::<libc::timespec>())
+            };
+            if result >= 0 {
+                return Ok(result as usize);
+            }
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for AioContext {
+    fn drop(&mut self) {
+        unsafe { libc::syscall(libc::SYS_io_destroy, self.0) };
+    }
+}
+
+/// Buffered reads submitted through an AIO context, keeping up to [`AIO_DEPTH`] reads of
+/// the file in flight. Pages stay cached unless `--drop-caches-after file`, in which case
+/// they're dropped with POSIX_FADV_DONTNEED once the file is read.
+#[cfg(target_os = "linux")]
+async fn warm_with_libaio_buffered(
+    path: &std::path::Path,
+    file_size: u64,
+    options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let path = path.to_path_buf();
+    let sparse = options.is_sparse(file_size);
+    let (read_size, stride, method) = if sparse {
+        (4096usize, Some(options.sample_interval(SPARSE_INTERVAL)), "libaio_buffered_sparse")
+    } else {
+        (options.read_size(FULL_READ_SIZE), None, "libaio_buffered_full")
+    };
+    let mut reads = options.reads(file_size, read_size as u64, stride, false);
+    let depth = if options.low_memory { LOW_MEMORY_AIO_DEPTH } else { AIO_DEPTH };
+    let drop_pages = options.drop_caches.per_file();
+
+    // Buffered AIO reads may complete inside io_submit(2), so keep it off the runtime threads
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let file = std::fs::File::open(&path)?;
+        let ctx = AioContext::new(depth)?;
+        let mut buffers = vec![vec![0u8; read_size]; depth];
+        let mut iocbs: Vec<Iocb> = (0..depth).map(|_| Iocb::default()).collect();
+        let mut free: Vec<usize> = (0..depth).rev().collect();
+        let mut events = vec![IoEvent::default(); depth];
+        // Reads io_submit(2) didn't take yet
+        let mut retry: Vec<(u64, u64)> = Vec::new();
+        let mut bytes_read = 0u64;
+
+        loop {
+            let mut batch = Vec::new();
+            while let Some(&slot) = free.last() {
+                let Some((offset, len)) = retry.pop().or_else(|| reads.next()) else { break };
+                free.pop();
+                iocbs[slot] = Iocb {
+                    aio_data: slot as u64,
+                    aio_lio_opcode: IOCB_CMD_PREAD,
+                    aio_fildes: file.as_raw_fd() as u32,
+                    aio_buf: buffers[slot].as_mut_ptr() as u64,
+                    aio_nbytes: len,
+                    aio_offset: offset as i64,
+                    ..Default::default()
+                };
+                batch.push(&mut iocbs[slot] as *mut Iocb);
+            }
+            let submitted = match ctx.submit(&mut batch) {
+                Ok(n) => n,
+                // Out of kernel AIO requests; try again once some have completed
+                Err(e) if e.raw_os_error() == Some(libc::EAGAIN) => 0,
+                Err(e) => return Err(e),
+            };
+            for slot in batch[submitted..].iter().map(|iocb| unsafe { (**iocb).aio_data } as usize) {
+                free.push(slot);
+                retry.push((iocbs[slot].aio_offset as u64, iocbs[slot].aio_nbytes));
+            }
+            if free.len() == depth {
+                if retry.is_empty() {
+                    break;
+                }
+                return Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, "io_submit accepted no reads"));
+            }
+
+            let completed = ctx.reap(&mut events)?;
+            for event in &events[..completed] {
+                free.push(event.data as usize);
+                if event.res < 0 {
+                    let e = std::io::Error::from_raw_os_error(-event.res as i32);
+                    warnings::report(Category::of(&e), format_args!("libaio read error in {}: {}", anonymize::display(&path), e));
+                    return Err(e);
+                }
+                bytes_read += event.res as u64;
+            }
+        }
+
+        if drop_pages {
+            unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+        }
+        debug!("libaio buffered ({}) completed: {} bytes read in {:?}", method, bytes_read, start.elapsed());
+        Ok(WarmingResult {
+            method,
+            coverage: if sparse { Coverage::Sampled } else { Coverage::Full },
+            success: true,
+            duration: start.elapsed(),
+            bytes_read,
+        })
     })
+    .await
+    .map_err(|e| std::io::Error::other(format!("libaio task failed: {}", e)))?
 }
 
 // Stub implementation for non-Linux systems
 #[cfg(not(target_os = "linux"))]
 pub async fn warm_file(
-    _path: &PathBuf,
+    _path: &Path,
     _file_size: u64,
     _options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
@@ -0,0 +1,163 @@
+//! mmap + `MADV_WILLNEED` strategy (Linux).
+//!
+//! `posix_fadvise(WILLNEED)` is only advice, and some filesystems ignore it. This
+//! strategy maps the file a window at a time, asks for the whole window with
+//! `madvise(MADV_WILLNEED)`, then touches every Nth page
+//! ([`WarmingOptions::mmap_touch_stride`]) so those pages have provably been fetched
+//! by the time the file is reported warm. Touching is done with
+//! `MADV_POPULATE_READ` (Linux 5.14), which faults pages in without copying them and
+//! returns an error instead of raising SIGBUS if the file shrinks under the mapping;
+//! older kernels get a one-byte `pread` per touched page instead. A stride of 0 only
+//! gives the advice, like fadvise.
+
+use std::path::Path;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::anonymize;
+use crate::warming::{Coverage, WarmingOptions, WarmingResult, SPARSE_INTERVAL};
+
+/// Bytes mapped at a time, so a huge file never needs a huge mapping
+#[cfg(target_os = "linux")]
+const WINDOW_SIZE: u64 = 64 * 1024 * 1024;
+
+#[cfg(target_os = "linux")]
+const PAGE_SIZE: u64 = 4096;
+
+
+#[cfg(target_os = "linux")]
+pub async fn warm_file(
+    path: &Path,
+    file_size: u64,
+    options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    use std::os::unix::prelude::AsRawFd;
+
+    let path = path.to_path_buf();
+    let sparse = options.is_sparse(file_size);
+    let stride = match options.mmap_touch_stride {
+        0 => 0,
+        // Pages between touches in sparse mode: one page per sample interval, like the other sparse strategies
+        stride if sparse => stride.max(options.sample_interval(SPARSE_INTERVAL) / PAGE_SIZE),
+        stride => stride,
+    };
+    let drop_pages = options.drop_caches.per_file();
+    // Mappings start on a page
+    let ranges = options.ranges_to_read(file_size, true);
+
+    // Faulting pages in blocks on the device, so keep it off the runtime threads
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let file = std::fs::File::open(&path)?;
+        let fd = file.as_raw_fd();
+
+        let (mut touched, mut mapped) = (0u64, 0u64);
+        for (from, length) in ranges {
+            // Pages past the end of the file can't be faulted in
+            let end = (from + length).min(file_size);
+            let mut offset = from;
+            while offset < end {
+                let len = WINDOW_SIZE.min(end - offset);
+                touched += warm_window(fd, offset, len, stride)?;
+                if drop_pages {
+                    let _ = nix::fcntl::posix_fadvise(fd, offset as i64, len as i64, nix::fcntl::PosixFadviseAdvice::POSIX_FADV_DONTNEED);
+                }
+                offset += len;
+            }
+            mapped += end.saturating_sub(from);
+        }
+
+        let (method, coverage) = match stride {
+            0 => ("mmap_willneed", Coverage::Advisory),
+            1 => ("mmap_touch", Coverage::Full),
+            _ if sparse => ("mmap_touch_sparse", Coverage::Sampled),
+            _ => ("mmap_touch_strided", Coverage::Sampled),
+        };
+        let bytes_read = if stride == 1 { mapped } else { touched.min(mapped) };
+        debug!("{} of {}: {} bytes touched in {:?}", method, anonymize::display(&path), bytes_read, start.elapsed());
+        Ok(WarmingResult { method, success: true, duration: start.elapsed(), bytes_read, coverage })
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/// Map `len` bytes at `offset`, advise WILLNEED and touch every `stride`th page.
+/// Returns the bytes touched.
+#[cfg(target_os = "linux")]
+fn warm_window(fd: libc::c_int, offset: u64, len: u64, stride: u64) -> Result<u64, std::io::Error> {
+    let addr = unsafe { libc::mmap(std::ptr::null_mut(), len as usize, libc::PROT_READ, libc::MAP_SHARED, fd, offset as libc::off_t) };
+    if addr == libc::MAP_FAILED {
+        let err = std::io::Error::last_os_error();
+        // ENODEV: the file type can't be mapped (e.g. some FUSE or special files)
+        if err.raw_os_error() == Some(libc::ENODEV) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, err));
+        }
+        return Err(err);
+    }
+    let result = advise_and_touch(fd, addr, offset, len, stride);
+    unsafe { libc::munmap(addr, len as usize) };
+    result
+}
+
+#[cfg(target_os = "linux")]
+fn advise_and_touch(fd: libc::c_int, addr: *mut libc::c_void, offset: u64, len: u64, stride: u64) -> Result<u64, std::io::Error> {
+    if unsafe { libc::madvise(addr, len as usize, libc::MADV_WILLNEED) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if stride == 0 {
+        return Ok(0);
+    }
+    if stride == 1 {
+        return populate(fd, addr, offset, 0, len).map(|()| len);
+    }
+
+    let mut touched = 0u64;
+    let mut page = 0u64;
+    while page < len {
+        let count = PAGE_SIZE.min(len - page);
+        populate(fd, addr, offset, page, count)?;
+        touched += count;
+        page += stride * PAGE_SIZE;
+    }
+    Ok(touched)
+}
+
+/// Fault in `count` bytes at `at` within the window mapped at `addr` (file offset
+/// `offset`)
+#[cfg(target_os = "linux")]
+fn populate(fd: libc::c_int, addr: *mut libc::c_void, offset: u64, at: u64, count: u64) -> Result<(), std::io::Error> {
+    let start = unsafe { addr.cast::<u8>().add(at as usize) }.cast();
+    if unsafe { libc::madvise(start, count as usize, libc::MADV_POPULATE_READ) } == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() != Some(libc::EINVAL) {
+        return Err(err);
+    }
+    // Kernels before 5.14 don't know MADV_POPULATE_READ; reading one byte of each page
+    // through the fd fetches the same pages without risking SIGBUS on a truncated file
+    let mut byte = [0u8; 1];
+    let mut page = 0u64;
+    while page < count {
+        let read = unsafe { libc::pread(fd, byte.as_mut_ptr().cast(), 1, (offset + at + page) as libc::off_t) };
+        if read < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        page += PAGE_SIZE;
+    }
+    Ok(())
+}
+
+// Stub implementation for non-Linux systems
+#[cfg(not(target_os = "linux"))]
+pub async fn warm_file(
+    _path: &Path,
+    _file_size: u64,
+    _options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the mmap strategy is only supported on Linux"
+    ))
+}
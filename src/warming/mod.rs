@@ -1,83 +1,1090 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use futures::future::LocalBoxFuture;
 use log::debug;
 
+use crate::anonymize;
+use crate::probe;
+use crate::telemetry::{self, Instrument};
+use crate::warnings::{self, Category};
+
+pub mod buffers;
+pub mod chunked;
+pub mod compressed;
+pub mod error;
 pub mod fallback;
+pub mod fds;
 pub mod tokio_async;
+pub mod libaio;
+pub mod io_uring;
+pub mod mmap;
+pub mod nowait;
+pub mod readahead;
+pub mod residency;
+pub mod sendfile;
+pub mod size_class;
 
 #[cfg(target_os = "linux")]
-pub mod libaio;
+pub mod ring;
+
+pub use error::{ErrorCounts, WarmingError};
+pub use size_class::{ClassStrategy, SizeClass, StrategyMap};
+
+/// Warming strategies that can be selected with `--strategy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Strategy {
+    /// Walk the default fallback chain for the current I/O mode
+    #[default]
+    Auto,
+    /// io_uring (Linux 5.1+)
+    Uring,
+    /// Linux AIO
+    Libaio,
+    /// OS hints: posix_fadvise on Linux, madvise on macOS
+    Fadvise,
+    /// readahead(2) into the page cache without userspace copies
+    Readahead,
+    /// mmap + MADV_WILLNEED, touching pages to make sure they were fetched (Linux)
+    Mmap,
+    /// sendfile(2) to /dev/null: reads through the page cache with no userspace buffer (Linux)
+    Sendfile,
+    /// Plain Tokio async reads, the universal fallback
+    Tokio,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 7] = [
+        Strategy::Uring,
+        Strategy::Libaio,
+        Strategy::Fadvise,
+        Strategy::Readahead,
+        Strategy::Mmap,
+        Strategy::Sendfile,
+        Strategy::Tokio,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Auto => "auto",
+            Strategy::Uring => "uring",
+            Strategy::Libaio => "libaio",
+            Strategy::Fadvise => "fadvise",
+            Strategy::Readahead => "readahead",
+            Strategy::Mmap => "mmap",
+            Strategy::Sendfile => "sendfile",
+            Strategy::Tokio => "tokio",
+        }
+    }
+
+    /// Fallback chain used by `auto`, in priority order
+    pub fn default_chain(use_direct_io: bool) -> &'static [Strategy] {
+        if use_direct_io {
+            &[Strategy::Uring, Strategy::Libaio, Strategy::Tokio]
+        } else {
+            &[Strategy::Readahead, Strategy::Mmap, Strategy::Sendfile, Strategy::Fadvise, Strategy::Tokio]
+        }
+    }
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(Strategy::Auto),
+            "uring" | "io_uring" | "io-uring" => Ok(Strategy::Uring),
+            "libaio" | "aio" => Ok(Strategy::Libaio),
+            "fadvise" | "madvise" | "os_hints" | "os-hints" => Ok(Strategy::Fadvise),
+            "readahead" => Ok(Strategy::Readahead),
+            "mmap" => Ok(Strategy::Mmap),
+            "sendfile" | "splice" | "zero-copy" => Ok(Strategy::Sendfile),
+            "tokio" => Ok(Strategy::Tokio),
+            other => Err(format!(
+                "unknown strategy '{}' (expected one of: auto, uring, libaio, fadvise, readahead, mmap, sendfile, tokio)",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What to try when the selected strategy is unavailable for a file
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FallbackPolicy {
+    /// Fall back through the default chain for the current I/O mode
+    #[default]
+    Auto,
+    /// Never fall back; fail files the selected strategy can't handle
+    None,
+    /// Fall back through exactly these strategies, in order
+    Chain(Vec<Strategy>),
+}
+
+impl std::str::FromStr for FallbackPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(FallbackPolicy::Auto),
+            "none" | "off" => Ok(FallbackPolicy::None),
+            list => {
+                let chain = list
+                    .split(',')
+                    .map(|name| name.parse::<Strategy>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                if chain.contains(&Strategy::Auto) {
+                    return Err("'auto' cannot be part of an explicit fallback chain".to_string());
+                }
+                Ok(FallbackPolicy::Chain(chain))
+            }
+        }
+    }
+}
+
+/// What happens to page-cache pages pulled in while warming (`--drop-caches-after`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropCaches {
+    /// Leave them cached
+    None,
+    /// Drop each file's pages (POSIX_FADV_DONTNEED) as soon as it has been warmed
+    #[default]
+    File,
+    /// Leave pages during the run and drop the whole page cache once at the end (needs root)
+    Global,
+}
+
+impl DropCaches {
+    /// Whether strategies should drop pages after each file
+    pub fn per_file(&self) -> bool {
+        *self == DropCaches::File
+    }
+}
+
+impl std::str::FromStr for DropCaches {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(DropCaches::None),
+            "file" => Ok(DropCaches::File),
+            "global" => Ok(DropCaches::Global),
+            other => Err(format!("unknown drop-caches policy '{}' (expected none, file or global)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for DropCaches {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DropCaches::None => "none",
+            DropCaches::File => "file",
+            DropCaches::Global => "global",
+        })
+    }
+}
 
+/// Flush dirty pages and drop all clean page-cache pages system-wide, as
+/// `sync; echo 1 > /proc/sys/vm/drop_caches` would. Requires CAP_SYS_ADMIN.
 #[cfg(target_os = "linux")]
-pub mod io_uring;
+pub fn drop_global_page_cache() -> Result<(), std::io::Error> {
+    unsafe { libc::sync() };
+    std::fs::write("/proc/sys/vm/drop_caches", "1")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_global_page_cache() -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "global cache drop is only supported on Linux"))
+}
 
 /// Warming strategy options
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct WarmingOptions {
-    pub use_io_uring: bool,
-    pub use_libaio: bool,
+    pub strategy: Strategy,
+    pub fallback: FallbackPolicy,
     pub use_direct_io: bool,
     pub sparse_large_files: u64,
+    /// Use small I/O chunks so per-file buffers stay well under a MiB
+    pub low_memory: bool,
+    /// Only read pages that aren't already in the page cache
+    pub skip_cached: bool,
+    /// Read sparse-file holes too, instead of only allocated extents
+    pub read_holes: bool,
+    pub drop_caches: DropCaches,
+    /// Read files of at least two [`chunked::MIN_RANGE_SIZE`] ranges in up to this many
+    /// ranges at once; 0 or 1 reads every file sequentially
+    pub intra_file_parallelism: usize,
+    /// Restricts reads to the byte ranges it selects for each file
+    pub range_selector: Option<Arc<dyn RangeSelector>>,
+    /// Bytes per read for full (non-sampled) reads; 0 leaves each backend's default.
+    /// Must be a multiple of 4096 for direct I/O.
+    pub chunk_size: usize,
+    /// Buffered io_uring and Tokio reads first try RWF_NOWAIT, so only page-cache misses
+    /// wait on the device
+    pub nowait_precheck: bool,
+    /// Files smaller than this many bytes (at most [`SMALL_FILE_MAX_SIZE`]) are read with
+    /// linked io_uring open/read/close chains when the strategy is `auto` or `uring` and
+    /// io_uring is available; 0 disables the small-file path
+    pub small_file_size: u64,
+    /// The mmap strategy touches every this-many pages after `MADV_WILLNEED`, so they
+    /// are known to be fetched; 1 touches every page, 0 only gives the advice
+    pub mmap_touch_stride: u64,
+    /// Give up on a file that takes longer than this per started [`FILE_TIMEOUT_CHUNK`]
+    pub file_timeout: Option<Duration>,
+    /// Read files stored compressed on btrfs or ZFS by extent (see [`compressed`])
+    pub compressed_extents: bool,
+    /// Bytes between samples in sparse mode; 0 leaves each backend's default
+    pub sparse_interval: u64,
+    /// [`Self::chunk_size`] for particular strategies, applied by [`Self::for_strategy`]
+    pub strategy_chunk_sizes: Vec<StrategySize>,
+    /// [`Self::sparse_interval`] for particular strategies, applied by [`Self::for_strategy`]
+    pub strategy_sparse_intervals: Vec<StrategySize>,
+    /// Strategies for particular size classes, applied by [`Self::for_size`]
+    pub strategy_map: StrategyMap,
+    /// Sorted `(offset, length)` ranges of the file to read instead of all of it, set by
+    /// a caller warming part of a file and narrowed per file by the registry for holes,
+    /// `--skip-cached` and the [`RangeSelector`]
+    pub ranges: Option<Arc<[(u64, u64)]>>,
 }
 
+impl WarmingOptions {
+    /// Read size for a backend whose own default is `default`: [`Self::chunk_size`] if
+    /// set, capped at [`LOW_MEMORY_CHUNK_SIZE`] in low-memory mode
+    pub fn read_size(&self, default: usize) -> usize {
+        let size = if self.chunk_size > 0 { self.chunk_size } else { default };
+        if self.low_memory {
+            size.min(LOW_MEMORY_CHUNK_SIZE)
+        } else {
+            size
+        }
+    }
+
+    /// Whether a file of `file_size` bytes is sampled rather than read in full
+    pub fn is_sparse(&self, file_size: u64) -> bool {
+        self.sparse_large_files > 0 && file_size > self.sparse_large_files
+    }
+
+    /// Bytes between samples in sparse mode for a backend whose own default is `default`
+    pub fn sample_interval(&self, default: u64) -> u64 {
+        if self.sparse_interval > 0 {
+            self.sparse_interval
+        } else {
+            default
+        }
+    }
+
+    /// These options with the chunk size and sparse interval set for `strategy`, if
+    /// `--strategy-chunk-size` or `--strategy-sparse-interval` name it
+    pub fn for_strategy(&self, strategy: Strategy) -> Cow<'_, WarmingOptions> {
+        let lookup = |sizes: &[StrategySize]| sizes.iter().rev().find(|size| size.strategy == strategy).map(|size| size.bytes);
+        let (chunk_size, sparse_interval) = (lookup(&self.strategy_chunk_sizes), lookup(&self.strategy_sparse_intervals));
+        if chunk_size.is_none() && sparse_interval.is_none() {
+            return Cow::Borrowed(self);
+        }
+        let mut options = self.clone();
+        options.chunk_size = chunk_size.map_or(options.chunk_size, |bytes| bytes as usize);
+        options.sparse_interval = sparse_interval.unwrap_or(options.sparse_interval);
+        Cow::Owned(options)
+    }
+
+    /// These options for a file of `file_size` bytes; see [`Self::for_class`]
+    pub fn for_size(&self, file_size: u64) -> Cow<'_, WarmingOptions> {
+        if self.strategy_map.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        self.for_class(SizeClass::of(file_size))
+    }
+
+    /// These options for files of `class`, if `--strategy-map` names it: its strategy,
+    /// without direct I/O if that strategy reads through the page cache, and for
+    /// `_sparse` entries sampling every file
+    pub fn for_class(&self, class: SizeClass) -> Cow<'_, WarmingOptions> {
+        let Some(mapped) = self.strategy_map.get(class) else {
+            return Cow::Borrowed(self);
+        };
+        let mut options = self.clone();
+        options.strategy = mapped.strategy;
+        if mapped.strategy != Strategy::Auto && !Strategy::default_chain(true).contains(&mapped.strategy) {
+            options.use_direct_io = false;
+        }
+        if mapped.sparse {
+            // Every file of more than a byte
+            options.sparse_large_files = 1;
+        }
+        Cow::Owned(options)
+    }
+
+    /// The ranges of a `file_size`-byte file a backend reads: [`Self::ranges`], or the
+    /// whole file. With `align`, widened to whole [`buffers::ALIGNMENT`] blocks, as
+    /// direct I/O needs.
+    pub fn ranges_to_read(&self, file_size: u64, align: bool) -> Vec<(u64, u64)> {
+        let ranges = match &self.ranges {
+            Some(ranges) => ranges.to_vec(),
+            None => vec![(0, file_size)],
+        };
+        if !align {
+            return ranges;
+        }
+        let block = buffers::ALIGNMENT as u64;
+        let mut aligned: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (offset, length) in ranges {
+            let (start, end) = (offset / block * block, (offset + length).next_multiple_of(block));
+            match aligned.last_mut() {
+                // Neighbouring ranges that share a block read it once
+                Some((last, last_length)) if start <= *last + *last_length => *last_length = end.max(*last + *last_length) - *last,
+                _ => aligned.push((start, end - start)),
+            }
+        }
+        aligned
+    }
+
+    /// The `(offset, length)` reads a backend makes of a `file_size`-byte file: up to
+    /// `read_size` bytes at a time across [`Self::ranges_to_read`], or with a `stride`,
+    /// one `read_size` sample at each multiple of it. A range between two samples has
+    /// its start sampled instead, so none is skipped.
+    pub fn reads(&self, file_size: u64, read_size: u64, stride: Option<u64>, align: bool) -> impl Iterator<Item = (u64, u64)> + Send + 'static {
+        reads_in(self.ranges_to_read(file_size, align), read_size, stride)
+    }
+
+    /// How long a file of `file_size` bytes may take under [`Self::file_timeout`]
+    pub fn timeout_for(&self, file_size: u64) -> Option<Duration> {
+        let chunks = file_size.div_ceil(FILE_TIMEOUT_CHUNK).max(1);
+        self.file_timeout.map(|timeout| timeout.saturating_mul(chunks.min(u32::MAX as u64) as u32))
+    }
+}
+
+/// `STRATEGY=BYTES` from `--strategy-chunk-size` and `--strategy-sparse-interval`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrategySize {
+    pub strategy: Strategy,
+    /// A non-zero multiple of 4096, so direct I/O reads stay aligned
+    pub bytes: u64,
+}
+
+impl FromStr for StrategySize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (strategy, bytes) = s.split_once('=').ok_or_else(|| format!("invalid '{}' (expected STRATEGY=BYTES, e.g. uring=262144)", s))?;
+        let strategy = strategy.parse::<Strategy>()?;
+        if strategy == Strategy::Auto {
+            return Err(format!("invalid '{}': name the strategy itself, not auto", s));
+        }
+        let bytes = bytes
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|&bytes| bytes > 0 && bytes % 4096 == 0)
+            .ok_or_else(|| format!("invalid '{}' (the size must be a non-zero multiple of 4096)", s))?;
+        Ok(StrategySize { strategy, bytes })
+    }
+}
+
+/// Narrows a file down to the byte ranges worth reading, e.g. only blocks an EBS
+/// snapshot actually holds data for. `Ok(None)` means "read the whole file". The ranges
+/// reach the strategy chain as [`WarmingOptions::ranges`].
+pub trait RangeSelector: Send + Sync + std::fmt::Debug {
+    fn select(&self, path: &Path, file_size: u64) -> Result<Option<Vec<(u64, u64)>>, std::io::Error>;
+}
+
+/// Files at or below this size are read whole without looking for holes; small files
+/// are rarely sparse and the extra open/stat would cost more than it saves
+pub const HOLE_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Default bytes between samples in sparse mode for the backends that read a 4 KiB
+/// block per sample
+pub const SPARSE_INTERVAL: u64 = 64 * 1024;
+
+/// Largest file size the small-file path handles: one block, read by a single op
+pub const SMALL_FILE_MAX_SIZE: u64 = 4096;
+
+/// Largest per-read buffer (or readahead request) used in low-memory mode
+pub const LOW_MEMORY_CHUNK_SIZE: usize = 256 * 1024;
+
+/// `--file-timeout` applies to each started chunk of this size, so huge files get time
+/// in proportion to their size rather than being cut off for it
+pub const FILE_TIMEOUT_CHUNK: u64 = 1024 * 1024 * 1024;
+
 /// Result of a warming operation
 #[derive(Debug)]
 pub struct WarmingResult {
     pub method: &'static str,
     pub success: bool,
     pub duration: std::time::Duration,
+    /// Bytes actually read (or synchronously requested, for readahead). Zero for
+    /// purely advisory strategies such as fadvise/madvise.
+    pub bytes_read: u64,
+    pub coverage: Coverage,
 }
 
-/// Main warming function that selects the best strategy
+/// How much of a file a warm actually hydrated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coverage {
+    /// Every block holding data was read (holes, and pages already in the page cache
+    /// under `--skip-cached`, need no read)
+    Full,
+    /// Only sampled blocks were read (sparse mode for large files)
+    Sampled,
+    /// Reading stopped early after an error
+    Partial,
+    /// The kernel was only given advice; nothing was read
+    Advisory,
+}
+
+impl Coverage {
+    /// The logical bytes of a `file_size`-byte file a warm with this coverage vouches
+    /// for, having read `bytes_read`: the whole file for a full warm (holes and cached
+    /// pages included), what was read for sampled and partial ones, nothing for advice
+    pub fn bytes_covered(self, file_size: u64, bytes_read: u64) -> u64 {
+        match self {
+            Coverage::Full => file_size,
+            Coverage::Sampled | Coverage::Partial => bytes_read.min(file_size),
+            Coverage::Advisory => 0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Coverage::Full => "full",
+            Coverage::Sampled => "sampled",
+            Coverage::Partial => "partial",
+            Coverage::Advisory => "advisory",
+        }
+    }
+}
+
+impl std::fmt::Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A warming implementation that can be registered with a [`StrategyRegistry`]
+pub trait WarmingBackend: Send + Sync {
+    fn strategy(&self) -> Strategy;
+
+    /// Whether the backend can run on this host at all. Called once per registry.
+    fn probe(&self) -> bool;
+
+    /// Why [`WarmingBackend::probe`] failed, for the startup banner and debug bundles
+    fn unavailable_reason(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the backend can serve the requested I/O mode
+    fn supports(&self, use_direct_io: bool) -> bool;
+
+    /// True for strategies that only issue advice and never read data themselves
+    fn is_advisory(&self) -> bool {
+        false
+    }
+
+    /// Warm the file, reading only [`WarmingOptions::ranges`] of it when they're set
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>>;
+}
+
+struct UringBackend;
+struct LibaioBackend;
+struct FadviseBackend;
+struct ReadaheadBackend;
+struct MmapBackend;
+struct SendfileBackend;
+struct TokioBackend;
+
+impl WarmingBackend for UringBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Uring
+    }
+
+    fn probe(&self) -> bool {
+        probe::io_uring().is_available()
+    }
+
+    fn unavailable_reason(&self) -> Option<String> {
+        probe::io_uring().reason().map(str::to_string)
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { io_uring::warm_file(path, file_size, options).await.map_err(WarmingError::from) })
+    }
+}
+
+impl WarmingBackend for LibaioBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Libaio
+    }
+
+    fn probe(&self) -> bool {
+        probe::libaio().is_available()
+    }
+
+    fn unavailable_reason(&self) -> Option<String> {
+        probe::libaio().reason().map(str::to_string)
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { libaio::warm_file(path, file_size, options).await.map_err(WarmingError::from) })
+    }
+}
+
+impl WarmingBackend for FadviseBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Fadvise
+    }
+
+    fn probe(&self) -> bool {
+        cfg!(any(target_os = "linux", target_os = "macos"))
+    }
+
+    // Advice goes through the page cache, which is exactly what direct I/O is avoiding
+    fn supports(&self, use_direct_io: bool) -> bool {
+        !use_direct_io
+    }
+
+    fn is_advisory(&self) -> bool {
+        true
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            let ranges = options.ranges_to_read(file_size, true);
+            fallback::warm_ranges_with_os_hints(path, file_size, &ranges, options.drop_caches.per_file()).await.map_err(WarmingError::from)
+        })
+    }
+}
+
+impl WarmingBackend for ReadaheadBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Readahead
+    }
+
+    fn probe(&self) -> bool {
+        cfg!(target_os = "linux")
+    }
+
+    fn supports(&self, use_direct_io: bool) -> bool {
+        !use_direct_io
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { readahead::warm_file(path, file_size, options).await.map_err(WarmingError::from) })
+    }
+}
+
+impl WarmingBackend for MmapBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Mmap
+    }
+
+    fn probe(&self) -> bool {
+        cfg!(target_os = "linux")
+    }
+
+    // Mapped pages are page-cache pages
+    fn supports(&self, use_direct_io: bool) -> bool {
+        !use_direct_io
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { mmap::warm_file(path, file_size, options).await.map_err(WarmingError::from) })
+    }
+}
+
+impl WarmingBackend for SendfileBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Sendfile
+    }
+
+    fn probe(&self) -> bool {
+        cfg!(target_os = "linux")
+    }
+
+    // The kernel reads through the page cache to splice the data out
+    fn supports(&self, use_direct_io: bool) -> bool {
+        !use_direct_io
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { sendfile::warm_file(path, file_size, options).await.map_err(WarmingError::from) })
+    }
+}
+
+impl WarmingBackend for TokioBackend {
+    fn strategy(&self) -> Strategy {
+        Strategy::Tokio
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(
+        &'a self,
+        path: &'a Path,
+        file_size: u64,
+        options: &'a WarmingOptions,
+    ) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { tokio_async::warm_file(path, file_size, options).await.map_err(WarmingError::from) })
+    }
+}
+
+/// Availability of one registered backend, as found by probing at startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub strategy: Strategy,
+    pub available: bool,
+    /// Why it's unavailable, if the backend can say
+    pub reason: Option<String>,
+}
+
+/// Set of warming backends keyed by strategy. Backends are probed once, on first
+/// use, and the per-file chain only ever visits backends that passed their probe.
+pub struct StrategyRegistry {
+    backends: Vec<Arc<dyn WarmingBackend>>,
+    probes: OnceLock<Vec<ProbeResult>>,
+}
+
+impl StrategyRegistry {
+    /// An empty registry; see [`StrategyRegistry::builtin`] for the standard backends
+    pub fn new() -> Self {
+        Self { backends: Vec::new(), probes: OnceLock::new() }
+    }
+
+    /// Registry containing every backend shipped with the warmer
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(Arc::new(UringBackend))
+            .register(Arc::new(LibaioBackend))
+            .register(Arc::new(FadviseBackend))
+            .register(Arc::new(ReadaheadBackend))
+            .register(Arc::new(MmapBackend))
+            .register(Arc::new(SendfileBackend))
+            .register(Arc::new(TokioBackend));
+        registry
+    }
+
+    /// Shared builtin registry used by [`warm_file`]
+    pub fn global() -> &'static StrategyRegistry {
+        static GLOBAL: OnceLock<StrategyRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(StrategyRegistry::builtin)
+    }
+
+    /// Add a backend, replacing any existing backend for the same strategy
+    pub fn register(&mut self, backend: Arc<dyn WarmingBackend>) -> &mut Self {
+        self.backends.retain(|existing| existing.strategy() != backend.strategy());
+        self.backends.push(backend);
+        self.probes = OnceLock::new();
+        self
+    }
+
+    pub fn backend(&self, strategy: Strategy) -> Option<&Arc<dyn WarmingBackend>> {
+        self.backends.iter().find(|backend| backend.strategy() == strategy)
+    }
+
+    /// Probe every registered backend (cached after the first call)
+    pub fn probe(&self) -> &[ProbeResult] {
+        self.probes.get_or_init(|| {
+            self.backends
+                .iter()
+                .map(|backend| {
+                    let available = backend.probe();
+                    let reason = if available { None } else { backend.unavailable_reason() };
+                    debug!("Probed {} strategy: available={}{}", backend.strategy(), available, reason.as_ref().map(|reason| format!(" ({})", reason)).unwrap_or_default());
+                    ProbeResult { strategy: backend.strategy(), available, reason }
+                })
+                .collect()
+        })
+    }
+
+    pub fn is_available(&self, strategy: Strategy) -> bool {
+        self.probe().iter().any(|p| p.strategy == strategy && p.available)
+    }
+
+    /// The ordered list of strategies each file will be offered to, after dropping
+    /// anything unavailable on this host or incompatible with the I/O mode.
+    pub fn plan(&self, options: &WarmingOptions) -> Vec<Strategy> {
+        let mut candidates: Vec<Strategy> = Vec::new();
+        if options.strategy != Strategy::Auto {
+            candidates.push(options.strategy);
+        }
+        match (&options.fallback, options.strategy) {
+            (_, Strategy::Auto) | (FallbackPolicy::Auto, _) => {
+                candidates.extend_from_slice(Strategy::default_chain(options.use_direct_io))
+            }
+            (FallbackPolicy::None, _) => {}
+            (FallbackPolicy::Chain(chain), _) => candidates.extend_from_slice(chain),
+        }
+
+        let mut plan = Vec::with_capacity(candidates.len());
+        for strategy in candidates {
+            if plan.contains(&strategy) || !self.is_available(strategy) {
+                continue;
+            }
+            if self.backend(strategy).is_some_and(|b| b.supports(options.use_direct_io)) {
+                plan.push(strategy);
+            }
+        }
+        plan
+    }
+
+    /// Whether the size-adaptive policy sends a file of `file_size` bytes down the
+    /// small-file path: it must hold data, fit in a block and be under
+    /// [`WarmingOptions::small_file_size`], with io_uring usable and not overridden by an
+    /// explicit choice of another strategy
+    pub fn small_file(&self, file_size: u64, options: &WarmingOptions) -> bool {
+        file_size > 0
+            && file_size < options.small_file_size.min(SMALL_FILE_MAX_SIZE + 1)
+            && matches!(options.strategy, Strategy::Auto | Strategy::Uring)
+            && self.is_available(Strategy::Uring)
+    }
+
+    /// Warm a file with the first strategy in the plan that can handle it, within
+    /// [`WarmingOptions::timeout_for`] the file
+    pub async fn warm(
+        &self,
+        path: &Path,
+        file_size: u64,
+        options: &WarmingOptions,
+    ) -> Result<WarmingResult, WarmingError> {
+        let options = &*options.for_size(file_size);
+        let Some(limit) = options.timeout_for(file_size) else {
+            return self.warm_untimed(path, file_size, options).await;
+        };
+        // Dropping the warm cancels its io_uring reads; a read already blocking a thread of
+        // the blocking pool finishes there on its own, but no longer holds a queue slot
+        match tokio::time::timeout(limit, self.warm_untimed(path, file_size, options)).await {
+            Ok(result) => result,
+            Err(_) => Err(WarmingError::Timeout(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("not warmed within {:?} (--file-timeout)", limit),
+            ))),
+        }
+    }
+
+    async fn warm_untimed(
+        &self,
+        path: &Path,
+        file_size: u64,
+        options: &WarmingOptions,
+    ) -> Result<WarmingResult, WarmingError> {
+        // Extent-by-extent reads cover the whole file, so a caller's ranges skip them
+        if options.compressed_extents && file_size > 0 && options.ranges.is_none() {
+            if let Some(result) = compressed::warm(path, file_size, options).await? {
+                return Ok(result);
+            }
+        }
+        let skip_holes = !options.read_holes && file_size > HOLE_CHECK_MIN_SIZE;
+        let selected;
+        let options = if options.skip_cached || skip_holes || options.range_selector.is_some() {
+            let start = std::time::Instant::now();
+            match selection(path, file_size, options).await? {
+                Selection::WholeFile => options,
+                Selection::Nothing(method) => {
+                    debug!("Nothing to read for {} ({})", anonymize::display(path), method);
+                    return Ok(WarmingResult { method, success: true, duration: start.elapsed(), bytes_read: 0, coverage: Coverage::Full });
+                }
+                Selection::Ranges(ranges) => {
+                    // Ranges the caller asked for narrow the selection rather than being replaced by it
+                    let ranges = match &options.ranges {
+                        Some(requested) => intersect_ranges(requested, &ranges),
+                        None => ranges,
+                    };
+                    if ranges.is_empty() {
+                        return Ok(WarmingResult { method: "no_selected_ranges", success: true, duration: start.elapsed(), bytes_read: 0, coverage: Coverage::Full });
+                    }
+                    debug!("{}: warming {} selected ranges of {} bytes", anonymize::display(path), ranges.len(), file_size);
+                    let mut with_ranges = options.clone();
+                    with_ranges.ranges = Some(ranges.into());
+                    selected = with_ranges;
+                    &selected
+                }
+            }
+        } else {
+            options
+        };
+
+        let plan = self.plan(options);
+        if self.small_file(file_size, options) {
+            if let Some(result) = io_uring::warm_small_file(path, options).await? {
+                return Ok(result);
+            }
+        }
+        if options.intra_file_parallelism > 1 {
+            let use_ring = plan.first() == Some(&Strategy::Uring);
+            let options = plan.first().map_or(Cow::Borrowed(options), |&strategy| options.for_strategy(strategy));
+            if let Some(result) = chunked::warm(path, file_size, &options, use_ring).await? {
+                return Ok(result);
+            }
+        }
+
+        let mut advisory_failure = None;
+
+        for strategy in plan {
+            let Some(backend) = self.backend(strategy) else { continue };
+            debug!("Attempting {} strategy for {}", strategy, anonymize::display(path));
+            let options = options.for_strategy(strategy);
+            match backend.warm(path, file_size, &options).instrument(telemetry::strategy_span(strategy.name())).await {
+                Ok(result) if result.success => return Ok(result),
+                Ok(result) => {
+                    warnings::report(Category::Fallback, format_args!("{} strategy did not succeed for {}, trying next", strategy, anonymize::display(path)));
+                    advisory_failure = Some(result);
+                }
+                Err(WarmingError::Unsupported(e)) => {
+                    debug!("{} not available: {}", strategy, e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        advisory_failure.map(Ok).unwrap_or_else(|| {
+            Err(WarmingError::Unsupported(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("no available warming strategy for {} (strategy={}, fallback={:?})", anonymize::display(path), options.strategy, options.fallback),
+            )))
+        })
+    }
+}
+
+impl Default for StrategyRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl std::fmt::Debug for StrategyRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StrategyRegistry")
+            .field("backends", &self.backends.iter().map(|b| b.strategy()).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Main warming function that selects the best strategy from the builtin registry
 pub async fn warm_file(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
     options: &WarmingOptions,
-) -> Result<WarmingResult, std::io::Error> {
-    let _start = std::time::Instant::now();
-    
-    // Strategy selection priority:
-    // 1. io_uring (if available and requested)
-    // 2. libaio (if available and requested)
-    // 3. OS hints (fadvise/madvise)
-    // 4. Tokio fallback
-    
-    #[cfg(target_os = "linux")]
-    if options.use_io_uring {
-        debug!("Attempting io_uring strategy for {}", path.display());
-        match io_uring::warm_file(path, file_size, options).await {
-            Ok(result) => {
-                return Ok(result);
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
-                debug!("io_uring not available: {}", e);
+) -> Result<WarmingResult, WarmingError> {
+    StrategyRegistry::global().warm(path, file_size, options).await
+}
+
+/// The ranges of a file worth reading for hole skipping, `--skip-cached` and range
+/// selectors, found on a blocking thread
+async fn selection(path: &Path, file_size: u64, options: &WarmingOptions) -> Result<Selection, std::io::Error> {
+    let (skip_cached, skip_holes) = (options.skip_cached, !options.read_holes && file_size > HOLE_CHECK_MIN_SIZE);
+    let selector = options.range_selector.clone();
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || select_ranges(&path, file_size, skip_cached, skip_holes, selector.as_deref()))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+enum Selection {
+    WholeFile,
+    Nothing(&'static str),
+    Ranges(Vec<(u64, u64)>),
+}
+
+fn select_ranges(
+    path: &Path,
+    file_size: u64,
+    skip_cached: bool,
+    skip_holes: bool,
+    selector: Option<&dyn RangeSelector>,
+) -> Result<Selection, std::io::Error> {
+    let mut ranges: Option<Vec<(u64, u64)>> = None;
+
+    if skip_holes && file_size > 0 {
+        if let Some(data) = crate::fiemap::data_ranges(path, file_size)? {
+            if data.is_empty() {
+                return Ok(Selection::Nothing("all_holes"));
             }
-            Err(e) => return Err(e),
+            ranges = Some(data);
         }
     }
-    
-    #[cfg(target_os = "linux")]
-    if options.use_libaio {
-        debug!("Attempting libaio strategy for {}", path.display());
-        match libaio::warm_file(path, file_size, options).await {
-            Ok(result) => {
-                return Ok(result);
+
+    if skip_cached {
+        match residency::scan(path, file_size) {
+            Ok(residency) if residency.is_fully_resident() => return Ok(Selection::Nothing("already_resident")),
+            Ok(residency) if !residency.is_fully_cold() => {
+                ranges = Some(match ranges {
+                    Some(data) => intersect_ranges(&data, &residency.cold_ranges),
+                    None => residency.cold_ranges,
+                })
             }
-            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
-                debug!("libaio not available: {}", e);
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound || e.kind() == std::io::ErrorKind::PermissionDenied => return Err(e),
+            Err(e) => {
+                warnings::report(Category::Backend, format_args!("Residency check failed for {}, warming whole file: {}", anonymize::display(path), e));
             }
-            Err(e) => return Err(e),
         }
     }
-    
-    // Try OS hints first (most efficient)
-    debug!("Trying OS hints (fadvise/madvise) for {}", path.display());
-    if let Ok(result) = fallback::warm_with_os_hints(path, file_size).await {
-        if result.success {
-            return Ok(result);
+
+    if let Some(selector) = selector {
+        if let Some(selected) = selector.select(path, file_size)? {
+            ranges = Some(match ranges {
+                Some(cold) => intersect_ranges(&cold, &selected),
+                None => selected,
+            });
+        }
+    }
+
+    Ok(match ranges {
+        None => Selection::WholeFile,
+        Some(ranges) if ranges.is_empty() => Selection::Nothing("no_selected_ranges"),
+        Some(ranges) => Selection::Ranges(ranges),
+    })
+}
+
+/// [`WarmingOptions::reads`] across the given `ranges`
+fn reads_in(ranges: Vec<(u64, u64)>, read_size: u64, stride: Option<u64>) -> impl Iterator<Item = (u64, u64)> + Send + 'static {
+    ranges.into_iter().flat_map(move |(offset, length)| {
+        let end = offset + length;
+        let first = match stride {
+            Some(stride) => Some(offset.next_multiple_of(stride)).filter(|&sample| sample < end).unwrap_or(offset),
+            None => offset,
+        };
+        (first..end).step_by(stride.unwrap_or(read_size) as usize).map(move |pos| (pos, read_size.min(end - pos)))
+    })
+}
+
+/// Intersection of two sorted, non-overlapping `(offset, length)` range lists
+fn intersect_ranges(a: &[(u64, u64)], b: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (a_start, a_end) = (a[i].0, a[i].0 + a[i].1);
+        let (b_start, b_end) = (b[j].0, b[j].0 + b[j].1);
+        let (start, end) = (a_start.max(b_start), a_end.min(b_end));
+        if start < end {
+            result.push((start, end - start));
+        }
+        if a_end < b_end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    result
+}
+
+/// Read `[offset, offset + length)` with positional reads, then (if `drop_pages`) drop
+/// just that range from the page cache on Linux. Returns the bytes read (short at EOF).
+fn read_range_blocking(
+    file: &std::fs::File,
+    offset: u64,
+    length: u64,
+    buffer: &mut [u8],
+    drop_pages: bool,
+) -> Result<u64, std::io::Error> {
+    use std::os::unix::fs::FileExt;
+
+    let end = offset.saturating_add(length);
+    let mut pos = offset;
+    while pos < end {
+        let want = std::cmp::min(buffer.len() as u64, end - pos) as usize;
+        let n = file.read_at(&mut buffer[..want], pos)?;
+        if n == 0 {
+            break;
         }
+        pos += n as u64;
     }
-    
-    // Fallback to Tokio async I/O
-    debug!("Using Tokio async I/O for {}", path.display());
-    tokio_async::warm_file(path, file_size, options).await
-} 
\ No newline at end of file
+
+    #[cfg(target_os = "linux")]
+    if drop_pages {
+        use std::os::unix::prelude::AsRawFd;
+        use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+        let _ = posix_fadvise(file.as_raw_fd(), offset as i64, (pos - offset) as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = drop_pages;
+
+    Ok(pos - offset)
+}
+
+/// Warm a single byte range of a file with plain positional reads, honouring the
+/// drop-caches policy on Linux. `--verify-rewarm` uses it to put a region back in the
+/// page cache before timing a read of it; other callers set [`WarmingOptions::ranges`]
+/// and go through [`warm_file`] so the selected strategy applies.
+pub async fn warm_range(
+    path: &Path,
+    offset: u64,
+    length: u64,
+    options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    let path = path.to_path_buf();
+    let chunk_size = options.read_size(1024 * 1024);
+    let drop_pages = options.drop_caches.per_file();
+    tokio::task::spawn_blocking(move || {
+        let start = std::time::Instant::now();
+        let file = std::fs::File::open(&path)?;
+        let mut buffer = vec![0u8; chunk_size];
+        let bytes_read = read_range_blocking(&file, offset, length, &mut buffer, drop_pages)?;
+
+        debug!("Range warm of {} [{}..{}) took {:?}", anonymize::display(&path), offset, offset + bytes_read, start.elapsed());
+        Ok(WarmingResult {
+            method: "range_pread",
+            success: true,
+            duration: start.elapsed(),
+            bytes_read,
+            coverage: Coverage::Full,
+        })
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
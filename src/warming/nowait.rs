@@ -0,0 +1,104 @@
+//! Page-cache-first reads (`--nowait-precheck`).
+//!
+//! A buffered `preadv2(2)` with `RWF_NOWAIT` returns what the page cache already holds
+//! and stops at the first page that would have to come from the device, failing with
+//! EAGAIN if that's the first one. Tried before each real read, it costs a copy for data
+//! that's cached and one syscall for data that isn't, so re-warming a mostly warm tree
+//! only waits on the volume for the misses. The io_uring backend submits the same flag
+//! through its ring; this is the synchronous version for the Tokio backend.
+
+use std::path::Path;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::anonymize;
+use crate::warming::{Coverage, WarmingResult};
+
+/// Read size of page-cache-first Tokio reads
+pub const READ_SIZE: usize = 65536;
+
+/// Copy what the page cache holds of `buf.len()` bytes at `offset` into `buf`, without
+/// waiting on the device. Returns how many bytes that was (0 at the end of the file or
+/// when the first page isn't cached), or `None` if the filesystem doesn't support
+/// `RWF_NOWAIT`.
+#[cfg(target_os = "linux")]
+pub fn read_cached(file: &std::fs::File, buf: &mut [u8], offset: u64) -> Result<Option<usize>, std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+    loop {
+        let n = unsafe { libc::preadv2(file.as_raw_fd(), &iov, 1, offset as libc::off_t, libc::RWF_NOWAIT) };
+        if n >= 0 {
+            return Ok(Some(n as usize));
+        }
+        let e = std::io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EAGAIN) => return Ok(Some(0)),
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) | Some(libc::EINVAL) => return Ok(None),
+            Some(libc::EINTR) => continue,
+            _ => return Err(e),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_cached(_file: &std::fs::File, _buf: &mut [u8], _offset: u64) -> Result<Option<usize>, std::io::Error> {
+    Ok(None)
+}
+
+/// Read all of `path` (or only its `(offset, length)` `ranges`) in `read_size` chunks,
+/// taking each from the page cache where it can and reading only the rest. Blocking; run
+/// it on the blocking pool.
+pub fn warm_file_blocking(path: &Path, ranges: Option<&[(u64, u64)]>, read_size: usize, drop_pages: bool) -> Result<WarmingResult, std::io::Error> {
+    use std::os::unix::fs::FileExt;
+
+    let start = Instant::now();
+    let file = std::fs::File::open(path)?;
+    let mut buf = vec![0u8; read_size];
+    let (mut bytes_read, mut cached) = (0u64, 0u64);
+    let mut nowait = true;
+    // Up to the end of the file, wherever that is by now
+    for &(from, length) in ranges.unwrap_or(&[(0, u64::MAX)]) {
+        let (mut offset, end) = (from, from.saturating_add(length));
+        while offset < end {
+            let want = (end - offset).min(buf.len() as u64) as usize;
+            let hit = match nowait.then(|| read_cached(&file, &mut buf[..want], offset)).transpose()? {
+                Some(Some(n)) => n,
+                Some(None) => {
+                    debug!("{} doesn't support RWF_NOWAIT; reading the rest of it normally", anonymize::display(path));
+                    nowait = false;
+                    0
+                }
+                None => 0,
+            };
+            cached += hit as u64;
+            if hit == want {
+                offset += hit as u64;
+                continue;
+            }
+            let n = file.read_at(&mut buf[hit..want], offset + hit as u64)?;
+            offset += (hit + n) as u64;
+            if hit + n == 0 {
+                break;
+            }
+        }
+        bytes_read += offset - from;
+    }
+
+    #[cfg(target_os = "linux")]
+    if drop_pages {
+        use std::os::unix::io::AsRawFd;
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = drop_pages;
+    debug!("Page-cache-first read of {}: {} bytes ({} already cached) in {:?}", anonymize::display(path), bytes_read, cached, start.elapsed());
+    Ok(WarmingResult {
+        method: "tokio_nowait_full",
+        success: true,
+        duration: start.elapsed(),
+        bytes_read,
+        coverage: Coverage::Full,
+    })
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::time::Instant;
+use log::debug;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::prelude::AsRawFd;
+#[cfg(target_os = "linux")]
+use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+
+use crate::anonymize;
+use crate::warming::{Coverage, WarmingResult, WarmingOptions, SPARSE_INTERVAL};
+
+/// Bytes requested per readahead() call for full warming, unless `--chunk-size` says otherwise
+#[cfg(target_os = "linux")]
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Warm file using the readahead(2) syscall, which populates the page cache
+/// (and so triggers EBS block fetches) without copying any data to userspace
+#[cfg(target_os = "linux")]
+pub async fn warm_file(
+    path: &Path,
+    file_size: u64,
+    options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    let path = path.to_path_buf();
+    let sparse = options.is_sparse(file_size);
+    let stride = options.sample_interval(SPARSE_INTERVAL);
+    let drop_pages = options.drop_caches.per_file();
+    let chunk_size = options.read_size(CHUNK_SIZE) as u64;
+    let reads = if sparse { options.reads(file_size, 4096, Some(stride), false) } else { options.reads(file_size, chunk_size, None, false) };
+
+    // readahead() blocks until the requested pages have been read, so keep it off the runtime threads
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let file = std::fs::File::open(&path)?;
+        let fd = file.as_raw_fd();
+
+        let mut bytes_requested = 0u64;
+        let method = if sparse {
+            debug!("Using sparse readahead for large file: {} ({} bytes)", anonymize::display(&path), file_size);
+            let mut samples = 0u64;
+            for (offset, count) in reads {
+                readahead(fd, offset, count)?;
+                bytes_requested += count;
+                samples += 1;
+            }
+            debug!("Sparse readahead completed: {} samples in {:?}", samples, start.elapsed());
+            "readahead_sparse"
+        } else {
+            let mut calls = 0u64;
+            for (offset, count) in reads {
+                readahead(fd, offset, count)?;
+                bytes_requested += count;
+                calls += 1;
+                // Drop each chunk as we go so large files don't balloon the page cache
+                if drop_pages {
+                    let _ = posix_fadvise(fd, offset as i64, count as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED);
+                }
+            }
+            debug!("Full readahead completed: {} bytes in {} calls in {:?}", bytes_requested, calls, start.elapsed());
+            "readahead_full"
+        };
+
+        // Drop pages from cache afterwards (we only wanted EBS warming)
+        if drop_pages {
+            let drop_result = posix_fadvise(fd, 0, file_size as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED);
+            debug!("readahead cache drop result: {:?}", drop_result.is_ok());
+        }
+
+        Ok(WarmingResult {
+            method,
+            success: true,
+            duration: start.elapsed(),
+            bytes_read: bytes_requested,
+            coverage: if method == "readahead_sparse" { Coverage::Sampled } else { Coverage::Full },
+        })
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+#[cfg(target_os = "linux")]
+fn readahead(fd: libc::c_int, offset: u64, count: u64) -> Result<(), std::io::Error> {
+    let result = unsafe { libc::readahead(fd, offset as libc::off64_t, count as libc::size_t) };
+    if result < 0 {
+        let err = std::io::Error::last_os_error();
+        // EINVAL means the file type doesn't support readahead (e.g. some FUSE or special files)
+        if err.raw_os_error() == Some(libc::EINVAL) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, err));
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+// Stub implementation for non-Linux systems
+#[cfg(not(target_os = "linux"))]
+pub async fn warm_file(
+    _path: &Path,
+    _file_size: u64,
+    _options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "readahead only supported on Linux"
+    ))
+}
@@ -0,0 +1,153 @@
+//! Page-cache residency checks for `--skip-cached`.
+//!
+//! Pages already in the page cache were read since boot, so their EBS blocks are
+//! hydrated and don't need warming again. `cachestat(2)` (Linux 6.5+) answers "how much
+//! of this file is cached" in one syscall, which settles the common fully-cold and
+//! fully-warm cases cheaply; anything in between is mapped and scanned with `mincore(2)`
+//! to find the exact non-resident ranges.
+
+use std::path::Path;
+
+/// Pages inspected per mmap window, so huge files never need a huge residency vector
+#[cfg(target_os = "linux")]
+const WINDOW_PAGES: u64 = 256 * 1024;
+
+/// Page-cache residency of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Residency {
+    pub file_size: u64,
+    pub page_size: u64,
+    /// Bytes of the file currently in the page cache
+    pub resident_bytes: u64,
+    /// Non-resident byte ranges as `(offset, length)`, in file order
+    pub cold_ranges: Vec<(u64, u64)>,
+}
+
+impl Residency {
+    fn all_cold(file_size: u64, page_size: u64) -> Self {
+        let cold_ranges = if file_size > 0 { vec![(0, file_size)] } else { Vec::new() };
+        Self { file_size, page_size, resident_bytes: 0, cold_ranges }
+    }
+
+    pub fn is_fully_resident(&self) -> bool {
+        self.cold_ranges.is_empty()
+    }
+
+    pub fn is_fully_cold(&self) -> bool {
+        self.resident_bytes == 0
+    }
+
+    pub fn cold_bytes(&self) -> u64 {
+        self.cold_ranges.iter().map(|(_, len)| len).sum()
+    }
+}
+
+/// Inspect which parts of `path` are already in the page cache
+#[cfg(target_os = "linux")]
+pub fn scan(path: &Path, file_size: u64) -> Result<Residency, std::io::Error> {
+    use std::os::unix::prelude::AsRawFd;
+
+    let page_size = page_size();
+    if file_size == 0 {
+        return Ok(Residency::all_cold(0, page_size));
+    }
+
+    let file = std::fs::File::open(path)?;
+    let fd = file.as_raw_fd();
+
+    // Settle the all-or-nothing cases without mapping the file
+    if let Some(cached_pages) = cachestat(fd, file_size) {
+        let total_pages = file_size.div_ceil(page_size);
+        if cached_pages == 0 {
+            return Ok(Residency::all_cold(file_size, page_size));
+        }
+        if cached_pages >= total_pages {
+            return Ok(Residency { file_size, page_size, resident_bytes: file_size, cold_ranges: Vec::new() });
+        }
+    }
+
+    let mut residency = Residency { file_size, page_size, resident_bytes: 0, cold_ranges: Vec::new() };
+    let window_bytes = WINDOW_PAGES * page_size;
+    let mut vec = Vec::new();
+    let mut window_start = 0u64;
+    while window_start < file_size {
+        let window_len = std::cmp::min(window_bytes, file_size - window_start);
+        let pages = window_len.div_ceil(page_size) as usize;
+        vec.resize(pages, 0u8);
+        mincore_window(fd, window_start, window_len, &mut vec)?;
+
+        for (i, &state) in vec.iter().enumerate() {
+            let page_offset = window_start + i as u64 * page_size;
+            let page_len = std::cmp::min(page_size, file_size - page_offset);
+            if state & 1 != 0 {
+                residency.resident_bytes += page_len;
+            } else {
+                match residency.cold_ranges.last_mut() {
+                    Some((offset, len)) if *offset + *len == page_offset => *len += page_len,
+                    _ => residency.cold_ranges.push((page_offset, page_len)),
+                }
+            }
+        }
+        window_start += window_len;
+    }
+
+    Ok(residency)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn scan(_path: &Path, _file_size: u64) -> Result<Residency, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "page-cache residency checks are only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn page_size() -> u64 {
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 { size as u64 } else { 4096 }
+}
+
+/// Cached page count for the whole file via cachestat(2), or None on kernels without it
+#[cfg(target_os = "linux")]
+fn cachestat(fd: libc::c_int, file_size: u64) -> Option<u64> {
+    // Same number on every architecture using the unified syscall table
+    const SYS_CACHESTAT: libc::c_long = 451;
+
+    #[repr(C)]
+    struct CachestatRange {
+        off: u64,
+        len: u64,
+    }
+    #[repr(C)]
+    #[derive(Default)]
+    struct Cachestat {
+        nr_cache: u64,
+        nr_dirty: u64,
+        nr_writeback: u64,
+        nr_evicted: u64,
+        nr_recently_evicted: u64,
+    }
+
+    let range = CachestatRange { off: 0, len: file_size };
+    let mut stat = Cachestat::default();
+    let result = unsafe { libc::syscall(SYS_CACHESTAT, fd, &range as *const CachestatRange, &mut stat as *mut Cachestat, 0u32) };
+    (result == 0).then_some(stat.nr_cache)
+}
+
+#[cfg(target_os = "linux")]
+fn mincore_window(fd: libc::c_int, offset: u64, len: u64, vec: &mut [u8]) -> Result<(), std::io::Error> {
+    let addr = unsafe {
+        libc::mmap(std::ptr::null_mut(), len as usize, libc::PROT_READ, libc::MAP_SHARED, fd, offset as libc::off_t)
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error());
+    }
+    let result = unsafe { libc::mincore(addr, len as usize, vec.as_mut_ptr()) };
+    let err = std::io::Error::last_os_error();
+    unsafe { libc::munmap(addr, len as usize) };
+    if result != 0 {
+        return Err(err);
+    }
+    Ok(())
+}
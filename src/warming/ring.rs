@@ -0,0 +1,444 @@
+//! A process-wide io_uring shared by every file being warmed.
+//!
+//! Setting up a ring costs several syscalls and mmaps, which dominates when warming
+//! millions of small files. Instead one ring is owned by a dedicated submitter thread:
+//! callers send read requests over a channel, the thread packs everything pending into
+//! the submission queue, submits it with a single io_uring_enter(2), and answers each
+//! request as its completion arrives. Reads for many files complete together.
+//!
+//! Files under a block are cheaper still as one linked chain: open into a registered
+//! (direct) descriptor slot, read the block, optionally drop it from the page cache, and
+//! close, so a single submission carries whole files rather than single reads.
+//!
+//! A caller that stops waiting (its file was cancelled by a shutdown, or another read of
+//! the file failed) cancels its request. One still queued is dropped; one already in the
+//! kernel gets an `IORING_OP_ASYNC_CANCEL`, so a read stuck on a cold block or a hung
+//! device doesn't hold its slot, and keep the process from exiting, for as long as the
+//! device takes. An eventfd read kept in the ring wakes the submitter for cancellations
+//! while it waits for completions.
+
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+
+use io_uring::{opcode, squeue, types, IoUring, Probe};
+use log::debug;
+use tokio::sync::oneshot;
+
+use crate::warming::buffers::{BufferPool, PooledBuffer, ALIGNMENT};
+use crate::warnings::{self, Category};
+
+/// Submission queue size, and the maximum number of reads in flight across all files
+const RING_ENTRIES: u32 = 256;
+
+/// Operations in a small-file chain (open, read, fadvise, close); `user_data` carries
+/// the slot and the operation's index in its chain
+const CHAIN_OPS: u64 = 4;
+
+/// Longest the submitter waits for buffer memory before looking at new messages again
+const STARVED_POLL: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// `user_data` of the submitter's own entries, above any slot's
+const WAKE_USER_DATA: u64 = u64::MAX;
+const CANCEL_USER_DATA: u64 = u64::MAX - 1;
+
+enum Message {
+    Submit(u64, Request),
+    /// The caller of this request stopped waiting for it
+    Cancel(u64),
+}
+
+enum Request {
+    Read(ReadRequest),
+    SmallFile(SmallFileRequest),
+}
+
+struct ReadRequest {
+    /// Held until the completion arrives so the fd can't be closed (and reused) mid-read
+    file: Arc<File>,
+    offset: u64,
+    len: usize,
+    /// `preadv2(2)` flags, e.g. `RWF_NOWAIT`
+    rw_flags: i32,
+    reply: oneshot::Sender<std::io::Result<usize>>,
+}
+
+struct SmallFileRequest {
+    path: CString,
+    direct: bool,
+    /// Drop the block from the page cache before closing
+    drop_pages: bool,
+    reply: oneshot::Sender<std::io::Result<usize>>,
+}
+
+impl Request {
+    fn len(&self) -> usize {
+        match self {
+            Request::Read(read) => read.len,
+            Request::SmallFile(_) => ALIGNMENT,
+        }
+    }
+
+    fn fail(self, error: std::io::Error) {
+        let _ = match self {
+            Request::Read(read) => read.reply.send(Err(error)),
+            Request::SmallFile(small) => small.reply.send(Err(error)),
+        };
+    }
+}
+
+struct InFlight {
+    id: u64,
+    /// An async cancel has been submitted for it
+    cancelled: bool,
+    request: Request,
+    _buffer: PooledBuffer,
+    /// Completions still to come for this slot
+    pending: usize,
+    /// Outcome of the open (if it failed) or the read; later ops in a chain can't change it
+    outcome: Option<std::io::Result<usize>>,
+}
+
+/// Handle to the shared ring's submitter thread
+pub struct SharedRing {
+    requests: mpsc::Sender<Message>,
+    /// Written to wake the submitter while it waits for completions
+    wake: File,
+    next_id: AtomicU64,
+    /// Requests queued or in the kernel, as of the submitter's last pass
+    outstanding: Arc<AtomicUsize>,
+    small_files: bool,
+}
+
+/// Cancels its request if dropped before the reply arrives
+struct CancelOnDrop<'a> {
+    ring: &'a SharedRing,
+    id: u64,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if self.armed && self.ring.requests.send(Message::Cancel(self.id)).is_ok() {
+            self.ring.wake();
+        }
+    }
+}
+
+impl SharedRing {
+    /// The process-wide ring, created on first use. Fails with `Unsupported` if the
+    /// kernel (or a seccomp profile) refuses io_uring.
+    pub fn global() -> std::io::Result<&'static SharedRing> {
+        static RING: OnceLock<Result<SharedRing, String>> = OnceLock::new();
+        RING.get_or_init(|| SharedRing::start().map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Unsupported, format!("io_uring setup failed: {}", e)))
+    }
+
+    fn start() -> std::io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let small_files = supports_linked_open(&ring);
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let wake = unsafe { File::from_raw_fd(fd) };
+        let waker = wake.try_clone()?;
+        let outstanding = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::channel();
+        let counter = Arc::clone(&outstanding);
+        std::thread::Builder::new()
+            .name("io-uring-submitter".to_string())
+            .spawn(move || run_submitter(ring, rx, waker, counter))?;
+        debug!("Started shared io_uring with {} entries (linked small-file reads: {})", RING_ENTRIES, small_files);
+        Ok(Self { requests: tx, wake, next_id: AtomicU64::new(0), outstanding, small_files })
+    }
+
+    /// Requests queued for or running in the kernel, as of the submitter's last pass
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    fn wake(&self) {
+        let _ = (&self.wake).write(&1u64.to_ne_bytes());
+    }
+
+    /// Whether [`SharedRing::read_small_file`] can be used: the kernel must support
+    /// opening into registered descriptor slots and closing them (Linux 5.19+)
+    pub fn supports_small_files(&self) -> bool {
+        self.small_files
+    }
+
+    /// Read `len` bytes at `offset` through the shared ring, returning the bytes read.
+    /// The data itself is discarded; only the device read matters for warming.
+    pub async fn read(&self, file: Arc<File>, offset: u64, len: usize) -> std::io::Result<usize> {
+        self.read_with_flags(file, offset, len, 0).await
+    }
+
+    /// [`SharedRing::read`] with `preadv2(2)` flags. With `RWF_NOWAIT` a buffered read
+    /// only returns what's already in the page cache, failing with `EAGAIN` if that's
+    /// nothing, instead of waiting for the device.
+    pub async fn read_with_flags(&self, file: Arc<File>, offset: u64, len: usize, rw_flags: i32) -> std::io::Result<usize> {
+        let (reply, done) = oneshot::channel();
+        self.submit(Request::Read(ReadRequest { file, offset, len, rw_flags, reply }), done).await
+    }
+
+    /// Open, read the first block of, and close a file under [`ALIGNMENT`] bytes as one
+    /// linked chain, returning the bytes read. With `drop_pages` the block is dropped
+    /// from the page cache again before the close.
+    pub async fn read_small_file(&self, path: &Path, direct: bool, drop_pages: bool) -> std::io::Result<usize> {
+        if !self.small_files {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "io_uring can't open files into registered slots here"));
+        }
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+        let (reply, done) = oneshot::channel();
+        self.submit(Request::SmallFile(SmallFileRequest { path, direct, drop_pages, reply }), done).await
+    }
+
+    async fn submit(&self, request: Request, done: oneshot::Receiver<std::io::Result<usize>>) -> std::io::Result<usize> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.requests
+            .send(Message::Submit(id, request))
+            .map_err(|_| std::io::Error::other("io_uring submitter thread has stopped"))?;
+        let mut guard = CancelOnDrop { ring: self, id, armed: true };
+        let result = done.await;
+        guard.armed = false;
+        result.map_err(|_| std::io::Error::other("io_uring submitter thread has stopped"))?
+    }
+}
+
+/// Probe for the opcodes a small-file chain needs and register one sparse descriptor
+/// slot per request slot
+fn supports_linked_open(ring: &IoUring) -> bool {
+    let mut probe = Probe::new();
+    if let Err(e) = ring.submitter().register_probe(&mut probe) {
+        debug!("io_uring opcode probe failed: {}", e);
+        return false;
+    }
+    let opcodes = [opcode::OpenAt::CODE, opcode::Read::CODE, opcode::Fadvise::CODE, opcode::Close::CODE];
+    if !opcodes.iter().all(|&code| probe.is_supported(code)) {
+        debug!("io_uring lacks openat/fadvise/close support");
+        return false;
+    }
+    match ring.submitter().register_files_sparse(RING_ENTRIES) {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("Registering sparse io_uring file slots failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Submission entries for a request in `slot`. A small file's chain is hard-linked, so
+/// a failed (or short) read still runs the close that frees the descriptor slot.
+fn entries(request: &Request, slot: usize, buffer: *mut u8) -> Vec<squeue::Entry> {
+    let user_data = |op: u64| slot as u64 * CHAIN_OPS + op;
+    match request {
+        Request::Read(read) => vec![opcode::Read::new(types::Fd(read.file.as_raw_fd()), buffer, read.len as u32)
+            .offset(read.offset)
+            .rw_flags(read.rw_flags)
+            .build()
+            .user_data(user_data(0))],
+        Request::SmallFile(small) => {
+            let fixed = types::Fixed(slot as u32);
+            // Direct descriptors never reach userspace, and the kernel rejects O_CLOEXEC for them
+            let mut flags = libc::O_RDONLY;
+            if small.direct {
+                flags |= libc::O_DIRECT;
+            }
+            let destination = types::DestinationSlot::try_from_slot_target(slot as u32).ok();
+            let mut chain = vec![
+                opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), small.path.as_ptr())
+                    .file_index(destination)
+                    .flags(flags)
+                    .build()
+                    .user_data(user_data(0)),
+                opcode::Read::new(fixed, buffer, ALIGNMENT as u32).build().user_data(user_data(1)),
+            ];
+            if small.drop_pages && !small.direct {
+                chain.push(opcode::Fadvise::new(fixed, 0, libc::POSIX_FADV_DONTNEED).build().user_data(user_data(2)));
+            }
+            let links = chain.len();
+            chain.push(opcode::Close::new(fixed).build().user_data(user_data(3)));
+            chain.into_iter()
+                .enumerate()
+                .map(|(i, entry)| if i < links { entry.flags(squeue::Flags::IO_HARDLINK) } else { entry })
+                .collect()
+        }
+    }
+}
+
+/// Async cancels for every operation of the request in `slot` that can still be
+/// waiting: the read, and a small file's open. The close always runs.
+fn cancel_entries(request: &Request, slot: usize) -> Vec<squeue::Entry> {
+    let ops: &[u64] = match request {
+        Request::Read(_) => &[0],
+        Request::SmallFile(_) => &[0, 1],
+    };
+    ops.iter()
+        .map(|op| opcode::AsyncCancel::new(slot as u64 * CHAIN_OPS + op).build().user_data(CANCEL_USER_DATA))
+        .collect()
+}
+
+fn run_submitter(mut ring: IoUring, requests: mpsc::Receiver<Message>, wake: File, outstanding: Arc<AtomicUsize>) {
+    let mut slots: Vec<Option<InFlight>> = (0..RING_ENTRIES).map(|_| None).collect();
+    let mut free: Vec<usize> = (0..RING_ENTRIES as usize).rev().collect();
+    let mut backlog: VecDeque<(u64, Request)> = VecDeque::new();
+    // Requests in the kernel whose callers have gone, still to be sent an async cancel
+    let mut to_cancel: Vec<u64> = Vec::new();
+    let mut in_flight = 0usize;
+    // Chains complete several entries per slot; stay within the completion queue
+    let completion_capacity = ring.completion().capacity();
+    let mut completions_due = 0usize;
+    let mut wake_buffer = [0u8; 8];
+    let mut wake_armed = false;
+    let pool = BufferPool::global();
+
+    loop {
+        outstanding.store(in_flight + backlog.len(), Ordering::Relaxed);
+        // Only block on the channel when the ring is idle; otherwise completions drive the loop
+        let mut messages = Vec::new();
+        if in_flight == 0 && backlog.is_empty() {
+            match requests.recv() {
+                Ok(message) => messages.push(message),
+                Err(_) => return,
+            }
+        }
+        messages.extend(requests.try_iter());
+        for message in messages {
+            match message {
+                Message::Submit(id, request) => backlog.push_back((id, request)),
+                Message::Cancel(id) => match backlog.iter().position(|(queued, _)| *queued == id) {
+                    Some(index) => drop(backlog.remove(index)),
+                    None => to_cancel.push(id),
+                },
+            }
+        }
+
+        // Cancels go first, so they aren't stuck behind new requests when the queues are full
+        to_cancel.retain(|&id| {
+            let Some((slot, pending)) = slots.iter_mut().enumerate().find_map(|(slot, s)| s.as_mut().filter(|p| p.id == id).map(|p| (slot, p))) else {
+                // Already completed
+                return false;
+            };
+            if pending.cancelled {
+                return false;
+            }
+            let entries = cancel_entries(&pending.request, slot);
+            if completions_due + entries.len() > completion_capacity {
+                return true;
+            }
+            // Safety: async cancels carry no buffers
+            if unsafe { ring.submission().push_multiple(&entries) }.is_err() {
+                return true;
+            }
+            completions_due += entries.len();
+            pending.cancelled = true;
+            false
+        });
+
+        if !wake_armed && completions_due < completion_capacity {
+            let entry = opcode::Read::new(types::Fd(wake.as_raw_fd()), wake_buffer.as_mut_ptr(), 8).build().user_data(WAKE_USER_DATA);
+            // Safety: the buffer lives as long as the thread, which outlives the ring's use of it
+            if unsafe { ring.submission().push(&entry) }.is_ok() {
+                wake_armed = true;
+                completions_due += 1;
+            }
+        }
+
+        // Out of buffer memory, requests wait in the backlog for buffers to come back
+        let mut starved = false;
+        while let Some(&slot) = free.last() {
+            let Some((id, request)) = backlog.pop_front() else { break };
+            let mut buffer = match pool.try_get(request.len()) {
+                Ok(Some(buffer)) => buffer,
+                Ok(None) => {
+                    backlog.push_front((id, request));
+                    starved = true;
+                    break;
+                }
+                Err(e) => {
+                    request.fail(e);
+                    continue;
+                }
+            };
+            let entries = entries(&request, slot, buffer.as_mut_ptr());
+            if completions_due + entries.len() > completion_capacity {
+                backlog.push_front((id, request));
+                break;
+            }
+            // Safety: the buffer, the file and the path stay alive in `slots` until every
+            // completion for the slot is reaped
+            if unsafe { ring.submission().push_multiple(&entries) }.is_err() {
+                backlog.push_front((id, request));
+                break;
+            }
+            free.pop();
+            completions_due += entries.len();
+            slots[slot] = Some(InFlight { id, cancelled: false, request, _buffer: buffer, pending: entries.len(), outcome: None });
+            in_flight += 1;
+        }
+
+        if in_flight == 0 {
+            // No completion will wake us; the buffers are held elsewhere
+            if starved {
+                pool.wait_for_return(STARVED_POLL);
+            }
+            continue;
+        }
+        if let Err(e) = ring.submit_and_wait(1) {
+            // Reads already handed to the kernel still own their buffers, so they can't be
+            // failed early; back off and retry until completions arrive.
+            if e.raw_os_error() != Some(libc::EINTR) {
+                warnings::report(Category::Backend, format_args!("io_uring submit failed with {} reads in flight, retrying: {}", in_flight, e));
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+        }
+
+        let completions: Vec<(u64, i32)> = ring.completion().map(|cqe| (cqe.user_data(), cqe.result())).collect();
+        for (user_data, result) in completions {
+            // A cancel's own result only says whether it caught the operation in time
+            if user_data == WAKE_USER_DATA || user_data == CANCEL_USER_DATA {
+                completions_due -= 1;
+                wake_armed &= user_data != WAKE_USER_DATA;
+                continue;
+            }
+            let (slot, op) = ((user_data / CHAIN_OPS) as usize, user_data % CHAIN_OPS);
+            let Some(entry) = slots.get_mut(slot) else { continue };
+            let Some(pending) = entry.as_mut() else { continue };
+            completions_due -= 1;
+            pending.pending -= 1;
+            // The open's failure explains everything after it; otherwise the read decides
+            let decides = match op {
+                0 => result < 0 || matches!(pending.request, Request::Read(_)),
+                1 => pending.outcome.is_none(),
+                _ => false,
+            };
+            if decides {
+                pending.outcome = Some(if result < 0 {
+                    Err(std::io::Error::from_raw_os_error(-result))
+                } else {
+                    Ok(result as usize)
+                });
+            }
+            if pending.pending > 0 {
+                continue;
+            }
+            let Some(done) = entry.take() else { continue };
+            free.push(slot);
+            in_flight -= 1;
+            let outcome = done.outcome.unwrap_or_else(|| Err(std::io::Error::other("io_uring completed a chain without a result")));
+            let _ = match done.request {
+                Request::Read(read) => read.reply.send(outcome),
+                Request::SmallFile(small) => small.reply.send(outcome),
+            };
+        }
+    }
+}
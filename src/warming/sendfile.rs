@@ -0,0 +1,138 @@
+//! Zero-copy warming: sendfile(2) from the file to `/dev/null`.
+//!
+//! The kernel reads each range into the page cache to splice it to the output, and
+//! `/dev/null` throws it away, so blocks are fetched without a userspace buffer or a
+//! mapping. That works on filesystems that refuse O_DIRECT, such as some overlayfs
+//! setups, where the direct I/O strategies can't run. copy_file_range(2) would do the
+//! same, but it rejects anything but a regular file as the destination.
+
+use std::path::Path;
+use std::time::Instant;
+use log::debug;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::prelude::AsRawFd;
+#[cfg(target_os = "linux")]
+use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+
+use crate::anonymize;
+use crate::warming::{Coverage, WarmingResult, WarmingOptions, SPARSE_INTERVAL};
+
+/// Bytes requested per sendfile() call for full warming
+#[cfg(target_os = "linux")]
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// `/dev/null`, opened once for the whole run
+#[cfg(target_os = "linux")]
+fn dev_null() -> Result<&'static std::fs::File, std::io::Error> {
+    static DEV_NULL: std::sync::OnceLock<std::fs::File> = std::sync::OnceLock::new();
+    if let Some(file) = DEV_NULL.get() {
+        return Ok(file);
+    }
+    let file = std::fs::OpenOptions::new().write(true).open("/dev/null")?;
+    Ok(DEV_NULL.get_or_init(|| file))
+}
+
+/// Warm file by sending it to `/dev/null`, which reads it into the page cache (and so
+/// triggers EBS block fetches) without copying any data to userspace
+#[cfg(target_os = "linux")]
+pub async fn warm_file(
+    path: &Path,
+    file_size: u64,
+    options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    let path = path.to_path_buf();
+    let sparse = options.is_sparse(file_size);
+    let stride = options.sample_interval(SPARSE_INTERVAL);
+    let drop_pages = options.drop_caches.per_file();
+    let chunk_size = options.read_size(CHUNK_SIZE) as u64;
+    let reads = if sparse { options.reads(file_size, 4096, Some(stride), false) } else { options.reads(file_size, chunk_size, None, false) };
+
+    // sendfile() blocks until the data has been read, so keep it off the runtime threads
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let file = std::fs::File::open(&path)?;
+        let (fd, null) = (file.as_raw_fd(), dev_null()?.as_raw_fd());
+
+        let mut bytes_read = 0u64;
+        let method = if sparse {
+            debug!("Using sparse sendfile for large file: {} ({} bytes)", anonymize::display(&path), file_size);
+            let mut samples = 0u64;
+            for (offset, count) in reads {
+                bytes_read += send_range(fd, null, offset, count)?;
+                samples += 1;
+            }
+            debug!("Sparse sendfile completed: {} samples in {:?}", samples, start.elapsed());
+            "sendfile_sparse"
+        } else {
+            for (offset, count) in reads {
+                let sent = send_range(fd, null, offset, count)?;
+                bytes_read += sent;
+                // Drop each chunk as we go so large files don't balloon the page cache
+                if drop_pages {
+                    let _ = posix_fadvise(fd, offset as i64, count as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED);
+                }
+                if sent < count {
+                    // The file shrank since it was stat'ed
+                    break;
+                }
+            }
+            debug!("Full sendfile completed: {} bytes in {:?}", bytes_read, start.elapsed());
+            "sendfile_full"
+        };
+
+        // Drop pages from cache afterwards (we only wanted EBS warming)
+        if drop_pages {
+            let drop_result = posix_fadvise(fd, 0, file_size as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED);
+            debug!("sendfile cache drop result: {:?}", drop_result.is_ok());
+        }
+
+        Ok(WarmingResult {
+            method,
+            success: true,
+            duration: start.elapsed(),
+            bytes_read,
+            coverage: if sparse { Coverage::Sampled } else { Coverage::Full },
+        })
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/// Send `count` bytes at `offset` of `fd` to `null`; fewer only at the end of the file
+#[cfg(target_os = "linux")]
+fn send_range(fd: libc::c_int, null: libc::c_int, offset: u64, count: u64) -> Result<u64, std::io::Error> {
+    let mut position = offset as libc::off_t;
+    let end = offset + count;
+    while (position as u64) < end {
+        let remaining = (end - position as u64) as libc::size_t;
+        // Advances `position` by what was sent, leaving the file offset alone
+        let sent = unsafe { libc::sendfile(null, fd, &mut position, remaining) };
+        if sent < 0 {
+            let err = std::io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                // The filesystem can't splice (e.g. some FUSE or special files)
+                Some(libc::EINVAL) | Some(libc::ENOSYS) => return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, err)),
+                _ => return Err(err),
+            }
+        }
+        if sent == 0 {
+            break;
+        }
+    }
+    Ok(position as u64 - offset)
+}
+
+// Stub implementation for non-Linux systems
+#[cfg(not(target_os = "linux"))]
+pub async fn warm_file(
+    _path: &Path,
+    _file_size: u64,
+    _options: &WarmingOptions,
+) -> Result<WarmingResult, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "sendfile only supported on Linux"
+    ))
+}
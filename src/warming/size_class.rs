@@ -0,0 +1,155 @@
+//! Strategy selection by file size (`--strategy-map`).
+//!
+//! The best backend depends on the file: a hint costs a tiny file less than setting up
+//! a read, medium files go fastest as batched io_uring reads, and a huge file may only
+//! be worth sampling. [`SizeClass`] sorts files into five classes and a [`StrategyMap`]
+//! names the strategy for some of them; [`WarmingOptions::for_size`] applies it to each
+//! file, and classes the map leaves out keep `--strategy`.
+//!
+//! [`WarmingOptions::for_size`]: super::WarmingOptions::for_size
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::Strategy;
+
+/// File sizes, by the largest size each class holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SizeClass {
+    /// Up to 4 KiB: one block
+    Tiny,
+    /// Up to 64 KiB
+    Small,
+    /// Up to 1 MiB
+    Medium,
+    /// Up to 100 MiB
+    Large,
+    /// Over 100 MiB
+    Huge,
+}
+
+impl SizeClass {
+    pub const ALL: [SizeClass; 5] = [SizeClass::Tiny, SizeClass::Small, SizeClass::Medium, SizeClass::Large, SizeClass::Huge];
+
+    /// The class of a file of `file_size` bytes
+    pub fn of(file_size: u64) -> SizeClass {
+        match file_size {
+            0..=4096 => SizeClass::Tiny,
+            4097..=65536 => SizeClass::Small,
+            65537..=1048576 => SizeClass::Medium,
+            1048577..=104857600 => SizeClass::Large,
+            _ => SizeClass::Huge,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SizeClass::Tiny => "tiny",
+            SizeClass::Small => "small",
+            SizeClass::Medium => "medium",
+            SizeClass::Large => "large",
+            SizeClass::Huge => "huge",
+        }
+    }
+}
+
+impl FromStr for SizeClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SizeClass::ALL
+            .into_iter()
+            .find(|class| class.name() == s.trim().to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown size class '{}' (expected tiny, small, medium, large or huge)", s))
+    }
+}
+
+impl fmt::Display for SizeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How the files of one class are warmed: a strategy, and with a `_sparse` suffix
+/// (`uring_sparse`) sampled like `--sparse-large-files` whatever their size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassStrategy {
+    pub strategy: Strategy,
+    pub sparse: bool,
+}
+
+impl FromStr for ClassStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let (name, sparse) = match s.strip_suffix("_sparse").or_else(|| s.strip_suffix("-sparse")) {
+            Some(name) => (name, true),
+            None => (s.as_str(), false),
+        };
+        let strategy = name.parse::<Strategy>()?;
+        if strategy == Strategy::Auto && sparse {
+            return Err(format!("invalid '{}': name the strategy to sample with, not auto", s));
+        }
+        Ok(ClassStrategy { strategy, sparse })
+    }
+}
+
+impl fmt::Display for ClassStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.strategy, if self.sparse { "_sparse" } else { "" })
+    }
+}
+
+/// The strategy for each size class the map names (`--strategy-map`), e.g.
+/// `tiny=fadvise,medium=uring,huge=uring_sparse`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StrategyMap {
+    classes: Vec<(SizeClass, ClassStrategy)>,
+}
+
+impl StrategyMap {
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// How files of `class` are warmed, if the map says
+    pub fn get(&self, class: SizeClass) -> Option<ClassStrategy> {
+        self.classes.iter().find(|(c, _)| *c == class).map(|&(_, strategy)| strategy)
+    }
+
+    /// The classes the map names, smallest first
+    pub fn classes(&self) -> impl Iterator<Item = (SizeClass, ClassStrategy)> + '_ {
+        self.classes.iter().copied()
+    }
+}
+
+impl FromStr for StrategyMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut classes: Vec<(SizeClass, ClassStrategy)> = Vec::new();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (class, strategy) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("invalid '{}' (expected CLASS=STRATEGY, e.g. tiny=fadvise)", entry))?;
+            let class = class.parse::<SizeClass>()?;
+            if classes.iter().any(|(c, _)| *c == class) {
+                return Err(format!("size class '{}' is mapped twice", class));
+            }
+            classes.push((class, strategy.parse()?));
+        }
+        if classes.is_empty() {
+            return Err("no size classes given (expected e.g. tiny=fadvise,huge=uring_sparse)".to_string());
+        }
+        classes.sort_by_key(|(class, _)| *class);
+        Ok(StrategyMap { classes })
+    }
+}
+
+impl fmt::Display for StrategyMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self.classes.iter().map(|(class, strategy)| format!("{}={}", class, strategy)).collect();
+        f.write_str(&entries.join(","))
+    }
+}
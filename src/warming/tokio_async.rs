@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::Instant;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, BufReader};
@@ -12,11 +12,24 @@ use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
 #[cfg(target_os = "linux")]
 use libc;
 
-use crate::warming::{WarmingResult, WarmingOptions};
+use crate::anonymize;
+#[cfg(target_os = "linux")]
+use crate::warming::buffers::{BufferPool, ALIGNMENT};
+use crate::warming::nowait;
+use crate::warming::{Coverage, WarmingResult, WarmingOptions, SPARSE_INTERVAL};
+use crate::warnings::{self, Category};
+
+/// Direct I/O read size; 1MB chunks give good throughput
+#[cfg(target_os = "linux")]
+const CHUNK_SIZE: usize = 1024 * 1024;
+/// Buffered sparse reads take one byte from every page by default
+const BUFFERED_SPARSE_INTERVAL: u64 = 4096;
+/// Bytes per read for full buffered reads
+const BUFFERED_READ_SIZE: usize = 8192;
 
 /// Warm file using standard Tokio async I/O (with optional direct I/O)
 pub async fn warm_file(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
     options: &WarmingOptions,
 ) -> Result<WarmingResult, std::io::Error> {
@@ -25,166 +38,160 @@ pub async fn warm_file(
     if options.use_direct_io && cfg!(target_os = "linux") {
         #[cfg(target_os = "linux")]
         {
-            debug!("Using Tokio + direct I/O for {}", path.display());
-            return warm_with_direct_io(path, file_size, options.sparse_large_files).await;
+            debug!("Using Tokio + direct I/O for {}", anonymize::display(path));
+            let sparse = options.is_sparse(file_size);
+            let chunk_size = options.read_size(CHUNK_SIZE);
+            let reads = if sparse {
+                options.reads(file_size, ALIGNMENT as u64, Some(options.sample_interval(SPARSE_INTERVAL)), true)
+            } else {
+                options.reads(file_size, chunk_size as u64, None, true)
+            };
+            return warm_with_direct_io(path, file_size, sparse, reads, chunk_size).await;
         }
     }
     
+    let sparse = options.is_sparse(file_size);
+    if options.nowait_precheck && !sparse {
+        debug!("Using page-cache-first Tokio reads for {}", anonymize::display(path));
+        let (path, read_size, drop_pages) = (path.to_path_buf(), options.read_size(nowait::READ_SIZE), options.drop_caches.per_file());
+        let ranges = options.ranges.clone();
+        return tokio::task::spawn_blocking(move || nowait::warm_file_blocking(&path, ranges.as_deref(), read_size, drop_pages))
+            .await
+            .map_err(std::io::Error::other)?;
+    }
+
     // Standard Tokio async I/O with manual reading
-    debug!("Using standard Tokio async I/O for {}", path.display());
-    warm_with_manual_reading(path, file_size, options.sparse_large_files).await
+    debug!("Using standard Tokio async I/O for {}", anonymize::display(path));
+    let read_size = options.read_size(BUFFERED_READ_SIZE);
+    // Sparse reads take one byte per sample
+    let reads = if sparse {
+        options.reads(file_size, 1, Some(options.sample_interval(BUFFERED_SPARSE_INTERVAL)), false)
+    } else {
+        options.reads(file_size, read_size as u64, None, false)
+    };
+    warm_with_manual_reading(path, file_size, sparse, reads, read_size, options.drop_caches.per_file()).await
 }
 
 #[cfg(target_os = "linux")]
-async fn open_file_direct_io(path: &PathBuf) -> Result<File, std::io::Error> {
-    let file = std::fs::OpenOptions::new()
+fn open_file_direct_io(path: &Path) -> Result<std::fs::File, std::io::Error> {
+    std::fs::OpenOptions::new()
         .read(true)
         .custom_flags(libc::O_DIRECT)
-        .open(path)?;
-    Ok(File::from_std(file))
+        .open(path)
 }
 
 #[cfg(target_os = "linux")]
 async fn warm_with_direct_io(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
-    sparse_threshold: u64,
+    sparse: bool,
+    reads: impl Iterator<Item = (u64, u64)> + Send + 'static,
+    chunk_size: usize,
 ) -> Result<WarmingResult, std::io::Error> {
+    let path = path.to_path_buf();
+
+    // O_DIRECT needs aligned buffers, which tokio::fs::File's internal buffering can't
+    // provide, so do positional reads on a blocking thread with our own aligned buffer.
+    tokio::task::spawn_blocking(move || direct_io_blocking(&path, file_size, sparse, reads, chunk_size))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+#[cfg(target_os = "linux")]
+fn direct_io_blocking(
+    path: &Path,
+    file_size: u64,
+    sparse: bool,
+    reads: impl Iterator<Item = (u64, u64)>,
+    chunk_size: usize,
+) -> Result<WarmingResult, std::io::Error> {
+    use std::os::unix::fs::FileExt;
+
     let _start = Instant::now();
-    const ALIGNMENT: usize = 4096; // 4KB alignment required for O_DIRECT
-    const CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks for good throughput
-    
-    let mut file = open_file_direct_io(path).await?;
-    
-    if sparse_threshold > 0 && file_size > sparse_threshold {
-        // Sparse reading for large files - sample every 64KB to minimize I/O while still warming EBS
+
+    let file = open_file_direct_io(path)?;
+    let buffer_size = if sparse { ALIGNMENT } else { chunk_size };
+
+    // Aligned buffer for direct I/O; waits here while the pool is at --max-buffer-memory
+    let mut buffer = BufferPool::global().get_blocking(buffer_size)?;
+    let buffer_slice = buffer.as_mut_slice();
+
+    let result = if sparse {
+        // Sparse reading for large files - sample every interval to minimize I/O while still warming EBS
         debug!("Using sparse direct I/O for large file ({} bytes)", file_size);
-        let sample_interval: u64 = 65536; // 64KB intervals
-        let mut offset: u64 = 0;
         let mut samples_read = 0;
-        
-        // Allocate aligned buffer for direct I/O
-        let layout = std::alloc::Layout::from_size_align(ALIGNMENT, ALIGNMENT)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to create aligned memory layout"))?;
-        let buffer = unsafe { std::alloc::alloc(layout) };
-        if buffer.is_null() {
-            return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
-        }
-        
-        let result = async {
-            while offset < file_size {
-                // Align offset to page boundary for O_DIRECT requirement
-                let aligned_offset = (offset / ALIGNMENT as u64) * ALIGNMENT as u64;
-                
-                if let Err(e) = file.seek(std::io::SeekFrom::Start(aligned_offset)).await {
-                    debug!("Failed to seek to offset {}: {}", aligned_offset, e);
-                    break;
-                }
-                
-                let buffer_slice = unsafe { std::slice::from_raw_parts_mut(buffer, ALIGNMENT) };
-                match file.read(buffer_slice).await {
-                    Ok(n) => {
-                        if n == 0 { break; }
-                        samples_read += 1;
-                    }
-                    Err(e) => {
-                        debug!("Failed to read at offset {}: {}", aligned_offset, e);
-                        break;
-                    }
-                }
-                offset += sample_interval;
+        let mut bytes_read = 0u64;
+
+        let read_result = (|| {
+            for (offset, len) in reads {
+                // Offsets are multiples of the sample interval or block-aligned range starts, so aligned for O_DIRECT
+                let n = file.read_at(&mut buffer_slice[..len as usize], offset)?;
+                if n == 0 { break; }
+                samples_read += 1;
+                bytes_read += n as u64;
             }
-            Ok(())
-        }.await;
-        
-        unsafe { std::alloc::dealloc(buffer, layout) };
+            Ok::<(), std::io::Error>(())
+        })();
         debug!("Sparse direct I/O completed: {} samples in {:?}", samples_read, _start.elapsed());
-        
-        match result {
-            Ok(()) => Ok(WarmingResult {
-                method: "tokio_direct_sparse",
-                success: true,
-                duration: _start.elapsed(),
-            }),
-            Err(e) => Err(e),
-        }
+
+        read_result.map(|()| WarmingResult {
+            method: "tokio_direct_sparse",
+            success: true,
+            duration: _start.elapsed(),
+            bytes_read,
+            coverage: Coverage::Sampled,
+        })
     } else {
         // Full direct I/O reading for smaller files
         debug!("Using full direct I/O for file ({} bytes)", file_size);
-        
-        let layout = std::alloc::Layout::from_size_align(CHUNK_SIZE, ALIGNMENT)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to create aligned memory layout"))?;
-        let buffer = unsafe { std::alloc::alloc(layout) };
-        if buffer.is_null() {
-            return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, "Failed to allocate aligned buffer"));
-        }
-        
-        let result = async {
-            let mut total_read = 0u64;
-            let mut offset = 0u64;
-            
-            while offset < file_size {
-                let remaining = file_size - offset;
-                let read_size = std::cmp::min(CHUNK_SIZE as u64, remaining);
-                
-                // Align read size to sector boundary for O_DIRECT
-                let aligned_read_size = ((read_size + ALIGNMENT as u64 - 1) / ALIGNMENT as u64) * ALIGNMENT as u64;
-                let actual_read_size = std::cmp::min(aligned_read_size, CHUNK_SIZE as u64) as usize;
-                
-                if let Err(e) = file.seek(std::io::SeekFrom::Start(offset)).await {
-                    debug!("Failed to seek to offset {}: {}", offset, e);
-                    break;
-                }
-                
-                let buffer_slice = unsafe { std::slice::from_raw_parts_mut(buffer, actual_read_size) };
-                match file.read(buffer_slice).await {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        total_read += n as u64;
-                        offset += n as u64;
-                    }
-                    Err(e) => {
-                        debug!("Failed to read chunk at offset {}: {}", offset, e);
-                        break;
-                    }
-                }
+        let mut total_read = 0u64;
+
+        let read_result = (|| {
+            for (offset, len) in reads {
+                // Reads are always whole aligned blocks; the kernel returns a short count at EOF
+                let n = file.read_at(&mut buffer_slice[..len as usize], offset)?;
+                if n == 0 { break; }
+                total_read += n as u64;
             }
-            Ok(total_read)
-        }.await;
-        
-        unsafe { std::alloc::dealloc(buffer, layout) };
-        
-        match result {
-            Ok(bytes_read) => {
-                debug!("Full direct I/O completed: {} bytes read in {:?}", bytes_read, _start.elapsed());
-                Ok(WarmingResult {
-                    method: "tokio_direct_full",
-                    success: true,
-                    duration: _start.elapsed(),
-                })
+            Ok::<(), std::io::Error>(())
+        })();
+
+        read_result.map(|()| {
+            debug!("Full direct I/O completed: {} bytes read in {:?}", total_read, _start.elapsed());
+            WarmingResult {
+                method: "tokio_direct_full",
+                success: true,
+                duration: _start.elapsed(),
+                bytes_read: total_read,
+                coverage: Coverage::Full,
             }
-            Err(e) => Err(e),
-        }
-    }
+        })
+    };
+
+    result
 }
 
 async fn warm_with_manual_reading(
-    path: &PathBuf,
+    path: &Path,
     file_size: u64,
-    sparse_threshold: u64,
+    sparse: bool,
+    reads: impl Iterator<Item = (u64, u64)>,
+    read_size: usize,
+    drop_pages: bool,
 ) -> Result<WarmingResult, std::io::Error> {
     let _start = Instant::now();
     let mut file = File::open(path).await?;
     
-    let method = if sparse_threshold > 0 && file_size > sparse_threshold {
-        debug!("Using sparse reading for large file: {} ({} bytes)", path.display(), file_size);
-        let page_size: u64 = 4096;
-        let mut offset: u64 = 0;
+    let mut bytes_read = 0u64;
+    let mut stopped_early = false;
+    let method = if sparse {
+        debug!("Using sparse reading for large file: {} ({} bytes)", anonymize::display(path), file_size);
         let mut pages_read = 0;
 
-        while offset < file_size {
+        for (offset, _) in reads {
             if let Err(e) = file.seek(std::io::SeekFrom::Start(offset)).await {
-                debug!("Failed to seek in file {} at offset {}: {}", path.display(), offset, e);
+                warnings::report(Category::of(&e), format_args!("Failed to seek in file {} at offset {}: {}", anonymize::display(path), offset, e));
+                stopped_early = true;
                 break;
             }
             let mut byte = [0; 1];
@@ -194,19 +201,20 @@ async fn warm_with_manual_reading(
                         break;
                     }
                     pages_read += 1;
+                    bytes_read += n as u64;
                 }
                 Err(e) => {
-                    debug!("Failed to read byte in file {} at offset {}: {}", path.display(), offset, e);
+                    warnings::report(Category::of(&e), format_args!("Failed to read byte in file {} at offset {}: {}", anonymize::display(path), offset, e));
+                    stopped_early = true;
                     break;
                 }
             }
-            offset += page_size;
         }
         debug!("Sparse read completed: {} pages sampled in {:?}", pages_read, _start.elapsed());
         
                  // Drop pages from cache after sparse reading (we only wanted EBS warming)
          #[cfg(target_os = "linux")]
-         {
+         if drop_pages {
              use std::os::unix::prelude::AsRawFd;
              let fd = file.as_raw_fd();
             let drop_result = posix_fadvise(fd, 0, file_size as i64, PosixFadviseAdvice::POSIX_FADV_DONTNEED);
@@ -215,17 +223,27 @@ async fn warm_with_manual_reading(
         
         "tokio_sparse"
     } else {
-        debug!("Using full buffer read for file: {} ({} bytes)", path.display(), file_size);
+        debug!("Using full buffer read for file: {} ({} bytes)", anonymize::display(path), file_size);
         let mut reader = BufReader::new(file);
-        let mut buffer = [0; 8192];
+        let mut buffer = vec![0; read_size];
         let mut total_read = 0;
+        let mut position = 0u64;
 
-        loop {
-            match reader.read(&mut buffer).await {
+        for (offset, len) in reads {
+            // Reads within a range follow on; only the start of the next range needs a seek
+            if offset != position {
+                if let Err(e) = reader.seek(std::io::SeekFrom::Start(offset)).await {
+                    warnings::report(Category::of(&e), format_args!("Failed to seek in file {} at offset {}: {}", anonymize::display(path), offset, e));
+                    stopped_early = true;
+                    break;
+                }
+            }
+            match reader.read(&mut buffer[..len as usize]).await {
                 Ok(0) => break,
-                Ok(n) => { total_read += n; },
+                Ok(n) => { total_read += n; bytes_read += n as u64; position = offset + n as u64; },
                 Err(e) => {
-                    debug!("Failed to read file {}: {}", path.display(), e);
+                    warnings::report(Category::of(&e), format_args!("Failed to read file {}: {}", anonymize::display(path), e));
+                    stopped_early = true;
                     break;
                 }
             }
@@ -234,7 +252,7 @@ async fn warm_with_manual_reading(
         
                  // Drop pages from cache after full reading (we only wanted EBS warming)
          #[cfg(target_os = "linux")]
-         {
+         if drop_pages {
              use std::os::unix::prelude::AsRawFd;
              let inner_file = reader.into_inner();
              let fd = inner_file.as_raw_fd();
@@ -245,9 +263,16 @@ async fn warm_with_manual_reading(
         "tokio_full"
     };
     
+    let coverage = match method {
+        _ if stopped_early => Coverage::Partial,
+        "tokio_sparse" => Coverage::Sampled,
+        _ => Coverage::Full,
+    };
     Ok(WarmingResult {
         method,
         success: true,
         duration: _start.elapsed(),
+        bytes_read,
+        coverage,
     })
 } 
\ No newline at end of file
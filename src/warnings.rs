@@ -0,0 +1,216 @@
+//! Rate-limited warnings.
+//!
+//! Per-file problems (unreadable files, failed reads, slow operations) can occur
+//! millions of times on a large volume. Instead of logging each one, strategies and the
+//! pipeline report them here by [`Category`]: the first few of each category are logged
+//! as warnings, after that at most one per interval with a count of what was held back,
+//! and the run ends with a per-category tally such as
+//! `1.2M permission-denied warnings suppressed (1.2M total)`.
+//! Suppressed warnings are still logged at debug level, so `--debug` shows everything.
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use log::{debug, log_enabled, warn, Level};
+
+/// Warnings of each category logged before rate limiting starts
+const BURST: u64 = 10;
+/// After the burst, at most one warning per category per interval is logged
+const INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    PermissionDenied,
+    NotFound,
+    /// Other I/O errors while reading a file
+    ReadError,
+    /// Directory walk errors that aren't about permissions
+    Discovery,
+    /// A strategy was unavailable or didn't succeed and the next one was tried
+    Fallback,
+    SlowOperation,
+    /// Errors from the I/O machinery itself, such as a failed io_uring submit
+    Backend,
+    /// The async runtime's workers were all busy past `--stall-threshold-ms`
+    RuntimeStall,
+}
+
+impl Category {
+    pub const ALL: [Category; 8] = [
+        Category::PermissionDenied,
+        Category::NotFound,
+        Category::ReadError,
+        Category::Discovery,
+        Category::Fallback,
+        Category::SlowOperation,
+        Category::Backend,
+        Category::RuntimeStall,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::PermissionDenied => "permission-denied",
+            Category::NotFound => "not-found",
+            Category::ReadError => "read-error",
+            Category::Discovery => "discovery",
+            Category::Fallback => "fallback",
+            Category::SlowOperation => "slow-operation",
+            Category::Backend => "backend",
+            Category::RuntimeStall => "runtime-stall",
+        }
+    }
+
+    /// Category for an I/O error hit while opening or reading a file
+    pub fn of(error: &io::Error) -> Category {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Category::PermissionDenied,
+            io::ErrorKind::NotFound => Category::NotFound,
+            _ => Category::ReadError,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    total: AtomicU64,
+    logged: AtomicU64,
+    /// Held back since the last logged warning
+    pending: AtomicU64,
+    /// When the last warning after the burst was logged, in ms since the channel started
+    last_logged_ms: AtomicU64,
+}
+
+/// Warning channel with per-category rate limiting; see the module docs
+#[derive(Debug)]
+pub struct Warnings {
+    burst: u64,
+    interval: Duration,
+    started: Instant,
+    counters: [Counter; Category::ALL.len()],
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Self::new(BURST, INTERVAL)
+    }
+}
+
+impl Warnings {
+    /// Log the first `burst` warnings of each category, then at most one per `interval`
+    pub fn new(burst: u64, interval: Duration) -> Self {
+        Self { burst, interval, started: Instant::now(), counters: Default::default() }
+    }
+
+    /// Process-wide channel used by [`report`]
+    pub fn global() -> &'static Warnings {
+        static GLOBAL: OnceLock<Warnings> = OnceLock::new();
+        GLOBAL.get_or_init(Warnings::default)
+    }
+
+    /// Count a warning and log it unless its category is being rate limited. Returns
+    /// whether it was logged.
+    pub fn report(&self, category: Category, message: fmt::Arguments<'_>) -> bool {
+        let counter = &self.counters[category.index()];
+        let n = counter.total.fetch_add(1, Ordering::Relaxed) + 1;
+        if n < self.burst {
+            counter.logged.fetch_add(1, Ordering::Relaxed);
+            warn!("{}", message);
+            return true;
+        }
+        if n == self.burst {
+            counter.logged.fetch_add(1, Ordering::Relaxed);
+            counter.last_logged_ms.store(self.now_ms(), Ordering::Relaxed);
+            warn!("{} (further {} warnings limited to one per {:?})", message, category, self.interval);
+            return true;
+        }
+
+        let now = self.now_ms();
+        let last = counter.last_logged_ms.load(Ordering::Relaxed);
+        let due = now.saturating_sub(last) >= self.interval.as_millis() as u64;
+        if due && counter.last_logged_ms.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            counter.logged.fetch_add(1, Ordering::Relaxed);
+            let held_back = counter.pending.swap(0, Ordering::Relaxed);
+            if held_back > 0 {
+                warn!("{} ({} similar {} warnings suppressed)", message, held_back, category);
+            } else {
+                warn!("{}", message);
+            }
+            return true;
+        }
+
+        counter.pending.fetch_add(1, Ordering::Relaxed);
+        if log_enabled!(Level::Debug) {
+            debug!("{}", message);
+        }
+        false
+    }
+
+    /// Per-category totals, for categories that saw at least one warning
+    pub fn counts(&self) -> Vec<WarningCount> {
+        Category::ALL
+            .iter()
+            .filter_map(|&category| {
+                let counter = &self.counters[category.index()];
+                let total = counter.total.load(Ordering::Relaxed);
+                (total > 0).then(|| WarningCount {
+                    category,
+                    total,
+                    suppressed: total.saturating_sub(counter.logged.load(Ordering::Relaxed)),
+                })
+            })
+            .collect()
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+/// Report a warning on the [global](Warnings::global) channel
+pub fn report(category: Category, message: fmt::Arguments<'_>) -> bool {
+    Warnings::global().report(category, message)
+}
+
+/// Warnings of one category over the whole run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarningCount {
+    pub category: Category,
+    pub total: u64,
+    /// Counted but not logged at warn level
+    pub suppressed: u64,
+}
+
+impl fmt::Display for WarningCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} warnings suppressed ({} total)",
+            abbreviate(self.suppressed),
+            self.category,
+            abbreviate(self.total)
+        )
+    }
+}
+
+/// `1234567` → `1.2M`
+fn abbreviate(n: u64) -> String {
+    match n {
+        0..=9_999 => n.to_string(),
+        10_000..=999_999 => format!("{:.1}K", n as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.1}M", n as f64 / 1e6),
+        _ => format!("{:.1}G", n as f64 / 1e9),
+    }
+}
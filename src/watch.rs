@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use log::{debug, info, warn};
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::anonymize;
+use crate::bloom::RecentSet;
+use crate::disk;
+use crate::filters::DiscoveryFilters;
+use crate::warming::{warm_file, WarmingOptions};
+use crate::warnings::{self, Category};
+
+/// Settings for the post-warm watch loop
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub directories: Vec<PathBuf>,
+    pub queue_depth: usize,
+    pub max_file_size: u64,
+    pub debounce: Duration,
+    pub filters: DiscoveryFilters,
+    /// Directories on non-EBS disks, warmed into the page cache only
+    pub page_cache_only: Vec<PathBuf>,
+    /// Directories on network filesystems, warmed with [`disk::network_warming`]
+    pub network: Vec<PathBuf>,
+    /// Warmed file identities remembered per generation of the dedupe filter; 0 re-warms
+    /// a file on every event
+    pub dedupe_capacity: usize,
+}
+
+/// False positive rate each generation of the dedupe filter is sized for; a false
+/// positive leaves a changed file unwarmed
+pub const DEDUPE_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+/// What a watched file looked like when it was warmed; an event for a file that still
+/// looks the same needs no re-warm
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Identity {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+/// Outcome of an event for one changed file
+enum Changed {
+    Warmed(Identity),
+    /// Already warmed as it is now
+    Unchanged,
+    Skipped,
+}
+
+/// Returns true for events that mean a file has new contents worth warming.
+fn is_warmable_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(CreateKind::File)
+            | EventKind::Create(CreateKind::Any)
+            | EventKind::Modify(ModifyKind::Data(_))
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To))
+            | EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+/// Keep running after the initial pass and warm files that are created or modified
+/// under the watched directories. Paths are debounced so a file that is still being
+/// written is only warmed once it has been quiet for `debounce`.
+pub async fn watch_and_warm(options: WatchOptions, warming_options: WarmingOptions) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    // notify delivers events on its own thread, so bridge them into the runtime via a channel
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            if is_warmable_event(&event.kind) {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        }
+        Err(e) => debug!("Watch error: {}", e),
+    })?;
+
+    for dir in &options.directories {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        debug!("Watching directory: {}", dir.display());
+    }
+
+    info!(
+        "Watching {} director{} for new files. Press Ctrl-C to stop.",
+        options.directories.len(),
+        if options.directories.len() == 1 { "y" } else { "ies" }
+    );
+
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut tick = tokio::time::interval(std::cmp::max(options.debounce / 2, Duration::from_millis(10)));
+    let mut watched_files = 0u64;
+    let mut unchanged_files = 0u64;
+    let mut warmed = (options.dedupe_capacity > 0).then(|| RecentSet::new(options.dedupe_capacity, DEDUPE_FALSE_POSITIVE_RATE));
+    if let Some(warmed) = &warmed {
+        debug!("Remembering warmed files in {} bytes of bloom filters", warmed.memory_bytes());
+    }
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!(
+                    "Stopping watch mode. Warmed {} new or modified files; skipped {} events for files already warmed.",
+                    watched_files, unchanged_files
+                );
+                break;
+            }
+            maybe_path = rx.recv() => {
+                match maybe_path {
+                    Some(path) => {
+                        if options.filters.matches_file(&path) {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                    None => {
+                        warn!("File watcher stopped unexpectedly");
+                        break;
+                    }
+                }
+            }
+            _ = tick.tick() => {
+                if pending.is_empty() {
+                    continue;
+                }
+
+                // Only warm paths that have been quiet for the debounce period
+                let ready: Vec<PathBuf> = pending
+                    .iter()
+                    .filter(|(_, last_seen)| last_seen.elapsed() >= options.debounce)
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                for path in &ready {
+                    pending.remove(path);
+                }
+
+                if !ready.is_empty() {
+                    debug!("Warming {} changed files", ready.len());
+                    for changed in warm_changed_files(ready, &options, &warming_options, warmed.as_ref()).await {
+                        match changed {
+                            Changed::Warmed(identity) => {
+                                watched_files += 1;
+                                if let Some(warmed) = warmed.as_mut() {
+                                    let rotations = warmed.rotations();
+                                    warmed.insert(&identity);
+                                    if warmed.rotations() > rotations {
+                                        debug!("Dedupe filter full; started a new generation");
+                                    }
+                                }
+                            }
+                            Changed::Unchanged => unchanged_files += 1,
+                            Changed::Skipped => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Warm `paths`, leaving out files `warmed` remembers warming as they are now
+async fn warm_changed_files(
+    paths: Vec<PathBuf>,
+    options: &WatchOptions,
+    warming_options: &WarmingOptions,
+    warmed: Option<&RecentSet>,
+) -> Vec<Changed> {
+    let page_cache_warming = &disk::page_cache_warming(warming_options);
+    let network_warming = &disk::network_warming(warming_options);
+    stream::iter(paths)
+        .map(|path| async move {
+            let metadata = match tokio::fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_file() => metadata,
+                Ok(_) => return Changed::Skipped,
+                Err(e) => {
+                    debug!("Changed file vanished before warming {}: {}", anonymize::display(&path), e);
+                    return Changed::Skipped;
+                }
+            };
+            let file_size = metadata.len();
+
+            if options.max_file_size > 0 && file_size > options.max_file_size {
+                debug!("Skipping large changed file: {} (size: {} > max: {})", anonymize::display(&path), file_size, options.max_file_size);
+                return Changed::Skipped;
+            }
+
+            let identity = Identity { path, modified: metadata.modified().ok(), size: file_size };
+            if warmed.is_some_and(|warmed| warmed.contains(&identity)) {
+                debug!("Changed file {} is unchanged since it was warmed", anonymize::display(&identity.path));
+                return Changed::Unchanged;
+            }
+            let path = &identity.path;
+
+            let warming_options = if options.page_cache_only.iter().any(|root| path.starts_with(root)) {
+                page_cache_warming
+            } else if options.network.iter().any(|root| path.starts_with(root)) {
+                network_warming
+            } else {
+                warming_options
+            };
+            match warm_file(path, file_size, warming_options).await {
+                Ok(result) => {
+                    debug!("Changed file {} warmed: method={}, duration={:?}", anonymize::display(path), result.method, result.duration);
+                    Changed::Warmed(identity)
+                }
+                Err(e) => {
+                    warnings::report(Category::of(e.io()), format_args!("Failed to warm changed file {}: {}", anonymize::display(path), e));
+                    Changed::Skipped
+                }
+            }
+        })
+        .buffer_unordered(options.queue_depth)
+        .collect()
+        .await
+}
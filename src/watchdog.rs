@@ -0,0 +1,93 @@
+//! Hung-I/O detection (`--hung-io-warning`).
+//!
+//! Reads against a badly degraded volume can block for minutes, and a handful of them
+//! hold every queue slot, so the run stops making progress without anything failing.
+//! [`Watchdog`] follows the pipeline's finished-file count, and when it hasn't moved for
+//! the threshold while files are being read, logs the reads that have been in flight
+//! the longest. It only reports; `--file-timeout` is what gives up on such reads.
+//! Files waiting on a pause, the pacer or their device aren't in flight yet, so
+//! deliberate waits don't count as hangs.
+
+use std::fmt;
+use std::time::Duration;
+
+use log::warn;
+use tokio::time::Instant;
+
+use crate::anonymize;
+use crate::introspect::{InFlightFile, Introspection, IntrospectionSnapshot};
+
+/// Default `--hung-io-warning`
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_secs(60);
+/// In-flight reads listed with each warning
+const LONGEST_LISTED: usize = 5;
+
+/// A stretch without completions, as reported
+#[derive(Debug, Clone)]
+pub struct Hang {
+    /// How long no file has finished
+    pub quiet: Duration,
+    /// Files being read right now
+    pub in_flight: usize,
+    /// The longest-running of them
+    pub longest: Vec<InFlightFile>,
+}
+
+impl fmt::Display for Hang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No file finished in {:.0?} with {} reads in flight; the volume may be degraded. Longest running:", self.quiet, self.in_flight)?;
+        for file in &self.longest {
+            write!(f, "\n    {:>8.0?} {:>10.2} MB  {}", file.elapsed, file.size as f64 / (1024.0 * 1024.0), anonymize::display(&file.path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Tells from successive snapshots when the pipeline has stopped finishing files
+#[derive(Debug)]
+pub struct Watchdog {
+    threshold: Duration,
+    finished: u64,
+    since: Instant,
+    /// Hangs reported since the last completion; each needs another threshold of quiet
+    reported: u32,
+}
+
+impl Watchdog {
+    pub fn new(threshold: Duration, now: Instant) -> Self {
+        Self { threshold, finished: 0, since: now, reported: 0 }
+    }
+
+    /// Look at `snapshot`, taken at `now`. Returns a hang once the threshold passes
+    /// without a file finishing, and again after each further threshold.
+    pub fn check(&mut self, snapshot: &IntrospectionSnapshot, now: Instant) -> Option<Hang> {
+        if snapshot.finished != self.finished || snapshot.in_flight.is_empty() {
+            (self.finished, self.since, self.reported) = (snapshot.finished, now, 0);
+            return None;
+        }
+        let quiet = now.saturating_duration_since(self.since);
+        if quiet < self.threshold.saturating_mul(self.reported + 1) {
+            return None;
+        }
+        self.reported += 1;
+        Some(Hang {
+            quiet,
+            in_flight: snapshot.in_flight.len(),
+            longest: snapshot.in_flight.iter().take(LONGEST_LISTED).cloned().collect(),
+        })
+    }
+}
+
+/// Watch `introspection` until the returned task is aborted, warning about each hang
+pub fn spawn(introspection: Introspection, threshold: Duration) -> tokio::task::JoinHandle<()> {
+    let interval = (threshold / 4).clamp(Duration::from_millis(100), Duration::from_secs(5));
+    tokio::spawn(async move {
+        let mut watchdog = Watchdog::new(threshold, Instant::now());
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Some(hang) = watchdog.check(&introspection.snapshot(), Instant::now()) {
+                warn!("{}", hang);
+            }
+        }
+    })
+}
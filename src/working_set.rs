@@ -0,0 +1,177 @@
+//! Warming what a running process uses (`--process PID`).
+//!
+//! A database restored from a snapshot starts up with its data directory cold, but only
+//! part of it is its working set: the files it has opened and the ranges of them it has
+//! mapped. Warming the whole directory first fetches everything else too. A
+//! [`WorkingSet`] reads `/proc/<pid>/maps` and `/proc/<pid>/fd` of the given processes
+//! once, before warming starts, and keeps the regular files under the target
+//! directories:
+//!
+//! - A mapped file is warmed only in the ranges mapped (merged across mappings and
+//!   processes), as the [`RangeSelector`] of the run.
+//! - A file that's open but not mapped is warmed whole. PostgreSQL and MySQL read their
+//!   data files through descriptors, so for them this is most of the working set.
+//!
+//! It's also the run's [`FileSource`], so nothing else is discovered. With
+//! `--skip-cached`, pages already in the page cache are left out of the ranges too.
+//!
+//! Another user's maps and descriptors can only be read as root (or with
+//! `CAP_SYS_PTRACE`); a process that can't be read fails the run before it starts.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::debug;
+
+use crate::open_files;
+use crate::sources::FileSource;
+use crate::warming::RangeSelector;
+
+/// One file-backed mapping of a `/proc/<pid>/maps` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub path: PathBuf,
+    /// Offset into the file the mapping starts at
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// The file-backed mappings listed in the contents of a `/proc/<pid>/maps` file.
+/// Anonymous memory, `[heap]`-style pseudo paths and deleted files are left out.
+pub fn parse_maps(maps: &str) -> Vec<Mapping> {
+    maps.lines().filter_map(parse_maps_line).collect()
+}
+
+/// `start-end perms offset dev inode path`, where the path may hold spaces
+fn parse_maps_line(line: &str) -> Option<Mapping> {
+    let mut rest = line.trim_start();
+    let mut fields = [""; 5];
+    for field in &mut fields {
+        let (value, tail) = rest.split_once(char::is_whitespace)?;
+        *field = value;
+        rest = tail.trim_start();
+    }
+    let [range, _perms, offset, _dev, inode] = fields;
+    let path = rest.trim_end();
+    if inode == "0" || !path.starts_with('/') || path.ends_with(" (deleted)") {
+        return None;
+    }
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (u64::from_str_radix(start, 16).ok()?, u64::from_str_radix(end, 16).ok()?);
+    Some(Mapping { path: PathBuf::from(path), offset: u64::from_str_radix(offset, 16).ok()?, length: end.checked_sub(start)? })
+}
+
+/// Sorted, merged `(offset, length)` ranges
+fn merge(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (offset, length) in ranges {
+        match merged.last_mut() {
+            Some((last, last_length)) if offset <= *last + *last_length => *last_length = (*last_length).max(offset + length - *last),
+            _ => merged.push((offset, length)),
+        }
+    }
+    merged
+}
+
+/// The files under the targets that the given processes map or hold open
+#[derive(Debug, Clone, Default)]
+pub struct WorkingSet {
+    pids: Vec<u32>,
+    /// Named under the target directory as given; mapped ranges, or `None` to warm the
+    /// whole file
+    files: BTreeMap<PathBuf, Option<Vec<(u64, u64)>>>,
+}
+
+impl WorkingSet {
+    /// Read the maps and descriptors of `pids` from `proc` (normally `/proc`) and keep the
+    /// regular files under `roots`
+    pub fn read(proc: &Path, pids: &[u32], roots: &[PathBuf]) -> Result<WorkingSet> {
+        // Maps and descriptors name files by their canonical path; discovery names them under the root as given
+        let canonical: Vec<(PathBuf, &PathBuf)> = roots.iter().filter_map(|root| Some((root.canonicalize().ok()?, root))).collect();
+        let mut mapped: BTreeMap<PathBuf, Vec<(u64, u64)>> = BTreeMap::new();
+        let mut open: Vec<PathBuf> = Vec::new();
+        for &pid in pids {
+            let dir = proc.join(pid.to_string());
+            let maps = std::fs::read_to_string(dir.join("maps"))
+                .with_context(|| format!("--process: failed to read the mappings of process {} (it must exist and be yours, or run as root)", pid))?;
+            for mapping in parse_maps(&maps) {
+                if let Some(path) = open_files::under_root(&mapping.path, &canonical) {
+                    mapped.entry(path).or_default().push((mapping.offset, mapping.length));
+                }
+            }
+            let fds = std::fs::read_dir(dir.join("fd")).with_context(|| format!("--process: failed to list the open files of process {}", pid))?;
+            for fd in fds.flatten() {
+                // Pipes and sockets read as `pipe:[…]` and the like, deleted files end in " (deleted)"
+                let Ok(target) = std::fs::read_link(fd.path()) else { continue };
+                open.extend(open_files::under_root(&target, &canonical));
+            }
+        }
+
+        let mut files: BTreeMap<PathBuf, Option<Vec<(u64, u64)>>> = mapped.into_iter().map(|(path, ranges)| (path, Some(merge(ranges)))).collect();
+        for path in open {
+            files.entry(path).or_insert(None);
+        }
+        files.retain(|path, _| std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file()));
+        let working_set = WorkingSet { pids: pids.to_vec(), files };
+        debug!("--process: {} files under the targets, {} of them mapped", working_set.len(), working_set.mapped());
+        Ok(working_set)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Files warmed in the ranges mapped rather than whole
+    pub fn mapped(&self) -> usize {
+        self.files.values().filter(|ranges| ranges.is_some()).count()
+    }
+
+    /// Bytes mapped across the mapped files; mappings may reach past the end of a file
+    pub fn mapped_bytes(&self) -> u64 {
+        self.files.values().flatten().flatten().map(|&(_, length)| length).sum()
+    }
+
+    /// The ranges of `path` to warm, or `None` for the whole file
+    pub fn ranges(&self, path: &Path) -> Option<&[(u64, u64)]> {
+        self.files.get(path)?.as_deref()
+    }
+}
+
+impl FileSource for WorkingSet {
+    fn list(&self, roots: &[PathBuf], found: &mut dyn FnMut(PathBuf) -> bool) -> std::io::Result<()> {
+        for path in self.files.keys().filter(|path| roots.iter().any(|root| path.starts_with(root))) {
+            if !found(path.clone()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RangeSelector for WorkingSet {
+    fn select(&self, path: &Path, file_size: u64) -> Result<Option<Vec<(u64, u64)>>, std::io::Error> {
+        let Some(ranges) = self.ranges(path) else { return Ok(None) };
+        Ok(Some(
+            ranges
+                .iter()
+                .filter(|&&(offset, _)| offset < file_size)
+                .map(|&(offset, length)| (offset, length.min(file_size - offset)))
+                .collect(),
+        ))
+    }
+}
+
+impl fmt::Display for WorkingSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pids: Vec<String> = self.pids.iter().map(u32::to_string).collect();
+        let (processes, verbs) = if pids.len() == 1 { ("process", "maps or holds") } else { ("processes", "map or hold") };
+        write!(f, "the {} files {} {} {} open", self.files.len(), processes, pids.join(", "), verbs)
+    }
+}
@@ -0,0 +1,70 @@
+//! `--anonymize-paths` replaces file names in output and keeps the originals in a
+//! mapping file that reuses its salt between runs.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_cache_warmer::anonymize::{self, AnonymizeMode, PathAnonymizer};
+
+use common::scratch;
+
+#[test]
+fn hashes_are_stable_and_mapped_back() {
+    let dir = scratch("hash");
+    let map = dir.join("paths.tsv");
+    let roots = vec![PathBuf::from("/data")];
+
+    let anonymizer = PathAnonymizer::open(AnonymizeMode::Hash, &roots, &map).unwrap();
+    let smith = anonymizer.anonymize(Path::new("/data/patients/smith.pdf"));
+    let jones = anonymizer.anonymize(Path::new("/data/patients/jones.pdf"));
+    assert!(!smith.contains("patients") && !smith.contains("smith"), "{}", smith);
+    let (smith_dir, smith_name) = smith.rsplit_once('/').unwrap();
+    let (jones_dir, jones_name) = jones.rsplit_once('/').unwrap();
+    assert_eq!(smith_dir, jones_dir);
+    assert_ne!(smith_name, jones_name);
+    assert!(smith.starts_with('/'));
+    assert_eq!(smith.split('/').count(), 4);
+    anonymizer.flush().unwrap();
+    drop(anonymizer);
+
+    let contents = fs::read_to_string(&map).unwrap();
+    assert!(contents.lines().any(|line| line == format!("{}\t/data/patients/smith.pdf", smith)), "{}", contents);
+
+    // A second run sharing the map keeps the salt, and so the hashes
+    let again = PathAnonymizer::open(AnonymizeMode::Hash, &roots, &map).unwrap();
+    assert_eq!(again.anonymize(Path::new("/data/patients/smith.pdf")), smith);
+    let other = PathAnonymizer::open(AnonymizeMode::Hash, &roots, &dir.join("other.tsv")).unwrap();
+    assert_ne!(other.anonymize(Path::new("/data/patients/smith.pdf")), smith);
+}
+
+#[test]
+fn strip_prefix_replaces_the_target_directory() {
+    let dir = scratch("strip");
+    let map = dir.join("paths.tsv");
+    let roots = vec![PathBuf::from("/srv/tenant-a"), PathBuf::from("/srv/tenant-b")];
+
+    let anonymizer = PathAnonymizer::open(AnonymizeMode::StripPrefix, &roots, &map).unwrap();
+    assert_eq!(anonymizer.anonymize(Path::new("/srv/tenant-b/logs/app.log")), "<1>/logs/app.log");
+    assert_eq!(anonymizer.anonymize(Path::new("/srv/tenant-a")), "<0>");
+    let outside = anonymizer.anonymize(Path::new("/home/alice/notes.txt"));
+    assert!(!outside.contains("alice") && !outside.contains("notes"), "{}", outside);
+    anonymizer.flush().unwrap();
+
+    let contents = fs::read_to_string(&map).unwrap();
+    assert!(contents.contains("<0>\t/srv/tenant-a\n"), "{}", contents);
+    assert!(contents.contains("<1>\t/srv/tenant-b\n"), "{}", contents);
+}
+
+#[test]
+fn display_follows_the_installed_anonymizer() {
+    let dir = scratch("display");
+    let path = Path::new("/srv/tenant-a/secret.db");
+    assert_eq!(anonymize::display(path).to_string(), "/srv/tenant-a/secret.db");
+
+    let anonymizer = PathAnonymizer::open(AnonymizeMode::StripPrefix, &[PathBuf::from("/srv/tenant-a")], &dir.join("paths.tsv")).unwrap();
+    assert!(anonymize::install(anonymizer));
+    assert_eq!(anonymize::display(path).to_string(), "<0>/secret.db");
+    assert_eq!(anonymize::global().unwrap().mode(), AnonymizeMode::StripPrefix);
+}
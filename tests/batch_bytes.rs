@@ -0,0 +1,91 @@
+//! `--batch-bytes`: batches close at a byte budget as well as at `--batch-size` files,
+//! and a file larger than the budget gets a batch of its own.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+const MIB: usize = 1024 * 1024;
+
+/// Eight 1 MiB files, then (with `large`) one of 10 MiB, in walk order
+fn tree(test: &str, large: bool) -> PathBuf {
+    let root = common::scratch(test);
+    for i in 0..8 {
+        fs::write(root.join(format!("a{}", i)), vec![1u8; MIB]).unwrap();
+    }
+    if large {
+        fs::write(root.join("b"), vec![2u8; 10 * MIB]).unwrap();
+    }
+    root
+}
+
+/// Batches warmed, walking on one thread so that a single visitor fills them
+async fn batches(root: &Path, batch_size: usize, batch_bytes: u64, physical_order: bool) -> u64 {
+    let directories = vec![root.to_path_buf()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size,
+        batch_bytes,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: true,
+    });
+    let summary = pipeline::run(options, PipelineContext::default()).await;
+    assert_eq!(summary.files_processed, summary.files_discovered);
+    summary.shares[0].batches
+}
+
+#[tokio::test]
+async fn batches_close_at_the_byte_budget() {
+    let root = tree("budget", false);
+    assert_eq!(batches(&root, 1000, 4 * MIB as u64, false).await, 2);
+    assert_eq!(batches(&root, 1000, 3 * MIB as u64, false).await, 3);
+    // The file count still caps a batch
+    assert_eq!(batches(&root, 3, 64 * MIB as u64, false).await, 3);
+    // No budget: by count alone
+    assert_eq!(batches(&root, 1000, 0, false).await, 1);
+}
+
+#[tokio::test]
+async fn a_file_over_the_budget_is_batched_alone() {
+    let root = tree("large", true);
+    assert_eq!(batches(&root, 1000, 4 * MIB as u64, false).await, 3);
+    // 5 MiB of small files, then the large one on its own rather than joining them
+    assert_eq!(batches(&root, 1000, 5 * MIB as u64, false).await, 3);
+    assert_eq!(batches(&root, 1000, 64 * MIB as u64, false).await, 1);
+}
+
+#[tokio::test]
+async fn physical_order_windows_are_split_by_bytes() {
+    // Equal sizes, so the count doesn't depend on where the files lie on disk
+    let root = tree("physical", false);
+    assert_eq!(batches(&root, 1000, 4 * MIB as u64, true).await, 2);
+    assert_eq!(batches(&root, 1000, 0, true).await, 1);
+}
@@ -0,0 +1,156 @@
+//! `bench`: the sample is dealt out so no two strategies read the same file, strategies
+//! that can't run are listed as skipped, and the fastest strategy that read every file
+//! is recommended.
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::bench::{self, BenchOptions};
+use rust_cache_warmer::warming::{Coverage, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+/// Takes `delay` per file, records the files it was given, and can be made unavailable,
+/// advisory or failing
+struct Fake {
+    strategy: Strategy,
+    delay: Duration,
+    available: bool,
+    advisory: bool,
+    fail: bool,
+    seen: Mutex<Vec<PathBuf>>,
+}
+
+impl Fake {
+    fn new(strategy: Strategy, delay_ms: u64) -> Self {
+        Self { strategy, delay: Duration::from_millis(delay_ms), available: true, advisory: false, fail: false, seen: Mutex::new(Vec::new()) }
+    }
+}
+
+impl WarmingBackend for Fake {
+    fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    fn probe(&self) -> bool {
+        self.available
+    }
+
+    fn supports(&self, use_direct_io: bool) -> bool {
+        !use_direct_io
+    }
+
+    fn is_advisory(&self) -> bool {
+        self.advisory
+    }
+
+    fn warm<'a>(&'a self, path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            self.seen.lock().unwrap().push(path.to_path_buf());
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(WarmingError::Other(std::io::Error::other("unreadable")));
+            }
+            let coverage = if self.advisory { Coverage::Advisory } else { Coverage::Full };
+            Ok(WarmingResult { method: self.strategy.name(), success: true, duration: self.delay, bytes_read: file_size, coverage })
+        })
+    }
+}
+
+fn tree(test: &str, files: usize) -> PathBuf {
+    let dir = common::scratch(test);
+    for i in 0..files {
+        fs::write(dir.join(format!("file{}", i)), vec![1u8; 1000 * (i + 1)]).unwrap();
+    }
+    // Empty files aren't worth timing
+    fs::write(dir.join("empty"), b"").unwrap();
+    dir
+}
+
+fn options(dir: &Path, files: usize) -> BenchOptions {
+    BenchOptions {
+        directories: vec![dir.to_path_buf()],
+        strategies: Vec::new(),
+        files,
+        queue_depth: 2,
+        synthetic_size: 0,
+        warming: WarmingOptions::default(),
+        seed: 7,
+    }
+}
+
+#[test]
+fn dealing_gives_each_hand_a_similar_mix_of_sizes() {
+    let hands = bench::deal((1..=8).collect::<Vec<u64>>(), 2);
+    assert_eq!(hands, vec![vec![1, 4, 5, 8], vec![2, 3, 6, 7]]);
+    assert_eq!(hands[0].iter().sum::<u64>(), hands[1].iter().sum::<u64>());
+}
+
+#[test]
+fn samples_are_reproducible_and_skip_empty_files() {
+    let dir = tree("sample", 20);
+    let sample = bench::sample_files(std::slice::from_ref(&dir), 8, 42);
+    assert_eq!(sample.len(), 8);
+    assert!(sample.iter().all(|(_, size)| *size > 0));
+    assert!(sample.windows(2).all(|pair| pair[0].1 <= pair[1].1), "smallest first");
+    assert_eq!(sample, bench::sample_files(&[dir], 8, 42));
+}
+
+#[tokio::test]
+async fn each_strategy_reads_its_own_files_and_the_fastest_complete_one_is_recommended() {
+    let dir = tree("run", 20);
+    let fast = Arc::new(Fake::new(Strategy::Tokio, 1));
+    let slow = Arc::new(Fake::new(Strategy::Mmap, 20));
+    let advisory = Arc::new(Fake { advisory: true, ..Fake::new(Strategy::Fadvise, 0) });
+    let failing = Arc::new(Fake { fail: true, ..Fake::new(Strategy::Readahead, 0) });
+    let unavailable = Arc::new(Fake { available: false, ..Fake::new(Strategy::Uring, 0) });
+    let mut registry = StrategyRegistry::new();
+    for backend in [&fast, &slow, &advisory, &failing, &unavailable] {
+        registry.register(Arc::clone(backend) as Arc<dyn WarmingBackend>);
+    }
+
+    let report = bench::run(&options(&dir, 4), &registry).await.unwrap();
+    assert_eq!(report.results.len(), 4);
+    assert!(report.results.iter().all(|result| result.files == 4));
+    assert_eq!(
+        report.skipped,
+        vec![(Strategy::Uring, "unavailable on this host"), (Strategy::Libaio, "not registered"), (Strategy::Sendfile, "not registered")]
+    );
+    assert_eq!(report.recommendation(), Some(Strategy::Tokio), "{}", report);
+
+    let mut union = BTreeSet::new();
+    for backend in [&fast, &slow, &advisory, &failing] {
+        let seen: BTreeSet<PathBuf> = backend.seen.lock().unwrap().iter().cloned().collect();
+        assert_eq!(seen.len(), 4);
+        assert!(union.is_disjoint(&seen), "{} read a file another strategy read", backend.strategy);
+        union.extend(seen);
+    }
+    assert!(unavailable.seen.lock().unwrap().is_empty());
+
+    let text = report.to_string();
+    assert!(text.contains("Recommended: --strategy tokio"), "{}", text);
+    assert!(text.contains("advice only"), "{}", text);
+}
+
+#[tokio::test]
+async fn a_synthetic_file_is_read_by_every_strategy_and_removed() {
+    let dir = tree("synthetic", 0);
+    let tokio = Arc::new(Fake::new(Strategy::Tokio, 0));
+    let mmap = Arc::new(Fake::new(Strategy::Mmap, 0));
+    let mut registry = StrategyRegistry::new();
+    registry.register(Arc::clone(&tokio) as Arc<dyn WarmingBackend>).register(Arc::clone(&mmap) as Arc<dyn WarmingBackend>);
+
+    let options = BenchOptions { strategies: vec![Strategy::Tokio, Strategy::Mmap], synthetic_size: 3 * 1024 * 1024 + 5, ..options(&dir, 3) };
+    let report = bench::run(&options, &registry).await.unwrap();
+    assert_eq!(report.queue_depth, 1, "reads of the one file don't overlap");
+    for result in &report.results {
+        assert_eq!((result.files, result.bytes), (3, 3 * (3 * 1024 * 1024 + 5)));
+    }
+    let synthetic = dir.join(bench::SYNTHETIC_FILE_NAME);
+    assert!(tokio.seen.lock().unwrap().iter().all(|path| *path == synthetic));
+    assert!(!synthetic.exists());
+}
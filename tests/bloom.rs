@@ -0,0 +1,34 @@
+//! The watch-mode dedupe filter: nothing inserted is ever reported missing, false
+//! positives stay near the rate the filter was sized for, and rotation bounds memory
+//! while keeping the most recent insertions.
+
+use rust_cache_warmer::bloom::{BloomFilter, RecentSet};
+
+#[test]
+fn a_filter_has_no_false_negatives_and_few_false_positives() {
+    let mut filter = BloomFilter::new(10_000, 0.01);
+    for i in 0..10_000u64 {
+        filter.insert(&i);
+    }
+    assert!(filter.is_full());
+    assert!((0..10_000u64).all(|i| filter.contains(&i)));
+
+    let false_positives = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+    assert!(false_positives < 2_000, "{} false positives in 100000", false_positives);
+    // About 9.6 bits per item at 1%
+    assert!(filter.memory_bytes() < 10_000 * 10 / 8 + 8, "{} bytes", filter.memory_bytes());
+}
+
+#[test]
+fn rotation_keeps_the_latest_generation_and_forgets_older_ones() {
+    let mut set = RecentSet::new(1_000, 0.001);
+    let memory = set.memory_bytes();
+    for i in 0..5_000u64 {
+        set.insert(&("path", i));
+    }
+    assert_eq!(set.rotations(), 4);
+    assert_eq!(set.memory_bytes(), memory, "rotation doesn't grow the filters");
+    assert!((4_000..5_000u64).all(|i| set.contains(&("path", i))), "the last capacity's worth is remembered");
+    let remembered = (0..3_000u64).filter(|i| set.contains(&("path", *i))).count();
+    assert!(remembered < 30, "{} of 3000 old generations still reported", remembered);
+}
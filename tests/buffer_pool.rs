@@ -0,0 +1,85 @@
+//! The direct-I/O buffer pool: returned buffers are reused, checked-out memory stays
+//! under the ceiling with callers waiting their turn, and the shared ring queues reads
+//! while the pool is exhausted instead of failing them.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::join_all;
+use rust_cache_warmer::warming::buffers::{BufferPool, ALIGNMENT};
+
+#[test]
+fn returned_buffers_are_reused() {
+    let pool = BufferPool::new(0);
+    for _ in 0..10 {
+        let mut buffer = pool.get_blocking(1000).unwrap();
+        assert_eq!(buffer.len(), 1000);
+        assert_eq!(buffer.as_mut_ptr() as usize % ALIGNMENT, 0);
+    }
+    let stats = pool.stats();
+    assert_eq!((stats.in_use, stats.idle, stats.peak), (0, ALIGNMENT, ALIGNMENT), "one buffer served every read");
+}
+
+#[test]
+fn checked_out_memory_stays_under_the_ceiling() {
+    let pool = BufferPool::new(3 * ALIGNMENT);
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let pool = Arc::clone(&pool);
+            std::thread::spawn(move || {
+                let _buffer = pool.get_blocking(ALIGNMENT).unwrap();
+                std::thread::sleep(Duration::from_millis(20));
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let stats = pool.stats();
+    assert_eq!(stats.in_use, 0);
+    assert!(stats.peak <= 3 * ALIGNMENT, "peak {}", stats.peak);
+    assert!(stats.waits > 0);
+
+    // Nothing can go out while the pool is full, except a read bigger than the whole ceiling
+    let held: Vec<_> = (0..3).map(|_| pool.try_get(ALIGNMENT).unwrap().unwrap()).collect();
+    assert!(pool.try_get(ALIGNMENT).unwrap().is_none());
+    drop(held);
+    assert!(pool.try_get(10 * ALIGNMENT).unwrap().is_some(), "an oversized read goes alone");
+}
+
+#[tokio::test]
+async fn async_callers_wake_when_a_buffer_comes_back() {
+    let pool = BufferPool::new(ALIGNMENT);
+    let held = pool.get(ALIGNMENT).await.unwrap();
+    let waiter = {
+        let pool = Arc::clone(&pool);
+        tokio::spawn(async move { pool.get(ALIGNMENT).await.map(|buffer| buffer.len()) })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiter.is_finished());
+    drop(held);
+    assert_eq!(tokio::time::timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap().unwrap(), ALIGNMENT);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn the_ring_waits_for_buffers_instead_of_failing() {
+    use rust_cache_warmer::warming::ring::SharedRing;
+
+    let Ok(ring) = SharedRing::global() else {
+        eprintln!("skipping: io_uring is unavailable here");
+        return;
+    };
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("buffer_pool_ring");
+    fs::write(&path, vec![7u8; 64 * ALIGNMENT]).unwrap();
+    let file = Arc::new(fs::File::open(&path).unwrap());
+
+    let pool = BufferPool::global();
+    pool.set_limit(2 * ALIGNMENT);
+    let reads = (0..64).map(|block| ring.read(Arc::clone(&file), (block * ALIGNMENT) as u64, ALIGNMENT));
+    let results = tokio::time::timeout(Duration::from_secs(10), join_all(reads)).await.unwrap();
+    assert!(results.iter().all(|result| matches!(result, Ok(n) if *n == ALIGNMENT)));
+    assert!(pool.stats().peak <= 2 * ALIGNMENT, "peak {}", pool.stats().peak);
+}
@@ -0,0 +1,85 @@
+//! Bytes accounting: the size of the files warmed, the logical data their warms covered,
+//! and the bytes actually read from storage are counted apart.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{Coverage, FallbackPolicy, Strategy, WarmingOptions};
+
+const MB: u64 = 1 << 20;
+
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    fs::write(root.join("small"), vec![1u8; 4096]).unwrap();
+    fs::write(root.join("large"), vec![1u8; 4 * MB as usize]).unwrap();
+    root
+}
+
+#[test]
+fn coverage_decides_the_bytes_covered() {
+    assert_eq!(Coverage::Full.bytes_covered(MB, 4096), MB, "cached pages and holes are covered without a read");
+    assert_eq!(Coverage::Sampled.bytes_covered(MB, 4096), 4096);
+    assert_eq!(Coverage::Partial.bytes_covered(MB, 2 * MB), MB);
+    assert_eq!(Coverage::Advisory.bytes_covered(MB, 0), 0);
+}
+
+#[tokio::test]
+async fn sampled_files_cover_less_than_their_size() {
+    let root = tree("pipeline");
+    let directories = vec![root.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: None,
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, sparse_large_files: MB, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let summary = pipeline::run(options, PipelineContext::default()).await;
+    assert_eq!(summary.bytes_warmed, 4 * MB + 4096);
+    // The small file in full, a sample of the large one
+    assert!(summary.bytes_covered > 4096 && summary.bytes_covered < MB, "{}", summary.bytes_covered);
+    assert_eq!(summary.bytes_read, summary.bytes_covered);
+    assert_eq!((summary.stats.totals.bytes_covered, summary.stats.totals.bytes_read), (summary.bytes_covered, summary.bytes_read));
+}
+
+#[test]
+fn the_summary_shows_data_covered_and_bytes_read() {
+    let root = tree("cli");
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+        .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--strategy", "tokio", "--no-auto-tune"])
+        .arg(&root)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Logical data covered: 4.00 MiB of 4.00 MiB (100.0%); physical bytes read: 4.00 MiB"), "{}", stderr);
+}
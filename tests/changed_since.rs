@@ -0,0 +1,126 @@
+//! `--changed-since`: timestamps, result log manifests, and discovery that leaves out
+//! unchanged files.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rust_cache_warmer::changed::{self, ChangeFilter, ChangedSince, Manifest};
+
+use common::scratch;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn timestamps_parse() {
+    assert_eq!(changed::parse_timestamp("1760666400"), Some(at(1_760_666_400)));
+    assert_eq!(changed::parse_timestamp("@1760666400"), Some(at(1_760_666_400)));
+    assert_eq!(changed::parse_timestamp("2025-10-17T02:00:00Z"), Some(at(1_760_666_400)));
+    assert_eq!(changed::parse_timestamp("2025-10-17 02:00"), Some(at(1_760_666_400)));
+    assert_eq!(changed::parse_timestamp("2025-10-17T04:00:00+02:00"), Some(at(1_760_666_400)));
+    assert_eq!(changed::parse_timestamp("2025-10-16T21:00:00-05:00"), Some(at(1_760_666_400)));
+    assert_eq!(changed::parse_timestamp("2024-02-29"), Some(at(1_709_164_800)));
+    assert_eq!(changed::parse_timestamp("2025-02-29"), None);
+    assert_eq!(changed::parse_timestamp("2025-10-17T25:00"), None);
+
+    assert_eq!("2025-10-17".parse(), Ok(ChangedSince::Time(at(1_760_659_200))));
+    assert_eq!("/var/log/warm.ndjson".parse(), Ok(ChangedSince::Manifest("/var/log/warm.ndjson".into())));
+    assert!("".parse::<ChangedSince>().is_err());
+}
+
+#[test]
+fn manifests_are_read_from_ndjson_and_csv_logs() {
+    let dir = scratch("manifest");
+    let ndjson = dir.join("run.ndjson");
+    fs::write(
+        &ndjson,
+        concat!(
+            r#"{"path":"/data/a","size":10,"status":"warmed","strategy":"readahead_full","duration_us":5,"bytes_read":10,"coverage":"full","error":null}"#,
+            "\n",
+            r#"{"path":"/data/b","size":20,"status":"failed","strategy":null,"duration_us":5,"bytes_read":0,"coverage":null,"error":"EIO"}"#,
+            "\nnot json\n"
+        ),
+    )
+    .unwrap();
+    let manifest = Manifest::load(&ndjson).unwrap();
+    assert_eq!(manifest.len(), 2);
+    assert_eq!(manifest.warmed(Path::new("/data/a"), 10), Some(true));
+    assert_eq!(manifest.warmed(Path::new("/data/a"), 11), Some(false), "the size changed");
+    assert_eq!(manifest.warmed(Path::new("/data/b"), 20), Some(false), "it failed");
+    assert_eq!(manifest.warmed(Path::new("/data/c"), 1), None);
+
+    let csv = dir.join("run.csv");
+    fs::write(&csv, "path,size,status,strategy,duration_us,bytes_read,coverage,error\n\"/data/x,\"\"y\"\"\",7,warmed,tokio,1,7,full,\n/data/z,3,skipped,,0,0,,\n").unwrap();
+    let manifest = Manifest::load(&csv).unwrap();
+    assert_eq!(manifest.warmed(Path::new("/data/x,\"y\""), 7), Some(true));
+    assert_eq!(manifest.warmed(Path::new("/data/z"), 3), Some(false));
+}
+
+#[test]
+fn unchanged_files_pass_only_when_the_manifest_agrees() {
+    let dir = scratch("filter");
+    let (kept, failed, missing) = (dir.join("kept"), dir.join("failed"), dir.join("missing"));
+    for path in [&kept, &failed, &missing] {
+        fs::write(path, b"12345").unwrap();
+    }
+    let metadata = |path: &Path| fs::metadata(path).unwrap();
+
+    let past = ChangeFilter::since(SystemTime::now() - Duration::from_secs(3600));
+    assert!(past.changed(&kept, &metadata(&kept)));
+    let future = ChangeFilter::since(SystemTime::now() + Duration::from_secs(3600));
+    assert!(!future.changed(&kept, &metadata(&kept)));
+    assert_eq!(future.unchanged(), 1);
+
+    let log = dir.join("log.ndjson");
+    let line = |path: &Path, status: &str| format!("{{\"path\":{:?},\"size\":5,\"status\":\"{}\"}}\n", path.to_str().unwrap(), status);
+    fs::write(&log, line(&kept, "warmed") + &line(&failed, "failed")).unwrap();
+    let filter = ChangeFilter::against(SystemTime::now() + Duration::from_secs(3600), Manifest::load(&log).unwrap());
+    assert!(!filter.changed(&kept, &metadata(&kept)));
+    assert!(filter.changed(&failed, &metadata(&failed)), "failed last time");
+    assert!(!filter.changed(&missing, &metadata(&missing)), "unchanged since before the earlier run");
+}
+
+#[test]
+fn a_second_run_warms_only_what_changed_after_the_first() {
+    let dir = scratch("cli");
+    let data = dir.join("data");
+    fs::create_dir_all(data.join("sub")).unwrap();
+    for name in ["a", "b", "sub/c"] {
+        fs::write(data.join(name), vec![1u8; 8192]).unwrap();
+    }
+    let warm = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+            .args(args)
+            .arg(&data)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output
+    };
+    let (first, second) = (dir.join("first.ndjson"), dir.join("second.ndjson"));
+    std::thread::sleep(Duration::from_millis(20));
+    warm(&["--result-log", first.to_str().unwrap()]);
+    assert_eq!(fs::read_to_string(&first).unwrap().lines().count(), 3);
+
+    std::thread::sleep(Duration::from_millis(20));
+    fs::write(data.join("sub/new"), b"fresh").unwrap();
+    let output = warm(&["--changed-since", first.to_str().unwrap(), "--result-log", second.to_str().unwrap()]);
+    let log = fs::read_to_string(&second).unwrap();
+    assert_eq!(log.lines().count(), 1, "{}", log);
+    assert!(log.contains("sub/new"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Left out 3 unchanged files"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+        .args(["--changed-since", dir.join("nope.ndjson").to_str().unwrap()])
+        .arg(&data)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a timestamp"));
+}
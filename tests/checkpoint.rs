@@ -0,0 +1,69 @@
+//! Resuming from a checkpoint: journaled files aren't warmed again, their earlier
+//! outcomes still count towards the summary, and a record cut short by a crash is
+//! ignored rather than corrupting the journal.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rust_cache_warmer::checkpoint::Checkpoint;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+
+fn tree(test: &str, files: usize) -> PathBuf {
+    let dir = common::scratch(test);
+    fs::create_dir_all(dir.join("data")).unwrap();
+    for i in 0..files {
+        fs::write(dir.join("data").join(format!("{}.bin", i)), vec![7u8; 1000]).unwrap();
+    }
+    dir
+}
+
+fn options(root: &Path) -> Arc<PipelineOptions> {
+    Arc::new(PipelineOptions { batch_size: 8, ..common::pipeline_options(vec![root.join("data")]) })
+}
+
+async fn run_with(root: &Path, journal: &Path) -> pipeline::PipelineSummary {
+    let context = PipelineContext {
+        checkpoint: Some(Arc::new(Checkpoint::open(journal).unwrap())),
+        ..Default::default()
+    };
+    pipeline::run(options(root), context).await
+}
+
+#[tokio::test]
+async fn resumed_runs_skip_journaled_files_and_merge_their_results() {
+    let root = tree("resume", 20);
+    let journal = root.join("journal");
+
+    let first = run_with(&root, &journal).await;
+    assert_eq!(first.files_processed, 20);
+    assert_eq!(first.bytes_warmed, 20_000);
+    assert_eq!(fs::read_to_string(&journal).unwrap().lines().count(), 20);
+
+    // Everything is already journaled: nothing new is recorded, but the totals carry over
+    let second = run_with(&root, &journal).await;
+    assert_eq!(second.files_processed, 20);
+    assert_eq!(second.bytes_warmed, 20_000);
+    assert_eq!(fs::read_to_string(&journal).unwrap().lines().count(), 20);
+}
+
+#[tokio::test]
+async fn truncated_records_are_rewarmed() {
+    let root = tree("truncated", 5);
+    let journal = root.join("journal");
+    run_with(&root, &journal).await;
+
+    // Simulate a kill in the middle of writing the last record
+    let contents = fs::read_to_string(&journal).unwrap();
+    let cut = contents.trim_end().len() - 10;
+    fs::write(&journal, &contents[..cut]).unwrap();
+    assert_eq!(Checkpoint::open(&journal).unwrap().completed_files(), 4);
+
+    let resumed = run_with(&root, &journal).await;
+    assert_eq!(resumed.files_processed, 5);
+    let lines: Vec<String> = fs::read_to_string(&journal).unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines.len(), 6, "the re-warmed file starts a new line after the truncated one");
+    assert_eq!(Checkpoint::open(&journal).unwrap().completed_files(), 5);
+}
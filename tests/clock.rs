@@ -0,0 +1,61 @@
+//! Pause-aware time: gaps in the monitor's wakeups count as pauses, and stopwatches and
+//! throughput leave them out.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use rust_cache_warmer::clock::{self, PauseClock, Stopwatch, PAUSE_THRESHOLD, TICK};
+use rust_cache_warmer::events::WarmEvent;
+use rust_cache_warmer::live::LiveStats;
+use rust_cache_warmer::stats::FileStatus;
+
+#[test]
+fn only_long_gaps_are_pauses() {
+    assert_eq!(clock::pause_in(TICK, TICK), None);
+    assert_eq!(clock::pause_in(TICK + PAUSE_THRESHOLD, TICK), None, "a slow wakeup is scheduling noise");
+    assert_eq!(clock::pause_in(TICK + Duration::from_secs(60), TICK), Some(Duration::from_secs(60)));
+}
+
+#[test]
+fn stopwatches_subtract_pauses_in_their_interval() {
+    let clock: &'static PauseClock = Box::leak(Box::new(PauseClock::new()));
+    clock.add_pause(Duration::from_secs(30));
+    let stopwatch = Stopwatch::start_on(clock);
+    assert_eq!(stopwatch.paused(), Duration::ZERO, "earlier pauses don't count");
+
+    std::thread::sleep(Duration::from_millis(20));
+    clock.add_pause(Duration::from_millis(15));
+    assert_eq!(stopwatch.paused(), Duration::from_millis(15));
+    assert_eq!(clock.pauses(), 2);
+    assert!(stopwatch.active() + Duration::from_millis(15) <= stopwatch.wall() + Duration::from_millis(1));
+    assert!(stopwatch.active() >= Duration::from_millis(5));
+
+    // A pause longer than the measured interval can't make it negative
+    clock.add_pause(Duration::from_secs(3600));
+    assert_eq!(stopwatch.active(), Duration::ZERO);
+}
+
+// The only test in this binary that touches the global clock
+#[test]
+fn a_pause_is_not_a_stall_in_throughput() {
+    let mut stats = LiveStats::new();
+    let start = Instant::now();
+    let mb = 1024 * 1024;
+    stats.record(&WarmEvent::FileFinished {
+        path: PathBuf::from("/data/file"),
+        bytes: 100 * mb,
+        latency: Duration::from_millis(1),
+        status: FileStatus::Warmed,
+        method: Some("tokio_full"),
+        bytes_read: 100 * mb,
+        error: None,
+        coverage: None,
+    });
+    // The sample a minute later spans a 59s pause, so only one second of it was running
+    PauseClock::global().add_pause(Duration::from_secs(59));
+    stats.sample(start + Duration::from_secs(60));
+    let snapshot = stats.snapshot();
+    assert!(snapshot.throughput_ewma > 90.0 * mb as f64, "{}", snapshot.throughput_ewma);
+    assert_eq!(snapshot.paused, Duration::from_secs(59));
+    assert!(snapshot.to_string().contains("paused"), "{}", snapshot);
+}
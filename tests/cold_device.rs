@@ -0,0 +1,240 @@
+//! Strategies against the simulated cold device of the `testing` feature: each must read
+//! the blocks its contract says, and cold blocks cost the device's latency once.
+
+#![cfg(target_os = "linux")]
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::testing::{ColdDevice, DEFAULT_BLOCK_SIZE};
+use rust_cache_warmer::warming::{Coverage, DropCaches, FallbackPolicy, RangeSelector, Strategy, WarmingOptions};
+
+const KB: u64 = 1024;
+const MB: u64 = 1024 * KB;
+
+/// Strategies that read data through the page cache
+const READERS: [Strategy; 6] = [Strategy::Tokio, Strategy::Readahead, Strategy::Mmap, Strategy::Sendfile, Strategy::Uring, Strategy::Libaio];
+
+fn device(test: &str, block_size: u64, latency: Duration) -> Arc<ColdDevice> {
+    ColdDevice::new(Path::new(env!("CARGO_TARGET_TMPDIR")).join("cold_device").join(test), block_size, latency).unwrap()
+}
+
+/// A cold file on `device`, or None where the page cache can't be emptied
+fn cold_file(device: &ColdDevice, name: &str, size: u64) -> Option<PathBuf> {
+    match device.create(name, size) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            eprintln!("skipping: {}", e);
+            None
+        }
+    }
+}
+
+fn options(strategy: Strategy) -> WarmingOptions {
+    WarmingOptions { strategy, fallback: FallbackPolicy::None, small_file_size: 0, mmap_touch_stride: 1, ..Default::default() }
+}
+
+/// Selects the same ranges of every file, like a snapshot or `--process` selector
+#[derive(Debug)]
+struct Fixed(Vec<(u64, u64)>);
+
+impl RangeSelector for Fixed {
+    fn select(&self, _path: &Path, _file_size: u64) -> Result<Option<Vec<(u64, u64)>>, std::io::Error> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+#[tokio::test]
+async fn full_reads_touch_every_block() {
+    let device = device("full", DEFAULT_BLOCK_SIZE, Duration::ZERO);
+    let registry = device.registry();
+    for strategy in READERS.into_iter().filter(|&strategy| registry.is_available(strategy)) {
+        let Some(path) = cold_file(&device, strategy.name(), 512 * KB + 100) else { return };
+        let result = registry.warm(&path, 512 * KB + 100, &options(strategy)).await.unwrap();
+        assert!(result.success, "{}", strategy);
+
+        let all: BTreeSet<u64> = (0..device.blocks_of(512 * KB + 100)).collect();
+        assert_eq!(device.hydrated(&path), all, "{} left blocks cold", strategy);
+        assert_eq!(device.cold_blocks(&path), 0);
+        assert_eq!(device.accesses(&path).len(), 1);
+        // readahead(2) only queues the I/O, and pages still under read can't be dropped
+        if strategy != Strategy::Readahead {
+            assert!(device.resident_blocks(&path).unwrap().is_empty(), "{}: --drop-caches-after file still applies", strategy);
+        }
+    }
+}
+
+#[tokio::test]
+async fn sparse_reads_touch_the_sample_offsets() {
+    let device = device("sparse", DEFAULT_BLOCK_SIZE, Duration::ZERO);
+    let registry = device.registry();
+    let size = 4 * MB;
+    let interval = 256 * KB;
+    let expected: BTreeSet<u64> = (0..size).step_by(interval as usize).map(|offset| offset / DEFAULT_BLOCK_SIZE).collect();
+    for strategy in READERS.into_iter().filter(|&strategy| registry.is_available(strategy)) {
+        let Some(path) = cold_file(&device, strategy.name(), size) else { return };
+        let options = WarmingOptions { sparse_large_files: MB, sparse_interval: interval, ..options(strategy) };
+        let result = registry.warm(&path, size, &options).await.unwrap();
+        assert!(result.success && result.method.contains("sparse"), "{}: {}", strategy, result.method);
+
+        let touched = device.hydrated(&path);
+        let missed: Vec<&u64> = expected.difference(&touched).collect();
+        assert!(missed.is_empty(), "{} skipped sample blocks {:?}", strategy, missed);
+        // MADV_WILLNEED lets the kernel read ahead across the whole window
+        if strategy != Strategy::Mmap {
+            assert!(touched.len() < device.blocks_of(size) as usize / 4, "{} read {} of {} blocks", strategy, touched.len(), device.blocks_of(size));
+        }
+    }
+}
+
+#[tokio::test]
+async fn selected_ranges_are_read_by_the_strategy() {
+    let device = device("ranges", DEFAULT_BLOCK_SIZE, Duration::ZERO);
+    let registry = device.registry();
+    let size = 4 * MB;
+    // The second range straddles a block boundary
+    let selector: Arc<dyn RangeSelector> = Arc::new(Fixed(vec![(MB, 8 * KB), (3 * MB + 100, 4 * KB)]));
+    let selected: BTreeSet<u64> = [256, 257, 768, 769].into();
+    for strategy in READERS.into_iter().filter(|&strategy| registry.is_available(strategy)) {
+        let Some(path) = cold_file(&device, strategy.name(), size) else { return };
+        let options = WarmingOptions { range_selector: Some(selector.clone()), ..options(strategy) };
+        let result = registry.warm(&path, size, &options).await.unwrap();
+        assert_eq!((result.success, result.coverage), (true, Coverage::Full), "{}", strategy);
+        assert_eq!(device.accesses(&path)[0].strategy, strategy, "{} wasn't the strategy that ran", strategy);
+
+        let touched = device.hydrated(&path);
+        let missed: Vec<&u64> = selected.difference(&touched).collect();
+        assert!(missed.is_empty(), "{} skipped selected blocks {:?}", strategy, missed);
+        // MADV_WILLNEED lets the kernel read ahead across the whole window
+        if strategy != Strategy::Mmap {
+            assert!(touched.len() < device.blocks_of(size) as usize / 4, "{} read {} of {} blocks", strategy, touched.len(), device.blocks_of(size));
+        }
+    }
+
+    // Direct I/O reads the selected blocks, whole, past the page cache
+    for strategy in [Strategy::Tokio, Strategy::Uring, Strategy::Libaio].into_iter().filter(|&strategy| registry.is_available(strategy)) {
+        let Some(path) = cold_file(&device, &format!("{}_direct", strategy.name()), size) else { return };
+        let options = WarmingOptions { range_selector: Some(selector.clone()), use_direct_io: true, drop_caches: DropCaches::None, ..options(strategy) };
+        let Ok(result) = registry.warm(&path, size, &options).await else {
+            eprintln!("skipping: O_DIRECT isn't supported here");
+            return;
+        };
+        assert_eq!(result.bytes_read, 16 * KB, "{}", strategy);
+        let access = &device.accesses(&path)[0];
+        assert!(access.direct_io && access.strategy == strategy && access.cold_blocks == 4, "{}: {:?}", strategy, access);
+        assert!(device.resident_blocks(&path).unwrap().is_empty(), "{} filled the page cache under --direct-io", strategy);
+    }
+}
+
+#[tokio::test]
+async fn skip_cached_reads_the_rest_with_the_strategy() {
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
+
+    let device = device("skip_cached", DEFAULT_BLOCK_SIZE, Duration::ZERO);
+    let registry = device.registry();
+    let size = 4 * MB;
+    let Some(path) = cold_file(&device, "data", size) else { return };
+    // The first half was read since boot, without readahead into the second
+    let file = std::fs::File::open(&path).unwrap();
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_RANDOM) };
+    file.read_exact_at(&mut vec![0u8; (2 * MB) as usize], 0).unwrap();
+    assert_eq!(device.resident_blocks(&path).unwrap().len() as u64, device.blocks_of(2 * MB));
+
+    let options = WarmingOptions { skip_cached: true, use_direct_io: true, drop_caches: DropCaches::None, ..options(Strategy::Tokio) };
+    let Ok(result) = registry.warm(&path, size, &options).await else {
+        eprintln!("skipping: O_DIRECT isn't supported here");
+        return;
+    };
+    assert_eq!((result.method, result.bytes_read), ("tokio_direct_full", 2 * MB));
+    assert!(device.accesses(&path)[0].direct_io);
+    assert!(device.resident_blocks(&path).unwrap().is_empty(), "the cold half went through the page cache");
+}
+
+#[tokio::test]
+async fn cold_blocks_cost_latency_once() {
+    let device = device("latency", 64 * KB, Duration::from_millis(20));
+    let registry = device.registry();
+    let Some(path) = cold_file(&device, "data", 256 * KB) else { return };
+    let options = WarmingOptions { drop_caches: DropCaches::None, ..options(Strategy::Tokio) };
+
+    let first = registry.warm(&path, 256 * KB, &options).await.unwrap();
+    assert!(first.duration >= Duration::from_millis(80), "{:?}", first.duration);
+    assert_eq!(device.latency_charged(), Duration::from_millis(80));
+    assert_eq!(device.resident_blocks(&path).unwrap().len(), 4, "--drop-caches-after none keeps the pages");
+
+    // Hydration outlives the page cache, as on EBS
+    registry.warm(&path, 256 * KB, &options).await.unwrap();
+    let accesses = device.accesses(&path);
+    assert_eq!(accesses.iter().map(|access| access.cold_blocks).collect::<Vec<_>>(), [4, 0]);
+    assert_eq!(accesses[1].blocks, [0, 1, 2, 3]);
+    assert_eq!(device.latency_charged(), Duration::from_millis(80));
+}
+
+#[tokio::test]
+async fn direct_reads_are_charged_by_bytes() {
+    let device = device("direct", DEFAULT_BLOCK_SIZE, Duration::from_micros(10));
+    let registry = device.registry();
+    let Some(path) = cold_file(&device, "data", 256 * KB) else { return };
+    let options = WarmingOptions { use_direct_io: true, ..options(Strategy::Tokio) };
+    let Ok(result) = registry.warm(&path, 256 * KB, &options).await else {
+        eprintln!("skipping: O_DIRECT isn't supported here");
+        return;
+    };
+    assert_eq!(result.bytes_read, 256 * KB);
+    let accesses = device.accesses(&path);
+    assert!(accesses[0].direct_io && accesses[0].blocks.is_empty());
+    assert_eq!(accesses[0].cold_blocks, 64);
+    assert_eq!(device.cold_blocks(&path), 0);
+}
+
+#[tokio::test]
+async fn a_pipeline_run_hydrates_the_device() {
+    let device = device("pipeline", 64 * KB, Duration::from_millis(1));
+    let names = ["a.db", "logs/b.log", "logs/c.log", "d.bin"];
+    let mut paths = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        let Some(path) = cold_file(&device, name, (i as u64 + 1) * 100 * KB) else { return };
+        paths.push(path);
+    }
+    let directories = vec![device.root().to_path_buf()];
+    let options = PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: None,
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: options(Strategy::Tokio),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    };
+    let context = PipelineContext { registry: Some(Arc::new(device.registry())), ..Default::default() };
+    let summary = pipeline::run(Arc::new(options), context).await;
+    assert_eq!(summary.files_processed, 4);
+    for path in &paths {
+        assert_eq!(device.cold_blocks(path), 0, "{}", path.display());
+    }
+    assert_eq!(device.latency_charged(), Duration::from_millis(2 + 4 + 5 + 7));
+}
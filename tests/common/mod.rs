@@ -0,0 +1,53 @@
+//! Helpers shared by the integration tests; a test file uses them with `mod common;`.
+
+// Each test file is its own crate and uses only some of these
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::PipelineOptions;
+use rust_cache_warmer::warming::WarmingOptions;
+
+/// An empty directory for `test`, under one per test file in the target's scratch space
+pub fn scratch(test: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(env!("CARGO_CRATE_NAME")).join(test);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Options for a pipeline run over `directories` with one thread, a queue depth of 4,
+/// batches of 16 files and default warming; tests override what they exercise
+pub fn pipeline_options(directories: Vec<PathBuf>) -> PipelineOptions {
+    PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 4,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: WarmingOptions::default(),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    }
+}
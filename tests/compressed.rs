@@ -0,0 +1,66 @@
+//! `--compressed-extents`: one page per compressed extent or ZFS record, uncompressed
+//! extents whole, and files elsewhere read as usual.
+
+mod common;
+
+use std::fs;
+
+use rust_cache_warmer::fiemap::Extent;
+use rust_cache_warmer::warming::compressed::{self, Plan, TOUCH_SIZE};
+use rust_cache_warmer::warming::{Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingOptions};
+
+use common::scratch;
+
+const KB: u64 = 1024;
+
+fn extent(logical: u64, length: u64, encoded: bool) -> Extent {
+    Extent { logical, physical: None, length, unwritten: false, encoded }
+}
+
+#[test]
+fn compressed_extents_are_touched_once() {
+    // 128 KiB compressed extents, as btrfs writes them, then an uncompressed tail
+    let extents = [
+        extent(0, 128 * KB, true),
+        extent(128 * KB, 128 * KB, true),
+        Extent { unwritten: true, ..extent(256 * KB, 64 * KB, false) },
+        extent(320 * KB, 8 * KB, false),
+    ];
+    let plan = compressed::plan_extents(&extents, 326 * KB).unwrap();
+    assert_eq!(plan.reads, vec![(0, TOUCH_SIZE), (128 * KB, TOUCH_SIZE), (320 * KB, 6 * KB)]);
+    assert_eq!(plan.touched, 2);
+    assert_eq!(plan.bytes(), 2 * TOUCH_SIZE + 6 * KB);
+
+    // Nothing compressed: read the file as usual
+    assert_eq!(compressed::plan_extents(&[extent(0, 1024 * KB, false)], 1024 * KB), None);
+    // A compressed extent shorter than a page (e.g. inline) is read as it is
+    assert_eq!(compressed::plan_extents(&[extent(0, 4 * KB, true)], 100).unwrap().reads, vec![(0, 100)]);
+}
+
+#[test]
+fn zfs_records_are_touched_once_each() {
+    let plan = compressed::plan_records(&[(0, 300 * KB)], 128 * KB);
+    assert_eq!(plan, Plan { reads: vec![(0, TOUCH_SIZE), (128 * KB, TOUCH_SIZE), (256 * KB, TOUCH_SIZE)], touched: 3 });
+
+    // Data ranges around holes: each record once, from where its data starts
+    let plan = compressed::plan_records(&[(0, 64 * KB), (100 * KB, 30 * KB), (512 * KB + 2 * KB, KB)], 128 * KB);
+    assert_eq!(plan.reads, vec![(0, TOUCH_SIZE), (128 * KB, 2 * KB), (514 * KB, KB)]);
+}
+
+#[tokio::test]
+async fn files_on_other_filesystems_are_read_as_usual() {
+    let dir = scratch("plain");
+    let path = dir.join("data");
+    fs::write(&path, vec![7u8; 2 * 1024 * 1024]).unwrap();
+    if compressed::filesystem(&dir).is_some() {
+        // Only meaningful where the test tree isn't itself on btrfs or ZFS
+        return;
+    }
+
+    let options = WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, compressed_extents: true, ..Default::default() };
+    assert_eq!(compressed::plan(&path, 2 * 1024 * 1024).unwrap(), None);
+    assert!(compressed::warm(&path, 2 * 1024 * 1024, &options).await.unwrap().is_none());
+    let result = StrategyRegistry::builtin().warm(&path, 2 * 1024 * 1024, &options).await.unwrap();
+    assert_ne!(result.method, "compressed_extents");
+    assert_eq!((result.bytes_read, result.coverage), (2 * 1024 * 1024, Coverage::Full));
+}
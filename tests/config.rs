@@ -0,0 +1,79 @@
+//! Config files: profiles layer over top-level settings, and options given on the
+//! command line override both.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rust_cache_warmer::config::ConfigFile;
+
+const CONFIG: &str = r#"
+queue-depth = 16
+direct_io = true
+include = ["*.db", "*.wal"]
+
+[profile.ebs-gp3]
+queue_depth = 128
+sparse-large-files = 1048576
+
+[profile.local-nvme]
+strategy = "readahead"
+"#;
+
+fn values(v: &[&str]) -> Vec<String> {
+    v.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn profiles_override_top_level_settings() {
+    let config = ConfigFile::parse(CONFIG).unwrap();
+    assert_eq!(config.profile_names().collect::<Vec<_>>(), ["ebs-gp3", "local-nvme"]);
+
+    let base = config.resolve(None).unwrap();
+    assert_eq!(base["queue_depth"], values(&["16"]));
+    assert_eq!(base["direct_io"], values(&["true"]));
+    assert_eq!(base["include"], values(&["*.db", "*.wal"]));
+    assert!(!base.contains_key("sparse_large_files"));
+
+    let gp3 = config.resolve(Some("ebs-gp3")).unwrap();
+    assert_eq!(gp3["queue_depth"], values(&["128"]));
+    assert_eq!(gp3["sparse_large_files"], values(&["1048576"]));
+    assert_eq!(gp3["include"], values(&["*.db", "*.wal"]));
+}
+
+#[test]
+fn bad_configs_are_rejected() {
+    let config = ConfigFile::parse(CONFIG).unwrap();
+    let err = config.resolve(Some("io2")).unwrap_err().to_string();
+    assert!(err.contains("unknown profile 'io2'") && err.contains("ebs-gp3, local-nvme"), "{}", err);
+
+    assert!(ConfigFile::parse("config = \"other.toml\"").unwrap().resolve(None).is_err());
+    assert!(ConfigFile::parse("[filters]\ninclude = 1").unwrap().resolve(None).is_err());
+    assert!(ConfigFile::parse("profile = 3").is_err());
+    assert!(ConfigFile::parse("queue_depth = ").is_err());
+}
+
+/// The binary logs its parsed options with --debug; read one field back from that line
+fn configured(args: &[&str], field: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state")).args(args).arg("--debug").output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr.lines().find(|line| line.contains("Configuration: Opts")).unwrap_or_else(|| panic!("no configuration line in:\n{}", stderr));
+    let start = line.find(&format!("{}: ", field)).unwrap() + field.len() + 2;
+    line[start..].split([',', ' ']).next().unwrap().to_string()
+}
+
+#[test]
+fn command_line_beats_profile_beats_config() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("config");
+    fs::create_dir_all(dir.join("data")).unwrap();
+    let config = dir.join("warmer.toml");
+    let data = dir.join("data");
+    fs::write(&config, format!("directories = [{:?}]\n{}", data, CONFIG)).unwrap();
+    let config = config.to_str().unwrap();
+
+    assert_eq!(configured(&["--config", config], "queue_depth"), "16");
+    assert_eq!(configured(&["--config", config, "--config-profile", "ebs-gp3"], "queue_depth"), "128");
+    assert_eq!(configured(&["--config", config, "--config-profile", "ebs-gp3", "-q", "3"], "queue_depth"), "3");
+    assert_eq!(configured(&["--config", config, "--config-profile", "local-nvme"], "strategy"), "Readahead");
+    assert_eq!(configured(&["--config", config, "--strategy", "tokio"], "strategy"), "Tokio");
+}
@@ -0,0 +1,151 @@
+//! `--status-listen`: the API reports progress and its pause and throttle reach the pipeline.
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rust_cache_warmer::control::{self, Control};
+use rust_cache_warmer::events::EventBus;
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::introspect::Introspection;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineProgress};
+use rust_cache_warmer::progress::ByteProgress;
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use common::scratch;
+
+const MB: u64 = 1024 * 1024;
+
+/// Send one request and return the status code and JSON body
+async fn request(addr: std::net::SocketAddr, method: &str, target: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, target).as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn the_throttle_spaces_reads_and_can_be_lifted() {
+    let control = Control::new();
+    let now = Instant::now();
+    assert_eq!(control.reserve(10 * MB, now), Duration::ZERO);
+
+    control.set_throttle(Some(10.0));
+    let now = Instant::now();
+    assert_eq!(control.reserve(10 * MB, now), Duration::ZERO);
+    assert_eq!(control.reserve(5 * MB, now), Duration::from_secs(1));
+    assert_eq!(control.reserve(MB, now), Duration::from_millis(1500));
+
+    // A new rate starts the schedule over
+    control.set_throttle(Some(20.0));
+    let now = Instant::now();
+    assert_eq!(control.reserve(20 * MB, now), Duration::ZERO);
+    assert_eq!(control.reserve(MB, now), Duration::from_secs(1));
+
+    control.set_throttle(Some(0.0));
+    assert_eq!(control.settings().throttle, None);
+    assert_eq!(control.reserve(100 * MB, Instant::now()), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn requests_change_the_settings_and_status_reports_them() {
+    let listener = control::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let control = Control::new();
+    let progress = ByteProgress::default();
+    progress.discovered(4);
+    progress.finished(3 * MB);
+    let mut events = EventBus::new();
+    let server = tokio::spawn(control::serve(listener, control.clone(), progress, Introspection::new(), events.subscribe()));
+
+    let (status, body) = request(addr, "GET", "/status").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["state"], "running");
+    assert_eq!(body["throttle_mbps"], Value::Null);
+    assert_eq!(body["bytes"]["done"], 3 * MB);
+
+    assert_eq!(request(addr, "POST", "/pause").await, (200, serde_json::json!({ "state": "paused", "throttle_mbps": null })));
+    assert!(control.settings().paused);
+    let (_, body) = request(addr, "POST", "/throttle?mbps=25").await;
+    assert_eq!(body["throttle_mbps"], 25.0);
+    assert_eq!(request(addr, "GET", "/status").await.1["state"], "paused");
+    let (_, body) = request(addr, "POST", "/resume").await;
+    assert_eq!(body["state"], "running");
+    assert!(!control.settings().paused);
+
+    assert_eq!(request(addr, "POST", "/throttle?mbps=fast").await.0, 400);
+    assert_eq!(request(addr, "POST", "/throttle").await.0, 400);
+    assert_eq!(request(addr, "GET", "/pause").await.0, 405);
+    assert_eq!(request(addr, "GET", "/metrics").await.0, 404);
+    assert_eq!(control.settings().throttle, Some(25.0 * MB as f64));
+
+    // The server stops with the run
+    drop(events);
+    tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn a_paused_run_starts_no_files_until_resumed() {
+    let root = scratch("pause");
+    for file in 0..20 {
+        fs::write(root.join(format!("file-{}", file)), b"data").unwrap();
+    }
+    let directories = vec![root.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 4,
+        threads: Some(2),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 5,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let control = Control::new();
+    control.pause();
+    let progress = ByteProgress::default();
+    let context = PipelineContext {
+        progress: PipelineProgress { bytes: progress.clone(), ..Default::default() },
+        control: Some(control.clone()),
+        ..Default::default()
+    };
+    let run = pipeline::run(options, context);
+    tokio::pin!(run);
+
+    tokio::select! {
+        _ = &mut run => panic!("a paused run finished"),
+        _ = tokio::time::sleep(Duration::from_millis(300)) => {}
+    }
+    assert_eq!(progress.done_bytes(), 0);
+
+    control.resume();
+    let summary = tokio::time::timeout(Duration::from_secs(10), run).await.unwrap();
+    assert_eq!(summary.files_processed, 20);
+    assert_eq!(progress.done_bytes(), 80);
+}
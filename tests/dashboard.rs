@@ -0,0 +1,101 @@
+//! `--tui`: what the dashboard gathers from the pipeline's events and from sysfs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rust_cache_warmer::dashboard::{self, DashboardState, DeviceSampler, DiskStat, LATENCY_BUCKETS_MS, SLOWEST_FILES};
+use rust_cache_warmer::events::WarmEvent;
+use rust_cache_warmer::stats::FileStatus;
+
+fn finished(path: &str, bytes: u64, latency_ms: u64, status: FileStatus, method: Option<&'static str>, error: Option<&str>) -> WarmEvent {
+    WarmEvent::FileFinished {
+        path: PathBuf::from(path),
+        bytes,
+        latency: Duration::from_millis(latency_ms),
+        status,
+        method,
+        bytes_read: bytes,
+        error: error.map(str::to_string),
+        coverage: None,
+    }
+}
+
+#[test]
+fn files_are_counted_by_method_latency_and_error() {
+    let mut state = DashboardState::default();
+    let start = Instant::now();
+    state.record_at(&finished("/data/a", 4096, 0, FileStatus::Warmed, Some("readahead_full"), None), start);
+    state.record_at(&finished("/data/b", 8192, 3, FileStatus::Warmed, Some("readahead_full"), None), start);
+    state.record_at(&finished("/data/c", 1 << 20, 1500, FileStatus::Warmed, Some("mmap_sparse"), None), start);
+    state.record_at(&finished("/data/d", 100, 7, FileStatus::Failed, None, Some("Input/output error (os error 5)")), start);
+    state.record_at(&finished("/data/e", 100, 9, FileStatus::Failed, None, Some("Input/output error (os error 5)")), start);
+    state.record_at(&finished("/data/f", 100, 0, FileStatus::Skipped, None, None), start);
+
+    let methods = state.methods_by_bytes();
+    assert_eq!(methods.iter().map(|(name, stats)| (*name, stats.files, stats.bytes)).collect::<Vec<_>>(), [("mmap_sparse", 1, 1 << 20), ("readahead_full", 2, 12288)]);
+    assert_eq!(state.latency[0], 1);
+    assert_eq!(state.latency[dashboard::latency_bucket(Duration::from_millis(3))], 1);
+    assert_eq!(state.latency[LATENCY_BUCKETS_MS.len()], 1, "over a second goes in the last bucket");
+    assert_eq!(state.latency.iter().sum::<u64>(), 3, "only warmed files are in the histogram");
+    assert_eq!(state.errors_by_count(), [("Input/output error (os error 5)", 2)]);
+    assert_eq!(state.skipped, 1);
+
+    let labels = dashboard::latency_labels();
+    assert_eq!((labels[0].as_str(), labels[3].as_str(), labels[9].as_str(), labels[10].as_str()), ("<1ms", "<10ms", "<1s", "≥1s"));
+}
+
+#[test]
+fn throughput_covers_the_recent_window() {
+    let mut state = DashboardState::default();
+    let start = Instant::now();
+    state.record_at(&finished("/data/a", 10_000_000, 1, FileStatus::Warmed, Some("tokio_full"), None), start);
+    state.record_at(&finished("/data/b", 20_000_000, 1, FileStatus::Warmed, Some("tokio_full"), None), start + Duration::from_secs(5));
+    assert_eq!(state.throughput.rate(start + Duration::from_secs(5)), 3_000_000.0);
+    // The first file has aged out of the window
+    assert_eq!(state.throughput.rate(start + Duration::from_secs(12)), 2_000_000.0);
+    assert_eq!(state.methods["tokio_full"].throughput.rate(start + Duration::from_secs(30)), 0.0);
+}
+
+#[test]
+fn only_the_slowest_files_are_kept() {
+    let mut state = DashboardState::default();
+    let now = Instant::now();
+    for i in 0..30u64 {
+        // Latencies 0, 7, 14, … in a scrambled order
+        let latency = (i * 7) % 30 * 7;
+        state.record_at(&finished(&format!("/data/{}", latency), 1, latency, FileStatus::Warmed, Some("tokio_full"), None), now);
+    }
+    state.record_at(&finished("/data/failed", 1, 1000, FileStatus::Failed, None, Some("timed out")), now);
+    state.record_at(&finished("/data/skipped", 1, 5000, FileStatus::Skipped, None, None), now);
+
+    assert_eq!(state.slowest.len(), SLOWEST_FILES);
+    let latencies: Vec<u64> = state.slowest.iter().map(|file| file.latency.as_millis() as u64).collect();
+    assert_eq!(latencies, [1000, 203, 196, 189, 182, 175, 168, 161, 154, 147]);
+    assert_eq!(state.slowest[0].status, FileStatus::Failed);
+    assert_eq!(state.slowest[1].path, Path::new("/data/203"));
+}
+
+#[test]
+fn device_counters_become_rates() {
+    let stat = "   52041     1203  8862290    23104     9011     4410   301344    18320       17    41230    44300        0        0        0        0";
+    assert_eq!(dashboard::parse_disk_stat(stat), Some(DiskStat { reads: 52041, sectors_read: 8862290, in_flight: 17, io_ticks: 41230 }));
+    assert_eq!(dashboard::parse_disk_stat("1 2 3"), None);
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("dashboard").join("nvme1n1");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("stat"), "1000 0 2048 0 0 0 0 0 4 500 0\n").unwrap();
+    let mut sampler = DeviceSampler::new("nvme1n1", &dir);
+    let start = Instant::now();
+    let first = sampler.sample(start).unwrap();
+    assert_eq!((first.in_flight, first.reads_per_sec, first.bytes_per_sec), (4, 0.0, 0.0));
+
+    fs::write(dir.join("stat"), "1400 0 6144 0 0 0 0 0 32 1500 0\n").unwrap();
+    let second = sampler.sample(start + Duration::from_secs(2)).unwrap();
+    assert_eq!(second.in_flight, 32);
+    assert_eq!(second.reads_per_sec, 200.0);
+    assert_eq!(second.bytes_per_sec, 1_048_576.0);
+    assert_eq!(second.utilization, 0.5);
+
+    assert!(DeviceSampler::new("gone", dir.join("absent")).sample(start).is_none());
+}
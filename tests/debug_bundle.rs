@@ -0,0 +1,86 @@
+//! `debug-bundle`: a gzipped tarball with a structured environment snapshot, raw `/proc`
+//! copies and the files of the run being reported.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use rust_cache_warmer::bundle::{self, BundleOptions, ROOT};
+use serde_json::Value;
+
+use common::scratch;
+
+/// Entry name → contents of a gzipped ustar archive
+fn unpack(path: &Path) -> BTreeMap<String, Vec<u8>> {
+    let gz = fs::read(path).unwrap();
+    assert_eq!(&gz[..3], &[0x1f, 0x8b, 8], "not gzip");
+    let tar = miniz_oxide::inflate::decompress_to_vec(&gz[10..gz.len() - 8]).unwrap();
+    let size = u32::from_le_bytes(gz[gz.len() - 4..].try_into().unwrap());
+    assert_eq!(tar.len(), size as usize);
+
+    let mut entries = BTreeMap::new();
+    let mut offset = 0;
+    while tar[offset] != 0 {
+        let header = &tar[offset..offset + 512];
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8(header[range].iter().copied().take_while(|&b| b != 0).collect()).unwrap()
+        };
+        assert_eq!(field(257..263), "ustar");
+        let checksum = u64::from_str_radix(field(148..154).trim(), 8).unwrap();
+        let sum: u64 = header.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { 32 } else { u64::from(b) }).sum();
+        assert_eq!(checksum, sum);
+        let len = usize::from_str_radix(field(124..135).as_str(), 8).unwrap();
+        let data = tar[offset + 512..offset + 512 + len].to_vec();
+        entries.insert(field(0..100), data);
+        offset += 512 + len.next_multiple_of(512);
+    }
+    entries
+}
+
+#[test]
+fn mounts_are_parsed() {
+    let mounts = bundle::parse_mounts("/dev/nvme1n1 /data xfs rw,noatime 0 0\nbroken\n");
+    assert_eq!(mounts.len(), 1);
+    assert_eq!(mounts[0].device, "/dev/nvme1n1");
+    assert_eq!(mounts[0].mount_point, "/data");
+    assert_eq!(mounts[0].fs_type, "xfs");
+    assert_eq!(mounts[0].options, "rw,noatime");
+}
+
+#[test]
+fn the_bundle_holds_the_snapshot_and_the_run_files() {
+    let dir = scratch("bundle");
+    fs::create_dir_all(dir.join("a")).unwrap();
+    fs::write(dir.join("warmer.toml"), "queue_depth = \"many\"\n[profile").unwrap();
+    fs::write(dir.join("a/results.ndjson"), "{\"path\":\"/data/x\"}\n").unwrap();
+    fs::write(dir.join("results.ndjson"), "second").unwrap();
+    let output = dir.join("out.tar.gz");
+
+    let options = BundleOptions {
+        config: Some(dir.join("warmer.toml")),
+        directories: vec![dir.clone()],
+        files: vec![dir.join("a/results.ndjson"), dir.join("results.ndjson"), dir.join("missing.ckpt")],
+    };
+    let environment = bundle::create(&output, &options).unwrap();
+    assert_eq!(environment.files.len(), 3, "config plus two run files");
+    assert!(environment.notes.iter().any(|note| note.contains("missing.ckpt")), "{:?}", environment.notes);
+    assert!(environment.config.as_ref().unwrap().error.is_some());
+    assert!(!environment.strategies.is_empty());
+
+    let entries = unpack(&output);
+    let entry = |name: &str| entries.get(&format!("{}/{}", ROOT, name)).unwrap_or_else(|| panic!("no {} in {:?}", name, entries.keys()));
+    assert_eq!(entry("files/results.ndjson"), b"{\"path\":\"/data/x\"}\n");
+    assert_eq!(entry("files/2-results.ndjson"), b"second");
+    assert!(entry("config/warmer.toml").starts_with(b"queue_depth"));
+    assert!(!entry("proc/self_limits").is_empty());
+
+    let json: Value = serde_json::from_slice(entry("environment.json")).unwrap();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert!(json["kernel"]["release"].as_str().is_some_and(|release| !release.is_empty()));
+    assert!(json["mounts"].as_array().unwrap().iter().any(|mount| mount["mount_point"] == "/"));
+    assert!(json["rlimits"].as_array().unwrap().iter().any(|limit| limit["resource"] == "nofile"));
+    assert_eq!(json["targets"][0]["path"], dir.to_str().unwrap());
+    assert_eq!(json["files"][2]["entry"], "files/2-results.ndjson");
+}
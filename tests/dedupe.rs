@@ -0,0 +1,158 @@
+//! Inode deduplication: hard links to one file, a directory reached by two paths and
+//! overlapping targets are warmed once unless `dedupe_inodes` is turned off.
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::dedupe::{self, InodeSet, NestedRoot};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineSummary};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+async fn run(root: &Path, dedupe_inodes: bool, follow_symlinks: bool) -> PipelineSummary {
+    run_targets(vec![root.to_path_buf()], dedupe_inodes, follow_symlinks).await
+}
+
+async fn run_targets(directories: Vec<PathBuf>, dedupe_inodes: bool, follow_symlinks: bool) -> PipelineSummary {
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 8,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    pipeline::run(options, PipelineContext::default()).await
+}
+
+#[test]
+fn single_link_files_are_never_recorded() {
+    let inodes = InodeSet::new();
+    assert!(inodes.first_file(1, 10, 1));
+    assert!(inodes.first_file(1, 10, 1));
+    assert!(inodes.first_file(1, 11, 3));
+    assert!(!inodes.first_file(1, 11, 3));
+    // The same inode number on another device is another file
+    assert!(inodes.first_file(2, 11, 3));
+    assert!(inodes.first_directory(1, 20));
+    assert!(!inodes.first_directory(1, 20));
+    assert_eq!((inodes.summary().files, inodes.summary().directories), (1, 1));
+}
+
+#[tokio::test]
+async fn hard_links_are_warmed_once() {
+    let root = scratch("hard_links");
+    fs::create_dir_all(root.join("snapshot-1")).unwrap();
+    fs::create_dir_all(root.join("snapshot-2")).unwrap();
+    fs::write(root.join("snapshot-1/shared.bin"), vec![1u8; 4096]).unwrap();
+    fs::hard_link(root.join("snapshot-1/shared.bin"), root.join("snapshot-2/shared.bin")).unwrap();
+    fs::hard_link(root.join("snapshot-1/shared.bin"), root.join("shared.bin")).unwrap();
+    fs::write(root.join("snapshot-2/changed.bin"), vec![2u8; 4096]).unwrap();
+
+    let summary = run(&root, true, false).await;
+    assert_eq!(summary.files_discovered, 2);
+    assert_eq!(summary.bytes_warmed, 8192);
+    assert_eq!(summary.duplicates.files, 2);
+
+    let summary = run(&root, false, false).await;
+    assert_eq!(summary.files_discovered, 4);
+    assert_eq!(summary.duplicates.files, 0);
+}
+
+#[tokio::test]
+async fn directories_reached_twice_are_walked_once() {
+    let root = scratch("directories");
+    fs::create_dir_all(root.join("data/nested")).unwrap();
+    for name in ["a", "b", "nested/c"] {
+        fs::write(root.join("data").join(name), b"data").unwrap();
+    }
+    // Followed, the link shows the same directory a second time, as a bind mount would
+    std::os::unix::fs::symlink(root.join("data"), root.join("alias")).unwrap();
+
+    let summary = run(&root, true, true).await;
+    assert_eq!(summary.files_discovered, 3);
+    assert_eq!(summary.duplicates.directories, 1);
+
+    let summary = run(&root, false, true).await;
+    assert_eq!(summary.files_discovered, 6);
+}
+
+#[tokio::test]
+async fn overlapping_targets_are_walked_once() {
+    let root = scratch("overlapping");
+    fs::create_dir_all(root.join("data/hot/deeper")).unwrap();
+    for name in ["top", "hot/a", "hot/b", "hot/deeper/c"] {
+        fs::write(root.join("data").join(name), b"data").unwrap();
+    }
+    let (data, hot) = (root.join("data"), root.join("data/hot"));
+
+    for targets in [vec![data.clone(), hot.clone()], vec![hot.clone(), data.clone()], vec![data.clone(), data.clone(), hot.clone()]] {
+        let summary = run_targets(targets.clone(), true, false).await;
+        assert_eq!(summary.files_discovered, 4, "{:?}", targets);
+        assert_eq!(summary.bytes_warmed, 16);
+    }
+    let summary = run_targets(vec![data.clone(), hot.clone()], false, false).await;
+    assert_eq!(summary.files_discovered, 7);
+
+    assert_eq!(
+        dedupe::nested_roots(&[hot.clone(), data.clone(), root.join("data/./")]),
+        [
+            NestedRoot { root: hot.clone(), containing: data.clone(), same: false },
+            NestedRoot { root: root.join("data/./"), containing: data.clone(), same: true },
+        ]
+    );
+    assert!(dedupe::nested_roots(&[hot.clone(), root.join("data/hotter")]).is_empty(), "a shared prefix isn't nesting");
+}
+
+#[test]
+fn a_directory_at_the_depth_limit_is_left_for_other_targets() {
+    let root = scratch("max_depth");
+    fs::create_dir_all(root.join("data/hot")).unwrap();
+    let inodes = InodeSet::with_max_depth(Some(1));
+    assert!(inodes.first_root(&root.join("data")));
+    assert!(!inodes.first_root(&root.join("data/.")), "the same directory again");
+    for entry in ignore::WalkBuilder::new(root.join("data")).max_depth(Some(1)).build().skip(1) {
+        assert!(inodes.first_visit(&entry.unwrap()));
+    }
+    assert!(inodes.first_root(&root.join("data/hot")), "not descended into under --max-depth 1");
+}
+
+#[tokio::test]
+async fn symlink_cycles_end_at_the_first_repeat() {
+    let root = scratch("cycle");
+    fs::create_dir_all(root.join("data/inner")).unwrap();
+    fs::write(root.join("data/inner/file"), b"data").unwrap();
+    std::os::unix::fs::symlink(root.join("data"), root.join("data/inner/back")).unwrap();
+
+    let summary = run(&root.join("data"), true, true).await;
+    assert_eq!(summary.files_discovered, 1);
+    assert_eq!(summary.duplicates.directories, 1);
+    assert_eq!(summary.discovery_errors, 0, "a cycle isn't a walk error");
+}
@@ -0,0 +1,109 @@
+//! `--deterministic`: files are queued in sorted walk order whatever the thread count,
+//! and clock-driven options are refused.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+/// Depth first, each directory's entries by name
+fn sorted_walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            sorted_walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+#[tokio::test]
+async fn files_are_warmed_in_sorted_order() {
+    let dir = scratch("order");
+    for (d, sub) in ["m", "b", "z/y", "a"].iter().enumerate() {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+        for i in [7, 3, 11, 0, 5] {
+            fs::write(dir.join(sub).join(format!("f{}", i)), vec![d as u8; 4096 * (i + 1)]).unwrap();
+        }
+    }
+    fs::write(dir.join("c"), b"top").unwrap();
+    let mut expected = Vec::new();
+    sorted_walk(&dir, &mut expected);
+
+    for _ in 0..2 {
+        let directories = vec![dir.clone()];
+        let options = Arc::new(PipelineOptions {
+            filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+            directories,
+            queue_depth: 1,
+            threads: Some(8),
+            follow_symlinks: false,
+            respect_gitignore: false,
+            max_depth: None,
+            ignore_hidden: false,
+            max_file_size: 0,
+            batch_size: 3,
+            batch_bytes: 0,
+            warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+            stats_by: Vec::new(),
+            low_memory: false,
+            page_cache_only: Vec::new(),
+            network: Vec::new(),
+            fail_fast: false,
+            fair_share: FairShareOptions::default(),
+            vanished_grace: Duration::ZERO,
+            physical_order: false,
+            dedupe_inodes: true,
+            max_duration: None,
+            discovery_buffer: 0,
+            per_device_queue_depth: None,
+            deterministic: true,
+        });
+        let mut events = EventBus::new();
+        let mut finished = events.subscribe();
+        let summary = pipeline::run(options, PipelineContext { events, ..Default::default() }).await;
+        assert_eq!(summary.files_processed, expected.len() as u64);
+
+        let mut order = Vec::new();
+        while let Ok(WarmEvent::FileFinished { path, .. }) = finished.try_recv() {
+            order.push(path);
+        }
+        assert_eq!(order, expected);
+    }
+}
+
+#[test]
+fn clock_driven_options_are_refused() {
+    let dir = scratch("cli");
+    fs::write(dir.join("file"), b"data").unwrap();
+    let warm = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+            .arg("--deterministic")
+            .args(args)
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+    let output = warm(&["--seed", "42"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Deterministic run: sorted discovery, sampling seed 42, no auto-tuning"));
+    assert!(String::from_utf8_lossy(&warm(&[]).stdout).contains("sampling seed 0"));
+
+    assert!(!warm(&["--max-duration", "1m"]).status.success());
+    assert!(!warm(&["--file-timeout", "30s"]).status.success());
+    assert!(!warm(&["--pace", "ebs-burst"]).status.success());
+}
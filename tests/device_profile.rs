@@ -0,0 +1,105 @@
+//! Device profiling: sysfs attributes and EBS volume IDs are read from the disk behind a
+//! target, and the profiles pick queue depth, read size and strategy.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use rust_cache_warmer::device::{self, DeviceProfile, EbsVolume, Tuning, VolumeType};
+use rust_cache_warmer::disk::DiskKind;
+use rust_cache_warmer::warming::{Strategy, WarmingOptions, LOW_MEMORY_CHUNK_SIZE};
+
+fn profile(device: &str, kind: DiskKind, rotational: bool, ebs: Option<(VolumeType, u32)>) -> DeviceProfile {
+    DeviceProfile {
+        device: device.to_string(),
+        kind,
+        rotational: Some(rotational),
+        nr_requests: Some(1023),
+        size_bytes: None,
+        volume_id: ebs.as_ref().map(|_| "vol-0abc".to_string()),
+        ebs: ebs.map(|(volume_type, iops)| EbsVolume { volume_type, iops: Some(iops), throughput_mbps: None, size_gib: None }),
+        directories: vec![PathBuf::from("/data")],
+    }
+}
+
+fn ebs(volume_type: VolumeType, iops: u32) -> DeviceProfile {
+    profile("nvme1n1", DiskKind::Ebs("nvme1n1".to_string()), false, Some((volume_type, iops)))
+}
+
+#[test]
+fn volume_ids_come_from_nvme_serials() {
+    assert_eq!(device::volume_id("vol0abc123").as_deref(), Some("vol-0abc123"));
+    assert_eq!(device::volume_id("vol-0abc123 ").as_deref(), Some("vol-0abc123"));
+    assert_eq!(device::volume_id("AWS1234567890"), None);
+    assert_eq!(device::volume_id("vol"), None);
+    assert_eq!(VolumeType::from("gp3"), VolumeType::Gp3);
+    assert_eq!(VolumeType::from("io3"), VolumeType::Other("io3".to_string()));
+}
+
+#[test]
+fn sysfs_attributes_are_read() {
+    let dir = common::scratch("nvme1n1");
+    fs::create_dir_all(dir.join("queue")).unwrap();
+    fs::create_dir_all(dir.join("device")).unwrap();
+    fs::write(dir.join("queue/rotational"), "0\n").unwrap();
+    fs::write(dir.join("queue/nr_requests"), "255\n").unwrap();
+    fs::write(dir.join("device/model"), "Amazon Elastic Block Store              \n").unwrap();
+    fs::write(dir.join("device/serial"), "vol0abc123\n").unwrap();
+    fs::write(dir.join("size"), "2097152\n").unwrap();
+
+    let profile = device::probe_sys(&dir);
+    assert_eq!(profile.device, "nvme1n1");
+    assert_eq!(profile.kind, DiskKind::Ebs("nvme1n1".to_string()));
+    assert_eq!(profile.rotational, Some(false));
+    assert_eq!(profile.nr_requests, Some(255));
+    assert_eq!(profile.size_bytes, Some(1 << 30));
+    assert_eq!(profile.volume_id.as_deref(), Some("vol-0abc123"));
+    assert!(profile.to_string().contains("vol-0abc123, volume type unknown"), "{}", profile);
+}
+
+#[test]
+fn devices_pick_settings() {
+    let gp3 = ebs(VolumeType::Gp3, 3000).tuning();
+    assert_eq!(gp3, Tuning { queue_depth: Some(30), chunk_size: Some(device::SSD_CHUNK_SIZE), strategy: None });
+    assert_eq!(ebs(VolumeType::Io2, 64000).tuning().queue_depth, Some(device::MAX_QUEUE_DEPTH));
+    assert_eq!(ebs(VolumeType::Gp2, 100).tuning().queue_depth, Some(device::MIN_QUEUE_DEPTH));
+
+    let st1 = ebs(VolumeType::St1, 500).tuning();
+    assert_eq!(st1, Tuning { queue_depth: Some(device::HDD_QUEUE_DEPTH), chunk_size: Some(device::HDD_CHUNK_SIZE), strategy: Some(Strategy::Tokio) });
+
+    let local = profile("nvme2n1", DiskKind::InstanceStore("nvme2n1".to_string()), false, None).tuning();
+    assert_eq!(local.queue_depth, Some(64));
+    let disk = profile("sda", DiskKind::Unknown("sda".to_string()), true, None).tuning();
+    assert_eq!(disk.strategy, Some(Strategy::Tokio));
+
+    // An EBS volume whose type couldn't be looked up is left alone
+    let unknown = profile("nvme1n1", DiskKind::Ebs("nvme1n1".to_string()), false, None);
+    assert_eq!(unknown.tuning(), Tuning::default());
+}
+
+#[test]
+fn profiles_combine() {
+    assert_eq!(Tuning::for_profiles(&[]), Tuning::default());
+
+    let mixed = Tuning::for_profiles(&[ebs(VolumeType::Gp3, 3000), ebs(VolumeType::St1, 500)]);
+    assert_eq!(mixed.queue_depth, Some(38), "each device has its own queue");
+    assert_eq!(mixed.chunk_size, Some(device::SSD_CHUNK_SIZE));
+    assert_eq!(mixed.strategy, None, "only st1 asks for a strategy");
+
+    let capped = Tuning::for_profiles(&[ebs(VolumeType::Io2, 64000), ebs(VolumeType::Io2, 64000)]);
+    assert_eq!(capped.queue_depth, Some(device::MAX_QUEUE_DEPTH));
+
+    let unknown = profile("nvme3n1", DiskKind::Ebs("nvme3n1".to_string()), false, None);
+    assert_eq!(Tuning::for_profiles(&[ebs(VolumeType::Gp3, 3000), unknown]), Tuning::default());
+}
+
+#[test]
+fn chunk_size_overrides_the_backend_default() {
+    let options = WarmingOptions::default();
+    assert_eq!(options.read_size(65536), 65536);
+    let options = WarmingOptions { chunk_size: 512 * 1024, ..Default::default() };
+    assert_eq!(options.read_size(65536), 512 * 1024);
+    let options = WarmingOptions { chunk_size: 4 * 1024 * 1024, low_memory: true, ..Default::default() };
+    assert_eq!(options.read_size(65536), LOW_MEMORY_CHUNK_SIZE);
+}
@@ -0,0 +1,86 @@
+//! `--per-device-queue-depth`: each device's semaphore bounds its own files in flight, and
+//! runs limited to one file per device still warm everything.
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::device_queue::{self, DeviceDepths, DeviceQueues, PerDeviceDepth};
+use rust_cache_warmer::fair::{FairShareOptions, ShareBy};
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+#[test]
+fn depth_is_auto_or_a_positive_number() {
+    assert_eq!("auto".parse::<PerDeviceDepth>(), Ok(PerDeviceDepth::Auto));
+    assert_eq!(" 8 ".parse::<PerDeviceDepth>(), Ok(PerDeviceDepth::Fixed(8)));
+    assert!("0".parse::<PerDeviceDepth>().is_err());
+    assert!("deep".parse::<PerDeviceDepth>().is_err());
+}
+
+#[tokio::test]
+async fn each_device_has_its_own_queue() {
+    let queues = DeviceQueues::new(DeviceDepths { default: 2, devices: HashMap::from([(7, 1)]) });
+    let slow = queues.acquire(7).await;
+    assert!(queues.is_full(7));
+    let fast = [queues.acquire(8).await, queues.acquire(8).await];
+    assert!(queues.is_full(8));
+    assert!(!queues.is_full(9), "a device nothing was read from yet has room");
+
+    // The slow device's waiter only gets in when its own slot frees up
+    assert!(tokio::time::timeout(Duration::from_millis(50), queues.acquire(7)).await.is_err());
+    drop(fast);
+    assert!(tokio::time::timeout(Duration::from_millis(50), queues.acquire(7)).await.is_err());
+    drop(slow);
+    let _slow = tokio::time::timeout(Duration::from_secs(1), queues.acquire(7)).await.unwrap();
+}
+
+#[tokio::test]
+async fn one_file_per_device_still_warms_every_share() {
+    let root = scratch("one_per_device");
+    let directories: Vec<PathBuf> = ["a", "b"].iter().map(|name| root.join(name)).collect();
+    for dir in &directories {
+        fs::create_dir_all(dir).unwrap();
+        for file in 0..40 {
+            fs::write(dir.join(format!("file-{}", file)), b"data").unwrap();
+        }
+    }
+    let dev = device_queue::device_of(&root).unwrap();
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 8,
+        threads: Some(2),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 3,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions { by: ShareBy::Directory, ..Default::default() },
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: Some(DeviceDepths { default: 4, devices: HashMap::from([(dev, 1)]) }),
+        deterministic: false,
+    });
+    let summary = tokio::time::timeout(Duration::from_secs(30), pipeline::run(options, PipelineContext::default())).await.unwrap();
+    assert_eq!((summary.files_discovered, summary.files_processed), (80, 80));
+    assert_eq!(summary.bytes_warmed, 320);
+}
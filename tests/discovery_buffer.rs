@@ -0,0 +1,82 @@
+//! `--discovery-buffer`: discovery threads walking in parallel into a small buffer still
+//! hand every file to warming exactly once, bounded or not.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineSummary};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+/// Ten directories of thirty files, each ten levels deep under the last
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    let mut dir = root.clone();
+    for level in 0..10 {
+        dir = dir.join(format!("level-{}", level));
+        fs::create_dir_all(&dir).unwrap();
+        for file in 0..30 {
+            fs::write(dir.join(format!("file-{}", file)), b"data").unwrap();
+        }
+    }
+    root
+}
+
+async fn run(root: &Path, discovery_buffer: usize, low_memory: bool) -> PipelineSummary {
+    let directories = vec![root.to_path_buf()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(4),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 7,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    pipeline::run(options, PipelineContext::default()).await
+}
+
+#[tokio::test]
+async fn a_one_file_buffer_loses_nothing() {
+    let root = tree("one_file");
+    let summary = run(&root, 1, false).await;
+    assert_eq!((summary.files_discovered, summary.files_processed), (300, 300));
+    assert_eq!(summary.bytes_warmed, 1200);
+}
+
+#[tokio::test]
+async fn an_unbounded_buffer_loses_nothing() {
+    let root = tree("unbounded");
+    let summary = run(&root, 0, false).await;
+    assert_eq!((summary.files_discovered, summary.files_processed), (300, 300));
+}
+
+#[tokio::test]
+async fn low_memory_mode_bounds_an_unbounded_buffer() {
+    let root = tree("low_memory");
+    let summary = run(&root, 0, true).await;
+    assert_eq!((summary.files_discovered, summary.files_processed), (300, 300));
+}
@@ -0,0 +1,84 @@
+//! `--drop-caches-after` policy: per-file drops must leave no warmed pages behind,
+//! while `none` and `global` must leave them cached during the run.
+
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::prelude::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use rust_cache_warmer::warming::residency;
+use rust_cache_warmer::warming::{self, DropCaches, FallbackPolicy, Strategy, StrategyRegistry, WarmingOptions};
+
+const SIZE: u64 = 1024 * 1024;
+
+/// Write a file and make sure none of it is in the page cache afterwards
+fn cold_file(test: &str, name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("drop_caches").join(test);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let mut file = fs::File::create(&path).unwrap();
+    file.write_all(&vec![0x5Au8; SIZE as usize]).unwrap();
+    file.sync_all().unwrap();
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    path
+}
+
+async fn resident_after_warming(test: &str, strategy: Strategy, drop_caches: DropCaches) -> Option<u64> {
+    let registry = StrategyRegistry::builtin();
+    if !registry.is_available(strategy) {
+        return None;
+    }
+    let path = cold_file(test, &format!("{}-{}", strategy, drop_caches));
+    if residency::scan(&path, SIZE).ok()?.resident_bytes != 0 {
+        eprintln!("skipping {}: could not evict the test file from the page cache", test);
+        return None;
+    }
+
+    let options = WarmingOptions { strategy, fallback: FallbackPolicy::None, drop_caches, ..Default::default() };
+    let result = registry.warm(&path, SIZE, &options).await.unwrap();
+    assert!(result.success, "{} failed under drop policy {}", strategy, drop_caches);
+    Some(residency::scan(&path, SIZE).unwrap().resident_bytes)
+}
+
+#[tokio::test]
+async fn file_policy_drops_each_file() {
+    // readahead(2) only queues the I/O and pages still under read can't be dropped, so
+    // only the synchronous read path can be held to "nothing left behind"
+    for strategy in [Strategy::Tokio] {
+        if let Some(resident) = resident_after_warming("file", strategy, DropCaches::File).await {
+            assert_eq!(resident, 0, "{} left {} bytes cached with --drop-caches-after file", strategy, resident);
+        }
+    }
+}
+
+#[tokio::test]
+async fn none_policy_leaves_pages_cached() {
+    for strategy in [Strategy::Tokio, Strategy::Readahead] {
+        if let Some(resident) = resident_after_warming("none", strategy, DropCaches::None).await {
+            assert_eq!(resident, SIZE, "{} dropped pages with --drop-caches-after none", strategy);
+        }
+    }
+}
+
+#[tokio::test]
+async fn global_policy_defers_dropping_to_the_end() {
+    for strategy in [Strategy::Tokio, Strategy::Readahead] {
+        if let Some(resident) = resident_after_warming("global", strategy, DropCaches::Global).await {
+            assert_eq!(resident, SIZE, "{} dropped pages per file with --drop-caches-after global", strategy);
+        }
+    }
+}
+
+#[test]
+fn policies_parse() {
+    assert_eq!("none".parse::<DropCaches>(), Ok(DropCaches::None));
+    assert_eq!("FILE".parse::<DropCaches>(), Ok(DropCaches::File));
+    assert_eq!("global".parse::<DropCaches>(), Ok(DropCaches::Global));
+    assert!("sometimes".parse::<DropCaches>().is_err());
+    assert_eq!(DropCaches::default(), DropCaches::File);
+    assert!(DropCaches::File.per_file() && !DropCaches::Global.per_file() && !DropCaches::None.per_file());
+    // Not invoked here: dropping the host's page cache from a test would be rude
+    let _ = warming::drop_global_page_cache;
+}
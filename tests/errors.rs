@@ -0,0 +1,123 @@
+//! Warming failures are sorted into causes and counted per cause for the end-of-run breakdown.
+
+mod common;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{
+    Coverage, ErrorCounts, FallbackPolicy, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult,
+};
+
+/// Fails files named after an errno with that errno, and warms the rest
+struct Errno;
+
+impl WarmingBackend for Errno {
+    fn strategy(&self) -> Strategy {
+        Strategy::Tokio
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(&'a self, path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            let errno = match path.file_name().unwrap().to_string_lossy().split('-').next().unwrap() {
+                "eacces" => libc::EACCES,
+                "eio" => libc::EIO,
+                "etimedout" => libc::ETIMEDOUT,
+                _ => return Ok(WarmingResult { method: "errno", success: true, duration: Duration::ZERO, bytes_read: file_size, coverage: Coverage::Full }),
+            };
+            Err(io::Error::from_raw_os_error(errno).into())
+        })
+    }
+}
+
+#[test]
+fn errors_are_classified_by_errno_before_kind() {
+    let cause = |error: io::Error| WarmingError::from(error).name();
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::EACCES)), "permission-denied");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::EPERM)), "permission-denied");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::ENOENT)), "vanished");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::ESTALE)), "vanished");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::EIO)), "device-error");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::EOPNOTSUPP)), "unsupported");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::ETIMEDOUT)), "timeout");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::EMFILE)), "resource");
+    assert_eq!(cause(io::Error::from_raw_os_error(libc::EINVAL)), "other");
+    // Errors made up by the backends themselves only have a kind
+    assert_eq!(cause(io::Error::new(io::ErrorKind::Unsupported, "io_uring setup failed")), "unsupported");
+    assert_eq!(cause(io::Error::new(io::ErrorKind::OutOfMemory, "failed to allocate")), "resource");
+    assert_eq!(cause(io::Error::other("unreadable")), "other");
+
+    let error = WarmingError::from(io::Error::from_raw_os_error(libc::EIO));
+    assert_eq!(error.to_string(), io::Error::from_raw_os_error(libc::EIO).to_string());
+    assert_eq!(io::Error::from(error).raw_os_error(), Some(libc::EIO));
+}
+
+#[test]
+fn counts_list_the_most_frequent_cause_first() {
+    let counts = ErrorCounts::default();
+    assert!(counts.snapshot().is_empty());
+    for errno in [libc::EIO, libc::EACCES, libc::EIO, libc::ETIMEDOUT, libc::EIO, libc::EACCES] {
+        counts.record(&io::Error::from_raw_os_error(errno).into());
+    }
+    assert_eq!(counts.snapshot(), vec![("device-error", 3), ("permission-denied", 2), ("timeout", 1)]);
+}
+
+#[tokio::test]
+async fn the_pipeline_counts_failed_files_per_cause() {
+    let root = common::scratch("pipeline");
+    for (prefix, count) in [("ok", 10), ("eio", 4), ("eacces", 2), ("etimedout", 1)] {
+        for i in 0..count {
+            fs::write(root.join(format!("{}-{}", prefix, i)), b"data").unwrap();
+        }
+    }
+
+    let directories = vec![root.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 4,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 4,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut registry = StrategyRegistry::new();
+    registry.register(Arc::new(Errno));
+    let summary = pipeline::run(options, PipelineContext { registry: Some(Arc::new(registry)), ..Default::default() }).await;
+
+    assert_eq!(summary.stats.totals.failed, 7);
+    assert_eq!(summary.failures_by_cause, vec![("device-error", 4), ("permission-denied", 2), ("timeout", 1)]);
+}
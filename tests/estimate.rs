@@ -0,0 +1,113 @@
+//! `estimate`: the cold fraction comes with a Wilson interval, and `--warm-below` turns
+//! it into an exit status.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::estimate::{self, EstimateOptions, SampleBy};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::PipelineOptions;
+use rust_cache_warmer::warming::WarmingOptions;
+
+fn tree(test: &str) -> PathBuf {
+    let dir = common::scratch(test);
+    fs::write(dir.join("large"), vec![1u8; 1024 * 1024]).unwrap();
+    for i in 0..9 {
+        fs::write(dir.join(format!("small{}", i)), vec![1u8; 4096]).unwrap();
+    }
+    dir
+}
+
+fn sampling(root: &Path) -> Arc<PipelineOptions> {
+    let directories = vec![root.to_path_buf()];
+    Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 4,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 100,
+        batch_bytes: 0,
+        warming: WarmingOptions::default(),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    })
+}
+
+#[test]
+fn z_scores_match_the_usual_table() {
+    for (confidence, z) in [(0.90, 1.645), (0.95, 1.960), (0.99, 2.576)] {
+        assert!((estimate::z_score(confidence) - z).abs() < 1e-3, "{}: {}", confidence, estimate::z_score(confidence));
+    }
+}
+
+#[test]
+fn wilson_intervals_stay_in_range_and_narrow_with_samples() {
+    let (low, high) = estimate::wilson_interval(0, 100, 0.95);
+    assert_eq!(low, 0.0);
+    assert!((high - 0.037).abs() < 1e-3, "{}", high);
+
+    let (low, high) = estimate::wilson_interval(100, 100, 0.95);
+    assert!((low - 0.963).abs() < 1e-3, "{}", low);
+    assert_eq!(high, 1.0);
+
+    let (low, high) = estimate::wilson_interval(50, 100, 0.95);
+    assert!((0.5 - low - (high - 0.5)).abs() < 1e-9, "symmetric around one half");
+    let (wide, narrow) = (high - low, estimate::wilson_interval(500, 1000, 0.95));
+    assert!(narrow.1 - narrow.0 < wide / 3.0);
+
+    assert_eq!(estimate::wilson_interval(0, 0, 0.95), (0.0, 1.0), "no samples, no information");
+}
+
+#[tokio::test]
+async fn every_sample_is_measured_by_bytes_and_by_files() {
+    let root = tree("library");
+    for sample_by in [SampleBy::Bytes, SampleBy::Files] {
+        let options = EstimateOptions { samples: 50, cold_threshold: Duration::from_secs(60), seed: 7, sample_by, ..Default::default() };
+        let estimate = estimate::estimate(sampling(&root), &options).await.unwrap();
+        assert_eq!((estimate.measured, estimate.failed, estimate.cold), (50, 0, 0), "{:?}", sample_by);
+        assert_eq!((estimate.files_covered, estimate.bytes_covered), (10, 1024 * 1024 + 9 * 4096));
+        assert_eq!(estimate.interval.0, 0.0);
+        assert!(estimate.interval.1 < 0.1);
+    }
+}
+
+#[test]
+fn warm_below_sets_the_exit_status() {
+    let dir = tree("cli");
+    let estimate = |cold_ms: &str| {
+        Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .args(["estimate", "--samples", "20", "--seed", "1", "--warm-below", "50", "--cold-ms", cold_ms])
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+    let output = estimate("60000");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("0 of 20 samples slower than 60.00s: 0.0% cold"), "{}", stdout);
+
+    // Every read takes longer than 0ms, so everything looks cold
+    assert_eq!(estimate("0").status.code(), Some(3));
+}
@@ -0,0 +1,139 @@
+//! Exit-code semantics: the error budget counts only attempted files, `--fail-fast`
+//! stops the pipeline at the first failure, and the binary exits 0 on a clean run and 1
+//! on bad options.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::exit;
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineSummary};
+use rust_cache_warmer::stats::GroupStats;
+use rust_cache_warmer::warming::{Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+/// Warms everything except files named `bad*`
+struct FailBad;
+
+impl WarmingBackend for FailBad {
+    fn strategy(&self) -> Strategy {
+        Strategy::Tokio
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(&'a self, path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            if path.file_name().unwrap().to_string_lossy().starts_with("bad") {
+                return Err(WarmingError::Other(std::io::Error::other("unreadable")));
+            }
+            Ok(WarmingResult { method: "fail_bad", success: true, duration: Duration::ZERO, bytes_read: file_size, coverage: Coverage::Full })
+        })
+    }
+}
+
+fn tree(test: &str, good: usize, bad: usize) -> PathBuf {
+    let dir = common::scratch(test);
+    for i in 0..good {
+        fs::write(dir.join(format!("good{}", i)), b"data").unwrap();
+    }
+    for i in 0..bad {
+        fs::write(dir.join(format!("bad{}", i)), b"data").unwrap();
+    }
+    dir
+}
+
+async fn run(root: &Path, fail_fast: bool) -> PipelineSummary {
+    let directories = vec![root.to_path_buf()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 1,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 1,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut registry = StrategyRegistry::new();
+    registry.register(Arc::new(FailBad));
+    pipeline::run(options, PipelineContext { registry: Some(Arc::new(registry)), ..Default::default() }).await
+}
+
+#[test]
+fn the_error_budget_ignores_skipped_files() {
+    let totals = GroupStats { files: 110, failed: 5, skipped: 10, ..Default::default() };
+    assert_eq!(exit::error_percent(&totals), 5.0);
+    assert!(exit::error_budget_exceeded(&totals, 0.0));
+    assert!(exit::error_budget_exceeded(&totals, 4.9));
+    assert!(!exit::error_budget_exceeded(&totals, 5.0));
+
+    let clean = GroupStats { files: 10, skipped: 10, ..Default::default() };
+    assert_eq!(exit::error_percent(&clean), 0.0);
+    assert!(!exit::error_budget_exceeded(&clean, 0.0));
+}
+
+#[tokio::test]
+async fn failures_are_counted_without_fail_fast() {
+    let root = tree("counted", 45, 5);
+    let summary = run(&root, false).await;
+    assert!(!summary.failed_fast);
+    assert_eq!(summary.stats.totals.files, 50);
+    assert_eq!(summary.stats.totals.failed, 5);
+    assert!((exit::error_percent(&summary.stats.totals) - 10.0).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn fail_fast_stops_at_the_first_failure() {
+    // One file at a time, so nothing is in flight when the first bad file fails
+    let root = tree("fail_fast", 200, 2);
+    let summary = run(&root, true).await;
+    assert!(summary.failed_fast);
+    assert!(!summary.interrupted, "fail-fast isn't a signal");
+    assert_eq!(summary.stats.totals.failed, 1, "the second bad file was never attempted");
+    assert!(summary.stats.totals.files < 202);
+}
+
+#[test]
+fn the_binary_exits_zero_on_success_and_one_on_bad_options() {
+    let root = tree("binary", 3, 0);
+    let status = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state")).arg(&root).output().unwrap().status;
+    assert_eq!(status.code(), Some(exit::SUCCESS));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--max-error-percent", "150"])
+        .arg(&root)
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(status.code(), Some(exit::FATAL));
+}
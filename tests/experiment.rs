@@ -0,0 +1,127 @@
+//! `--ab-test`: files are split between strategies by weight, every file is counted
+//! against the arm that warmed it, and the report only names a winner when the
+//! confidence intervals separate.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::experiment::{ArmStats, Experiment, ExperimentReport, RunningStats, Split};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+#[test]
+fn files_are_assigned_in_proportion_to_the_split() {
+    let experiment = Experiment::new(&[Strategy::Readahead, Strategy::Tokio], &[70, 30], &WarmingOptions::default(), 42).unwrap();
+    let paths: Vec<PathBuf> = (0..10_000).map(|i| PathBuf::from(format!("/data/shard-{}/part-{:05}.parquet", i % 7, i))).collect();
+    let first = paths.iter().filter(|path| experiment.assign(path) == 0).count();
+    assert!((6_700..7_300).contains(&first), "{} of 10000 files went to the 70% arm", first);
+
+    let again = Experiment::new(&[Strategy::Readahead, Strategy::Tokio], &[70, 30], &WarmingOptions::default(), 42).unwrap();
+    assert!(paths.iter().all(|path| experiment.assign(path) == again.assign(path)), "the same seed splits the same way");
+
+    assert_eq!(experiment.options(1).strategy, Strategy::Tokio);
+    assert_eq!(experiment.options(1).fallback, FallbackPolicy::None, "arms never fall back to another strategy");
+}
+
+#[test]
+fn bad_experiments_are_rejected() {
+    let base = WarmingOptions::default();
+    assert!(Experiment::new(&[Strategy::Tokio], &[], &base, 0).is_err());
+    assert!(Experiment::new(&[Strategy::Auto, Strategy::Tokio], &[], &base, 0).is_err());
+    assert!(Experiment::new(&[Strategy::Fadvise, Strategy::Tokio], &[1, 2, 3], &base, 0).is_err());
+
+    assert_eq!("70/30".parse::<Split>(), Ok(Split(vec![70, 30])));
+    assert!("70:30".parse::<Split>().is_err());
+    assert!("0/0".parse::<Split>().is_err());
+}
+
+#[test]
+fn running_stats_match_the_textbook_formulas() {
+    let mut stats = RunningStats::default();
+    for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+        stats.push(value);
+    }
+    assert_eq!(stats.count, 8);
+    assert!((stats.mean - 5.0).abs() < 1e-9);
+    assert!((stats.std_dev() - (32.0f64 / 7.0).sqrt()).abs() < 1e-9);
+    assert!((stats.ci95() - 1.96 * stats.std_dev() / 8f64.sqrt()).abs() < 1e-9);
+    assert!(RunningStats::default().ci95().is_infinite());
+}
+
+fn arm(latencies_ms: &[f64]) -> ArmStats {
+    let mut stats = ArmStats::default();
+    for &latency in latencies_ms {
+        stats.files += 1;
+        stats.latency_ms.push(latency);
+    }
+    stats
+}
+
+#[test]
+fn a_winner_needs_separated_confidence_intervals() {
+    let fast = arm(&[1.0, 1.1, 0.9, 1.0, 1.05, 0.95]);
+    let slow = arm(&[3.0, 3.2, 2.9, 3.1, 3.0, 2.8]);
+    let noisy = arm(&[0.5, 6.0, 1.0, 4.0, 0.2, 5.0]);
+
+    let report = ExperimentReport { arms: vec![(Strategy::Tokio, 1, slow), (Strategy::Readahead, 1, fast)] };
+    assert_eq!(report.clear_winner(), Some(Strategy::Readahead));
+    assert!(report.to_string().contains("readahead has the lowest per-file latency"), "{}", report);
+
+    let report = ExperimentReport { arms: vec![(Strategy::Tokio, 1, noisy), (Strategy::Readahead, 1, fast)] };
+    assert_eq!(report.clear_winner(), None);
+}
+
+#[tokio::test]
+async fn every_file_is_counted_against_its_arm() {
+    let root = common::scratch("arms");
+    for i in 0..100 {
+        fs::write(root.join(format!("{}.bin", i)), vec![1u8; 1000]).unwrap();
+    }
+
+    let directories = vec![root.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 4,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 10,
+        batch_bytes: 0,
+        warming: WarmingOptions::default(),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let experiment = Arc::new(Experiment::new(&[Strategy::Tokio, Strategy::Fadvise], &[], &options.warming, 7).unwrap());
+    let context = PipelineContext { experiment: Some(Arc::clone(&experiment)), ..Default::default() };
+    let summary = pipeline::run(options, context).await;
+    assert_eq!(summary.files_processed, 100);
+
+    let report = experiment.report();
+    let files: u64 = report.arms.iter().map(|(_, _, stats)| stats.files).sum();
+    assert_eq!(files, 100);
+    assert!(report.arms.iter().all(|(_, _, stats)| stats.files > 20), "{:?}", report.arms);
+    let (_, _, tokio) = &report.arms[0];
+    assert_eq!(tokio.bytes, tokio.files * 1000, "tokio reads every byte of its files");
+    assert!(tokio.busy > Duration::ZERO);
+}
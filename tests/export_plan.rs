@@ -0,0 +1,80 @@
+//! `export-plan`: shard manifests balanced by bytes or by on-disk layout.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_cache_warmer::plan::{self, Balance, PlannedFile};
+
+fn file(path: &str, size: u64, physical: Option<u64>) -> PlannedFile {
+    PlannedFile { path: PathBuf::from(path), size, physical }
+}
+
+#[test]
+fn bytes_deals_the_largest_files_to_the_lightest_shard() {
+    let files = vec![file("/d/a", 70, None), file("/d/b", 50, None), file("/d/c", 40, None), file("/d/d", 30, None), file("/d/e", 10, None)];
+    let planned = plan::plan(files, 2, Balance::Bytes);
+    assert_eq!(planned.iter().map(|shard| shard.bytes).collect::<Vec<_>>(), [100, 100]);
+    let paths: Vec<Vec<&str>> = planned.iter().map(|shard| shard.files.iter().map(|f| f.path.to_str().unwrap()).collect()).collect();
+    assert_eq!(paths, [vec!["/d/a", "/d/d"], vec!["/d/b", "/d/c", "/d/e"]]);
+
+    // More shards than files leaves some empty
+    assert_eq!(plan::plan(vec![file("/d/a", 1, None)], 3, Balance::Bytes).iter().filter(|shard| shard.files.is_empty()).count(), 2);
+}
+
+#[test]
+fn layout_cuts_the_on_disk_order_into_contiguous_runs() {
+    let files = vec![
+        file("/d/late", 200, Some(9000)),
+        file("/d/unknown", 100, None),
+        file("/d/early", 100, Some(0)),
+        file("/d/middle", 100, Some(4000)),
+        file("/d/big", 400, Some(5000)),
+    ];
+    let planned = plan::plan(files, 2, Balance::Layout);
+    let paths: Vec<Vec<&str>> = planned.iter().map(|shard| shard.files.iter().map(|f| f.path.to_str().unwrap()).collect()).collect();
+    assert_eq!(paths, [vec!["/d/early", "/d/middle", "/d/big"], vec!["/d/late", "/d/unknown"]]);
+    assert_eq!((planned[0].bytes, planned[1].bytes), (600, 300));
+
+    assert_eq!("Bytes".parse::<Balance>(), Ok(Balance::Bytes));
+    assert!("size".parse::<Balance>().is_err());
+}
+
+#[test]
+fn export_plan_writes_manifests_files_from_reads() {
+    let root = common::scratch("manifests");
+    let data = root.join("data");
+    fs::create_dir_all(data.join("tables")).unwrap();
+    for (name, size) in [("tables/a.db", 40960), ("tables/b.db", 20480), ("c.log", 12288), ("d.log", 8192), ("skip.tmp", 4096)] {
+        fs::write(data.join(name), vec![1u8; size]).unwrap();
+    }
+    let output = root.join("plan");
+    let warmer = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"));
+        command.env("XDG_STATE_HOME", root.join("state"));
+        command
+    };
+
+    let result = warmer().arg("export-plan").arg(&data).args(["--shards", "2", "--balance", "bytes", "--exclude", "*.tmp", "--output"]).arg(&output).output().unwrap();
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert!(String::from_utf8_lossy(&result.stdout).contains("Planned 4 files"));
+
+    let manifest = fs::read_to_string(output.join("shard-0-of-2.txt")).unwrap();
+    assert_eq!(manifest.lines().skip(1).collect::<Vec<_>>(), ["tables/a.db"]);
+    assert!(manifest.starts_with("# Shard 0 of 2: 1 files, 40960 bytes, balanced by bytes"));
+    let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(output.join("plan.json")).unwrap()).unwrap();
+    assert_eq!((summary["balance"].as_str(), summary["files"].as_u64(), summary["bytes"].as_u64()), (Some("bytes"), Some(4), Some(81920)));
+    assert_eq!(summary["shards"][1]["manifest"], "shard-1-of-2.txt");
+    assert_eq!(summary["shards"][1]["bytes"], 40960);
+
+    // A shard's manifest warms just its files
+    let result = warmer().arg("--files-from").arg(output.join("shard-1-of-2.txt")).arg(&data).output().unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(result.status.success(), "{}", stderr);
+    assert!(stderr.contains("Warmed 40960 bytes (0.04 MB) across 3 files"), "{}", stderr);
+
+    let result = warmer().arg("export-plan").arg(&data).args(["--shards", "0", "--output"]).arg(&output).output().unwrap();
+    assert!(!result.status.success());
+}
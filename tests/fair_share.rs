@@ -0,0 +1,200 @@
+//! `--fair-share`: target directories (or volumes) split the queue depth by weight instead
+//! of being warmed one after another, optionally capped per share.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::{self, FairShareOptions, Share, ShareBy, ShareWeight};
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineSummary};
+use rust_cache_warmer::warming::{Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+/// Takes a couple of milliseconds per file and remembers the most files warmed at once
+#[derive(Default)]
+struct Slow {
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl WarmingBackend for Slow {
+    fn strategy(&self) -> Strategy {
+        Strategy::Tokio
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(&'a self, _path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(WarmingResult { method: "slow", success: true, duration: Duration::ZERO, bytes_read: file_size, coverage: Coverage::Full })
+        })
+    }
+}
+
+/// `a/` and `b/` under a fresh directory, with `files` files each
+fn tree(test: &str, files: usize) -> PathBuf {
+    let dir = common::scratch(test);
+    for sub in ["a", "b"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+        for i in 0..files {
+            fs::write(dir.join(sub).join(format!("f{}", i)), b"data").unwrap();
+        }
+    }
+    dir
+}
+
+/// Warm `a/` and `b/` under `root`; returns the summary, the directory (`a` or `b`) of
+/// every file in the order they finished, and the backend's peak concurrency
+async fn run(root: &Path, queue_depth: usize, fair_share: FairShareOptions) -> (PipelineSummary, Vec<String>, usize) {
+    let directories = vec![root.join("a"), root.join("b")];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 1,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share,
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let backend = Arc::new(Slow::default());
+    let mut registry = StrategyRegistry::new();
+    registry.register(backend.clone());
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let context = PipelineContext { events, registry: Some(Arc::new(registry)), ..Default::default() };
+    let summary = pipeline::run(options, context).await;
+
+    let mut order = Vec::new();
+    while let Some(WarmEvent::FileFinished { path, .. }) = finished.recv().await {
+        order.push(path.parent().unwrap().file_name().unwrap().to_string_lossy().into_owned());
+    }
+    (summary, order, backend.peak.load(Ordering::SeqCst))
+}
+
+fn count(order: &[String], dir: &str) -> usize {
+    order.iter().filter(|d| *d == dir).count()
+}
+
+#[test]
+fn options_parse() {
+    assert_eq!("mount".parse::<ShareBy>().unwrap(), ShareBy::Mount);
+    assert_eq!("dir".parse::<ShareBy>().unwrap(), ShareBy::Directory);
+    assert_eq!("none".parse::<ShareBy>().unwrap(), ShareBy::None);
+    assert!("disk".parse::<ShareBy>().is_err());
+
+    let weight: ShareWeight = "/mnt/a=b=3".parse().unwrap();
+    assert_eq!(weight, ShareWeight { dir: PathBuf::from("/mnt/a=b"), weight: 3 });
+    assert!("/mnt/a".parse::<ShareWeight>().is_err());
+    assert!("/mnt/a=0".parse::<ShareWeight>().is_err());
+    assert!("/mnt/a=x".parse::<ShareWeight>().is_err());
+}
+
+#[test]
+fn directories_are_grouped_per_setting() {
+    let root = tree("grouping", 0);
+    let directories = vec![root.join("a"), root.join("b")];
+    let weights = vec![ShareWeight { dir: root.join("b"), weight: 4 }];
+
+    let by_dir = fair::shares(&directories, &FairShareOptions { by: ShareBy::Directory, weights: weights.clone(), queue_depth: 0 });
+    assert_eq!(by_dir.len(), 2);
+    assert_eq!((by_dir[0].roots.clone(), by_dir[0].weight), (vec![root.join("a")], 1));
+    assert_eq!((by_dir[1].roots.clone(), by_dir[1].weight), (vec![root.join("b")], 4));
+
+    // Both live on the same filesystem, so they form one volume with the larger weight
+    let by_mount = fair::shares(&directories, &FairShareOptions { by: ShareBy::Mount, weights, queue_depth: 0 });
+    assert_eq!(by_mount.len(), 1);
+    assert_eq!(by_mount[0].roots, directories);
+    assert_eq!(by_mount[0].weight, 4);
+
+    assert_eq!(fair::shares(&directories, &FairShareOptions { by: ShareBy::None, ..Default::default() }).len(), 1);
+}
+
+#[test]
+fn the_least_served_share_goes_first() {
+    let shares = [Arc::new(Share::new(vec![PathBuf::from("/a")], 1)), Arc::new(Share::new(vec![PathBuf::from("/b")], 3))];
+    shares[0].batch_started();
+    for _ in 0..2 {
+        shares[1].batch_started();
+    }
+    // 1 in flight per unit of weight against 2/3
+    assert_eq!(fair::dispatch_order(&shares, 0), vec![1, 0]);
+    // At the per-share cap, a share gets nothing more
+    assert_eq!(fair::dispatch_order(&shares, 2), vec![0]);
+
+    // Equal in-flight batches: fewer bytes warmed per unit of weight wins
+    shares[1].batch_started();
+    shares[0].file_warmed(100);
+    shares[1].file_warmed(200);
+    assert_eq!(fair::dispatch_order(&shares, 0), vec![1, 0]);
+}
+
+#[tokio::test]
+async fn directories_are_warmed_side_by_side() {
+    let root = tree("side_by_side", 40);
+    let (summary, order, _) = run(&root, 2, FairShareOptions { by: ShareBy::Directory, ..Default::default() }).await;
+    assert_eq!(order.len(), 80);
+    let early = &order[..20];
+    assert!(count(early, "a") >= 5 && count(early, "b") >= 5, "{:?}", early);
+
+    assert_eq!(summary.shares.len(), 2);
+    assert_eq!((summary.shares[0].files, summary.shares[0].bytes), (40, 160));
+    assert_eq!((summary.shares[1].files, summary.shares[1].bytes), (40, 160));
+    assert_eq!(summary.files_discovered, 80);
+
+    // Without fair sharing the second directory waits for the first
+    let (_, order, _) = run(&root, 2, FairShareOptions { by: ShareBy::None, ..Default::default() }).await;
+    assert_eq!(count(&order[..20], "a"), 20);
+}
+
+#[tokio::test]
+async fn queue_depth_is_split_by_weight() {
+    let root = tree("weighted", 60);
+    let weights = vec![ShareWeight { dir: root.join("a"), weight: 3 }];
+    let (_, order, _) = run(&root, 4, FairShareOptions { by: ShareBy::Directory, weights, queue_depth: 0 }).await;
+    let early = &order[..40];
+    assert!(count(early, "a") > 2 * count(early, "b"), "{:?}", early);
+    assert!(count(early, "b") > 0, "{:?}", early);
+}
+
+#[tokio::test]
+async fn the_per_share_cap_limits_concurrency() {
+    let root = tree("capped", 20);
+    let (summary, order, peak) = run(&root, 8, FairShareOptions { by: ShareBy::Directory, weights: Vec::new(), queue_depth: 1 }).await;
+    assert_eq!(order.len(), 40);
+    assert_eq!(summary.files_processed, 40);
+    assert!(peak <= 2, "{} files warmed at once", peak);
+}
@@ -0,0 +1,92 @@
+//! `--max-open-files`: files wait for a descriptor instead of failing with EMFILE.
+
+mod common;
+
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use rust_cache_warmer::warming::fds::{self, FdBudget, NofileLimit};
+
+#[tokio::test]
+async fn files_wait_for_a_returned_descriptor() {
+    let budget = FdBudget::new(2);
+    let first = budget.acquire().await;
+    let _second = budget.acquire().await;
+    assert!(budget.try_acquire().is_none());
+
+    let waiting = tokio::spawn({
+        let budget = budget.clone();
+        async move { budget.acquire().await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished());
+    drop(first);
+    let _third = tokio::time::timeout(Duration::from_secs(5), waiting).await.unwrap().unwrap();
+
+    let stats = budget.stats();
+    assert_eq!((stats.limit, stats.in_use, stats.peak, stats.waits), (2, 2, 2, 1));
+}
+
+#[test]
+fn running_out_shrinks_the_budget() {
+    let budget = FdBudget::new(0);
+    let mut held: Vec<_> = (0..5).map(|_| budget.try_acquire().unwrap()).collect();
+    let failed = budget.try_acquire().unwrap();
+    // Five others were open when the sixth ran out
+    assert_eq!(budget.exhausted(failed), Some(5));
+    assert!(budget.try_acquire().is_none());
+    // Running out under a budget already below what's open leaves it be
+    budget.set_limit(3);
+    assert_eq!(budget.exhausted(held.pop().unwrap()), None);
+    assert_eq!((budget.limit(), budget.stats().exhausted), (3, 2));
+    held.truncate(2);
+    assert!(budget.try_acquire().is_some());
+
+    assert!(fds::is_exhaustion(&std::io::Error::from_raw_os_error(libc::EMFILE)));
+    assert!(!fds::is_exhaustion(&std::io::Error::from_raw_os_error(libc::EIO)));
+}
+
+#[test]
+fn limits_leave_a_reserve() {
+    assert_eq!(fds::reserve(64), 16);
+    assert_eq!(fds::reserve(1_048_576), fds::FD_RESERVE);
+    let limit = NofileLimit { before: 1024, soft: 4096, hard: 4096 };
+    assert!(limit.raised());
+    assert_eq!(limit.available(), 4096 - fds::FD_RESERVE);
+
+    // Already enough: left alone
+    let current = fds::raise_limit(1).unwrap();
+    assert!(!current.raised() && current.soft <= current.hard);
+}
+
+#[test]
+fn a_low_ulimit_makes_files_wait() {
+    let dir = common::scratch("ulimit");
+    for i in 0..300 {
+        fs::write(dir.join(format!("f{}", i)), vec![1u8; 4096]).unwrap();
+    }
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"));
+    command
+        .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--queue-depth", "200", "--batch-size", "1", "--strategy", "tokio", "--no-auto-tune"])
+        .arg(&dir);
+    // A hard limit of 128 that the warmer can't raise
+    unsafe {
+        command.pre_exec(|| {
+            let limit = libc::rlimit { rlim_cur: 128, rlim_max: 128 };
+            if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let output = command.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("At most 96 files open at once (ulimit -n 128)"));
+    assert!(!stderr.contains("Too many open files"), "{}", stderr);
+}
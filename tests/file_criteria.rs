@@ -0,0 +1,90 @@
+//! `--min-file-size`, `--newer-than`, `--older-than` and `--file-type`: discovery filters
+//! on a file's size, age and kind.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rust_cache_warmer::filters::{self, FileCriteria, FileKind};
+
+const DAY: Duration = Duration::from_secs(86_400);
+
+/// `old.db` last modified 30 days ago, `new.db` and `tiny.db` now, `holes.img` sparse,
+/// and `link.db` a symlink to `new.db`
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    fs::write(root.join("old.db"), vec![1u8; 8192]).unwrap();
+    fs::File::options().write(true).open(root.join("old.db")).unwrap().set_modified(SystemTime::now() - 30 * DAY).unwrap();
+    fs::write(root.join("new.db"), vec![1u8; 8192]).unwrap();
+    fs::write(root.join("tiny.db"), b"tiny").unwrap();
+    fs::File::create(root.join("holes.img")).unwrap().set_len(1 << 20).unwrap();
+    std::os::unix::fs::symlink(root.join("new.db"), root.join("link.db")).unwrap();
+    root
+}
+
+#[test]
+fn times_and_types_parse() {
+    assert_eq!(filters::parse_time_bound("2025-10-17T02:00:00Z"), Ok(UNIX_EPOCH + Duration::from_secs(1_760_666_400)));
+    let week_ago = filters::parse_time_bound("7d").unwrap();
+    let expected = SystemTime::now() - 7 * DAY;
+    assert!(week_ago <= expected && expected.duration_since(week_ago).unwrap() < Duration::from_secs(60));
+    assert!(filters::parse_time_bound("last week").is_err());
+
+    assert_eq!("f".parse(), Ok(FileKind::Regular));
+    assert_eq!("L".parse(), Ok(FileKind::Symlink));
+    assert_eq!("sparse".parse(), Ok(FileKind::Sparse));
+    assert!("d".parse::<FileKind>().is_err());
+}
+
+#[test]
+fn files_are_matched_by_size_age_and_kind() {
+    let root = tree("matches");
+    let metadata = |name: &str| fs::metadata(root.join(name)).unwrap();
+    assert!(FileCriteria::default().is_empty());
+
+    let recent = FileCriteria { min_size: 4096, newer_than: Some(SystemTime::now() - 7 * DAY), ..Default::default() };
+    assert!(recent.matches(&metadata("new.db"), false));
+    assert!(!recent.matches(&metadata("old.db"), false), "too old");
+    assert!(!recent.matches(&metadata("tiny.db"), false), "too small");
+
+    let stale = FileCriteria { older_than: Some(SystemTime::now() - 7 * DAY), ..Default::default() };
+    assert!(stale.matches(&metadata("old.db"), false) && !stale.matches(&metadata("new.db"), false));
+
+    let sparse = FileCriteria { kinds: vec![FileKind::Sparse], ..Default::default() };
+    assert!(sparse.matches(&metadata("holes.img"), false) && !sparse.matches(&metadata("new.db"), false));
+    let linked = FileCriteria { kinds: vec![FileKind::Symlink], ..Default::default() };
+    assert!(linked.matches(&metadata("link.db"), true) && !linked.matches(&metadata("new.db"), false));
+    let regular = FileCriteria { kinds: vec![FileKind::Regular], ..Default::default() };
+    assert!(regular.matches(&metadata("new.db"), false) && !regular.matches(&metadata("link.db"), true));
+}
+
+#[test]
+fn warming_keeps_to_the_matching_files() {
+    let root = tree("cli");
+    let warm = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+            .args(args)
+            .arg(&root)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert!(output.status.success(), "{}", stderr);
+        stderr
+    };
+
+    let stderr = warm(&["--newer-than", "7d", "--min-file-size", "4096", "--include", "*.db"]);
+    assert!(stderr.contains("Warmed 8192 bytes (0.01 MB) across 1 files"), "{}", stderr);
+    let stderr = warm(&["--older-than", "7d"]);
+    assert!(stderr.contains("Warmed 8192 bytes (0.01 MB) across 1 files"), "{}", stderr);
+    let stderr = warm(&["--file-type", "s"]);
+    assert!(stderr.contains("across 1 files"), "{}", stderr);
+    let stderr = warm(&["--file-type", "l", "--follow-symlinks"]);
+    assert!(stderr.contains("Warmed 8192 bytes (0.01 MB) across 1 files"), "{}", stderr);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).args(["--newer-than", "1d", "--older-than", "7d"]).arg(&root).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--newer-than is not earlier than --older-than"));
+}
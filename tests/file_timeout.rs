@@ -0,0 +1,169 @@
+//! `--file-timeout` gives up on hung reads and the run moves on; `--hung-io-warning`
+//! notices when nothing finishes.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::introspect::{InFlightFile, IntrospectionSnapshot};
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{
+    Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult, FILE_TIMEOUT_CHUNK,
+};
+use rust_cache_warmer::watchdog::Watchdog;
+use tokio::time::Instant;
+
+/// Never finishes files named `hang*`, like a read stuck on a dead volume
+struct Hangs;
+
+impl WarmingBackend for Hangs {
+    fn strategy(&self) -> Strategy {
+        Strategy::Tokio
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(&'a self, path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            if path.file_name().unwrap().to_string_lossy().starts_with("hang") {
+                std::future::pending::<()>().await;
+            }
+            Ok(WarmingResult { method: "hangs", success: true, duration: Duration::ZERO, bytes_read: file_size, coverage: Coverage::Full })
+        })
+    }
+}
+
+fn registry() -> Arc<StrategyRegistry> {
+    let mut registry = StrategyRegistry::new();
+    registry.register(Arc::new(Hangs));
+    Arc::new(registry)
+}
+
+fn options(file_timeout: Option<Duration>) -> WarmingOptions {
+    WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, file_timeout, ..Default::default() }
+}
+
+#[test]
+fn huge_files_get_the_timeout_per_gib() {
+    let options = options(Some(Duration::from_secs(10)));
+    assert_eq!(options.timeout_for(0), Some(Duration::from_secs(10)));
+    assert_eq!(options.timeout_for(FILE_TIMEOUT_CHUNK), Some(Duration::from_secs(10)));
+    assert_eq!(options.timeout_for(FILE_TIMEOUT_CHUNK + 1), Some(Duration::from_secs(20)));
+    assert_eq!(options.timeout_for(10 * FILE_TIMEOUT_CHUNK), Some(Duration::from_secs(100)));
+    assert_eq!(self::options(None).timeout_for(FILE_TIMEOUT_CHUNK), None);
+}
+
+#[tokio::test]
+async fn a_hung_read_times_out() {
+    let registry = registry();
+    let path = PathBuf::from("/nonexistent/hang");
+    let start = std::time::Instant::now();
+    let err = registry.warm(&path, 4096, &options(Some(Duration::from_millis(200)))).await.unwrap_err();
+    assert!(matches!(err, WarmingError::Timeout(_)), "{}", err);
+    assert_eq!(err.io().kind(), std::io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // Without a timeout it would wait forever
+    let untimed = options(None);
+    tokio::select! {
+        _ = registry.warm(&path, 4096, &untimed) => panic!("a hung read finished"),
+        _ = tokio::time::sleep(Duration::from_millis(200)) => {}
+    }
+}
+
+#[tokio::test]
+async fn timed_out_files_are_listed_and_the_rest_are_warmed() {
+    let root = common::scratch("pipeline");
+    for name in ["hang-a", "hang-b", "ok-1", "ok-2", "ok-3", "ok-4"] {
+        fs::write(root.join(name), b"data").unwrap();
+    }
+
+    let directories = vec![root.clone()];
+    let pipeline_options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 1,
+        batch_bytes: 0,
+        warming: options(Some(Duration::from_millis(100))),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let context = PipelineContext { registry: Some(registry()), ..Default::default() };
+    let summary = tokio::time::timeout(Duration::from_secs(10), pipeline::run(pipeline_options, context)).await.unwrap();
+
+    assert_eq!(summary.stats.totals.files, 6);
+    assert_eq!(summary.stats.totals.failed, 2);
+    let mut timed_out = summary.timed_out.clone();
+    timed_out.sort();
+    assert_eq!(timed_out, vec![root.join("hang-a"), root.join("hang-b")]);
+    assert_eq!(summary.failures_by_cause, vec![("timeout", 2)]);
+}
+
+fn snapshot(finished: u64, in_flight: usize) -> IntrospectionSnapshot {
+    IntrospectionSnapshot {
+        uptime: Duration::ZERO,
+        discovered: 10,
+        discovery_done: true,
+        queued: 0,
+        waiting: 0,
+        active: 0,
+        finished,
+        in_flight: (0..in_flight)
+            .map(|n| InFlightFile { path: PathBuf::from(format!("/data/{}", n)), size: 1024, elapsed: Duration::from_secs(90 - n as u64) })
+            .collect(),
+    }
+}
+
+#[test]
+fn the_watchdog_reports_once_per_threshold_without_completions() {
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut watchdog = Watchdog::new(Duration::from_secs(60), start);
+
+    assert!(watchdog.check(&snapshot(3, 8), at(10)).is_none());
+    assert!(watchdog.check(&snapshot(3, 8), at(69)).is_none());
+    let hang = watchdog.check(&snapshot(3, 8), at(70)).unwrap();
+    assert_eq!((hang.quiet, hang.in_flight, hang.longest.len()), (Duration::from_secs(60), 8, 5));
+    assert_eq!(hang.longest[0].path, PathBuf::from("/data/0"));
+    assert!(watchdog.check(&snapshot(3, 8), at(100)).is_none(), "reported once per threshold");
+    assert!(watchdog.check(&snapshot(3, 8), at(130)).is_some());
+
+    // A completion starts the count over
+    assert!(watchdog.check(&snapshot(4, 8), at(131)).is_none());
+    assert!(watchdog.check(&snapshot(4, 8), at(190)).is_none());
+    assert!(watchdog.check(&snapshot(4, 8), at(191)).is_some());
+
+    // Nothing being read (paused, paced, or between phases) isn't a hang
+    assert!(watchdog.check(&snapshot(4, 0), at(300)).is_none());
+    assert!(watchdog.check(&snapshot(4, 2), at(359)).is_none());
+}
@@ -0,0 +1,82 @@
+//! `--one-file-system` and `--only-device`: devices are named by path or number, and a
+//! walk restricted to a device keeps its files and only passes through other
+//! filesystems on the way to one of its mounts.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+use rust_cache_warmer::filesystems::{BlockDevice, FilesystemScope};
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::layers::parse_mountinfo;
+
+/// A device number no test machine has
+const ABSENT: &str = "4095:4095";
+
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    for dir in ["mnt/data", "other"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+        fs::write(root.join(dir).join("file"), b"data").unwrap();
+    }
+    fs::write(root.join("top"), b"data").unwrap();
+    root
+}
+
+fn device_of(path: &Path) -> String {
+    let dev = fs::metadata(path).unwrap().dev() as libc::dev_t;
+    format!("{}:{}", libc::major(dev), libc::minor(dev))
+}
+
+/// Everything a walk of `root` keeps, relative to it
+fn walk(root: &Path, scope: FilesystemScope) -> BTreeSet<PathBuf> {
+    let filters = DiscoveryFilters::new(&[root.to_path_buf()], &[], &[], &[], &[]).unwrap().with_filesystems(scope);
+    let mut builder = WalkBuilder::new(root);
+    builder.hidden(false);
+    filters.apply(&mut builder, root, None);
+    builder.build().flatten().map(|entry| entry.path().strip_prefix(root).unwrap().to_path_buf()).collect()
+}
+
+#[test]
+fn devices_are_given_by_number_or_block_special_file() {
+    let device: BlockDevice = "259:1".parse().unwrap();
+    assert_eq!(device.dev, libc::makedev(259, 1) as u64);
+    assert_eq!(device.to_string(), "259:1");
+    assert!("/dev/null".parse::<BlockDevice>().is_err(), "a character device isn't a block device");
+    assert!("/no/such/device".parse::<BlockDevice>().is_err());
+}
+
+#[test]
+fn files_on_the_device_are_kept() {
+    let root = tree("same_device");
+    let device: BlockDevice = device_of(&root).parse().unwrap();
+    let scope = FilesystemScope::with_mounts(true, Some(&device), &[]);
+    assert!(scope.reaches(&root));
+    assert_eq!(walk(&root, scope.clone()).len(), 7, "everything is on one filesystem");
+
+    let filters = DiscoveryFilters::new(std::slice::from_ref(&root), &[], &[], &[], &[]).unwrap().with_filesystems(scope);
+    assert!(filters.matches_file(&root.join("other/file")));
+}
+
+#[test]
+fn other_filesystems_are_only_walked_to_reach_a_mount_of_the_device() {
+    let root = tree("other_device");
+    let device: BlockDevice = ABSENT.parse().unwrap();
+    let mountinfo = format!("40 28 {} / {} rw - ext4 /dev/absent rw\n", ABSENT, std::path::absolute(root.join("mnt/data")).unwrap().display());
+    let scope = FilesystemScope::with_mounts(false, Some(&device), &parse_mountinfo(&mountinfo));
+    assert!(scope.reaches(&root));
+    assert!(!scope.reaches(&root.join("other")));
+
+    // The test tree isn't really on the device, so no file is, but the way to its mount is walked
+    let kept: BTreeSet<PathBuf> = ["", "mnt", "mnt/data"].into_iter().map(PathBuf::from).collect();
+    assert_eq!(walk(&root, scope.clone()), kept);
+
+    let filters = DiscoveryFilters::new(std::slice::from_ref(&root), &[], &[], &[], &[]).unwrap().with_filesystems(scope);
+    assert!(!filters.matches_file(&root.join("top")));
+}
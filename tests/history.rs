@@ -0,0 +1,114 @@
+//! Run history: records round-trip through the state directory, and the latest run is
+//! flagged only when it's slower than its baseline in a way its size doesn't explain.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rust_cache_warmer::history::{self, Metric, RunRecord};
+
+use common::scratch;
+
+fn run(minute: u64, bytes: u64, duration_secs: f64) -> RunRecord {
+    RunRecord {
+        timestamp_ms: 1_760_000_000_000 + minute * 60_000,
+        targets_key: "data".to_string(),
+        targets: vec!["/data".to_string()],
+        version: "1.0.0".to_string(),
+        kernel: Some("6.1.0".to_string()),
+        strategy: "uring".to_string(),
+        files: 1000,
+        failed: 0,
+        bytes,
+        duration_secs,
+        volumes: vec!["vol-0abc".to_string()],
+        snapshot: None,
+        interrupted: false,
+    }
+}
+
+const GIB: u64 = 1 << 30;
+
+#[test]
+fn a_slower_latest_run_is_flagged_with_what_changed() {
+    let mut latest = run(4, 10 * GIB, 200.0);
+    latest.kernel = Some("6.8.0".to_string());
+    let runs = vec![run(1, 10 * GIB, 100.0), run(2, 10 * GIB, 110.0), run(3, 10 * GIB, 90.0), latest];
+    let history = history::by_targets(runs).remove(0);
+    let regressions = history.regressions(25.0);
+    assert_eq!(regressions.iter().map(|r| r.metric).collect::<Vec<_>>(), vec![Metric::Throughput, Metric::Duration]);
+    assert_eq!(regressions[1].baseline, 100.0);
+    assert_eq!(regressions[1].baseline_runs, 3);
+    assert_eq!(regressions[0].changes, vec!["kernel 6.1.0 → 6.8.0".to_string()]);
+    assert!(regressions[0].to_string().contains("50% below"), "{}", regressions[0]);
+}
+
+#[test]
+fn more_data_taking_longer_is_no_regression() {
+    let runs = vec![run(1, 10 * GIB, 100.0), run(2, 10 * GIB, 100.0), run(3, 20 * GIB, 190.0)];
+    assert!(history::by_targets(runs).remove(0).regressions(25.0).is_empty());
+}
+
+#[test]
+fn interrupted_runs_and_short_histories_are_not_compared() {
+    let mut interrupted = run(3, GIB, 100.0);
+    interrupted.interrupted = true;
+    let runs = vec![run(1, 10 * GIB, 100.0), run(2, 10 * GIB, 100.0), interrupted];
+    assert!(history::by_targets(runs).remove(0).regressions(25.0).is_empty());
+
+    let runs = vec![run(1, 10 * GIB, 100.0), run(2, 10 * GIB, 500.0)];
+    assert!(history::by_targets(runs).remove(0).regressions(25.0).is_empty(), "one earlier run is no baseline");
+}
+
+#[test]
+fn records_round_trip_and_torn_lines_are_skipped() {
+    let state = scratch("round_trip");
+    let first = run(1, GIB, 10.0);
+    let mut other = run(2, GIB, 10.0);
+    other.targets_key = "logs".to_string();
+    history::record(&state, &first).unwrap();
+    history::record(&state, &other).unwrap();
+    let path = state.join(history::HISTORY_FILE);
+    let mut text = fs::read_to_string(&path).unwrap();
+    text.push_str("{\"timestamp_ms\": 17");
+    fs::write(&path, text).unwrap();
+
+    assert_eq!(history::load(&state).unwrap(), vec![first, other]);
+    assert_eq!(history::by_targets(history::load(&state).unwrap()).len(), 2);
+    assert!(history::load(&state.join("missing")).unwrap().is_empty());
+}
+
+#[test]
+fn target_sets_match_whatever_the_order() {
+    let root = scratch("key");
+    let (a, b) = (root.join("a"), root.join("b"));
+    fs::create_dir_all(&a).unwrap();
+    fs::create_dir_all(&b).unwrap();
+    assert_eq!(history::targets_key(&[a.clone(), b.clone()]), history::targets_key(&[b.join("../b"), a.clone()]));
+    assert_ne!(history::targets_key(std::slice::from_ref(&a)), history::targets_key(&[a, b]));
+}
+
+#[test]
+fn warm_runs_show_up_in_the_history_subcommand() {
+    let root = scratch("binary");
+    let (data, state) = (root.join("data"), root.join("state"));
+    fs::create_dir_all(&data).unwrap();
+    fs::write(data.join("file"), vec![1u8; 8192]).unwrap();
+    let warmer = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"));
+        command.arg("--state-dir").arg(&state);
+        command
+    };
+    for _ in 0..2 {
+        assert!(warmer().arg(&data).output().unwrap().status.success());
+    }
+    assert!(warmer().arg("--no-history").arg(&data).output().unwrap().status.success());
+    assert_eq!(history::load(&state).unwrap().len(), 2);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).args(["history", "--state-dir"]).arg(&state).arg(&data).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("(2 runs)"), "{}", stdout);
+    assert!(stdout.contains("8.00 KiB"), "{}", stdout);
+}
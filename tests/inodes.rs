@@ -0,0 +1,93 @@
+//! `rust-cache-warmer inodes`: filesystems are recognized from their superblock, the
+//! resolvers' output is turned into device ranges, and those are read aligned, in
+//! on-disk order and without overlaps.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rust_cache_warmer::inodes::{self, Geometry, InodeExtents, Resolver};
+
+use common::scratch;
+
+#[test]
+fn inode_lists_allow_commas_and_comments() {
+    assert_eq!(inodes::parse_inode_list("12 13,14\n# hot tables\n15 # and one more\n"), Ok(vec![12, 13, 14, 15]));
+    assert!(inodes::parse_inode_list("12\n/data/file\n").unwrap_err().starts_with("line 2"));
+    assert!(inodes::parse_inode_list("0").is_err(), "there is no inode 0");
+}
+
+#[test]
+fn superblocks_name_the_filesystem() {
+    let mut ext = vec![0u8; 4096];
+    ext[1080..1082].copy_from_slice(&0xEF53u16.to_le_bytes());
+    ext[1048..1052].copy_from_slice(&2u32.to_le_bytes());
+    assert_eq!(Geometry::parse(&ext), Some(Geometry::Ext { block_size: 4096 }));
+
+    let mut xfs = vec![0u8; 4096];
+    xfs[..4].copy_from_slice(b"XFSB");
+    xfs[4..8].copy_from_slice(&4096u32.to_be_bytes());
+    xfs[84..88].copy_from_slice(&65536u32.to_be_bytes());
+    let geometry = Geometry::parse(&xfs).unwrap();
+    assert_eq!(geometry, Geometry::Xfs { block_size: 4096, ag_blocks: 65536 });
+    assert_eq!(geometry.resolver(Resolver::Auto), Ok(Resolver::XfsDb));
+    assert!(geometry.resolver(Resolver::Debugfs).is_err());
+
+    assert_eq!(Geometry::parse(&[0u8; 4096]), None);
+}
+
+#[test]
+fn debugfs_blocks_become_contiguous_ranges() {
+    let output = "debugfs: blocks <12>\n100 101 102 200 \ndebugfs: blocks <13>\n\ndebugfs: blocks <14>\n7 \n";
+    let extents = inodes::parse_debugfs(output, 4096);
+    assert_eq!(
+        extents,
+        [
+            InodeExtents { inode: 12, ranges: vec![(409600, 12288), (819200, 4096)] },
+            InodeExtents { inode: 13, ranges: vec![] },
+            InodeExtents { inode: 14, ranges: vec![(28672, 4096)] },
+        ]
+    );
+}
+
+#[test]
+fn xfs_block_numbers_are_per_allocation_group() {
+    let output = "ino 131\ndata offset 0 startblock 24 (0/24) count 8 flag 0\ndata offset 8 startblock 4194305 (1/1) count 2 flag 0\ndata offset 10 startblock 40 (0/40) count 4 flag 1\nino 132\n";
+    let extents = inodes::parse_xfs_bmap(output, 4096, 1000);
+    assert_eq!(extents[0].ranges, [(24 * 4096, 8 * 4096), (1001 * 4096, 2 * 4096)], "the unwritten extent is left out");
+    assert_eq!(extents[1], InodeExtents { inode: 132, ranges: vec![] });
+}
+
+#[test]
+fn reads_are_aligned_merged_and_split() {
+    let extents = [
+        InodeExtents { inode: 2, ranges: vec![(1 << 20, 3 << 20)] },
+        // Two 1KiB blocks in one 4KiB page, and a range overlapping the first inode's
+        InodeExtents { inode: 1, ranges: vec![(1024, 1024), (2048, 1024), ((3 << 20) + 4096, 2 << 20)] },
+    ];
+    assert_eq!(inodes::plan_reads(&extents, 2 << 20), [(0, 4096), (1 << 20, 2 << 20), (3 << 20, 2 << 20), (5 << 20, 4096)]);
+}
+
+#[test]
+fn debugfs_resolves_inodes_of_an_ext4_image() {
+    let dir = scratch("ext4");
+    fs::create_dir_all(dir.join("content")).unwrap();
+    fs::write(dir.join("content/data"), vec![7u8; 40960]).unwrap();
+    let image = dir.join("image.ext4");
+    let made = Command::new("mkfs.ext4").args(["-q", "-F", "-b", "4096", "-d"]).arg(dir.join("content")).arg(&image).arg("8M").output();
+    if !made.is_ok_and(|output| output.status.success()) || Command::new("debugfs").arg("-V").output().is_err() {
+        eprintln!("mkfs.ext4 or debugfs unavailable, skipping");
+        return;
+    }
+
+    let geometry = Geometry::probe(&image).unwrap();
+    assert_eq!(geometry, Geometry::Ext { block_size: 4096 });
+    // The first inode after lost+found (11) is the only file
+    let extents = inodes::resolve(&image, &geometry, Resolver::Auto, &[12, 999]).unwrap();
+    assert_eq!(extents.iter().map(|inode| inode.ranges.iter().map(|(_, length)| length).sum::<u64>()).collect::<Vec<_>>(), [40960, 0]);
+
+    let (offset, _) = extents[0].ranges[0];
+    let on_disk = fs::read(&image).unwrap();
+    assert!(on_disk[offset as usize..offset as usize + 40960].iter().all(|&byte| byte == 7), "the ranges hold the file's data");
+}
@@ -0,0 +1,88 @@
+//! `--intra-file-parallelism`: large files are split into aligned ranges that cover the
+//! file exactly once and are read concurrently.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_cache_warmer::warming::chunked::{self, MIN_RANGE_SIZE};
+use rust_cache_warmer::warming::{StrategyRegistry, WarmingOptions};
+
+const MB: u64 = 1024 * 1024;
+
+fn assert_covers(ranges: &[(u64, u64)], file_size: u64) {
+    let mut next = 0;
+    for &(offset, length) in ranges {
+        assert_eq!(offset, next, "ranges must be contiguous: {:?}", ranges);
+        assert_eq!(offset % MB, 0, "range starts must stay aligned for O_DIRECT: {:?}", ranges);
+        next = offset + length;
+    }
+    assert_eq!(next, file_size, "ranges must end at EOF: {:?}", ranges);
+}
+
+#[test]
+fn large_files_split_into_aligned_ranges() {
+    let size = 10 * 1024 * MB + 12345;
+    let ranges = chunked::split(size, 8);
+    assert_eq!(ranges.len(), 8);
+    assert_covers(&ranges, size);
+
+    // Never more ranges than MIN_RANGE_SIZE allows
+    let ranges = chunked::split(3 * MIN_RANGE_SIZE + 1, 16);
+    assert_eq!(ranges.len(), 3);
+    assert_covers(&ranges, 3 * MIN_RANGE_SIZE + 1);
+    assert!(ranges.iter().all(|&(_, length)| length >= MIN_RANGE_SIZE / 2));
+
+    assert_eq!(chunked::split(2 * MIN_RANGE_SIZE - 1, 8), [(0, 2 * MIN_RANGE_SIZE - 1)]);
+    assert_eq!(chunked::split(100 * MIN_RANGE_SIZE, 1), [(0, 100 * MIN_RANGE_SIZE)]);
+}
+
+/// A file big enough to split, made of holes so it costs no disk space
+fn big_file(name: &str) -> (PathBuf, u64) {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("intra_file");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let size = 2 * MIN_RANGE_SIZE + 3 * MB;
+    fs::File::create(&path).unwrap().set_len(size).unwrap();
+    (path, size)
+}
+
+#[tokio::test]
+async fn big_files_are_read_in_parallel_ranges() {
+    let (path, size) = big_file("full");
+    let options = WarmingOptions { intra_file_parallelism: 4, read_holes: true, ..Default::default() };
+    let result = StrategyRegistry::builtin().warm(&path, size, &options).await.unwrap();
+    assert_eq!(result.method, "chunked_pread_full");
+    assert_eq!(result.bytes_read, size);
+
+    let (path, size) = big_file("sparse");
+    let options = WarmingOptions { sparse_large_files: MB, ..options };
+    let result = StrategyRegistry::builtin().warm(&path, size, &options).await.unwrap();
+    assert_eq!(result.method, "chunked_pread_sparse");
+    assert_eq!(result.bytes_read, size / 16, "one 4KB sample per 64KB across all ranges");
+}
+
+#[tokio::test]
+async fn direct_io_ranges_cover_the_file() {
+    let (path, size) = big_file("direct");
+    let options = WarmingOptions { intra_file_parallelism: 4, read_holes: true, use_direct_io: true, ..Default::default() };
+    match StrategyRegistry::builtin().warm(&path, size, &options).await {
+        Ok(result) => {
+            assert!(result.method.starts_with("chunked_") && result.method.contains("direct"), "{}", result.method);
+            assert_eq!(result.bytes_read, size);
+        }
+        // Filesystems such as tmpfs reject O_DIRECT
+        Err(e) if e.io().raw_os_error() == Some(libc::EINVAL) => eprintln!("skipping: O_DIRECT unsupported here"),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[tokio::test]
+async fn small_files_use_the_normal_strategies() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("intra_file");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("small");
+    fs::write(&path, vec![7u8; 4096]).unwrap();
+    let options = WarmingOptions { intra_file_parallelism: 8, ..Default::default() };
+    let result = StrategyRegistry::builtin().warm(&path, 4096, &options).await.unwrap();
+    assert!(!result.method.starts_with("chunked_"), "{}", result.method);
+}
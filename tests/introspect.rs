@@ -0,0 +1,65 @@
+//! Introspection counters must balance: once a run ends every discovered file has
+//! moved through the stages and nothing is left in flight.
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::introspect::Introspection;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::WarmingOptions;
+
+#[tokio::test]
+async fn stages_drain_by_the_end_of_a_run() {
+    let root = common::scratch("drain");
+    for dir in ["a", "b"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+        for i in 0..25 {
+            fs::write(root.join(dir).join(format!("{}.txt", i)), b"hello").unwrap();
+        }
+    }
+
+    let directories = vec![root.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 7,
+        batch_bytes: 0,
+        warming: WarmingOptions::default(),
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let introspection = Introspection::new();
+    let context = PipelineContext { introspection: introspection.clone(), ..Default::default() };
+    let summary = pipeline::run(options, context).await;
+
+    let snapshot = introspection.snapshot();
+    assert!(snapshot.discovery_done);
+    assert_eq!(snapshot.discovered, 50);
+    assert_eq!(snapshot.finished, summary.files_processed);
+    assert_eq!((snapshot.queued, snapshot.waiting, snapshot.active), (0, 0, 0));
+    assert!(snapshot.in_flight.is_empty());
+    assert!(snapshot.to_string().contains("50 files found (done)"));
+}
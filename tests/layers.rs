@@ -0,0 +1,102 @@
+//! Overlay and bind-mount targets resolve to the layer and source directories behind
+//! them, and shared layers are walked once.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_cache_warmer::layers::{self, Layer, LayerRole, MountTable, Resolution};
+
+use common::scratch;
+
+fn layer(path: &Path, role: LayerRole) -> Layer {
+    Layer { path: path.to_path_buf(), role }
+}
+
+#[test]
+fn mountinfo_is_parsed() {
+    let mounts = layers::parse_mountinfo(
+        "36 35 98:0 /mnt1 /mnt/with\\040space rw,noatime master:1 - ext3 /dev/root rw,errors=continue\n\
+         46 28 0:39 / /merged rw - overlay overlay rw,lowerdir=/l1:/l2,upperdir=/u,workdir=/w\n\
+         garbage\n",
+    );
+    assert_eq!(mounts.len(), 2);
+    assert_eq!(mounts[0].device, "98:0");
+    assert_eq!(mounts[0].root, PathBuf::from("/mnt1"));
+    assert_eq!(mounts[0].mount_point, PathBuf::from("/mnt/with space"));
+    assert_eq!(mounts[0].fs_type, "ext3");
+    assert_eq!(mounts[0].source, "/dev/root");
+    assert_eq!(mounts[1].super_options, "rw,lowerdir=/l1:/l2,upperdir=/u,workdir=/w");
+}
+
+#[test]
+fn overlay_options_list_the_layers() {
+    let (upper, lowers) = layers::overlay_layers("rw,lowerdir=/l1:/with\\:colon:/l2,upperdir=/u,workdir=/w");
+    assert_eq!(upper, Some(PathBuf::from("/u")));
+    assert_eq!(lowers, [PathBuf::from("/l1"), PathBuf::from("/with:colon"), PathBuf::from("/l2")]);
+
+    let (upper, lowers) = layers::overlay_layers("ro,lowerdir+=/l1,lowerdir+=/l2,datadir+=/data");
+    assert_eq!(upper, None);
+    assert_eq!(lowers, [PathBuf::from("/l1"), PathBuf::from("/l2"), PathBuf::from("/data")]);
+}
+
+#[test]
+fn overlay_targets_resolve_to_their_layers() {
+    let dir = scratch("overlay");
+    for layer in ["upper/app", "lower1/app", "lower2/other"] {
+        fs::create_dir_all(dir.join(layer)).unwrap();
+    }
+    let (upper, lower1, lower2) = (dir.join("upper"), dir.join("lower1"), dir.join("lower2"));
+    let mounts = MountTable::new(layers::parse_mountinfo(&format!(
+        "1 0 259:1 / / rw - xfs /dev/nvme1n1 rw\n\
+         46 1 0:39 / /merged rw - overlay overlay rw,lowerdir={}:{},upperdir={},workdir=/w\n",
+        lower1.display(),
+        lower2.display(),
+        upper.display(),
+    )));
+
+    let merged = mounts.resolve(Path::new("/merged"));
+    assert!(merged.is_resolved());
+    assert_eq!(merged.layers, [layer(&upper, LayerRole::Upper), layer(&lower1, LayerRole::Lower(1)), layer(&lower2, LayerRole::Lower(2))]);
+
+    // A subdirectory only comes from the layers that have it
+    let app = mounts.resolve(Path::new("/merged/app"));
+    assert_eq!(app.layers, [layer(&upper.join("app"), LayerRole::Upper), layer(&lower1.join("app"), LayerRole::Lower(1))]);
+
+    // Anything else is left as given
+    let plain = mounts.resolve(Path::new("relative/dir"));
+    assert!(!plain.is_resolved());
+    assert_eq!(plain.layers, [layer(Path::new("relative/dir"), LayerRole::Direct)]);
+}
+
+#[test]
+fn bind_mounts_resolve_to_their_source() {
+    let dir = scratch("bind");
+    fs::create_dir_all(dir.join("volumes/app/data")).unwrap();
+    let mounts = MountTable::new(layers::parse_mountinfo(&format!(
+        "1 0 259:1 / {} rw - xfs /dev/nvme1n1 rw\n\
+         2 1 259:1 /volumes/app /container/data rw - xfs /dev/nvme1n1 rw\n",
+        dir.display(),
+    )));
+    let resolution = mounts.resolve(Path::new("/container/data/data"));
+    assert_eq!(resolution.layers, [layer(&dir.join("volumes/app/data"), LayerRole::BindSource)]);
+
+    // Nothing else mounts that filesystem: the bind mount is all there is
+    let mounts = MountTable::new(layers::parse_mountinfo("2 1 259:2 /volumes/app /container/data rw - xfs /dev/nvme2n1 rw\n"));
+    assert!(!mounts.resolve(Path::new("/container/data")).is_resolved());
+}
+
+#[test]
+fn shared_and_nested_layers_are_walked_once() {
+    let resolution = |target: &str, paths: &[&str]| Resolution {
+        target: PathBuf::from(target),
+        layers: paths.iter().map(|path| layer(Path::new(path), LayerRole::Upper)).collect(),
+    };
+    let resolutions = [
+        resolution("/merged1", &["/layers/u1", "/layers/base/app", "/layers/base/lib"]),
+        resolution("/merged2", &["/layers/u2", "/layers/base/app"]),
+        resolution("/all", &["/layers/base"]),
+    ];
+    assert_eq!(layers::roots(&resolutions), [PathBuf::from("/layers/u1"), PathBuf::from("/layers/u2"), PathBuf::from("/layers/base")]);
+}
@@ -0,0 +1,52 @@
+//! Live statistics: totals and the per-strategy breakdown follow the event stream, and
+//! the throughput average smooths over bursts.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use rust_cache_warmer::events::WarmEvent;
+use rust_cache_warmer::live::{LiveStats, MethodStats};
+use rust_cache_warmer::stats::FileStatus;
+
+const MB: u64 = 1024 * 1024;
+
+fn finished(bytes: u64, status: FileStatus, method: Option<&'static str>) -> WarmEvent {
+    WarmEvent::FileFinished { path: PathBuf::from("/data/file"), bytes, latency: Duration::from_millis(1), status, method, bytes_read: bytes, error: None, coverage: None }
+}
+
+#[test]
+fn snapshot_breaks_totals_down_by_strategy() {
+    let mut stats = LiveStats::new();
+    for _ in 0..3 {
+        stats.record(&finished(MB, FileStatus::Warmed, Some("readahead_full")));
+    }
+    stats.record(&finished(4 * MB, FileStatus::Warmed, Some("io_uring_direct")));
+    stats.record(&finished(0, FileStatus::Failed, None));
+    stats.record(&finished(10 * MB, FileStatus::Skipped, None));
+
+    let snapshot = stats.snapshot();
+    assert_eq!((snapshot.warmed, snapshot.failed, snapshot.skipped), (4, 1, 1));
+    assert_eq!(snapshot.bytes, 7 * MB, "skipped and failed files add no bytes");
+    assert_eq!(snapshot.by_method["readahead_full"], MethodStats { files: 3, bytes: 3 * MB });
+    assert_eq!(snapshot.by_method["io_uring_direct"], MethodStats { files: 1, bytes: 4 * MB });
+
+    let text = snapshot.to_string();
+    assert!(text.contains("4 warmed, 1 failed, 1 skipped"), "{}", text);
+    assert!(text.contains("readahead_full"), "{}", text);
+}
+
+#[test]
+fn throughput_is_an_exponentially_weighted_average() {
+    let mut stats = LiveStats::new();
+    let start = Instant::now();
+
+    stats.record(&finished(100 * MB, FileStatus::Warmed, Some("tokio_full")));
+    stats.sample(start + Duration::from_secs(1));
+    let first = stats.snapshot().throughput_ewma;
+    assert!(first >= 99.0 * MB as f64, "the first sample seeds the average: {}", first);
+
+    // A stalled second pulls the average down but not to zero
+    stats.sample(start + Duration::from_secs(2));
+    let stalled = stats.snapshot().throughput_ewma;
+    assert!(stalled < first && stalled > 0.8 * first, "{} vs {}", stalled, first);
+}
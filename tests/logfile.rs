@@ -0,0 +1,175 @@
+//! Compressed and rotated logs: `*.gz`/`*.zst` checkpoint journals and result logs are
+//! compressed as they're written, split into segments at the rotation size, and read
+//! back (including after a crash) transparently.
+
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rust_cache_warmer::checkpoint::Checkpoint;
+use rust_cache_warmer::events::WarmEvent;
+use rust_cache_warmer::logfile::{self, Compression, LogReader, LogWriter};
+use rust_cache_warmer::result_log::{ResultFormat, ResultLog};
+use rust_cache_warmer::stats::{FileOutcome, FileStatus};
+
+use common::scratch;
+
+fn record(i: usize) -> String {
+    format!("{{\"path\":\"/data/file-{:06}\",\"bytes\":{}}}\n", i, i * 4096)
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+    BufReader::new(LogReader::open(path).unwrap()).lines().map(Result::unwrap).collect()
+}
+
+/// Decompress a complete gzip file in one go, checking its trailer
+fn gunzip(path: &Path) -> Vec<u8> {
+    let gz = fs::read(path).unwrap();
+    assert_eq!(&gz[..3], &[0x1f, 0x8b, 8], "{} is not gzip", path.display());
+    let data = miniz_oxide::inflate::decompress_to_vec(&gz[10..gz.len() - 8]).unwrap();
+    assert_eq!(u32::from_le_bytes(gz[gz.len() - 4..].try_into().unwrap()) as usize, data.len());
+    data
+}
+
+#[test]
+fn segments_are_named_before_the_compression_extension() {
+    assert_eq!(logfile::segment_path(Path::new("/var/log/results.ndjson.zst"), 0), PathBuf::from("/var/log/results.ndjson.zst"));
+    assert_eq!(logfile::segment_path(Path::new("/var/log/results.ndjson.zst"), 2), PathBuf::from("/var/log/results.ndjson.2.zst"));
+    assert_eq!(logfile::segment_path(Path::new("journal.gz"), 1), PathBuf::from("journal.1.gz"));
+    assert_eq!(logfile::segment_path(Path::new("results.csv"), 1), PathBuf::from("results.csv.1"));
+    assert_eq!(Compression::for_path(Path::new("a.ZST")), Compression::Zstd);
+    assert_eq!(Compression::for_path(Path::new("a.ndjson")), Compression::None);
+    assert_eq!(ResultFormat::for_path(Path::new("results.csv.gz")), ResultFormat::Csv);
+    assert_eq!(ResultFormat::for_path(Path::new("results.ndjson.zst")), ResultFormat::Ndjson);
+}
+
+#[test]
+fn gzip_logs_rotate_and_read_back_in_order() {
+    let dir = scratch("rotate");
+    let path = dir.join("results.ndjson.gz");
+
+    let mut writer = LogWriter::create(&path, 4096, None).unwrap();
+    for i in 0..5000 {
+        writer.write_record(record(i).as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let segments = logfile::segments(&path);
+    assert!(segments.len() > 1, "{:?}", segments);
+    let mut plain = 0;
+    for segment in &segments {
+        plain += gunzip(segment).len();
+    }
+    let expected: Vec<String> = (0..5000).map(|i| record(i).trim_end().to_string()).collect();
+    assert_eq!(plain, expected.iter().map(|line| line.len() + 1).sum::<usize>());
+    assert!(segments.iter().map(|s| fs::metadata(s).unwrap().len()).sum::<u64>() * 4 < plain as u64, "records compress");
+    assert_eq!(read_lines(&path), expected);
+
+    // Creating the log again replaces every old segment
+    LogWriter::create(&path, 0, None).unwrap().finish().unwrap();
+    assert_eq!(logfile::segments(&path), vec![path.clone()]);
+    assert!(read_lines(&path).is_empty());
+    logfile::remove(&path).unwrap();
+    assert!(logfile::segments(&path).is_empty());
+}
+
+#[test]
+fn flushed_records_survive_a_crash() {
+    let dir = scratch("crash");
+    let path = dir.join("journal.gz");
+    let mut writer = LogWriter::create(&path, 0, None).unwrap();
+    for i in 0..100 {
+        writer.write_record(record(i).as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+    writer.write_record(record(100).as_bytes()).unwrap();
+    // Killed before the last record was flushed or the segment finished
+    std::mem::forget(writer);
+    assert_eq!(read_lines(&path).len(), 100);
+
+    // Cut mid-block as well: the rest of the segment is lost, the next one still reads
+    let gz = fs::read(&path).unwrap();
+    fs::write(&path, &gz[..gz.len() - 20]).unwrap();
+    let mut writer = LogWriter::append(&path, 0).unwrap();
+    assert_eq!(writer.current_segment(), dir.join("journal.1.gz"));
+    writer.write_record(record(200).as_bytes()).unwrap();
+    writer.finish().unwrap();
+
+    let lines = read_lines(&path);
+    assert_eq!(lines.last().unwrap(), record(200).trim_end());
+    // All but the cut-off record just before the new segment are intact
+    assert!(lines.len() > 50, "most flushed records are still there: {}", lines.len());
+    for (i, line) in lines[..lines.len() - 2].iter().enumerate() {
+        assert_eq!(line, record(i).trim_end());
+    }
+}
+
+#[test]
+fn compressed_checkpoints_resume() {
+    let dir = scratch("checkpoint");
+    let journal = dir.join("journal.gz");
+    let outcome = FileOutcome { bytes: 4096, bytes_covered: 4096, bytes_read: 4096, latency: Duration::from_micros(10), status: FileStatus::Warmed };
+
+    let checkpoint = Checkpoint::open_rotating(&journal, 512).unwrap();
+    for i in 0..40 {
+        checkpoint.record(&dir.join(format!("file-{}", i)), &outcome);
+    }
+    checkpoint.flush();
+    drop(checkpoint);
+    assert!(logfile::segments(&journal).len() > 1);
+
+    let checkpoint = Checkpoint::open_rotating(&journal, 512).unwrap();
+    assert_eq!(checkpoint.completed_files(), 40);
+    assert!(checkpoint.is_completed(&dir.join("file-39")));
+    checkpoint.record(&dir.join("file-40"), &outcome);
+    checkpoint.finish();
+    assert_eq!(Checkpoint::open(&journal).unwrap().completed_files(), 41);
+}
+
+#[test]
+fn compressed_csv_segments_each_get_the_header() {
+    let dir = scratch("csv");
+    let path = dir.join("results.csv.gz");
+    let mut log = ResultLog::create_rotating(&path, 256).unwrap();
+    for i in 0..50 {
+        log.write(&WarmEvent::FileFinished {
+            path: PathBuf::from(format!("/data/{}", i)),
+            bytes: 10,
+            latency: Duration::from_micros(15),
+            status: FileStatus::Warmed,
+            method: Some("tokio_full"),
+            bytes_read: 10,
+            error: None,
+            coverage: None,
+        })
+        .unwrap();
+    }
+    log.finish().unwrap();
+
+    let segments = logfile::segments(&path);
+    assert!(segments.len() > 1);
+    for segment in &segments {
+        assert!(gunzip(segment).starts_with(b"path,size,status,"), "{}", segment.display());
+    }
+    let rows = read_lines(&path).into_iter().filter(|line| line.starts_with("/data/")).count();
+    assert_eq!(rows, 50);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_logs_round_trip() {
+    let dir = scratch("zstd");
+    let path = dir.join("results.ndjson.zst");
+    let mut writer = LogWriter::create(&path, 2048, None).unwrap();
+    for i in 0..2000 {
+        writer.write_record(record(i).as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+    std::mem::forget(writer);
+    let lines = read_lines(&path);
+    assert_eq!(lines.len(), 2000);
+    assert_eq!(lines[1999], record(1999).trim_end());
+}
@@ -0,0 +1,127 @@
+//! `--max-duration`: durations parse in the usual units, and at the deadline the pipeline
+//! starts no new files but lets the ones being read finish.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::deadline::parse_duration;
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineSummary};
+use rust_cache_warmer::report::{ErrorSamples, RunReport};
+use rust_cache_warmer::warming::{Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+/// Takes 50ms per file and counts the files it started
+#[derive(Default)]
+struct Slow {
+    started: AtomicU64,
+}
+
+impl WarmingBackend for Slow {
+    fn strategy(&self) -> Strategy {
+        Strategy::Tokio
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(&'a self, _path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            self.started.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(WarmingResult { method: "slow", success: true, duration: Duration::from_millis(50), bytes_read: file_size, coverage: Coverage::Full })
+        })
+    }
+}
+
+fn tree(test: &str, files: usize) -> PathBuf {
+    let dir = common::scratch(test);
+    for i in 0..files {
+        fs::write(dir.join(format!("file{}", i)), b"data").unwrap();
+    }
+    dir
+}
+
+async fn run(root: &Path, max_duration: Option<Duration>) -> (PipelineSummary, u64) {
+    let directories = vec![root.to_path_buf()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 1,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let slow = Arc::new(Slow::default());
+    let mut registry = StrategyRegistry::new();
+    registry.register(Arc::clone(&slow) as Arc<dyn WarmingBackend>);
+    let summary = pipeline::run(options, PipelineContext { registry: Some(Arc::new(registry)), ..Default::default() }).await;
+    (summary, slow.started.load(Ordering::Relaxed))
+}
+
+#[test]
+fn durations_parse_with_units() {
+    assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+    assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+    assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 3600)));
+    assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(90 * 60)));
+    assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86_400)));
+    assert!(parse_duration("").is_err());
+    assert!(parse_duration("30x").is_err());
+    assert!(parse_duration("1h30").is_err(), "a trailing number needs a unit");
+    assert!(parse_duration("m").is_err());
+}
+
+#[tokio::test]
+async fn the_deadline_stops_new_files_and_lets_started_ones_finish() {
+    let root = tree("deadline", 100);
+    let (summary, started) = run(&root, Some(Duration::from_millis(300))).await;
+
+    assert!(summary.deadline_reached);
+    assert!(!summary.interrupted);
+    assert!(summary.files_processed > 0 && summary.files_processed < 100, "processed {}", summary.files_processed);
+    assert_eq!(summary.files_processed, started, "every started file finished and was counted");
+    assert_eq!(summary.stats.totals.failed, 0);
+
+    let report = RunReport::new(&summary, &[root], &[], &ErrorSamples::new(0));
+    assert_eq!(report.status, "deadline_reached");
+}
+
+#[tokio::test]
+async fn a_run_that_fits_its_window_is_complete() {
+    let root = tree("fits", 4);
+    let (summary, _) = run(&root, Some(Duration::from_secs(60))).await;
+    assert!(!summary.deadline_reached);
+    assert_eq!(summary.files_processed, 4);
+}
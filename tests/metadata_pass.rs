@@ -0,0 +1,65 @@
+//! `--metadata-first`/`--metadata-only`: every directory is listed and every entry
+//! stat'ed, within the run's filters, and a shutdown stops the walk.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use indicatif::ProgressBar;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::metadata_pass;
+use rust_cache_warmer::pipeline::PipelineOptions;
+use rust_cache_warmer::shutdown::Shutdown;
+
+/// Five directories of twenty files, and one of logs
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    for dir in 0..5 {
+        for file in 0..20 {
+            let dir = root.join(format!("dir-{}", dir));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("file-{}", file)), b"data").unwrap();
+        }
+    }
+    fs::create_dir_all(root.join("logs")).unwrap();
+    fs::write(root.join("logs/app.log"), b"data").unwrap();
+    root
+}
+
+fn options(root: &Path, exclude: &[String]) -> PipelineOptions {
+    let directories = vec![root.to_path_buf()];
+    PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], exclude, &[], &[]).unwrap(),
+        queue_depth: 8,
+        ..common::pipeline_options(directories)
+    }
+}
+
+#[tokio::test]
+async fn every_entry_is_stated_once() {
+    let root = tree("all");
+    let progress = ProgressBar::hidden();
+    let summary = metadata_pass::warm_metadata(Arc::new(options(&root, &[])), 8, Shutdown::new(), progress.clone()).await.unwrap();
+    assert_eq!((summary.directories, summary.entries, summary.errors), (7, 108, 0), "{}", summary);
+    assert_eq!(progress.position(), 108);
+    assert!(!summary.interrupted);
+}
+
+#[test]
+fn the_walk_keeps_to_the_filters() {
+    let root = tree("filtered");
+    let summary = metadata_pass::walk(&options(&root, &["logs".to_string()]), 4, &Shutdown::new(), &ProgressBar::hidden());
+    assert_eq!((summary.directories, summary.entries), (6, 106));
+}
+
+#[test]
+fn a_shutdown_stops_the_walk() {
+    let root = tree("shutdown");
+    let shutdown = Shutdown::new();
+    shutdown.trigger(libc::SIGTERM);
+    let summary = metadata_pass::walk(&options(&root, &[]), 4, &shutdown, &ProgressBar::hidden());
+    assert!(summary.interrupted);
+    assert_eq!(summary.entries, 0);
+}
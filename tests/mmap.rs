@@ -0,0 +1,55 @@
+//! The mmap strategy touches pages after `MADV_WILLNEED`, so a warmed file is actually
+//! resident rather than only advised.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+
+use rust_cache_warmer::warming::{self, residency, Coverage, DropCaches, FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+const PAGE: u64 = 4096;
+
+fn options(stride: u64) -> WarmingOptions {
+    WarmingOptions {
+        strategy: Strategy::Mmap,
+        fallback: FallbackPolicy::None,
+        drop_caches: DropCaches::None,
+        mmap_touch_stride: stride,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn touched_files_are_resident() {
+    let dir = scratch("resident");
+    let size = 64 * PAGE + 123;
+    let path = dir.join("data.bin");
+    fs::write(&path, vec![7u8; size as usize]).unwrap();
+
+    let result = warming::warm_file(&path, size, &options(1)).await.unwrap();
+    assert_eq!(result.method, "mmap_touch");
+    assert_eq!(result.coverage, Coverage::Full);
+    assert_eq!(result.bytes_read, size);
+    assert!(residency::scan(&path, size).unwrap().is_fully_resident());
+}
+
+#[tokio::test]
+async fn strides_sample_the_file() {
+    let dir = scratch("strided");
+    let size = 64 * PAGE;
+    let path = dir.join("data.bin");
+    fs::write(&path, vec![7u8; size as usize]).unwrap();
+
+    let result = warming::warm_file(&path, size, &options(4)).await.unwrap();
+    assert_eq!(result.method, "mmap_touch_strided");
+    assert_eq!(result.coverage, Coverage::Sampled);
+    assert_eq!(result.bytes_read, 16 * PAGE);
+
+    let advised = warming::warm_file(&path, size, &options(0)).await.unwrap();
+    assert_eq!(advised.coverage, Coverage::Advisory);
+    assert_eq!(advised.bytes_read, 0);
+    assert_eq!("mmap".parse::<Strategy>(), Ok(Strategy::Mmap));
+}
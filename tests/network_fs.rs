@@ -0,0 +1,141 @@
+//! Network filesystems (EFS, NFS, SMB): told apart by statfs magic, warmed with large
+//! buffered Tokio reads instead of OS hints, tuned deeper, and paced by `efs-burst`.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::device::{DeviceProfile, NETWORK_QUEUE_DEPTH};
+use rust_cache_warmer::disk::{self, DiskKind, NETWORK_CHUNK_SIZE};
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pace::{BurstModel, PaceMode, Pacing};
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+const MIB: f64 = 1024.0 * 1024.0;
+const GIB: u64 = 1024 * 1024 * 1024;
+
+fn profile(device: &str, kind: DiskKind, directory: &str, size_bytes: Option<u64>) -> DeviceProfile {
+    DeviceProfile {
+        device: device.to_string(),
+        kind,
+        rotational: None,
+        nr_requests: None,
+        size_bytes,
+        volume_id: None,
+        ebs: None,
+        directories: vec![PathBuf::from(directory)],
+    }
+}
+
+fn efs(directory: &str, size_bytes: Option<u64>) -> DeviceProfile {
+    profile("fs-0abc.efs.us-east-1.amazonaws.com:/", DiskKind::Network("nfs".to_string()), directory, size_bytes)
+}
+
+#[test]
+fn network_filesystems_are_recognized_by_statfs_magic() {
+    assert_eq!(disk::classify_filesystem(disk::NFS_SUPER_MAGIC), Some(DiskKind::Network("nfs".to_string())));
+    assert_eq!(disk::classify_filesystem(disk::SMB2_MAGIC), Some(DiskKind::Network("smb2".to_string())));
+    assert_eq!(disk::classify_filesystem(disk::CIFS_MAGIC), Some(DiskKind::Network("cifs".to_string())));
+    assert_eq!(disk::classify_filesystem(0xef53), None, "ext4 is left to the block device checks");
+
+    let nfs = DiskKind::Network("nfs".to_string());
+    assert!(nfs.is_network() && !nfs.is_non_ebs(), "--non-ebs doesn't apply to network filesystems");
+    assert_eq!(nfs.to_string(), "network filesystem nfs");
+    assert!(!DiskKind::Ebs("nvme1n1".to_string()).is_network());
+}
+
+#[test]
+fn network_warming_uses_large_buffered_tokio_reads() {
+    let options = WarmingOptions {
+        strategy: Strategy::Fadvise,
+        fallback: FallbackPolicy::Auto,
+        use_direct_io: true,
+        nowait_precheck: true,
+        chunk_size: 64 * 1024,
+        ..Default::default()
+    };
+    let network = disk::network_warming(&options);
+    assert_eq!(network.strategy, Strategy::Tokio);
+    assert_eq!(network.fallback, FallbackPolicy::None);
+    assert!(!network.use_direct_io && !network.nowait_precheck && network.range_selector.is_none());
+    assert_eq!(network.chunk_size, NETWORK_CHUNK_SIZE);
+    assert_eq!(network.read_size(8192), NETWORK_CHUNK_SIZE);
+
+    let larger = disk::network_warming(&WarmingOptions { chunk_size: 4 * NETWORK_CHUNK_SIZE, ..Default::default() });
+    assert_eq!(larger.chunk_size, 4 * NETWORK_CHUNK_SIZE, "larger reads asked for are kept");
+}
+
+#[test]
+fn network_mounts_are_tuned_and_paced_as_efs() {
+    let tuning = efs("/mnt/efs", None).tuning();
+    assert_eq!(tuning.queue_depth, Some(NETWORK_QUEUE_DEPTH));
+    assert_eq!(tuning.chunk_size, Some(NETWORK_CHUNK_SIZE));
+    assert_eq!(tuning.strategy, Some(Strategy::Tokio));
+
+    // Small file systems burst at 100 MiB/s metered, reads counting a third
+    let small = BurstModel::for_efs(10.0);
+    assert_eq!((small.burst / MIB, small.baseline / MIB), (300.0, 3.0));
+    assert_eq!(small.bucket, BurstModel::for_efs(1024.0).bucket, "every file system starts with 2.1 TiB of credit");
+    let large = BurstModel::for_efs(4096.0);
+    assert_eq!((large.burst / MIB, large.baseline / MIB), (1200.0, 600.0));
+    assert_eq!("efs-burst".parse::<PaceMode>(), Ok(PaceMode::EfsBurst));
+    assert_eq!(BurstModel::for_profile(&efs("/mnt/efs", Some(4096 * GIB))), Ok(large));
+    assert!(BurstModel::for_profile(&efs("/mnt/efs", None)).is_err());
+
+    let profiles = vec![efs("/mnt/efs", Some(100 * GIB)), profile("nvme1n1", DiskKind::Ebs("nvme1n1".to_string()), "/data", None)];
+    let (pacing, unpaced) = Pacing::from_profiles(&profiles, PaceMode::EfsBurst, 0.5);
+    assert!(unpaced.is_empty(), "the EBS volume isn't efs-burst's to explain: {:?}", unpaced);
+    assert!(pacing.for_path(Path::new("/mnt/efs/a.bin")).is_some());
+    assert!(pacing.for_path(Path::new("/data/b.bin")).is_none());
+    let (pacing, unpaced) = Pacing::from_profiles(&profiles, PaceMode::EbsBurst, 0.5);
+    assert!(pacing.is_empty());
+    assert_eq!(unpaced.len(), 1, "only the EBS volume is reported: {:?}", unpaced);
+}
+
+#[tokio::test]
+async fn network_directories_skip_os_hints() {
+    let root = common::scratch("pipeline");
+    fs::write(root.join("model.bin"), vec![7u8; 3 * 1024 * 1024 + 17]).unwrap();
+    let directories = vec![root.clone()];
+    let options = PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: None,
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Fadvise, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: vec![root.clone()],
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    };
+
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let summary = pipeline::run(Arc::new(options), PipelineContext { events, ..Default::default() }).await;
+    assert_eq!((summary.files_processed, summary.bytes_warmed), (1, 3 * 1024 * 1024 + 17));
+    let Ok(WarmEvent::FileFinished { method, bytes_read, .. }) = finished.try_recv() else { panic!("no file finished") };
+    assert_eq!(method, Some("tokio_full"));
+    assert_eq!(bytes_read, 3 * 1024 * 1024 + 17);
+}
@@ -0,0 +1,60 @@
+//! `--non-ebs`: disks are classified by what backs them, and page-cache mode turns
+//! off the options that only make sense on EBS.
+
+use std::path::Path;
+
+use rust_cache_warmer::disk::{self, DiskKind, NonEbsPolicy};
+use rust_cache_warmer::warming::{DropCaches, FallbackPolicy, Strategy, WarmingOptions};
+
+#[test]
+fn nvme_devices_are_classified_by_model() {
+    assert_eq!(disk::classify_nvme("nvme0n1", "Amazon Elastic Block Store              \n"), DiskKind::Ebs("nvme0n1".to_string()));
+    assert_eq!(
+        disk::classify_nvme("nvme1n1", "Amazon EC2 NVMe Instance Storage        \n"),
+        DiskKind::InstanceStore("nvme1n1".to_string())
+    );
+    let other = disk::classify_nvme("nvme2n1", "Samsung SSD 980 PRO 1TB");
+    assert_eq!(other, DiskKind::Local("nvme2n1 (Samsung SSD 980 PRO 1TB)".to_string()));
+
+    assert!(other.is_non_ebs());
+    assert!(DiskKind::InstanceStore("nvme1n1".to_string()).is_non_ebs());
+    assert!(!DiskKind::Ebs("nvme0n1".to_string()).is_non_ebs());
+    assert!(!DiskKind::Unknown("xvda".to_string()).is_non_ebs(), "undetectable disks are warmed as before");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn memory_filesystems_are_local() {
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() {
+        assert!(disk::detect(shm).is_non_ebs(), "{}", disk::detect(shm));
+    }
+    assert!(matches!(disk::detect(Path::new("/no/such/dir")), DiskKind::Unknown(_)));
+}
+
+#[test]
+fn page_cache_mode_reads_through_the_cache_and_keeps_it() {
+    let ebs = WarmingOptions {
+        strategy: Strategy::Uring,
+        fallback: FallbackPolicy::None,
+        use_direct_io: true,
+        drop_caches: DropCaches::File,
+        sparse_large_files: 1 << 30,
+        ..Default::default()
+    };
+    let local = disk::page_cache_warming(&ebs);
+    assert_eq!(local.strategy, Strategy::Auto);
+    assert_eq!(local.fallback, FallbackPolicy::Auto);
+    assert!(!local.use_direct_io);
+    assert_eq!(local.drop_caches, DropCaches::None);
+    assert_eq!(local.sparse_large_files, 1 << 30, "unrelated settings carry over");
+}
+
+#[test]
+fn policies_parse() {
+    assert_eq!("skip".parse::<NonEbsPolicy>(), Ok(NonEbsPolicy::Skip));
+    assert_eq!("PageCache".parse::<NonEbsPolicy>(), Ok(NonEbsPolicy::PageCache));
+    assert_eq!("force".parse::<NonEbsPolicy>(), Ok(NonEbsPolicy::Force));
+    assert!("ignore".parse::<NonEbsPolicy>().is_err());
+    assert_eq!(NonEbsPolicy::default(), NonEbsPolicy::PageCache);
+}
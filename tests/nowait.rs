@@ -0,0 +1,40 @@
+//! `--nowait-precheck`: RWF_NOWAIT reads take cached data and the rest is still read.
+
+mod common;
+
+use std::fs;
+
+use rust_cache_warmer::warming::nowait;
+
+use common::scratch;
+
+#[test]
+fn a_just_written_file_reads_from_the_page_cache() {
+    let path = scratch("cached").join("file");
+    let data: Vec<u8> = (0..100_000u32).map(|n| n as u8).collect();
+    fs::write(&path, &data).unwrap();
+
+    let file = fs::File::open(&path).unwrap();
+    let mut buf = vec![0u8; 65536];
+    // Filesystems without RWF_NOWAIT support answer None, which callers treat as "read normally"
+    if let Some(n) = nowait::read_cached(&file, &mut buf, 0).unwrap() {
+        assert_eq!(n, 65536);
+        assert_eq!(buf[..n], data[..n]);
+        assert_eq!(nowait::read_cached(&file, &mut buf, 65536).unwrap(), Some(100_000 - 65536));
+        assert_eq!(nowait::read_cached(&file, &mut buf, 100_000).unwrap(), Some(0), "end of file");
+    }
+}
+
+#[test]
+fn every_byte_is_covered_whatever_was_cached() {
+    let root = scratch("warm");
+    for (name, size) in [("empty", 0usize), ("odd", 12_345), ("exact", 131_072), ("large", 1_000_000)] {
+        let path = root.join(name);
+        fs::write(&path, vec![7u8; size]).unwrap();
+        let result = nowait::warm_file_blocking(&path, None, 65536, true).unwrap();
+        assert_eq!(result.bytes_read, size as u64, "{}", name);
+        assert_eq!(result.method, "tokio_nowait_full");
+        // Dropped pages are read from the device the second time
+        assert_eq!(nowait::warm_file_blocking(&path, None, 4096, false).unwrap().bytes_read, size as u64, "{}", name);
+    }
+}
@@ -0,0 +1,131 @@
+//! `--prioritize-open-files`: descriptors under the targets are found in `/proc`, named
+//! as discovery names them, and warmed ahead of the walk without being warmed twice.
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::open_files::{self, OpenFiles};
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+/// A fake `/proc` where process `pid` has descriptors pointing at `targets`
+fn fake_process(proc: &Path, pid: u32, targets: &[&Path]) {
+    let fd = proc.join(pid.to_string()).join("fd");
+    fs::create_dir_all(&fd).unwrap();
+    for (n, target) in targets.iter().enumerate() {
+        symlink(target, fd.join(n.to_string())).unwrap();
+    }
+}
+
+#[test]
+fn most_widely_held_files_come_first() {
+    let root = scratch("snapshot");
+    let data = root.join("data");
+    fs::create_dir_all(data.join("db")).unwrap();
+    for name in ["db/index", "db/table", "log"] {
+        fs::write(data.join(name), b"data").unwrap();
+    }
+    let outside = root.join("outside");
+    fs::write(&outside, b"data").unwrap();
+    let canonical = data.canonicalize().unwrap();
+
+    let proc = root.join("proc");
+    fake_process(&proc, 100, &[&canonical.join("db/table"), &canonical.join("log"), &outside, Path::new("pipe:[1234]")]);
+    fake_process(&proc, 200, &[&canonical.join("db/table"), &canonical.join("gone (deleted)"), &canonical.join("db")]);
+    fake_process(&proc, 300, &[&outside]);
+    fs::create_dir_all(proc.join("self")).unwrap();
+
+    let open = open_files::snapshot(&proc, std::slice::from_ref(&data));
+    let files: Vec<(PathBuf, u32)> = open.files().iter().map(|file| (file.path.clone(), file.descriptors)).collect();
+    assert_eq!(files, vec![(data.join("db/table"), 2), (data.join("log"), 1)]);
+    assert_eq!((open.processes, open.unreadable), (2, 0));
+    assert!(open.contains(&data.join("log")));
+    assert!(!open.contains(&data.join("db/index")));
+}
+
+#[test]
+fn a_real_process_holding_a_target_file_is_found() {
+    let root = scratch("real");
+    let held = root.join("held");
+    fs::write(&held, b"data").unwrap();
+    let mut child = Command::new("sleep").arg("30").stdin(fs::File::open(&held).unwrap()).stdout(Stdio::null()).spawn().unwrap();
+    let open = open_files::snapshot(Path::new("/proc"), std::slice::from_ref(&root));
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(open.files().first().map(|file| &file.path), Some(&held));
+}
+
+#[tokio::test]
+async fn open_files_are_warmed_first_and_once() {
+    let root = scratch("pipeline");
+    for dir in 0..5 {
+        fs::create_dir_all(root.join(format!("dir-{}", dir))).unwrap();
+        for file in 0..20 {
+            fs::write(root.join(format!("dir-{}/file-{}", dir, file)), b"data").unwrap();
+        }
+    }
+    let held = [root.join("dir-4/file-19"), root.join("dir-2/file-7")];
+    let proc = root.join("proc");
+    fake_process(&proc, 100, &[&held[0].canonicalize().unwrap(), &held[0].canonicalize().unwrap(), &held[1].canonicalize().unwrap()]);
+    let open = open_files::snapshot(&proc, std::slice::from_ref(&root));
+    fs::remove_dir_all(&proc).unwrap();
+
+    let directories = vec![root.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 1,
+        threads: Some(2),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 10,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let context = PipelineContext { events, open_files: Some(Arc::new(open)), ..Default::default() };
+    let summary = pipeline::run(options, context).await;
+
+    assert_eq!((summary.files_discovered, summary.files_processed), (100, 100));
+    let mut warmed = Vec::new();
+    while let Ok(WarmEvent::FileFinished { path, .. }) = finished.try_recv() {
+        warmed.push(path);
+    }
+    assert_eq!(warmed[..2], held);
+    assert_eq!(warmed.iter().filter(|path| held.contains(path)).count(), 2);
+}
+
+#[test]
+fn an_empty_snapshot_prioritizes_nothing() {
+    let open = OpenFiles::default();
+    assert!(open.is_empty());
+    assert_eq!(open.under(&[PathBuf::from("/")]).count(), 0);
+}
@@ -0,0 +1,80 @@
+//! `--pace ebs-burst`: burst/baseline models for bursting EBS volume types, and a pacer
+//! that spends the budgeted credits at the burst rate before settling to baseline.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rust_cache_warmer::device::{DeviceProfile, EbsVolume, VolumeType};
+use rust_cache_warmer::disk::DiskKind;
+use rust_cache_warmer::pace::{BurstModel, PaceMode, Pacer, Pacing};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+fn profile(device: &str, dir: &str, volume_type: Option<VolumeType>, size_bytes: Option<u64>) -> DeviceProfile {
+    DeviceProfile {
+        device: device.to_string(),
+        kind: DiskKind::Ebs(device.to_string()),
+        rotational: Some(false),
+        nr_requests: None,
+        size_bytes,
+        volume_id: Some("vol-0abc".to_string()),
+        ebs: volume_type.map(|volume_type| EbsVolume { volume_type, iops: None, throughput_mbps: None, size_gib: None }),
+        directories: vec![PathBuf::from(dir)],
+    }
+}
+
+#[test]
+fn bursting_volume_types_have_models() {
+    let st1 = BurstModel::for_volume(&VolumeType::St1, 2048.0).unwrap();
+    assert_eq!((st1.baseline / MIB, st1.burst / MIB), (80.0, 500.0));
+    assert_eq!(st1.bucket, 2.0 * 1024.0 * 1024.0 * MIB);
+
+    let sc1 = BurstModel::for_volume(&VolumeType::Sc1, 1024.0).unwrap();
+    assert_eq!((sc1.baseline / MIB, sc1.burst / MIB), (12.0, 80.0));
+
+    // 300 baseline IOPS of 256 KiB; the burst is capped by small gp2 volumes' 128 MiB/s
+    let gp2 = BurstModel::for_volume(&VolumeType::Gp2, 100.0).unwrap();
+    assert_eq!((gp2.baseline / MIB, gp2.burst / MIB), (75.0, 128.0));
+
+    assert!(BurstModel::for_volume(&VolumeType::Gp2, 1000.0).is_err(), "3000 baseline IOPS leave nothing to burst");
+    assert!(BurstModel::for_volume(&VolumeType::Gp3, 500.0).is_err());
+    assert_eq!("ebs-burst".parse::<PaceMode>(), Ok(PaceMode::EbsBurst));
+    assert!("fast".parse::<PaceMode>().is_err());
+}
+
+#[test]
+fn the_budget_is_spent_at_burst_rate_then_reads_hold_baseline() {
+    let model = BurstModel { baseline: 10.0, burst: 100.0, bucket: 1800.0 };
+    let pacer = Pacer::new(model, 0.5);
+    let start = Instant::now();
+
+    // Each 100 bytes at burst take a second and spend 90 bytes of credit above baseline
+    for second in 0..10 {
+        assert_eq!(pacer.reserve(100, start), Duration::from_secs(second));
+    }
+    assert!(pacer.credits() < 1e-6, "{}", pacer.credits());
+    // Out of budget: 100 bytes at baseline take ten seconds each
+    assert_eq!(pacer.reserve(100, start), Duration::from_secs(10));
+    assert_eq!(pacer.reserve(100, start), Duration::from_secs(20));
+    // Time the volume sat idle isn't saved up
+    assert_eq!(pacer.reserve(100, start + Duration::from_secs(1000)), Duration::ZERO);
+}
+
+#[test]
+fn files_are_paced_by_the_device_they_live_on() {
+    let profiles = vec![
+        profile("nvme1n1", "/data", Some(VolumeType::St1), Some(1 << 40)),
+        profile("nvme2n1", "/data/fast", Some(VolumeType::Gp3), Some(1 << 40)),
+        profile("nvme3n1", "/logs", None, None),
+        profile("nvme4n1", "/archive", Some(VolumeType::Sc1), None),
+    ];
+    let (pacing, unpaced) = Pacing::from_profiles(&profiles, PaceMode::EbsBurst, 1.0);
+    assert_eq!(pacing.devices.len(), 1);
+    let unpaced: Vec<&str> = unpaced.iter().map(|(device, _)| device.as_str()).collect();
+    assert_eq!(unpaced, ["nvme2n1", "nvme3n1", "nvme4n1"]);
+
+    let st1 = pacing.for_path(Path::new("/data/a/b.bin")).unwrap();
+    assert_eq!(st1.model().baseline / MIB, 40.0);
+    assert!(pacing.for_path(Path::new("/data/fast/c.bin")).is_none(), "the nested gp3 target isn't paced");
+    assert!(pacing.for_path(Path::new("/logs/d.log")).is_none());
+}
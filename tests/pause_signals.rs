@@ -0,0 +1,43 @@
+//! SIGUSR1 pauses a run and SIGUSR2 resumes it, through the same [`Control`] as the
+//! status API's `/pause` and `/resume`.
+
+#![cfg(unix)]
+
+use std::time::Duration;
+
+use rust_cache_warmer::control::{self, Control};
+use rust_cache_warmer::introspect::Introspection;
+
+/// Send `signal` to this process and wait until `control` reports `paused`
+async fn signal_until(signal: libc::c_int, control: &Control, paused: bool) {
+    unsafe {
+        libc::kill(libc::getpid(), signal);
+    }
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while control.settings().paused != paused {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("signal {} didn't leave the run {}", signal, if paused { "paused" } else { "running" }));
+}
+
+#[tokio::test]
+async fn usr1_pauses_and_usr2_resumes() {
+    let control = Control::new();
+    let introspection = Introspection::new();
+    let _in_flight = introspection.begin("/data/a.bin".as_ref(), 4096);
+    let listener = control::listen_for_pause_signals(control.clone(), introspection).unwrap();
+
+    signal_until(libc::SIGUSR1, &control, true).await;
+    let mut turn = Box::pin(control.wait_turn(4096));
+    assert!(tokio::time::timeout(Duration::from_millis(200), &mut turn).await.is_err(), "no file starts while paused");
+
+    signal_until(libc::SIGUSR2, &control, false).await;
+    tokio::time::timeout(Duration::from_secs(5), turn).await.expect("the waiting file starts on resume");
+
+    // The API and the signals share the pause
+    control.pause();
+    signal_until(libc::SIGUSR2, &control, false).await;
+    listener.abort();
+}
@@ -0,0 +1,86 @@
+//! `--physical-order` warms files in the order their data lies on disk rather than the
+//! order the walk found them.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::fiemap;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+#[test]
+fn empty_files_have_no_location() {
+    let dir = scratch("empty");
+    fs::write(dir.join("empty"), "").unwrap();
+    assert_eq!(fiemap::first_physical(&dir.join("empty")).unwrap(), None);
+}
+
+#[tokio::test]
+async fn files_are_warmed_in_on_disk_order() {
+    let dir = scratch("order");
+    for i in 0..24 {
+        let mut file = fs::File::create(dir.join(format!("f{:02}", i))).unwrap();
+        file.write_all(&vec![i as u8; 8192]).unwrap();
+        file.sync_all().unwrap();
+    }
+    let mut expected: Vec<(u64, PathBuf)> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter_map(|path| fiemap::first_physical(&path).unwrap().map(|offset| (offset, path)))
+        .collect();
+    if expected.len() < 24 {
+        eprintln!("skipping: this filesystem doesn't report physical offsets");
+        return;
+    }
+    expected.sort();
+
+    let directories = vec![dir.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 1,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 5,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: true,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let summary = pipeline::run(options, PipelineContext { events, ..Default::default() }).await;
+    assert_eq!(summary.files_processed, 24);
+
+    let mut order = Vec::new();
+    while let Ok(WarmEvent::FileFinished { path, .. }) = finished.try_recv() {
+        order.push(path);
+    }
+    assert_eq!(order, expected.into_iter().map(|(_, path)| path).collect::<Vec<_>>());
+}
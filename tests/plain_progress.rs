@@ -0,0 +1,69 @@
+//! `--progress plain`: one status line per interval for logs that can't show bars.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use rust_cache_warmer::progress::{self, ByteProgress, PlainProgress, ProgressMode};
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+#[test]
+fn modes_parse() {
+    assert_eq!("plain".parse(), Ok(ProgressMode::Plain));
+    assert_eq!("Bar".parse(), Ok(ProgressMode::Bar));
+    assert_eq!("none".parse(), Ok(ProgressMode::None));
+    assert!("fancy".parse::<ProgressMode>().is_err());
+}
+
+#[test]
+fn lines_show_files_bytes_rate_and_eta() {
+    assert_eq!(progress::plain_line(0, 0, 0, false, None), "Progress: 0 files, 0 B");
+    assert_eq!(
+        progress::plain_line(120, GIB, 4 * GIB, true, Some(GIB as f64 / 60.0)),
+        "Progress: 120 files, 1.00 GiB of 4.00 GiB (25%), 17.07 MiB/s, ETA 3 minutes"
+    );
+    assert_eq!(progress::plain_line(120, GIB, 2 * GIB, false, Some(0.0)), "Progress: 120 files, 1.00 GiB of ~2.00 GiB (50%), stalled");
+    assert_eq!(progress::plain_line(300, 2 * GIB, 2 * GIB, true, Some(1e6)), "Progress: 300 files, 2.00 GiB of 2.00 GiB (100%), 976.56 KiB/s");
+}
+
+#[test]
+fn the_rate_covers_the_time_since_the_previous_line() {
+    let progress = ByteProgress::default();
+    progress.set_total(1000 * 1000);
+    let mut plain = PlainProgress::new();
+    let start = Instant::now();
+
+    assert_eq!(plain.line(&progress, start), "Progress: 0 files, 0 B of 976.56 KiB (0%)", "no rate before a second line");
+    for _ in 0..10 {
+        progress.finished(10 * 1000);
+    }
+    let line = plain.line(&progress, start + Duration::from_secs(10));
+    assert!(line.starts_with("Progress: 10 files, 97.66 KiB of 976.56 KiB (10%), 9.77 KiB/s, ETA 2 minutes"), "{}", line);
+    let line = plain.line(&progress, start + Duration::from_secs(20));
+    assert!(line.ends_with("(10%), stalled"), "{}", line);
+}
+
+#[test]
+fn piped_output_has_no_escape_codes() {
+    let dir = common::scratch("cli");
+    for i in 0..20 {
+        fs::write(dir.join(format!("file{}", i)), vec![1u8; 64 * 1024]).unwrap();
+    }
+    for mode in [None, Some("plain"), Some("none")] {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"));
+        command.env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state")).arg(&dir);
+        if let Some(mode) = mode {
+            command.args(["--progress", mode]);
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        for stream in [&output.stdout, &output.stderr] {
+            assert!(!stream.contains(&0x1b), "{:?}: {}", mode, String::from_utf8_lossy(stream));
+        }
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Cache warming complete"), "{:?}", mode);
+    }
+}
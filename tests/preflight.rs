@@ -0,0 +1,92 @@
+//! `--preflight`: a few files per directory are opened and read before the run, and
+//! denials are summarized per subtree with their likely cause.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rust_cache_warmer::pipeline::PipelineOptions;
+use rust_cache_warmer::preflight::{self, Cause, Credentials, Denial, PreflightOptions, SecurityContext};
+
+fn options(root: &Path) -> Arc<PipelineOptions> {
+    Arc::new(common::pipeline_options(vec![root.to_path_buf()]))
+}
+
+fn denial(path: &str, is_dir: bool, cause: Cause) -> Denial {
+    Denial { path: PathBuf::from(path), is_dir, cause, error: "Permission denied (os error 13)".to_string() }
+}
+
+#[test]
+fn mode_bits_are_checked_like_the_kernel() {
+    let user = Credentials { uid: 1000, gids: vec![100, 27] };
+    assert!(user.mode_allows(0o600, 1000, 0, false));
+    assert!(!user.mode_allows(0o060, 1000, 100, false), "owner bits apply to the owner even if group bits would allow");
+    assert!(user.mode_allows(0o640, 0, 27, false));
+    assert!(!user.mode_allows(0o640, 0, 0, false));
+    assert!(user.mode_allows(0o604, 0, 0, false));
+    assert!(!user.mode_allows(0o744, 0, 0, true), "directories need search permission too");
+    assert!(user.mode_allows(0o755, 0, 0, true));
+    assert!(Credentials { uid: 0, gids: vec![0] }.mode_allows(0o000, 1000, 1000, false));
+}
+
+#[test]
+fn apparmor_labels_are_parsed() {
+    assert_eq!(SecurityContext::apparmor("docker-default (enforce)").enforcing, Some(true));
+    assert_eq!(SecurityContext::apparmor("warmer (complain)").enforcing, Some(false));
+    assert_eq!(SecurityContext::apparmor("unconfined").enforcing, Some(false));
+    assert_eq!(SecurityContext::apparmor("docker-default (enforce)").to_string(), "apparmor docker-default (enforce), enforcing");
+}
+
+#[test]
+fn denials_are_grouped_by_subtree() {
+    let sampled: BTreeMap<PathBuf, usize> =
+        [("/data/a", 2), ("/data/a/x", 2), ("/data/b", 2), ("/data/c", 2)].into_iter().map(|(dir, n)| (PathBuf::from(dir), n)).collect();
+    let subtrees = preflight::summarize(
+        &sampled,
+        vec![
+            denial("/data/a/x/1", false, Cause::SecurityPolicy),
+            denial("/data/a/2", false, Cause::SecurityPolicy),
+            denial("/data/a/x/2", false, Cause::SecurityPolicy),
+            denial("/data/c/secret", true, Cause::Permissions),
+            denial("/data/b/1", false, Cause::Permissions),
+            denial("/data/b/2", false, Cause::SecurityPolicy),
+        ],
+    );
+    let summary: Vec<_> = subtrees.iter().map(|s| (s.path.to_str().unwrap(), s.sampled, s.denied_files, s.denied_dirs, s.cause)).collect();
+    assert_eq!(
+        summary,
+        [
+            ("/data/a", 4, 3, 0, Cause::SecurityPolicy),
+            ("/data/b", 2, 2, 0, Cause::Unknown),
+            ("/data/c/secret", 0, 0, 1, Cause::Permissions),
+        ]
+    );
+    assert_eq!(subtrees[0].example.path, PathBuf::from("/data/a/2"));
+    assert_eq!(subtrees[2].example.error, "Permission denied (os error 13)");
+}
+
+#[tokio::test]
+async fn readable_trees_pass_and_sampling_is_bounded() {
+    let root = common::scratch("tree");
+    for dir in 0..5 {
+        fs::create_dir_all(root.join(format!("dir-{}", dir))).unwrap();
+        for file in 0..10 {
+            fs::write(root.join(format!("dir-{}/file-{}", dir, file)), "data").unwrap();
+        }
+    }
+
+    let report = preflight::preflight(options(&root), PreflightOptions::default()).await.unwrap();
+    assert!(report.is_clean(), "{}", report);
+    assert_eq!(report.files_sampled, 10, "two files in each of five directories");
+    assert_eq!(report.dirs_seen, 6);
+    assert!(!report.truncated);
+    assert!(report.to_string().contains("every sampled file and directory is readable"), "{}", report);
+
+    let limited = PreflightOptions { files_per_dir: 10, max_files: 15, ..Default::default() };
+    let report = preflight::preflight(options(&root), limited).await.unwrap();
+    assert_eq!(report.files_sampled, 15);
+    assert!(report.truncated);
+}
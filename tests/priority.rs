@@ -0,0 +1,108 @@
+//! `--nice`, `--ionice-class` and `--cgroup-read-bps`: ioprio values, sysfs and
+//! /proc/self/cgroup parsing, and io.max limits that are put back afterwards.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rust_cache_warmer::priority::{self, CgroupIoLimit, Disk, IoClass, IoMax};
+
+use common::scratch;
+
+#[test]
+fn ioprio_values_match_ionice() {
+    assert_eq!("idle".parse(), Ok(IoClass::Idle));
+    assert_eq!("be".parse(), Ok(IoClass::BestEffort));
+    assert_eq!("Best-Effort".parse(), Ok(IoClass::BestEffort));
+    assert_eq!("1".parse(), Ok(IoClass::Realtime));
+    assert!("lowest".parse::<IoClass>().is_err());
+
+    // ionice -c 2 -n 7 and ionice -c 3, as the kernel sees them
+    assert_eq!(priority::ioprio_value(IoClass::BestEffort, 7), 0x4007);
+    assert_eq!(priority::ioprio_value(IoClass::Realtime, 0), 0x2000);
+    assert_eq!(priority::ioprio_value(IoClass::Idle, 5), 0x6000, "the idle class has no levels");
+}
+
+#[test]
+fn disks_are_read_from_sysfs() {
+    assert_eq!(priority::active_scheduler("[none] mq-deadline kyber bfq"), Some("none"));
+    assert_eq!(priority::active_scheduler("none [mq-deadline] kyber"), Some("mq-deadline"));
+    assert_eq!(priority::active_scheduler("none"), None);
+
+    let sys = scratch("sysfs").join("nvme1n1");
+    fs::create_dir_all(sys.join("queue")).unwrap();
+    fs::write(sys.join("dev"), "259:4\n").unwrap();
+    fs::write(sys.join("queue/scheduler"), "[none] mq-deadline\n").unwrap();
+    let disk = Disk::from_sys(&sys).unwrap();
+    assert_eq!(disk, Disk { name: "nvme1n1".into(), dev: "259:4".into(), scheduler: Some("none".into()) });
+    assert!(disk.ignores_io_priority());
+}
+
+#[test]
+fn the_own_cgroup_is_the_unified_one() {
+    let v2 = "0::/system.slice/warm-data.scope\n";
+    assert_eq!(priority::parse_cgroup_v2_path(v2), Some("/system.slice/warm-data.scope"));
+    let hybrid = "12:memory:/user.slice\n1:name=systemd:/user.slice\n0::/user.slice/session-3.scope\n";
+    assert_eq!(priority::parse_cgroup_v2_path(hybrid), Some("/user.slice/session-3.scope"));
+    assert_eq!(priority::parse_cgroup_v2_path("12:memory:/user.slice\n"), None);
+}
+
+#[test]
+fn io_max_limits_are_put_back() {
+    assert_eq!(IoMax { read_bps: Some(50_000_000), read_iops: None }.line("259:0"), "259:0 rbps=50000000 riops=max");
+    assert!(IoMax::default().is_empty());
+
+    // A plain file stands in for io.max, keeping only the last line written
+    let cgroup = scratch("cgroup");
+    let io_max = cgroup.join("io.max");
+    fs::write(&io_max, "259:0 rbps=1000 wbps=max riops=max wiops=max\n").unwrap();
+    let limit = IoMax { read_bps: Some(20_000_000), read_iops: Some(500) };
+
+    let applied = CgroupIoLimit::apply(&cgroup, &["259:0".to_string()], limit).unwrap();
+    assert_eq!(applied.file(), io_max);
+    assert_eq!(fs::read_to_string(&io_max).unwrap(), "259:0 rbps=20000000 riops=500");
+    drop(applied);
+    assert_eq!(fs::read_to_string(&io_max).unwrap(), "259:0 rbps=1000 wbps=max riops=max wiops=max", "the previous limit is restored");
+
+    let applied = CgroupIoLimit::apply(&cgroup, &["259:8".to_string()], limit).unwrap();
+    drop(applied);
+    assert_eq!(fs::read_to_string(&io_max).unwrap(), "259:8 rbps=max wbps=max riops=max wiops=max", "a disk without a limit goes back to none");
+}
+
+#[test]
+fn a_cgroup_without_io_max_is_explained() {
+    let root = scratch("no-io");
+    let child = root.join("warm.scope");
+    fs::create_dir_all(&child).unwrap();
+    fs::write(root.join("cgroup.controllers"), "cpu memory io\n").unwrap();
+    fs::write(child.join("cgroup.controllers"), "cpu memory\n").unwrap();
+
+    let err = CgroupIoLimit::apply(&child, &["259:0".to_string()], IoMax { read_bps: Some(1), read_iops: None }).unwrap_err();
+    assert!(err.to_string().contains("subtree_control"), "{}", err);
+    let err = CgroupIoLimit::apply(&root, &["259:0".to_string()], IoMax { read_bps: Some(1), read_iops: None }).unwrap_err();
+    assert!(err.to_string().contains("root cgroup"), "{}", err);
+}
+
+#[test]
+fn the_warmer_runs_at_a_lower_priority() {
+    let dir = scratch("cli");
+    fs::write(dir.join("file"), b"data").unwrap();
+    let warm = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+            .args(args)
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+    // Raising the nice value and dropping to the idle class need no privileges
+    let output = warm(&["--nice", "19", "--ionice-class", "idle"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Running at nice 19, I/O class idle"));
+
+    assert!(!warm(&["--nice", "20"]).status.success());
+    assert!(!warm(&["--ionice-class", "best-effort", "--ionice-level", "8"]).status.success());
+    assert!(!warm(&["--ionice-level", "7"]).status.success(), "--ionice-level needs --ionice-class");
+}
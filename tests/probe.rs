@@ -0,0 +1,41 @@
+//! Startup probes: O_DIRECT is tried on a real file of each target, and the io_uring and
+//! AIO probes behind the registry explain every "no".
+
+mod common;
+
+use std::fs;
+
+use rust_cache_warmer::probe::{self, Capability};
+use rust_cache_warmer::warming::{Strategy, StrategyRegistry};
+
+use common::scratch;
+
+#[test]
+fn a_target_without_files_is_unknown() {
+    let root = scratch("empty");
+    fs::create_dir_all(root.join("nested/deeper")).unwrap();
+    fs::write(root.join("nested/empty"), b"").unwrap();
+    let result = probe::direct_io(&root);
+    assert!(matches!(result.capability, Capability::Unknown(_)), "{:?}", result);
+}
+
+#[test]
+fn o_direct_is_tried_on_a_nested_file() {
+    let root = scratch("nested");
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/b/data"), vec![1u8; 8192]).unwrap();
+    let result = probe::direct_io(&root);
+    assert!(!matches!(result.capability, Capability::Unknown(_)), "a file was there to test: {:?}", result);
+    assert_eq!(result.root, root);
+}
+
+#[test]
+fn registry_probes_carry_their_reasons() {
+    let registry = StrategyRegistry::builtin();
+    for (strategy, capability) in [(Strategy::Uring, probe::io_uring()), (Strategy::Libaio, probe::libaio())] {
+        let result = registry.probe().iter().find(|result| result.strategy == strategy).unwrap();
+        assert_eq!(result.available, capability.is_available());
+        assert_eq!(result.reason.as_deref(), capability.reason(), "{}", strategy);
+        assert_eq!(result.available, result.reason.is_none());
+    }
+}
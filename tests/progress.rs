@@ -0,0 +1,95 @@
+//! Byte-based progress: the bar's length is a pre-scanned total or a rolling estimate
+//! from the mean size of finished files, and a resumed run starts from the bytes its
+//! checkpoint already covers.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rust_cache_warmer::checkpoint::Checkpoint;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineProgress};
+use rust_cache_warmer::progress::{self, ByteProgress, ScanTotals};
+
+fn tree(test: &str) -> PathBuf {
+    let dir = common::scratch(test);
+    fs::create_dir_all(dir.join("data/nested")).unwrap();
+    for i in 0..12u64 {
+        let sub = if i % 3 == 0 { "data/nested" } else { "data" };
+        fs::write(dir.join(sub).join(format!("{}.bin", i)), vec![1u8; (i * 1000) as usize]).unwrap();
+    }
+    fs::write(dir.join("data/skip.tmp"), vec![1u8; 50_000]).unwrap();
+    dir
+}
+
+fn options(root: &Path) -> Arc<PipelineOptions> {
+    let directories = vec![root.join("data")];
+    Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &["*.tmp".to_string()], &[], &[]).unwrap(),
+        queue_depth: 2,
+        batch_size: 4,
+        ..common::pipeline_options(directories)
+    })
+}
+
+#[test]
+fn estimate_extrapolates_from_finished_files() {
+    let progress = ByteProgress::default();
+    assert_eq!(progress.estimated_total(), 0);
+    progress.discovered(10);
+    progress.finished(100);
+    progress.finished(300);
+    // 400 bytes done, 8 pending files at 200 bytes each
+    assert_eq!(progress.estimated_total(), 2000);
+    assert!(!progress.is_exact());
+
+    // Files finished by an earlier attempt are done, not pending, when they're rediscovered
+    let resumed = ByteProgress::default();
+    resumed.resume(3, 600);
+    resumed.discovered(5);
+    for _ in 0..3 {
+        resumed.already_done();
+    }
+    resumed.finished(100);
+    assert_eq!(resumed.done_bytes(), 700);
+    assert_eq!(resumed.estimated_total(), 700 + 175);
+}
+
+#[tokio::test]
+async fn pre_scan_matches_what_the_run_warms() {
+    let root = tree("scan");
+    let options = options(&root);
+    let totals = progress::scan_totals(&options);
+    assert_eq!(totals, ScanTotals { files: 12, bytes: (0..12).sum::<u64>() * 1000 });
+
+    let bytes = ByteProgress::default();
+    bytes.set_total(totals.bytes);
+    let context = PipelineContext {
+        progress: PipelineProgress { bytes: bytes.clone(), ..Default::default() },
+        ..Default::default()
+    };
+    let summary = pipeline::run(options, context).await;
+    assert_eq!(summary.bytes_warmed, totals.bytes);
+    assert_eq!(bytes.done_bytes(), totals.bytes);
+    assert!(bytes.is_exact());
+}
+
+#[tokio::test]
+async fn resumed_runs_start_from_checkpointed_bytes() {
+    let root = tree("resume");
+    let journal = root.join("journal");
+    let total = (0..12).sum::<u64>() * 1000;
+    for _ in 0..2 {
+        let bytes = ByteProgress::default();
+        let context = PipelineContext {
+            progress: PipelineProgress { bytes: bytes.clone(), ..Default::default() },
+            checkpoint: Some(Arc::new(Checkpoint::open(&journal).unwrap())),
+            ..Default::default()
+        };
+        pipeline::run(options(&root), context).await;
+        assert_eq!(bytes.done_bytes(), total);
+        assert_eq!(bytes.estimated_total(), total, "nothing should be left pending");
+    }
+}
@@ -0,0 +1,47 @@
+//! `--progress-file`: a one-line "<percent> <done> <total> <state>" file for shell
+//! progress wrappers, rewritten while the run goes and finalized when it ends.
+
+mod common;
+
+use std::fs;
+
+use rust_cache_warmer::events::EventBus;
+use rust_cache_warmer::progress::ByteProgress;
+use rust_cache_warmer::progress_file::{self, State};
+use rust_cache_warmer::shutdown::Shutdown;
+
+use common::scratch;
+
+#[test]
+fn percent_stays_below_100_until_done() {
+    assert_eq!(progress_file::render(0, 0, State::Running), "0 0 0 running\n");
+    assert_eq!(progress_file::render(250, 1000, State::Running), "25 250 1000 running\n");
+    // An estimate the run has caught up with isn't finished yet
+    assert_eq!(progress_file::render(1200, 1000, State::Running), "99 1200 1200 running\n");
+    assert_eq!(progress_file::render(1000, 1000, State::Done), "100 1000 1000 done\n");
+    assert_eq!(progress_file::render(400, 1000, State::Interrupted), "40 400 1000 interrupted\n");
+}
+
+#[tokio::test]
+async fn the_file_is_finalized_when_the_run_ends() {
+    let dir = scratch("final");
+    let path = dir.join("warmer.progress");
+    let progress = ByteProgress::default();
+    progress.set_total(4096);
+
+    let mut events = EventBus::new();
+    let writer = tokio::spawn(progress_file::run_writer(path.clone(), progress.clone(), Shutdown::new(), events.subscribe()));
+    progress.finished(4096);
+    drop(events);
+    writer.await.unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "100 4096 4096 done\n");
+    // Only the progress file is left behind, no temporaries
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    let shutdown = Shutdown::new();
+    shutdown.trigger(15);
+    let events = EventBus::new().subscribe();
+    progress_file::run_writer(path.clone(), ByteProgress::default(), shutdown, events).await;
+    assert_eq!(fs::read_to_string(&path).unwrap(), "0 0 0 interrupted\n");
+}
@@ -0,0 +1,114 @@
+//! The JSON report keeps the first few errors of each category as structured samples
+//! with stable field names.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::hooks::{FileMetadata, MetadataHooks};
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::report::{ErrorSamples, RunReport, Stage, REPORT_VERSION};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+use rust_cache_warmer::warnings::{Category, Warnings};
+
+use common::scratch;
+
+#[tokio::test]
+async fn errors_are_sampled_per_category() {
+    let dir = scratch("samples");
+    for i in 0..8 {
+        fs::write(dir.join(format!("gone-{}.bin", i)), "x").unwrap();
+    }
+    fs::write(dir.join("kept.bin"), "hello").unwrap();
+    std::os::unix::fs::symlink(dir.join("nowhere"), dir.join("dangling")).unwrap();
+
+    let directories = vec![dir.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories: directories.clone(),
+        queue_depth: 1,
+        threads: Some(1),
+        follow_symlinks: true,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 4,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    // Files named gone-* disappear between discovery and warming
+    let mut hooks = MetadataHooks::new();
+    hooks.register(|path: &Path, _: &mut FileMetadata| {
+        if path.file_name().unwrap().to_string_lossy().starts_with("gone-") {
+            fs::remove_file(path).unwrap();
+        }
+    });
+    let errors = Arc::new(ErrorSamples::new(3));
+    let context = PipelineContext { hooks: Arc::new(hooks), errors: Arc::clone(&errors), ..Default::default() };
+    let summary = pipeline::run(options, context).await;
+    assert_eq!(summary.stats.totals.failed, 8);
+
+    let samples = errors.snapshot();
+    let not_found = &samples["not-found"];
+    assert_eq!(not_found.len(), 3, "only the first 3 errors of a category are kept");
+    for sample in not_found {
+        assert!(sample.path.contains("gone-"), "{:?}", sample);
+        assert_eq!(sample.errno, Some(libc::ENOENT));
+        assert_eq!(sample.stage, Stage::Metadata);
+        assert!(sample.timestamp_ms > 0);
+    }
+    let discovery = &samples["discovery"];
+    assert_eq!(discovery.len(), 1);
+    assert!(discovery[0].path.ends_with("dangling"), "{:?}", discovery[0]);
+    assert_eq!(discovery[0].stage, Stage::Discovery);
+
+    let report = RunReport::new(&summary, &directories, &Warnings::new(10, Duration::from_secs(10)).counts(), &errors);
+    let path = dir.join("report.json");
+    report.write(&path).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["version"], REPORT_VERSION);
+    assert_eq!(json["status"], "complete");
+    assert_eq!(json["files_failed"], 8);
+    assert_eq!(json["discovery_errors"], 1);
+    assert_eq!(json["failures_by_cause"], serde_json::json!({ "vanished": 8 }));
+    let sample = &json["error_samples"]["not-found"][0];
+    let mut fields: Vec<&str> = sample.as_object().unwrap().keys().map(String::as_str).collect();
+    fields.sort();
+    assert_eq!(fields, ["errno", "error", "path", "stage", "strategy", "timestamp_ms"]);
+    assert_eq!(sample["stage"], "metadata");
+}
+
+#[test]
+fn warm_errors_name_the_strategy() {
+    let errors = ErrorSamples::new(1);
+    let error = std::io::Error::from_raw_os_error(libc::EIO);
+    errors.record(Category::ReadError, Path::new("/data/a"), &error, Stage::Warm, Some("uring"));
+    errors.record(Category::ReadError, Path::new("/data/b"), &error, Stage::Warm, Some("uring"));
+    let samples = errors.snapshot();
+    assert_eq!(samples["read-error"].len(), 1);
+    let sample = &samples["read-error"][0];
+    assert_eq!((sample.path.as_str(), sample.errno, sample.strategy), ("/data/a", Some(libc::EIO), Some("uring")));
+
+    let none = ErrorSamples::new(0);
+    none.record(Category::ReadError, Path::new("/data/a"), &error, Stage::Warm, None);
+    assert!(none.snapshot().is_empty());
+}
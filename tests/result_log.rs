@@ -0,0 +1,117 @@
+//! `--result-log`: one record per processed file, in NDJSON or (for `*.csv`) CSV, with
+//! the strategy, the bytes actually read and the error for failed files.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::result_log::{self, ResultFormat, ResultLog};
+use rust_cache_warmer::stats::FileStatus;
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+use serde_json::Value;
+
+use common::scratch;
+
+#[test]
+fn the_format_follows_the_extension() {
+    assert_eq!(ResultFormat::for_path(Path::new("results.csv")), ResultFormat::Csv);
+    assert_eq!(ResultFormat::for_path(Path::new("results.CSV")), ResultFormat::Csv);
+    assert_eq!(ResultFormat::for_path(Path::new("results.ndjson")), ResultFormat::Ndjson);
+    assert_eq!(ResultFormat::for_path(Path::new("results")), ResultFormat::Ndjson);
+}
+
+#[test]
+fn csv_fields_are_quoted_when_needed() {
+    let dir = scratch("csv");
+    let path = dir.join("results.csv");
+    let mut log = ResultLog::create(&path).unwrap();
+    log.write(&WarmEvent::FileFinished {
+        path: PathBuf::from("/data/a,b \"c\".bin"),
+        bytes: 10,
+        latency: Duration::from_micros(1500),
+        status: FileStatus::Failed,
+        method: None,
+        bytes_read: 0,
+        error: Some("Input/output error, retry".to_string()),
+        coverage: None,
+    })
+    .unwrap();
+    log.flush().unwrap();
+
+    let text = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        text,
+        "path,size,status,strategy,duration_us,bytes_read,coverage,error\n\
+         \"/data/a,b \"\"c\"\".bin\",10,failed,,1500,0,,\"Input/output error, retry\"\n"
+    );
+}
+
+#[tokio::test]
+async fn every_processed_file_gets_a_record() {
+    let dir = scratch("pipeline");
+    fs::create_dir_all(dir.join("data")).unwrap();
+    fs::write(dir.join("data/small"), vec![1u8; 1000]).unwrap();
+    fs::write(dir.join("data/large"), vec![1u8; 50_000]).unwrap();
+
+    let directories = vec![dir.join("data")];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 10_000,
+        batch_size: 10,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let log_path = dir.join("results.ndjson");
+    let mut events = EventBus::new();
+    let writer = tokio::spawn(result_log::run_writer(ResultLog::create(&log_path).unwrap(), events.subscribe()));
+    pipeline::run(options, PipelineContext { events, ..Default::default() }).await;
+    writer.await.unwrap();
+
+    let records: Vec<Value> = fs::read_to_string(&log_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    let record = |name: &str| records.iter().find(|r| r["path"].as_str().unwrap().ends_with(name)).unwrap();
+
+    let small = record("small");
+    assert_eq!(small["status"], "warmed");
+    assert_eq!(small["size"], 1000);
+    assert_eq!(small["bytes_read"], 1000);
+    assert_eq!(small["strategy"], "tokio_full");
+    assert_eq!(small["coverage"], "full");
+    assert!(small["error"].is_null());
+
+    let large = record("large");
+    assert_eq!(large["status"], "skipped");
+    assert_eq!(large["bytes_read"], 0);
+    assert!(large["strategy"].is_null());
+}
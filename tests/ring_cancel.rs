@@ -0,0 +1,142 @@
+//! Reads abandoned by their caller are cancelled in the shared io_uring instead of being
+//! waited out: a pipe nobody writes to stands in for a device that never answers, so
+//! without cancellation its reads would stay outstanding forever.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs::{self, File};
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::{join_all, LocalBoxFuture};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::shutdown::Shutdown;
+use rust_cache_warmer::warming::ring::SharedRing;
+use rust_cache_warmer::warming::{Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+fn ring() -> Option<&'static SharedRing> {
+    let ring = SharedRing::global().ok();
+    if ring.is_none() {
+        eprintln!("skipping: io_uring is unavailable here");
+    }
+    ring
+}
+
+/// Both ends of a pipe; reads of the first block until the second is written to
+fn pipe() -> (Arc<File>, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+    unsafe { (Arc::new(File::from_raw_fd(fds[0])), File::from_raw_fd(fds[1])) }
+}
+
+/// Wait for the ring to hold no requests, failing after `limit`
+async fn drained(ring: &SharedRing, limit: Duration) {
+    let start = Instant::now();
+    while ring.outstanding() > 0 {
+        assert!(start.elapsed() < limit, "{} requests still outstanding after {:?}", ring.outstanding(), limit);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn abandoned_reads_are_cancelled_whether_queued_or_in_the_kernel() {
+    let Some(ring) = ring() else { return };
+    let (reader, _writer) = pipe();
+
+    // More reads than the ring has entries, so some are still queued when abandoned
+    let reads = join_all((0..300).map(|_| ring.read(Arc::clone(&reader), 0, 4096)));
+    assert!(tokio::time::timeout(Duration::from_millis(200), reads).await.is_err());
+
+    drained(ring, Duration::from_secs(5)).await;
+    // The ring still works afterwards
+    let (reader, mut writer) = pipe();
+    std::io::Write::write_all(&mut writer, b"data").unwrap();
+    assert_eq!(ring.read(reader, 0, 4096).await.unwrap(), 4);
+}
+
+/// Reads every file from a pipe that never delivers
+struct Hung {
+    pipe: Arc<File>,
+}
+
+impl WarmingBackend for Hung {
+    fn strategy(&self) -> Strategy {
+        Strategy::Uring
+    }
+
+    fn probe(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _use_direct_io: bool) -> bool {
+        true
+    }
+
+    fn warm<'a>(&'a self, _path: &'a Path, _file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move {
+            SharedRing::global()?.read(Arc::clone(&self.pipe), 0, 4096).await?;
+            unreachable!("nothing is ever written to the pipe")
+        })
+    }
+}
+
+#[tokio::test]
+async fn shutdown_is_not_held_up_by_reads_that_never_complete() {
+    let Some(ring) = ring() else { return };
+    let (reader, _writer) = pipe();
+    let dir = common::scratch("shutdown");
+    for i in 0..16 {
+        fs::write(dir.join(format!("file{}", i)), b"data").unwrap();
+    }
+
+    let directories = vec![dir];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 8,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 1,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Uring, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut registry = StrategyRegistry::new();
+    registry.register(Arc::new(Hung { pipe: reader }));
+    let shutdown = Shutdown::new();
+    let trigger = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        trigger.trigger(libc::SIGTERM);
+    });
+
+    let start = Instant::now();
+    let context = PipelineContext { registry: Some(Arc::new(registry)), shutdown, ..Default::default() };
+    let summary = pipeline::run(options, context).await;
+    assert!(summary.interrupted);
+    assert_eq!(summary.files_processed, 0);
+    drained(ring, Duration::from_secs(5)).await;
+    assert!(start.elapsed() < Duration::from_secs(3), "shutdown took {:?}", start.elapsed());
+}
@@ -0,0 +1,60 @@
+//! The sendfile strategy reads files into the page cache through `/dev/null`, and
+//! `bench` measures it in buffered mode.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+
+use rust_cache_warmer::bench;
+use rust_cache_warmer::warming::{self, residency, Coverage, DropCaches, FallbackPolicy, Strategy, StrategyRegistry, WarmingOptions};
+
+use common::scratch;
+
+const PAGE: u64 = 4096;
+
+fn options() -> WarmingOptions {
+    WarmingOptions { strategy: Strategy::Sendfile, fallback: FallbackPolicy::None, drop_caches: DropCaches::None, ..Default::default() }
+}
+
+#[tokio::test]
+async fn sent_files_are_resident() {
+    let dir = scratch("resident");
+    let size = 64 * PAGE + 123;
+    let path = dir.join("data.bin");
+    fs::write(&path, vec![7u8; size as usize]).unwrap();
+
+    // Small chunks, so the file takes several calls
+    let result = warming::warm_file(&path, size, &WarmingOptions { chunk_size: 4 * PAGE as usize, ..options() }).await.unwrap();
+    assert_eq!(result.method, "sendfile_full");
+    assert_eq!((result.bytes_read, result.coverage), (size, Coverage::Full));
+    assert!(residency::scan(&path, size).unwrap().is_fully_resident());
+}
+
+#[tokio::test]
+async fn large_files_are_sampled() {
+    let dir = scratch("sparse");
+    let size = 64 * PAGE;
+    let path = dir.join("data.bin");
+    fs::write(&path, vec![7u8; size as usize]).unwrap();
+
+    let options = WarmingOptions { sparse_large_files: PAGE, sparse_interval: 16 * PAGE, ..options() };
+    let result = warming::warm_file(&path, size, &options).await.unwrap();
+    assert_eq!(result.method, "sendfile_sparse");
+    assert_eq!((result.bytes_read, result.coverage), (4 * PAGE, Coverage::Sampled));
+}
+
+#[test]
+fn it_is_a_buffered_candidate() {
+    assert_eq!("sendfile".parse(), Ok(Strategy::Sendfile));
+    assert_eq!("zero-copy".parse(), Ok(Strategy::Sendfile));
+
+    let registry = StrategyRegistry::builtin();
+    assert!(registry.plan(&WarmingOptions::default()).contains(&Strategy::Sendfile));
+    assert!(!registry.plan(&WarmingOptions { use_direct_io: true, ..Default::default() }).contains(&Strategy::Sendfile));
+
+    let (run, _) = bench::candidates(&registry, &[], false);
+    assert!(run.contains(&Strategy::Sendfile));
+    let (_, skipped) = bench::candidates(&registry, &[], true);
+    assert!(skipped.contains(&(Strategy::Sendfile, "doesn't support --direct-io")));
+}
@@ -0,0 +1,116 @@
+//! `--shards`/`--shard-index`: processes split the files between them by a hash of the
+//! path relative to the target directory, so the subsets are disjoint, cover everything
+//! and don't depend on where the tree is mounted.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::{DiscoveryFilters, Shard};
+use rust_cache_warmer::hooks::{FileMetadata, MetadataHooks};
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+fn tree(test: &str, mount: &str) -> PathBuf {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("shard").join(test).join(mount);
+    let _ = fs::remove_dir_all(&root);
+    for dir in 0..10 {
+        fs::create_dir_all(root.join(format!("dir-{}", dir))).unwrap();
+        for file in 0..20 {
+            fs::write(root.join(format!("dir-{}/file-{}.bin", dir, file)), b"data").unwrap();
+        }
+    }
+    root
+}
+
+/// Files discovered under `root` by shard `index` of `count`, relative to `root`
+async fn discover(root: &Path, shard: Option<Shard>) -> BTreeSet<PathBuf> {
+    let directories = vec![root.to_path_buf()];
+    let mut filters = DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap();
+    if let Some(shard) = shard {
+        filters = filters.with_shard(shard);
+    }
+    let options = Arc::new(PipelineOptions {
+        filters,
+        directories,
+        queue_depth: 4,
+        threads: Some(2),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let found = Arc::new(Mutex::new(BTreeSet::new()));
+    let mut hooks = MetadataHooks::new();
+    let (sink, base) = (Arc::clone(&found), root.to_path_buf());
+    hooks.register(move |path: &Path, _: &mut FileMetadata| {
+        sink.lock().unwrap().insert(path.strip_prefix(&base).unwrap().to_path_buf());
+    });
+    let summary = pipeline::run(options, PipelineContext { hooks: Arc::new(hooks), ..Default::default() }).await;
+    let found = found.lock().unwrap().clone();
+    assert_eq!(summary.files_discovered, found.len() as u64);
+    assert_eq!(summary.stats.totals.files, found.len() as u64);
+    found
+}
+
+#[test]
+fn shards_are_validated_and_stable() {
+    assert!(Shard::new(0, 0).is_err());
+    assert!(Shard::new(4, 4).is_err());
+    assert_eq!(Shard::new(3, 4).unwrap().to_string(), "3/4");
+    // Pinned so that warmers of different versions and platforms keep agreeing
+    assert_eq!(Shard::of(Path::new("dir-0/file-0.bin"), 1_000_003), 76_423);
+}
+
+#[tokio::test]
+async fn shards_partition_the_files() {
+    let root = tree("partition", "mnt");
+    let all = discover(&root, None).await;
+    assert_eq!(all.len(), 200);
+
+    let mut union = BTreeSet::new();
+    for index in 0..4 {
+        let shard = discover(&root, Some(Shard::new(index, 4).unwrap())).await;
+        assert!(shard.len() > 25 && shard.len() < 75, "shard {} has {} files", index, shard.len());
+        assert!(union.is_disjoint(&shard));
+        union.extend(shard);
+    }
+    assert_eq!(union, all);
+}
+
+#[tokio::test]
+async fn shards_agree_across_mount_points() {
+    let shard = Shard::new(1, 3).unwrap();
+    let here = discover(&tree("mounts", "host-a/data"), Some(shard)).await;
+    let there = discover(&tree("mounts", "host-b/mnt/volume"), Some(shard)).await;
+    assert_eq!(here, there);
+
+    // Files seen outside a walk (watch mode) are sharded the same way
+    let root = tree("mounts", "host-c");
+    let filters = DiscoveryFilters::new(std::slice::from_ref(&root), &[], &[], &[], &[]).unwrap().with_shard(shard);
+    let matched: BTreeSet<PathBuf> = (0..10)
+        .flat_map(|dir| (0..20).map(move |file| PathBuf::from(format!("dir-{}/file-{}.bin", dir, file))))
+        .filter(|relative| filters.matches_file(&root.join(relative)))
+        .collect();
+    assert_eq!(matched, here);
+}
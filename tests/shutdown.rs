@@ -0,0 +1,81 @@
+//! Graceful shutdown: a triggered run stops early, reports only finished files, leaves
+//! them all in the checkpoint, and a resumed run picks up the rest.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rust_cache_warmer::checkpoint::Checkpoint;
+use rust_cache_warmer::events::EventBus;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::shutdown::Shutdown;
+
+const FILES: usize = 200;
+
+fn tree(test: &str) -> PathBuf {
+    let dir = common::scratch(test);
+    fs::create_dir_all(dir.join("data")).unwrap();
+    for i in 0..FILES {
+        fs::write(dir.join("data").join(format!("{}.bin", i)), vec![1u8; 100]).unwrap();
+    }
+    dir
+}
+
+fn options(root: &Path) -> Arc<PipelineOptions> {
+    Arc::new(PipelineOptions { queue_depth: 1, batch_size: 10, ..common::pipeline_options(vec![root.join("data")]) })
+}
+
+#[tokio::test]
+async fn interrupted_runs_report_partial_totals_and_resume() {
+    let root = tree("resume");
+    let journal = root.join("journal");
+
+    // Request a shutdown as soon as a few files have finished
+    let shutdown = Shutdown::new();
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let trigger = shutdown.clone();
+    tokio::spawn(async move {
+        for _ in 0..5 {
+            finished.recv().await;
+        }
+        trigger.trigger(libc::SIGINT);
+    });
+
+    let context = PipelineContext {
+        events,
+        checkpoint: Some(Arc::new(Checkpoint::open(&journal).unwrap())),
+        shutdown: shutdown.clone(),
+        ..Default::default()
+    };
+    let partial = pipeline::run(options(&root), context).await;
+    assert!(partial.interrupted);
+    assert_eq!(shutdown.exit_code(), Some(128 + libc::SIGINT));
+    assert!(partial.files_processed >= 5 && partial.files_processed < FILES as u64, "{:?}", partial.files_processed);
+    let journaled = fs::read_to_string(&journal).unwrap().lines().count() as u64;
+    assert_eq!(journaled, partial.files_processed, "every finished file is flushed to the checkpoint");
+
+    let context = PipelineContext {
+        checkpoint: Some(Arc::new(Checkpoint::open(&journal).unwrap())),
+        ..Default::default()
+    };
+    let resumed = pipeline::run(options(&root), context).await;
+    assert!(!resumed.interrupted);
+    assert_eq!(resumed.files_processed, FILES as u64);
+    assert_eq!(fs::read_to_string(&journal).unwrap().lines().count(), FILES);
+}
+
+#[tokio::test]
+async fn shutdown_before_start_warms_nothing() {
+    let root = tree("early");
+    let shutdown = Shutdown::new();
+    shutdown.trigger(libc::SIGTERM);
+    shutdown.trigger(libc::SIGINT);
+    assert_eq!(shutdown.signal(), Some(libc::SIGTERM), "only the first request counts");
+
+    let summary = pipeline::run(options(&root), PipelineContext { shutdown, ..Default::default() }).await;
+    assert!(summary.interrupted);
+    assert_eq!(summary.files_processed, 0);
+}
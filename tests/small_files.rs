@@ -0,0 +1,104 @@
+//! Files under a block are read with linked io_uring open/read/close chains, chosen by
+//! the size-adaptive policy when the strategy is `auto` or `uring`.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use futures::stream::{self, StreamExt};
+use rust_cache_warmer::warming::ring::SharedRing;
+use rust_cache_warmer::warming::{Coverage, DropCaches, Strategy, StrategyRegistry, WarmingError, WarmingOptions};
+
+use common::scratch;
+
+/// Whether linked chains can run here; io_uring is often blocked in containers
+fn chains_supported() -> bool {
+    let supported = SharedRing::global().is_ok_and(|ring| ring.supports_small_files());
+    if !supported {
+        eprintln!("skipping: io_uring can't open files into registered slots here");
+    }
+    supported
+}
+
+#[test]
+fn policy_only_takes_small_files_under_auto_or_uring() {
+    let registry = StrategyRegistry::builtin();
+    let options = WarmingOptions { small_file_size: 4096, ..Default::default() };
+    let uring = registry.is_available(Strategy::Uring);
+
+    assert_eq!(registry.small_file(1, &options), uring);
+    assert_eq!(registry.small_file(4095, &options), uring);
+    assert!(!registry.small_file(0, &options), "empty files have nothing to read");
+    assert!(!registry.small_file(4096, &options));
+    assert!(!registry.small_file(1, &WarmingOptions { small_file_size: 0, ..options.clone() }), "0 disables the path");
+    assert!(!registry.small_file(1, &WarmingOptions { strategy: Strategy::Tokio, ..options.clone() }), "an explicit strategy wins");
+    assert_eq!(registry.small_file(1, &WarmingOptions { strategy: Strategy::Uring, ..options.clone() }), uring);
+    assert!(!registry.small_file(8191, &WarmingOptions { small_file_size: 1 << 20, ..options }), "the path only reads one block");
+}
+
+#[tokio::test]
+async fn many_small_files_share_the_ring() {
+    if !chains_supported() {
+        return;
+    }
+    let dir = scratch("many");
+    // More files than the ring has slots, so chains queue up behind each other
+    let files: Vec<(PathBuf, u64)> = (0..600u64)
+        .map(|i| {
+            let path = dir.join(format!("file-{}", i));
+            let size = 1 + i * 7 % 4095;
+            fs::write(&path, vec![b'x'; size as usize]).unwrap();
+            (path, size)
+        })
+        .collect();
+
+    let registry = StrategyRegistry::builtin();
+    for drop_caches in [DropCaches::File, DropCaches::None] {
+        let options = WarmingOptions { small_file_size: 4096, drop_caches, ..Default::default() };
+        let results: Vec<_> = stream::iter(&files)
+            .map(|(path, size)| {
+                let (registry, options) = (&registry, &options);
+                async move { (*size, registry.warm(path, *size, options).await.unwrap()) }
+            })
+            .buffer_unordered(512)
+            .collect()
+            .await;
+        for (size, result) in results {
+            assert_eq!(result.method, "io_uring_small_linked");
+            assert_eq!(result.bytes_read, size);
+            assert_eq!(result.coverage, Coverage::Full);
+        }
+    }
+}
+
+#[tokio::test]
+async fn failed_opens_are_reported_and_free_their_slot() {
+    if !chains_supported() {
+        return;
+    }
+    let dir = scratch("missing");
+    let options = WarmingOptions { small_file_size: 4096, ..Default::default() };
+    let registry = StrategyRegistry::builtin();
+    // Every slot fails at least once; the ring must still be usable afterwards
+    for i in 0..300 {
+        let err = registry.warm(&dir.join(format!("gone-{}", i)), 10, &options).await.unwrap_err();
+        assert!(matches!(err, WarmingError::Vanished(_)), "{}", err);
+    }
+    let path = dir.join("present");
+    fs::write(&path, "hello").unwrap();
+    let result = registry.warm(&path, 5, &options).await.unwrap();
+    assert_eq!((result.method, result.bytes_read), ("io_uring_small_linked", 5));
+}
+
+#[tokio::test]
+async fn larger_files_take_the_normal_chain() {
+    let dir = scratch("larger");
+    let path = dir.join("big");
+    fs::write(&path, vec![0u8; 10_000]).unwrap();
+    let options = WarmingOptions { small_file_size: 4096, ..Default::default() };
+    let result = StrategyRegistry::builtin().warm(&path, 10_000, &options).await.unwrap();
+    assert_ne!(result.method, "io_uring_small_linked");
+}
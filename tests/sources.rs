@@ -0,0 +1,169 @@
+//! `--files-from` and `--s3-inventory`: discovery from a list of paths instead of a walk.
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::sources::{self, FileSource, PathList, S3Inventory};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+fn listed(source: &dyn FileSource, roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    source
+        .list(roots, &mut |path| {
+            paths.push(path);
+            true
+        })
+        .unwrap();
+    paths
+}
+
+#[test]
+fn keys_and_records_decode() {
+    assert_eq!(sources::decode_key("logs/2026%2F10/a+b%2Bc.txt").as_deref(), Some("logs/2026/10/a b+c.txt"));
+    assert_eq!(sources::decode_key("bad%2"), None);
+    assert_eq!(sources::csv_fields(r#""bucket","a,""b""","12""#), vec!["bucket", "a,\"b\"", "12"]);
+
+    let roots = [PathBuf::from("/data"), PathBuf::from("/logs")];
+    assert_eq!(sources::resolve(Path::new("x/y"), &roots).collect::<Vec<_>>(), vec![PathBuf::from("/data/x/y"), PathBuf::from("/logs/x/y")]);
+    assert_eq!(sources::resolve(Path::new("/logs/z"), &roots).collect::<Vec<_>>(), vec![PathBuf::from("/logs/z")]);
+    assert_eq!(sources::resolve(Path::new("/etc/passwd"), &roots).count(), 0);
+    assert_eq!(sources::resolve(Path::new("../etc/passwd"), &roots).count(), 0);
+}
+
+#[test]
+fn path_lists_skip_comments_and_blank_lines() {
+    let dir = scratch("list");
+    let list = dir.join("files.txt");
+    fs::write(&list, "# hot files\na\n\nsub/b\r\n/elsewhere/c\n").unwrap();
+    let roots = [dir.join("root")];
+    let source = PathList::open(&list).unwrap();
+    assert_eq!(listed(&source, &roots), vec![roots[0].join("a"), roots[0].join("sub/b")]);
+    assert!(PathList::open(&dir.join("absent")).is_err());
+}
+
+#[test]
+fn inventories_keep_current_objects_under_the_prefix() {
+    let dir = scratch("inventory");
+    let report = dir.join("reports/2026-10-17T01-00Z");
+    fs::create_dir_all(&report).unwrap();
+    fs::create_dir_all(dir.join("reports/data")).unwrap();
+    fs::write(
+        dir.join("reports/data/0f1e.csv"),
+        concat!(
+            "\"bucket\",\"db/users.ibd\",\"v2\",\"true\",\"false\",\"4096\"\n",
+            "\"bucket\",\"db/users.ibd\",\"v1\",\"false\",\"false\",\"4096\"\n",
+            "\"bucket\",\"db/old.ibd\",\"v3\",\"true\",\"true\",\"0\"\n",
+            "\"bucket\",\"db/\",\"v4\",\"true\",\"false\",\"0\"\n",
+            "\"bucket\",\"db/my+table%231.ibd\",\"v5\",\"true\",\"false\",\"8192\"\n",
+            "\"bucket\",\"other/x\",\"v6\",\"true\",\"false\",\"1\"\n",
+        ),
+    )
+    .unwrap();
+    let manifest = report.join("manifest.json");
+    fs::write(
+        &manifest,
+        r#"{"sourceBucket":"bucket","fileFormat":"CSV","fileSchema":"Bucket, Key, VersionId, IsLatest, IsDeleteMarker, Size","files":[{"key":"inv/bucket/cfg/data/0f1e.csv","size":1,"MD5checksum":"x"}]}"#,
+    )
+    .unwrap();
+
+    let roots = [PathBuf::from("/var/lib/mysql")];
+    let inventory = S3Inventory::open(std::slice::from_ref(&manifest), "db/").unwrap();
+    assert_eq!(listed(&inventory, &roots), vec![PathBuf::from("/var/lib/mysql/users.ibd"), PathBuf::from("/var/lib/mysql/my table#1.ibd")]);
+
+    fs::write(&manifest, r#"{"fileFormat":"Parquet","fileSchema":"message s3.inventory {}","files":[]}"#).unwrap();
+    assert!(S3Inventory::open(&[manifest], "").unwrap_err().to_string().contains("only CSV"));
+}
+
+#[tokio::test]
+async fn only_listed_files_are_warmed() {
+    let dir = scratch("pipeline");
+    for name in ["a", "b", "c", "sub/d"] {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![1u8; 8192]).unwrap();
+    }
+    let list = scratch("pipeline-list").join("files.txt");
+    fs::write(&list, "sub/d\nmissing\nsub\na\n").unwrap();
+
+    let directories = vec![dir.clone()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 1,
+        threads: None,
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let source: Arc<dyn FileSource> = Arc::new(PathList::open(&list).unwrap());
+    let summary = pipeline::run(options, PipelineContext { events, source: Some(source), ..Default::default() }).await;
+    assert_eq!((summary.files_discovered, summary.files_processed, summary.missing), (2, 2, 1));
+
+    let mut order = Vec::new();
+    while let Ok(WarmEvent::FileFinished { path, .. }) = finished.try_recv() {
+        order.push(path);
+    }
+    assert_eq!(order, vec![dir.join("sub/d"), dir.join("a")]);
+}
+
+#[test]
+fn files_from_stdin() {
+    let dir = scratch("cli");
+    fs::write(dir.join("hot"), vec![1u8; 4096]).unwrap();
+    fs::write(dir.join("cold"), vec![1u8; 4096]).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+        .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--files-from", "-"])
+        .arg(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"hot\ngone\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Warming the 2 paths listed on stdin instead of walking the target directories"));
+    assert!(stderr.contains("1 listed files don't exist under the target directories"), "{}", stderr);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+        .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--files-from", "-", "--precompute-total"])
+        .arg(&dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
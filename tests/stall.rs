@@ -0,0 +1,35 @@
+//! `--stall-threshold-ms`: a worker held without yielding shows up as a stall, and a
+//! runtime that keeps yielding doesn't.
+
+use std::time::Duration;
+
+use rust_cache_warmer::stall::StallMonitor;
+use rust_cache_warmer::warnings::{Category, Warnings};
+
+#[tokio::test(flavor = "current_thread")]
+async fn a_blocked_worker_is_a_stall() {
+    let monitor = StallMonitor::start(Duration::from_millis(50));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    // What a synchronous loop on the only worker looks like to everything else
+    std::thread::sleep(Duration::from_millis(300));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let summary = monitor.summary();
+    assert_eq!(summary.stalls, 1, "{}", summary);
+    assert!(summary.worst_lag >= Duration::from_millis(200), "{}", summary);
+    assert!(Warnings::global().counts().iter().any(|count| count.category == Category::RuntimeStall));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn yielding_work_is_not_a_stall() {
+    let monitor = StallMonitor::start(Duration::from_millis(100));
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_millis(5));
+        tokio::task::yield_now().await;
+    }
+    tokio::time::sleep(Duration::from_millis(60)).await;
+
+    let summary = monitor.summary();
+    assert!(summary.probes > 0);
+    assert_eq!(summary.stalls, 0, "{}", summary);
+}
@@ -0,0 +1,403 @@
+//! Strategy conformance suite.
+//!
+//! Every warming backend must honour the same observable contract before it is
+//! selectable. The checks below are written once and run against each strategy:
+//!
+//! * empty files succeed without reading anything
+//! * tiny files and files ending in a partial ("tail") block are read in full by
+//!   read-based strategies (`bytes_read == file size`, `Coverage::Full`); advisory
+//!   strategies report 0 and `Coverage::Advisory`
+//! * above the sparse threshold, read-based strategies take the sparse path
+//!   (method name contains `sparse`), read strictly less than the file size and
+//!   report `Coverage::Sampled`
+//! * files that vanished before warming fail with `NotFound` (ESTALE on NFS is
+//!   classified the same way by callers, but cannot be reproduced on a local fs)
+//! * unreadable files fail with `PermissionDenied` rather than reporting success
+
+mod common;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::warming::{self, Coverage, FallbackPolicy, Strategy, StrategyRegistry, WarmingOptions, WarmingResult};
+
+use common::scratch;
+
+const BLOCK: u64 = 4096;
+
+type WarmFn = fn(PathBuf, u64, WarmingOptions) -> LocalBoxFuture<'static, io::Result<WarmingResult>>;
+
+struct StrategyUnderTest {
+    name: &'static str,
+    /// Whether the strategy reads data (as opposed to only issuing advice)
+    reads_data: bool,
+    direct_io: bool,
+    warm: WarmFn,
+}
+
+fn strategies() -> Vec<StrategyUnderTest> {
+    let mut list = vec![
+        StrategyUnderTest {
+            name: "auto",
+            reads_data: cfg!(target_os = "linux"),
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::warm_file(&p, s, &o).await.map_err(io::Error::from) }),
+        },
+        StrategyUnderTest {
+            name: "os_hints",
+            reads_data: false,
+            direct_io: false,
+            warm: |p, s, _| Box::pin(async move { warming::fallback::warm_with_os_hints(&p, s, true).await }),
+        },
+        StrategyUnderTest {
+            name: "tokio",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::tokio_async::warm_file(&p, s, &o).await }),
+        },
+        StrategyUnderTest {
+            name: "tokio_nowait",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::tokio_async::warm_file(&p, s, &WarmingOptions { nowait_precheck: true, ..o }).await }),
+        },
+        StrategyUnderTest {
+            name: "tokio_direct",
+            reads_data: true,
+            direct_io: true,
+            warm: |p, s, o| Box::pin(async move { warming::tokio_async::warm_file(&p, s, &o).await }),
+        },
+    ];
+
+    #[cfg(target_os = "linux")]
+    list.extend([
+        StrategyUnderTest {
+            name: "readahead",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::readahead::warm_file(&p, s, &o).await }),
+        },
+        StrategyUnderTest {
+            name: "sendfile",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::sendfile::warm_file(&p, s, &o).await }),
+        },
+        StrategyUnderTest {
+            name: "mmap_touch",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::mmap::warm_file(&p, s, &WarmingOptions { mmap_touch_stride: 1, ..o }).await }),
+        },
+        StrategyUnderTest {
+            name: "mmap_willneed",
+            reads_data: false,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::mmap::warm_file(&p, s, &WarmingOptions { mmap_touch_stride: 0, ..o }).await }),
+        },
+        StrategyUnderTest {
+            name: "io_uring_direct",
+            reads_data: true,
+            direct_io: true,
+            warm: |p, s, o| Box::pin(async move { warming::io_uring::warm_file(&p, s, &o).await }),
+        },
+        StrategyUnderTest {
+            name: "io_uring_buffered",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::io_uring::warm_file(&p, s, &o).await }),
+        },
+        StrategyUnderTest {
+            name: "io_uring_buffered_nowait",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::io_uring::warm_file(&p, s, &WarmingOptions { nowait_precheck: true, ..o }).await }),
+        },
+        StrategyUnderTest {
+            name: "small_file_chain",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::warm_file(&p, s, &WarmingOptions { small_file_size: BLOCK, ..o }).await.map_err(io::Error::from) }),
+        },
+        StrategyUnderTest {
+            name: "small_file_chain_direct",
+            reads_data: true,
+            direct_io: true,
+            warm: |p, s, o| Box::pin(async move { warming::warm_file(&p, s, &WarmingOptions { small_file_size: BLOCK, ..o }).await.map_err(io::Error::from) }),
+        },
+        StrategyUnderTest {
+            name: "libaio_direct",
+            reads_data: true,
+            direct_io: true,
+            warm: |p, s, o| Box::pin(async move { warming::libaio::warm_file(&p, s, &o).await }),
+        },
+        StrategyUnderTest {
+            name: "libaio_buffered",
+            reads_data: true,
+            direct_io: false,
+            warm: |p, s, o| Box::pin(async move { warming::libaio::warm_file(&p, s, &o).await }),
+        },
+    ]);
+
+    list
+}
+
+fn options_for(strategy: &StrategyUnderTest, sparse_large_files: u64) -> WarmingOptions {
+    WarmingOptions {
+        use_direct_io: strategy.direct_io,
+        sparse_large_files,
+        ..Default::default()
+    }
+}
+
+fn write_file(dir: &Path, name: &str, size: u64) -> PathBuf {
+    let path = dir.join(name);
+    let mut file = fs::File::create(&path).unwrap();
+    let chunk = vec![0xA5u8; 64 * 1024];
+    let mut remaining = size;
+    while remaining > 0 {
+        let n = remaining.min(chunk.len() as u64) as usize;
+        file.write_all(&chunk[..n]).unwrap();
+        remaining -= n as u64;
+    }
+    file.sync_all().unwrap();
+    path
+}
+
+/// O_DIRECT is not available on every filesystem (e.g. older tmpfs); skip those strategies there.
+fn direct_io_supported(dir: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let probe = write_file(dir, ".direct_probe", BLOCK);
+        fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(probe)
+            .is_ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = dir;
+        false
+    }
+}
+
+/// Run `check` against every strategy that can run in this environment.
+async fn for_each_strategy<F>(test: &str, mut check: F)
+where
+    F: FnMut(&StrategyUnderTest, &Path) -> LocalBoxFuture<'static, ()>,
+{
+    let dir = scratch(test);
+    let direct_ok = direct_io_supported(&dir);
+    for strategy in strategies() {
+        if strategy.direct_io && !direct_ok {
+            eprintln!("skipping {} for {}: O_DIRECT unsupported here", strategy.name, test);
+            continue;
+        }
+        let strategy_dir = dir.join(strategy.name);
+        fs::create_dir_all(&strategy_dir).unwrap();
+        check(&strategy, &strategy_dir).await;
+    }
+}
+
+#[tokio::test]
+async fn empty_files_succeed_without_reading() {
+    for_each_strategy("empty", |strategy, dir| {
+        let path = write_file(dir, "empty", 0);
+        let (name, warm, options) = (strategy.name, strategy.warm, options_for(strategy, 0));
+        Box::pin(async move {
+            let result = warm(path, 0, options).await.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(result.success, "{} reported failure on an empty file", name);
+            assert_eq!(result.bytes_read, 0, "{} read bytes from an empty file", name);
+        })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn tiny_files_are_read_in_full() {
+    for_each_strategy("tiny", |strategy, dir| {
+        let path = write_file(dir, "tiny", 1);
+        let (name, reads_data, warm, options) = (strategy.name, strategy.reads_data, strategy.warm, options_for(strategy, 0));
+        Box::pin(async move {
+            let result = warm(path, 1, options).await.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(result.success, "{} reported failure on a tiny file", name);
+            let expected = if reads_data { 1 } else { 0 };
+            assert_eq!(result.bytes_read, expected, "{} bytes touched on a 1-byte file", name);
+        })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn tail_blocks_are_not_dropped() {
+    let size = 3 * BLOCK + 17;
+    for_each_strategy("tail", move |strategy, dir| {
+        let path = write_file(dir, "tail", size);
+        let (name, reads_data, warm, options) = (strategy.name, strategy.reads_data, strategy.warm, options_for(strategy, 0));
+        Box::pin(async move {
+            let result = warm(path, size, options).await.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(result.success, "{} reported failure on a file with a tail block", name);
+            let expected = if reads_data { size } else { 0 };
+            assert_eq!(result.bytes_read, expected, "{} bytes touched with a partial tail block", name);
+            let coverage = if reads_data { Coverage::Full } else { Coverage::Advisory };
+            assert_eq!(result.coverage, coverage, "{} misreported its coverage", name);
+        })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn sparse_threshold_is_honoured() {
+    let size = 64 * 65536 + 100;
+    for_each_strategy("sparse", move |strategy, dir| {
+        let path = write_file(dir, "sparse", size);
+        let (name, reads_data, warm, options) = (strategy.name, strategy.reads_data, strategy.warm, options_for(strategy, 65536));
+        Box::pin(async move {
+            let result = warm(path, size, options).await.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(result.success, "{} reported failure in sparse mode", name);
+            if reads_data {
+                assert!(result.method.contains("sparse"), "{} ignored the sparse threshold (method {})", name, result.method);
+                assert!(result.bytes_read > 0 && result.bytes_read < size, "{} sparse read touched {} of {} bytes", name, result.bytes_read, size);
+                assert_eq!(result.coverage, Coverage::Sampled, "{} didn't report its sparse read as sampled", name);
+            }
+        })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn vanished_files_fail_with_not_found() {
+    for_each_strategy("vanished", |strategy, dir| {
+        let path = dir.join("does-not-exist");
+        let (name, warm, options) = (strategy.name, strategy.warm, options_for(strategy, 0));
+        Box::pin(async move {
+            match warm(path, BLOCK, options).await {
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::NotFound, "{} error kind for a vanished file", name),
+                Ok(result) => panic!("{} reported {:?} for a vanished file", name, result),
+            }
+        })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn unreadable_files_fail_with_permission_denied() {
+    #[cfg(unix)]
+    {
+        // Root bypasses file permissions, so this contract can only be observed as a regular user
+        if unsafe { libc::geteuid() } == 0 {
+            eprintln!("skipping permission conformance checks: running as root");
+            return;
+        }
+    }
+
+    for_each_strategy("permission", |strategy, dir| {
+        let path = write_file(dir, "locked", BLOCK);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        }
+        let (name, warm, options) = (strategy.name, strategy.warm, options_for(strategy, 0));
+        Box::pin(async move {
+            match warm(path, BLOCK, options).await {
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied, "{} error kind for an unreadable file", name),
+                Ok(result) => panic!("{} reported {:?} for an unreadable file", name, result),
+            }
+        })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn pinned_strategies_warm_through_the_registry() {
+    let registry = StrategyRegistry::builtin();
+    let dir = scratch("registry");
+    let size = 3 * BLOCK + 17;
+    let path = write_file(&dir, "pinned", size);
+
+    for strategy in Strategy::ALL {
+        let options = WarmingOptions { strategy, fallback: FallbackPolicy::None, ..Default::default() };
+        let plan = registry.plan(&options);
+        let usable = registry.is_available(strategy) && registry.backend(strategy).is_some_and(|b| b.supports(false));
+        if !usable {
+            assert!(plan.is_empty(), "{} is unusable here but planned as {:?}", strategy, plan);
+            continue;
+        }
+        assert_eq!(plan, vec![strategy], "fallback 'none' must pin the plan to {}", strategy);
+        let result = registry.warm(&path, size, &options).await.unwrap_or_else(|e| panic!("{}: {}", strategy, e));
+        assert!(result.success, "{} reported failure when pinned", strategy);
+    }
+}
+
+#[test]
+fn fallback_policies_parse() {
+    assert_eq!("auto".parse::<FallbackPolicy>(), Ok(FallbackPolicy::Auto));
+    assert_eq!("none".parse::<FallbackPolicy>(), Ok(FallbackPolicy::None));
+    assert_eq!(
+        "libaio,tokio".parse::<FallbackPolicy>(),
+        Ok(FallbackPolicy::Chain(vec![Strategy::Libaio, Strategy::Tokio]))
+    );
+    assert!("uring,auto".parse::<FallbackPolicy>().is_err());
+    assert!("bogus".parse::<FallbackPolicy>().is_err());
+}
+
+#[tokio::test]
+async fn holes_in_sparse_files_are_not_read() {
+    let dir = scratch("holes");
+    let size = 16 * 1024 * 1024;
+    let data_offset = 8 * 1024 * 1024;
+    let path = dir.join("holey");
+    {
+        use std::io::{Seek, SeekFrom};
+        let mut file = fs::File::create(&path).unwrap();
+        file.set_len(size).unwrap();
+        file.seek(SeekFrom::Start(data_offset)).unwrap();
+        file.write_all(&vec![0xA5u8; 128 * 1024]).unwrap();
+        file.sync_all().unwrap();
+    }
+
+    let result = warming::warm_file(&path, size, &WarmingOptions::default()).await.unwrap();
+    assert!(result.success);
+    if result.bytes_read < size {
+        assert!(result.bytes_read >= 128 * 1024, "read {} bytes of a mostly-hole file", result.bytes_read);
+    } else {
+        // Filesystems that don't report holes (or allocate eagerly) warm the whole file
+        eprintln!("hole detection unavailable here (method {})", result.method);
+    }
+
+    let options = WarmingOptions { read_holes: true, ..Default::default() };
+    let result = warming::warm_file(&path, size, &options).await.unwrap();
+    assert_eq!(result.bytes_read, size, "--read-holes still skipped holes");
+}
+
+#[tokio::test]
+async fn requested_ranges_are_narrowed_to_data_not_replaced() {
+    let dir = scratch("requested_ranges");
+    let size = 16 * 1024 * 1024;
+    let data_offset = 8 * 1024 * 1024;
+    let path = dir.join("holey");
+    {
+        use std::io::{Seek, SeekFrom};
+        let mut file = fs::File::create(&path).unwrap();
+        file.set_len(size).unwrap();
+        file.seek(SeekFrom::Start(data_offset)).unwrap();
+        file.write_all(&vec![0xA5u8; 128 * 1024]).unwrap();
+        file.sync_all().unwrap();
+    }
+
+    // Half of the data and a megabyte of the hole after it
+    let requested = (data_offset + 64 * 1024, 1024 * 1024);
+    let options = WarmingOptions { ranges: Some(Arc::from([requested])), ..Default::default() };
+    let result = warming::warm_file(&path, size, &options).await.unwrap();
+    assert!(result.success);
+    if result.bytes_read != 64 * 1024 {
+        // Without hole detection the whole requested range is read, and nothing else
+        assert_eq!(result.bytes_read, requested.1, "method {}", result.method);
+    }
+}
@@ -0,0 +1,103 @@
+//! `--strategy-map`: a strategy per size class, applied to each file by its size.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rust_cache_warmer::warming::{ClassStrategy, DropCaches, FallbackPolicy, SizeClass, Strategy, StrategyMap, StrategyRegistry, WarmingOptions};
+
+const KB: u64 = 1024;
+const MB: u64 = 1024 * KB;
+
+#[test]
+fn maps_parse() {
+    assert_eq!(SizeClass::of(0), SizeClass::Tiny);
+    assert_eq!(SizeClass::of(4 * KB), SizeClass::Tiny);
+    assert_eq!(SizeClass::of(4 * KB + 1), SizeClass::Small);
+    assert_eq!(SizeClass::of(MB), SizeClass::Medium);
+    assert_eq!(SizeClass::of(100 * MB), SizeClass::Large);
+    assert_eq!(SizeClass::of(100 * MB + 1), SizeClass::Huge);
+
+    let map: StrategyMap = "huge=uring_sparse, tiny=fadvise,medium=io_uring".parse().unwrap();
+    assert_eq!(map.get(SizeClass::Tiny), Some(ClassStrategy { strategy: Strategy::Fadvise, sparse: false }));
+    assert_eq!(map.get(SizeClass::Huge), Some(ClassStrategy { strategy: Strategy::Uring, sparse: true }));
+    assert_eq!(map.get(SizeClass::Large), None);
+    assert_eq!(map.to_string(), "tiny=fadvise,medium=uring,huge=uring_sparse", "smallest class first");
+
+    assert!("tiny=fadvise,tiny=tokio".parse::<StrategyMap>().is_err(), "mapped twice");
+    assert!("gigantic=uring".parse::<StrategyMap>().is_err());
+    assert!("tiny".parse::<StrategyMap>().is_err());
+    assert!("huge=auto_sparse".parse::<StrategyMap>().is_err(), "sampling needs a strategy");
+    assert!("".parse::<StrategyMap>().is_err());
+}
+
+#[test]
+fn each_class_gets_its_own_options() {
+    let options = WarmingOptions {
+        strategy: Strategy::Libaio,
+        use_direct_io: true,
+        strategy_map: "tiny=fadvise,huge=uring_sparse".parse().unwrap(),
+        ..Default::default()
+    };
+    let tiny = options.for_size(100);
+    assert_eq!(tiny.strategy, Strategy::Fadvise);
+    assert!(!tiny.use_direct_io, "advice goes through the page cache");
+    let huge = options.for_size(200 * MB);
+    assert_eq!(huge.strategy, Strategy::Uring);
+    assert!(huge.use_direct_io && huge.is_sparse(200 * MB));
+    let medium = options.for_size(MB);
+    assert_eq!(medium.strategy, Strategy::Libaio, "unmapped classes keep --strategy");
+    assert!(!medium.is_sparse(MB));
+}
+
+#[tokio::test]
+async fn files_are_warmed_by_their_class_strategy() {
+    let dir = common::scratch("warm");
+    let (tiny, medium) = (dir.join("tiny"), dir.join("medium"));
+    fs::write(&tiny, vec![1u8; 100]).unwrap();
+    fs::write(&medium, vec![2u8; 256 * KB as usize]).unwrap();
+
+    let registry = StrategyRegistry::builtin();
+    let options = WarmingOptions {
+        strategy: Strategy::Tokio,
+        fallback: FallbackPolicy::None,
+        drop_caches: DropCaches::None,
+        small_file_size: 0,
+        strategy_map: "tiny=fadvise,medium=tokio_sparse".parse().unwrap(),
+        ..Default::default()
+    };
+    let result = registry.warm(&tiny, 100, &options).await.unwrap();
+    assert_eq!(result.method, "linux_fadvise");
+    let result = registry.warm(&medium, 256 * KB, &options).await.unwrap();
+    assert_eq!(result.method, "tokio_sparse");
+    assert_eq!(result.bytes_read, 256 * KB / 4096, "a byte per page");
+
+    let unmapped = WarmingOptions { strategy_map: StrategyMap::default(), ..options };
+    assert_eq!(registry.warm(&medium, 256 * KB, &unmapped).await.unwrap().method, "tokio_full");
+}
+
+#[test]
+fn the_map_is_shown_and_checked() {
+    let root = common::scratch("cli");
+    fs::write(root.join("a"), b"tiny").unwrap();
+    let warmer = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+            .args(args)
+            .arg(&root)
+            .output()
+            .unwrap()
+    };
+
+    let output = warmer(&["--strategy", "tokio", "--strategy-map", "tiny=fadvise"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("📐 By size: tiny fadvise"), "{}", String::from_utf8_lossy(&output.stdout));
+
+    assert!(!warmer(&["--strategy-map", "tiny=fadvise", "--ab-test", "tokio,uring"]).status.success());
+    let output = warmer(&["--strategy-map", "tiny=fadvise", "--strict-coverage"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--strict-coverage requires reading files"));
+    assert!(!warmer(&["--strategy-map", "gigantic=uring"]).status.success());
+}
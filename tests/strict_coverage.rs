@@ -0,0 +1,129 @@
+//! `--strict-coverage`: only files fully read by a read-based strategy count as covered;
+//! failures, skips, sampled and advisory warms, and unreadable directories don't.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::coverage::{self, CoverageAudit, Shortfall};
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::stats::FileStatus;
+use rust_cache_warmer::warming::{Coverage, FallbackPolicy, Strategy, WarmingOptions};
+
+fn finished(name: &str, status: FileStatus, coverage: Option<Coverage>) -> WarmEvent {
+    WarmEvent::FileFinished {
+        path: PathBuf::from("/data").join(name),
+        bytes: 4096,
+        latency: Duration::from_millis(1),
+        status,
+        method: None,
+        bytes_read: 0,
+        error: None,
+        coverage,
+    }
+}
+
+#[test]
+fn only_fully_read_files_are_covered() {
+    let mut audit = CoverageAudit::new();
+    audit.record(&finished("full", FileStatus::Warmed, Some(Coverage::Full)));
+    assert!(audit.is_complete());
+
+    audit.record(&finished("sampled", FileStatus::Warmed, Some(Coverage::Sampled)));
+    audit.record(&finished("hinted", FileStatus::Warmed, Some(Coverage::Advisory)));
+    audit.record(&finished("cut-short", FileStatus::Warmed, Some(Coverage::Partial)));
+    audit.record(&finished("too-big", FileStatus::Skipped, None));
+    audit.record(&finished("broken", FileStatus::Failed, None));
+    assert!(!audit.is_complete());
+    assert_eq!(audit.covered_files(), 1);
+    for shortfall in [Shortfall::Sampled, Shortfall::Advisory, Shortfall::Partial, Shortfall::Skipped, Shortfall::Failed] {
+        assert_eq!(audit.shortfall_files(shortfall), 1, "{:?}", shortfall);
+    }
+
+    let report = audit.to_string();
+    assert!(report.contains("1 files (0.00 MB) fully read, 5 files not fully read"), "{}", report);
+    assert!(report.contains("/data/hinted"), "{}", report);
+}
+
+#[test]
+fn unreadable_directories_break_coverage() {
+    let mut audit = CoverageAudit::new();
+    audit.record(&finished("full", FileStatus::Warmed, Some(Coverage::Full)));
+    audit.add_discovery_errors(2);
+    assert!(!audit.is_complete());
+    assert!(audit.to_string().contains("2 directory entries couldn't be read"));
+}
+
+#[test]
+fn examples_are_capped() {
+    let mut audit = CoverageAudit::new();
+    for i in 0..25 {
+        audit.record(&finished(&format!("f{}", i), FileStatus::Failed, None));
+    }
+    let report = audit.to_string();
+    assert_eq!(report.lines().filter(|line| line.starts_with("    /data/")).count(), 10);
+    assert!(report.contains("... and 15 more"), "{}", report);
+}
+
+async fn audit_run(root: &Path, sparse_large_files: u64) -> CoverageAudit {
+    let directories = vec![root.to_path_buf()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 2,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 10,
+        batch_bytes: 0,
+        warming: WarmingOptions {
+            strategy: Strategy::Tokio,
+            fallback: FallbackPolicy::None,
+            sparse_large_files,
+            ..Default::default()
+        },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut events = EventBus::new();
+    let audit = tokio::spawn(coverage::run_audit(events.subscribe()));
+    let summary = pipeline::run(options, PipelineContext { events, ..Default::default() }).await;
+    let mut audit = audit.await.unwrap();
+    audit.add_discovery_errors(summary.discovery_errors);
+    audit
+}
+
+#[tokio::test]
+async fn strategies_report_how_much_they_read() {
+    let root = common::scratch("bytes_read");
+    fs::write(root.join("small"), vec![1u8; 10_000]).unwrap();
+    fs::write(root.join("large"), vec![1u8; 300_000]).unwrap();
+
+    let audit = audit_run(&root, 0).await;
+    assert!(audit.is_complete(), "{}", audit);
+    assert_eq!(audit.covered_files(), 2);
+
+    let audit = audit_run(&root, 100_000).await;
+    assert!(!audit.is_complete());
+    assert_eq!(audit.shortfall_files(Shortfall::Sampled), 1, "{}", audit);
+}
@@ -0,0 +1,53 @@
+//! Subcommands: `warm` is assumed when the arguments don't name a subcommand, so bare
+//! directories keep working, and `verify` checks a tree without warming it.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn tree(test: &str) -> PathBuf {
+    let dir = common::scratch(test);
+    for i in 0..3 {
+        fs::write(dir.join(format!("file{}", i)), vec![1u8; 8192]).unwrap();
+    }
+    dir
+}
+
+fn warmer(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state")).args(args).arg(dir).output().unwrap()
+}
+
+#[test]
+fn bare_directories_and_leading_flags_mean_warm() {
+    let dir = tree("warm");
+    for args in [&[][..], &["warm"], &["--queue-depth", "4"], &["warm", "--queue-depth", "4"]] {
+        let output = warmer(args, &dir);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Cache Warming Strategy"), "{:?} didn't warm", args);
+    }
+}
+
+#[test]
+fn verify_samples_without_warming() {
+    let dir = tree("verify");
+    let output = warmer(&["verify", "--samples", "10", "--seed", "3"], &dir);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Verification: 10 samples over 3 files"), "{}", stdout);
+    assert!(!stdout.contains("Cache Warming Strategy"), "verify warmed: {}", stdout);
+}
+
+#[test]
+fn warm_options_belong_to_warm() {
+    let dir = tree("options");
+    let output = warmer(&["verify", "--direct-io"], &dir);
+    assert!(!output.status.success(), "verify accepted a warm option");
+
+    let help = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state")).arg("--help").output().unwrap();
+    let help = String::from_utf8_lossy(&help.stdout);
+    for subcommand in ["warm", "verify", "estimate", "bench", "debug-bundle", "history"] {
+        assert!(help.contains(&format!("  {} ", subcommand)), "{} missing from:\n{}", subcommand, help);
+    }
+}
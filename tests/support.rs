@@ -0,0 +1,109 @@
+//! Feature negotiation: requests this host or build can't serve are downgraded and
+//! listed together, granted ones are left alone, and only a request that leaves nothing
+//! to run is refused.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use rust_cache_warmer::support::{self, Platform, RunRequests};
+use rust_cache_warmer::warming::{Coverage, DropCaches, FallbackPolicy, Strategy, StrategyRegistry, WarmingBackend, WarmingError, WarmingOptions, WarmingResult};
+
+/// A backend whose availability and I/O modes are fixed
+struct Fake {
+    strategy: Strategy,
+    available: bool,
+    direct_io: bool,
+}
+
+impl WarmingBackend for Fake {
+    fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    fn probe(&self) -> bool {
+        self.available
+    }
+
+    fn supports(&self, use_direct_io: bool) -> bool {
+        !use_direct_io || self.direct_io
+    }
+
+    fn warm<'a>(&'a self, _path: &'a Path, file_size: u64, _options: &'a WarmingOptions) -> LocalBoxFuture<'a, Result<WarmingResult, WarmingError>> {
+        Box::pin(async move { Ok(WarmingResult { method: "fake", success: true, duration: Duration::ZERO, bytes_read: file_size, coverage: Coverage::Full }) })
+    }
+}
+
+/// io_uring missing, as on an old kernel or a macOS laptop
+fn registry() -> StrategyRegistry {
+    let mut registry = StrategyRegistry::new();
+    registry
+        .register(Arc::new(Fake { strategy: Strategy::Uring, available: false, direct_io: true }))
+        .register(Arc::new(Fake { strategy: Strategy::Fadvise, available: true, direct_io: false }))
+        .register(Arc::new(Fake { strategy: Strategy::Tokio, available: true, direct_io: true }));
+    registry
+}
+
+const EVERYTHING: Platform = Platform { linux: true, otel: true, aws: true, zstd: true, tui: true, direct_io: true };
+const BARE: Platform = Platform { linux: false, otel: false, aws: false, zstd: false, tui: false, direct_io: false };
+
+#[test]
+fn granted_requests_are_left_alone() {
+    let mut warming = WarmingOptions { strategy: Strategy::Tokio, skip_cached: true, drop_caches: DropCaches::Global, ..Default::default() };
+    let mut run = RunRequests { physical_order: true, tracing: true, snapshot_ranges: true, small_files: false, dashboard: true };
+    let matrix = support::negotiate(&registry(), &mut warming, &mut run, &EVERYTHING).unwrap();
+
+    assert_eq!(matrix.notice(), None);
+    assert_eq!(matrix.options.len(), 7);
+    assert!(warming.skip_cached && warming.drop_caches == DropCaches::Global);
+    assert!(run.physical_order && run.tracing && run.snapshot_ranges && run.dashboard);
+    let tokio = matrix.strategies.iter().find(|support| support.strategy == "tokio").unwrap();
+    assert!(tokio.available && tokio.requested && tokio.planned);
+}
+
+#[test]
+fn unsupported_requests_are_downgraded_in_one_notice() {
+    let mut warming = WarmingOptions { strategy: Strategy::Uring, nowait_precheck: true, small_file_size: 4096, skip_cached: true, drop_caches: DropCaches::Global, ..Default::default() };
+    let mut run = RunRequests { physical_order: true, tracing: true, snapshot_ranges: true, small_files: true, dashboard: true };
+    let matrix = support::negotiate(&registry(), &mut warming, &mut run, &BARE).unwrap();
+
+    assert!(!warming.nowait_precheck && !warming.skip_cached && warming.small_file_size == 0);
+    assert_eq!(warming.drop_caches, DropCaches::File);
+    assert_eq!(run, RunRequests::default());
+    let downgraded: Vec<&str> = matrix.downgrades().map(|option| option.option).collect();
+    assert_eq!(
+        downgraded,
+        ["--strategy", "--nowait-precheck", "--small-file-size", "--skip-cached", "--drop-caches-after", "--physical-order", "--otlp-endpoint", "--ebs-snapshot-id", "--tui"]
+    );
+    assert_eq!(matrix.options[0].granted, "fadvise", "the fallback chain takes over");
+    let notice = matrix.notice().unwrap();
+    assert!(notice.contains("--strategy uring → fadvise (unavailable on this host)"), "{}", notice);
+    assert!(matrix.lines()[0].contains("uring ✗ ←"), "{:?}", matrix.lines());
+}
+
+#[test]
+fn a_pinned_strategy_keeps_its_io_mode_compatible() {
+    let mut warming = WarmingOptions { strategy: Strategy::Fadvise, fallback: FallbackPolicy::None, use_direct_io: true, ..Default::default() };
+    let matrix = support::negotiate(&registry(), &mut warming, &mut RunRequests::default(), &EVERYTHING).unwrap();
+    assert!(!warming.use_direct_io);
+    assert_eq!(matrix.downgrades().next().unwrap().option, "--direct-io");
+    assert_eq!(registry().plan(&warming), [Strategy::Fadvise]);
+}
+
+#[test]
+fn nothing_left_to_run_is_an_error() {
+    let mut warming = WarmingOptions { strategy: Strategy::Uring, fallback: FallbackPolicy::None, ..Default::default() };
+    let error = support::negotiate(&registry(), &mut warming, &mut RunRequests::default(), &EVERYTHING).unwrap_err();
+    assert!(error.contains("unavailable"), "{}", error);
+}
+
+#[test]
+fn a_filesystem_refusing_o_direct_turns_direct_io_off_first() {
+    let mut warming = WarmingOptions { strategy: Strategy::Tokio, use_direct_io: true, ..Default::default() };
+    let platform = Platform { direct_io: false, ..EVERYTHING };
+    let matrix = support::negotiate(&registry(), &mut warming, &mut RunRequests::default(), &platform).unwrap();
+    assert!(!warming.use_direct_io);
+    assert_eq!(matrix.downgrades().next().unwrap().option, "--direct-io");
+    assert_eq!(matrix.options[1], support::Negotiated { option: "--strategy", requested: "tokio".into(), granted: "tokio".into(), reason: None });
+}
@@ -0,0 +1,78 @@
+//! Targets given through a symlink: they resolve to the real directory, two spellings of
+//! one tree are walked once, and a checkpoint written through the link resumes under
+//! the real path.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rust_cache_warmer::checkpoint::Checkpoint;
+use rust_cache_warmer::stats::{FileOutcome, FileStatus};
+use rust_cache_warmer::symlinks::{self, LinkedRoot, SymlinkRoots};
+
+/// `real/` with two files, and `link` pointing at it; returns the canonical `real`
+fn tree(test: &str) -> (PathBuf, PathBuf) {
+    let dir = common::scratch(test);
+    fs::create_dir_all(dir.join("real/sub")).unwrap();
+    fs::write(dir.join("real/a"), b"data").unwrap();
+    fs::write(dir.join("real/sub/b"), b"data").unwrap();
+    symlink("real", dir.join("link")).unwrap();
+    let real = fs::canonicalize(dir.join("real")).unwrap();
+    (real.parent().unwrap().to_path_buf(), real)
+}
+
+#[test]
+fn modes_parse() {
+    assert_eq!("resolve".parse(), Ok(SymlinkRoots::Resolve));
+    assert_eq!("Keep".parse(), Ok(SymlinkRoots::Keep));
+    assert!("follow".parse::<SymlinkRoots>().is_err());
+}
+
+#[test]
+fn only_targets_through_a_link_are_resolved() {
+    let (dir, real) = tree("resolve");
+    assert_eq!(symlinks::resolve_root(&dir.join("link")), Some(real.clone()));
+    assert_eq!(symlinks::resolve_root(&dir.join("link/sub")), Some(real.join("sub")));
+    assert_eq!(symlinks::resolve_root(&real), None);
+    assert_eq!(symlinks::resolve_root(&real.join("./sub/")), None, "dots and trailing slashes aren't links");
+    assert_eq!(symlinks::resolve_root(&dir.join("missing")), None);
+}
+
+#[test]
+fn both_spellings_of_a_tree_are_walked_once() {
+    let (dir, real) = tree("dedupe");
+    let (directories, linked) = symlinks::resolve_roots(&[dir.join("link"), real.clone(), dir.join("link/sub")]);
+    assert_eq!(directories, vec![real.clone(), real.join("sub")]);
+    assert_eq!(linked.len(), 2);
+    assert_eq!(linked[0], LinkedRoot { target: dir.join("link"), resolved: real });
+}
+
+#[test]
+fn a_checkpoint_written_through_the_link_resumes_under_the_real_path() {
+    let (dir, real) = tree("checkpoint");
+    let journal = dir.join("journal.ndjson");
+    let done = FileOutcome { bytes: 4, bytes_covered: 4, bytes_read: 4, latency: Duration::from_micros(10), status: FileStatus::Warmed };
+    {
+        let checkpoint = Checkpoint::open(&journal).unwrap();
+        checkpoint.record(&dir.join("link/a"), &done);
+        // The same file again under its real path, e.g. from a later resolved run
+        checkpoint.record(&real.join("a"), &done);
+        checkpoint.record(&dir.join("elsewhere"), &done);
+        checkpoint.flush();
+    }
+
+    let mut checkpoint = Checkpoint::open(&journal).unwrap();
+    assert_eq!(checkpoint.completed_files(), 3);
+    let (_, linked) = symlinks::resolve_roots(&[dir.join("link")]);
+    checkpoint.resolve_links(&linked);
+    assert!(checkpoint.is_completed(&real.join("a")));
+    assert!(!checkpoint.is_completed(&dir.join("link/a")));
+    assert!(checkpoint.is_completed(&dir.join("elsewhere")));
+    assert_eq!(checkpoint.completed_files(), 2, "one file, journaled under both spellings");
+    assert_eq!(checkpoint.completed_bytes(), 8);
+}
@@ -0,0 +1,58 @@
+//! `--systemd`: sd_notify messages go to `$NOTIFY_SOCKET` and journal entries use the
+//! native protocol.
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use rust_cache_warmer::events::EventBus;
+use rust_cache_warmer::progress::ByteProgress;
+use rust_cache_warmer::systemd::{self, Notifier};
+
+use common::scratch;
+
+fn receive(socket: &UnixDatagram) -> String {
+    let mut buf = [0u8; 4096];
+    let len = socket.recv(&mut buf).unwrap();
+    String::from_utf8(buf[..len].to_vec()).unwrap()
+}
+
+#[test]
+fn journal_entries_length_prefix_multiline_values() {
+    let entry = systemd::encode_entry(&[("MESSAGE", "one\ntwo"), ("PRIORITY", "6")]);
+    let mut expected = b"MESSAGE\n".to_vec();
+    expected.extend_from_slice(&7u64.to_le_bytes());
+    expected.extend_from_slice(b"one\ntwo\nPRIORITY=6\n");
+    assert_eq!(entry, expected);
+}
+
+// The only test that touches NOTIFY_SOCKET and WATCHDOG_*, so there's no race on them
+#[tokio::test(flavor = "multi_thread")]
+async fn status_and_watchdog_reach_the_notify_socket() {
+    let dir = scratch("notify");
+    let socket = UnixDatagram::bind(dir.join("notify.sock")).unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    std::env::set_var("NOTIFY_SOCKET", dir.join("notify.sock"));
+    std::env::set_var("WATCHDOG_USEC", "4000000");
+    std::env::remove_var("WATCHDOG_PID");
+
+    let notifier = Notifier::from_env().unwrap();
+    assert_eq!(Notifier::watchdog_interval(), Some(Duration::from_secs(2)));
+    notifier.notify("READY=1").unwrap();
+    assert_eq!(receive(&socket), "READY=1");
+
+    let progress = ByteProgress::default();
+    progress.set_total(1000);
+    progress.finished(250);
+    let mut events = EventBus::new();
+    let status = tokio::spawn(systemd::run_status(Some(notifier), progress, events.subscribe()));
+    // The first update goes out as soon as the task starts
+    assert_eq!(receive(&socket), "STATUS=Warming: 0 files, 0.0 MB warmed, 25%");
+    drop(events);
+    status.await.unwrap();
+
+    std::env::set_var("WATCHDOG_PID", "1");
+    assert_eq!(Notifier::watchdog_interval(), None);
+}
@@ -0,0 +1,118 @@
+//! node_exporter textfile output: counters and the latency histogram are cumulative,
+//! labels are escaped, and the file is replaced atomically with a final write marking
+//! the run complete.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use rust_cache_warmer::events::WarmEvent;
+use rust_cache_warmer::stats::FileStatus;
+use rust_cache_warmer::textfile::{self, TextfileMetrics, TextfileOptions, FILE_NAME};
+use rust_cache_warmer::warming::Coverage;
+use tokio::sync::mpsc;
+
+use common::scratch;
+
+fn finished(bytes: u64, latency_ms: u64, status: FileStatus, method: Option<&'static str>) -> WarmEvent {
+    WarmEvent::FileFinished { path: PathBuf::from("/data/f"), bytes, latency: Duration::from_millis(latency_ms), status, method, bytes_read: bytes, error: None, coverage: None }
+}
+
+/// Value of the sample line starting with `series`
+fn sample(text: &str, series: &str) -> f64 {
+    text.lines()
+        .find_map(|line| line.strip_prefix(series).and_then(|rest| rest.strip_prefix(' ')))
+        .unwrap_or_else(|| panic!("no sample {} in\n{}", series, text))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn bytes_read_and_covered_are_counted_apart() {
+    let mut metrics = TextfileMetrics::new();
+    let mut sampled = finished(1 << 20, 1, FileStatus::Warmed, Some("tokio_sparse"));
+    let WarmEvent::FileFinished { bytes_read, coverage, .. } = &mut sampled;
+    (*bytes_read, *coverage) = (65536, Some(Coverage::Sampled));
+    metrics.record(&sampled);
+    let mut advised = finished(4096, 1, FileStatus::Warmed, Some("fadvise"));
+    let WarmEvent::FileFinished { bytes_read, coverage, .. } = &mut advised;
+    (*bytes_read, *coverage) = (0, Some(Coverage::Advisory));
+    metrics.record(&advised);
+    let mut full = finished(8192, 1, FileStatus::Warmed, Some("tokio_full"));
+    let WarmEvent::FileFinished { coverage, .. } = &mut full;
+    *coverage = Some(Coverage::Full);
+    metrics.record(&full);
+
+    let text = metrics.render("/data", SystemTime::now(), false);
+    let t = r#"target="/data""#;
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_bytes_warmed_total{{{}}}", t)), (1 << 20) as f64 + 12288.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_bytes_covered_total{{{}}}", t)), 65536.0 + 8192.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_bytes_read_total{{{}}}", t)), 65536.0 + 8192.0);
+}
+
+#[test]
+fn counters_and_histogram_are_cumulative() {
+    let mut metrics = TextfileMetrics::new();
+    metrics.record(&finished(1000, 0, FileStatus::Warmed, Some("readahead_full")));
+    metrics.record(&finished(3000, 20, FileStatus::Warmed, Some("readahead_full")));
+    metrics.record(&finished(500, 2000, FileStatus::Warmed, Some("tokio_full")));
+    metrics.record(&finished(0, 30_000, FileStatus::Failed, None));
+    metrics.record(&finished(9999, 0, FileStatus::Skipped, None));
+
+    let text = metrics.render("/data", SystemTime::now(), false);
+    let t = r#"target="/data""#;
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_files_total{{{},status=\"warmed\"}}", t)), 3.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_files_total{{{},status=\"failed\"}}", t)), 1.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_files_total{{{},status=\"skipped\"}}", t)), 1.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_bytes_warmed_total{{{}}}", t)), 4500.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_method_bytes_total{{{},method=\"readahead_full\"}}", t)), 4000.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_method_files_total{{{},method=\"tokio_full\"}}", t)), 1.0);
+
+    // Skipped files aren't timed; the failed one lands past the last bucket
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_file_latency_seconds_bucket{{{},le=\"0.001\"}}", t)), 1.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_file_latency_seconds_bucket{{{},le=\"0.05\"}}", t)), 2.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_file_latency_seconds_bucket{{{},le=\"5\"}}", t)), 3.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_file_latency_seconds_bucket{{{},le=\"10\"}}", t)), 3.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_file_latency_seconds_bucket{{{},le=\"+Inf\"}}", t)), 4.0);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_file_latency_seconds_count{{{}}}", t)), 4.0);
+    assert!((sample(&text, &format!("rust_cache_warmer_file_latency_seconds_sum{{{}}}", t)) - 32.02).abs() < 1e-9);
+    assert_eq!(sample(&text, &format!("rust_cache_warmer_run_complete{{{}}}", t)), 0.0);
+
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        assert!(line.starts_with("rust_cache_warmer_"), "{}", line);
+    }
+}
+
+#[test]
+fn label_values_are_escaped() {
+    let text = TextfileMetrics::new().render("/a \"quoted\"\\dir", SystemTime::now(), true);
+    assert!(text.contains(r#"rust_cache_warmer_run_complete{target="/a \"quoted\"\\dir"} 1"#), "{}", text);
+}
+
+#[test]
+fn the_file_is_replaced_atomically() {
+    let dir = scratch("atomic");
+    textfile::write_atomically(&dir, "first\n").unwrap();
+    textfile::write_atomically(&dir, "second\n").unwrap();
+    assert_eq!(fs::read_to_string(dir.join(FILE_NAME)).unwrap(), "second\n");
+    let names: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names, vec![std::ffi::OsString::from(FILE_NAME)], "no temporary files left behind");
+}
+
+#[tokio::test]
+async fn the_writer_marks_the_run_complete_when_events_end() {
+    let dir = scratch("writer");
+    let (tx, rx) = mpsc::unbounded_channel();
+    let options = TextfileOptions { dir: dir.clone(), interval: Duration::from_secs(3600), target: "/data".to_string() };
+    let writer = tokio::spawn(textfile::run_writer(options, rx));
+
+    tx.send(finished(100, 1, FileStatus::Warmed, Some("fadvise"))).unwrap();
+    drop(tx);
+    writer.await.unwrap();
+
+    let text = fs::read_to_string(dir.join(FILE_NAME)).unwrap();
+    assert_eq!(sample(&text, r#"rust_cache_warmer_files_total{target="/data",status="warmed"}"#), 1.0);
+    assert_eq!(sample(&text, r#"rust_cache_warmer_run_complete{target="/data"}"#), 1.0);
+}
@@ -0,0 +1,97 @@
+//! `--sparse-interval` and the per-strategy `--strategy-chunk-size` and
+//! `--strategy-sparse-interval` overrides.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, StrategyRegistry, StrategySize, WarmingOptions, SPARSE_INTERVAL};
+
+const MB: u64 = 1024 * 1024;
+
+#[test]
+fn strategy_sizes_parse() {
+    assert_eq!("uring=262144".parse(), Ok(StrategySize { strategy: Strategy::Uring, bytes: 262144 }));
+    assert_eq!("tokio=4194304".parse(), Ok(StrategySize { strategy: Strategy::Tokio, bytes: 4 * MB }));
+    assert!("uring".parse::<StrategySize>().is_err());
+    assert!("auto=4096".parse::<StrategySize>().is_err(), "auto picks a strategy per file");
+    assert!("uring=1000".parse::<StrategySize>().is_err(), "not a multiple of 4096");
+    assert!("uring=0".parse::<StrategySize>().is_err());
+    assert!("floppy=4096".parse::<StrategySize>().is_err());
+}
+
+#[test]
+fn overrides_apply_to_their_strategy_only() {
+    let options = WarmingOptions {
+        chunk_size: 1024 * 1024,
+        sparse_interval: 0,
+        strategy_chunk_sizes: vec!["uring=262144".parse().unwrap(), "uring=524288".parse().unwrap()],
+        strategy_sparse_intervals: vec!["readahead=1048576".parse().unwrap()],
+        ..Default::default()
+    };
+    assert_eq!(options.sample_interval(SPARSE_INTERVAL), SPARSE_INTERVAL, "0 leaves the backend's default");
+
+    let uring = options.for_strategy(Strategy::Uring);
+    assert_eq!(uring.chunk_size, 512 * 1024, "the last override wins");
+    assert_eq!(uring.sample_interval(SPARSE_INTERVAL), SPARSE_INTERVAL);
+    let readahead = options.for_strategy(Strategy::Readahead);
+    assert_eq!((readahead.chunk_size, readahead.sample_interval(SPARSE_INTERVAL)), (1024 * 1024, MB));
+    let tokio = options.for_strategy(Strategy::Tokio);
+    assert_eq!((tokio.chunk_size, tokio.sparse_interval), (1024 * 1024, 0));
+}
+
+#[tokio::test]
+async fn the_sparse_interval_sets_the_sample_count() {
+    let dir = common::scratch("sparse");
+    let path = dir.join("data");
+    fs::write(&path, vec![3u8; 8 * MB as usize]).unwrap();
+
+    // Buffered Tokio reads sample a single byte, so bytes read count the samples
+    let registry = StrategyRegistry::builtin();
+    let warm = |options: WarmingOptions| {
+        let (registry, path) = (&registry, &path);
+        async move { registry.warm(path, 8 * MB, &options).await.unwrap() }
+    };
+    let base = WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, sparse_large_files: MB, ..Default::default() };
+    assert_eq!(warm(base.clone()).await.bytes_read, 8 * MB / 4096, "a byte per page by default");
+    assert_eq!(warm(WarmingOptions { sparse_interval: MB, ..base.clone() }).await.bytes_read, 8);
+    let per_strategy = WarmingOptions { sparse_interval: MB, strategy_sparse_intervals: vec!["tokio=2097152".parse().unwrap()], ..base };
+    assert_eq!(warm(per_strategy).await.bytes_read, 4);
+}
+
+#[test]
+fn unaligned_intervals_are_refused() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+        .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--sparse-interval", "1000", env!("CARGO_TARGET_TMPDIR")])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--sparse-interval must be"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn readahead_requests_the_configured_chunk_size() {
+    let dir = common::scratch("readahead");
+    fs::write(dir.join("data"), vec![5u8; 8 * MB as usize]).unwrap();
+
+    // The backend logs how many readahead() calls covered the file
+    let calls = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+            .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+            .args(["--strategy", "readahead", "--no-auto-tune", "--debug"])
+            .args(args)
+            .arg(&dir)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let line = stderr.lines().find(|line| line.contains("Full readahead completed")).unwrap_or_else(|| panic!("no readahead in:\n{}", stderr)).to_string();
+        line.split(" calls").next().unwrap().rsplit(' ').next().unwrap().parse::<u64>().unwrap()
+    };
+    assert_eq!(calls(&[]), 1, "16MiB by default");
+    assert_eq!(calls(&["--chunk-size", "1048576"]), 8);
+    assert_eq!(calls(&["--chunk-size", "1048576", "--strategy-chunk-size", "readahead=2097152"]), 4);
+}
@@ -0,0 +1,93 @@
+//! `--two-phase`: hydrate everything without the page cache, then advise the hot subset
+//! into it.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::events::{EventBus, WarmEvent};
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::phases;
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions};
+use rust_cache_warmer::warming::{DropCaches, FallbackPolicy, Strategy, WarmingOptions};
+
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    fs::create_dir_all(root.join("tables")).unwrap();
+    for name in ["tables/users.db", "tables/orders.db", "tables/orders.log", "README"] {
+        fs::write(root.join(name), vec![1u8; 16384]).unwrap();
+    }
+    root
+}
+
+#[tokio::test]
+async fn the_page_cache_phase_advises_only_hot_files() {
+    let root = tree("pipeline");
+    let directories = vec![root.clone()];
+    let hydrate = PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &[], &[], &[]).unwrap(),
+        directories: directories.clone(),
+        queue_depth: 2,
+        threads: None,
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 16,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, use_direct_io: true, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: vec![root.clone()],
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: Duration::ZERO,
+        physical_order: true,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: true,
+    };
+    let hot = DiscoveryFilters::new(&directories, &["*.db".to_string()], &["orders*".to_string()], &[], &[]).unwrap();
+    let options = phases::page_cache_options(&hydrate, hot);
+    assert_eq!((options.warming.strategy, options.warming.drop_caches), (Strategy::Fadvise, DropCaches::None));
+    assert_eq!(options.warming.fallback, FallbackPolicy::None);
+    assert!(!options.warming.use_direct_io && !options.physical_order && options.page_cache_only.is_empty());
+    assert_eq!((options.queue_depth, options.batch_size), (2, 16));
+
+    let mut events = EventBus::new();
+    let mut finished = events.subscribe();
+    let summary = pipeline::run(Arc::new(options), PipelineContext { events, ..Default::default() }).await;
+    assert_eq!((summary.files_discovered, summary.files_processed), (1, 1));
+    let Ok(WarmEvent::FileFinished { path, .. }) = finished.try_recv() else { panic!("no file finished") };
+    assert_eq!(path, root.join("tables/users.db"));
+}
+
+#[test]
+fn two_phase_runs() {
+    let root = tree("cli");
+    let warmer = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"));
+        command.env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state")).arg(&root);
+        command
+    };
+
+    let output = warmer().args(["--two-phase", "--hot-include", "*.db"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Two phases: hydrating without the page cache, then caching files matching *.db"));
+    assert!(stderr.contains("Page-cache phase: advised 2 hot files"), "{}", stderr);
+
+    assert!(!warmer().arg("--two-phase").output().unwrap().status.success(), "--hot-include is required");
+    assert!(!warmer().args(["--hot-include", "*.db"]).output().unwrap().status.success(), "--two-phase is required");
+    let output = warmer().args(["--two-phase", "--hot-include", "*.db", "--drop-caches-after", "none"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--drop-caches-after none contradicts"));
+}
@@ -0,0 +1,118 @@
+//! Files that vanish between discovery and warming are looked for again after a grace
+//! period: at the same path (atomic replacement) and, by inode, under a new name in the
+//! same directory (log rotation).
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::fair::FairShareOptions;
+use rust_cache_warmer::filters::DiscoveryFilters;
+use rust_cache_warmer::hooks::{FileMetadata, MetadataHooks};
+use rust_cache_warmer::pipeline::{self, PipelineContext, PipelineOptions, PipelineSummary};
+use rust_cache_warmer::vanished::{self, Found, VanishedSummary};
+use rust_cache_warmer::warming::{FallbackPolicy, Strategy, WarmingOptions};
+
+use common::scratch;
+
+/// Warm `dir`, calling `on_discovery` for each file as it's discovered (and so before
+/// it's warmed). Rotated `*.1` files are left out of discovery.
+async fn run(dir: &Path, grace: Duration, on_discovery: impl Fn(&Path) + Send + Sync + 'static) -> PipelineSummary {
+    let directories = vec![dir.to_path_buf()];
+    let options = Arc::new(PipelineOptions {
+        filters: DiscoveryFilters::new(&directories, &[], &["*.1".to_string()], &[], &[]).unwrap(),
+        directories,
+        queue_depth: 1,
+        threads: Some(1),
+        follow_symlinks: false,
+        respect_gitignore: false,
+        max_depth: None,
+        ignore_hidden: false,
+        max_file_size: 0,
+        batch_size: 10,
+        batch_bytes: 0,
+        warming: WarmingOptions { strategy: Strategy::Tokio, fallback: FallbackPolicy::None, ..Default::default() },
+        stats_by: Vec::new(),
+        low_memory: false,
+        page_cache_only: Vec::new(),
+        network: Vec::new(),
+        fail_fast: false,
+        fair_share: FairShareOptions::default(),
+        vanished_grace: grace,
+        physical_order: false,
+        dedupe_inodes: true,
+        max_duration: None,
+        discovery_buffer: 0,
+        per_device_queue_depth: None,
+        deterministic: false,
+    });
+    let mut hooks = MetadataHooks::new();
+    hooks.register(move |path: &Path, _: &mut FileMetadata| on_discovery(path));
+    pipeline::run(options, PipelineContext { hooks: Arc::new(hooks), ..Default::default() }).await
+}
+
+#[tokio::test]
+async fn files_are_found_at_the_same_path_or_by_inode() {
+    let dir = scratch("find");
+    let path = dir.join("app.log");
+    fs::write(&path, "old").unwrap();
+    let ino = fs::metadata(&path).unwrap().ino();
+
+    assert_eq!(vanished::find(&path, ino).await, Some(Found::Replaced));
+    fs::rename(&path, dir.join("app.log.1")).unwrap();
+    assert_eq!(vanished::find(&path, ino).await, Some(Found::Renamed(dir.join("app.log.1"))));
+    assert_eq!(vanished::find(&path, 0).await, None, "without an inode renames can't be followed");
+    fs::remove_file(dir.join("app.log.1")).unwrap();
+    assert_eq!(vanished::find(&path, ino).await, None);
+}
+
+#[tokio::test]
+async fn rotated_files_are_followed() {
+    let dir = scratch("rotated");
+    fs::write(dir.join("app.log"), vec![7u8; 5000]).unwrap();
+
+    let summary = run(&dir, Duration::from_millis(20), |path| {
+        fs::rename(path, path.with_extension("log.1")).unwrap();
+    })
+    .await;
+    assert_eq!(summary.vanished, VanishedSummary { vanished: 1, replaced: 0, renamed: 1, lost: 0 });
+    assert_eq!(summary.stats.totals.files, 1);
+    assert_eq!(summary.stats.totals.failed, 0);
+    assert_eq!(summary.stats.totals.bytes, 5000);
+}
+
+#[tokio::test]
+async fn replaced_files_are_warmed_at_their_path() {
+    let dir = scratch("replaced");
+    fs::write(dir.join("state.json"), "{}").unwrap();
+
+    // Gone at discovery, and back (with new contents) a little later
+    let summary = run(&dir, Duration::from_millis(200), |path| {
+        fs::remove_file(path).unwrap();
+        let path = path.to_path_buf();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            fs::write(path, "{\"version\":2}").unwrap();
+        });
+    })
+    .await;
+    assert_eq!(summary.vanished, VanishedSummary { vanished: 1, replaced: 1, renamed: 0, lost: 0 });
+    assert_eq!(summary.stats.totals.failed, 0);
+    assert_eq!(summary.stats.totals.bytes, 13);
+}
+
+#[tokio::test]
+async fn deleted_files_still_fail() {
+    for grace in [Duration::ZERO, Duration::from_millis(10)] {
+        let dir = scratch(&format!("deleted-{}", grace.as_millis()));
+        fs::write(dir.join("tmp.bin"), "x").unwrap();
+        let summary = run(&dir, grace, |path| fs::remove_file(path).unwrap()).await;
+        assert_eq!(summary.stats.totals.failed, 1);
+        let lost = if grace.is_zero() { 0 } else { 1 };
+        assert_eq!(summary.vanished, VanishedSummary { vanished: lost, replaced: 0, renamed: 0, lost });
+    }
+}
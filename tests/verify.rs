@@ -0,0 +1,100 @@
+//! `--verify` sampling: offsets are block-aligned, fall inside the sampled files, are
+//! spread by bytes rather than by file, and are reproducible for a given seed.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_cache_warmer::pipeline::PipelineOptions;
+use rust_cache_warmer::verify::{self, VerifyOptions, SAMPLE_SIZE};
+use rust_cache_warmer::warming::WarmingOptions;
+
+fn options(root: &Path) -> Arc<PipelineOptions> {
+    Arc::new(PipelineOptions { batch_size: 100, ..common::pipeline_options(vec![root.to_path_buf()]) })
+}
+
+fn tree(test: &str) -> PathBuf {
+    let root = common::scratch(test);
+    // One large file and many small ones: by bytes, nearly every sample lands in the large one
+    fs::write(root.join("large.bin"), vec![3u8; 4 * 1024 * 1024]).unwrap();
+    for i in 0..10 {
+        fs::write(root.join(format!("small-{}.bin", i)), vec![3u8; 100]).unwrap();
+    }
+    fs::write(root.join("empty.bin"), b"").unwrap();
+    root
+}
+
+/// Every sample is reported as cold with a zero threshold, exposing where they landed
+fn sample_everything(seed: u64) -> VerifyOptions {
+    VerifyOptions { samples: 200, cold_threshold: Duration::ZERO, concurrency: 4, seed }
+}
+
+#[tokio::test]
+async fn samples_are_aligned_and_weighted_by_bytes() {
+    let root = tree("weighted");
+    let report = verify::verify(options(&root), &sample_everything(7)).await.unwrap();
+
+    assert_eq!(report.samples, 200);
+    assert_eq!(report.failed, 0);
+    assert_eq!(report.files_covered, 11, "empty files can't be sampled");
+    assert_eq!(report.bytes_covered, 4 * 1024 * 1024 + 1000);
+    assert_eq!(report.cold.len(), 200);
+    assert!(report.latency.p50 <= report.latency.p90 && report.latency.p90 <= report.latency.p99);
+    assert!(report.latency.p99 <= report.latency.max);
+
+    let mut in_large = 0;
+    for sample in &report.cold {
+        let size = fs::metadata(&sample.path).unwrap().len();
+        assert_eq!(sample.offset % SAMPLE_SIZE, 0);
+        assert!(sample.offset < size, "{} @ {} is past the end", sample.path.display(), sample.offset);
+        in_large += (sample.path.file_name().unwrap() == "large.bin") as usize;
+    }
+    assert!(in_large >= 190, "only {} of 200 samples landed in the large file", in_large);
+}
+
+#[tokio::test]
+async fn the_same_seed_picks_the_same_offsets() {
+    let root = tree("seed");
+    let picks = |report: verify::VerifyReport| {
+        let mut picks: Vec<(PathBuf, u64)> = report.cold.into_iter().map(|s| (s.path, s.offset)).collect();
+        picks.sort();
+        picks
+    };
+    let first = picks(verify::verify(options(&root), &sample_everything(42)).await.unwrap());
+    let second = picks(verify::verify(options(&root), &sample_everything(42)).await.unwrap());
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn fast_reads_are_not_flagged() {
+    let root = tree("fast");
+    let verify_options = VerifyOptions { cold_threshold: Duration::from_secs(60), ..sample_everything(1) };
+    let report = verify::verify(options(&root), &verify_options).await.unwrap();
+    assert!(report.is_hydrated());
+    assert!(report.to_string().contains("looks hydrated"));
+}
+
+#[tokio::test]
+async fn cold_samples_are_rewarmed_once_per_region() {
+    let root = tree("rewarm");
+    let report = verify::verify(options(&root), &sample_everything(3)).await.unwrap();
+    let rewarmed = verify::rewarm_cold(&report, &WarmingOptions::default(), 4).await;
+
+    // 200 samples collapse into at most 4 regions of the large file plus the small files
+    assert!(!rewarmed.regions.is_empty() && rewarmed.regions.len() <= 14, "{} regions", rewarmed.regions.len());
+    for region in &rewarmed.regions {
+        let size = fs::metadata(&region.path).unwrap().len();
+        assert_eq!(region.offset % verify::REWARM_REGION, 0);
+        assert!(region.length <= verify::REWARM_REGION && region.offset + region.length <= size);
+        assert!(region.after.is_ok(), "{:?}", region.after);
+    }
+    // Every re-measured block is slower than a zero threshold
+    assert_eq!(rewarmed.still_cold().len(), rewarmed.regions.len());
+
+    let hydrated = VerifyOptions { cold_threshold: Duration::from_secs(60), ..sample_everything(3) };
+    let report = verify::verify(options(&root), &hydrated).await.unwrap();
+    assert!(verify::rewarm_cold(&report, &WarmingOptions::default(), 4).await.regions.is_empty());
+}
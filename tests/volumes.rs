@@ -0,0 +1,124 @@
+//! `--all-ebs-volumes`: EBS filesystems are picked out of the mount table, once each, with
+//! the root disk left out unless asked for.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use rust_cache_warmer::disk::DiskKind;
+use rust_cache_warmer::layers;
+use rust_cache_warmer::volumes::{self, Discovered, EbsMount};
+
+const MOUNTINFO: &str = "\
+22 1 259:1 / / rw,relatime - ext4 /dev/nvme0n1p1 rw
+23 22 259:2 / /boot/efi rw,relatime - vfat /dev/nvme0n1p15 rw
+24 22 0:21 / /proc rw - proc proc rw
+25 22 0:24 / /run rw - tmpfs tmpfs rw
+30 22 259:5 / /data rw,relatime - xfs /dev/nvme1n1 rw
+31 22 259:5 /shared /srv/shared rw,relatime - xfs /dev/nvme1n1 rw
+32 22 259:6 /logs /var/log/app rw,relatime - ext4 /dev/nvme2n1 rw
+33 22 259:7 / /scratch rw,relatime - xfs /dev/nvme3n1 rw
+";
+
+/// The disks behind the mount points in [`MOUNTINFO`]
+fn nitro(path: &Path) -> DiskKind {
+    match path.to_str().unwrap() {
+        "/" | "/boot/efi" => DiskKind::Ebs("nvme0n1".to_string()),
+        "/data" | "/srv/shared" => DiskKind::Ebs("nvme1n1".to_string()),
+        "/var/log/app" => DiskKind::Ebs("nvme2n1".to_string()),
+        "/scratch" => DiskKind::InstanceStore("nvme3n1".to_string()),
+        other => DiskKind::Unknown(format!("{} has no backing block device", other)),
+    }
+}
+
+fn mount(path: &str, disk: &str) -> EbsMount {
+    EbsMount { mount_point: PathBuf::from(path), disk: disk.to_string() }
+}
+
+#[test]
+fn each_ebs_filesystem_is_found_once_without_the_root_disk() {
+    let mounts = layers::parse_mountinfo(MOUNTINFO);
+    let found = volumes::select(&mounts, nitro, &[], false);
+    assert_eq!(
+        found,
+        Discovered {
+            volumes: vec![mount("/data", "nvme1n1"), mount("/var/log/app", "nvme2n1")],
+            root: vec![mount("/", "nvme0n1"), mount("/boot/efi", "nvme0n1")],
+        }
+    );
+
+    let found = volumes::select(&mounts, nitro, &[], true);
+    assert_eq!(found.volumes.len(), 4);
+    assert!(found.root.is_empty());
+}
+
+#[test]
+fn xen_disks_count_when_the_block_device_mapping_lists_them() {
+    let mounts = layers::parse_mountinfo(
+        "\
+22 1 202:1 / / rw - ext4 /dev/xvda1 rw
+30 22 202:80 / /data rw - xfs /dev/xvdf rw
+31 22 202:96 / /media/ephemeral0 rw - ext4 /dev/xvdg rw
+",
+    );
+    let xen = |path: &Path| match path.to_str().unwrap() {
+        "/" => DiskKind::Unknown("xvda".to_string()),
+        "/data" => DiskKind::Unknown("xvdf".to_string()),
+        _ => DiskKind::Unknown("xvdg".to_string()),
+    };
+    assert_eq!(volumes::select(&mounts, xen, &[], false), Discovered::default());
+
+    let mapped = ["/dev/sda1".to_string(), "sdf".to_string()];
+    let found = volumes::select(&mounts, xen, &mapped, false);
+    assert_eq!(found.volumes, vec![mount("/data", "xvdf")]);
+    assert_eq!(found.root, vec![mount("/", "xvda")]);
+}
+
+#[test]
+fn mapping_names_match_however_the_device_is_spelled() {
+    for name in ["/dev/sdf", "sdf", "xvdf", "/dev/xvdf1", "sdf2"] {
+        assert_eq!(volumes::mapping_name(name), "sdf", "{}", name);
+    }
+    assert_eq!(volumes::mapping_name("nvme1n1"), "nvme1n1");
+}
+
+#[test]
+fn the_block_device_mapping_is_read_with_an_imdsv2_token() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for stream in listener.incoming().take(4) {
+            let mut stream = stream.unwrap();
+            let mut head = Vec::new();
+            let mut reader = BufReader::new(&stream);
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let authorized = head.iter().any(|line| line == "X-aws-ec2-metadata-token: secret");
+            let body = match head[0].split_whitespace().nth(1).unwrap() {
+                "/latest/api/token" => Some("secret"),
+                _ if !authorized => None,
+                "/latest/meta-data/block-device-mapping/" => Some("ami\nebs1\nephemeral0\nroot"),
+                "/latest/meta-data/block-device-mapping/ebs1" => Some("sdf"),
+                "/latest/meta-data/block-device-mapping/root" => Some("/dev/sda1"),
+                _ => None,
+            };
+            let response = match body {
+                Some(body) => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body),
+                None => "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".to_string(),
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+            requests.push(head.remove(0));
+        }
+        requests
+    });
+
+    assert_eq!(volumes::imds_ebs_devices(&endpoint).unwrap(), vec!["sdf".to_string(), "/dev/sda1".to_string()]);
+    assert_eq!(server.join().unwrap()[0], "PUT /latest/api/token HTTP/1.1");
+}
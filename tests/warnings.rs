@@ -0,0 +1,45 @@
+//! Rate-limited warnings: a burst per category is logged, the rest are counted and
+//! reported as suppressed.
+
+use std::io;
+use std::time::Duration;
+
+use rust_cache_warmer::warnings::{Category, WarningCount, Warnings};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[test]
+fn categories_are_limited_independently() {
+    let warnings = Warnings::new(3, HOUR);
+    let logged = (0..1000).filter(|i| warnings.report(Category::PermissionDenied, format_args!("denied {}", i))).count();
+    assert_eq!(logged, 3);
+    assert!(warnings.report(Category::SlowOperation, format_args!("slow")), "other categories keep their own burst");
+
+    assert_eq!(
+        warnings.counts(),
+        [
+            WarningCount { category: Category::PermissionDenied, total: 1000, suppressed: 997 },
+            WarningCount { category: Category::SlowOperation, total: 1, suppressed: 0 },
+        ]
+    );
+}
+
+#[test]
+fn one_warning_per_interval_after_the_burst() {
+    let warnings = Warnings::new(1, Duration::ZERO);
+    assert!((0..50).all(|_| warnings.report(Category::ReadError, format_args!("read failed"))));
+    assert_eq!(warnings.counts()[0].suppressed, 0);
+}
+
+#[test]
+fn tallies_read_like_a_summary() {
+    let count = WarningCount { category: Category::PermissionDenied, total: 1_234_567, suppressed: 1_234_557 };
+    assert_eq!(count.to_string(), "1.2M permission-denied warnings suppressed (1.2M total)");
+    let count = WarningCount { category: Category::ReadError, total: 12_000, suppressed: 42 };
+    assert_eq!(count.to_string(), "42 read-error warnings suppressed (12.0K total)");
+
+    let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+    assert_eq!(Category::of(&denied), Category::PermissionDenied);
+    assert_eq!(Category::of(&io::Error::from(io::ErrorKind::NotFound)), Category::NotFound);
+    assert_eq!(Category::of(&io::Error::other("EIO")), Category::ReadError);
+}
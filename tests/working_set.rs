@@ -0,0 +1,148 @@
+//! `--process`: the files a process maps are warmed in the ranges mapped, the files it
+//! holds open are warmed whole, and nothing else under the targets is discovered.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use rust_cache_warmer::sources::FileSource;
+use rust_cache_warmer::warming::{DropCaches, FallbackPolicy, RangeSelector, Strategy, StrategyRegistry, WarmingOptions};
+use rust_cache_warmer::working_set::{self, Mapping, WorkingSet};
+
+use common::scratch;
+
+const KB: u64 = 1024;
+
+/// A `/proc` with one process, `pid`, mapping `maps` and holding `open` open
+fn fake_proc(root: &Path, pid: u32, maps: &str, open: &[&Path]) -> PathBuf {
+    let proc = root.join("proc");
+    fs::create_dir_all(proc.join(pid.to_string()).join("fd")).unwrap();
+    fs::write(proc.join(pid.to_string()).join("maps"), maps).unwrap();
+    for (fd, path) in open.iter().enumerate() {
+        std::os::unix::fs::symlink(path, proc.join(pid.to_string()).join("fd").join(fd.to_string())).unwrap();
+    }
+    std::os::unix::fs::symlink("pipe:[4242]", proc.join(pid.to_string()).join("fd").join("99")).unwrap();
+    proc
+}
+
+#[test]
+fn maps_lines_parse() {
+    let maps = "\
+55d0c0a00000-55d0c0a02000 r--p 00000000 103:02 1311 /usr/bin/postgres
+7f3a10000000-7f3a10004000 rw-s 00002000 103:02 2222 /data/base/16384/my table
+7f3a20000000-7f3a20021000 rw-p 00000000 00:00 0 \n\
+7f3a30000000-7f3a30001000 r--s 00000000 103:02 3333 /data/gone (deleted)
+7ffd40000000-7ffd40021000 rw-p 00000000 00:00 0                          [stack]
+";
+    assert_eq!(
+        working_set::parse_maps(maps),
+        [
+            Mapping { path: PathBuf::from("/usr/bin/postgres"), offset: 0, length: 0x2000 },
+            Mapping { path: PathBuf::from("/data/base/16384/my table"), offset: 0x2000, length: 0x4000 },
+        ]
+    );
+}
+
+#[test]
+fn the_working_set_is_the_mapped_and_open_files_under_the_targets() {
+    let root = scratch("read").canonicalize().unwrap();
+    let data = root.join("data");
+    fs::create_dir_all(&data).unwrap();
+    for name in ["mapped", "open", "both", "untouched"] {
+        fs::write(data.join(name), vec![0u8; 64 * KB as usize]).unwrap();
+    }
+    let maps = format!(
+        "\
+1000-3000 r--s 00001000 103:02 11 {mapped}
+5000-7000 r--s 00002000 103:02 11 {mapped}
+9000-a000 r--s 0000a000 103:02 11 {mapped}
+1000-2000 r--s 00000000 103:02 12 {both}
+1000-100000 r--s 00000000 103:02 13 {outside}
+",
+        mapped = data.join("mapped").display(),
+        both = data.join("both").display(),
+        outside = root.join("outside").display(),
+    );
+    fs::write(root.join("outside"), b"not a target").unwrap();
+    let proc = fake_proc(&root, 4242, &maps, &[&data.join("open"), &data.join("both"), &root.join("outside")]);
+
+    let working_set = WorkingSet::read(&proc, &[4242], std::slice::from_ref(&data)).unwrap();
+    assert_eq!((working_set.len(), working_set.mapped()), (3, 2));
+    assert_eq!(working_set.to_string(), "the 3 files process 4242 maps or holds open");
+    assert_eq!(working_set.ranges(&data.join("mapped")), Some(&[(0x1000, 0x3000), (0xa000, 0x1000)][..]), "overlapping mappings merge");
+    assert_eq!(working_set.ranges(&data.join("both")), Some(&[(0, 0x1000)][..]), "a mapped file keeps its ranges while open");
+    assert_eq!(working_set.ranges(&data.join("open")), None);
+    assert_eq!(working_set.select(&data.join("open"), 64 * KB).unwrap(), None, "open files are read whole");
+    assert_eq!(working_set.select(&data.join("mapped"), 0xa800).unwrap(), Some(vec![(0x1000, 0x3000), (0xa000, 0x800)]), "clamped to the file");
+
+    let mut listed = Vec::new();
+    working_set.list(std::slice::from_ref(&data), &mut |path| {
+        listed.push(path);
+        true
+    })
+    .unwrap();
+    assert_eq!(listed, [data.join("both"), data.join("mapped"), data.join("open")]);
+
+    assert!(WorkingSet::read(&proc, &[4243], &[data]).is_err(), "a process that isn't there");
+}
+
+#[tokio::test]
+async fn only_the_mapped_ranges_are_read() {
+    let root = scratch("warm").canonicalize().unwrap();
+    let path = root.join("table");
+    fs::write(&path, vec![1u8; 256 * KB as usize]).unwrap();
+    let maps = format!("1000-5000 r--s 00010000 103:02 11 {}\n", path.display());
+    let proc = fake_proc(&root, 7, &maps, &[]);
+    let working_set = Arc::new(WorkingSet::read(&proc, &[7], std::slice::from_ref(&root)).unwrap());
+
+    let options = WarmingOptions {
+        strategy: Strategy::Tokio,
+        fallback: FallbackPolicy::None,
+        drop_caches: DropCaches::None,
+        range_selector: Some(working_set as Arc<dyn RangeSelector>),
+        ..Default::default()
+    };
+    let result = StrategyRegistry::builtin().warm(&path, 256 * KB, &options).await.unwrap();
+    assert_eq!(result.method, "tokio_full", "read by the strategy asked for");
+    assert_eq!(result.bytes_read, 16 * KB);
+
+    let direct = WarmingOptions { use_direct_io: true, ..options };
+    match StrategyRegistry::builtin().warm(&path, 256 * KB, &direct).await {
+        Ok(result) => assert_eq!((result.method, result.bytes_read), ("tokio_direct_full", 16 * KB)),
+        Err(e) => eprintln!("skipping direct I/O: {}", e),
+    }
+}
+
+#[test]
+fn a_running_process_is_warmed_by_what_it_maps() {
+    let root = scratch("cli").canonicalize().unwrap();
+    let (mapped, other) = (root.join("mapped.db"), root.join("other.db"));
+    fs::write(&mapped, vec![3u8; 64 * KB as usize]).unwrap();
+    fs::write(&other, vec![4u8; 64 * KB as usize]).unwrap();
+    // This test process is the one whose working set is warmed
+    let file = fs::File::open(&mapped).unwrap();
+    let addr = unsafe { libc::mmap(std::ptr::null_mut(), 16 * KB as usize, libc::PROT_READ, libc::MAP_SHARED, std::os::unix::io::AsRawFd::as_raw_fd(&file), 0) };
+    assert_ne!(addr, libc::MAP_FAILED);
+    drop(file);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer"))
+        .env("XDG_STATE_HOME", Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"))
+        .args(["--process", &std::process::id().to_string()])
+        .arg(&root)
+        .output()
+        .unwrap();
+    unsafe { libc::munmap(addr, 16 * KB as usize) };
+    let (stdout, stderr) = (String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stdout.contains(&format!("📜 Warming the 1 files process {} maps or holds open", std::process::id())), "{}", stdout);
+    assert!(stdout.contains("🧠 1 of them mapped: warming only the 16.00 KiB mapped"), "{}", stdout);
+    assert!(stderr.contains("across 1 files"), "{}", stderr);
+
+    let missing = Command::new(env!("CARGO_BIN_EXE_rust-cache-warmer")).args(["--process", "0"]).arg(&root).output().unwrap();
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("failed to read the mappings of process 0"));
+}